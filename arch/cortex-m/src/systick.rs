@@ -116,6 +116,19 @@ impl kernel::SysTick for SysTick {
         SYSTICK_BASE.syst_cvr.set(0);
     }
 
+    fn get_value(&self) -> u32 {
+        // Convert the remaining native tics back to microseconds, again in
+        // 64-bit arithmetic to avoid overflow.
+        let tics = SYSTICK_BASE.syst_cvr.read(CurrentValue::CURRENT) as u64;
+        let hertz = self.hertz() as u64;
+
+        if hertz == 0 {
+            0
+        } else {
+            (tics * 1_000_000 / hertz) as u32
+        }
+    }
+
     fn greater_than(&self, us: u32) -> bool {
         let tics = {
             // We need to convert from microseconds to native tics, which could overflow in 32-bit
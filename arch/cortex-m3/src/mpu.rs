@@ -484,6 +484,24 @@ impl kernel::mpu::MPU for MPU {
         Some(mpu::Region::new(start as *const u8, size))
     }
 
+    fn remove_memory_region(
+        &self,
+        region: mpu::Region,
+        config: &mut Self::MpuConfig,
+    ) -> Result<(), ()> {
+        let region_num = config
+            .regions
+            .iter()
+            .enumerate()
+            .position(|(number, r)| {
+                number != APP_MEMORY_REGION_NUM
+                    && r.location() == Some((region.start_address(), region.size()))
+            })
+            .ok_or(())?;
+        config.regions[region_num] = CortexMRegion::empty(region_num);
+        Ok(())
+    }
+
     fn allocate_app_memory_region(
         &self,
         unallocated_memory_start: *const u8,
@@ -6,7 +6,6 @@
 
 use capsules::virtual_alarm::VirtualMuxAlarm;
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil;
 use kernel::hil::entropy::Entropy32;
@@ -64,7 +63,7 @@ pub struct Platform {
     led: &'static capsules::led::LED<'static>,
     rng: &'static capsules::rng::RngDriver<'static>,
     temp: &'static capsules::temperature::TemperatureSensor<'static>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
     alarm: &'static capsules::alarm::AlarmDriver<
         'static,
         VirtualMuxAlarm<'static, nrf52832::rtc::Rtc<'static>>,
@@ -95,7 +94,7 @@ impl kernel::Platform for Platform {
             capsules::gpio_async::DRIVER_NUM => f(Some(self.gpio_async)),
             capsules::ambient_light::DRIVER_NUM => f(Some(self.light)),
             capsules::buzzer_driver::DRIVER_NUM => f(Some(self.buzzer)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
@@ -114,15 +113,7 @@ pub unsafe fn reset_handler() {
     let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
     let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
-
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
     // GPIOs
     let gpio_pins = static_init!(
@@ -329,8 +320,7 @@ pub unsafe fn reset_handler() {
     //
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(rtt, 115200, dynamic_deferred_caller)
-        .finalize(());
+    let uart_mux = components::console::UartMuxComponent::new(rtt, 115200).finalize(());
 
     // Setup the console.
     let console = components::console::ConsoleComponent::new(board_kernel, uart_mux).finalize(());
@@ -525,7 +515,7 @@ pub unsafe fn reset_handler() {
         gpio_async: gpio_async,
         light: light,
         buzzer: buzzer,
-        ipc: kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability),
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
     };
 
     let chip = static_init!(nrf52832::chip::Chip, nrf52832::chip::new());
@@ -545,10 +535,17 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_management_capability,
     );
 
-    board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(
+        &platform,
+        chip,
+        Some(platform.ipc),
+        scheduler,
+        &main_loop_capability,
+    );
 }
@@ -6,7 +6,6 @@
 
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil;
 use kernel::Platform;
@@ -91,15 +90,7 @@ pub unsafe fn reset_handler() {
     let main_loop_cap = create_capability!(capabilities::MainLoopCapability);
     let memory_allocation_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
-
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
     // Configure kernel debug gpios as early as possible
     kernel::debug::assign_gpios(
@@ -109,12 +100,8 @@ pub unsafe fn reset_handler() {
     );
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(
-        &arty_e21::uart::UART0,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&arty_e21::uart::UART0, 115200).finalize(());
 
     let console = components::console::ConsoleComponent::new(board_kernel, uart_mux).finalize(());
 
@@ -242,10 +229,11 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_mgmt_cap,
     );
 
-    board_kernel.kernel_loop(&artye21, chip, None, &main_loop_cap);
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(&artye21, chip, None, scheduler, &main_loop_cap);
 }
@@ -10,9 +10,7 @@
 //! Usage
 //! -----
 //! ```rust
-//! let uart_mux = UartMuxComponent::new(&sam4l::usart::USART3,
-//!                                      115200,
-//!                                      deferred_caller).finalize(());
+//! let uart_mux = UartMuxComponent::new(&sam4l::usart::USART3, 115200).finalize(());
 //! let console = ConsoleComponent::new(board_kernel, uart_mux).finalize(());
 //! ```
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -21,30 +19,22 @@
 use capsules::console;
 use capsules::virtual_uart::{MuxUart, UartDevice};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::DynamicDeferredCall;
+use kernel::common::deferred_call::DeferredCallClient;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil;
 use kernel::hil::uart;
 use kernel::static_init;
+use kernel::GrantCleanupHook;
 
 pub struct UartMuxComponent {
     uart: &'static dyn uart::Uart<'static>,
     baud_rate: u32,
-    deferred_caller: &'static DynamicDeferredCall,
 }
 
 impl UartMuxComponent {
-    pub fn new(
-        uart: &'static dyn uart::Uart<'static>,
-        baud_rate: u32,
-        deferred_caller: &'static DynamicDeferredCall,
-    ) -> UartMuxComponent {
-        UartMuxComponent {
-            uart,
-            baud_rate,
-            deferred_caller,
-        }
+    pub fn new(uart: &'static dyn uart::Uart<'static>, baud_rate: u32) -> UartMuxComponent {
+        UartMuxComponent { uart, baud_rate }
     }
 }
 
@@ -59,14 +49,9 @@ impl Component for UartMuxComponent {
                 self.uart,
                 &mut capsules::virtual_uart::RX_BUF,
                 self.baud_rate,
-                self.deferred_caller,
             )
         );
-        uart_mux.initialize_callback_handle(
-            self.deferred_caller
-                .register(uart_mux)
-                .expect("no deferred call slot available for uart mux"),
-        );
+        uart_mux.register();
 
         uart_mux.initialize();
         hil::uart::Transmit::set_transmit_client(self.uart, uart_mux);
@@ -116,6 +101,9 @@ impl Component for ConsoleComponent {
         hil::uart::Transmit::set_transmit_client(console_uart, console);
         hil::uart::Receive::set_receive_client(console_uart, console);
 
+        let cleanup_hook = static_init!(GrantCleanupHook, GrantCleanupHook::new(console));
+        self.board_kernel.register_grant_cleanup_hook(cleanup_hook);
+
         console
     }
 }
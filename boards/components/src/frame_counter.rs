@@ -0,0 +1,66 @@
+//! Component for the 802.15.4 frame counters used for replay protection.
+//!
+//! This provides one Component, FrameCounterComponent, which keeps the
+//! frame counters of this device and of the devices it hears from. The
+//! outgoing frame counter is persisted at the given address of a nonvolatile
+//! storage, of which it becomes the kernel client. If the persisted counter
+//! cannot be read, secured frames cannot be sent. Without storage, outgoing
+//! frame counters restart from zero on every boot.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let frame_counters = components::frame_counter::FrameCounterComponent::new(
+//!     Some(nonvolatile_storage),
+//!     &FRAME_COUNTER_VOLUME as *const u8 as usize,
+//! )
+//! .finalize(());
+//! ```
+
+use capsules::ieee802154::frame_counter::FrameCounters;
+use kernel::component::Component;
+use kernel::debug;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::static_init;
+use kernel::ReturnCode;
+
+pub struct FrameCounterComponent {
+    storage: Option<&'static dyn NonvolatileStorage<'static>>,
+    address: usize,
+}
+
+impl FrameCounterComponent {
+    pub fn new(
+        storage: Option<&'static dyn NonvolatileStorage<'static>>,
+        address: usize,
+    ) -> FrameCounterComponent {
+        FrameCounterComponent {
+            storage: storage,
+            address: address,
+        }
+    }
+}
+
+impl Component for FrameCounterComponent {
+    type StaticInput = ();
+    type Output = &'static FrameCounters<'static>;
+
+    unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
+        let frame_counters = static_init!(
+            FrameCounters<'static>,
+            FrameCounters::new(
+                self.storage,
+                self.address,
+                &mut capsules::ieee802154::frame_counter::BUFFER
+            )
+        );
+        if let Some(storage) = self.storage {
+            storage.set_client(frame_counters);
+            let result = frame_counters.load();
+            if result != ReturnCode::SUCCESS {
+                debug!("Failed to read the 802.15.4 frame counter: {:?}", result);
+            }
+        }
+        frame_counters
+    }
+}
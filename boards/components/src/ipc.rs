@@ -0,0 +1,46 @@
+//! Component for the kernel's IPC driver.
+//!
+//! This provides one Component, IpcComponent, which creates the IPC driver
+//! and registers it with the kernel so that the shares between processes are
+//! revoked when a process is restarted, stopped or unloaded.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ipc = components::ipc::IpcComponent::new(board_kernel).finalize(());
+//! ```
+
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::ipc::IPC;
+use kernel::static_init;
+use kernel::GrantCleanupHook;
+
+pub struct IpcComponent {
+    board_kernel: &'static kernel::Kernel,
+}
+
+impl IpcComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel) -> IpcComponent {
+        IpcComponent {
+            board_kernel: board_kernel,
+        }
+    }
+}
+
+impl Component for IpcComponent {
+    type StaticInput = ();
+    type Output = &'static IPC;
+
+    unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let ipc = static_init!(IPC, IPC::new(self.board_kernel, &grant_cap));
+
+        let cleanup_hook = static_init!(GrantCleanupHook, GrantCleanupHook::new(ipc));
+        self.board_kernel.register_grant_cleanup_hook(cleanup_hook);
+
+        ipc
+    }
+}
@@ -12,10 +12,16 @@ pub mod alarm;
 pub mod console;
 pub mod crc;
 pub mod debug_writer;
+pub mod frame_counter;
+pub mod ipc;
 pub mod isl29035;
 pub mod lldb;
 pub mod nrf51822;
+pub mod pan_joiner;
 pub mod process_console;
+pub mod process_restarter;
 pub mod rng;
 pub mod si7021;
 pub mod spi;
+pub mod syscall_trace;
+pub mod watchdog;
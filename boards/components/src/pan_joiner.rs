@@ -0,0 +1,45 @@
+//! Component to find or form the 802.15.4 PAN the board belongs to.
+//!
+//! This provides one Component, PanJoinerComponent, which scans for a PAN
+//! coordinator through its own user of the MAC mux, associates with it, or
+//! starts the PAN the MAC is configured with as its coordinator if none is
+//! found. It must be started once the radio is, and only by boards that want
+//! to join a PAN other than the one they are configured with.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pan_joiner = components::pan_joiner::PanJoinerComponent::new(mux_mac).finalize(());
+//! ...
+//! pan_joiner.start();
+//! ```
+
+use capsules::ieee802154::device::MacDevice;
+use capsules::ieee802154::pan_joiner::PanJoiner;
+use capsules::ieee802154::virtual_mac::{MacUser, MuxMac};
+use kernel::component::Component;
+use kernel::static_init;
+
+pub struct PanJoinerComponent {
+    mux_mac: &'static MuxMac<'static>,
+}
+
+impl PanJoinerComponent {
+    pub fn new(mux_mac: &'static MuxMac<'static>) -> PanJoinerComponent {
+        PanJoinerComponent { mux_mac: mux_mac }
+    }
+}
+
+impl Component for PanJoinerComponent {
+    type StaticInput = ();
+    type Output = &'static PanJoiner<'static>;
+
+    unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
+        let joiner_mac = static_init!(MacUser<'static>, MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(joiner_mac);
+
+        let pan_joiner = static_init!(PanJoiner<'static>, PanJoiner::new(joiner_mac));
+        joiner_mac.set_mlme_client(pan_joiner);
+        pan_joiner
+    }
+}
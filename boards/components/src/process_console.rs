@@ -56,6 +56,7 @@ impl Component for ProcessConsoleComponent {
                 &mut process_console::WRITE_BUF,
                 &mut process_console::READ_BUF,
                 &mut process_console::COMMAND_BUF,
+                &mut process_console::HISTORY_BUF,
                 self.board_kernel,
                 Capability,
             )
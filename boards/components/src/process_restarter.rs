@@ -0,0 +1,93 @@
+//! Component for ProcessRestarter, which restarts faulted processes after the
+//! backoff delay requested by their fault policy.
+//!
+//! This provides one Component, ProcessRestarterComponent, which creates a
+//! virtual alarm for the restarter and installs it as the kernel's restart
+//! timer. The helper macro takes the number of process slots on the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! ProcessRestarterComponent::new(board_kernel, mux_alarm).finalize(
+//!     components::process_restarter_component_helper!(sam4l::ast::Ast, NUM_PROCS),
+//! );
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules::process_restarter::ProcessRestarter;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::time;
+use kernel::static_init_half;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! process_restarter_component_helper {
+    ($A:ty, $N:expr) => {{
+        use capsules::process_restarter::ProcessRestarter;
+        use capsules::virtual_alarm::VirtualMuxAlarm;
+        use components::process_restarter::Capability;
+        use core::mem::MaybeUninit;
+        static mut BUF1: MaybeUninit<VirtualMuxAlarm<'static, $A>> = MaybeUninit::uninit();
+        static mut BUF2: MaybeUninit<
+            ProcessRestarter<'static, VirtualMuxAlarm<'static, $A>, Capability>,
+        > = MaybeUninit::uninit();
+        static mut BUF3: [Option<(u32, u32)>; $N] = [None; $N];
+        (&mut BUF1, &mut BUF2, &mut BUF3)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct ProcessRestarterComponent<A: 'static + time::Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + time::Alarm<'static>> ProcessRestarterComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        mux: &'static MuxAlarm<'static, A>,
+    ) -> ProcessRestarterComponent<A> {
+        ProcessRestarterComponent {
+            board_kernel: board_kernel,
+            alarm_mux: mux,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> Component for ProcessRestarterComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            ProcessRestarter<'static, VirtualMuxAlarm<'static, A>, Capability>,
+        >,
+        &'static mut [Option<(u32, u32)>],
+    );
+    type Output = &'static ProcessRestarter<'static, VirtualMuxAlarm<'static, A>, Capability>;
+
+    unsafe fn finalize(&mut self, static_buffer: Self::StaticInput) -> Self::Output {
+        let virtual_alarm = static_init_half!(
+            static_buffer.0,
+            VirtualMuxAlarm<'static, A>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        let restarter = static_init_half!(
+            static_buffer.1,
+            ProcessRestarter<'static, VirtualMuxAlarm<'static, A>, Capability>,
+            ProcessRestarter::new(
+                virtual_alarm,
+                self.board_kernel,
+                static_buffer.2,
+                Capability,
+            )
+        );
+
+        time::Alarm::set_client(virtual_alarm, restarter);
+        self.board_kernel.set_restart_timer(restarter);
+        restarter
+    }
+}
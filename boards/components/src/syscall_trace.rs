@@ -0,0 +1,56 @@
+//! Component for the kernel's syscall trace.
+//!
+//! This provides one `Component`, `SyscallTraceComponent`, which gives the
+//! kernel a buffer to record system calls in and writes the records to the
+//! debug output. The kernel only records system calls if
+//! `trace_syscalls` is enabled in `kernel/src/config.rs`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let trace_clock = static_init!(
+//!     TimeTraceClock<'static, sam4l::ast::Ast>,
+//!     TimeTraceClock::new(&sam4l::ast::AST)
+//! );
+//! let syscall_trace = SyscallTraceComponent::new(board_kernel, trace_clock).finalize(());
+//! pconsole.set_syscall_trace(syscall_trace);
+//! ```
+
+use kernel::common::RingBuffer;
+use kernel::component::Component;
+use kernel::debug::DebugTraceWriter;
+use kernel::static_init;
+use kernel::syscall_trace::{SyscallTrace, TraceClock, TraceRecord};
+
+pub struct SyscallTraceComponent {
+    board_kernel: &'static kernel::Kernel,
+    clock: &'static dyn TraceClock,
+}
+
+impl SyscallTraceComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        clock: &'static dyn TraceClock,
+    ) -> SyscallTraceComponent {
+        SyscallTraceComponent {
+            board_kernel: board_kernel,
+            clock: clock,
+        }
+    }
+}
+
+impl Component for SyscallTraceComponent {
+    type StaticInput = ();
+    type Output = &'static SyscallTrace;
+
+    unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
+        let records = static_init!([TraceRecord; 16], [TraceRecord::EMPTY; 16]);
+        let ring_buffer = static_init!(RingBuffer<'static, TraceRecord>, RingBuffer::new(records));
+        let syscall_trace = static_init!(SyscallTrace, SyscallTrace::new(ring_buffer));
+        let writer = static_init!(DebugTraceWriter, DebugTraceWriter);
+        syscall_trace.set_clock(self.clock);
+        syscall_trace.set_writer(writer);
+        self.board_kernel.set_syscall_trace(syscall_trace);
+        syscall_trace
+    }
+}
@@ -0,0 +1,96 @@
+//! Component for WatchdogService, which services a hardware watchdog from the
+//! kernel loop while watched tasks and processes keep checking in.
+//!
+//! This provides one Component, WatchdogComponent, which installs the service
+//! as the kernel's watchdog, lets it fault processes that miss their deadline
+//! and stops watching processes when they are torn down. The helper macro
+//! takes the number of process slots on the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let watchdog = WatchdogComponent::new(board_kernel, &sam4l::ast::AST, &sam4l::wdt::WDT, 1000)
+//!     .finalize(components::watchdog_component_helper!(
+//!         sam4l::ast::Ast,
+//!         sam4l::wdt::Wdt,
+//!         NUM_PROCS
+//!     ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules::watchdog::WatchdogService;
+use kernel::capabilities;
+use kernel::hil::time::Time;
+use kernel::hil::watchdog::Watchdog;
+use kernel::{static_init, static_init_half, GrantCleanupHook};
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! watchdog_component_helper {
+    ($T:ty, $W:ty, $N:expr) => {{
+        use capsules::watchdog::WatchdogService;
+        use components::watchdog::Capability;
+        use core::mem::MaybeUninit;
+        static mut BUF1: MaybeUninit<WatchdogService<'static, $T, $W, Capability>> =
+            MaybeUninit::uninit();
+        static mut BUF2: [Option<(u32, u32)>; $N] = [None; $N];
+        (&mut BUF1, &mut BUF2)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct WatchdogComponent<T: 'static + Time, W: 'static + Watchdog> {
+    board_kernel: &'static kernel::Kernel,
+    time: &'static T,
+    watchdog: &'static W,
+    period_ms: usize,
+}
+
+impl<T: 'static + Time, W: 'static + Watchdog> WatchdogComponent<T, W> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        time: &'static T,
+        watchdog: &'static W,
+        period_ms: usize,
+    ) -> WatchdogComponent<T, W> {
+        WatchdogComponent {
+            board_kernel: board_kernel,
+            time: time,
+            watchdog: watchdog,
+            period_ms: period_ms,
+        }
+    }
+}
+
+impl<T: 'static + Time, W: 'static + Watchdog> kernel::component::Component
+    for WatchdogComponent<T, W>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<WatchdogService<'static, T, W, Capability>>,
+        &'static mut [Option<(u32, u32)>],
+    );
+    type Output = &'static WatchdogService<'static, T, W, Capability>;
+
+    unsafe fn finalize(&mut self, static_buffer: Self::StaticInput) -> Self::Output {
+        let watchdog = static_init_half!(
+            static_buffer.0,
+            WatchdogService<'static, T, W, Capability>,
+            WatchdogService::new(
+                self.time,
+                self.watchdog,
+                self.period_ms,
+                self.board_kernel,
+                static_buffer.1,
+                Capability,
+            )
+        );
+
+        self.board_kernel.set_watchdog(watchdog);
+        let cleanup_hook = static_init!(GrantCleanupHook, GrantCleanupHook::new(watchdog));
+        self.board_kernel.register_grant_cleanup_hook(cleanup_hook);
+        watchdog
+    }
+}
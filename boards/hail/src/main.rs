@@ -11,13 +11,14 @@ use capsules::virtual_alarm::VirtualMuxAlarm;
 use capsules::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules::virtual_spi::{MuxSpiMaster, VirtualSpiMasterDevice};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
+use kernel::common::deferred_call::DeferredCallClient;
 use kernel::component::Component;
 use kernel::hil;
 use kernel::hil::Controller;
 use kernel::Platform;
+use kernel::ReturnCode;
 #[allow(unused_imports)]
-use kernel::{create_capability, debug, debug_gpio, static_init};
+use kernel::{create_capability, debug, debug_gpio, static_init, storage_volume};
 
 /// Support routines for debugging I/O.
 ///
@@ -43,6 +44,18 @@ static mut PROCESSES: [Option<&'static dyn kernel::procs::ProcessType>; NUM_PROC
     [None; NUM_PROCS];
 static mut CHIP: Option<&'static sam4l::chip::Sam4l> = None;
 
+storage_volume!(
+    /// Flash region for the crash dump of the last kernel panic or app fault.
+    CRASH_DUMP_VOLUME,
+    8
+);
+
+// Page buffer for writing crash dumps.
+static mut CRASH_DUMP_PAGE: [u8; 512] = [0; 512];
+
+// Buffer for the text of a fault dump until it is written to flash.
+static mut CRASH_DUMP_FAULT_TEXT: [u8; 2048] = [0; 2048];
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -67,9 +80,16 @@ struct Hail {
     led: &'static capsules::led::LED<'static>,
     button: &'static capsules::button::Button<'static>,
     rng: &'static capsules::rng::RngDriver<'static>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
     crc: &'static capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
     dac: &'static capsules::dac::Dac<'static>,
+    crash_dump: &'static capsules::crash_dump::CrashDumpDriver,
+    watchdog: &'static capsules::watchdog::WatchdogService<
+        'static,
+        sam4l::ast::Ast<'static>,
+        sam4l::wdt::Wdt,
+        components::watchdog::Capability,
+    >,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -98,8 +118,10 @@ impl Platform for Hail {
             capsules::crc::DRIVER_NUM => f(Some(self.crc)),
 
             capsules::dac::DRIVER_NUM => f(Some(self.dac)),
+            capsules::crash_dump::DRIVER_NUM => f(Some(self.crash_dump)),
+            capsules::watchdog::DRIVER_NUM => f(Some(self.watchdog)),
 
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
@@ -167,6 +189,13 @@ unsafe fn set_pin_primary_functions() {
     PB[15].configure(None); //... D1
 }
 
+/// Reset the board, for the `reboot` command of the process console.
+fn reboot() {
+    unsafe {
+        cortexm4::scb::reset();
+    }
+}
+
 /// Reset Handler.
 ///
 /// This symbol is loaded into vector table by the SAM4L chip crate.
@@ -187,7 +216,7 @@ pub unsafe fn reset_handler() {
 
     set_pin_primary_functions();
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
     // Create capabilities that the board needs to call certain protected kernel
     // functions.
@@ -206,24 +235,19 @@ pub unsafe fn reset_handler() {
     let chip = static_init!(sam4l::chip::Sam4l, sam4l::chip::Sam4l::new());
     CHIP = Some(&chip);
 
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
+    // Let peripherals and capsules limit how deeply the chip sleeps.
+    let power_manager = static_init!(
+        kernel::power::PowerManager,
+        kernel::power::PowerManager::new()
     );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    kernel::power::PowerManager::set_global_instance(power_manager);
 
     // Initialize USART0 for Uart
     sam4l::usart::USART0.set_mode(sam4l::usart::UsartMode::Uart);
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(
-        &sam4l::usart::USART0,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&sam4l::usart::USART0, 115200).finalize(());
     uart_mux.initialize();
 
     hil::uart::Transmit::set_transmit_client(&sam4l::usart::USART0, uart_mux);
@@ -234,6 +258,7 @@ pub unsafe fn reset_handler() {
     let process_console =
         components::process_console::ProcessConsoleComponent::new(board_kernel, uart_mux)
             .finalize(());
+    process_console.set_reboot(reboot, &create_capability!(capabilities::RebootCapability));
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
     // Initialize USART3 for UART for the nRF serialization link.
@@ -370,6 +395,48 @@ pub unsafe fn reset_handler() {
         capsules::dac::Dac::new(&sam4l::dac::DAC)
     );
 
+    // Crash dumps
+    sam4l::flashcalw::FLASH_CONTROLLER.configure();
+    let crash_dump = static_init!(
+        kernel::crash_dump::CrashDump,
+        kernel::crash_dump::CrashDump::new(
+            &sam4l::flashcalw::FLASH_CONTROLLER,
+            &CRASH_DUMP_VOLUME as *const u8 as usize,
+            CRASH_DUMP_VOLUME.len(),
+            &mut CRASH_DUMP_PAGE,
+            &mut CRASH_DUMP_FAULT_TEXT
+        )
+    );
+    // Without its deferred call, kernel panics are still saved, but process
+    // faults are not.
+    if crash_dump.register() != ReturnCode::SUCCESS {
+        debug!("Crash dump: no deferred call left, process faults are not saved");
+    }
+    kernel::crash_dump::set_crash_dump(crash_dump);
+    // Apps can only read the dump if they are listed for the driver with
+    // `board_kernel.set_syscall_permissions()`.
+    let crash_dump_driver = static_init!(
+        capsules::crash_dump::CrashDumpDriver,
+        capsules::crash_dump::CrashDumpDriver::new(
+            crash_dump,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+
+    // Reset the board if the kernel loop stops running, and fault watched
+    // processes that stop checking in.
+    let watchdog = components::watchdog::WatchdogComponent::new(
+        board_kernel,
+        &sam4l::ast::AST,
+        &sam4l::wdt::WDT,
+        1000,
+    )
+    .finalize(components::watchdog_component_helper!(
+        sam4l::ast::Ast<'static>,
+        sam4l::wdt::Wdt,
+        NUM_PROCS
+    ));
+
     // // DEBUG Restart All Apps
     // //
     // // Uncomment to enable a button press to restart all apps.
@@ -404,9 +471,11 @@ pub unsafe fn reset_handler() {
         led: led,
         button: button,
         rng: rng,
-        ipc: kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability),
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
         crc: crc,
         dac: dac,
+        crash_dump: crash_dump_driver,
+        watchdog: watchdog,
     };
 
     // Reset the nRF and setup the UART bus.
@@ -432,9 +501,16 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_management_capability,
     );
-    board_kernel.kernel_loop(&hail, chip, Some(&hail.ipc), &main_loop_capability);
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(
+        &hail,
+        chip,
+        Some(hail.ipc),
+        scheduler,
+        &main_loop_capability,
+    );
 }
@@ -14,7 +14,6 @@
 
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil;
 use kernel::Platform;
@@ -97,15 +96,7 @@ pub unsafe fn reset_handler() {
 
     let main_loop_cap = create_capability!(capabilities::MainLoopCapability);
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
-
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
     // Configure kernel debug gpios as early as possible
     kernel::debug::assign_gpios(
@@ -127,12 +118,8 @@ pub unsafe fn reset_handler() {
     csr::CSR.mstatus.modify(csr::mstatus::mstatus::mie::SET);
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(
-        &e310x::uart::UART0,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&e310x::uart::UART0, 115200).finalize(());
 
     // Initialize some GPIOs which are useful for debugging.
     hil::gpio::Pin::make_output(&e310x::gpio::PORT[22]);
@@ -198,10 +185,11 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_mgmt_cap,
     );
 
-    board_kernel.kernel_loop(&hifive1, chip, None, &main_loop_cap);
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(&hifive1, chip, None, scheduler, &main_loop_cap);
 }
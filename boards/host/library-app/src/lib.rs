@@ -0,0 +1,34 @@
+//! An app for the host simulator that is built separately, as a shared
+//! library, and loaded from a TBF file with `--app`.
+//!
+//! It prints a greeting, waiting for the console to call back once it has
+//! been written.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use host::userspace;
+
+/// Whether the console has finished writing.
+static WRITTEN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn written(_: usize, _: usize, _: usize, _: usize) {
+    WRITTEN.store(true, Ordering::SeqCst);
+}
+
+fn print(text: &str) {
+    let driver = capsules::console::DRIVER_NUM;
+    let buffer = userspace::alloc(text.len());
+    buffer.copy_from_slice(text.as_bytes());
+    userspace::allow_readonly(driver, 1, buffer);
+    userspace::subscribe(driver, 1, Some(written), 0);
+    WRITTEN.store(false, Ordering::SeqCst);
+    if userspace::command(driver, 1, text.len(), 0) == 0 {
+        userspace::yield_for(&|| WRITTEN.load(Ordering::SeqCst));
+    }
+}
+
+extern "C" fn main(_: usize, _: usize, _: usize, _: usize) {
+    print("library: Hello from a TBF file!\n");
+}
+
+host::library_app!(main);
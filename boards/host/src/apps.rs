@@ -0,0 +1,334 @@
+//! Apps built into the simulator.
+//!
+//! - `hello` prints a greeting, and checks that it may not read the crash
+//!   dump. Then it blinks the first LED and prints a tick every half second.
+//!   It checks in with the watchdog on every tick.
+//! - `bootcount` counts how often it has been started in nonvolatile storage,
+//!   which persists across runs when the simulator is given a flash file.
+//! - `crashdump` prints the first line of the crash dump the kernel saved, if
+//!   there is one, and clears it. With `--fault` it then faults, which panics
+//!   the kernel and saves a new dump.
+//! - `ipcservice` is an IPC service that shares a buffer with the first
+//!   client that notifies it, and notifies every client back.
+//! - `ipcclient` notifies `ipcservice` and prints how large the buffer it was
+//!   shared is. The first time it runs it then faults, and the board restarts
+//!   it. The restarted client must not see the buffer that was shared with
+//!   the old one.
+//! - `spinner` computes without making system calls, until `hello` has
+//!   checked, on its first tick, that `spinner` is parked while `hello` runs.
+
+use std::cell::Cell;
+use std::fmt::Write;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use host::tbf::HostApp;
+use host::userspace;
+
+/// Whether the `crashdump` app faults, set by `--fault`.
+pub static FAULT: AtomicBool = AtomicBool::new(false);
+
+/// How often the `ipcclient` app has been started.
+static IPC_CLIENT_STARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the `ipcservice` app has shared its buffer.
+static IPC_SERVICE_SHARED: AtomicBool = AtomicBool::new(false);
+
+/// Length of the buffer the `ipcservice` app shares.
+const IPC_SERVICE_BUFFER_LEN: usize = 16;
+
+/// How many times the `spinner` app went around its loop.
+static SPINS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the `hello` app has checked that `spinner` is parked.
+static SPINNER_CHECKED: AtomicBool = AtomicBool::new(false);
+
+/// The apps in the flash image of the simulator.
+pub const APPS: [HostApp; 6] = [
+    HostApp {
+        name: "hello",
+        main: hello_main,
+        minimum_ram_size: 8192,
+    },
+    HostApp {
+        name: "bootcount",
+        main: bootcount_main,
+        minimum_ram_size: 8192,
+    },
+    HostApp {
+        name: "crashdump",
+        main: crashdump_main,
+        minimum_ram_size: 8192,
+    },
+    HostApp {
+        name: "ipcservice",
+        main: ipcservice_main,
+        minimum_ram_size: 8192,
+    },
+    HostApp {
+        name: "ipcclient",
+        main: ipcclient_main,
+        minimum_ram_size: 8192,
+    },
+    HostApp {
+        name: "spinner",
+        main: spinner_main,
+        minimum_ram_size: 4096,
+    },
+];
+
+/// Callback that sets the `Cell<bool>` its userdata points to.
+extern "C" fn set_done(_: usize, _: usize, _: usize, done: usize) {
+    unsafe { (*(done as *const Cell<bool>)).set(true) };
+}
+
+/// Run callbacks until the operation started by `start` completes, which it
+/// signals with the upcall `subscribe_num` of the driver `driver_num`.
+fn wait_for(driver_num: usize, subscribe_num: usize, start: &dyn Fn() -> isize) -> isize {
+    let done = Cell::new(false);
+    userspace::subscribe(
+        driver_num,
+        subscribe_num,
+        Some(set_done),
+        &done as *const Cell<bool> as usize,
+    );
+    let result = start();
+    if result >= 0 {
+        userspace::yield_for(&|| done.get());
+    }
+    userspace::subscribe(driver_num, subscribe_num, None, 0);
+    result
+}
+
+/// Console output through a buffer in app memory.
+struct Console {
+    buffer: &'static mut [u8],
+    line: String,
+}
+
+impl Console {
+    fn new() -> Console {
+        Console {
+            buffer: userspace::alloc(128),
+            line: String::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        let len = self.line.len().min(self.buffer.len());
+        self.buffer[..len].copy_from_slice(&self.line.as_bytes()[..len]);
+        self.line.clear();
+        // The console writes the end of the allowed buffer, so only allow the
+        // bytes to write.
+        userspace::allow_readonly(capsules::console::DRIVER_NUM, 1, &self.buffer[..len]);
+        wait_for(capsules::console::DRIVER_NUM, 1, &|| {
+            userspace::command(capsules::console::DRIVER_NUM, 1, len, 0)
+        });
+    }
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.line.push_str(s);
+        if self.line.ends_with('\n') {
+            self.flush();
+        }
+        Ok(())
+    }
+}
+
+fn sleep_ms(ms: usize) {
+    let driver = capsules::alarm::DRIVER_NUM;
+    let frequency = userspace::command(driver, 1, 0, 0) as usize;
+    let now = userspace::command(driver, 2, 0, 0) as usize;
+    let expiration = now.wrapping_add(ms * frequency / 1000) as u32;
+    wait_for(driver, 0, &|| {
+        userspace::command(driver, 4, expiration as usize, 0)
+    });
+}
+
+extern "C" fn hello_main(_: usize, _: usize, _: usize, _: usize) {
+    let mut console = Console::new();
+    let _ = writeln!(console, "Hello from a host app!");
+    if userspace::command(capsules::crash_dump::DRIVER_NUM, 1, 0, 0)
+        == isize::from(kernel::ReturnCode::EPERM)
+    {
+        let _ = writeln!(console, "hello: crash dump denied");
+    }
+    userspace::command(capsules::watchdog::DRIVER_NUM, 1, 1000, 0);
+    for tick in 1.. {
+        sleep_ms(500);
+        userspace::command(capsules::watchdog::DRIVER_NUM, 2, 0, 0);
+        userspace::command(capsules::led::DRIVER_NUM, 3, 0, 0);
+        let _ = writeln!(console, "tick {}", tick);
+        if tick == 1 {
+            check_spinner(&mut console);
+        }
+    }
+}
+
+/// Check that the `spinner` app does not run while this app does. If this app
+/// is preempted, `spinner` may run in the meantime, so the check is repeated
+/// until this app was not preempted during it.
+fn check_spinner(console: &mut Console) {
+    let result = (0..100).find_map(|_| {
+        let preemptions = userspace::preemptions();
+        let before = SPINS.load(Ordering::SeqCst);
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(5) {}
+        let after = SPINS.load(Ordering::SeqCst);
+        if userspace::preemptions() == preemptions {
+            Some((before, after))
+        } else {
+            None
+        }
+    });
+    SPINNER_CHECKED.store(true, Ordering::SeqCst);
+    let _ = match result {
+        None => writeln!(console, "spinner: not checked"),
+        Some((0, _)) => writeln!(console, "spinner: not started"),
+        Some((before, after)) if before == after => {
+            writeln!(console, "spinner: parked while hello ran")
+        }
+        Some(_) => writeln!(console, "spinner: ran while hello ran"),
+    };
+}
+
+extern "C" fn spinner_main(_: usize, _: usize, _: usize, _: usize) {
+    // Give up after a while, in case `hello` never checks.
+    let start = Instant::now();
+    while !SPINNER_CHECKED.load(Ordering::SeqCst) && start.elapsed() < Duration::from_secs(5) {
+        SPINS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+extern "C" fn bootcount_main(_: usize, _: usize, _: usize, _: usize) {
+    let driver = capsules::nonvolatile_storage_driver::DRIVER_NUM;
+    let mut console = Console::new();
+    let buffer = userspace::alloc(4);
+
+    userspace::allow(driver, 0, buffer);
+    let result = wait_for(driver, 0, &|| userspace::command(driver, 2 | 4 << 8, 0, 0));
+    userspace::unallow(driver, 0);
+    if result < 0 {
+        let _ = writeln!(console, "bootcount: read failed ({})", result);
+        return;
+    }
+
+    // Erased flash reads as all ones.
+    let count = match u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) {
+        0xffffffff => 1,
+        count => count + 1,
+    };
+    buffer.copy_from_slice(&count.to_le_bytes());
+
+    userspace::allow(driver, 1, buffer);
+    let result = wait_for(driver, 1, &|| userspace::command(driver, 3 | 4 << 8, 0, 0));
+    userspace::unallow(driver, 1);
+    if result < 0 {
+        let _ = writeln!(console, "bootcount: write failed ({})", result);
+        return;
+    }
+    let _ = writeln!(console, "bootcount: started {} times", count);
+}
+
+extern "C" fn crashdump_main(_: usize, _: usize, _: usize, _: usize) {
+    let driver = capsules::crash_dump::DRIVER_NUM;
+    let mut console = Console::new();
+
+    let length = userspace::command(driver, 1, 0, 0);
+    if length > 0 {
+        let kind = match userspace::command(driver, 2, 0, 0) {
+            1 => "kernel panic",
+            2 => "process fault",
+            _ => "unknown",
+        };
+        let buffer = userspace::alloc(128);
+        userspace::allow(driver, 0, buffer);
+        let len = userspace::command(driver, 3, 0, 0).max(0) as usize;
+        userspace::unallow(driver, 0);
+
+        // The first line of the dump says what crashed.
+        let text = String::from_utf8_lossy(&buffer[..len]);
+        let first_line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("");
+        let _ = writeln!(
+            console,
+            "crashdump: {} dump of {} bytes: {}",
+            kind, length, first_line
+        );
+        userspace::command(driver, 4, 0, 0);
+    } else {
+        let _ = writeln!(console, "crashdump: no dump");
+    }
+
+    if FAULT.load(Ordering::Relaxed) {
+        panic!("faulting on purpose");
+    }
+}
+
+extern "C" fn ipcservice_main(_: usize, _: usize, _: usize, _: usize) {
+    let buffer = userspace::alloc(IPC_SERVICE_BUFFER_LEN);
+    userspace::subscribe(
+        kernel::ipc::DRIVER_NUM,
+        0,
+        Some(ipcservice_notified),
+        buffer.as_mut_ptr() as usize,
+    );
+}
+
+/// Called when the client `client` notifies the service, with the service's
+/// buffer as userdata.
+extern "C" fn ipcservice_notified(client: usize, _: usize, _: usize, buffer: usize) {
+    if !IPC_SERVICE_SHARED.swap(true, Ordering::Relaxed) {
+        let buffer =
+            unsafe { slice::from_raw_parts_mut(buffer as *mut u8, IPC_SERVICE_BUFFER_LEN) };
+        userspace::allow(kernel::ipc::DRIVER_NUM, client, buffer);
+    }
+    userspace::command(kernel::ipc::DRIVER_NUM, client, 1, 0);
+}
+
+/// Called when the service notifies the client, with a `Cell<Option<usize>>`
+/// to store the length of the shared buffer in as userdata.
+extern "C" fn ipcclient_notified(_: usize, len: usize, _: usize, shared: usize) {
+    unsafe { (*(shared as *const Cell<Option<usize>>)).set(Some(len)) };
+}
+
+extern "C" fn ipcclient_main(_: usize, _: usize, _: usize, _: usize) {
+    let driver = kernel::ipc::DRIVER_NUM;
+    let start = IPC_CLIENT_STARTS.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut console = Console::new();
+
+    let name = userspace::alloc(10);
+    name.copy_from_slice(b"ipcservice");
+    let service = userspace::allow(driver, 0, name);
+    if service <= 0 {
+        let _ = writeln!(console, "ipcclient: no service ({})", service);
+        return;
+    }
+
+    let shared: Cell<Option<usize>> = Cell::new(None);
+    userspace::subscribe(
+        driver,
+        service as usize,
+        Some(ipcclient_notified),
+        &shared as *const Cell<Option<usize>> as usize,
+    );
+    userspace::command(driver, service as usize, 0, 0);
+    userspace::yield_for(&|| shared.get().is_some());
+    userspace::subscribe(driver, service as usize, None, 0);
+    let _ = writeln!(
+        console,
+        "ipcclient: start {} sees {} shared bytes",
+        start,
+        shared.get().unwrap_or(0)
+    );
+
+    if start == 1 {
+        panic!("faulting to be restarted");
+    }
+}
@@ -0,0 +1,387 @@
+//! Board file for running Tock as a Linux process.
+//!
+//! The kernel runs on the main thread, with the peripherals of the host chip
+//! and the apps in `apps.rs`. See the README for the command line options.
+
+#![deny(missing_docs)]
+
+use std::fs;
+use std::panic;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use capsules::virtual_alarm::VirtualMuxAlarm;
+use kernel::capabilities;
+use kernel::common::deferred_call::DeferredCallClient;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+use kernel::procs::{AppPermissions, DefaultPermission, DriverPermission};
+use kernel::Platform;
+use kernel::ReturnCode;
+use kernel::{create_capability, debug, static_init};
+
+mod apps;
+
+// Number of concurrent processes this platform supports: the built-in apps,
+// and one loaded with `--app`.
+const NUM_PROCS: usize = 7;
+
+// How should the kernel respond when a process faults.
+const FAULT_RESPONSE: kernel::procs::FaultResponse = kernel::procs::FaultResponse::Panic;
+
+// Memory given to the apps packaged with `tock-host package`.
+const LIBRARY_APP_RAM_SIZE: u32 = 8192;
+
+// Size of the RAM shared by all application processes.
+const APP_MEMORY_SIZE: usize = 0x10000;
+
+// Actual memory for holding the active process structures.
+static mut PROCESSES: [Option<&'static dyn kernel::procs::ProcessType>; NUM_PROCS] =
+    [None; NUM_PROCS];
+
+// The chip, for printing its state into crash dumps.
+static mut CHIP: Option<&'static host::chip::Host> = None;
+
+// Page buffer for the nonvolatile storage driver.
+static mut FLASH_PAGEBUFFER: host::flash::HostPage = host::flash::HostPage::new();
+
+// Page buffer for writing crash dumps.
+static mut CRASH_DUMP_PAGE: [u8; host::flash::PAGE_SIZE] = [0; host::flash::PAGE_SIZE];
+
+// Buffer for the text of a fault dump until it is written to flash.
+static mut CRASH_DUMP_FAULT_TEXT: [u8; 4096] = [0; 4096];
+
+// Only the `crashdump` app may read and clear the crash dump.
+static PERMISSIONS: [AppPermissions; 1] = [AppPermissions {
+    app: "crashdump",
+    drivers: &[
+        DriverPermission::driver(capsules::console::DRIVER_NUM),
+        DriverPermission::driver(capsules::crash_dump::DRIVER_NUM),
+    ],
+}];
+
+// The crash dump is kept in the last 8 kB of the simulated flash.
+const CRASH_DUMP_SIZE: usize = 0x2000;
+
+/// A structure representing this platform that holds references to all
+/// capsules for this platform.
+struct HostPlatform {
+    console: &'static capsules::console::Console<'static>,
+    alarm: &'static capsules::alarm::AlarmDriver<
+        'static,
+        VirtualMuxAlarm<'static, host::alarm::HostAlarm<'static>>,
+    >,
+    led: &'static capsules::led::LED<'static>,
+    button: &'static capsules::button::Button<'static>,
+    gpio: &'static capsules::gpio::GPIO<'static>,
+    nonvolatile_storage: &'static capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    crash_dump: &'static capsules::crash_dump::CrashDumpDriver,
+    watchdog: &'static capsules::watchdog::WatchdogService<
+        'static,
+        host::alarm::HostAlarm<'static>,
+        host::watchdog::HostWatchdog,
+        components::watchdog::Capability,
+    >,
+    ipc: &'static kernel::ipc::IPC,
+}
+
+/// Mapping of integer syscalls to objects that implement syscalls.
+impl Platform for HostPlatform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::Driver>) -> R,
+    {
+        match driver_num {
+            capsules::console::DRIVER_NUM => f(Some(self.console)),
+            capsules::alarm::DRIVER_NUM => f(Some(self.alarm)),
+            capsules::led::DRIVER_NUM => f(Some(self.led)),
+            capsules::button::DRIVER_NUM => f(Some(self.button)),
+            capsules::gpio::DRIVER_NUM => f(Some(self.gpio)),
+            capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
+            capsules::crash_dump::DRIVER_NUM => f(Some(self.crash_dump)),
+            capsules::watchdog::DRIVER_NUM => f(Some(self.watchdog)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
+            _ => f(None),
+        }
+    }
+}
+
+/// Command line options.
+struct Options {
+    config: host::chip::Config,
+    /// Exit after this long, for running the simulator in tests.
+    exit_after: Option<Duration>,
+    /// Make the `crashdump` app fault, to test crash dumps.
+    fault: bool,
+    /// TBF files of apps to load after the built-in apps.
+    apps: Vec<PathBuf>,
+}
+
+/// "Reset" the simulator for the `reboot` command of the process console, the
+/// same way the watchdog does.
+fn reboot() {
+    process::exit(host::watchdog::RESET_EXIT_STATUS);
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: tock-host [--flash FILE] [--gpio-in FILE] [--gpio-out FILE] [--exit-after MS] \
+         [--exit-when TEXT]... [--fault] [--app FILE]...\n       \
+         tock-host package NAME LIBRARY OUTPUT"
+    );
+    process::exit(2);
+}
+
+/// Package the shared library `library` as the TBF file `output` of the app
+/// `name`, for `--app`.
+fn package(name: &str, library: &str, output: &str) {
+    let library = fs::read(library).unwrap_or_else(|error| {
+        eprintln!("failed to read {}: {}", library, error);
+        process::exit(1);
+    });
+    let image = host::tbf::library_app_image(name, &library, LIBRARY_APP_RAM_SIZE);
+    if let Err(error) = fs::write(output, image) {
+        eprintln!("failed to write {}: {}", output, error);
+        process::exit(1);
+    }
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        config: Default::default(),
+        exit_after: None,
+        fault: false,
+        apps: Vec::new(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--fault" {
+            options.fault = true;
+            continue;
+        }
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--flash" => options.config.flash = Some(PathBuf::from(value)),
+            "--gpio-in" => options.config.gpio_input = Some(PathBuf::from(value)),
+            "--gpio-out" => options.config.gpio_output = Some(PathBuf::from(value)),
+            "--exit-when" => options.config.exit_when.push(value),
+            "--app" => options.apps.push(PathBuf::from(value)),
+            "--exit-after" => {
+                let ms = value.parse().unwrap_or_else(|_| usage());
+                options.exit_after = Some(Duration::from_millis(ms));
+            }
+            _ => usage(),
+        }
+    }
+    options
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("package") {
+        match &args[2..] {
+            [name, library, output] => package(name, library, output),
+            _ => usage(),
+        }
+        return;
+    }
+    let options = parse_options();
+    unsafe { start(options) }
+}
+
+unsafe fn start(options: Options) {
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
+
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+
+    let chip = static_init!(host::chip::Host, host::chip::Host::new(&options.config));
+    CHIP = Some(chip);
+
+    // Create a shared UART channel for the console and for kernel debug.
+    let uart_mux = components::console::UartMuxComponent::new(&chip.uart, 115200).finalize(());
+    uart_mux.initialize();
+
+    hil::uart::Transmit::set_transmit_client(&chip.uart, uart_mux);
+    hil::uart::Receive::set_receive_client(&chip.uart, uart_mux);
+    chip.uart.start_input();
+
+    // Setup the console and the process inspection console.
+    let console = components::console::ConsoleComponent::new(board_kernel, uart_mux).finalize(());
+    let process_console =
+        components::process_console::ProcessConsoleComponent::new(board_kernel, uart_mux)
+            .finalize(());
+    process_console.set_reboot(reboot, &create_capability!(capabilities::RebootCapability));
+    components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
+
+    // Alarm
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(&chip.alarm).finalize(
+        components::alarm_mux_component_helper!(host::alarm::HostAlarm<'static>),
+    );
+    chip.alarm.set_client(mux_alarm);
+    let alarm = components::alarm::AlarmDriverComponent::new(board_kernel, mux_alarm).finalize(
+        components::alarm_component_helper!(host::alarm::HostAlarm<'static>),
+    );
+
+    // LEDs, buttons and GPIO pins, all on the simulated GPIO port.
+    let led = components::led::LedsComponent::new().finalize(components::led_component_helper!(
+        (&chip.gpio[0], capsules::led::ActivationMode::ActiveHigh),
+        (&chip.gpio[1], capsules::led::ActivationMode::ActiveHigh)
+    ));
+    let button = components::button::ButtonComponent::new(board_kernel).finalize(
+        components::button_component_helper!((
+            &chip.gpio[8],
+            capsules::button::GpioMode::HighWhenPressed,
+            kernel::hil::gpio::FloatingState::PullNone
+        )),
+    );
+    let gpio = components::gpio::GpioComponent::new(board_kernel).finalize(
+        components::gpio_component_helper!(
+            &chip.gpio[12],
+            &chip.gpio[13],
+            &chip.gpio[14],
+            &chip.gpio[15]
+        ),
+    );
+
+    // Nonvolatile storage on the simulated flash: the first half is for apps,
+    // the second half, up to the crash dump, for the kernel.
+    let nv_to_page = static_init!(
+        capsules::nonvolatile_to_pages::NonvolatileToPages<'static, host::flash::HostFlash>,
+        capsules::nonvolatile_to_pages::NonvolatileToPages::new(&chip.flash, &mut FLASH_PAGEBUFFER)
+    );
+    hil::flash::HasClient::set_client(&chip.flash, nv_to_page);
+    let flash_size = chip.flash.number_of_pages() * host::flash::PAGE_SIZE;
+    let nonvolatile_storage = static_init!(
+        capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+        capsules::nonvolatile_storage_driver::NonvolatileStorage::new(
+            nv_to_page,
+            board_kernel.create_grant(&memory_allocation_capability),
+            0,                                // Start address for userspace accessible region
+            flash_size / 2,                   // Length of userspace accessible region
+            flash_size / 2,                   // Start address of kernel region
+            flash_size / 2 - CRASH_DUMP_SIZE, // Length of kernel region
+            &mut capsules::nonvolatile_storage_driver::BUFFER
+        )
+    );
+    hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, nonvolatile_storage);
+
+    // Save a crash dump when the kernel panics or an app faults. Apps fault
+    // by panicking on their own threads, so only panics of the kernel thread
+    // are kernel panics.
+    let crash_dump = static_init!(
+        kernel::crash_dump::CrashDump,
+        kernel::crash_dump::CrashDump::new(
+            &chip.flash,
+            flash_size - CRASH_DUMP_SIZE,
+            CRASH_DUMP_SIZE,
+            &mut CRASH_DUMP_PAGE,
+            &mut CRASH_DUMP_FAULT_TEXT
+        )
+    );
+    // Without its deferred call, kernel panics are still saved, but process
+    // faults are not.
+    if crash_dump.register() != ReturnCode::SUCCESS {
+        debug!("Crash dump: no deferred call left, process faults are not saved");
+    }
+    kernel::crash_dump::set_crash_dump(crash_dump);
+    let default_panic_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        default_panic_hook(panic_info);
+        if thread::current().name() == Some("main") {
+            kernel::crash_dump::record_panic(panic_info, &PROCESSES, &CHIP);
+        }
+    }));
+    let crash_dump_driver = static_init!(
+        capsules::crash_dump::CrashDumpDriver,
+        capsules::crash_dump::CrashDumpDriver::new(
+            crash_dump,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    apps::FAULT.store(options.fault, Ordering::Relaxed);
+
+    // Exit if the kernel loop stops running, and fault the `hello` app if it
+    // stops checking in.
+    let watchdog = components::watchdog::WatchdogComponent::new(
+        board_kernel,
+        &chip.alarm,
+        &chip.watchdog,
+        1000,
+    )
+    .finalize(components::watchdog_component_helper!(
+        host::alarm::HostAlarm<'static>,
+        host::watchdog::HostWatchdog,
+        NUM_PROCS
+    ));
+
+    let platform = HostPlatform {
+        console: console,
+        alarm: alarm,
+        led: led,
+        button: button,
+        gpio: gpio,
+        nonvolatile_storage: nonvolatile_storage,
+        crash_dump: crash_dump_driver,
+        watchdog: watchdog,
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
+    };
+
+    process_console.start();
+
+    if let Some(exit_after) = options.exit_after {
+        thread::spawn(move || {
+            thread::sleep(exit_after);
+            process::exit(0);
+        });
+    }
+
+    debug!("Initialization complete. Entering main loop");
+
+    board_kernel.set_syscall_permissions(&PERMISSIONS, DefaultPermission::AllowAll);
+    let app_files: Vec<Vec<u8>> = options
+        .apps
+        .iter()
+        .map(|path| {
+            fs::read(path).unwrap_or_else(|error| {
+                eprintln!("failed to read {}: {}", path.display(), error);
+                process::exit(1);
+            })
+        })
+        .collect();
+    let app_memory: &'static mut [u8] = Box::leak(vec![0; APP_MEMORY_SIZE].into_boxed_slice());
+    kernel::procs::load_processes(
+        board_kernel,
+        chip,
+        host::tbf::flash_image(&apps::APPS, &app_files).as_ptr(),
+        app_memory,
+        FAULT_RESPONSE,
+        None,
+        &process_management_capability,
+    );
+    // `ipcclient` faults once on purpose to be restarted.
+    board_kernel.process_each_capability(&process_management_capability, |_, process| {
+        if process.get_process_name() == "ipcclient" {
+            process.set_fault_policy(kernel::procs::FaultPolicy::restart_limited(
+                1,
+                FAULT_RESPONSE,
+            ));
+        }
+    });
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(
+        &platform,
+        chip,
+        Some(platform.ipc),
+        scheduler,
+        &main_loop_capability,
+    );
+}
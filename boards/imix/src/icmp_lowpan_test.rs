@@ -27,10 +27,12 @@ use capsules::net::ieee802154::MacAddress;
 use capsules::net::ipv6::ip_utils::IPAddr;
 use capsules::net::ipv6::ipv6::{IP6Packet, IPPayload, TransportHeader};
 use capsules::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules::net::network_interface::{NetworkInterface, IPV6_MIN_MTU};
 use capsules::net::sixlowpan::sixlowpan_compression;
 use capsules::net::sixlowpan::sixlowpan_state::{Sixlowpan, SixlowpanState, TxState};
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use core::cell::Cell;
+use kernel::common::leasable_buffer::LeasableBuffer;
 use kernel::debug;
 use kernel::hil::radio;
 use kernel::hil::time::Frequency;
@@ -56,6 +58,7 @@ pub const TEST_DELAY_MS: u32 = 10000;
 pub const TEST_LOOP: bool = false;
 
 static mut ICMP_PAYLOAD: [u8; 10] = [0; 10];
+static mut ECHO_DATA: [u8; 10] = [0; 10];
 
 pub static mut RF233_BUF: [u8; radio::MAX_BUF_SIZE] = [0 as u8; radio::MAX_BUF_SIZE];
 
@@ -109,6 +112,12 @@ pub unsafe fn initialize_all(
         VirtualMuxAlarm::new(mux_alarm)
     );
 
+    let interface_addresses = static_init!([Option<IPAddr>; 1], [Some(SRC_ADDR)]);
+    let interface = static_init!(
+        NetworkInterface,
+        NetworkInterface::new(interface_addresses, SRC_MAC_ADDR, IPV6_MIN_MTU)
+    );
+
     let ip6_sender = static_init!(
         IP6SendStruct<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>>,
         IP6SendStruct::new(
@@ -118,7 +127,7 @@ pub unsafe fn initialize_all(
             sixlowpan_tx,
             radio_mac,
             DST_MAC_ADDR,
-            SRC_MAC_ADDR
+            interface
         )
     );
     radio_mac.set_transmit_client(ip6_sender);
@@ -219,7 +228,8 @@ impl<A: time::Alarm<'a>> LowpanICMPTest<'a, A> {
 
     fn send_next(&self) {
         let icmp_hdr = ICMP6Header::new(ICMP6Type::Type128); // Echo Request
-        unsafe { self.icmp_sender.send(DST_ADDR, icmp_hdr, &mut ICMP_PAYLOAD) };
+        let payload = unsafe { LeasableBuffer::new(&mut ECHO_DATA) };
+        self.icmp_sender.send(DST_ADDR, icmp_hdr, &payload);
     }
 }
 
@@ -0,0 +1,181 @@
+//! Component to initialize the ICMPv6 layer: the echo responder and
+//! neighbor discovery.
+//!
+//! This provides one Component, ICMP6Component. It receives ICMPv6 messages
+//! through the IP receiver of the UDPMuxComponent, and sends them with its
+//! own IP sender. It returns the neighbor discovery, which must be started
+//! once the radio is.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let nd = ICMP6Component::new(
+//!        mux_mac,
+//!        ip_receive,
+//!        sixlowpan_state,
+//!        neighbor_cache,
+//!        DST_MAC_ADDR,
+//!        serial_num.get_lower_64().to_be_bytes(),
+//!        interface,
+//!        mux_alarm,
+//!    )
+//!    .finalize(());
+//!    ...
+//!    nd.start();
+//! ```
+
+#![allow(dead_code)] // Components are intended to be conditionally included
+
+use capsules;
+use capsules::ieee802154::device::MacDevice;
+use capsules::net::icmpv6::icmpv6::{ICMP6Header, ICMP6Type};
+use capsules::net::icmpv6::icmpv6_echo::ICMP6EchoResponder;
+use capsules::net::icmpv6::icmpv6_nd::NeighborDiscovery;
+use capsules::net::icmpv6::icmpv6_recv::ICMP6RecvStruct;
+use capsules::net::icmpv6::icmpv6_send::ICMP6SendStruct;
+use capsules::net::ieee802154::MacAddress;
+use capsules::net::ipv6::ip_utils::ip6_nh;
+use capsules::net::ipv6::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvStruct};
+use capsules::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules::net::ipv6::neighbor_cache::NeighborCache;
+use capsules::net::network_interface::NetworkInterface;
+use capsules::net::sixlowpan::sixlowpan_state;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+use kernel::static_init;
+use sam4l;
+
+/// The largest amount of data an echo request can carry to be answered.
+const ECHO_DATA_LEN: usize = 64;
+
+/// The largest Neighbor Discovery message that is sent.
+const ND_MESSAGE_LEN: usize = 48;
+
+// The ICMPv6 layer needs a buffer for the IP sender to pass frames to the
+// radio, a buffer for the payload of the IP6_Packet, and a buffer for the
+// messages of each capsule that sends them.
+static mut RF233_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
+static mut ICMP_PAYLOAD: [u8; ECHO_DATA_LEN] = [0; ECHO_DATA_LEN];
+static mut ECHO_BUF: [u8; ECHO_DATA_LEN] = [0; ECHO_DATA_LEN];
+static mut ND_BUF: [u8; ND_MESSAGE_LEN] = [0; ND_MESSAGE_LEN];
+
+pub struct ICMP6Component {
+    mux_mac: &'static capsules::ieee802154::virtual_mac::MuxMac<'static>,
+    ip_receive: &'static IP6RecvStruct<'static>,
+    sixlowpan_state: &'static dyn sixlowpan_state::SixlowpanState<'static>,
+    neighbor_cache: &'static NeighborCache,
+    dst_mac_addr: MacAddress,
+    eui64: [u8; 8],
+    interface: &'static NetworkInterface,
+    alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+}
+
+impl ICMP6Component {
+    pub fn new(
+        mux_mac: &'static capsules::ieee802154::virtual_mac::MuxMac<'static>,
+        ip_receive: &'static IP6RecvStruct<'static>,
+        sixlowpan_state: &'static dyn sixlowpan_state::SixlowpanState<'static>,
+        neighbor_cache: &'static NeighborCache,
+        dst_mac_addr: MacAddress,
+        eui64: [u8; 8],
+        interface: &'static NetworkInterface,
+        alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    ) -> ICMP6Component {
+        ICMP6Component {
+            mux_mac: mux_mac,
+            ip_receive: ip_receive,
+            sixlowpan_state: sixlowpan_state,
+            neighbor_cache: neighbor_cache,
+            dst_mac_addr: dst_mac_addr,
+            eui64: eui64,
+            interface: interface,
+            alarm_mux: alarm,
+        }
+    }
+}
+
+impl Component for ICMP6Component {
+    type StaticInput = ();
+    type Output =
+        &'static NeighborDiscovery<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>>;
+
+    unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
+        let ipsender_virtual_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        let nd_virtual_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+
+        // Received frames reach the 6LoWPAN layer through the MAC user of
+        // the UDP stack, so this one only transmits.
+        let icmp_mac = static_init!(
+            capsules::ieee802154::virtual_mac::MacUser<'static>,
+            capsules::ieee802154::virtual_mac::MacUser::new(self.mux_mac)
+        );
+        self.mux_mac.add_user(icmp_mac);
+
+        let sixlowpan_tx = sixlowpan_state::TxState::new(self.sixlowpan_state);
+
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type129)),
+            payload: &mut ICMP_PAYLOAD,
+        };
+        let ip6_dg = static_init!(IP6Packet<'static>, IP6Packet::new(ip_pyld));
+
+        let ip_send = static_init!(
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            IP6SendStruct::new(
+                ip6_dg,
+                ipsender_virtual_alarm,
+                &mut RF233_BUF,
+                sixlowpan_tx,
+                icmp_mac,
+                self.dst_mac_addr,
+                self.interface,
+            )
+        );
+        ipsender_virtual_alarm.set_client(ip_send);
+        ip_send.set_neighbor_cache(self.neighbor_cache);
+        icmp_mac.set_transmit_client(ip_send);
+
+        let icmp_send = static_init!(
+            ICMP6SendStruct<
+                'static,
+                IP6SendStruct<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            >,
+            ICMP6SendStruct::new(ip_send)
+        );
+        ip_send.set_client(icmp_send);
+
+        let icmp_recv = static_init!(ICMP6RecvStruct<'static>, ICMP6RecvStruct::new());
+        self.ip_receive.set_protocol_client(ip6_nh::ICMP, icmp_recv);
+
+        let echo_responder = static_init!(
+            ICMP6EchoResponder<'static>,
+            ICMP6EchoResponder::new(icmp_send, self.interface, &mut ECHO_BUF)
+        );
+        icmp_recv.add_client(echo_responder);
+
+        let nd = static_init!(
+            NeighborDiscovery<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            NeighborDiscovery::new(
+                icmp_send,
+                self.neighbor_cache,
+                nd_virtual_alarm,
+                self.interface,
+                self.eui64,
+                &mut ND_BUF,
+            )
+        );
+        nd_virtual_alarm.set_client(nd);
+        icmp_recv.add_client(nd);
+
+        nd
+    }
+}
@@ -1,9 +1,11 @@
 pub mod adc;
 pub mod analog_comparator;
 pub mod fxos8700;
+pub mod icmp6;
 pub mod nonvolatile_storage;
 pub mod radio;
 pub mod rf233;
+pub mod tcp_driver;
 pub mod test;
 pub mod udp_driver;
 pub mod udp_mux;
@@ -12,9 +14,11 @@ pub mod usb;
 pub use self::adc::AdcComponent;
 pub use self::analog_comparator::AcComponent;
 pub use self::fxos8700::NineDofComponent;
+pub use self::icmp6::ICMP6Component;
 pub use self::nonvolatile_storage::NonvolatileStorageComponent;
 pub use self::radio::RadioComponent;
 pub use self::rf233::RF233Component;
+pub use self::tcp_driver::TCPDriverComponent;
 pub use self::udp_driver::UDPDriverComponent;
 pub use self::udp_mux::UDPMuxComponent;
 pub use self::usb::UsbComponent;
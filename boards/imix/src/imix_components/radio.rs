@@ -7,7 +7,9 @@
 //! Usage
 //! -----
 //! ```rust
-//! let (radio_driver, mux_mac) = RadioComponent::new(rf233, PAN_ID, 0x1008).finalize(());
+//! let (radio_driver, mux_mac) =
+//!     RadioComponent::new(board_kernel, rf233, PAN_ID, 0x1008, mux_alarm, frame_counters)
+//!         .finalize(());
 //! ```
 
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -16,7 +18,9 @@
 #![allow(dead_code)] // Components are intended to be conditionally included
 
 use capsules::ieee802154::device::MacDevice;
+use capsules::ieee802154::frame_counter::FrameCounters;
 use capsules::ieee802154::mac::{AwakeMac, Mac};
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_spi::VirtualSpiMasterDevice;
 
 use kernel::capabilities;
@@ -26,6 +30,7 @@ use kernel::hil::radio;
 use kernel::hil::radio::RadioData;
 use kernel::hil::symmetric_encryption;
 use kernel::hil::symmetric_encryption::{AES128, AES128CCM};
+use kernel::hil::time::Alarm;
 use kernel::static_init;
 
 // Save some deep nesting
@@ -37,6 +42,8 @@ pub struct RadioComponent {
     rf233: &'static RF233Device,
     pan_id: capsules::net::ieee802154::PanID,
     short_addr: u16,
+    alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    frame_counters: &'static FrameCounters<'static>,
 }
 
 impl RadioComponent {
@@ -45,12 +52,16 @@ impl RadioComponent {
         rf233: &'static RF233Device,
         pan_id: capsules::net::ieee802154::PanID,
         addr: u16,
+        alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+        frame_counters: &'static FrameCounters<'static>,
     ) -> RadioComponent {
         RadioComponent {
             board_kernel: board_kernel,
             rf233: rf233,
             pan_id: pan_id,
             short_addr: addr,
+            alarm_mux: alarm,
+            frame_counters: frame_counters,
         }
     }
 }
@@ -62,6 +73,9 @@ static mut RADIO_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
 // The buffer RF233 packets are received into.
 static mut RF233_RX_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
 
+// The buffer the framer sends beacons and MAC commands from.
+static mut MLME_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
+
 // This buffer is used as an intermediate buffer for AES CCM encryption
 // An upper bound on the required size is 3 * BLOCK_SIZE + radio::MAX_BUF_SIZE
 const CRYPT_SIZE: usize = 3 * symmetric_encryption::AES128_BLOCK_SIZE + radio::MAX_BUF_SIZE;
@@ -90,18 +104,30 @@ impl Component for RadioComponent {
         self.rf233.set_transmit_client(awake_mac);
         self.rf233.set_receive_client(awake_mac, &mut RF233_RX_BUF);
 
+        let mlme_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
         let mac_device = static_init!(
             capsules::ieee802154::framer::Framer<
                 'static,
                 AwakeMac<'static, RF233Device>,
                 capsules::aes_ccm::AES128CCM<'static, sam4l::aes::Aes<'static>>,
+                VirtualMuxAlarm<'static, sam4l::ast::Ast>,
             >,
-            capsules::ieee802154::framer::Framer::new(awake_mac, aes_ccm)
+            capsules::ieee802154::framer::Framer::new(
+                awake_mac,
+                aes_ccm,
+                mlme_alarm,
+                &mut MLME_BUF
+            )
         );
         aes_ccm.set_client(mac_device);
         awake_mac.set_transmit_client(mac_device);
         awake_mac.set_receive_client(mac_device);
         awake_mac.set_config_client(mac_device);
+        awake_mac.set_energy_detect_client(mac_device);
+        mlme_alarm.set_client(mac_device);
 
         let mux_mac = static_init!(
             capsules::ieee802154::virtual_mac::MuxMac<'static>,
@@ -109,6 +135,7 @@ impl Component for RadioComponent {
         );
         mac_device.set_transmit_client(mux_mac);
         mac_device.set_receive_client(mux_mac);
+        mac_device.set_mlme_client(mux_mac);
 
         let radio_mac = static_init!(
             capsules::ieee802154::virtual_mac::MacUser<'static>,
@@ -127,8 +154,11 @@ impl Component for RadioComponent {
 
         mac_device.set_key_procedure(radio_driver);
         mac_device.set_device_procedure(radio_driver);
+        mac_device.set_frame_counter_procedure(self.frame_counters);
+        radio_driver.set_frame_counters(self.frame_counters);
         radio_mac.set_transmit_client(radio_driver);
         radio_mac.set_receive_client(radio_driver);
+        radio_mac.set_mlme_client(radio_driver);
         radio_mac.set_pan(self.pan_id);
         radio_mac.set_address(self.short_addr);
 
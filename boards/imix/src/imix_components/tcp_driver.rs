@@ -0,0 +1,198 @@
+//! Component to initialize the TCP/6lowpan interface and its userspace
+//! driver.
+//!
+//! This provides one Component, TCPDriverComponent. It creates a TCP stack
+//! on top of the IP receiver and 6LoWPAN state of the UDPMuxComponent, with
+//! its own IP sender and a virtual RNG device for the secret of its initial
+//! sequence numbers, and the TCP driver with `NUM_SOCKETS` sockets.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let tcp_driver = TCPDriverComponent::new(
+//!        board_kernel,
+//!        mux_mac,
+//!        ip_receive,
+//!        sixlowpan_state,
+//!        DST_MAC_ADDR,
+//!        interface,
+//!        neighbor_cache,
+//!        mux_alarm,
+//!        mux_rng,
+//!    )
+//!    .finalize(());
+//! ```
+
+#![allow(dead_code)] // Components are intended to be conditionally included
+
+use capsules;
+use capsules::ieee802154::device::MacDevice;
+use capsules::net::ieee802154::MacAddress;
+use capsules::net::ipv6::ip_utils::ip6_nh;
+use capsules::net::ipv6::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvStruct};
+use capsules::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules::net::ipv6::neighbor_cache::NeighborCache;
+use capsules::net::network_interface::NetworkInterface;
+use capsules::net::sixlowpan::sixlowpan_state;
+use capsules::net::tcp::driver::TCPDriverSocket;
+use capsules::net::tcp::tcp::{TCPHeader, TCP_HDR_LEN};
+use capsules::net::tcp::tcp_socket::MuxTcp;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules::virtual_rng::{MuxRng, VirtualRngDevice};
+use kernel;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::radio;
+use kernel::hil::rng::Rng;
+use kernel::hil::time::Alarm;
+use kernel::{create_capability, static_init};
+use sam4l;
+
+/// The number of connections that can be open at once.
+pub const NUM_SOCKETS: usize = 2;
+
+/// The largest TCP payload, which must fit in a 6LoWPAN datagram along with
+/// the TCP header.
+pub const MAX_SEGMENT_SIZE: usize = 200 - TCP_HDR_LEN;
+
+// Like the UDP stack, the TCP stack needs a buffer for the IP sender to pass
+// frames to the radio, a buffer for the payload of the IP6_Packet, and a
+// buffer per socket for the data the socket sends.
+static mut RF233_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
+static mut TCP_SEGMENT: [u8; MAX_SEGMENT_SIZE] = [0; MAX_SEGMENT_SIZE];
+static mut SOCKET_BUF_0: [u8; MAX_SEGMENT_SIZE] = [0; MAX_SEGMENT_SIZE];
+static mut SOCKET_BUF_1: [u8; MAX_SEGMENT_SIZE] = [0; MAX_SEGMENT_SIZE];
+
+pub struct TCPDriverComponent {
+    board_kernel: &'static kernel::Kernel,
+    mux_mac: &'static capsules::ieee802154::virtual_mac::MuxMac<'static>,
+    ip_receive: &'static IP6RecvStruct<'static>,
+    sixlowpan_state: &'static dyn sixlowpan_state::SixlowpanState<'static>,
+    dst_mac_addr: MacAddress,
+    interface: &'static NetworkInterface,
+    neighbor_cache: &'static NeighborCache,
+    alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    rng_mux: &'static MuxRng<'static>,
+}
+
+impl TCPDriverComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        mux_mac: &'static capsules::ieee802154::virtual_mac::MuxMac<'static>,
+        ip_receive: &'static IP6RecvStruct<'static>,
+        sixlowpan_state: &'static dyn sixlowpan_state::SixlowpanState<'static>,
+        dst_mac_addr: MacAddress,
+        interface: &'static NetworkInterface,
+        neighbor_cache: &'static NeighborCache,
+        alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+        rng: &'static MuxRng<'static>,
+    ) -> TCPDriverComponent {
+        TCPDriverComponent {
+            board_kernel: board_kernel,
+            mux_mac: mux_mac,
+            ip_receive: ip_receive,
+            sixlowpan_state: sixlowpan_state,
+            dst_mac_addr: dst_mac_addr,
+            interface: interface,
+            neighbor_cache: neighbor_cache,
+            alarm_mux: alarm,
+            rng_mux: rng,
+        }
+    }
+}
+
+impl Component for TCPDriverComponent {
+    type StaticInput = ();
+    type Output = &'static capsules::net::tcp::TCPDriver<'static>;
+
+    unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let ipsender_virtual_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        let tcp_virtual_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+
+        // Received frames reach the 6LoWPAN layer through the MAC user of
+        // the UDP stack, so this one only transmits.
+        let tcp_mac = static_init!(
+            capsules::ieee802154::virtual_mac::MacUser<'static>,
+            capsules::ieee802154::virtual_mac::MacUser::new(self.mux_mac)
+        );
+        self.mux_mac.add_user(tcp_mac);
+
+        let sixlowpan_tx = sixlowpan_state::TxState::new(self.sixlowpan_state);
+
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::TCP(TCPHeader::new()),
+            payload: &mut TCP_SEGMENT,
+        };
+        let ip6_dg = static_init!(IP6Packet<'static>, IP6Packet::new(ip_pyld));
+
+        let ip_send = static_init!(
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            IP6SendStruct::new(
+                ip6_dg,
+                ipsender_virtual_alarm,
+                &mut RF233_BUF,
+                sixlowpan_tx,
+                tcp_mac,
+                self.dst_mac_addr,
+                self.interface,
+            )
+        );
+        ipsender_virtual_alarm.set_client(ip_send);
+        ip_send.set_neighbor_cache(self.neighbor_cache);
+        tcp_mac.set_transmit_client(ip_send);
+
+        let tcp_rng = static_init!(
+            VirtualRngDevice<'static>,
+            VirtualRngDevice::new(self.rng_mux)
+        );
+        tcp_rng.setup();
+
+        let tcp_mux = static_init!(
+            MuxTcp<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            MuxTcp::new(ip_send, tcp_virtual_alarm, tcp_rng, MAX_SEGMENT_SIZE)
+        );
+        tcp_virtual_alarm.set_client(tcp_mux);
+        tcp_rng.set_client(tcp_mux);
+        ip_send.set_client(tcp_mux);
+        self.ip_receive.set_protocol_client(ip6_nh::TCP, tcp_mux);
+
+        let sockets = static_init!(
+            [TCPDriverSocket<'static>; NUM_SOCKETS],
+            [
+                TCPDriverSocket::new(0, &mut SOCKET_BUF_0),
+                TCPDriverSocket::new(1, &mut SOCKET_BUF_1),
+            ]
+        );
+
+        let tcp_driver = static_init!(
+            capsules::net::tcp::TCPDriver<'static>,
+            capsules::net::tcp::TCPDriver::new(
+                tcp_mux,
+                self.board_kernel.create_grant(&grant_cap),
+                sockets,
+            )
+        );
+        for socket in sockets.iter() {
+            socket.get_socket().set_client(tcp_driver);
+            tcp_mux.add_socket(socket.get_socket());
+        }
+
+        let cleanup_hook = static_init!(
+            kernel::GrantCleanupHook,
+            kernel::GrantCleanupHook::new(tcp_driver)
+        );
+        self.board_kernel.register_grant_cleanup_hook(cleanup_hook);
+
+        tcp_mux.start();
+        tcp_driver
+    }
+}
@@ -11,7 +11,7 @@
 //!        udp_send_mux,
 //!        udp_recv_mux,
 //!        udp_port_table,
-//!        local_ip_ifaces,
+//!        interface,
 //!        PAYLOAD_LEN,
 //!     )
 //!     .finalize();
@@ -24,8 +24,8 @@
 #![allow(dead_code)] // Components are intended to be conditionally included
 
 use capsules;
-use capsules::net::ipv6::ip_utils::IPAddr;
 use capsules::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules::net::network_interface::NetworkInterface;
 use capsules::net::udp::udp_port_table::UdpPortManager;
 use capsules::net::udp::udp_recv::MuxUdpReceiver;
 use capsules::net::udp::udp_recv::UDPReceiver;
@@ -51,7 +51,7 @@ pub struct UDPDriverComponent {
     >,
     udp_recv_mux: &'static MuxUdpReceiver<'static>,
     port_table: &'static UdpPortManager,
-    interface_list: &'static [IPAddr],
+    interface: &'static NetworkInterface,
 }
 
 impl UDPDriverComponent {
@@ -63,14 +63,14 @@ impl UDPDriverComponent {
         >,
         udp_recv_mux: &'static MuxUdpReceiver<'static>,
         port_table: &'static UdpPortManager,
-        interface_list: &'static [IPAddr],
+        interface: &'static NetworkInterface,
     ) -> UDPDriverComponent {
         UDPDriverComponent {
             board_kernel: board_kernel,
             udp_send_mux: udp_send_mux,
             udp_recv_mux: udp_recv_mux,
             port_table: port_table,
-            interface_list: interface_list,
+            interface: interface,
         }
     }
 }
@@ -102,7 +102,7 @@ impl Component for UDPDriverComponent {
             capsules::net::udp::UDPDriver::new(
                 udp_send,
                 self.board_kernel.create_grant(&grant_cap),
-                self.interface_list,
+                self.interface,
                 PAYLOAD_LEN,
                 self.port_table,
                 kernel::common::leasable_buffer::LeasableBuffer::new(&mut DRIVER_BUF),
@@ -110,11 +110,18 @@ impl Component for UDPDriverComponent {
             )
         );
         udp_send.set_client(udp_driver);
+        self.interface.set_client(udp_driver);
         self.port_table.set_user_ports(udp_driver, &DRIVER_CAP);
 
         let udp_driver_rcvr = static_init!(UDPReceiver<'static>, UDPReceiver::new());
         self.udp_recv_mux.set_driver(udp_driver);
         self.udp_recv_mux.add_client(udp_driver_rcvr);
+
+        let cleanup_hook = static_init!(
+            kernel::GrantCleanupHook,
+            kernel::GrantCleanupHook::new(udp_driver)
+        );
+        self.board_kernel.register_grant_cleanup_hook(cleanup_hook);
         udp_driver
     }
 }
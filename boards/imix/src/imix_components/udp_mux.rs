@@ -2,20 +2,21 @@
 //!
 //! This provides one Component, UDPMuxComponent. This component
 //! exposes a MuxUdpSender that other components can implement
-//! UDPSenders on top of to use the UDP/6Lowpan stack.
+//! UDPSenders on top of to use the UDP/6Lowpan stack. It also exposes
+//! the IP receiver and the 6LoWPAN state, so that other transport
+//! protocols can share the interface.
 //!
 //! Usage
 //! -----
 //! ```rust
-//!    let (udp_mux, udp_recv) = UDPMuxComponent::new(
+//!    let (udp_mux, udp_recv, port_table, ip_recv, sixlowpan) = UDPMuxComponent::new(
 //!        mux_mac,
 //!        DEFAULT_CTX_PREFIX_LEN,
 //!        DEFAULT_CTX_PREFIX,
 //!        DST_MAC_ADDR,
-//!        src_mac_from_serial_num,
-//!        local_ip_ifaces,
+//!        interface,
+//!        neighbor_cache,
 //!        mux_alarm,
-//!        PAYLOAD_LEN,
 //!    )
 //!    .finalize();
 //! ```
@@ -28,11 +29,12 @@
 use capsules;
 use capsules::ieee802154::device::MacDevice;
 use capsules::net::ieee802154::MacAddress;
-use capsules::net::ipv6::ip_utils::IPAddr;
 use capsules::net::ipv6::ipv6::{IP6Packet, IPPayload, TransportHeader};
-use capsules::net::ipv6::ipv6_recv::IP6Receiver;
+use capsules::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvStruct};
 use capsules::net::ipv6::ipv6_send::IP6SendStruct;
 use capsules::net::ipv6::ipv6_send::IP6Sender;
+use capsules::net::ipv6::neighbor_cache::NeighborCache;
+use capsules::net::network_interface::NetworkInterface;
 use capsules::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
 use capsules::net::udp::udp::UDPHeader;
 use capsules::net::udp::udp_port_table::{SocketBindingEntry, UdpPortManager, MAX_NUM_BOUND_PORTS};
@@ -81,8 +83,8 @@ pub struct UDPMuxComponent {
     ctx_pfix_len: u8,
     ctx_pfix: [u8; 16],
     dst_mac_addr: MacAddress,
-    src_mac_addr: MacAddress,
-    interface_list: &'static [IPAddr],
+    interface: &'static NetworkInterface,
+    neighbor_cache: &'static NeighborCache,
     alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
 }
 
@@ -92,8 +94,8 @@ impl UDPMuxComponent {
         ctx_pfix_len: u8,
         ctx_pfix: [u8; 16],
         dst_mac_addr: MacAddress,
-        src_mac_addr: MacAddress,
-        interface_list: &'static [IPAddr],
+        interface: &'static NetworkInterface,
+        neighbor_cache: &'static NeighborCache,
         alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
     ) -> UDPMuxComponent {
         UDPMuxComponent {
@@ -101,8 +103,8 @@ impl UDPMuxComponent {
             ctx_pfix_len: ctx_pfix_len,
             ctx_pfix: ctx_pfix,
             dst_mac_addr: dst_mac_addr,
-            src_mac_addr: src_mac_addr,
-            interface_list: interface_list,
+            interface: interface,
+            neighbor_cache: neighbor_cache,
             alarm_mux: alarm,
         }
     }
@@ -117,6 +119,8 @@ impl Component for UDPMuxComponent {
         >,
         &'static MuxUdpReceiver<'static>,
         &'static UdpPortManager,
+        &'static IP6RecvStruct<'static>,
+        &'static dyn sixlowpan_state::SixlowpanState<'static>,
     );
 
     unsafe fn finalize(&mut self, _s: Self::StaticInput) -> Self::Output {
@@ -164,12 +168,9 @@ impl Component for UDPMuxComponent {
         };
         let ip6_dg = static_init!(IP6Packet<'static>, IP6Packet::new(ip_pyld));
 
-        // In current design, all udp senders share same IP sender, and the IP sender
-        // holds the destination mac address. This means all UDP senders must send to
-        // the same mac address...this works fine under the assumption
-        // of all packets being routed via a single gateway router, but doesn't work
-        // if multiple senders want to send to different addresses on a local network.
-        // This will be fixed once we have an ipv6_nd cache mapping IP addresses to dst macs
+        // All udp senders share the same IP sender, which finds the destination
+        // mac address of each packet in the neighbor cache. The destination mac
+        // address passed here is only used for packets the cache cannot resolve.
         let ip_send = static_init!(
             capsules::net::ipv6::ipv6_send::IP6SendStruct<
                 'static,
@@ -182,16 +183,12 @@ impl Component for UDPMuxComponent {
                 sixlowpan_tx,
                 udp_mac,
                 self.dst_mac_addr,
-                self.src_mac_addr,
+                self.interface,
             )
         );
         ipsender_virtual_alarm.set_client(ip_send);
 
-        // Initially, set src IP of the sender to be the first IP in the Interface
-        // list. Userland apps can change this if they so choose.
-        // Notably, the src addr is the same regardless of if messages are sent from
-        // userland or capsules.
-        ip_send.set_addr(self.interface_list[0]);
+        ip_send.set_neighbor_cache(self.neighbor_cache);
         udp_mac.set_transmit_client(ip_send);
 
         let ip_receive = static_init!(
@@ -199,6 +196,7 @@ impl Component for UDPMuxComponent {
             capsules::net::ipv6::ipv6_recv::IP6RecvStruct::new()
         );
         sixlowpan_state.set_rx_client(ip_receive);
+        sixlowpan_state.set_interface(self.interface);
         let udp_recv_mux = static_init!(MuxUdpReceiver<'static>, MuxUdpReceiver::new());
         ip_receive.set_client(udp_recv_mux);
 
@@ -220,6 +218,12 @@ impl Component for UDPMuxComponent {
             UdpPortManager::new(&create_table_cap, &mut USED_KERNEL_PORTS)
         );
 
-        (udp_send_mux, udp_recv_mux, udp_port_table)
+        (
+            udp_send_mux,
+            udp_recv_mux,
+            udp_port_table,
+            ip_receive,
+            sixlowpan_state,
+        )
     }
 }
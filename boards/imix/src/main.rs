@@ -12,18 +12,19 @@ mod imix_components;
 use capsules::alarm::AlarmDriver;
 use capsules::net::ieee802154::MacAddress;
 use capsules::net::ipv6::ip_utils::IPAddr;
+use capsules::net::ipv6::neighbor_cache::{Neighbor, NeighborCache};
+use capsules::net::network_interface::{NetworkInterface, IPV6_MIN_MTU};
 use capsules::virtual_alarm::VirtualMuxAlarm;
 use capsules::virtual_i2c::MuxI2C;
 use capsules::virtual_spi::{MuxSpiMaster, VirtualSpiMasterDevice};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil::radio;
 #[allow(unused_imports)]
 use kernel::hil::radio::{RadioConfig, RadioData};
 use kernel::hil::Controller;
 #[allow(unused_imports)]
-use kernel::{create_capability, debug, debug_gpio, static_init};
+use kernel::{create_capability, debug, debug_gpio, static_init, storage_volume};
 
 use components;
 use components::alarm::{AlarmDriverComponent, AlarmMuxComponent};
@@ -34,16 +35,21 @@ use components::gpio::GpioComponent;
 use components::isl29035::AmbientLightComponent;
 use components::led::LedsComponent;
 use components::nrf51822::Nrf51822Component;
+use components::pan_joiner::PanJoinerComponent;
 use components::process_console::ProcessConsoleComponent;
-use components::rng::RngComponent;
+use components::process_restarter::ProcessRestarterComponent;
+use components::rng::{RngDriverComponent, RngMuxComponent};
 use components::si7021::{HumidityComponent, SI7021Component, TemperatureComponent};
 use components::spi::{SpiComponent, SpiSyscallComponent};
+use components::syscall_trace::SyscallTraceComponent;
 use imix_components::adc::AdcComponent;
 use imix_components::analog_comparator::AcComponent;
 use imix_components::fxos8700::NineDofComponent;
+use imix_components::icmp6::ICMP6Component;
 use imix_components::nonvolatile_storage::NonvolatileStorageComponent;
 use imix_components::radio::RadioComponent;
 use imix_components::rf233::RF233Component;
+use imix_components::tcp_driver::TCPDriverComponent;
 use imix_components::udp_driver::UDPDriverComponent;
 use imix_components::udp_mux::UDPMuxComponent;
 use imix_components::usb::UsbComponent;
@@ -97,10 +103,21 @@ const DST_MAC_ADDR: MacAddress = MacAddress::Short(49138);
 const DEFAULT_CTX_PREFIX_LEN: u8 = 8; //Length of context for 6LoWPAN compression
 const DEFAULT_CTX_PREFIX: [u8; 16] = [0x0 as u8; 16]; //Context for 6LoWPAN Compression
 const PAN_ID: u16 = 0xABCD;
+// Set to true to have the board scan for a PAN to join at boot. If it finds
+// none, it starts the PAN above as its coordinator.
+const JOIN_PAN: bool = false;
 
 // how should the kernel respond when a process faults
 const FAULT_RESPONSE: kernel::procs::FaultResponse = kernel::procs::FaultResponse::Panic;
 
+// Public key apps must be signed with (`tools/tbf_sign --ed25519`) to be
+// loaded. Without a public key, apps need an HMAC-SHA256 with the key below
+// (`tools/tbf_sign --hmac-sha256`), or without that key only a SHA-256 hash
+// (`tools/tbf_sign --sha256`), which protects against corrupted apps. Apps
+// that are rejected are skipped.
+const APP_SIGNING_PUBLIC_KEY: Option<&[u8; 32]> = None;
+const APP_CREDENTIALS_KEY: Option<&[u8]> = None;
+
 #[link_section = ".app_memory"]
 static mut APP_MEMORY: [u8; 32768] = [0; 32768];
 
@@ -108,6 +125,12 @@ static mut PROCESSES: [Option<&'static dyn kernel::procs::ProcessType>; NUM_PROC
     [None; NUM_PROCS];
 static mut CHIP: Option<&'static sam4l::chip::Sam4l> = None;
 
+storage_volume!(
+    /// Flash region where the 802.15.4 outgoing frame counter is persisted.
+    FRAME_COUNTER_VOLUME,
+    1
+);
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -133,10 +156,11 @@ struct Imix {
         sam4l::acifc::Acifc<'static>,
     >,
     spi: &'static capsules::spi::Spi<'static, VirtualSpiMasterDevice<'static, sam4l::spi::SpiHw>>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
     ninedof: &'static capsules::ninedof::NineDof<'static>,
     radio_driver: &'static capsules::ieee802154::RadioDriver<'static>,
     udp_driver: &'static capsules::net::udp::UDPDriver<'static>,
+    tcp_driver: &'static capsules::net::tcp::TCPDriver<'static>,
     crc: &'static capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
     usb_driver: &'static capsules::usb::usb_user::UsbSyscallDriver<
         'static,
@@ -181,10 +205,11 @@ impl kernel::Platform for Imix {
             capsules::usb::usb_user::DRIVER_NUM => f(Some(self.usb_driver)),
             capsules::ieee802154::DRIVER_NUM => f(Some(self.radio_driver)),
             capsules::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules::net::tcp::DRIVER_NUM => f(Some(self.tcp_driver)),
             capsules::nrf51822_serialization::DRIVER_NUM => f(Some(self.nrf51822)),
             capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
             capsules::rng::DRIVER_NUM => f(Some(self.rng)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
@@ -261,6 +286,13 @@ unsafe fn set_pin_primary_functions() {
     PC[31].configure(None); //... D2          -- GPIO Pin
 }
 
+/// Reset the board, for the `reboot` command of the process console.
+fn reboot() {
+    unsafe {
+        cortexm4::scb::reset();
+    }
+}
+
 /// Reset Handler.
 ///
 /// This symbol is loaded into vector table by the SAM4L chip crate.
@@ -285,7 +317,6 @@ pub unsafe fn reset_handler() {
     // functions.
     let process_mgmt_cap = create_capability!(capabilities::ProcessManagementCapability);
     let main_cap = create_capability!(capabilities::MainLoopCapability);
-    let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
     power::configure_submodules(power::SubmoduleConfig {
         rf233: true,
@@ -294,23 +325,22 @@ pub unsafe fn reset_handler() {
         trng: true,
     });
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
+    // Let peripherals and capsules limit how deeply the chip sleeps.
+    let power_manager = static_init!(
+        kernel::power::PowerManager,
+        kernel::power::PowerManager::new()
     );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    kernel::power::PowerManager::set_global_instance(power_manager);
 
     // # CONSOLE
     // Create a shared UART channel for the consoles and for kernel debug.
     sam4l::usart::USART3.set_mode(sam4l::usart::UsartMode::Uart);
-    let uart_mux =
-        UartMuxComponent::new(&sam4l::usart::USART3, 115200, dynamic_deferred_caller).finalize(());
+    let uart_mux = UartMuxComponent::new(&sam4l::usart::USART3, 115200).finalize(());
 
     let pconsole = ProcessConsoleComponent::new(board_kernel, uart_mux).finalize(());
+    pconsole.set_reboot(reboot, &create_capability!(capabilities::RebootCapability));
     let console = ConsoleComponent::new(board_kernel, uart_mux).finalize(());
     DebugWriterComponent::new(uart_mux).finalize(());
 
@@ -326,6 +356,14 @@ pub unsafe fn reset_handler() {
     ast.configure(mux_alarm);
     let alarm = AlarmDriverComponent::new(board_kernel, mux_alarm)
         .finalize(components::alarm_component_helper!(sam4l::ast::Ast));
+    let trace_clock = static_init!(
+        kernel::syscall_trace::TimeTraceClock<'static, sam4l::ast::Ast>,
+        kernel::syscall_trace::TimeTraceClock::new(ast)
+    );
+    let syscall_trace = SyscallTraceComponent::new(board_kernel, trace_clock).finalize(());
+    ProcessRestarterComponent::new(board_kernel, mux_alarm).finalize(
+        components::process_restarter_component_helper!(sam4l::ast::Ast, NUM_PROCS),
+    );
 
     // # I2C and I2C Sensors
     let mux_i2c = static_init!(MuxI2C<'static>, MuxI2C::new(&sam4l::i2c::I2C2));
@@ -382,7 +420,9 @@ pub unsafe fn reset_handler() {
     let crc = CrcComponent::new(board_kernel, &sam4l::crccu::CRCCU)
         .finalize(components::crc_component_helper!(sam4l::crccu::Crccu));
     let analog_comparator = AcComponent::new().finalize(());
-    let rng = RngComponent::new(board_kernel, &sam4l::trng::TRNG).finalize(());
+    // The TRNG is shared between the RNG driver and the TCP stack
+    let mux_rng = RngMuxComponent::new(&sam4l::trng::TRNG).finalize(());
+    let rng = RngDriverComponent::new(board_kernel, mux_rng).finalize(());
 
     // For now, assign the 802.15.4 MAC address on the device as
     // simply a 16-bit short address which represents the last 16 bits
@@ -395,44 +435,71 @@ pub unsafe fn reset_handler() {
 
     // Can this initialize be pushed earlier, or into component? -pal
     rf233.initialize(&mut RF233_BUF, &mut RF233_REG_WRITE, &mut RF233_REG_READ);
+    let nonvolatile_storage = NonvolatileStorageComponent::new(board_kernel).finalize(());
+    let frame_counters = components::frame_counter::FrameCounterComponent::new(
+        Some(nonvolatile_storage),
+        &FRAME_COUNTER_VOLUME as *const u8 as usize,
+    )
+    .finalize(());
     let (radio_driver, mux_mac) = RadioComponent::new(
         board_kernel,
         rf233,
         PAN_ID,
         serial_num_bottom_16, //comment out for dual rx test only
-                              //49138, //comment in for dual rx test only
+        //49138, //comment in for dual rx test only
+        mux_alarm,
+        frame_counters,
     )
     .finalize(());
+    let pan_joiner = PanJoinerComponent::new(mux_mac).finalize(());
 
     let usb_driver = UsbComponent::new(board_kernel).finalize(());
-    let nonvolatile_storage = NonvolatileStorageComponent::new(board_kernel).finalize(());
 
-    let local_ip_ifaces = static_init!(
-        [IPAddr; 3],
+    // The network interface starts with two hardcoded addresses and the
+    // link-local address generated from the serial number, and has room for
+    // two more addresses added at runtime.
+    let interface_addresses = static_init!(
+        [Option<IPAddr>; 5],
         [
-            IPAddr([
+            Some(IPAddr([
                 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
                 0x0e, 0x0f,
-            ]),
-            IPAddr([
+            ])),
+            Some(IPAddr([
                 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
                 0x1e, 0x1f,
-            ]),
-            IPAddr::generate_from_mac(src_mac_from_serial_num),
+            ])),
+            Some(IPAddr::generate_from_mac(src_mac_from_serial_num)),
+            None,
+            None,
         ]
     );
+    let interface = static_init!(
+        NetworkInterface,
+        NetworkInterface::new(
+            interface_addresses,
+            src_mac_from_serial_num, //comment out for dual rx test only
+            //MacAddress::Short(49138), //comment in for dual rx test only
+            IPV6_MIN_MTU,
+        )
+    );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        src_mac_from_serial_num, //comment out for dual rx test only
-        //MacAddress::Short(49138), //comment in for dual rx test only
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(());
+    // The link-layer addresses of the neighbors, which neighbor discovery
+    // fills. Packets it cannot resolve are sent to DST_MAC_ADDR.
+    let neighbor_cache_entries = static_init!([Option<Neighbor>; 8], [None; 8]);
+    let neighbor_cache = static_init!(NeighborCache, NeighborCache::new(neighbor_cache_entries));
+
+    let (udp_send_mux, udp_recv_mux, udp_port_table, ip_receive, sixlowpan_state) =
+        UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            interface,
+            neighbor_cache,
+            mux_alarm,
+        )
+        .finalize(());
 
     // UDP driver initialization happens here
     let udp_driver = UDPDriverComponent::new(
@@ -440,7 +507,34 @@ pub unsafe fn reset_handler() {
         udp_send_mux,
         udp_recv_mux,
         udp_port_table,
-        local_ip_ifaces,
+        interface,
+    )
+    .finalize(());
+
+    // TCP driver initialization happens here
+    let tcp_driver = TCPDriverComponent::new(
+        board_kernel,
+        mux_mac,
+        ip_receive,
+        sixlowpan_state,
+        DST_MAC_ADDR,
+        interface,
+        neighbor_cache,
+        mux_alarm,
+        mux_rng,
+    )
+    .finalize(());
+
+    // ICMPv6 echo responder and neighbor discovery
+    let nd = ICMP6Component::new(
+        mux_mac,
+        ip_receive,
+        sixlowpan_state,
+        neighbor_cache,
+        DST_MAC_ADDR,
+        serial_num.get_lower_64().to_be_bytes(),
+        interface,
+        mux_alarm,
     )
     .finalize(());
 
@@ -463,10 +557,11 @@ pub unsafe fn reset_handler() {
         analog_comparator,
         crc,
         spi: spi_syscalls,
-        ipc: kernel::ipc::IPC::new(board_kernel, &grant_cap),
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
         ninedof,
         radio_driver,
         udp_driver,
+        tcp_driver,
         usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage: nonvolatile_storage,
@@ -483,6 +578,10 @@ pub unsafe fn reset_handler() {
     // initialization to work.
     rf233.reset();
     rf233.start();
+    if JOIN_PAN {
+        pan_joiner.start();
+    }
+    nd.start();
 
     imix.pconsole.start();
 
@@ -506,16 +605,52 @@ pub unsafe fn reset_handler() {
     extern "C" {
         /// Beginning of the ROM region containing app images.
         static _sapps: u8;
+        /// End of the ROM region containing app images.
+        static _eapps: u8;
     }
-    kernel::procs::load_processes(
+    let app_checker: &'static dyn kernel::procs::CredentialsChecker = match APP_SIGNING_PUBLIC_KEY {
+        Some(public_key) => static_init!(
+            capsules::app_checker::AppCheckerEd25519,
+            capsules::app_checker::AppCheckerEd25519::new(public_key)
+        ),
+        None => static_init!(
+            capsules::app_checker::AppCheckerSha256,
+            capsules::app_checker::AppCheckerSha256::new(APP_CREDENTIALS_KEY)
+        ),
+    };
+    let credentials_check =
+        kernel::procs::CredentialsCheck::new(app_checker, kernel::procs::RejectedAppResponse::Skip);
+
+    let remaining_app_memory = kernel::procs::load_processes(
         board_kernel,
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        Some(credentials_check),
         &process_mgmt_cap,
     );
 
-    board_kernel.kernel_loop(&imix, chip, Some(&imix.ipc), &main_cap);
+    // Allow the process console to load apps that are flashed after boot
+    // into the app memory that is left over.
+    let process_loader = static_init!(
+        kernel::procs::DynamicProcessLoader<sam4l::chip::Sam4l>,
+        kernel::procs::DynamicProcessLoader::new(
+            board_kernel,
+            chip,
+            core::slice::from_raw_parts(
+                &_sapps as *const u8,
+                &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+            ),
+            remaining_app_memory,
+            FAULT_RESPONSE,
+            Some(credentials_check),
+        )
+    );
+    imix.pconsole.set_process_loader(process_loader);
+    imix.pconsole.set_syscall_trace(syscall_trace);
+    imix.pconsole.set_network_interface(interface);
+
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(&imix, chip, Some(imix.ipc), scheduler, &main_cap);
 }
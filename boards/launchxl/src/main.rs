@@ -14,7 +14,6 @@ use cc26x2::aon;
 use cc26x2::prcm;
 use cc26x2::pwm;
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil;
 use kernel::hil::entropy::Entropy32;
@@ -63,7 +62,7 @@ pub struct Platform {
     >,
     rng: &'static capsules::rng::RngDriver<'static>,
     i2c_master: &'static capsules::i2c_master::I2CMasterDriver<cc26x2::i2c::I2CMaster<'static>>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
 }
 
 impl kernel::Platform for Platform {
@@ -79,7 +78,7 @@ impl kernel::Platform for Platform {
             capsules::alarm::DRIVER_NUM => f(Some(self.alarm)),
             capsules::rng::DRIVER_NUM => f(Some(self.rng)),
             capsules::i2c_master::DRIVER_NUM => f(Some(self.i2c_master)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
@@ -163,15 +162,7 @@ pub unsafe fn reset_handler() {
 
     while !prcm::Power::is_enabled(prcm::PowerDomain::Serial) {}
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
-
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
     // Enable the GPIO clocks
     prcm::Clock::enable_gpio();
@@ -230,12 +221,8 @@ pub unsafe fn reset_handler() {
 
     // UART
     cc26x2::uart::UART0.initialize();
-    let uart_mux = components::console::UartMuxComponent::new(
-        &cc26x2::uart::UART0,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&cc26x2::uart::UART0, 115200).finalize(());
 
     // Setup the console.
     let console = components::console::ConsoleComponent::new(board_kernel, uart_mux).finalize(());
@@ -336,7 +323,7 @@ pub unsafe fn reset_handler() {
         pwm_channel.enable();
     }
 
-    let ipc = kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability);
+    let ipc = components::ipc::IpcComponent::new(board_kernel).finalize(());
 
     let launchxl = Platform {
         console,
@@ -359,10 +346,17 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_management_capability,
     );
 
-    board_kernel.kernel_loop(&launchxl, chip, Some(&launchxl.ipc), &main_loop_capability);
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(
+        &launchxl,
+        chip,
+        Some(launchxl.ipc),
+        scheduler,
+        &main_loop_capability,
+    );
 }
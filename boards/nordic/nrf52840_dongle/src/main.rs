@@ -63,7 +63,7 @@ pub unsafe fn reset_handler() {
     // Loads relocations and clears BSS
     nrf52840::init();
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
     // GPIOs
     let gpio = components::gpio::GpioComponent::new(board_kernel).finalize(
         components::gpio_component_helper!(
@@ -140,7 +140,6 @@ pub unsafe fn reset_handler() {
         button,
         true,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
         nrf52840::uicr::Regulator0Output::V3_0,
         false,
@@ -128,7 +128,7 @@ pub unsafe fn reset_handler() {
     // Loads relocations and clears BSS
     nrf52832::init();
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
     let gpio = components::gpio::GpioComponent::new(board_kernel).finalize(
         components::gpio_component_helper!(
             // Bottom right header on DK board
@@ -209,7 +209,6 @@ pub unsafe fn reset_handler() {
         button,
         false,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
         nrf52832::uicr::Regulator0Output::DEFAULT,
         false,
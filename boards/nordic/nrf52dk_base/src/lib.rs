@@ -9,7 +9,6 @@ use capsules::analog_comparator;
 use capsules::virtual_alarm::VirtualMuxAlarm;
 use capsules::virtual_spi::MuxSpiMaster;
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil;
 use nrf52::gpio::Pin;
@@ -23,6 +22,9 @@ use nrf52_components::ieee802154::Ieee802154Component;
 // Constants related to the configuration of the 15.4 network stack
 const SRC_MAC: u16 = 0xf00f;
 const PAN_ID: u16 = 0xABCD;
+// Set to true to have the board scan for a PAN to join at boot. If it finds
+// none, it starts the PAN above as its coordinator.
+const JOIN_PAN: bool = false;
 
 /// Pins for SPI for the flash chip MX25R6435F
 #[derive(Debug)]
@@ -89,7 +91,7 @@ pub struct Platform {
     led: &'static capsules::led::LED<'static>,
     rng: &'static capsules::rng::RngDriver<'static>,
     temp: &'static capsules::temperature::TemperatureSensor<'static>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
     analog_comparator:
         &'static capsules::analog_comparator::AnalogComparator<'static, nrf52::acomp::Comparator>,
     alarm: &'static capsules::alarm::AlarmDriver<
@@ -123,12 +125,19 @@ impl kernel::Platform for Platform {
             capsules::nonvolatile_storage_driver::DRIVER_NUM => {
                 f(self.nonvolatile_storage.map_or(None, |nv| Some(nv)))
             }
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
 }
 
+/// Reset the board, for the `reboot` command of the process console.
+fn reboot() {
+    unsafe {
+        cortexm4::scb::reset();
+    }
+}
+
 /// Generic function for starting an nrf52dk board.
 #[inline]
 pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
@@ -146,7 +155,6 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
     button: &'static capsules::button::Button<'static>,
     ieee802154: bool,
     app_memory: &mut [u8],
-    process_pointers: &'static mut [Option<&'static dyn kernel::procs::ProcessType>],
     app_fault_response: kernel::procs::FaultResponse,
     reg_vout: Regulator0Output,
     nfc_as_gpios: bool,
@@ -236,21 +244,16 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
     let alarm = components::alarm::AlarmDriverComponent::new(board_kernel, mux_alarm)
         .finalize(components::alarm_component_helper!(nrf52::rtc::Rtc));
 
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
+    // Let peripherals and capsules limit how deeply the chip sleeps.
+    let power_manager = static_init!(
+        kernel::power::PowerManager,
+        kernel::power::PowerManager::new()
     );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    kernel::power::PowerManager::set_global_instance(power_manager);
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(
-        &nrf52::uart::UARTE0,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&nrf52::uart::UARTE0, 115200).finalize(());
 
     nrf52::uart::UARTE0.initialize(
         nrf52::pinmux::Pinmux::new(uart_pins.txd as u32),
@@ -261,6 +264,7 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
     let pconsole =
         components::process_console::ProcessConsoleComponent::new(board_kernel, uart_mux)
             .finalize(());
+    pconsole.set_reboot(reboot, &create_capability!(capabilities::RebootCapability));
 
     // Setup the console.
     let console = components::console::ConsoleComponent::new(board_kernel, uart_mux).finalize(());
@@ -270,19 +274,6 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
     let ble_radio =
         BLEComponent::new(board_kernel, &nrf52::ble_radio::RADIO, mux_alarm).finalize(());
 
-    let ieee802154_radio = if ieee802154 {
-        let (radio, _) = Ieee802154Component::new(
-            board_kernel,
-            &nrf52::ieee802154_radio::RADIO,
-            PAN_ID,
-            SRC_MAC,
-        )
-        .finalize(());
-        Some(radio)
-    } else {
-        None
-    };
-
     let temp = static_init!(
         capsules::temperature::TemperatureSensor<'static>,
         capsules::temperature::TemperatureSensor::new(
@@ -380,6 +371,32 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
         None
     };
 
+    // The outgoing 802.15.4 frame counter is persisted at the start of the
+    // kernel region of the external flash, if there is one.
+    let ieee802154_radio = if ieee802154 {
+        let frame_counters = components::frame_counter::FrameCounterComponent::new(
+            nonvolatile_storage.map(|nv| nv as &dyn hil::nonvolatile_storage::NonvolatileStorage),
+            0,
+        )
+        .finalize(());
+        let (radio, mux_mac) = Ieee802154Component::new(
+            board_kernel,
+            &nrf52::ieee802154_radio::RADIO,
+            PAN_ID,
+            SRC_MAC,
+            mux_alarm,
+            frame_counters,
+        )
+        .finalize(());
+        if JOIN_PAN {
+            let pan_joiner = components::pan_joiner::PanJoinerComponent::new(mux_mac).finalize(());
+            pan_joiner.start();
+        }
+        Some(radio)
+    } else {
+        None
+    };
+
     // Initialize AC using AIN5 (P0.29) as VIN+ and VIN- as AIN0 (P0.02)
     // These are hardcoded pin assignments specified in the driver
     let ac_channels = static_init!(
@@ -404,14 +421,6 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
     while !nrf52::clock::CLOCK.low_started() {}
     while !nrf52::clock::CLOCK.high_started() {}
 
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 1], Default::default());
-    let dynamic_deferred_call = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_call);
-
     let platform = Platform {
         button: button,
         ble_radio: ble_radio,
@@ -425,7 +434,7 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
         alarm: alarm,
         analog_comparator: analog_comparator,
         nonvolatile_storage: nonvolatile_storage,
-        ipc: kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability),
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
     };
 
     platform.pconsole.start();
@@ -441,10 +450,17 @@ pub unsafe fn setup_board<I: nrf52::interrupt_service::InterruptService>(
         chip,
         &_sapps as *const u8,
         app_memory,
-        process_pointers,
         app_fault_response,
+        None,
         &process_management_capability,
     );
 
-    board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
+    board_kernel.kernel_loop(
+        &platform,
+        chip,
+        Some(platform.ipc),
+        scheduler,
+        &main_loop_capability,
+    );
 }
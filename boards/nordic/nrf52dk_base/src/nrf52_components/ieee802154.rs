@@ -7,20 +7,23 @@
 //! Usage
 //! -----
 //! ```rust
-//! let (ieee802154_radio, _) = Ieee802154Component::new(board_kernel, &nrf52::ieee802154_radio::RADIO, PAN_ID, SRC_MAC).finalize();
+//! let (ieee802154_radio, mux_mac) = Ieee802154Component::new(board_kernel, &nrf52::ieee802154_radio::RADIO, PAN_ID, SRC_MAC, mux_alarm, frame_counters).finalize(());
 //! ```
 
 #![allow(dead_code)] // Components are intended to be conditionally included
 
 use capsules;
 use capsules::ieee802154::device::MacDevice;
+use capsules::ieee802154::frame_counter::FrameCounters;
 use capsules::ieee802154::mac::{AwakeMac, Mac};
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::hil::radio;
 use kernel::hil::radio::RadioData;
 use kernel::hil::symmetric_encryption::AES128CCM;
+use kernel::hil::time::Alarm;
 use kernel::{create_capability, static_init};
 
 // Save some deep nesting
@@ -30,6 +33,8 @@ pub struct Ieee802154Component {
     radio: &'static nrf52::ieee802154_radio::Radio,
     pan_id: capsules::net::ieee802154::PanID,
     short_addr: u16,
+    mux_alarm: &'static MuxAlarm<'static, nrf52::rtc::Rtc<'static>>,
+    frame_counters: &'static FrameCounters<'static>,
 }
 
 impl Ieee802154Component {
@@ -38,12 +43,16 @@ impl Ieee802154Component {
         radio: &'static nrf52::ieee802154_radio::Radio,
         pan_id: capsules::net::ieee802154::PanID,
         addr: u16,
+        mux_alarm: &'static MuxAlarm<'static, nrf52::rtc::Rtc>,
+        frame_counters: &'static FrameCounters<'static>,
     ) -> Ieee802154Component {
         Ieee802154Component {
             board_kernel: board_kernel,
             radio: radio,
             pan_id: pan_id,
             short_addr: addr,
+            mux_alarm: mux_alarm,
+            frame_counters: frame_counters,
         }
     }
 }
@@ -53,6 +62,9 @@ static mut RADIO_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
 // The buffer RF233 packets are received into.
 static mut RADIO_RX_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
 
+// The buffer the framer sends beacons and MAC commands from.
+static mut MLME_BUF: [u8; radio::MAX_BUF_SIZE] = [0x00; radio::MAX_BUF_SIZE];
+
 // This buffer is used as an intermediate buffer for AES CCM encryption
 // An upper bound on the required size is 3 * BLOCK_SIZE + radio::MAX_BUF_SIZE
 const CRYPT_SIZE: usize = 1;
@@ -81,18 +93,30 @@ impl Component for Ieee802154Component {
         self.radio.set_transmit_client(awake_mac);
         self.radio.set_receive_client(awake_mac, &mut RADIO_RX_BUF);
 
+        let mlme_alarm = static_init!(
+            VirtualMuxAlarm<'static, nrf52::rtc::Rtc>,
+            VirtualMuxAlarm::new(self.mux_alarm)
+        );
         let mac_device = static_init!(
             capsules::ieee802154::framer::Framer<
                 'static,
                 AwakeMac<'static, nrf52::ieee802154_radio::Radio>,
                 capsules::aes_ccm::AES128CCM<'static, nrf52::aes::AesECB<'static>>,
+                VirtualMuxAlarm<'static, nrf52::rtc::Rtc>,
             >,
-            capsules::ieee802154::framer::Framer::new(awake_mac, aes_ccm)
+            capsules::ieee802154::framer::Framer::new(
+                awake_mac,
+                aes_ccm,
+                mlme_alarm,
+                &mut MLME_BUF
+            )
         );
         aes_ccm.set_client(mac_device);
         awake_mac.set_transmit_client(mac_device);
         awake_mac.set_receive_client(mac_device);
         awake_mac.set_config_client(mac_device);
+        awake_mac.set_energy_detect_client(mac_device);
+        mlme_alarm.set_client(mac_device);
 
         let mux_mac = static_init!(
             capsules::ieee802154::virtual_mac::MuxMac<'static>,
@@ -100,6 +124,7 @@ impl Component for Ieee802154Component {
         );
         mac_device.set_transmit_client(mux_mac);
         mac_device.set_receive_client(mux_mac);
+        mac_device.set_mlme_client(mux_mac);
 
         let radio_mac = static_init!(
             capsules::ieee802154::virtual_mac::MacUser<'static>,
@@ -118,9 +143,12 @@ impl Component for Ieee802154Component {
 
         mac_device.set_key_procedure(radio_driver);
         mac_device.set_device_procedure(radio_driver);
+        mac_device.set_frame_counter_procedure(self.frame_counters);
+        radio_driver.set_frame_counters(self.frame_counters);
 
         radio_mac.set_transmit_client(radio_driver);
         radio_mac.set_receive_client(radio_driver);
+        radio_mac.set_mlme_client(radio_driver);
 
         radio_mac.set_pan(self.pan_id);
         radio_mac.set_address(self.short_addr);
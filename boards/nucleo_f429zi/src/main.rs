@@ -9,7 +9,6 @@
 
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil::time::Alarm;
 use kernel::Platform;
@@ -51,7 +50,7 @@ const NUM_LEDS: usize = 1;
 /// capsules for this platform.
 struct NucleoF429ZI {
     console: &'static capsules::console::Console<'static>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
     led: &'static capsules::led::LED<'static>,
     button: &'static capsules::button::Button<'static>,
     alarm: &'static capsules::alarm::AlarmDriver<
@@ -71,7 +70,7 @@ impl Platform for NucleoF429ZI {
             capsules::led::DRIVER_NUM => f(Some(self.led)),
             capsules::button::DRIVER_NUM => f(Some(self.button)),
             capsules::alarm::DRIVER_NUM => f(Some(self.alarm)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
@@ -182,15 +181,7 @@ pub unsafe fn reset_handler() {
 
     setup_peripherals();
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
-
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
 
     let chip = static_init!(
         stm32f4xx::chip::Stm32f4xx,
@@ -202,12 +193,8 @@ pub unsafe fn reset_handler() {
 
     // Create a shared UART channel for kernel debug.
     stm32f4xx::usart::USART3.enable_clock();
-    let uart_mux = components::console::UartMuxComponent::new(
-        &stm32f4xx::usart::USART3,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&stm32f4xx::usart::USART3, 115200).finalize(());
 
     io::WRITER.set_initialized();
 
@@ -235,6 +222,7 @@ pub unsafe fn reset_handler() {
     //         &mut capsules::process_console::WRITE_BUF,
     //         &mut capsules::process_console::READ_BUF,
     //         &mut capsules::process_console::COMMAND_BUF,
+    //         &mut capsules::process_console::HISTORY_BUF,
     //         board_kernel,
     //         ProcessConsoleCapability,
     //     )
@@ -293,7 +281,7 @@ pub unsafe fn reset_handler() {
 
     let nucleo_f429zi = NucleoF429ZI {
         console: console,
-        ipc: kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability),
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
         led: led,
         button: button,
         alarm: alarm,
@@ -318,15 +306,17 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_management_capability,
     );
 
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
     board_kernel.kernel_loop(
         &nucleo_f429zi,
         chip,
-        Some(&nucleo_f429zi.ipc),
+        Some(nucleo_f429zi.ipc),
+        scheduler,
         &main_loop_capability,
     );
 }
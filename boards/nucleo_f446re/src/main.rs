@@ -9,7 +9,6 @@
 
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::capabilities;
-use kernel::common::dynamic_deferred_call::{DynamicDeferredCall, DynamicDeferredCallClientState};
 use kernel::component::Component;
 use kernel::hil::gpio::Configure;
 use kernel::hil::time::Alarm;
@@ -55,7 +54,7 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 /// capsules for this platform.
 struct NucleoF446RE {
     console: &'static capsules::console::Console<'static>,
-    ipc: kernel::ipc::IPC,
+    ipc: &'static kernel::ipc::IPC,
     led: &'static capsules::led::LED<'static>,
     button: &'static capsules::button::Button<'static>,
     alarm: &'static capsules::alarm::AlarmDriver<
@@ -75,7 +74,7 @@ impl Platform for NucleoF446RE {
             capsules::led::DRIVER_NUM => f(Some(self.led)),
             capsules::button::DRIVER_NUM => f(Some(self.button)),
             capsules::alarm::DRIVER_NUM => f(Some(self.alarm)),
-            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc::DRIVER_NUM => f(Some(self.ipc)),
             _ => f(None),
         }
     }
@@ -183,15 +182,7 @@ pub unsafe fn reset_handler() {
 
     setup_peripherals();
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
-    let dynamic_deferred_call_clients =
-        static_init!([DynamicDeferredCallClientState; 2], Default::default());
-    let dynamic_deferred_caller = static_init!(
-        DynamicDeferredCall,
-        DynamicDeferredCall::new(dynamic_deferred_call_clients)
-    );
-    DynamicDeferredCall::set_global_instance(dynamic_deferred_caller);
-
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&mut PROCESSES));
     let chip = static_init!(
         stm32f4xx::chip::Stm32f4xx,
         stm32f4xx::chip::Stm32f4xx::new()
@@ -202,12 +193,8 @@ pub unsafe fn reset_handler() {
 
     // Create a shared UART channel for kernel debug.
     stm32f4xx::usart::USART2.enable_clock();
-    let uart_mux = components::console::UartMuxComponent::new(
-        &stm32f4xx::usart::USART2,
-        115200,
-        dynamic_deferred_caller,
-    )
-    .finalize(());
+    let uart_mux =
+        components::console::UartMuxComponent::new(&stm32f4xx::usart::USART2, 115200).finalize(());
 
     // `finalize()` configures the underlying USART, so we need to
     // tell `send_byte()` not to configure the USART again.
@@ -237,6 +224,7 @@ pub unsafe fn reset_handler() {
     //         &mut capsules::process_console::WRITE_BUF,
     //         &mut capsules::process_console::READ_BUF,
     //         &mut capsules::process_console::COMMAND_BUF,
+    //         &mut capsules::process_console::HISTORY_BUF,
     //         board_kernel,
     //         ProcessConsoleCapability,
     //     )
@@ -294,7 +282,7 @@ pub unsafe fn reset_handler() {
 
     let nucleo_f446re = NucleoF446RE {
         console: console,
-        ipc: kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability),
+        ipc: components::ipc::IpcComponent::new(board_kernel).finalize(()),
         led: led,
         button: button,
         alarm: alarm,
@@ -319,15 +307,17 @@ pub unsafe fn reset_handler() {
         chip,
         &_sapps as *const u8,
         &mut APP_MEMORY,
-        &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
         &process_management_capability,
     );
 
+    let scheduler = static_init!(kernel::RoundRobinSched, kernel::RoundRobinSched::new());
     board_kernel.kernel_loop(
         &nucleo_f446re,
         chip,
-        Some(&nucleo_f446re.ipc),
+        Some(nucleo_f446re.ipc),
+        scheduler,
         &main_loop_capability,
     );
 }
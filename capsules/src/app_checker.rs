@@ -0,0 +1,116 @@
+//! Checks the credentials of apps with SHA-256, HMAC-SHA256 or Ed25519.
+//!
+//! `AppCheckerSha256` and `AppCheckerEd25519` are `CredentialsChecker`s for
+//! the process loaders. They accept apps whose TBF header carries credentials
+//! that match the app. `AppCheckerSha256` accepts:
+//!
+//! - Without a key, apps need `Sha256` credentials: a SHA-256 hash of the
+//!   app. This detects corrupted or partially written apps, but anyone can
+//!   compute the hash of a modified app.
+//! - With a key, apps need `HmacSha256` credentials: an HMAC-SHA256 of the
+//!   app with the same key. Only apps signed by someone who knows the key,
+//!   such as a build server, are accepted. The key is part of the kernel
+//!   image, so anyone who can read the kernel can sign apps too.
+//!
+//! `AppCheckerEd25519` accepts apps with `Ed25519` credentials: a signature of
+//! the app that verifies with the board's public key. Only the holder of the
+//! private key can sign apps, and the kernel image contains no secret.
+//!
+//! Apps without credentials, or with credentials in any other format, are
+//! rejected. `tools/tbf_sign` adds credentials to TBF files.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let checker = static_init!(
+//!     capsules::app_checker::AppCheckerEd25519,
+//!     capsules::app_checker::AppCheckerEd25519::new(&BUILD_SERVER_PUBLIC_KEY)
+//! );
+//! kernel::procs::load_processes(
+//!     board_kernel,
+//!     chip,
+//!     &_sapps as *const u8,
+//!     &mut APP_MEMORY,
+//!     FAULT_RESPONSE,
+//!     Some(CredentialsCheck::new(checker, RejectedAppResponse::Panic)),
+//!     &process_mgmt_cap,
+//! );
+//! ```
+
+use crate::ed25519;
+use crate::sha256::{digests_equal, hmac_sha256, Sha256};
+use kernel::procs::{Credentials, CredentialsChecker, CredentialsFormat};
+
+pub struct AppCheckerSha256 {
+    /// Key for HMAC-SHA256 credentials. If set, plain SHA-256 credentials are
+    /// not accepted.
+    key: Option<&'static [u8]>,
+}
+
+impl AppCheckerSha256 {
+    pub fn new(key: Option<&'static [u8]>) -> AppCheckerSha256 {
+        AppCheckerSha256 { key }
+    }
+}
+
+impl CredentialsChecker for AppCheckerSha256 {
+    fn check_credentials(
+        &self,
+        _name: &str,
+        credentials: Option<Credentials>,
+        message: &[&'static [u8]],
+    ) -> bool {
+        match (credentials, self.key) {
+            (
+                Some(Credentials {
+                    format: CredentialsFormat::Sha256,
+                    data,
+                }),
+                None,
+            ) => {
+                let mut hash = Sha256::new();
+                for part in message {
+                    hash.update(part);
+                }
+                digests_equal(data, &hash.finish())
+            }
+            (
+                Some(Credentials {
+                    format: CredentialsFormat::HmacSha256,
+                    data,
+                }),
+                Some(key),
+            ) => digests_equal(data, &hmac_sha256(key, message)),
+            _ => false,
+        }
+    }
+}
+
+pub struct AppCheckerEd25519 {
+    /// Public key that app signatures must verify with.
+    public_key: &'static [u8; ed25519::PUBLIC_KEY_LEN],
+}
+
+impl AppCheckerEd25519 {
+    pub fn new(public_key: &'static [u8; ed25519::PUBLIC_KEY_LEN]) -> AppCheckerEd25519 {
+        AppCheckerEd25519 { public_key }
+    }
+}
+
+impl CredentialsChecker for AppCheckerEd25519 {
+    fn check_credentials(
+        &self,
+        _name: &str,
+        credentials: Option<Credentials>,
+        message: &[&'static [u8]],
+    ) -> bool {
+        match credentials {
+            Some(Credentials {
+                format: CredentialsFormat::Ed25519,
+                data,
+            }) => ed25519::verify(self.public_key, data, message),
+            _ => false,
+        }
+    }
+}
@@ -33,11 +33,18 @@
 //! When the buffer has been written successfully, the buffer is released from
 //! the driver. Successive writes must call `allow` each time a buffer is to be
 //! written.
+//!
+//! The buffer to write can also be shared with `allow_readonly` instead of
+//! `allow`, so that an app can write a constant string from its flash without
+//! copying it into RAM first.
 
 use core::cmp;
 use kernel::common::cells::{OptionalCell, TakeCell};
 use kernel::hil::uart;
-use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use kernel::{
+    AppId, AppSlice, Callback, Driver, Grant, GrantCleanupClient, ReadOnlyAppSlice, ReturnCode,
+    Shared,
+};
 
 /// Syscall driver number.
 use crate::driver;
@@ -46,7 +53,7 @@ pub const DRIVER_NUM: usize = driver::NUM::Console as usize;
 #[derive(Default)]
 pub struct App {
     write_callback: Option<Callback>,
-    write_buffer: Option<AppSlice<Shared, u8>>,
+    write_buffer: Option<ReadOnlyAppSlice<u8>>,
     write_len: usize,
     write_remaining: usize, // How many bytes didn't fit in the buffer and still need to be printed.
     pending_write: bool,
@@ -115,7 +122,7 @@ impl Console<'a> {
 
     /// Internal helper function for sending data for an existing transaction.
     /// Cannot fail. If can't send now, it will schedule for sending later.
-    fn send(&self, app_id: AppId, app: &mut App, slice: AppSlice<Shared, u8>) {
+    fn send(&self, app_id: AppId, app: &mut App, slice: ReadOnlyAppSlice<u8>) {
         if self.tx_in_progress.is_none() {
             self.tx_in_progress.set(app_id);
             self.tx_buffer.take().map(|buffer| {
@@ -195,17 +202,34 @@ impl Driver for Console<'a> {
         slice: Option<AppSlice<Shared, u8>>,
     ) -> ReturnCode {
         match allow_num {
-            1 => self
+            1 => self.allow_readonly(appid, allow_num, slice.map(ReadOnlyAppSlice::from)),
+            2 => self
                 .apps
                 .enter(appid, |app, _| {
-                    app.write_buffer = slice;
+                    app.read_buffer = slice;
                     ReturnCode::SUCCESS
                 })
                 .unwrap_or_else(|err| err.into()),
-            2 => self
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Setup read-only shared buffers, which may be in flash.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `1`: Write buffer
+    fn allow_readonly(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<ReadOnlyAppSlice<u8>>,
+    ) -> ReturnCode {
+        match allow_num {
+            1 => self
                 .apps
                 .enter(appid, |app, _| {
-                    app.read_buffer = slice;
+                    app.write_buffer = slice;
                     ReturnCode::SUCCESS
                 })
                 .unwrap_or_else(|err| err.into()),
@@ -341,6 +365,19 @@ impl uart::TransmitClient for Console<'a> {
     }
 }
 
+impl GrantCleanupClient for Console<'a> {
+    fn process_teardown(&self, appid: AppId) {
+        // A pending receive for a process that is gone would hold the receive
+        // buffer forever, so cancel it. The aborted buffer is returned in
+        // `received_buffer()` and not delivered to anyone. An in-progress
+        // transmit is left to finish since the UART owns the buffer.
+        if self.rx_in_progress.map_or(false, |id| *id == appid) {
+            self.rx_in_progress.clear();
+            self.uart.receive_abort();
+        }
+    }
+}
+
 impl uart::ReceiveClient for Console<'a> {
     fn received_buffer(
         &self,
@@ -0,0 +1,153 @@
+//! Lets apps read and clear the crash dump the kernel saved in flash.
+//!
+//! The kernel saves a dump when it panics or when a process faults, see
+//! `kernel::crash_dump`. After the board has been reset, an app can read the
+//! text of the dump, for example to print it or to send it somewhere, and
+//! then clear it so that the next crash is recorded.
+//!
+//! The dump can hold the memory of any process, so only processes that the
+//! board allows to use this driver with `Kernel::set_syscall_permissions()`
+//! can read or clear it. Listing the driver in the Permissions element of a
+//! TBF header is not enough, since an app can list any driver in its own
+//! header. Other processes get `EPERM`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! static mut CRASH_DUMP_PAGE: [u8; 512] = [0; 512];
+//! static mut CRASH_DUMP_FAULT_TEXT: [u8; 2048] = [0; 2048];
+//! let crash_dump = static_init!(
+//!     kernel::crash_dump::CrashDump,
+//!     kernel::crash_dump::CrashDump::new(
+//!         &sam4l::flashcalw::FLASH_CONTROLLER,
+//!         &CRASH_DUMP_VOLUME as *const u8 as usize,
+//!         CRASH_DUMP_VOLUME.len(),
+//!         &mut CRASH_DUMP_PAGE,
+//!         &mut CRASH_DUMP_FAULT_TEXT
+//!     )
+//! );
+//! if crash_dump.register() != ReturnCode::SUCCESS {
+//!     debug!("Crash dump: no deferred call left, process faults are not saved");
+//! }
+//! kernel::crash_dump::set_crash_dump(crash_dump);
+//! let crash_dump_driver = static_init!(
+//!     capsules::crash_dump::CrashDumpDriver,
+//!     capsules::crash_dump::CrashDumpDriver::new(
+//!         crash_dump,
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//!
+//! // Let the app named "crash_reporter" read and clear the dump.
+//! static PERMISSIONS: [AppPermissions; 1] = [AppPermissions {
+//!     app: "crash_reporter",
+//!     drivers: &[DriverPermission::driver(capsules::crash_dump::DRIVER_NUM)],
+//! }];
+//! board_kernel.set_syscall_permissions(&PERMISSIONS, DefaultPermission::AllowAll);
+//! ```
+
+use kernel::crash_dump::CrashDump;
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::CrashDump as usize;
+
+#[derive(Default)]
+pub struct App {
+    buffer: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct CrashDumpDriver {
+    dump: &'static CrashDump,
+    apps: Grant<App>,
+}
+
+impl CrashDumpDriver {
+    pub fn new(dump: &'static CrashDump, grant: Grant<App>) -> CrashDumpDriver {
+        CrashDumpDriver { dump, apps: grant }
+    }
+}
+
+impl Driver for CrashDumpDriver {
+    /// Setup buffer to read into.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Set the buffer the text of the dump is copied into.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        if !appid.has_board_permission(DRIVER_NUM) {
+            return ReturnCode::EPERM;
+        }
+        match allow_num {
+            0 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                })
+                .unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Crash dump access. All commands complete at once, so there are no
+    /// callbacks. Commands other than the driver check return `EPERM` to
+    /// processes the board does not allow to use this driver.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Return the length of the text of the dump in bytes, or 0 if
+    ///        there is no dump.
+    /// - `2`: Return what caused the dump: 1 for a kernel panic, 2 for a
+    ///        process fault, or 0 if there is no dump.
+    /// - `3`: Copy the text of the dump, starting at offset `arg1`, into the
+    ///        `allow` buffer. Returns the number of bytes copied.
+    /// - `4`: Erase the dump.
+    fn command(&self, command_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
+        if command_num != 0 && !appid.has_board_permission(DRIVER_NUM) {
+            return ReturnCode::EPERM;
+        }
+        match command_num {
+            0 =>
+            /* This driver exists. */
+            {
+                ReturnCode::SUCCESS
+            }
+
+            // Length of the dump.
+            1 => ReturnCode::SuccessWithValue {
+                value: self.dump.header().map_or(0, |header| header.length),
+            },
+
+            // Kind of the dump.
+            2 => ReturnCode::SuccessWithValue {
+                value: self.dump.header().map_or(0, |header| header.kind as usize),
+            },
+
+            // Copy the dump into the allowed buffer.
+            3 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.buffer.as_mut().map_or(ReturnCode::EINVAL, |buffer| {
+                        ReturnCode::SuccessWithValue {
+                            value: self.dump.read(arg1, buffer.as_mut()),
+                        }
+                    })
+                })
+                .unwrap_or_else(|err| err.into()),
+
+            // Erase the dump.
+            4 => self.dump.clear(),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
@@ -68,7 +68,7 @@
 use kernel::common::cells::OptionalCell;
 use kernel::hil;
 use kernel::hil::crc::CrcAlg;
-use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReadOnlyAppSlice, ReturnCode, Shared};
 
 /// Syscall driver number.
 use crate::driver;
@@ -78,7 +78,7 @@ pub const DRIVER_NUM: usize = driver::NUM::Crc as usize;
 #[derive(Default)]
 pub struct App {
     callback: Option<Callback>,
-    buffer: Option<AppSlice<Shared, u8>>,
+    buffer: Option<ReadOnlyAppSlice<u8>>,
 
     // if Some, the application is awaiting the result of a CRC
     //   using the given algorithm
@@ -168,13 +168,27 @@ impl<C: hil::crc::CRC> Crc<'a, C> {
 impl<C: hil::crc::CRC> Driver for Crc<'a, C> {
     /// The `allow` syscall for this driver supports the single
     /// `allow_num` zero, which is used to provide a buffer over which
-    /// to compute a CRC computation.
+    /// to compute a CRC computation. The buffer can also be shared with
+    /// `allow_readonly`.
     ///
     fn allow(
         &self,
         appid: AppId,
         allow_num: usize,
         slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        self.allow_readonly(appid, allow_num, slice.map(ReadOnlyAppSlice::from))
+    }
+
+    /// The `allow_readonly` syscall supports the single `allow_num` zero,
+    /// which is used to provide a buffer over which to compute a CRC
+    /// computation, for example a constant in the app's flash.
+    ///
+    fn allow_readonly(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<ReadOnlyAppSlice<u8>>,
     ) -> ReturnCode {
         match allow_num {
             // Provide user buffer to compute CRC over
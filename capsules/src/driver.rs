@@ -19,6 +19,7 @@ pub enum NUM {
 
     // Kernel
     Ipc                   = 0x10000,
+    Watchdog              = 0x10001,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -29,6 +30,7 @@ pub enum NUM {
     BleAdvertising        = 0x30000,
     Ieee802154            = 0x30001,
     Udp                   = 0x30002,
+    Tcp                   = 0x30003,
 
     // Cryptography
     Rng                   = 0x40001,
@@ -39,6 +41,7 @@ pub enum NUM {
     AppFlash              = 0x50000,
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
+    CrashDump             = 0x50003,
 
     // Sensors
     Temperature           = 0x60000,
@@ -0,0 +1,455 @@
+//! Software verification of Ed25519 signatures.
+//!
+//! This only verifies signatures: the kernel checks apps against a public key
+//! and never needs a private key. The arithmetic follows TweetNaCl, which
+//! trades speed for a small, easily reviewed implementation; verifying a
+//! signature takes two scalar multiplications, which is slow on a
+//! microcontroller but only happens while processes are loaded. Timing does
+//! not depend on anything secret, since the signature, message and public key
+//! are all public.
+//!
+//! Signatures with a non-canonical `S` are rejected, so a valid signature
+//! cannot be modified into another valid signature.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let valid = capsules::ed25519::verify(&PUBLIC_KEY, signature, &[header, binary]);
+//! ```
+
+use crate::sha512::Sha512;
+
+/// Length of an Ed25519 public key, in bytes.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length of an Ed25519 signature, in bytes.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// An element of the field modulo 2^255 - 19, as 16 limbs of 16 bits. Limbs
+/// can temporarily hold larger or negative values between carries.
+type Field = [i64; 16];
+
+/// A point on the curve in extended coordinates (X, Y, Z, T).
+type Point = [Field; 4];
+
+const ZERO: Field = [0; 16];
+
+const ONE: Field = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// The curve constant d.
+const D: Field = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070, 0xe898, 0x7779, 0x4079, 0x8cc7,
+    0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+
+/// 2 * d.
+const D2: Field = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0, 0xd130, 0xeef3, 0x80f2, 0x198e,
+    0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+
+/// X coordinate of the base point.
+const X: Field = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c, 0xdc5c, 0xfdd6, 0xe231, 0xc0a4,
+    0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+
+/// Y coordinate of the base point.
+const Y: Field = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666,
+];
+
+/// A square root of -1.
+const I: Field = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43, 0xd7a7, 0x3dfb, 0x0099, 0x2b4d,
+    0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+
+/// The order of the base point, little endian.
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// Verify that `signature` is a signature of the concatenation of the
+/// `message` parts by the owner of `public_key`.
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], signature: &[u8], message: &[&[u8]]) -> bool {
+    if signature.len() != SIGNATURE_LEN {
+        return false;
+    }
+    let mut s = [0; 32];
+    s.copy_from_slice(&signature[32..]);
+    if !scalar_is_canonical(&s) {
+        return false;
+    }
+    let negated_key = match unpack_negated_point(public_key) {
+        Some(point) => point,
+        None => return false,
+    };
+
+    let mut hash = Sha512::new();
+    hash.update(&signature[..32]);
+    hash.update(public_key);
+    for part in message {
+        hash.update(part);
+    }
+    let h = reduce(&hash.finish());
+
+    // [S]B - [h]A must be the point R the signer committed to.
+    let r = point_add(&scalar_mult(negated_key, &h), &scalar_mult_base(&s));
+    pack_point(&r)[..] == signature[..32]
+}
+
+/// Propagate carries so that all limbs are back in 16 bits, folding the carry
+/// out of the top limb back in as 38 = 2 * 19.
+fn carry(o: &mut Field) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swap `p` and `q` if `b` is 1, leave them if `b` is 0.
+fn select(p: &mut Field, q: &mut Field, b: i64) {
+    let c = !(b - 1);
+    for i in 0..16 {
+        let t = c & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+/// The canonical little-endian encoding of `n`.
+fn pack_field(n: &Field) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    // Subtract the modulus twice if the value is not below it.
+    for _ in 0..2 {
+        let mut m = ZERO;
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let b = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - b);
+    }
+    let mut o = [0; 32];
+    for i in 0..16 {
+        o[2 * i] = t[i] as u8;
+        o[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    o
+}
+
+fn fields_differ(a: &Field, b: &Field) -> bool {
+    pack_field(a) != pack_field(b)
+}
+
+fn parity(a: &Field) -> u8 {
+    pack_field(a)[0] & 1
+}
+
+fn unpack_field(n: &[u8; 32]) -> Field {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = n[2 * i] as i64 + ((n[2 * i + 1] as i64) << 8);
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+fn add(a: &Field, b: &Field) -> Field {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = a[i] + b[i];
+    }
+    o
+}
+
+fn sub(a: &Field, b: &Field) -> Field {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = a[i] - b[i];
+    }
+    o
+}
+
+fn mul(a: &Field, b: &Field) -> Field {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut o = ZERO;
+    o.copy_from_slice(&t[..16]);
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Field) -> Field {
+    mul(a, a)
+}
+
+/// a^(p - 2), the inverse of `a`.
+fn invert(a: &Field) -> Field {
+    let mut c = *a;
+    for i in (0..254).rev() {
+        c = square(&c);
+        if i != 2 && i != 4 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+/// a^((p - 5) / 8), used to compute square roots.
+fn pow2523(a: &Field) -> Field {
+    let mut c = *a;
+    for i in (0..251).rev() {
+        c = square(&c);
+        if i != 1 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+fn point_add(p: &Point, q: &Point) -> Point {
+    let a = mul(&sub(&p[1], &p[0]), &sub(&q[1], &q[0]));
+    let b = mul(&add(&p[0], &p[1]), &add(&q[0], &q[1]));
+    let c = mul(&mul(&p[3], &q[3]), &D2);
+    let d = mul(&p[2], &q[2]);
+    let d = add(&d, &d);
+    let e = sub(&b, &a);
+    let f = sub(&d, &c);
+    let g = add(&d, &c);
+    let h = add(&b, &a);
+    [mul(&e, &f), mul(&h, &g), mul(&g, &f), mul(&e, &h)]
+}
+
+fn point_swap(p: &mut Point, q: &mut Point, b: i64) {
+    for i in 0..4 {
+        select(&mut p[i], &mut q[i], b);
+    }
+}
+
+fn pack_point(p: &Point) -> [u8; 32] {
+    let zi = invert(&p[2]);
+    let tx = mul(&p[0], &zi);
+    let ty = mul(&p[1], &zi);
+    let mut r = pack_field(&ty);
+    r[31] ^= parity(&tx) << 7;
+    r
+}
+
+/// [s]q, for a little-endian scalar `s`.
+fn scalar_mult(mut q: Point, s: &[u8; 32]) -> Point {
+    let mut p = [ZERO, ONE, ONE, ZERO];
+    for i in (0..256).rev() {
+        let b = ((s[i / 8] >> (i & 7)) & 1) as i64;
+        point_swap(&mut p, &mut q, b);
+        q = point_add(&q, &p);
+        p = point_add(&p, &p);
+        point_swap(&mut p, &mut q, b);
+    }
+    p
+}
+
+/// [s]B, for the base point B.
+fn scalar_mult_base(s: &[u8; 32]) -> Point {
+    scalar_mult([X, Y, ONE, mul(&X, &Y)], s)
+}
+
+/// Decode the point `p` and negate it, or return `None` if `p` is not on the
+/// curve.
+fn unpack_negated_point(p: &[u8; 32]) -> Option<Point> {
+    let z = ONE;
+    let y = unpack_field(p);
+    let num = sub(&square(&y), &z);
+    let den = add(&z, &mul(&square(&y), &D));
+
+    // x = sqrt(num / den), computed as num * den^3 * (num * den^7)^((p-5)/8).
+    let den2 = square(&den);
+    let den4 = square(&den2);
+    let den6 = mul(&den4, &den2);
+    let t = mul(&mul(&den6, &num), &den);
+    let t = mul(&mul(&mul(&pow2523(&t), &num), &den), &den);
+    let mut x = mul(&t, &den);
+
+    if fields_differ(&mul(&square(&x), &den), &num) {
+        x = mul(&x, &I);
+    }
+    if fields_differ(&mul(&square(&x), &den), &num) {
+        return None;
+    }
+
+    if parity(&x) == p[31] >> 7 {
+        x = sub(&ZERO, &x);
+    }
+    Some([x, y, z, mul(&x, &y)])
+}
+
+/// Whether the little-endian scalar `s` is below L.
+fn scalar_is_canonical(s: &[u8; 32]) -> bool {
+    for i in (0..32).rev() {
+        if (s[i] as i64) < L[i] {
+            return true;
+        }
+        if (s[i] as i64) > L[i] {
+            return false;
+        }
+    }
+    false
+}
+
+/// Reduce the little-endian number `x` modulo L.
+fn mod_l(x: &mut [i64; 64]) -> [u8; 32] {
+    for i in (32..64).rev() {
+        let mut carry = 0;
+        let mut j = i - 32;
+        while j < i - 12 {
+            x[j] += carry - 16 * x[i] * L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+            j += 1;
+        }
+        x[j] += carry;
+        x[i] = 0;
+    }
+    let mut carry = 0;
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * L[j];
+        carry = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= carry * L[j];
+    }
+    let mut r = [0; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        r[i] = (x[i] & 255) as u8;
+    }
+    r
+}
+
+/// Reduce a 64-byte hash modulo L.
+fn reduce(hash: &[u8; 64]) -> [u8; 32] {
+    let mut x = [0; 64];
+    for i in 0..64 {
+        x[i] = hash[i] as i64;
+    }
+    mod_l(&mut x)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify, L};
+
+    fn unhex(hex: &[u8], out: &mut [u8]) {
+        fn digit(c: u8) -> u8 {
+            match c {
+                b'0'..=b'9' => c - b'0',
+                _ => c - b'a' + 10,
+            }
+        }
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = digit(hex[2 * i]) << 4 | digit(hex[2 * i + 1]);
+        }
+    }
+
+    fn key(hex: &[u8]) -> [u8; 32] {
+        let mut key = [0; 32];
+        unhex(hex, &mut key);
+        key
+    }
+
+    fn signature(hex: &[u8]) -> [u8; 64] {
+        let mut signature = [0; 64];
+        unhex(hex, &mut signature);
+        signature
+    }
+
+    // Test vectors 1 to 3 from RFC 8032, section 7.1.
+
+    #[test]
+    fn ed25519_rfc8032_vectors() {
+        assert!(verify(
+            &key(b"d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"),
+            &signature(
+                b"e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+                  5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+            ),
+            &[],
+        ));
+        assert!(verify(
+            &key(b"3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c"),
+            &signature(
+                b"92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+                  085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"
+            ),
+            &[&[0x72]],
+        ));
+        assert!(verify(
+            &key(b"fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025"),
+            &signature(
+                b"6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+                  18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a"
+            ),
+            &[&[0xaf], &[0x82]],
+        ));
+    }
+
+    #[test]
+    fn ed25519_rejects_invalid_signatures() {
+        let public_key = key(b"fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025");
+        let valid = signature(
+            b"6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+              18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        );
+
+        // Different message.
+        assert!(!verify(&public_key, &valid, &[&[0xaf, 0x83]]));
+
+        // Modified R and S.
+        let mut modified = valid;
+        modified[0] ^= 1;
+        assert!(!verify(&public_key, &modified, &[&[0xaf, 0x82]]));
+        let mut modified = valid;
+        modified[40] ^= 1;
+        assert!(!verify(&public_key, &modified, &[&[0xaf, 0x82]]));
+
+        // S + L verifies mathematically but is not canonical.
+        let mut malleated = valid;
+        let mut carry = 0;
+        for i in 0..32 {
+            let sum = malleated[32 + i] as i64 + L[i] + carry;
+            malleated[32 + i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!verify(&public_key, &malleated, &[&[0xaf, 0x82]]));
+
+        // Wrong key and truncated signature.
+        let other_key = key(b"3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        assert!(!verify(&other_key, &valid, &[&[0xaf, 0x82]]));
+        assert!(!verify(&public_key, &valid[..63], &[&[0xaf, 0x82]]));
+    }
+}
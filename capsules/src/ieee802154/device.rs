@@ -5,6 +5,7 @@
 //! - Configuration of addresses and transmit power
 //! - Preparing frames (data frame, command frames, beacon frames)
 //! - Transmitting and receiving frames
+//! - Scanning for and associating with PANs
 //!
 //! Outlining this in a trait allows other implementations of MAC devices that
 //! divide the responsibilities of software and hardware differently. For
@@ -12,7 +13,7 @@
 //! procedure in hardware, as opposed to requiring a software implementation.
 
 use crate::ieee802154::framer::Frame;
-use crate::net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
+use crate::net::ieee802154::{AssociationStatus, Header, KeyId, MacAddress, PanID, SecurityLevel};
 use kernel::ReturnCode;
 
 pub trait MacDevice<'a> {
@@ -20,6 +21,8 @@ pub trait MacDevice<'a> {
     fn set_transmit_client(&self, client: &'a dyn TxClient);
     /// Sets the receive client of this MAC device
     fn set_receive_client(&self, client: &'a dyn RxClient);
+    /// Sets the client notified of scan and association results
+    fn set_mlme_client(&self, client: &'a dyn MlmeClient);
 
     /// The short 16-bit address of the MAC device
     fn get_address(&self) -> u16;
@@ -27,6 +30,8 @@ pub trait MacDevice<'a> {
     fn get_address_long(&self) -> [u8; 8];
     /// The 16-bit PAN ID of the MAC device
     fn get_pan(&self) -> u16;
+    /// The 802.15.4 channel of the MAC device
+    fn get_channel(&self) -> u8;
 
     /// Set the short 16-bit address of the MAC device
     fn set_address(&self, addr: u16);
@@ -34,6 +39,8 @@ pub trait MacDevice<'a> {
     fn set_address_long(&self, addr: [u8; 8]);
     /// Set the 16-bit PAN ID of the MAC device
     fn set_pan(&self, id: u16);
+    /// Set the 802.15.4 channel of the MAC device, from 11 to 26
+    fn set_channel(&self, chan: u8) -> ReturnCode;
 
     /// This method must be called after one or more calls to `set_*`. If
     /// `set_*` is called without calling `config_commit`, there is no guarantee
@@ -73,6 +80,56 @@ pub trait MacDevice<'a> {
     /// transmission process fails, the buffer inside the frame is returned so
     /// that it can be re-used.
     fn transmit(&self, frame: Frame) -> (ReturnCode, Option<&'static mut [u8]>);
+
+    /// Scans the channels set in the `channels` bitmask (bits 11 to 26) one
+    /// after the other, spending `aBaseSuperframeDuration * (2^duration + 1)`
+    /// symbols on each. An energy detection scan reports the peak energy
+    /// measured on each channel, while an active scan sends a beacon request
+    /// and reports every beacon received in response. The MLME client is
+    /// notified with `scan_done` once every channel has been scanned, and the
+    /// original channel is restored.
+    ///
+    /// Returns `EBUSY` if a scan or association is already in progress and
+    /// `EINVAL` if no valid channel is selected or `duration` exceeds 14.
+    fn scan(&self, scan_type: ScanType, channels: u32, duration: u8) -> ReturnCode;
+
+    /// Requests association with the coordinator described by `coordinator`,
+    /// usually found through an active scan. The channel and PAN ID of the
+    /// device are switched to those of the coordinator. `capability` is a
+    /// combination of the `net::ieee802154::capability` flags. The MLME client
+    /// is notified with `associate_done`, and a short address allocated by the
+    /// coordinator is configured automatically.
+    fn associate(&self, coordinator: PanDescriptor, capability: u8) -> ReturnCode;
+
+    /// Starts a PAN with this device as its coordinator on `channel`. The
+    /// device answers beacon requests and, if permitted, association requests
+    /// from then on.
+    fn start_pan(&self, pan: PanID, channel: u8) -> ReturnCode;
+
+    /// Sets whether this device, when acting as a coordinator, accepts
+    /// association requests.
+    fn set_association_permit(&self, permit: bool);
+}
+
+/// The channel mask selecting every 2.4 GHz channel, 11 to 26, for
+/// `MacDevice::scan`.
+pub const ALL_CHANNELS: u32 = 0x07ff_f800;
+
+/// The kind of channel scan performed by `MacDevice::scan`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ScanType {
+    EnergyDetect,
+    Active,
+}
+
+/// A PAN discovered during an active scan.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct PanDescriptor {
+    pub channel: u8,
+    pub pan: PanID,
+    pub coord_addr: MacAddress,
+    pub pan_coordinator: bool,
+    pub association_permit: bool,
 }
 
 /// Trait to be implemented by any user of the IEEE 802.15.4 device that
@@ -112,3 +169,28 @@ pub trait RxClient {
     /// - `data_len`: Length of the data payload
     fn receive<'a>(&self, buf: &'a [u8], header: Header<'a>, data_offset: usize, data_len: usize);
 }
+
+/// Trait to be implemented by users of the IEEE 802.15.4 device that scan for
+/// or associate with PANs, or coordinate a PAN of their own.
+pub trait MlmeClient {
+    /// Reports the peak energy level measured on `channel` during an energy
+    /// detection scan, from 0 (at most -90 dBm) to 255 (at least -50 dBm).
+    fn energy_detected(&self, channel: u8, energy: u8);
+
+    /// Reports a beacon received during an active scan.
+    fn beacon_received(&self, pan: PanDescriptor);
+
+    /// Called once a scan has covered every requested channel.
+    fn scan_done(&self, result: ReturnCode);
+
+    /// Called when an association attempt completes. `result` is `ENOACK` if
+    /// the request was not acknowledged and `FAIL` if no response arrived in
+    /// time. Otherwise, `response` holds the status and short address
+    /// returned by the coordinator.
+    fn associate_done(&self, result: ReturnCode, response: Option<(AssociationStatus, u16)>);
+
+    /// Called on a coordinator when the device `device_addr` asks to join the
+    /// PAN. Returns the short address to allocate to the device, or `None` to
+    /// refuse it.
+    fn associate_indication(&self, device_addr: [u8; 8], capability: u8) -> Option<u16>;
+}
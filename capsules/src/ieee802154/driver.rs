@@ -2,11 +2,15 @@
 //!
 //! Implements a userspace interface for sending and receiving IEEE 802.15.4
 //! frames. Also provides a minimal list-based interface for managing keys and
-//! known link neighbors, which is needed for 802.15.4 security.
+//! known link neighbors, which is needed for 802.15.4 security, and access to
+//! channel scans, association, PAN coordination and frame counter statistics.
 
-use crate::ieee802154::{device, framer};
-use crate::net::ieee802154::{AddressMode, Header, KeyId, MacAddress, PanID, SecurityLevel};
-use crate::net::stream::{decode_bytes, decode_u8, encode_bytes, encode_u8, SResult};
+use crate::ieee802154::{device, frame_counter, framer};
+use crate::net::ieee802154::{
+    AddressMode, AssociationStatus, Header, KeyId, MacAddress, PanID, SecurityLevel,
+};
+use crate::net::stream::{decode_bytes, decode_u16, decode_u8, SResult};
+use crate::net::stream::{encode_bytes, encode_u16, encode_u8};
 use core::cell::Cell;
 use core::cmp::min;
 use kernel::common::cells::{MapCell, OptionalCell, TakeCell};
@@ -15,6 +19,16 @@ use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
 const MAX_NEIGHBORS: usize = 4;
 const MAX_KEYS: usize = 4;
 
+/// The size of an energy detection scan result: the channel and the energy.
+const ENERGY_RESULT_LEN: usize = 2;
+/// The size of a PAN descriptor: the channel, the PAN ID, the coordinator
+/// address mode and address, and the beacon flags.
+const PAN_DESCRIPTOR_LEN: usize = 13;
+const PAN_COORDINATOR_FLAG: u8 = 1 << 0;
+const ASSOCIATION_PERMIT_FLAG: u8 = 1 << 1;
+/// The size of the frame counter statistics: four 32-bit counters.
+const FRAME_COUNTER_STATS_LEN: usize = 16;
+
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Ieee802154 as usize;
 
@@ -98,6 +112,55 @@ fn decode_key_id(buf: &[u8]) -> SResult<KeyId> {
     }
 }
 
+/// Encodes a PAN descriptor in the format expected by the userland driver.
+/// Short addresses are stored in the first two bytes of the address field.
+fn encode_pan_descriptor(pan: &device::PanDescriptor, buf: &mut [u8]) -> SResult {
+    let mut addr = [0u8; 8];
+    match pan.coord_addr {
+        MacAddress::Short(short_addr) => addr[..2].copy_from_slice(&short_addr.to_le_bytes()),
+        MacAddress::Long(long_addr) => addr = long_addr,
+    }
+    let mut flags = 0;
+    if pan.pan_coordinator {
+        flags |= PAN_COORDINATOR_FLAG;
+    }
+    if pan.association_permit {
+        flags |= ASSOCIATION_PERMIT_FLAG;
+    }
+    let off = enc_consume!(buf; encode_u8, pan.channel);
+    let off = enc_consume!(buf, off; encode_u16, pan.pan.to_be());
+    let off = enc_consume!(buf, off; encode_u8, AddressMode::from(&Some(pan.coord_addr)) as u8);
+    let off = enc_consume!(buf, off; encode_bytes, &addr);
+    let off = enc_consume!(buf, off; encode_u8, flags);
+    stream_done!(off);
+}
+
+/// Decodes a PAN descriptor that is in the format produced by the userland
+/// driver.
+fn decode_pan_descriptor(buf: &[u8]) -> SResult<device::PanDescriptor> {
+    let (off, channel) = dec_try!(buf; decode_u8);
+    let (off, pan_be) = dec_try!(buf, off; decode_u16);
+    let (off, mode) = dec_try!(buf, off; decode_u8);
+    let mut addr = [0u8; 8];
+    let off = dec_consume!(buf, off; decode_bytes, &mut addr);
+    let (off, flags) = dec_try!(buf, off; decode_u8);
+    let coord_addr = match stream_from_option!(AddressMode::from_mode(mode as u16)) {
+        AddressMode::NotPresent => stream_err!(),
+        AddressMode::Short => MacAddress::Short(u16::from_le_bytes([addr[0], addr[1]])),
+        AddressMode::Long => MacAddress::Long(addr),
+    };
+    stream_done!(
+        off,
+        device::PanDescriptor {
+            channel: channel,
+            pan: u16::from_be(pan_be),
+            coord_addr: coord_addr,
+            pan_coordinator: (flags & PAN_COORDINATOR_FLAG) != 0,
+            association_permit: (flags & ASSOCIATION_PERMIT_FLAG) != 0,
+        }
+    );
+}
+
 impl From<&'a KeyId> for KeyIdModeUserland {
     fn from(key_id: &'a KeyId) -> Self {
         match *key_id {
@@ -147,10 +210,15 @@ impl KeyDescriptor {
 pub struct App {
     rx_callback: Option<Callback>,
     tx_callback: Option<Callback>,
+    scan_callback: Option<Callback>,
+    associate_callback: Option<Callback>,
     app_read: Option<AppSlice<Shared, u8>>,
     app_write: Option<AppSlice<Shared, u8>>,
     app_cfg: Option<AppSlice<Shared, u8>>,
+    app_scan: Option<AppSlice<Shared, u8>>,
     pending_tx: Option<(u16, Option<(SecurityLevel, KeyId)>)>,
+    /// Number of results written to `app_scan` by the current scan
+    scan_results: usize,
 }
 
 impl Default for App {
@@ -158,10 +226,14 @@ impl Default for App {
         App {
             rx_callback: None,
             tx_callback: None,
+            scan_callback: None,
+            associate_callback: None,
             app_read: None,
             app_write: None,
             app_cfg: None,
+            app_scan: None,
             pending_tx: None,
+            scan_results: 0,
         }
     }
 }
@@ -186,6 +258,11 @@ pub struct RadioDriver<'a> {
     apps: Grant<App>,
     /// ID of app whose transmission request is being processed.
     current_app: OptionalCell<AppId>,
+    /// ID of app whose scan or association request is being processed.
+    mlme_app: OptionalCell<AppId>,
+
+    /// Frame counters of this device and its neighbors, if they are tracked.
+    frame_counters: OptionalCell<&'a frame_counter::FrameCounters<'a>>,
 
     /// Buffer that stores the IEEE 802.15.4 frame to be transmitted.
     kernel_tx: TakeCell<'static, [u8]>,
@@ -205,10 +282,17 @@ impl RadioDriver<'a> {
             num_keys: Cell::new(0),
             apps: grant,
             current_app: OptionalCell::empty(),
+            mlme_app: OptionalCell::empty(),
+            frame_counters: OptionalCell::empty(),
             kernel_tx: TakeCell::new(kernel_tx),
         }
     }
 
+    /// Sets the frame counters whose statistics are exposed to userspace.
+    pub fn set_frame_counters(&self, frame_counters: &'a frame_counter::FrameCounters<'a>) {
+        self.frame_counters.set(frame_counters);
+    }
+
     // Neighbor management functions
 
     /// Add a new neighbor to the end of the list if there is still space
@@ -466,6 +550,40 @@ impl RadioDriver<'a> {
         })
     }
 
+    /// Records the app whose scan or association request was accepted by the
+    /// MAC device, so that the results are delivered to it.
+    fn start_mlme_op(&self, appid: AppId, result: ReturnCode) -> ReturnCode {
+        if result == ReturnCode::SUCCESS {
+            self.mlme_app.set(appid);
+        }
+        result
+    }
+
+    /// Appends a scan result to the scan buffer of the app that started the
+    /// scan. Results that do not fit are dropped.
+    fn add_scan_result<F>(&self, len: usize, encode: F)
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        self.mlme_app.map(|appid| {
+            let _ = self.apps.enter(*appid, |app, _| {
+                let offset = app.scan_results * len;
+                let written = app.app_scan.as_mut().map_or(false, |results| {
+                    let results = results.as_mut();
+                    if offset + len <= results.len() {
+                        encode(&mut results[offset..offset + len]);
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if written {
+                    app.scan_results += 1;
+                }
+            });
+        });
+    }
+
     /// Schedule the next transmission if there is one pending. Performs the
     /// transmission asynchronously, returning any errors via callbacks.
     #[inline]
@@ -533,6 +651,10 @@ impl Driver for RadioDriver<'a> {
     /// - `2`: Config buffer. Used to contain miscellaneous data associated with
     ///        some commands because the system call parameters / return codes are
     ///        not enough to convey the desired information.
+    /// - `3`: Scan buffer. Will contain the results of a scan: 2 bytes per
+    ///        channel for energy detection scans (the channel, then the energy
+    ///        level from 0 to 255), and 13 bytes per beacon received for active
+    ///        scans (the PAN descriptor format described under command 28).
     fn allow(
         &self,
         appid: AppId,
@@ -540,11 +662,12 @@ impl Driver for RadioDriver<'a> {
         slice: Option<AppSlice<Shared, u8>>,
     ) -> ReturnCode {
         match allow_num {
-            0 | 1 | 2 => self.do_with_app(appid, |app| {
+            0 | 1 | 2 | 3 => self.do_with_app(appid, |app| {
                 match allow_num {
                     0 => app.app_read = slice,
                     1 => app.app_write = slice,
                     2 => app.app_cfg = slice,
+                    3 => app.app_scan = slice,
                     _ => {}
                 }
                 ReturnCode::SUCCESS
@@ -559,6 +682,11 @@ impl Driver for RadioDriver<'a> {
     ///
     /// - `0`: Setup callback for when frame is received.
     /// - `1`: Setup callback for when frame is transmitted.
+    /// - `2`: Setup callback for when a scan completes. The arguments are the
+    ///        result and the number of scan results written to the scan buffer.
+    /// - `3`: Setup callback for when an association attempt completes. The
+    ///        arguments are the result, the association status and the short
+    ///        address allocated by the coordinator.
     fn subscribe(
         &self,
         subscribe_num: usize,
@@ -574,6 +702,14 @@ impl Driver for RadioDriver<'a> {
                 app.tx_callback = callback;
                 ReturnCode::SUCCESS
             }),
+            2 => self.do_with_app(app_id, |app| {
+                app.scan_callback = callback;
+                ReturnCode::SUCCESS
+            }),
+            3 => self.do_with_app(app_id, |app| {
+                app.associate_callback = callback;
+                ReturnCode::SUCCESS
+            }),
             _ => ReturnCode::ENOSUPPORT,
         }
     }
@@ -627,7 +763,31 @@ impl Driver for RadioDriver<'a> {
     ///                      9 bytes: the key ID (might not use all bytes) +
     ///                      16 bytes: the key.
     /// - `25`: Remove the key at an index.
-    fn command(&self, command_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
+    /// - `26`: Transmit a frame to the given short address.
+    ///        app_cfg (in): 1 byte: the security level +
+    ///                      1 byte: the key ID mode +
+    ///                      9 bytes: the key ID (might not use all bytes).
+    /// - `27`: Scan the channels set in the channel mask (bits 11 to 26).
+    ///        The second argument is the scan type (0: energy detection, 1:
+    ///        active) in bits 0-7 and the scan duration exponent in bits 8-15.
+    /// - `28`: Associate with a coordinator, with the given capability
+    ///        information.
+    ///        app_cfg (in): 13 bytes: the PAN descriptor: 1 byte: the channel +
+    ///                      2 bytes: the PAN ID, little-endian +
+    ///                      1 byte: the address mode (2: short, 3: long) +
+    ///                      8 bytes: the coordinator address +
+    ///                      1 byte: flags (bit 0: PAN coordinator,
+    ///                      bit 1: association permitted).
+    /// - `29`: Start a PAN as its coordinator with the given PAN ID, on the
+    ///        channel given as the second argument.
+    /// - `30`: Set whether association requests are accepted (nonzero) or not.
+    /// - `31`: Get the frame counter statistics.
+    ///        app_cfg (out): 16 bytes: four 32-bit little-endian counters: the
+    ///                       secured frames accepted, the secured frames
+    ///                       rejected as replays, the devices evicted from the
+    ///                       frame counter table, and the next outgoing frame
+    ///                       counter.
+    fn command(&self, command_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
         match command_num {
             0 => ReturnCode::SUCCESS,
             1 => {
@@ -651,8 +811,7 @@ impl Driver for RadioDriver<'a> {
                 self.mac.set_pan(arg1 as u16);
                 ReturnCode::SUCCESS
             }
-            // XXX: Setting channel DEPRECATED by MAC layer channel control
-            5 => ReturnCode::ENOSUPPORT,
+            5 => self.mac.set_channel(arg1 as u8),
             // XXX: Setting tx power DEPRECATED by MAC layer tx power control
             6 => ReturnCode::ENOSUPPORT,
             7 => {
@@ -677,8 +836,13 @@ impl Driver for RadioDriver<'a> {
                     value: (pan as usize) + 1,
                 }
             }
-            // XXX: Getting channel DEPRECATED by MAC layer channel control
-            11 => ReturnCode::ENOSUPPORT,
+            11 => {
+                // Guarantee that the channel is positive by adding 1
+                let channel = self.mac.get_channel();
+                ReturnCode::SuccessWithValue {
+                    value: (channel as usize) + 1,
+                }
+            }
             // XXX: Getting tx power DEPRECATED by MAC layer tx power control
             12 => ReturnCode::ENOSUPPORT,
             13 => {
@@ -792,6 +956,48 @@ impl Driver for RadioDriver<'a> {
                     self.do_next_tx_sync(appid)
                 })
             }
+            27 => {
+                let scan_type = match arg2 & 0xff {
+                    0 => device::ScanType::EnergyDetect,
+                    1 => device::ScanType::Active,
+                    _ => {
+                        return ReturnCode::EINVAL;
+                    }
+                };
+                let duration = ((arg2 >> 8) & 0xff) as u8;
+                let result = self.do_with_app(appid, |app| {
+                    app.scan_results = 0;
+                    self.mac.scan(scan_type, arg1 as u32, duration)
+                });
+                self.start_mlme_op(appid, result)
+            }
+            28 => {
+                let result = self.do_with_cfg(appid, PAN_DESCRIPTOR_LEN, |cfg| {
+                    decode_pan_descriptor(cfg)
+                        .done()
+                        .map_or(ReturnCode::EINVAL, |(_, coordinator)| {
+                            self.mac.associate(coordinator, arg1 as u8)
+                        })
+                });
+                self.start_mlme_op(appid, result)
+            }
+            29 => self.mac.start_pan(arg1 as u16, arg2 as u8),
+            30 => {
+                self.mac.set_association_permit(arg1 != 0);
+                ReturnCode::SUCCESS
+            }
+            31 => self
+                .frame_counters
+                .map_or(ReturnCode::ENOSUPPORT, |frame_counters| {
+                    let stats = frame_counters.statistics();
+                    self.do_with_cfg_mut(appid, FRAME_COUNTER_STATS_LEN, |cfg| {
+                        cfg[0..4].copy_from_slice(&stats.accepted.to_le_bytes());
+                        cfg[4..8].copy_from_slice(&stats.replayed.to_le_bytes());
+                        cfg[8..12].copy_from_slice(&stats.evicted.to_le_bytes());
+                        cfg[12..16].copy_from_slice(&stats.outgoing.to_le_bytes());
+                        ReturnCode::SUCCESS
+                    })
+                }),
             _ => ReturnCode::ENOSUPPORT,
         }
     }
@@ -850,3 +1056,47 @@ impl device::RxClient for RadioDriver<'a> {
         });
     }
 }
+
+impl device::MlmeClient for RadioDriver<'a> {
+    fn energy_detected(&self, channel: u8, energy: u8) {
+        self.add_scan_result(ENERGY_RESULT_LEN, |result| {
+            result[0] = channel;
+            result[1] = energy;
+        });
+    }
+
+    fn beacon_received(&self, pan: device::PanDescriptor) {
+        self.add_scan_result(PAN_DESCRIPTOR_LEN, |result| {
+            encode_pan_descriptor(&pan, result);
+        });
+    }
+
+    fn scan_done(&self, result: ReturnCode) {
+        self.mlme_app.take().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                let count = app.scan_results;
+                app.scan_callback
+                    .take()
+                    .map(|mut cb| cb.schedule(result.into(), count, 0));
+            });
+        });
+    }
+
+    fn associate_done(&self, result: ReturnCode, response: Option<(AssociationStatus, u16)>) {
+        self.mlme_app.take().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                let (status, short_addr) = response.map_or((0, 0), |(status, short_addr)| {
+                    (status as usize, short_addr as usize)
+                });
+                app.associate_callback
+                    .take()
+                    .map(|mut cb| cb.schedule(result.into(), status, short_addr));
+            });
+        });
+    }
+
+    fn associate_indication(&self, _device_addr: [u8; 8], _capability: u8) -> Option<u16> {
+        // Address allocation is left to other clients of the MAC device
+        None
+    }
+}
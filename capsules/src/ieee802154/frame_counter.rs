@@ -0,0 +1,435 @@
+//! Keeps the IEEE 802.15.4 frame counters used to protect secured frames
+//! against replay.
+//!
+//! `FrameCounters` implements the framer's `FrameCounterProcedure`. It hands
+//! out the frame counters of outgoing secured frames, and keeps a small table
+//! of the last frame counter accepted from each device, keyed by extended
+//! address. Secured frames whose frame counter is not newer than the last one
+//! accepted from their source are rejected as replays. When the table is full,
+//! the oldest entry is evicted in a round-robin fashion, so frames from a
+//! device that was evicted are accepted again until it is seen anew.
+//!
+//! Outgoing frame counters must never be reused with the same key, including
+//! across reboots. Rather than writing every counter to nonvolatile storage,
+//! a block of `COUNTER_RESERVE` counters is reserved ahead of time by
+//! persisting the limit of the block, and the next block is reserved once half
+//! of the current one is used. At boot, counting resumes from the persisted
+//! limit, and secured frames cannot be sent until the first block has been
+//! reserved. Erased storage holds no limit, so counting starts from zero. If
+//! the limit cannot be read, secured frames cannot be sent until `load()`
+//! succeeds. Without storage, counting starts from zero on every boot.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let frame_counters = static_init!(
+//!     capsules::ieee802154::frame_counter::FrameCounters<'static>,
+//!     capsules::ieee802154::frame_counter::FrameCounters::new(
+//!         Some(nonvolatile_storage),
+//!         FRAME_COUNTER_ADDRESS,
+//!         &mut capsules::ieee802154::frame_counter::BUFFER));
+//! nonvolatile_storage.set_client(frame_counters);
+//! mac_device.set_frame_counter_procedure(frame_counters);
+//! frame_counters.load();
+//! ```
+
+use crate::ieee802154::framer::FrameCounterProcedure;
+use core::cell::Cell;
+use core::cmp;
+use kernel::common::cells::{MapCell, TakeCell};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::ReturnCode;
+
+/// The number of devices whose frame counters are remembered.
+pub const MAX_DEVICES: usize = 8;
+/// The number of outgoing frame counters reserved in storage at a time.
+const COUNTER_RESERVE: u32 = 1024;
+/// The frame counter value that may not be used, IEEE 802.15.4-2015 9.2.1.
+const COUNTER_ERROR: u32 = 0xffffffff;
+/// The highest limit that is persisted: erased storage reads as
+/// `COUNTER_ERROR`, so that value means that no limit has been persisted.
+const MAX_LIMIT: u32 = COUNTER_ERROR - 1;
+
+/// The size of the persisted outgoing frame counter limit.
+pub const BUF_LEN: usize = 4;
+pub static mut BUFFER: [u8; BUF_LEN] = [0; BUF_LEN];
+
+/// Counters describing the frame counters of this device and the secured
+/// frames received from other devices.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct FrameCounterStats {
+    /// Secured frames accepted from other devices
+    pub accepted: u32,
+    /// Secured frames rejected because their frame counter was stale
+    pub replayed: u32,
+    /// Devices forgotten to make room in the device table
+    pub evicted: u32,
+    /// The frame counter of the next secured frame sent by this device
+    pub outgoing: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct DeviceCounter {
+    addr: [u8; 8],
+    counter: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum StorageState {
+    /// The persisted limit has not been read yet
+    Loading,
+    /// The persisted limit could not be read
+    Failed,
+    Idle,
+    /// The given limit is being persisted
+    Saving(u32),
+}
+
+pub struct FrameCounters<'a> {
+    storage: Option<&'a dyn NonvolatileStorage<'a>>,
+    /// Where the outgoing frame counter limit is persisted
+    address: usize,
+    buffer: TakeCell<'a, [u8]>,
+    storage_state: Cell<StorageState>,
+
+    /// The frame counter of the next secured frame sent
+    outgoing: Cell<u32>,
+    /// Outgoing frame counters below this limit have been reserved in storage
+    limit: Cell<u32>,
+
+    /// The last frame counter accepted from each known device
+    devices: MapCell<[Option<DeviceCounter>; MAX_DEVICES]>,
+    /// The entry of the device table to evict next
+    next_evicted: Cell<usize>,
+    stats: Cell<FrameCounterStats>,
+}
+
+impl FrameCounters<'a> {
+    pub fn new(
+        storage: Option<&'a dyn NonvolatileStorage<'a>>,
+        address: usize,
+        buffer: &'a mut [u8],
+    ) -> FrameCounters<'a> {
+        FrameCounters {
+            storage: storage,
+            address: address,
+            buffer: TakeCell::new(buffer),
+            storage_state: Cell::new(if storage.is_some() {
+                StorageState::Loading
+            } else {
+                StorageState::Idle
+            }),
+            outgoing: Cell::new(0),
+            limit: Cell::new(if storage.is_some() { 0 } else { COUNTER_ERROR }),
+            devices: MapCell::new([None; MAX_DEVICES]),
+            next_evicted: Cell::new(0),
+            stats: Cell::new(Default::default()),
+        }
+    }
+
+    /// Reads the persisted outgoing frame counter limit, after which the
+    /// first block of frame counters is reserved. Until this completes,
+    /// secured frames cannot be sent. Can be called again if reading fails.
+    pub fn load(&self) -> ReturnCode {
+        match self.storage_state.get() {
+            StorageState::Loading | StorageState::Failed => {}
+            _ => return ReturnCode::EALREADY,
+        }
+        let result = match (self.storage, self.buffer.take()) {
+            (Some(storage), Some(buffer)) => storage.read(buffer, self.address, BUF_LEN),
+            _ => ReturnCode::ERESERVE,
+        };
+        self.storage_state.set(if result == ReturnCode::SUCCESS {
+            StorageState::Loading
+        } else {
+            StorageState::Failed
+        });
+        result
+    }
+
+    pub fn statistics(&self) -> FrameCounterStats {
+        let mut stats = self.stats.get();
+        stats.outgoing = self.outgoing.get();
+        stats
+    }
+
+    fn update_stats<F: FnOnce(&mut FrameCounterStats)>(&self, update: F) {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /// Persists the limit of the next block of outgoing frame counters, if
+    /// storage is idle and the limit would change.
+    fn reserve(&self) {
+        let limit = cmp::min(
+            self.outgoing.get().saturating_add(COUNTER_RESERVE),
+            MAX_LIMIT,
+        );
+        if self.storage_state.get() != StorageState::Idle || limit <= self.limit.get() {
+            return;
+        }
+        self.storage.map(|storage| {
+            self.buffer.take().map(|buffer| {
+                buffer[..BUF_LEN].copy_from_slice(&limit.to_le_bytes());
+                if storage.write(buffer, self.address, BUF_LEN) == ReturnCode::SUCCESS {
+                    self.storage_state.set(StorageState::Saving(limit));
+                }
+            });
+        });
+    }
+}
+
+impl FrameCounterProcedure for FrameCounters<'a> {
+    fn next_frame_counter(&self) -> Option<u32> {
+        let counter = self.outgoing.get();
+        if counter >= self.limit.get() || counter == COUNTER_ERROR {
+            self.reserve();
+            return None;
+        }
+        self.outgoing.set(counter + 1);
+        if self.limit.get() - (counter + 1) <= COUNTER_RESERVE / 2 {
+            self.reserve();
+        }
+        Some(counter)
+    }
+
+    fn check_frame_counter(&self, device_addr: [u8; 8], frame_counter: u32) -> bool {
+        let fresh = self
+            .devices
+            .map(|devices| {
+                devices
+                    .iter()
+                    .filter_map(|device| *device)
+                    .find(|device| device.addr == device_addr)
+                    .map_or(true, |device| frame_counter > device.counter)
+            })
+            .unwrap_or(true);
+        if !fresh {
+            self.update_stats(|stats| stats.replayed += 1);
+        }
+        fresh
+    }
+
+    fn update_frame_counter(&self, device_addr: [u8; 8], frame_counter: u32) {
+        let mut evicted = false;
+        self.devices.map(|devices| {
+            let known = devices
+                .iter()
+                .position(|device| device.map_or(false, |device| device.addr == device_addr));
+            let index = match known.or_else(|| devices.iter().position(|device| device.is_none())) {
+                Some(index) => index,
+                None => {
+                    let index = self.next_evicted.get();
+                    self.next_evicted.set((index + 1) % MAX_DEVICES);
+                    evicted = true;
+                    index
+                }
+            };
+            devices[index] = Some(DeviceCounter {
+                addr: device_addr,
+                counter: frame_counter,
+            });
+        });
+        self.update_stats(|stats| {
+            stats.accepted += 1;
+            if evicted {
+                stats.evicted += 1;
+            }
+        });
+    }
+}
+
+impl NonvolatileStorageClient<'a> for FrameCounters<'a> {
+    fn read_done(&self, buffer: &'a mut [u8], length: usize) {
+        if length < BUF_LEN {
+            self.buffer.replace(buffer);
+            self.storage_state.set(StorageState::Failed);
+            return;
+        }
+        let mut limit = [0; BUF_LEN];
+        limit.copy_from_slice(&buffer[..BUF_LEN]);
+        self.buffer.replace(buffer);
+
+        // Counters below the persisted limit may have been used before the
+        // reboot, so counting resumes from it.
+        let limit = match u32::from_le_bytes(limit) {
+            COUNTER_ERROR => 0,
+            limit => limit,
+        };
+        self.outgoing.set(limit);
+        self.limit.set(limit);
+        self.storage_state.set(StorageState::Idle);
+        self.reserve();
+    }
+
+    fn write_done(&self, buffer: &'a mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        if let StorageState::Saving(limit) = self.storage_state.get() {
+            self.limit.set(limit);
+        }
+        self.storage_state.set(StorageState::Idle);
+
+        // Frames may have been sent while the limit was being written
+        if self.limit.get() - self.outgoing.get() <= COUNTER_RESERVE / 2 {
+            self.reserve();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::{FrameCounters, BUF_LEN, COUNTER_RESERVE, MAX_DEVICES};
+    use crate::ieee802154::framer::FrameCounterProcedure;
+    use core::cell::Cell;
+    use kernel::common::cells::TakeCell;
+    use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+    use kernel::ReturnCode;
+
+    const ADDRESS: usize = 0x100;
+
+    // Storage holding one persisted limit, whose operations are completed by
+    // the tests.
+    struct MockStorage<'a> {
+        contents: Cell<[u8; BUF_LEN]>,
+        buffer: TakeCell<'a, [u8]>,
+        writing: Cell<bool>,
+    }
+
+    impl MockStorage<'a> {
+        fn new(contents: [u8; BUF_LEN]) -> MockStorage<'a> {
+            MockStorage {
+                contents: Cell::new(contents),
+                buffer: TakeCell::empty(),
+                writing: Cell::new(false),
+            }
+        }
+
+        fn persisted(&self) -> u32 {
+            u32::from_le_bytes(self.contents.get())
+        }
+
+        fn complete(&self, counters: &FrameCounters<'a>, length: usize) {
+            let buffer = self.buffer.take().expect("no pending operation");
+            if self.writing.get() {
+                let mut contents = [0; BUF_LEN];
+                contents.copy_from_slice(&buffer[..BUF_LEN]);
+                self.contents.set(contents);
+                counters.write_done(buffer, length);
+            } else {
+                buffer[..BUF_LEN].copy_from_slice(&self.contents.get());
+                counters.read_done(buffer, length);
+            }
+        }
+    }
+
+    impl NonvolatileStorage<'a> for MockStorage<'a> {
+        fn set_client(&self, _client: &'a dyn NonvolatileStorageClient<'a>) {}
+
+        fn read(&self, buffer: &'a mut [u8], address: usize, length: usize) -> ReturnCode {
+            assert_eq!((address, length), (ADDRESS, BUF_LEN));
+            self.writing.set(false);
+            self.buffer.replace(buffer);
+            ReturnCode::SUCCESS
+        }
+
+        fn write(&self, buffer: &'a mut [u8], address: usize, length: usize) -> ReturnCode {
+            assert_eq!((address, length), (ADDRESS, BUF_LEN));
+            self.writing.set(true);
+            self.buffer.replace(buffer);
+            ReturnCode::SUCCESS
+        }
+    }
+
+    fn buffer() -> &'static mut [u8] {
+        std::boxed::Box::leak(std::boxed::Box::new([0; BUF_LEN]))
+    }
+
+    // Load the persisted limit and reserve the first block of counters.
+    fn load(storage: &'a MockStorage<'a>, counters: &FrameCounters<'a>) {
+        assert_eq!(counters.load(), ReturnCode::SUCCESS);
+        storage.complete(counters, BUF_LEN);
+        assert_eq!(counters.next_frame_counter(), None);
+        storage.complete(counters, BUF_LEN);
+    }
+
+    #[test]
+    fn erased_storage_counts_from_zero() {
+        let storage = MockStorage::new([0xff; BUF_LEN]);
+        let counters = FrameCounters::new(Some(&storage), ADDRESS, buffer());
+        load(&storage, &counters);
+        assert_eq!(storage.persisted(), COUNTER_RESERVE);
+        assert_eq!(counters.next_frame_counter(), Some(0));
+        assert_eq!(counters.next_frame_counter(), Some(1));
+    }
+
+    #[test]
+    fn counting_resumes_from_persisted_limit() {
+        let storage = MockStorage::new(5000u32.to_le_bytes());
+        let counters = FrameCounters::new(Some(&storage), ADDRESS, buffer());
+        load(&storage, &counters);
+        assert_eq!(storage.persisted(), 5000 + COUNTER_RESERVE);
+        assert_eq!(counters.next_frame_counter(), Some(5000));
+    }
+
+    #[test]
+    fn counters_are_limited_to_reserved_block() {
+        let storage = MockStorage::new([0xff; BUF_LEN]);
+        let counters = FrameCounters::new(Some(&storage), ADDRESS, buffer());
+        load(&storage, &counters);
+
+        // The next block is reserved once half of the current one is used,
+        // but counters beyond the current one are not handed out until it
+        // has been persisted.
+        for counter in 0..COUNTER_RESERVE {
+            assert_eq!(counters.next_frame_counter(), Some(counter));
+        }
+        assert_eq!(counters.next_frame_counter(), None);
+        assert_eq!(storage.persisted(), COUNTER_RESERVE);
+
+        storage.complete(&counters, BUF_LEN);
+        assert_eq!(storage.persisted(), COUNTER_RESERVE + COUNTER_RESERVE / 2);
+        assert_eq!(counters.next_frame_counter(), Some(COUNTER_RESERVE));
+    }
+
+    #[test]
+    fn read_error_blocks_sending_until_loaded() {
+        let storage = MockStorage::new(5000u32.to_le_bytes());
+        let counters = FrameCounters::new(Some(&storage), ADDRESS, buffer());
+        assert_eq!(counters.load(), ReturnCode::SUCCESS);
+        storage.complete(&counters, 0);
+        assert_eq!(counters.next_frame_counter(), None);
+        assert!(storage.buffer.is_none());
+
+        load(&storage, &counters);
+        assert_eq!(counters.next_frame_counter(), Some(5000));
+        assert_eq!(counters.load(), ReturnCode::EALREADY);
+    }
+
+    #[test]
+    fn replayed_frames_are_rejected() {
+        let counters = FrameCounters::new(None, 0, buffer());
+        let device = [1; 8];
+        assert!(counters.check_frame_counter(device, 5));
+        counters.update_frame_counter(device, 5);
+        assert!(!counters.check_frame_counter(device, 5));
+        assert!(!counters.check_frame_counter(device, 4));
+        assert!(counters.check_frame_counter(device, 6));
+        assert!(counters.check_frame_counter([2; 8], 0));
+
+        // Filling the device table evicts the oldest device
+        for i in 0..MAX_DEVICES as u8 {
+            counters.update_frame_counter([i + 2; 8], 1);
+        }
+        assert!(counters.check_frame_counter(device, 5));
+        assert!(!counters.check_frame_counter([2; 8], 1));
+
+        let stats = counters.statistics();
+        assert_eq!(stats.accepted, 1 + MAX_DEVICES as u32);
+        assert_eq!(stats.replayed, 3);
+        assert_eq!(stats.evicted, 1);
+    }
+}
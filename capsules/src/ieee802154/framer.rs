@@ -8,6 +8,19 @@
 //! and automatic acknowledgement. Radio power management and channel selection
 //! is also passed down to the MAC control layer.
 //!
+//! The framer also implements the parts of the MAC sublayer management entity
+//! (MLME) needed to form nonbeacon-enabled PANs: energy detection and active
+//! channel scans, answering beacon requests with beacons, and the association
+//! request/response exchange. Beacon and MAC command frames are consumed by the
+//! framer and never passed to the receive client. Client frames submitted while
+//! a scan or association is in progress are held back until it completes, since
+//! the radio may be tuned to another channel.
+//!
+//! Frame counters are handed out and checked by a `FrameCounterProcedure`, such
+//! as `capsules::ieee802154::frame_counter::FrameCounters`. Secured frames
+//! whose frame counter is not newer than the last one authenticated from their
+//! source are dropped.
+//!
 //! Usage
 //! -----
 //!
@@ -18,11 +31,14 @@
 //! ```rust
 //! let xmac: &XMacDevice = /* ... */;
 //! let mac_device = static_init!(
-//!     capsules::ieee802154::mac::Framer<'static, XMacDevice>,
-//!     capsules::ieee802154::mac::Framer::new(xmac));
+//!     capsules::ieee802154::framer::Framer<'static, XMacDevice, AESDevice, AlarmDevice>,
+//!     capsules::ieee802154::framer::Framer::new(xmac, aes_ccm, alarm, &mut MLME_BUF));
 //! xmac.set_transmit_client(mac_device);
-//! xmac.set_receive_client(mac_device, &mut MAC_RX_BUF);
+//! xmac.set_receive_client(mac_device);
+//! xmac.set_receive_buffer(&mut MAC_RX_BUF);
 //! xmac.set_config_client(mac_device);
+//! xmac.set_energy_detect_client(mac_device);
+//! alarm.set_client(mac_device);
 //! ```
 //!
 //! The `mac_device` device is now set up. Users of the MAC device can now
@@ -59,27 +75,31 @@
 //!     capsules::ieee802154::RadioDriver::new(mac_device, kernel::Grant::create(), &mut RADIO_BUF));
 //! mac_device.set_key_procedure(radio_capsule);
 //! mac_device.set_device_procedure(radio_capsule);
+//! mac_device.set_frame_counter_procedure(frame_counters);
 //! mac_device.set_transmit_client(radio_capsule);
 //! mac_device.set_receive_client(radio_capsule);
 //! ```
 
 //
 // TODO: Encryption/decryption
-// TODO: Sending beacon frames
-// TODO: Channel scanning
+// TODO: Beacon-enabled PANs and indirect transmission
 //
 
-use crate::ieee802154::device::{MacDevice, RxClient, TxClient};
+use crate::ieee802154::device::{
+    MacDevice, MlmeClient, PanDescriptor, RxClient, ScanType, TxClient, ALL_CHANNELS,
+};
 use crate::ieee802154::mac::Mac;
 use crate::net::ieee802154::{
-    FrameType, FrameVersion, Header, KeyId, MacAddress, PanID, Security, SecurityLevel,
+    AssociationStatus, Beacon, FrameType, FrameVersion, Header, KeyId, MacAddress, MacCommand,
+    PanID, Security, SecurityLevel, BROADCAST_ADDR, BROADCAST_PAN, NO_SHORT_ADDR,
 };
 use crate::net::stream::SResult;
 use crate::net::stream::{encode_bytes, encode_u32, encode_u8};
 use core::cell::Cell;
-use kernel::common::cells::{MapCell, OptionalCell};
+use kernel::common::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::hil::radio;
 use kernel::hil::symmetric_encryption::{CCMClient, AES128CCM};
+use kernel::hil::time::{self, Frequency};
 use kernel::ReturnCode;
 
 /// A `Frame` wraps a static mutable byte slice and keeps just enough
@@ -244,6 +264,25 @@ pub trait DeviceProcedure {
     fn lookup_addr_long(&self, addr: MacAddress) -> Option<[u8; 8]>;
 }
 
+/// IEEE 802.15.4-2015, 9.2.1 and 9.2.3, frame counter procedures.
+/// Trait to be implemented by an upper layer that keeps the outgoing frame
+/// counter of this device and the frame counters last accepted from other
+/// devices, so that secured frames cannot be replayed.
+pub trait FrameCounterProcedure {
+    /// Return the frame counter to secure the next outgoing frame with, and
+    /// advance it. Returns `None` if no frame counter can be used, either
+    /// because the counter is exhausted or because it is not yet known.
+    fn next_frame_counter(&self) -> Option<u32>;
+
+    /// Check that the frame counter of a frame received from the device with
+    /// the given extended address is newer than the last one accepted from it.
+    fn check_frame_counter(&self, device_addr: [u8; 8], frame_counter: u32) -> bool;
+
+    /// Record the frame counter of an authenticated frame received from the
+    /// device with the given extended address.
+    fn update_frame_counter(&self, device_addr: [u8; 8], frame_counter: u32);
+}
+
 /// This state enum describes the state of the transmission pipeline.
 /// Conditionally-present state is also included as fields in the enum variants.
 /// We can view the transmission process as a state machine driven by the
@@ -267,6 +306,63 @@ enum TxState {
     ReadyToTransmit(FrameInfo, &'static mut [u8]),
 }
 
+/// IEEE 802.15.4-2015, 8.1.3.1, aBaseSuperframeDuration in microseconds,
+/// 960 symbols of 16 us each on the 2.4 GHz O-QPSK PHY.
+const BASE_SUPERFRAME_DURATION_US: u64 = 960 * 16;
+/// IEEE 802.15.4-2015, 8.4.3.1, macResponseWaitTime: how long a device waits
+/// for an association response after its request was acknowledged.
+const RESPONSE_WAIT_US: u64 = 32 * BASE_SUPERFRAME_DURATION_US;
+/// The largest scan duration exponent allowed by the standard.
+const MAX_SCAN_DURATION: u8 = 14;
+/// Received signal powers mapped to the lowest and highest energy levels
+/// reported by energy detection scans.
+const ED_MIN_POWER: i16 = -90;
+const ED_MAX_POWER: i16 = -50;
+
+/// Frames sent by the framer itself on behalf of the MLME procedures.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum MlmeFrame {
+    BeaconRequest,
+    Beacon,
+    /// The coordinator to associate with, and the capability information
+    AssociationRequest(PanDescriptor, u8),
+    /// The device address, its new short address and the status
+    AssociationResponse([u8; 8], u16, AssociationStatus),
+}
+
+/// The state of the scan and association procedures. Both switch the radio
+/// to another channel and wait for the configuration to be committed before
+/// sending any frames.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum MlmeState {
+    Idle,
+    /// Waiting to be tuned to the given channel before scanning it.
+    ScanSwitching(ScanType, u8),
+    /// Listening on the given channel until the scan alarm fires.
+    Scanning(ScanType, u8),
+    /// Every channel has been scanned and the original channel is being
+    /// restored.
+    ScanRestoring,
+    /// Waiting to be tuned to the coordinator's channel and PAN.
+    AssociateSwitching(PanDescriptor, u8),
+    /// Sending an association request to the coordinator, then waiting for
+    /// its response until the alarm fires.
+    Associating(PanDescriptor),
+}
+
+/// Converts a received signal power to an ED value, linear between
+/// `ED_MIN_POWER` (0) and `ED_MAX_POWER` (255).
+fn energy_level(power: i8) -> u8 {
+    let power = power as i16;
+    if power <= ED_MIN_POWER {
+        0
+    } else if power >= ED_MAX_POWER {
+        255
+    } else {
+        ((power - ED_MIN_POWER) * 255 / (ED_MAX_POWER - ED_MIN_POWER)) as u8
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 enum RxState {
     /// There is no frame that has been received.
@@ -291,15 +387,19 @@ enum RxState {
 /// machines corresponding to the transmission, reception and
 /// encryption/decryption pipelines. See the documentation in
 /// `capsules/src/mac.rs` for more details.
-pub struct Framer<'a, M: Mac, A: AES128CCM<'a>> {
+pub struct Framer<'a, M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> {
     mac: &'a M,
     aes_ccm: &'a A,
+    alarm: &'a T,
     data_sequence: Cell<u8>,
+    beacon_sequence: Cell<u8>,
 
     /// KeyDescriptor lookup procedure
     key_procedure: OptionalCell<&'a dyn KeyProcedure>,
     /// DeviceDescriptor lookup procedure
     device_procedure: OptionalCell<&'a dyn DeviceProcedure>,
+    /// Frame counter procedures
+    frame_counter_procedure: OptionalCell<&'a dyn FrameCounterProcedure>,
 
     /// Transmision pipeline state. This should never be `None`, except when
     /// transitioning between states. That is, any method that consumes the
@@ -307,25 +407,75 @@ pub struct Framer<'a, M: Mac, A: AES128CCM<'a>> {
     /// associated state information.
     tx_state: MapCell<TxState>,
     tx_client: OptionalCell<&'a dyn TxClient>,
+    /// Whether a client frame is in the transmission pipeline or the radio.
+    client_tx: Cell<bool>,
+    /// A client frame held back until the MLME procedures complete.
+    tx_held: MapCell<Frame>,
 
     /// Reception pipeline state. Similar to the above, this should never be
     /// `None`, except when transitioning between states.
     rx_state: MapCell<RxState>,
     rx_client: OptionalCell<&'a dyn RxClient>,
+
+    /// Scan and association state
+    mlme_state: Cell<MlmeState>,
+    mlme_client: OptionalCell<&'a dyn MlmeClient>,
+    /// Buffer for the frames sent by the MLME procedures
+    mlme_buf: TakeCell<'static, [u8]>,
+    /// The MLME frame in the transmission pipeline or the radio, if any
+    mlme_tx: Cell<Option<MlmeFrame>>,
+    /// An MLME frame waiting for the transmission pipeline to be free
+    mlme_pending: Cell<Option<MlmeFrame>>,
+
+    /// Channels left to scan, the scan duration exponent, and the channel to
+    /// return to once done
+    scan_channels: Cell<u32>,
+    scan_duration: Cell<u8>,
+    scan_return_channel: Cell<u8>,
+    /// The peak energy level measured on the channel being scanned
+    scan_energy: Cell<u8>,
+    /// The channel of the energy measurement in progress, if any
+    ed_channel: Cell<Option<u8>>,
+
+    /// Whether this device coordinates a PAN, and accepts new devices in it
+    coordinator: Cell<bool>,
+    association_permit: Cell<bool>,
 }
 
-impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
-    pub fn new(mac: &'a M, aes_ccm: &'a A) -> Framer<'a, M, A> {
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> Framer<'a, M, A, T> {
+    pub fn new(
+        mac: &'a M,
+        aes_ccm: &'a A,
+        alarm: &'a T,
+        mlme_buf: &'static mut [u8],
+    ) -> Framer<'a, M, A, T> {
         Framer {
             mac: mac,
             aes_ccm: aes_ccm,
+            alarm: alarm,
             data_sequence: Cell::new(0),
+            beacon_sequence: Cell::new(0),
             key_procedure: OptionalCell::empty(),
             device_procedure: OptionalCell::empty(),
+            frame_counter_procedure: OptionalCell::empty(),
             tx_state: MapCell::new(TxState::Idle),
             tx_client: OptionalCell::empty(),
+            client_tx: Cell::new(false),
+            tx_held: MapCell::empty(),
             rx_state: MapCell::new(RxState::Idle),
             rx_client: OptionalCell::empty(),
+            mlme_state: Cell::new(MlmeState::Idle),
+            mlme_client: OptionalCell::empty(),
+            mlme_buf: TakeCell::new(mlme_buf),
+            mlme_tx: Cell::new(None),
+            mlme_pending: Cell::new(None),
+            scan_channels: Cell::new(0),
+            scan_duration: Cell::new(0),
+            scan_return_channel: Cell::new(0),
+            scan_energy: Cell::new(0),
+            ed_channel: Cell::new(None),
+            coordinator: Cell::new(false),
+            association_permit: Cell::new(false),
         }
     }
 
@@ -339,6 +489,13 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
         self.device_procedure.set(device_procedure);
     }
 
+    /// Sets the IEEE 802.15.4 frame counter procedures to be used. Without
+    /// them, outgoing frames use a frame counter of zero and incoming frame
+    /// counters are not checked.
+    pub fn set_frame_counter_procedure(&self, procedure: &'a dyn FrameCounterProcedure) {
+        self.frame_counter_procedure.set(procedure);
+    }
+
     /// Look up the key using the IEEE 802.15.4 KeyDescriptor lookup prodecure
     /// implemented elsewhere.
     fn lookup_key(&self, level: SecurityLevel, key_id: KeyId) -> Option<[u8; 16]> {
@@ -399,10 +556,13 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                 // will not include the payload IEs.
                 let mic_len = header.security.map_or(0, |sec| sec.level.mic_len());
                 let data_len = frame_len - data_offset - mic_len;
+                let mlme_frame = header.frame_type == FrameType::Beacon
+                    || header.frame_type == FrameType::MACCommand;
                 if let Some(security) = header.security {
                     // IEEE 802.15.4-2015: 9.2.3, incoming frame security procedure
-                    // for security-enabled headers
-                    if header.version == FrameVersion::V2003 {
+                    // for security-enabled headers. Secured beacons and MAC
+                    // commands are not supported.
+                    if header.version == FrameVersion::V2003 || mlme_frame {
                         None
                     } else {
                         // Step e: Lookup the key.
@@ -432,7 +592,14 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                                     // Counter error
                                     return None;
                                 }
-                                // TODO: Check frame counter against source device
+                                let fresh =
+                                    self.frame_counter_procedure.map_or(true, |procedure| {
+                                        procedure.check_frame_counter(device_addr, frame_counter)
+                                    });
+                                if !fresh {
+                                    // Replayed frame
+                                    return None;
+                                }
                                 frame_counter
                             }
                             // TSCH mode, where ASN is used instead, not supported
@@ -453,6 +620,11 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                             security_params: Some((security.level, key, nonce)),
                         })
                     }
+                } else if mlme_frame {
+                    // Beacons and MAC commands are handled here
+                    let payload_offset = radio::PSDU_OFFSET + data_offset;
+                    self.mlme_receive(header, &buf[payload_offset..payload_offset + data_len]);
+                    None
                 } else {
                     // No security needed, can yield the frame immediately
                     self.rx_client.map(|client| {
@@ -615,6 +787,25 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                     if let Some((data_offset, (header, _))) =
                         Header::decode(&buf[radio::PSDU_OFFSET..], true).done()
                     {
+                        // IEEE 802.15.4-2015: 9.2.3, incoming frame security
+                        // procedure, step k: the frame is authentic, so its
+                        // frame counter can be recorded. Frames that are only
+                        // encrypted are not authenticated, and their frame
+                        // counter could be forged to block later frames.
+                        if let Some(security) = header
+                            .security
+                            .filter(|security| security.level.mic_len() > 0)
+                        {
+                            let device_addr = self.lookup_addr_long(header.src_addr);
+                            if let (Some(device_addr), Some(frame_counter)) =
+                                (device_addr, security.frame_counter)
+                            {
+                                self.frame_counter_procedure.map(|procedure| {
+                                    procedure.update_frame_counter(device_addr, frame_counter)
+                                });
+                            }
+                        }
+
                         // IEEE 802.15.4-2015 specifies that unsecured
                         // frames do not have auxiliary security headers,
                         // but we do not remove the auxiliary security
@@ -643,9 +834,362 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
             }
         });
     }
+
+    /// Hands a frame to the transmission pipeline if it is idle.
+    fn start_transmit(
+        &self,
+        buf: &'static mut [u8],
+        info: FrameInfo,
+    ) -> (ReturnCode, Option<&'static mut [u8]>) {
+        let state = match self.tx_state.take() {
+            None => {
+                return (ReturnCode::FAIL, Some(buf));
+            }
+            Some(state) => state,
+        };
+        match state {
+            TxState::Idle => {
+                let next_state = self.outgoing_frame_security(buf, info);
+                self.tx_state.replace(next_state);
+                self.step_transmit_state()
+            }
+            other_state => {
+                self.tx_state.replace(other_state);
+                (ReturnCode::EBUSY, Some(buf))
+            }
+        }
+    }
+
+    /// Whether client frames must be held back: the MLME procedures are using
+    /// the radio, or have frames to send.
+    fn mlme_busy(&self) -> bool {
+        self.mlme_state.get() != MlmeState::Idle
+            || self.mlme_tx.get().is_some()
+            || self.mlme_pending.get().is_some()
+    }
+
+    /// Called when the frame in the transmission pipeline is done with, either
+    /// because it was sent or because the pipeline failed. Returns the buffer
+    /// to its owner and starts the next frame.
+    fn tx_done(&self, buf: &'static mut [u8], acked: bool, result: ReturnCode) {
+        match self.mlme_tx.take() {
+            Some(frame) => {
+                self.mlme_buf.replace(buf);
+                self.mlme_send_done(frame, acked, result);
+            }
+            None => {
+                self.client_tx.set(false);
+                self.tx_client.map(move |client| {
+                    client.send_done(buf, acked, result);
+                });
+            }
+        }
+        self.tx_next();
+    }
+
+    /// Starts the next frame waiting for the transmission pipeline, if it is
+    /// free. MLME frames take priority over held client frames, which are
+    /// only released once no scan or association is in progress.
+    fn tx_next(&self) {
+        if self.mlme_tx.get().is_some() || self.client_tx.get() {
+            return;
+        }
+        if let Some(frame) = self.mlme_pending.take() {
+            self.send_mlme_frame(frame);
+        } else if self.mlme_state.get() == MlmeState::Idle {
+            self.tx_held.take().map(|frame| {
+                let Frame { buf, info } = frame;
+                let (rval, buf) = self.start_transmit(buf, info);
+                match buf {
+                    Some(buf) => {
+                        self.tx_client.map(move |client| {
+                            client.send_done(buf, false, rval);
+                        });
+                    }
+                    None => self.client_tx.set(rval == ReturnCode::SUCCESS),
+                }
+            });
+        }
+    }
+
+    /// Sends a frame on behalf of the MLME procedures, or keeps it until the
+    /// transmission pipeline is free. Only one such frame is kept, so a newer
+    /// frame replaces one that is still waiting.
+    fn send_mlme_frame(&self, frame: MlmeFrame) {
+        if self.mlme_tx.get().is_some() || self.client_tx.get() {
+            self.mlme_pending.set(Some(frame));
+            return;
+        }
+
+        let result = self.mlme_buf.take().map_or(ReturnCode::ENOMEM, |buf| {
+            match self.prepare_mlme_frame(buf, frame) {
+                Err(buf) => {
+                    self.mlme_buf.replace(buf);
+                    ReturnCode::FAIL
+                }
+                Ok(Frame { buf, info }) => {
+                    let (rval, buf) = self.start_transmit(buf, info);
+                    match buf {
+                        Some(buf) => {
+                            self.mlme_buf.replace(buf);
+                        }
+                        None => {
+                            if rval == ReturnCode::SUCCESS {
+                                self.mlme_tx.set(Some(frame));
+                            }
+                        }
+                    }
+                    rval
+                }
+            }
+        });
+        if result != ReturnCode::SUCCESS {
+            self.mlme_send_done(frame, false, result);
+        }
+    }
+
+    /// Encodes the header and MAC payload of an MLME frame. These frames are
+    /// never secured.
+    fn prepare_mlme_frame(
+        &self,
+        buf: &'static mut [u8],
+        frame: MlmeFrame,
+    ) -> Result<Frame, &'static mut [u8]> {
+        let pan = self.mac.get_pan();
+        let addr_long = MacAddress::Long(self.mac.get_address_long());
+        let (frame_type, seq, ack_requested, dst_pan, dst_addr, src_pan, src_addr) = match frame {
+            MlmeFrame::BeaconRequest => (
+                FrameType::MACCommand,
+                self.data_sequence.get(),
+                false,
+                Some(BROADCAST_PAN),
+                Some(MacAddress::Short(BROADCAST_ADDR)),
+                None,
+                None,
+            ),
+            MlmeFrame::Beacon => {
+                // Coordinators without a short address identify themselves
+                // by their extended address
+                let addr = self.mac.get_address();
+                let src_addr = if addr == NO_SHORT_ADDR || addr == BROADCAST_ADDR {
+                    addr_long
+                } else {
+                    MacAddress::Short(addr)
+                };
+                let seq = self.beacon_sequence.get();
+                self.beacon_sequence.set(seq.wrapping_add(1));
+                (
+                    FrameType::Beacon,
+                    seq,
+                    false,
+                    None,
+                    None,
+                    Some(pan),
+                    Some(src_addr),
+                )
+            }
+            MlmeFrame::AssociationRequest(coordinator, _) => (
+                FrameType::MACCommand,
+                self.data_sequence.get(),
+                true,
+                Some(coordinator.pan),
+                Some(coordinator.coord_addr),
+                Some(BROADCAST_PAN),
+                Some(addr_long),
+            ),
+            MlmeFrame::AssociationResponse(device_addr, _, _) => (
+                FrameType::MACCommand,
+                self.data_sequence.get(),
+                true,
+                Some(pan),
+                Some(MacAddress::Long(device_addr)),
+                Some(pan),
+                Some(addr_long),
+            ),
+        };
+        let header = Header {
+            frame_type: frame_type,
+            frame_pending: false,
+            ack_requested: ack_requested,
+            version: FrameVersion::V2006,
+            seq: Some(seq),
+            dst_pan: dst_pan,
+            dst_addr: dst_addr,
+            src_pan: src_pan,
+            src_addr: src_addr,
+            security: None,
+            header_ies: Default::default(),
+            header_ies_len: 0,
+            payload_ies: Default::default(),
+            payload_ies_len: 0,
+        };
+
+        let (data_offset, mac_payload_offset) =
+            match header.encode(&mut buf[radio::PSDU_OFFSET..], true).done() {
+                Some(offsets) => offsets,
+                None => {
+                    return Err(buf);
+                }
+            };
+        let payload = &mut buf[radio::PSDU_OFFSET + data_offset..];
+        let encoded = match frame {
+            MlmeFrame::BeaconRequest => MacCommand::BeaconRequest.encode(payload),
+            MlmeFrame::Beacon => Beacon {
+                pan_coordinator: true,
+                association_permit: self.association_permit.get(),
+            }
+            .encode(payload),
+            MlmeFrame::AssociationRequest(_, capability) => {
+                MacCommand::AssociationRequest(capability).encode(payload)
+            }
+            MlmeFrame::AssociationResponse(_, short_addr, status) => {
+                MacCommand::AssociationResponse(short_addr, status).encode(payload)
+            }
+        };
+        match encoded.done() {
+            Some((data_len, _)) => Ok(Frame {
+                buf: buf,
+                info: FrameInfo {
+                    frame_type: frame_type,
+                    mac_payload_offset: mac_payload_offset,
+                    data_offset: data_offset,
+                    data_len: data_len,
+                    mic_len: 0,
+                    security_params: None,
+                },
+            }),
+            None => Err(buf),
+        }
+    }
+
+    /// Called once an MLME frame has been sent, or could not be.
+    fn mlme_send_done(&self, frame: MlmeFrame, acked: bool, result: ReturnCode) {
+        if let MlmeFrame::AssociationRequest(coordinator, _) = frame {
+            if self.mlme_state.get() == MlmeState::Associating(coordinator) {
+                if result != ReturnCode::SUCCESS {
+                    self.associate_done(result, None);
+                } else if !acked {
+                    self.associate_done(ReturnCode::ENOACK, None);
+                } else {
+                    self.start_timer(RESPONSE_WAIT_US);
+                }
+            }
+        }
+    }
+
+    /// Handles a received beacon or MAC command frame.
+    fn mlme_receive(&self, header: Header, payload: &[u8]) {
+        match header.frame_type {
+            FrameType::Beacon => {
+                if let MlmeState::Scanning(ScanType::Active, channel) = self.mlme_state.get() {
+                    let beacon = Beacon::decode(payload).done().map(|(_, beacon)| beacon);
+                    if let (Some(beacon), Some(pan), Some(coord_addr)) =
+                        (beacon, header.src_pan, header.src_addr)
+                    {
+                        self.mlme_client.map(|client| {
+                            client.beacon_received(PanDescriptor {
+                                channel: channel,
+                                pan: pan,
+                                coord_addr: coord_addr,
+                                pan_coordinator: beacon.pan_coordinator,
+                                association_permit: beacon.association_permit,
+                            })
+                        });
+                    }
+                }
+            }
+            FrameType::MACCommand => match MacCommand::decode(payload).done() {
+                Some((_, MacCommand::BeaconRequest)) => {
+                    if self.coordinator.get() {
+                        self.send_mlme_frame(MlmeFrame::Beacon);
+                    }
+                }
+                Some((_, MacCommand::AssociationRequest(capability))) => {
+                    // Association requests always carry the extended address
+                    // of the device. The response is sent directly rather than
+                    // kept for the device to poll for.
+                    if let Some(MacAddress::Long(device_addr)) = header.src_addr {
+                        if self.coordinator.get() && self.association_permit.get() {
+                            let response = self
+                                .mlme_client
+                                .and_then(|client| {
+                                    client.associate_indication(device_addr, capability)
+                                })
+                                .map_or(
+                                    (BROADCAST_ADDR, AssociationStatus::PanAtCapacity),
+                                    |short_addr| (short_addr, AssociationStatus::Successful),
+                                );
+                            self.send_mlme_frame(MlmeFrame::AssociationResponse(
+                                device_addr,
+                                response.0,
+                                response.1,
+                            ));
+                        }
+                    }
+                }
+                Some((_, MacCommand::AssociationResponse(short_addr, status))) => {
+                    if let MlmeState::Associating(_) = self.mlme_state.get() {
+                        if status == AssociationStatus::Successful && short_addr != NO_SHORT_ADDR {
+                            self.mac.set_address(short_addr);
+                            self.mac.config_commit();
+                        }
+                        self.associate_done(ReturnCode::SUCCESS, Some((status, short_addr)));
+                    }
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        self.tx_next();
+    }
+
+    /// Tunes the radio to the next channel to scan, or back to the original
+    /// channel once every channel has been scanned.
+    fn scan_next_channel(&self) {
+        let channels = self.scan_channels.get();
+        if channels == 0 {
+            self.mlme_state.set(MlmeState::ScanRestoring);
+            self.mac.set_channel(self.scan_return_channel.get());
+        } else {
+            let channel = channels.trailing_zeros() as u8;
+            self.scan_channels.set(channels & !(1 << channel));
+            let scan_type = match self.mlme_state.get() {
+                MlmeState::Scanning(scan_type, _) | MlmeState::ScanSwitching(scan_type, _) => {
+                    scan_type
+                }
+                _ => ScanType::Active,
+            };
+            self.mlme_state
+                .set(MlmeState::ScanSwitching(scan_type, channel));
+            self.mac.set_channel(channel);
+        }
+        self.mac.config_commit();
+    }
+
+    /// Starts measuring the energy on the channel being scanned, unless a
+    /// measurement is already in progress.
+    fn start_energy_detect(&self, channel: u8) {
+        if self.ed_channel.get().is_none() && self.mac.energy_detect() == ReturnCode::SUCCESS {
+            self.ed_channel.set(Some(channel));
+        }
+    }
+
+    fn associate_done(&self, result: ReturnCode, response: Option<(AssociationStatus, u16)>) {
+        self.alarm.disable();
+        self.mlme_state.set(MlmeState::Idle);
+        self.mlme_client.map(|client| {
+            client.associate_done(result, response);
+        });
+    }
+
+    fn start_timer(&self, us: u64) {
+        let tics = us * <T::Frequency>::frequency() as u64 / 1_000_000;
+        self.alarm
+            .set_alarm(self.alarm.now().wrapping_add(tics as u32));
+    }
 }
 
-impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> MacDevice<'a> for Framer<'a, M, A, T> {
     fn set_transmit_client(&self, client: &'a dyn TxClient) {
         self.tx_client.set(client);
     }
@@ -654,6 +1198,10 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
         self.rx_client.set(client);
     }
 
+    fn set_mlme_client(&self, client: &'a dyn MlmeClient) {
+        self.mlme_client.set(client);
+    }
+
     fn get_address(&self) -> u16 {
         self.mac.get_address()
     }
@@ -666,6 +1214,10 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
         self.mac.get_pan()
     }
 
+    fn get_channel(&self) -> u8 {
+        self.mac.get_channel()
+    }
+
     fn set_address(&self, addr: u16) {
         self.mac.set_address(addr)
     }
@@ -678,6 +1230,10 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
         self.mac.set_pan(id)
     }
 
+    fn set_channel(&self, chan: u8) -> ReturnCode {
+        self.mac.set_channel(chan)
+    }
+
     fn config_commit(&self) {
         self.mac.config_commit()
     }
@@ -703,24 +1259,25 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
         // specification.
         let src_addr_long = self.get_address_long();
         let security_desc = security_needed.and_then(|(level, key_id)| {
-            self.lookup_key(level, key_id).map(|key| {
-                // TODO: lookup frame counter for device
-                let frame_counter = 0;
-                let nonce = get_ccm_nonce(&src_addr_long, frame_counter, level);
-                (
-                    Security {
-                        level: level,
-                        asn_in_nonce: false,
-                        frame_counter: Some(frame_counter),
-                        key_id: key_id,
-                    },
-                    key,
-                    nonce,
-                )
-            })
+            let key = self.lookup_key(level, key_id)?;
+            let frame_counter = self
+                .frame_counter_procedure
+                .map_or(Some(0), |procedure| procedure.next_frame_counter())?;
+            let nonce = get_ccm_nonce(&src_addr_long, frame_counter, level);
+            Some((
+                Security {
+                    level: level,
+                    asn_in_nonce: false,
+                    frame_counter: Some(frame_counter),
+                    key_id: key_id,
+                },
+                key,
+                nonce,
+            ))
         });
         if security_needed.is_some() && security_desc.is_none() {
-            // If security was requested, fail when desired key was not found.
+            // If security was requested, fail when desired key was not found
+            // or no frame counter is available.
             return Err(buf);
         }
 
@@ -764,37 +1321,83 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
     }
 
     fn transmit(&self, frame: Frame) -> (ReturnCode, Option<&'static mut [u8]>) {
+        if self.client_tx.get() || self.tx_held.is_some() {
+            return (ReturnCode::EBUSY, Some(frame.into_buf()));
+        }
+        if self.mlme_busy() {
+            self.tx_held.replace(frame);
+            return (ReturnCode::SUCCESS, None);
+        }
+
         let Frame { buf, info } = frame;
-        let state = match self.tx_state.take() {
-            None => {
-                return (ReturnCode::FAIL, Some(buf));
-            }
-            Some(state) => state,
-        };
-        match state {
-            TxState::Idle => {
-                let next_state = self.outgoing_frame_security(buf, info);
-                self.tx_state.replace(next_state);
-                self.step_transmit_state()
-            }
-            other_state => {
-                self.tx_state.replace(other_state);
-                (ReturnCode::EBUSY, Some(buf))
-            }
+        let (rval, buf) = self.start_transmit(buf, info);
+        self.client_tx
+            .set(rval == ReturnCode::SUCCESS && buf.is_none());
+        (rval, buf)
+    }
+
+    fn scan(&self, scan_type: ScanType, channels: u32, duration: u8) -> ReturnCode {
+        if self.mlme_state.get() != MlmeState::Idle {
+            return ReturnCode::EBUSY;
         }
+        if channels & ALL_CHANNELS == 0 || duration > MAX_SCAN_DURATION {
+            return ReturnCode::EINVAL;
+        }
+
+        self.scan_channels.set(channels & ALL_CHANNELS);
+        self.scan_duration.set(duration);
+        self.scan_return_channel.set(self.mac.get_channel());
+        self.mlme_state.set(MlmeState::ScanSwitching(scan_type, 0));
+        self.scan_next_channel();
+        ReturnCode::SUCCESS
+    }
+
+    fn associate(&self, coordinator: PanDescriptor, capability: u8) -> ReturnCode {
+        if self.mlme_state.get() != MlmeState::Idle {
+            return ReturnCode::EBUSY;
+        }
+        let rval = self.mac.set_channel(coordinator.channel);
+        if rval != ReturnCode::SUCCESS {
+            return rval;
+        }
+
+        self.coordinator.set(false);
+        self.mac.set_pan(coordinator.pan);
+        self.mlme_state
+            .set(MlmeState::AssociateSwitching(coordinator, capability));
+        self.mac.config_commit();
+        ReturnCode::SUCCESS
+    }
+
+    fn start_pan(&self, pan: PanID, channel: u8) -> ReturnCode {
+        if self.mlme_state.get() != MlmeState::Idle {
+            return ReturnCode::EBUSY;
+        }
+        let rval = self.mac.set_channel(channel);
+        if rval != ReturnCode::SUCCESS {
+            return rval;
+        }
+
+        self.mac.set_pan(pan);
+        self.mac.config_commit();
+        self.coordinator.set(true);
+        ReturnCode::SUCCESS
+    }
+
+    fn set_association_permit(&self, permit: bool) {
+        self.association_permit.set(permit);
     }
 }
 
-impl<M: Mac, A: AES128CCM<'a>> radio::TxClient for Framer<'a, M, A> {
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> radio::TxClient for Framer<'a, M, A, T> {
     fn send_done(&self, buf: &'static mut [u8], acked: bool, result: ReturnCode) {
-        self.data_sequence.set(self.data_sequence.get() + 1);
-        self.tx_client.map(move |client| {
-            client.send_done(buf, acked, result);
-        });
+        self.data_sequence
+            .set(self.data_sequence.get().wrapping_add(1));
+        self.tx_done(buf, acked, result);
     }
 }
 
-impl<M: Mac, A: AES128CCM<'a>> radio::RxClient for Framer<'a, M, A> {
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> radio::RxClient for Framer<'a, M, A, T> {
     fn receive(&self, buf: &'static mut [u8], frame_len: usize, crc_valid: bool, _: ReturnCode) {
         // Drop all frames with invalid CRC
         if !crc_valid {
@@ -824,22 +1427,86 @@ impl<M: Mac, A: AES128CCM<'a>> radio::RxClient for Framer<'a, M, A> {
     }
 }
 
-impl<M: Mac, A: AES128CCM<'a>> radio::ConfigClient for Framer<'a, M, A> {
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> radio::ConfigClient for Framer<'a, M, A, T> {
     fn config_done(&self, _: ReturnCode) {
-        // The transmission pipeline is the only state machine that
-        // waits for the configuration procedure to complete before
-        // advancing.
+        // The transmission pipeline waits for the configuration procedure to
+        // complete before advancing, and so do the scan and association
+        // procedures when switching channels.
         let (rval, buf) = self.step_transmit_state();
         if let Some(buf) = buf {
-            // Return the buffer to the transmit client
-            self.tx_client.map(move |client| {
-                client.send_done(buf, false, rval);
-            });
+            // Return the buffer to its owner
+            self.tx_done(buf, false, rval);
+        }
+
+        match self.mlme_state.get() {
+            MlmeState::ScanSwitching(scan_type, channel) => {
+                self.mlme_state.set(MlmeState::Scanning(scan_type, channel));
+                self.scan_energy.set(0);
+                self.start_timer(
+                    BASE_SUPERFRAME_DURATION_US * ((1 << self.scan_duration.get()) + 1),
+                );
+                match scan_type {
+                    ScanType::Active => self.send_mlme_frame(MlmeFrame::BeaconRequest),
+                    ScanType::EnergyDetect => self.start_energy_detect(channel),
+                }
+            }
+            MlmeState::ScanRestoring => {
+                self.mlme_state.set(MlmeState::Idle);
+                self.mlme_client.map(|client| {
+                    client.scan_done(ReturnCode::SUCCESS);
+                });
+            }
+            MlmeState::AssociateSwitching(coordinator, capability) => {
+                self.mlme_state.set(MlmeState::Associating(coordinator));
+                self.send_mlme_frame(MlmeFrame::AssociationRequest(coordinator, capability));
+            }
+            _ => {}
+        }
+        self.tx_next();
+    }
+}
+
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> radio::EnergyDetectClient
+    for Framer<'a, M, A, T>
+{
+    fn energy_detect_done(&self, power: i8, result: ReturnCode) {
+        let measured_channel = self.ed_channel.take();
+        if let MlmeState::Scanning(ScanType::EnergyDetect, channel) = self.mlme_state.get() {
+            if result == ReturnCode::SUCCESS && measured_channel == Some(channel) {
+                let energy = energy_level(power);
+                if energy > self.scan_energy.get() {
+                    self.scan_energy.set(energy);
+                }
+            }
+            // Keep measuring until the scan moves on to the next channel
+            self.start_energy_detect(channel);
+        }
+    }
+}
+
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> time::AlarmClient for Framer<'a, M, A, T> {
+    fn fired(&self) {
+        match self.mlme_state.get() {
+            MlmeState::Scanning(scan_type, channel) => {
+                if scan_type == ScanType::EnergyDetect {
+                    let energy = self.scan_energy.get();
+                    self.mlme_client.map(|client| {
+                        client.energy_detected(channel, energy);
+                    });
+                }
+                self.scan_next_channel();
+            }
+            MlmeState::Associating(_) => {
+                // No association response arrived in time
+                self.associate_done(ReturnCode::FAIL, None);
+            }
+            _ => {}
         }
+        self.tx_next();
     }
 }
 
-impl<M: Mac, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
+impl<M: Mac, A: AES128CCM<'a>, T: time::Alarm<'a>> CCMClient for Framer<'a, M, A, T> {
     fn crypt_done(&self, buf: &'static mut [u8], res: ReturnCode, tag_is_valid: bool) {
         let mut tx_waiting = false;
         let mut rx_waiting = false;
@@ -858,9 +1525,7 @@ impl<M: Mac, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
 
                     if let Some(buf) = opt_buf {
                         // Abort the transmission process. Return the buffer to the client.
-                        self.tx_client.map(move |client| {
-                            client.send_done(buf, false, rval);
-                        });
+                        self.tx_done(buf, false, rval);
                     }
                     None
                 }
@@ -906,9 +1571,7 @@ impl<M: Mac, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
             let (rval, opt_buf) = self.step_transmit_state();
             if let Some(buf) = opt_buf {
                 // Return the buffer to the client.
-                self.tx_client.map(move |client| {
-                    client.send_done(buf, false, rval);
-                });
+                self.tx_done(buf, false, rval);
             }
         } else if rx_waiting {
             self.step_receive_state();
@@ -9,7 +9,7 @@
 //! the underlying kernel::hil::radio::Radio powered at all times and passing
 //! through each frame for transmission.
 
-use crate::net::ieee802154::{Header, MacAddress};
+use crate::net::ieee802154::{Header, MacAddress, BROADCAST_ADDR};
 use kernel::common::cells::OptionalCell;
 use kernel::debug;
 use kernel::hil::radio;
@@ -28,6 +28,8 @@ pub trait Mac {
     fn set_receive_client(&self, client: &'static dyn radio::RxClient);
     /// Sets the buffer for packet reception
     fn set_receive_buffer(&self, buffer: &'static mut [u8]);
+    /// Sets the notified client for energy measurements
+    fn set_energy_detect_client(&self, client: &'static dyn radio::EnergyDetectClient);
 
     /// The short 16-bit address of the radio
     fn get_address(&self) -> u16;
@@ -35,6 +37,8 @@ pub trait Mac {
     fn get_address_long(&self) -> [u8; 8];
     /// The 16-bit PAN id of the radio
     fn get_pan(&self) -> u16;
+    /// The 802.15.4 channel of the radio
+    fn get_channel(&self) -> u8;
 
     /// Sets the short 16-bit address of the radio
     fn set_address(&self, addr: u16);
@@ -42,6 +46,8 @@ pub trait Mac {
     fn set_address_long(&self, addr: [u8; 8]);
     /// Sets the 16-bit PAN id of the radio
     fn set_pan(&self, id: u16);
+    /// Sets the 802.15.4 channel of the radio, from 11 to 26
+    fn set_channel(&self, chan: u8) -> ReturnCode;
 
     /// Must be called after one or more calls to `set_*`. If
     /// `set_*` is called without calling `config_commit`, there is no guarantee
@@ -53,6 +59,10 @@ pub trait Mac {
     /// Indicates whether or not the MAC protocol is active and can send frames
     fn is_on(&self) -> bool;
 
+    /// Measures the energy on the current channel. The energy detect client
+    /// is notified of the received signal power.
+    fn energy_detect(&self) -> ReturnCode;
+
     /// Transmits complete MAC frames, which must be prepared by an ieee802154::device::MacDevice
     /// before being passed to the Mac layer. Returns the frame buffer in case of an error.
     fn transmit(
@@ -110,6 +120,10 @@ impl<R: radio::Radio> Mac for AwakeMac<'a, R> {
         self.radio.set_pan(id)
     }
 
+    fn set_channel(&self, chan: u8) -> ReturnCode {
+        self.radio.set_channel(chan)
+    }
+
     fn get_address(&self) -> u16 {
         self.radio.get_address()
     }
@@ -122,6 +136,10 @@ impl<R: radio::Radio> Mac for AwakeMac<'a, R> {
         self.radio.get_pan()
     }
 
+    fn get_channel(&self) -> u8 {
+        self.radio.get_channel()
+    }
+
     fn config_commit(&self) {
         self.radio.config_commit()
     }
@@ -138,6 +156,14 @@ impl<R: radio::Radio> Mac for AwakeMac<'a, R> {
         self.radio.set_receive_buffer(buffer);
     }
 
+    fn set_energy_detect_client(&self, client: &'static dyn radio::EnergyDetectClient) {
+        self.radio.set_energy_detect_client(client);
+    }
+
+    fn energy_detect(&self) -> ReturnCode {
+        self.radio.energy_detect()
+    }
+
     fn transmit(
         &self,
         full_mac_frame: &'static mut [u8],
@@ -163,15 +189,18 @@ impl<R: radio::Radio> radio::RxClient for AwakeMac<'a, R> {
         crc_valid: bool,
         result: ReturnCode,
     ) {
-        // Filter packets by destination because radio is in promiscuous mode
+        // Filter packets by destination because radio is in promiscuous mode.
+        // Frames without a destination address, such as beacons, are for
+        // every device.
         let mut addr_match = false;
         if let Some((_, (header, _))) = Header::decode(&buf[radio::PSDU_OFFSET..], false).done() {
-            if let Some(dst_addr) = header.dst_addr {
-                addr_match = match dst_addr {
-                    MacAddress::Short(addr) => addr == self.radio.get_address(),
-                    MacAddress::Long(long_addr) => long_addr == self.radio.get_address_long(),
-                };
-            }
+            addr_match = match header.dst_addr {
+                Some(MacAddress::Short(addr)) => {
+                    addr == self.radio.get_address() || addr == BROADCAST_ADDR
+                }
+                Some(MacAddress::Long(long_addr)) => long_addr == self.radio.get_address_long(),
+                None => true,
+            };
         }
 
         if addr_match {
@@ -1,8 +1,10 @@
 //! Support for IEEE 802.15.4.
 
 pub mod device;
+pub mod frame_counter;
 pub mod framer;
 pub mod mac;
+pub mod pan_joiner;
 pub mod virtual_mac;
 pub mod xmac;
 
@@ -0,0 +1,174 @@
+//! Finds or forms an IEEE 802.15.4 PAN, so that boards can join a PAN other
+//! than the one they are configured with.
+//!
+//! When started, `PanJoiner` actively scans every channel for coordinators that
+//! accept new devices and associates with the best one, preferring PAN
+//! coordinators. If no coordinator answers, or association keeps failing, it
+//! starts the PAN the MAC was configured with before the scan, on the channel
+//! it was configured with, and accepts any device that asks to join. Boards
+//! that start together and do not find each other thus still end up in the
+//! same PAN, rather than each coordinating its own.
+//!
+//! Devices keep their own short address: association requests do not ask for
+//! an address to be allocated, so that addresses derived from it, such as
+//! IPv6 interface identifiers, remain valid. When coordinating, addresses are
+//! still allocated to the devices that ask for one.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let joiner_mac = static_init!(
+//!     capsules::ieee802154::virtual_mac::MacUser<'static>,
+//!     capsules::ieee802154::virtual_mac::MacUser::new(mux_mac));
+//! mux_mac.add_user(joiner_mac);
+//! let pan_joiner = static_init!(
+//!     capsules::ieee802154::pan_joiner::PanJoiner<'static>,
+//!     capsules::ieee802154::pan_joiner::PanJoiner::new(joiner_mac));
+//! joiner_mac.set_mlme_client(pan_joiner);
+//! // After the PAN ID and channel of the MAC are configured
+//! pan_joiner.start();
+//! ```
+
+use crate::ieee802154::device::{MacDevice, MlmeClient, PanDescriptor, ScanType, ALL_CHANNELS};
+use crate::net::ieee802154::{capability, AssociationStatus, PanID, BROADCAST_ADDR, NO_SHORT_ADDR};
+use core::cell::Cell;
+use kernel::ReturnCode;
+
+/// The scan duration exponent: each channel is scanned for about 138 ms.
+const SCAN_DURATION: u8 = 3;
+/// How many times to scan for a PAN to join before forming one.
+const MAX_JOIN_ATTEMPTS: u8 = 3;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum JoinerState {
+    Idle,
+    /// Looking for coordinators that accept new devices
+    Scanning,
+    /// Associating with the best coordinator found
+    Associating,
+    /// Part of a PAN coordinated by another device
+    Associated,
+    /// Coordinating a PAN of its own
+    Coordinating,
+}
+
+pub struct PanJoiner<'a> {
+    mac: &'a dyn MacDevice<'a>,
+    state: Cell<JoinerState>,
+    attempts: Cell<u8>,
+    /// The best coordinator found by the current active scan
+    best_pan: Cell<Option<PanDescriptor>>,
+    /// The PAN ID and channel the MAC was configured with, which are used to
+    /// start a PAN if none is found
+    default_pan: Cell<(PanID, u8)>,
+}
+
+impl PanJoiner<'a> {
+    pub fn new(mac: &'a dyn MacDevice<'a>) -> PanJoiner<'a> {
+        PanJoiner {
+            mac: mac,
+            state: Cell::new(JoinerState::Idle),
+            attempts: Cell::new(0),
+            best_pan: Cell::new(None),
+            default_pan: Cell::new((0, 0)),
+        }
+    }
+
+    /// Starts looking for a PAN to join.
+    pub fn start(&self) {
+        self.default_pan
+            .set((self.mac.get_pan(), self.mac.get_channel()));
+        self.attempts.set(0);
+        self.scan_for_pans();
+    }
+
+    pub fn get_state(&self) -> JoinerState {
+        self.state.get()
+    }
+
+    fn scan_for_pans(&self) {
+        if self.attempts.get() >= MAX_JOIN_ATTEMPTS {
+            self.start_pan();
+            return;
+        }
+        self.attempts.set(self.attempts.get() + 1);
+        self.best_pan.set(None);
+        self.state.set(JoinerState::Scanning);
+        if self.mac.scan(ScanType::Active, ALL_CHANNELS, SCAN_DURATION) != ReturnCode::SUCCESS {
+            self.state.set(JoinerState::Idle);
+        }
+    }
+
+    /// Starts the PAN the MAC was configured with.
+    fn start_pan(&self) {
+        let (pan, channel) = self.default_pan.get();
+        if self.mac.start_pan(pan, channel) == ReturnCode::SUCCESS {
+            self.mac.set_association_permit(true);
+            self.state.set(JoinerState::Coordinating);
+        } else {
+            self.state.set(JoinerState::Idle);
+        }
+    }
+}
+
+impl MlmeClient for PanJoiner<'a> {
+    fn energy_detected(&self, _channel: u8, _energy: u8) {}
+
+    fn beacon_received(&self, pan: PanDescriptor) {
+        if self.state.get() != JoinerState::Scanning || !pan.association_permit {
+            return;
+        }
+        let better = self
+            .best_pan
+            .get()
+            .map_or(true, |best| pan.pan_coordinator && !best.pan_coordinator);
+        if better {
+            self.best_pan.set(Some(pan));
+        }
+    }
+
+    fn scan_done(&self, _result: ReturnCode) {
+        match self.state.get() {
+            JoinerState::Scanning => match self.best_pan.get() {
+                Some(pan) => {
+                    self.state.set(JoinerState::Associating);
+                    if self.mac.associate(pan, capability::RX_ON_WHEN_IDLE) != ReturnCode::SUCCESS {
+                        self.scan_for_pans();
+                    }
+                }
+                None => self.start_pan(),
+            },
+            _ => {}
+        }
+    }
+
+    fn associate_done(&self, result: ReturnCode, response: Option<(AssociationStatus, u16)>) {
+        if self.state.get() != JoinerState::Associating {
+            return;
+        }
+        match response {
+            Some((AssociationStatus::Successful, _)) if result == ReturnCode::SUCCESS => {
+                self.state.set(JoinerState::Associated);
+            }
+            _ => self.scan_for_pans(),
+        }
+    }
+
+    fn associate_indication(&self, device_addr: [u8; 8], capability: u8) -> Option<u16> {
+        if self.state.get() != JoinerState::Coordinating {
+            return None;
+        }
+        if capability & capability::ALLOCATE_ADDRESS == 0 {
+            return Some(NO_SHORT_ADDR);
+        }
+        // Allocate the low bits of the extended address, which are unlikely
+        // to collide within a small PAN
+        let short_addr = u16::from_be_bytes([device_addr[6], device_addr[7]]);
+        if short_addr == NO_SHORT_ADDR || short_addr == BROADCAST_ADDR {
+            Some(short_addr & 0x7fff)
+        } else {
+            Some(short_addr)
+        }
+    }
+}
@@ -6,7 +6,10 @@
 //! subsequently 6LoWPAN-encoded and fragmented IP packets. This capsule allows
 //! that to happen by providing a mechanism for sequencing transmission attempts,
 //! Every radio frame received is provided to all listening clients so that each
-//! client can perform its own frame filtering logic.
+//! client can perform its own frame filtering logic. Scan and association
+//! results are provided to the client that started the procedure, while
+//! association requests from other devices are offered to every client in
+//! turn.
 //!
 //! Usage
 //! -----
@@ -18,6 +21,7 @@
 //!     capsules::ieee802154::virtual_mac::MuxMac::new(&'static mac_device));
 //! mac_device.set_transmit_client(mux_mac);
 //! mac_device.set_receive_client(mux_mac);
+//! mac_device.set_mlme_client(mux_mac);
 //!
 //! // Everything that uses the virtualized MAC device must create one of these.
 //! let virtual_mac = static_init!(
@@ -27,7 +31,7 @@
 //! ```
 
 use crate::ieee802154::{device, framer};
-use crate::net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
+use crate::net::ieee802154::{AssociationStatus, Header, KeyId, MacAddress, PanID, SecurityLevel};
 use core::cell::Cell;
 use kernel::common::cells::{MapCell, OptionalCell};
 use kernel::common::{List, ListLink, ListNode};
@@ -59,6 +63,51 @@ impl device::RxClient for MuxMac<'a> {
     }
 }
 
+impl device::MlmeClient for MuxMac<'a> {
+    fn energy_detected(&self, channel: u8, energy: u8) {
+        self.mlme_user().map(|user| {
+            user.mlme_client
+                .get()
+                .map(|client| client.energy_detected(channel, energy));
+        });
+    }
+
+    fn beacon_received(&self, pan: device::PanDescriptor) {
+        self.mlme_user().map(|user| {
+            user.mlme_client
+                .get()
+                .map(|client| client.beacon_received(pan));
+        });
+    }
+
+    fn scan_done(&self, result: ReturnCode) {
+        self.mlme_user().map(|user| {
+            user.mlme_active.set(false);
+            user.mlme_client
+                .get()
+                .map(|client| client.scan_done(result));
+        });
+    }
+
+    fn associate_done(&self, result: ReturnCode, response: Option<(AssociationStatus, u16)>) {
+        self.mlme_user().map(|user| {
+            user.mlme_active.set(false);
+            user.mlme_client
+                .get()
+                .map(|client| client.associate_done(result, response));
+        });
+    }
+
+    fn associate_indication(&self, device_addr: [u8; 8], capability: u8) -> Option<u16> {
+        // The first user to accept the device allocates its address
+        self.users.iter().find_map(|user| {
+            user.mlme_client
+                .get()
+                .and_then(|client| client.associate_indication(device_addr, capability))
+        })
+    }
+}
+
 impl MuxMac<'a> {
     pub const fn new(mac: &'a dyn device::MacDevice<'a>) -> MuxMac<'a> {
         MuxMac {
@@ -68,6 +117,11 @@ impl MuxMac<'a> {
         }
     }
 
+    /// Finds the user whose scan or association is in progress.
+    fn mlme_user(&self) -> Option<&'a MacUser<'a>> {
+        self.users.iter().find(|user| user.mlme_active.get())
+    }
+
     /// Registers a MAC user with this MAC mux device. Each MAC user should only
     /// be registered once.
     pub fn add_user(&self, user: &'a MacUser<'a>) {
@@ -191,6 +245,9 @@ pub struct MacUser<'a> {
     next: ListLink<'a, MacUser<'a>>,
     tx_client: Cell<Option<&'a dyn device::TxClient>>,
     rx_client: Cell<Option<&'a dyn device::RxClient>>,
+    mlme_client: Cell<Option<&'a dyn device::MlmeClient>>,
+    /// Whether this user started the scan or association in progress
+    mlme_active: Cell<bool>,
 }
 
 impl MacUser<'a> {
@@ -201,6 +258,8 @@ impl MacUser<'a> {
             next: ListLink::empty(),
             tx_client: Cell::new(None),
             rx_client: Cell::new(None),
+            mlme_client: Cell::new(None),
+            mlme_active: Cell::new(false),
         }
     }
 }
@@ -234,6 +293,10 @@ impl device::MacDevice<'a> for MacUser<'a> {
         self.rx_client.set(Some(client));
     }
 
+    fn set_mlme_client(&self, client: &'a dyn device::MlmeClient) {
+        self.mlme_client.set(Some(client));
+    }
+
     fn get_address(&self) -> u16 {
         self.mux.mac.get_address()
     }
@@ -246,6 +309,10 @@ impl device::MacDevice<'a> for MacUser<'a> {
         self.mux.mac.get_pan()
     }
 
+    fn get_channel(&self) -> u8 {
+        self.mux.mac.get_channel()
+    }
+
     fn set_address(&self, addr: u16) {
         self.mux.mac.set_address(addr)
     }
@@ -258,6 +325,10 @@ impl device::MacDevice<'a> for MacUser<'a> {
         self.mux.mac.set_pan(id)
     }
 
+    fn set_channel(&self, chan: u8) -> ReturnCode {
+        self.mux.mac.set_channel(chan)
+    }
+
     fn config_commit(&self) {
         self.mux.mac.config_commit()
     }
@@ -300,4 +371,27 @@ impl device::MacDevice<'a> for MacUser<'a> {
                 }
             })
     }
+    fn scan(&self, scan_type: device::ScanType, channels: u32, duration: u8) -> ReturnCode {
+        let result = self.mux.mac.scan(scan_type, channels, duration);
+        if result == ReturnCode::SUCCESS {
+            self.mlme_active.set(true);
+        }
+        result
+    }
+
+    fn associate(&self, coordinator: device::PanDescriptor, capability: u8) -> ReturnCode {
+        let result = self.mux.mac.associate(coordinator, capability);
+        if result == ReturnCode::SUCCESS {
+            self.mlme_active.set(true);
+        }
+        result
+    }
+
+    fn start_pan(&self, pan: PanID, channel: u8) -> ReturnCode {
+        self.mux.mac.start_pan(pan, channel)
+    }
+
+    fn set_association_permit(&self, permit: bool) {
+        self.mux.mac.set_association_permit(permit)
+    }
 }
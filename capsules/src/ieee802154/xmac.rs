@@ -58,11 +58,13 @@
 //!
 //! // We can now use the XMac driver to instantiate a MacDevice like a Framer
 //! let mac_device = static_init!(
-//!     capsules::ieee802154::framer::Framer<'static, XMacDevice>,
-//!     capsules::ieee802154::framer::Framer::new(xmac));
+//!     capsules::ieee802154::framer::Framer<'static, XMacDevice, AESDevice, AlarmDevice>,
+//!     capsules::ieee802154::framer::Framer::new(xmac, aes_ccm, framer_alarm, &mut MLME_BUF));
 //! xmac.set_transmit_client(mac_device);
 //! xmac.set_receive_client(mac_device);
 //! xmac.set_config_client(mac_device);
+//! xmac.set_energy_detect_client(mac_device);
+//! framer_alarm.set_client(mac_device);
 //! ```
 
 //
@@ -371,6 +373,10 @@ impl<R: radio::Radio, A: Alarm<'a>> Mac for XMac<'a, R, A> {
         self.radio.set_pan(id)
     }
 
+    fn set_channel(&self, chan: u8) -> ReturnCode {
+        self.radio.set_channel(chan)
+    }
+
     fn get_address(&self) -> u16 {
         self.radio.get_address()
     }
@@ -383,6 +389,10 @@ impl<R: radio::Radio, A: Alarm<'a>> Mac for XMac<'a, R, A> {
         self.radio.get_pan()
     }
 
+    fn get_channel(&self) -> u8 {
+        self.radio.get_channel()
+    }
+
     fn config_commit(&self) {
         self.radio.config_commit()
     }
@@ -399,6 +409,14 @@ impl<R: radio::Radio, A: Alarm<'a>> Mac for XMac<'a, R, A> {
         self.radio.set_receive_buffer(buffer);
     }
 
+    fn set_energy_detect_client(&self, client: &'static dyn radio::EnergyDetectClient) {
+        self.radio.set_energy_detect_client(client);
+    }
+
+    fn energy_detect(&self) -> ReturnCode {
+        self.radio.energy_detect()
+    }
+
     fn transmit(
         &self,
         full_mac_frame: &'static mut [u8],
@@ -13,15 +13,18 @@ pub mod alarm;
 pub mod ambient_light;
 pub mod analog_comparator;
 pub mod analog_sensor;
+pub mod app_checker;
 pub mod app_flash_driver;
 pub mod ble_advertising_driver;
 pub mod button;
 pub mod buzzer_driver;
 pub mod console;
+pub mod crash_dump;
 pub mod crc;
 pub mod dac;
 pub mod debug_process_restart;
 pub mod driver;
+pub mod ed25519;
 pub mod fm25cl;
 pub mod fxos8700cq;
 pub mod gpio;
@@ -44,13 +47,17 @@ pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
 pub mod pca9544a;
 pub mod process_console;
+pub mod process_restarter;
 pub mod rf233;
 pub mod rf233_const;
 pub mod rng;
 pub mod sdcard;
 pub mod segger_rtt;
+pub mod sha256;
+pub mod sha512;
 pub mod si7021;
 pub mod spi;
+pub mod syscall_trace_uart;
 pub mod temperature;
 pub mod tmp006;
 pub mod tsl2561;
@@ -59,5 +66,7 @@ pub mod virtual_alarm;
 pub mod virtual_flash;
 pub mod virtual_i2c;
 pub mod virtual_pwm;
+pub mod virtual_rng;
 pub mod virtual_spi;
 pub mod virtual_uart;
+pub mod watchdog;
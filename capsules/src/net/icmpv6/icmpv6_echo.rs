@@ -0,0 +1,64 @@
+//! This file contains the ICMPv6 echo responder, which answers the Echo
+//! Requests sent to this node (pings) with an Echo Reply carrying the same
+//! identifier, sequence number and data.
+//!
+//! Requests are answered if the network interface accepts them: they are sent
+//! to one of its addresses or to a multicast address. The data of a request is copied into
+//! the buffer of the responder, so requests with more data than the buffer
+//! holds are dropped, as are requests that arrive while the sender is busy.
+//! The node that sent them tries again, as it does for lost packets.
+
+use crate::net::icmpv6::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::icmpv6::icmpv6_recv::ICMP6RecvClient;
+use crate::net::icmpv6::icmpv6_send::ICMP6Sender;
+use crate::net::ipv6::ipv6::IP6Header;
+use crate::net::network_interface::NetworkInterface;
+use kernel::common::cells::MapCell;
+use kernel::common::leasable_buffer::LeasableBuffer;
+
+pub struct ICMP6EchoResponder<'a> {
+    icmp_sender: &'a dyn ICMP6Sender<'a>,
+    interface: &'a NetworkInterface,
+    buffer: MapCell<LeasableBuffer<'static, u8>>,
+}
+
+impl<'a> ICMP6EchoResponder<'a> {
+    /// `buffer` holds the data of a reply, so its length is the largest
+    /// amount of data a request can carry.
+    pub fn new(
+        icmp_sender: &'a dyn ICMP6Sender<'a>,
+        interface: &'a NetworkInterface,
+        buffer: &'static mut [u8],
+    ) -> ICMP6EchoResponder<'a> {
+        ICMP6EchoResponder {
+            icmp_sender: icmp_sender,
+            interface: interface,
+            buffer: MapCell::new(LeasableBuffer::new(buffer)),
+        }
+    }
+}
+
+impl<'a> ICMP6RecvClient for ICMP6EchoResponder<'a> {
+    fn receive(&self, ip_header: &IP6Header, icmp_header: &ICMP6Header, payload: &[u8]) {
+        let (id, seqno) = match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type128 { id, seqno } => (id, seqno),
+            _ => return,
+        };
+        if !self.interface.accepts(&ip_header.dst_addr) {
+            return;
+        }
+        self.buffer.map(|buf| {
+            buf.reset();
+            if payload.len() > buf.len() {
+                return;
+            }
+            buf[..payload.len()].copy_from_slice(payload);
+            buf.slice(0..payload.len());
+
+            let mut reply = ICMP6Header::new(ICMP6Type::Type129);
+            reply.set_options(ICMP6HeaderOptions::Type129 { id, seqno });
+            // If the sender is busy, the request is dropped
+            let _ = self.icmp_sender.send(ip_header.src_addr, reply, buf);
+        });
+    }
+}
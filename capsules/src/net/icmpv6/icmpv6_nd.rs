@@ -0,0 +1,521 @@
+//! This file implements the host side of Neighbor Discovery for 6LoWPAN
+//! networks (6LoWPAN-ND, RFC 6775), which fills the neighbor cache the IP
+//! sender resolves link-layer addresses with.
+//!
+//! Once started, the host looks for a router by sending Router Solicitations
+//! to the all-routers address. The first router that answers with a Router
+//! Advertisement becomes the default router, and the host registers its
+//! address with it: it sends a Neighbor Solicitation with an Address
+//! Registration Option (ARO) to the router, which answers with a Neighbor
+//! Advertisement carrying the status of the registration. The registration
+//! is refreshed before its lifetime ends. If the router stops answering, or
+//! refuses the registration, the host looks for a router again.
+//!
+//! The prefixes for autonomous address configuration that the default router
+//! advertises are set as the prefix of the network interface, which forms
+//! the global address of the host from it and the link-layer address
+//! (SLAAC, RFC 4862).
+//!
+//! The host also answers the Neighbor Solicitations for its addresses, and
+//! adds the link-layer addresses carried by the messages it receives to the
+//! neighbor cache.
+//!
+//! Limitations:
+//!
+//! - Only one address is registered: the first address of the network
+//!   interface that is not link-local, or the link-local address if there is
+//!   none.
+//! - The lifetimes of prefixes are not tracked: a prefix is used until the
+//!   router advertises it with a valid lifetime of zero, or advertises
+//!   another one.
+//! - The 6LoWPAN contexts in Router Advertisements are ignored, so contexts
+//!   are configured by the board.
+//! - There is no duplicate address detection, so a registration refused for
+//!   a duplicate address is retried later.
+
+use crate::net::icmpv6::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::icmpv6::icmpv6_recv::ICMP6RecvClient;
+use crate::net::icmpv6::icmpv6_send::ICMP6Sender;
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6::IP6Header;
+use crate::net::ipv6::neighbor_cache::{mac_from_iid, NeighborCache};
+use crate::net::network_interface::NetworkInterface;
+use core::cell::Cell;
+use kernel::common::cells::MapCell;
+use kernel::common::leasable_buffer::LeasableBuffer;
+use kernel::hil::time::{self, Frequency};
+
+/// The all-routers multicast address, which Router Solicitations are sent
+/// to.
+const ALL_ROUTERS: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+
+/// The all-nodes multicast address.
+const ALL_NODES: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+
+// Protocol constants, from RFC 4861 and RFC 6775
+const RTR_SOLICITATION_INTERVAL_MS: u32 = 10_000;
+const MAX_RTR_SOLICITATIONS: u8 = 3;
+const MAX_RTR_SOLICITATION_INTERVAL_MS: u32 = 60_000;
+const RETRANS_TIMER_MS: u32 = 1_000;
+const MAX_UNICAST_SOLICIT: u8 = 3;
+
+/// The lifetime of a registration, in minutes. The registration is refreshed
+/// after three quarters of it.
+const REGISTRATION_LIFETIME_MIN: u16 = 15;
+
+/// The hop limit of all Neighbor Discovery messages, which lets receivers
+/// check that they come from the link.
+const ND_HOP_LIMIT: u8 = 255;
+
+/// The length of the target address at the start of Neighbor Solicitations
+/// and Advertisements.
+const TARGET_LEN: usize = 16;
+
+/// The length of the fields of Router Advertisements before their options.
+const RA_FIELDS_LEN: usize = 8;
+
+/// The length of a Prefix Information option.
+const PREFIX_INFO_LEN: usize = 32;
+
+/// The flag of Prefix Information options for prefixes that can be used for
+/// address autoconfiguration.
+const PREFIX_AUTONOMOUS: u8 = 0x40;
+
+/// The length of the prefixes SLAAC forms addresses from, as the interface
+/// identifier is the last 64 bits of the address.
+const SLAAC_PREFIX_LEN: u8 = 64;
+
+/// The flags of Neighbor Advertisements.
+mod na_flags {
+    pub const SOLICITED: u32 = 0x4000_0000;
+    pub const OVERRIDE: u32 = 0x2000_0000;
+}
+
+/// The types of the Neighbor Discovery options the host uses.
+mod nd_option {
+    pub const SOURCE_LL_ADDR: u8 = 1;
+    pub const TARGET_LL_ADDR: u8 = 2;
+    pub const PREFIX_INFORMATION: u8 = 3;
+    pub const ADDRESS_REGISTRATION: u8 = 33;
+}
+
+/// The status of a successful registration.
+const ARO_SUCCESS: u8 = 0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NDState {
+    /// Neighbor discovery has not been started.
+    Idle,
+    /// Looking for a router.
+    Soliciting,
+    /// Registering the address with the default router.
+    Registering,
+    /// The address is registered with the default router.
+    Registered,
+}
+
+/// Returns the options of a Neighbor Discovery message, as pairs of type
+/// and option, the option including its type and length.
+fn options<'b>(buf: &'b [u8]) -> impl Iterator<Item = (u8, &'b [u8])> {
+    let mut rest = buf;
+    core::iter::from_fn(move || {
+        if rest.len() < 2 {
+            return None;
+        }
+        let len = rest[1] as usize * 8;
+        if len == 0 || len > rest.len() {
+            // Malformed options end the list
+            return None;
+        }
+        let (option, next) = rest.split_at(len);
+        rest = next;
+        Some((option[0], option))
+    })
+}
+
+/// Decodes a link-layer address option (RFC 4944, section 8).
+fn decode_ll_addr(option: &[u8]) -> Option<MacAddress> {
+    match option[1] {
+        1 => Some(MacAddress::Short(
+            (option[2] as u16) << 8 | option[3] as u16,
+        )),
+        2 => {
+            let mut long_addr = [0; 8];
+            long_addr.copy_from_slice(&option[2..10]);
+            Some(MacAddress::Long(long_addr))
+        }
+        _ => None,
+    }
+}
+
+/// Encodes a link-layer address option, and returns its length.
+fn encode_ll_addr(buf: &mut [u8], option_type: u8, mac_addr: MacAddress) -> usize {
+    let len = match mac_addr {
+        MacAddress::Short(short_addr) => {
+            buf[2] = (short_addr >> 8) as u8;
+            buf[3] = short_addr as u8;
+            8
+        }
+        MacAddress::Long(long_addr) => {
+            buf[2..10].copy_from_slice(&long_addr);
+            16
+        }
+    };
+    buf[0] = option_type;
+    buf[1] = (len / 8) as u8;
+    len
+}
+
+/// Decodes a Prefix Information option (RFC 4861, section 4.6.2) into the
+/// prefix, its length, its flags and its valid lifetime.
+fn decode_prefix_info(option: &[u8]) -> Option<(IPAddr, u8, u8, u32)> {
+    if option.len() != PREFIX_INFO_LEN {
+        return None;
+    }
+    let valid_lifetime = (option[4] as u32) << 24
+        | (option[5] as u32) << 16
+        | (option[6] as u32) << 8
+        | option[7] as u32;
+    let mut prefix = IPAddr::new();
+    prefix.0.copy_from_slice(&option[16..32]);
+    Some((prefix, option[2], option[3], valid_lifetime))
+}
+
+fn decode_target(payload: &[u8]) -> Option<IPAddr> {
+    if payload.len() < TARGET_LEN {
+        return None;
+    }
+    let mut target = IPAddr::new();
+    target.0.copy_from_slice(&payload[..TARGET_LEN]);
+    Some(target)
+}
+
+pub struct NeighborDiscovery<'a, A: time::Alarm<'a>> {
+    icmp_sender: &'a dyn ICMP6Sender<'a>,
+    neighbor_cache: &'a NeighborCache,
+    alarm: &'a A,
+    interface: &'a NetworkInterface,
+    eui64: [u8; 8],
+    buffer: MapCell<LeasableBuffer<'static, u8>>,
+    state: Cell<NDState>,
+    tries: Cell<u8>,
+}
+
+impl<'a, A: time::Alarm<'a>> NeighborDiscovery<'a, A> {
+    /// `eui64` is the extended address of the interface, which identifies
+    /// the host when it registers its address. `buffer` holds the messages that are sent, and must hold at
+    /// least 48 bytes.
+    pub fn new(
+        icmp_sender: &'a dyn ICMP6Sender<'a>,
+        neighbor_cache: &'a NeighborCache,
+        alarm: &'a A,
+        interface: &'a NetworkInterface,
+        eui64: [u8; 8],
+        buffer: &'static mut [u8],
+    ) -> NeighborDiscovery<'a, A> {
+        NeighborDiscovery {
+            icmp_sender: icmp_sender,
+            neighbor_cache: neighbor_cache,
+            alarm: alarm,
+            interface: interface,
+            eui64: eui64,
+            buffer: MapCell::new(LeasableBuffer::new(buffer)),
+            state: Cell::new(NDState::Idle),
+            tries: Cell::new(0),
+        }
+    }
+
+    /// Starts looking for a router to register with.
+    pub fn start(&self) {
+        self.solicit_router();
+    }
+
+    pub fn get_state(&self) -> NDState {
+        self.state.get()
+    }
+
+    fn ms_to_ticks(ms: u32) -> u32 {
+        ms * (<A::Frequency>::frequency() / 1000)
+    }
+
+    fn start_timer(&self, ms: u32) {
+        let tics = self.alarm.now().wrapping_add(Self::ms_to_ticks(ms));
+        self.alarm.set_alarm(tics);
+    }
+
+    /// Sends a message whose payload is written by `fill`, which returns its
+    /// length. If the sender is busy, the message is lost like any other, and
+    /// sent again when the timer fires.
+    fn send<F>(&self, dst: IPAddr, icmp_header: ICMP6Header, fill: F)
+    where
+        F: FnOnce(&mut [u8]) -> usize,
+    {
+        self.buffer.map(|buf| {
+            buf.reset();
+            let message = &mut buf[..];
+            for byte in message.iter_mut() {
+                *byte = 0;
+            }
+            let len = fill(message);
+            buf.slice(0..len);
+            let _ = self.icmp_sender.send(dst, icmp_header, buf);
+        });
+    }
+
+    fn solicit_router(&self) {
+        self.state.set(NDState::Soliciting);
+        self.tries.set(0);
+        self.send_router_solicitation();
+    }
+
+    fn send_router_solicitation(&self) {
+        let mac_addr = self.interface.get_mac_addr();
+        self.send(ALL_ROUTERS, ICMP6Header::new(ICMP6Type::Type133), |buf| {
+            encode_ll_addr(buf, nd_option::SOURCE_LL_ADDR, mac_addr)
+        });
+        // Solicit quickly at first, then back off
+        let tries = self.tries.get();
+        let interval = if tries < MAX_RTR_SOLICITATIONS {
+            RTR_SOLICITATION_INTERVAL_MS
+        } else {
+            let shift = (tries - MAX_RTR_SOLICITATIONS + 1).min(3);
+            (RTR_SOLICITATION_INTERVAL_MS << shift).min(MAX_RTR_SOLICITATION_INTERVAL_MS)
+        };
+        self.tries.set(tries.saturating_add(1));
+        self.start_timer(interval);
+    }
+
+    /// The address registered with the router: the first global address of
+    /// the interface, or its link-local address if it has no other.
+    fn registration_address(&self) -> Option<IPAddr> {
+        (0..self.interface.address_count())
+            .filter_map(|index| self.interface.get_address(index))
+            .find(|address| !address.is_unicast_link_local())
+            .or_else(|| self.interface.get_address(0))
+    }
+
+    fn register(&self) {
+        self.state.set(NDState::Registering);
+        self.tries.set(0);
+        self.send_registration();
+    }
+
+    fn send_registration(&self) {
+        let router = match self.neighbor_cache.get_default_router() {
+            Some(router) => router,
+            None => return self.solicit_router(),
+        };
+        let address = match self.registration_address() {
+            Some(address) => address,
+            // Try again once the interface has an address
+            None => return self.start_timer(RETRANS_TIMER_MS),
+        };
+        let mac_addr = self.interface.get_mac_addr();
+        let eui64 = self.eui64;
+        self.send(router, ICMP6Header::new(ICMP6Type::Type135), |buf| {
+            buf[..TARGET_LEN].copy_from_slice(&address.0);
+            let mut off = TARGET_LEN;
+            off += encode_ll_addr(&mut buf[off..], nd_option::SOURCE_LL_ADDR, mac_addr);
+            let aro = &mut buf[off..off + 16];
+            aro[0] = nd_option::ADDRESS_REGISTRATION;
+            aro[1] = 2;
+            aro[6] = (REGISTRATION_LIFETIME_MIN >> 8) as u8;
+            aro[7] = REGISTRATION_LIFETIME_MIN as u8;
+            aro[8..16].copy_from_slice(&eui64);
+            off + 16
+        });
+        self.tries.set(self.tries.get() + 1);
+        self.start_timer(RETRANS_TIMER_MS);
+    }
+
+    fn send_neighbor_advertisement(&self, dst: IPAddr, target: IPAddr, solicited: bool) {
+        let mut header = ICMP6Header::new(ICMP6Type::Type136);
+        let flags = if solicited {
+            na_flags::SOLICITED | na_flags::OVERRIDE
+        } else {
+            na_flags::OVERRIDE
+        };
+        header.set_options(ICMP6HeaderOptions::Type136 { flags });
+        let mac_addr = self.interface.get_mac_addr();
+        self.send(dst, header, |buf| {
+            buf[..TARGET_LEN].copy_from_slice(&target.0);
+            TARGET_LEN + encode_ll_addr(&mut buf[TARGET_LEN..], nd_option::TARGET_LL_ADDR, mac_addr)
+        });
+    }
+
+    fn router_advertisement(&self, ip_header: &IP6Header, lifetime: u16, opts: &[u8]) {
+        let router = ip_header.src_addr;
+        if !router.is_unicast_link_local() {
+            return;
+        }
+        if lifetime == 0 {
+            // The router is leaving
+            if self.neighbor_cache.get_default_router() == Some(router) {
+                self.neighbor_cache.clear_default_router();
+                self.solicit_router();
+            }
+            return;
+        }
+        let mac_addr = options(opts)
+            .find(|(option_type, _)| *option_type == nd_option::SOURCE_LL_ADDR)
+            .and_then(|(_, option)| decode_ll_addr(option))
+            .unwrap_or_else(|| mac_from_iid(&router));
+        match self.neighbor_cache.get_default_router() {
+            Some(current) if current == router => {
+                self.neighbor_cache.insert(router, mac_addr);
+                if self.configure_prefix(opts) && self.state.get() == NDState::Registered {
+                    // Register the new address
+                    self.register();
+                }
+            }
+            None => {
+                self.neighbor_cache.set_default_router(router, mac_addr);
+                self.configure_prefix(opts);
+                if self.state.get() == NDState::Soliciting {
+                    self.register();
+                }
+            }
+            // Advertisements of other routers are ignored while we have one
+            Some(_) => {}
+        }
+    }
+
+    /// Sets the prefix of the interface from the Prefix Information options
+    /// of an advertisement of the default router. Returns whether the prefix
+    /// changed.
+    fn configure_prefix(&self, opts: &[u8]) -> bool {
+        let mut changed = false;
+        for (prefix, prefix_len, flags, valid_lifetime) in options(opts)
+            .filter(|(option_type, _)| *option_type == nd_option::PREFIX_INFORMATION)
+            .filter_map(|(_, option)| decode_prefix_info(option))
+        {
+            if flags & PREFIX_AUTONOMOUS == 0
+                || prefix_len != SLAAC_PREFIX_LEN
+                || prefix.is_unicast_link_local()
+            {
+                continue;
+            }
+            let current = self.interface.get_prefix() == Some((prefix, prefix_len));
+            if valid_lifetime == 0 {
+                if current {
+                    self.interface.clear_prefix();
+                    changed = true;
+                }
+            } else if !current {
+                self.interface.set_prefix(prefix, prefix_len);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn neighbor_solicitation(&self, ip_header: &IP6Header, payload: &[u8]) {
+        let target = match decode_target(payload) {
+            Some(target) => target,
+            None => return,
+        };
+        if !self.interface.has_address(&target) {
+            return;
+        }
+        let src = ip_header.src_addr;
+        if src.is_unspecified() {
+            // Duplicate address detection by another node
+            self.send_neighbor_advertisement(ALL_NODES, target, false);
+            return;
+        }
+        if let Some(mac_addr) = options(&payload[TARGET_LEN..])
+            .find(|(option_type, _)| *option_type == nd_option::SOURCE_LL_ADDR)
+            .and_then(|(_, option)| decode_ll_addr(option))
+        {
+            self.neighbor_cache.insert(src, mac_addr);
+        }
+        self.send_neighbor_advertisement(src, target, true);
+    }
+
+    fn neighbor_advertisement(&self, ip_header: &IP6Header, payload: &[u8]) {
+        let target = match decode_target(payload) {
+            Some(target) => target,
+            None => return,
+        };
+        let mut aro_status = None;
+        for (option_type, option) in options(&payload[TARGET_LEN..]) {
+            match option_type {
+                nd_option::TARGET_LL_ADDR => {
+                    if let Some(mac_addr) = decode_ll_addr(option) {
+                        if !self.interface.has_address(&target) {
+                            self.neighbor_cache.insert(target, mac_addr);
+                        }
+                    }
+                }
+                nd_option::ADDRESS_REGISTRATION if option.len() == 16 => {
+                    aro_status = Some(option[2]);
+                }
+                _ => {}
+            }
+        }
+
+        // The answer of the router to our registration
+        let status = match aro_status {
+            Some(status) => status,
+            None => return,
+        };
+        if self.state.get() != NDState::Registering
+            || Some(target) != self.registration_address()
+            || Some(ip_header.src_addr) != self.neighbor_cache.get_default_router()
+        {
+            return;
+        }
+        if status == ARO_SUCCESS {
+            self.state.set(NDState::Registered);
+            self.start_timer(REGISTRATION_LIFETIME_MIN as u32 * 60_000 / 4 * 3);
+        } else {
+            self.neighbor_cache.clear_default_router();
+            self.state.set(NDState::Soliciting);
+            self.tries.set(MAX_RTR_SOLICITATIONS);
+            self.start_timer(MAX_RTR_SOLICITATION_INTERVAL_MS);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> ICMP6RecvClient for NeighborDiscovery<'a, A> {
+    fn receive(&self, ip_header: &IP6Header, icmp_header: &ICMP6Header, payload: &[u8]) {
+        // Neighbor Discovery messages that were forwarded are not valid
+        if self.state.get() == NDState::Idle
+            || ip_header.get_hop_limit() != ND_HOP_LIMIT
+            || icmp_header.get_code() != 0
+        {
+            return;
+        }
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type134 { lifetime, .. } if payload.len() >= RA_FIELDS_LEN => {
+                self.router_advertisement(ip_header, lifetime, &payload[RA_FIELDS_LEN..])
+            }
+            ICMP6HeaderOptions::Type135 { .. } => self.neighbor_solicitation(ip_header, payload),
+            ICMP6HeaderOptions::Type136 { .. } => self.neighbor_advertisement(ip_header, payload),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for NeighborDiscovery<'a, A> {
+    fn fired(&self) {
+        match self.state.get() {
+            NDState::Idle => {}
+            NDState::Soliciting => self.send_router_solicitation(),
+            NDState::Registering => {
+                if self.tries.get() < MAX_UNICAST_SOLICIT {
+                    self.send_registration();
+                } else {
+                    // The router stopped answering, look for another one
+                    self.neighbor_cache
+                        .get_default_router()
+                        .map(|router| self.neighbor_cache.remove(router));
+                    self.solicit_router();
+                }
+            }
+            // Refresh the registration
+            NDState::Registered => self.register(),
+        }
+    }
+}
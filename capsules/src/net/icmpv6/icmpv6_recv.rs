@@ -0,0 +1,71 @@
+//! This file contains the definition and implementation of the ICMPv6
+//! receive path. The [ICMP6RecvStruct](struct.ICMP6RecvStruct.html) is the
+//! IP receive client for ICMPv6 packets: it decodes the ICMPv6 header of
+//! each packet and passes the message to all of its clients, which implement
+//! the [ICMP6RecvClient](trait.ICMP6RecvClient.html) trait and ignore the
+//! types of messages they do not handle. The checksum of the packet is
+//! verified by the IP layer before it is passed up.
+
+use crate::net::icmpv6::icmpv6::ICMP6Header;
+use crate::net::ipv6::ipv6::IP6Header;
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use kernel::common::cells::OptionalCell;
+
+/// A trait for a client of the `ICMP6RecvStruct`.
+pub trait ICMP6RecvClient {
+    /// Called for every received ICMPv6 message.
+    ///
+    /// # Arguments
+    ///
+    /// `ip_header` - The IPv6 header of the packet
+    /// `icmp_header` - The decoded ICMPv6 header
+    /// `payload` - The message after the ICMPv6 header
+    fn receive(&self, ip_header: &IP6Header, icmp_header: &ICMP6Header, payload: &[u8]);
+}
+
+/// How many clients can receive ICMPv6 messages.
+const MAX_CLIENTS: usize = 4;
+
+pub struct ICMP6RecvStruct<'a> {
+    clients: [OptionalCell<&'a dyn ICMP6RecvClient>; MAX_CLIENTS],
+}
+
+impl<'a> ICMP6RecvStruct<'a> {
+    pub fn new() -> ICMP6RecvStruct<'a> {
+        ICMP6RecvStruct {
+            clients: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
+        }
+    }
+
+    /// Adds a client that receives all ICMPv6 messages. Panics if there are
+    /// `MAX_CLIENTS` clients already, as this is a mistake in the setup of
+    /// the board.
+    pub fn add_client(&self, client: &'a dyn ICMP6RecvClient) {
+        self.clients
+            .iter()
+            .find(|slot| slot.is_none())
+            .expect("Too many ICMPv6 clients")
+            .set(client);
+    }
+}
+
+impl<'a> IP6RecvClient for ICMP6RecvStruct<'a> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        let (offset, icmp_header) = match ICMP6Header::decode(payload).done() {
+            Some((offset, mut icmp_header)) => {
+                icmp_header.set_len(payload.len() as u16);
+                (offset, icmp_header)
+            }
+            // Unknown types of messages are dropped
+            None => return,
+        };
+        for slot in self.clients.iter() {
+            slot.map(|client| client.receive(&ip_header, &icmp_header, &payload[offset..]));
+        }
+    }
+}
@@ -5,12 +5,19 @@
 //! upper layer to allow them to receive the `send_done` callback once
 //! transmission has completed.
 //!
+//! The payload is copied into the packet when it is sent, so the caller keeps
+//! its buffer. Only one message can be in flight at a time, which lets
+//! several capsules that reply to messages, such as the echo responder and
+//! neighbor discovery, share a sender: a message sent while another one is in
+//! flight is refused with `EBUSY`, and the capsules send it later or drop it.
+//!
 //! - Author: Conor McAvity <cmcavity@stanford.edu>
 
 use crate::net::icmpv6::icmpv6::ICMP6Header;
 use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::ipv6::TransportHeader;
 use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
 use kernel::common::leasable_buffer::LeasableBuffer;
 use kernel::ReturnCode;
@@ -39,20 +46,27 @@ pub trait ICMP6Sender<'a> {
     ///
     /// `dest` - The destination IP address
     /// `icmp_header` - The ICMPv6 header to be sent
-    /// `buf` - The byte array containing the ICMPv6 payload
+    /// `buf` - The ICMPv6 payload, which is copied before this function
+    /// returns
     ///
     /// # Return Value
     ///
     /// This function returns a code reporting either success or any
-    /// synchronous errors. Note that any asynchronous errors are returned
-    /// via the callback.
-    fn send(&self, dest: IPAddr, icmp_header: ICMP6Header, buf: &'static mut [u8]) -> ReturnCode;
+    /// synchronous errors, such as EBUSY if a message is in flight. Note that
+    /// any asynchronous errors are returned via the callback.
+    fn send(
+        &self,
+        dest: IPAddr,
+        icmp_header: ICMP6Header,
+        buf: &LeasableBuffer<'static, u8>,
+    ) -> ReturnCode;
 }
 
 /// A struct that implements the `ICMP6Sender` trait.
 pub struct ICMP6SendStruct<'a, T: IP6Sender<'a>> {
     ip_send_struct: &'a T,
     client: OptionalCell<&'a dyn ICMP6SendClient>,
+    sending: Cell<bool>,
 }
 
 impl<T: IP6Sender<'a>> ICMP6SendStruct<'a, T> {
@@ -60,6 +74,7 @@ impl<T: IP6Sender<'a>> ICMP6SendStruct<'a, T> {
         ICMP6SendStruct {
             ip_send_struct: ip_send_struct,
             client: OptionalCell::empty(),
+            sending: Cell::new(false),
         }
     }
 }
@@ -73,13 +88,21 @@ impl<T: IP6Sender<'a>> ICMP6Sender<'a> for ICMP6SendStruct<'a, T> {
         &self,
         dest: IPAddr,
         mut icmp_header: ICMP6Header,
-        buf: &'static mut [u8],
+        buf: &LeasableBuffer<'static, u8>,
     ) -> ReturnCode {
+        if self.sending.get() {
+            return ReturnCode::EBUSY;
+        }
         let total_len = buf.len() + icmp_header.get_hdr_size();
         icmp_header.set_len(total_len as u16);
         let transport_header = TransportHeader::ICMP(icmp_header);
-        self.ip_send_struct
-            .send_to(dest, transport_header, &LeasableBuffer::new(buf))
+        // The IP sender can call `send_done()` before it returns
+        self.sending.set(true);
+        let result = self.ip_send_struct.send_to(dest, transport_header, buf);
+        if result != ReturnCode::SUCCESS {
+            self.sending.set(false);
+        }
+        result
     }
 }
 
@@ -87,6 +110,7 @@ impl<T: IP6Sender<'a>> IP6SendClient for ICMP6SendStruct<'a, T> {
     /// Forwards callback received from the `IP6Sender` to the
     /// `ICMP6SendClient`.
     fn send_done(&self, result: ReturnCode) {
+        self.sending.set(false);
         self.client.map(|client| client.send_done(result));
     }
 }
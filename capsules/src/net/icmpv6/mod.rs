@@ -1,2 +1,5 @@
 pub mod icmpv6;
+pub mod icmpv6_echo;
+pub mod icmpv6_nd;
+pub mod icmpv6_recv;
 pub mod icmpv6_send;
@@ -39,6 +39,12 @@ impl MacAddress {
 
 pub type PanID = u16;
 
+/// The short address and PAN ID that address every device and every PAN.
+pub const BROADCAST_ADDR: u16 = 0xffff;
+pub const BROADCAST_PAN: PanID = 0xffff;
+/// The short address of associated devices that use their extended address.
+pub const NO_SHORT_ADDR: u16 = 0xfffe;
+
 mod frame_control {
     pub const FRAME_TYPE_MASK: u16 = 0b111;
     pub const SECURITY_ENABLED: u16 = 1 << 3;
@@ -857,3 +863,166 @@ impl Header<'a> {
         stream_done!(off, (dst_pan, dst_addr, src_pan, src_addr));
     }
 }
+
+mod superframe_spec {
+    pub const SUPERFRAME_ORDER_POS: usize = 4;
+    pub const FINAL_CAP_SLOT_POS: usize = 8;
+    pub const PAN_COORDINATOR: u16 = 1 << 14;
+    pub const ASSOCIATION_PERMIT: u16 = 1 << 15;
+    // The beacon order, superframe order and final CAP slot of a PAN that
+    // does not send periodic beacons
+    pub const NONBEACON_ORDER: u16 = 15;
+
+    pub const GTS_DESCRIPTOR_COUNT_MASK: u8 = 0b111;
+    pub const GTS_DESCRIPTOR_LEN: usize = 3;
+    pub const PENDING_COUNT_MASK: u8 = 0b111;
+    pub const PENDING_LONG_COUNT_POS: usize = 4;
+}
+
+/// IEEE 802.15.4-2015, 7.3.1, the MAC payload of a beacon frame. Only PANs
+/// that do not send periodic beacons are supported: their coordinators send
+/// a beacon in answer to each beacon request, without GTS or pending
+/// addresses. These fields are skipped when decoding the beacons of other
+/// PANs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Beacon {
+    pub pan_coordinator: bool,
+    pub association_permit: bool,
+}
+
+impl Beacon {
+    pub fn encode(&self, buf: &mut [u8]) -> SResult {
+        let mut spec = superframe_spec::NONBEACON_ORDER
+            | (superframe_spec::NONBEACON_ORDER << superframe_spec::SUPERFRAME_ORDER_POS)
+            | (superframe_spec::NONBEACON_ORDER << superframe_spec::FINAL_CAP_SLOT_POS);
+        if self.pan_coordinator {
+            spec |= superframe_spec::PAN_COORDINATOR;
+        }
+        if self.association_permit {
+            spec |= superframe_spec::ASSOCIATION_PERMIT;
+        }
+        let off = enc_consume!(buf; encode_u16, spec.to_be());
+        // No GTS descriptors and no pending addresses
+        let off = enc_consume!(buf, off; encode_u8, 0);
+        let off = enc_consume!(buf, off; encode_u8, 0);
+        stream_done!(off);
+    }
+
+    pub fn decode(buf: &[u8]) -> SResult<Beacon> {
+        let (off, spec_be) = dec_try!(buf; decode_u16);
+        let spec = u16::from_be(spec_be);
+
+        // GTS fields: the GTS directions and descriptors are only present if
+        // there is at least one descriptor
+        let (off, gts_spec) = dec_try!(buf, off; decode_u8);
+        let gts_count = (gts_spec & superframe_spec::GTS_DESCRIPTOR_COUNT_MASK) as usize;
+        let off = if gts_count > 0 {
+            off + 1 + gts_count * superframe_spec::GTS_DESCRIPTOR_LEN
+        } else {
+            off
+        };
+
+        // Pending address fields
+        stream_len_cond!(buf, off + 1);
+        let pending_spec = buf[off];
+        let short_count = (pending_spec & superframe_spec::PENDING_COUNT_MASK) as usize;
+        let long_count = ((pending_spec >> superframe_spec::PENDING_LONG_COUNT_POS)
+            & superframe_spec::PENDING_COUNT_MASK) as usize;
+        let off = off + 1 + short_count * 2 + long_count * 8;
+        stream_len_cond!(buf, off);
+
+        stream_done!(
+            off,
+            Beacon {
+                pan_coordinator: (spec & superframe_spec::PAN_COORDINATOR) != 0,
+                association_permit: (spec & superframe_spec::ASSOCIATION_PERMIT) != 0,
+            }
+        );
+    }
+}
+
+/// IEEE 802.15.4-2015, 7.5.2, the bits of the Capability Information field
+/// of association requests.
+pub mod capability {
+    pub const DEVICE_TYPE_FFD: u8 = 1 << 1;
+    pub const POWER_SOURCE_MAINS: u8 = 1 << 2;
+    pub const RX_ON_WHEN_IDLE: u8 = 1 << 3;
+    pub const SECURITY: u8 = 1 << 6;
+    pub const ALLOCATE_ADDRESS: u8 = 1 << 7;
+}
+
+/// IEEE 802.15.4-2015, 7.5.3, the status of an association response
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AssociationStatus {
+    Successful = 0x00,
+    PanAtCapacity = 0x01,
+    PanAccessDenied = 0x02,
+}
+
+impl AssociationStatus {
+    pub fn from_u8(status: u8) -> Option<AssociationStatus> {
+        match status {
+            0x00 => Some(AssociationStatus::Successful),
+            0x01 => Some(AssociationStatus::PanAtCapacity),
+            0x02 => Some(AssociationStatus::PanAccessDenied),
+            _ => None,
+        }
+    }
+}
+
+mod command_id {
+    pub const ASSOCIATION_REQUEST: u8 = 0x01;
+    pub const ASSOCIATION_RESPONSE: u8 = 0x02;
+    pub const BEACON_REQUEST: u8 = 0x07;
+}
+
+/// IEEE 802.15.4-2015, 7.5, the MAC payload of the MAC command frames used
+/// for scanning and association.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MacCommand {
+    /// The capability information of the device
+    AssociationRequest(u8),
+    /// The short address assigned to the device, and the status
+    AssociationResponse(u16, AssociationStatus),
+    BeaconRequest,
+}
+
+impl MacCommand {
+    pub fn encode(&self, buf: &mut [u8]) -> SResult {
+        let off = match *self {
+            MacCommand::AssociationRequest(capability) => {
+                let off = enc_consume!(buf; encode_u8, command_id::ASSOCIATION_REQUEST);
+                enc_consume!(buf, off; encode_u8, capability)
+            }
+            MacCommand::AssociationResponse(short_addr, status) => {
+                let off = enc_consume!(buf; encode_u8, command_id::ASSOCIATION_RESPONSE);
+                let off = enc_consume!(buf, off; encode_u16, short_addr.to_be());
+                enc_consume!(buf, off; encode_u8, status as u8)
+            }
+            MacCommand::BeaconRequest => enc_consume!(buf; encode_u8, command_id::BEACON_REQUEST),
+        };
+        stream_done!(off);
+    }
+
+    pub fn decode(buf: &[u8]) -> SResult<MacCommand> {
+        let (off, id) = dec_try!(buf; decode_u8);
+        match id {
+            command_id::ASSOCIATION_REQUEST => {
+                let (off, capability) = dec_try!(buf, off; decode_u8);
+                stream_done!(off, MacCommand::AssociationRequest(capability));
+            }
+            command_id::ASSOCIATION_RESPONSE => {
+                let (off, short_addr_be) = dec_try!(buf, off; decode_u16);
+                let (off, status) = dec_try!(buf, off; decode_u8);
+                let status = stream_from_option!(AssociationStatus::from_u8(status));
+                stream_done!(
+                    off,
+                    MacCommand::AssociationResponse(u16::from_be(short_addr_be), status)
+                );
+            }
+            command_id::BEACON_REQUEST => stream_done!(off, MacCommand::BeaconRequest),
+            _ => stream_err!(),
+        }
+    }
+}
@@ -5,7 +5,9 @@
 use crate::net::icmpv6::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
 use crate::net::ieee802154::MacAddress;
 use crate::net::ipv6::ipv6::IP6Header;
+use crate::net::tcp::tcp::{TCPHeader, TCP_HDR_LEN};
 use crate::net::udp::udp::UDPHeader;
+use core::fmt;
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum MacAddr {
@@ -37,6 +39,41 @@ impl PartialEq for IPAddr {
 
 impl Eq for IPAddr {}
 
+/// Prints the address as eight groups of hexadecimal digits, without
+/// shortening runs of zero groups to `::`.
+impl fmt::Display for IPAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, group) in self.0.chunks(2).enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{:x}", (group[0] as u16) << 8 | group[1] as u16)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses colon-separated groups of up to four hexadecimal digits into
+// `groups`, and returns how many there were.
+fn parse_groups(s: &str, groups: &mut [u16]) -> Option<usize> {
+    if s.is_empty() {
+        return Some(0);
+    }
+    let mut count = 0;
+    for group in s.split(':') {
+        if count == groups.len()
+            || group.is_empty()
+            || group.len() > 4
+            || !group.bytes().all(|b| (b as char).is_ascii_hexdigit())
+        {
+            return None;
+        }
+        groups[count] = u16::from_str_radix(group, 16).ok()?;
+        count += 1;
+    }
+    Some(count)
+}
+
 impl IPAddr {
     pub fn new() -> IPAddr {
         // Defaults to the unspecified address
@@ -111,6 +148,36 @@ impl IPAddr {
     pub fn is_multicast(&self) -> bool {
         self.0[0] == 0xff
     }
+
+    /// Parses an address in the text form of RFC 4291, such as
+    /// `fe80::1`, where `::` stands for one or more zero groups. The forms
+    /// with an embedded IPv4 address are not supported.
+    pub fn parse(s: &str) -> Option<IPAddr> {
+        let mut groups = [0u16; 8];
+        match s.find("::") {
+            Some(i) => {
+                let mut tail = [0u16; 8];
+                let head_len = parse_groups(&s[..i], &mut groups)?;
+                let tail_len = parse_groups(&s[i + 2..], &mut tail)?;
+                if head_len + tail_len > 7 {
+                    return None;
+                }
+                groups[8 - tail_len..].copy_from_slice(&tail[..tail_len]);
+            }
+            None => {
+                if parse_groups(s, &mut groups)? != 8 {
+                    return None;
+                }
+            }
+        }
+
+        let mut addr = IPAddr::new();
+        for (bytes, group) in addr.0.chunks_mut(2).zip(groups.iter()) {
+            bytes[0] = (group >> 8) as u8;
+            bytes[1] = *group as u8;
+        }
+        Some(addr)
+    }
 }
 
 pub fn compute_udp_checksum(
@@ -180,6 +247,10 @@ pub fn compute_udp_checksum(
     (sum as u16) //Return result as u16 in host byte order */
 }
 
+/// Computes the checksum of an ICMPv6 message, whose length is taken from
+/// `icmp_header`. `payload` holds the message after the header. If the
+/// checksum field of `icmp_header` already holds the checksum of the message,
+/// the result is zero.
 pub fn compute_icmp_checksum(
     ipv6_header: &IP6Header,
     icmp_header: &ICMP6Header,
@@ -187,17 +258,27 @@ pub fn compute_icmp_checksum(
 ) -> u16 {
     let mut sum: u32 = 0;
 
-    // add ipv6 pseudo-header
-    sum += compute_ipv6_ph_sum(ipv6_header);
+    // add ipv6 pseudo-header, with the length of the message
+    for i in (0..16).step_by(2) {
+        sum += (ipv6_header.src_addr.0[i] as u32) << 8 | ipv6_header.src_addr.0[i + 1] as u32;
+        sum += (ipv6_header.dst_addr.0[i] as u32) << 8 | ipv6_header.dst_addr.0[i + 1] as u32;
+    }
+    sum += icmp_header.get_len() as u32;
+    sum += ip6_nh::ICMP as u32;
 
-    // add type and code
+    // add type, code and checksum
     let msb = (icmp_header.get_type_as_int() as u32) << 8;
     let lsb = icmp_header.get_code() as u32;
     sum += msb + lsb;
+    sum += icmp_header.get_cksum() as u32;
 
     // add options
     match icmp_header.get_options() {
-        ICMP6HeaderOptions::Type1 { unused } | ICMP6HeaderOptions::Type3 { unused } => {
+        ICMP6HeaderOptions::Type1 { unused }
+        | ICMP6HeaderOptions::Type3 { unused }
+        | ICMP6HeaderOptions::Type133 { reserved: unused }
+        | ICMP6HeaderOptions::Type135 { reserved: unused }
+        | ICMP6HeaderOptions::Type136 { flags: unused } => {
             sum += unused >> 16; // upper 16 bits
             sum += unused & 0xffff; // lower 16 bits
         }
@@ -205,23 +286,73 @@ pub fn compute_icmp_checksum(
             sum += id as u32;
             sum += seqno as u32;
         }
+        ICMP6HeaderOptions::Type134 {
+            hop_limit,
+            flags,
+            lifetime,
+        } => {
+            sum += ((hop_limit as u32) << 8) + flags as u32;
+            sum += lifetime as u32;
+        }
     }
 
-    // add icmp payload
-    let payload_len = icmp_header.get_len() - icmp_header.get_hdr_size() as u16;
-    sum += compute_sum(payload, payload_len);
+    // add icmp payload, padding an odd last byte
+    let payload_len = icmp_header.get_len() as usize - icmp_header.get_hdr_size();
+    for chunk in payload[..payload_len].chunks(2) {
+        let lsb = if chunk.len() == 2 { chunk[1] as u32 } else { 0 };
+        sum += (chunk[0] as u32) << 8 | lsb;
+    }
 
     // carry overflow
     while sum > 0xffff {
-        let sum_upper = sum >> 16;
-        let sum_lower = sum & 0xffff;
-        sum += sum_upper + sum_lower;
+        sum = (sum >> 16) + (sum & 0xffff);
     }
 
-    sum = !sum;
-    sum = sum & 0xffff;
+    !sum as u16
+}
+
+/// Computes the checksum of a TCP segment, whose length is taken from
+/// `tcp_header`. `payload` holds the rest of the segment after the first
+/// `TCP_HDR_LEN` bytes, so any options followed by the data. If the checksum
+/// field of `tcp_header` already holds the checksum of the segment, the
+/// result is zero.
+pub fn compute_tcp_checksum(ip6_header: &IP6Header, tcp_header: &TCPHeader, payload: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+
+    // Add the pseudo-header: the addresses, the segment length and the next
+    // header. The length of a segment always fits in 16 bits.
+    for i in (0..16).step_by(2) {
+        sum += (ip6_header.src_addr.0[i] as u32) << 8 | ip6_header.src_addr.0[i + 1] as u32;
+        sum += (ip6_header.dst_addr.0[i] as u32) << 8 | ip6_header.dst_addr.0[i + 1] as u32;
+    }
+    sum += tcp_header.get_len() as u32;
+    sum += ip6_nh::TCP as u32;
+
+    // Add the header without options
+    sum += tcp_header.get_src_port() as u32;
+    sum += tcp_header.get_dst_port() as u32;
+    sum += tcp_header.get_seq_num() >> 16;
+    sum += tcp_header.get_seq_num() & 0xffff;
+    sum += tcp_header.get_ack_num() >> 16;
+    sum += tcp_header.get_ack_num() & 0xffff;
+    sum += tcp_header.get_offset_and_control() as u32;
+    sum += tcp_header.get_window() as u32;
+    sum += tcp_header.get_cksum() as u32;
+    sum += tcp_header.get_urg_ptr() as u32;
+
+    // Add the options and data, padding an odd last byte with zero
+    let payload_len = tcp_header.get_len() as usize - TCP_HDR_LEN;
+    for chunk in payload[..payload_len].chunks(2) {
+        let lsb = if chunk.len() == 2 { chunk[1] as u32 } else { 0 };
+        sum += (chunk[0] as u32) << 8 | lsb;
+    }
+
+    // carry overflow
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
 
-    sum as u16
+    !sum as u16
 }
 
 pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
@@ -260,3 +391,44 @@ pub fn compute_sum(buf: &[u8], len: u16) -> u32 {
 
     sum
 }
+
+#[cfg(test)]
+mod test {
+    use super::IPAddr;
+
+    #[test]
+    fn parse_addresses() {
+        let mut addr = IPAddr::new();
+        addr.set_unicast_link_local();
+        addr.0[15] = 1;
+        assert_eq!(IPAddr::parse("fe80::1"), Some(addr));
+        assert_eq!(IPAddr::parse("fe80:0:0:0:0:0:0:1"), Some(addr));
+        assert_eq!(IPAddr::parse("::"), Some(IPAddr::new()));
+
+        addr.0[0..4].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        addr.0[15] = 0;
+        assert_eq!(IPAddr::parse("2001:db8::"), Some(addr));
+        assert_eq!(IPAddr::parse("2001:DB8:0::0"), Some(addr));
+    }
+
+    #[test]
+    fn reject_invalid_addresses() {
+        for text in [
+            "",
+            ":",
+            "1:2:3:4:5:6:7",
+            "1:2:3:4:5:6:7:8:9",
+            "1::2::3",
+            "1:2:3:4::5:6:7:8",
+            "12345::",
+            "fe80::g",
+            "fe80:::1",
+            "fe80::+1",
+            "::ffff:192.0.2.1",
+        ]
+        .iter()
+        {
+            assert_eq!(IPAddr::parse(text), None, "{}", text);
+        }
+    }
+}
@@ -21,8 +21,9 @@ use kernel::ReturnCode;
 - The udp_mac MacUser has a single receive client, which is the `sixlowpan_state` struct
 - `sixlowpan_state` has a single rx_client, which in our case is a single struct that
   implements the `ip_receive ` trait.
-- the `ip_receive` implementing struct (`IP6RecvStruct`) has a single client, which is
-  udp_recv, a `UDPReceive` struct.
+- the `ip_receive` implementing struct (`IP6RecvStruct`) has a default client, which is
+  udp_recv, a `UDPReceive` struct. Other transport protocols, such as TCP, register their
+  own client for the packets with their next header.
 - The UDPReceive struct is a field of the UDPDriver, which ultimately passes the
   packets up to userland.
 */
@@ -37,25 +38,61 @@ pub trait IP6RecvClient {
 /// The receiver should drop any packets with destination addresses
 /// that are not among the local addresses of this device.
 pub trait IP6Receiver<'a> {
+    /// Sets the client that receives the packets of every transport protocol
+    /// without a client of its own.
     fn set_client(&self, client: &'a dyn IP6RecvClient);
+
+    /// Sets the client that receives the packets whose next header is
+    /// `next_header` (one of the `ip6_nh` constants).
+    fn set_protocol_client(&self, next_header: u8, client: &'a dyn IP6RecvClient);
 }
 
+/// How many transport protocols can have a client of their own.
+const MAX_PROTOCOL_CLIENTS: usize = 4;
+
 pub struct IP6RecvStruct<'a> {
     client: OptionalCell<&'a dyn IP6RecvClient>,
+    protocol_clients: [OptionalCell<(u8, &'a dyn IP6RecvClient)>; MAX_PROTOCOL_CLIENTS],
 }
 
 impl<'a> IP6Receiver<'a> for IP6RecvStruct<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient) {
         self.client.set(client);
     }
+
+    /// Panics if `MAX_PROTOCOL_CLIENTS` protocols have a client already, as
+    /// this is a mistake in the setup of the board.
+    fn set_protocol_client(&self, next_header: u8, client: &'a dyn IP6RecvClient) {
+        let slot = self
+            .protocol_clients
+            .iter()
+            .find(|slot| slot.map_or(true, |(nh, _)| *nh == next_header))
+            .expect("Too many IPv6 protocol clients");
+        slot.set((next_header, client));
+    }
 }
 
 impl<'a> IP6RecvStruct<'a> {
     pub fn new() -> IP6RecvStruct<'a> {
         IP6RecvStruct {
             client: OptionalCell::empty(),
+            protocol_clients: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
         }
     }
+
+    fn client_for(&self, next_header: u8) -> Option<&'a dyn IP6RecvClient> {
+        self.protocol_clients
+            .iter()
+            .filter_map(|slot| slot.map(|entry| *entry))
+            .find(|(nh, _)| *nh == next_header)
+            .map(|(_, client)| client)
+            .or_else(|| self.client.map(|client| *client))
+    }
 }
 
 impl<'a> SixlowpanRxClient for IP6RecvStruct<'a> {
@@ -71,10 +108,10 @@ impl<'a> SixlowpanRxClient for IP6RecvStruct<'a> {
                     debug!("cksum fail!: {:?}", checksum_result);
                     return; //Dropped.
                 }
-                // Note: Protocols for which checksum verification is not implemented (TCP, etc.)
+                // Note: Protocols for which checksum verification is not implemented
                 // are automatically assumed as fine, rather than dropped
 
-                self.client
+                self.client_for(ip6_header.get_next_header())
                     .map(|client| client.receive(ip6_header, &buf[offset..len]));
             }
             None => {
@@ -20,6 +20,8 @@ use crate::ieee802154::device::{MacDevice, TxClient};
 use crate::net::ieee802154::MacAddress;
 use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::ipv6::{IP6Header, IP6Packet, TransportHeader};
+use crate::net::ipv6::neighbor_cache::NeighborCache;
+use crate::net::network_interface::NetworkInterface;
 use crate::net::sixlowpan::sixlowpan_state::TxState;
 use core::cell::Cell;
 use kernel::common::cells::{OptionalCell, TakeCell};
@@ -37,9 +39,9 @@ pub trait IP6SendClient {
 }
 
 /// This trait provides a basic IPv6 sending interface. It exposes basic
-/// configuration information for the IPv6 layer (setting the source address,
-/// setting the gateway MAC address), as well as a way to send an IPv6
-/// packet.
+/// configuration information for the IPv6 layer (setting the gateway MAC
+/// address), as well as a way to send an IPv6 packet. The source address of
+/// each packet is selected by the network interface of the sender.
 pub trait IP6Sender<'a> {
     /// This method sets the `IP6SendClient` for the `IP6Sender` instance, which
     /// receives the `send_done` callback when transmission has finished.
@@ -49,16 +51,9 @@ pub trait IP6Sender<'a> {
     /// `send_done` callback
     fn set_client(&self, client: &'a dyn IP6SendClient);
 
-    /// This method sets the source address for packets sent from the
-    /// `IP6Sender` instance.
-    ///
-    /// # Arguments
-    /// `src_addr` - `IPAddr` to set as the source address for packets sent
-    /// from this instance of `IP6Sender`
-    fn set_addr(&self, src_addr: IPAddr);
-
     /// This method sets the gateway/next hop MAC address for this `IP6Sender`
-    /// instance.
+    /// instance. Packets are sent to it if the neighbor cache cannot resolve
+    /// their destination, or if there is no neighbor cache.
     ///
     /// # Arguments
     /// `gateway` - MAC address to send the constructed packet to
@@ -72,7 +67,9 @@ pub trait IP6Sender<'a> {
     fn set_header(&mut self, ip6_header: IP6Header);
 
     /// This method sends the provided transport header and payload to the
-    /// given destination IP address
+    /// given destination IP address. Returns EINVAL if the interface has no
+    /// address to send it from, and ESIZE if the packet is larger than the
+    /// MTU of the interface.
     ///
     /// # Arguments
     /// `dst` - IPv6 address to send the packet to
@@ -94,13 +91,12 @@ pub struct IP6SendStruct<'a, A: time::Alarm<'a>> {
     alarm: &'a A, // Alarm so we can introduce a small delay between fragments to ensure
     // successful reception on receivers with slow copies out of the radio buffer
     // (imix)
-    src_addr: Cell<IPAddr>,
     gateway: Cell<MacAddress>,
     tx_buf: TakeCell<'static, [u8]>,
     sixlowpan: TxState<'a>,
     radio: &'a dyn MacDevice<'a>,
-    dst_mac_addr: MacAddress,
-    src_mac_addr: MacAddress,
+    interface: &'a NetworkInterface,
+    neighbor_cache: OptionalCell<&'a NeighborCache>,
     client: OptionalCell<&'a dyn IP6SendClient>,
 }
 
@@ -109,10 +105,6 @@ impl<A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         self.client.set(client);
     }
 
-    fn set_addr(&self, src_addr: IPAddr) {
-        self.src_addr.set(src_addr);
-    }
-
     fn set_gateway(&self, gateway: MacAddress) {
         self.gateway.set(gateway);
     }
@@ -128,19 +120,32 @@ impl<A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         transport_header: TransportHeader,
         payload: &LeasableBuffer<'static, u8>,
     ) -> ReturnCode {
+        let src_addr = match self.interface.select_src_addr(&dst) {
+            Some(src_addr) => src_addr,
+            None => return ReturnCode::EINVAL,
+        };
+        let ret = self.init_packet(src_addr, dst, transport_header, payload);
+        if ret != ReturnCode::SUCCESS {
+            return ret;
+        }
+        let dst_mac_addr = self
+            .neighbor_cache
+            .map_or(None, |cache| cache.resolve(dst))
+            .unwrap_or(self.gateway.get());
         self.sixlowpan.init(
-            self.src_mac_addr,
-            self.dst_mac_addr,
+            self.interface.get_mac_addr(),
+            dst_mac_addr,
             self.radio.get_pan(),
             None,
         );
-        self.init_packet(dst, transport_header, payload);
         let ret = self.send_next_fragment();
         ret
     }
 }
 
 impl<A: time::Alarm<'a>> IP6SendStruct<'a, A> {
+    /// `dst_mac_addr` is the initial gateway, see `IP6Sender::set_gateway()`.
+    /// Packets are sent from the addresses of `interface`.
     pub fn new(
         ip6_packet: &'static mut IP6Packet<'static>,
         alarm: &'a A,
@@ -148,40 +153,51 @@ impl<A: time::Alarm<'a>> IP6SendStruct<'a, A> {
         sixlowpan: TxState<'a>,
         radio: &'a dyn MacDevice<'a>,
         dst_mac_addr: MacAddress,
-        src_mac_addr: MacAddress,
+        interface: &'a NetworkInterface,
     ) -> IP6SendStruct<'a, A> {
         IP6SendStruct {
             ip6_packet: TakeCell::new(ip6_packet),
             alarm: alarm,
-            src_addr: Cell::new(IPAddr::new()),
             gateway: Cell::new(dst_mac_addr),
             tx_buf: TakeCell::new(tx_buf),
             sixlowpan: sixlowpan,
             radio: radio,
-            dst_mac_addr: dst_mac_addr,
-            src_mac_addr: src_mac_addr,
+            interface: interface,
+            neighbor_cache: OptionalCell::empty(),
             client: OptionalCell::empty(),
         }
     }
 
+    /// Sets the neighbor cache used to find the MAC address each packet is
+    /// sent to.
+    pub fn set_neighbor_cache(&self, neighbor_cache: &'a NeighborCache) {
+        self.neighbor_cache.set(neighbor_cache);
+    }
+
     fn init_packet(
         &self,
+        src_addr: IPAddr,
         dst_addr: IPAddr,
         transport_header: TransportHeader,
         payload: &LeasableBuffer<'static, u8>,
-    ) {
+    ) -> ReturnCode {
         self.ip6_packet.map_or_else(
             || {
                 debug!("init packet failed.");
+                ReturnCode::EBUSY
             },
             |ip6_packet| {
                 ip6_packet.header = IP6Header::default();
-                ip6_packet.header.src_addr = self.src_addr.get();
+                ip6_packet.header.src_addr = src_addr;
                 ip6_packet.header.dst_addr = dst_addr;
                 ip6_packet.set_payload(transport_header, payload);
+                if ip6_packet.get_total_len() as usize > self.interface.get_mtu() {
+                    return ReturnCode::ESIZE;
+                }
                 ip6_packet.set_transport_checksum();
+                ReturnCode::SUCCESS
             },
-        );
+        )
     }
 
     // Returns EBUSY if the tx_buf is not there
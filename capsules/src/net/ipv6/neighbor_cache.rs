@@ -0,0 +1,152 @@
+//! This file contains the neighbor cache, which maps the IPv6 addresses of
+//! the neighbors of this node to their link-layer (802.15.4) addresses, and
+//! remembers the default router. The `IP6SendStruct` uses it to pick the MAC
+//! address a packet is sent to, and neighbor discovery fills it.
+//!
+//! Following 6LoWPAN-ND (RFC 6775), addresses are resolved without sending
+//! Neighbor Solicitations:
+//!
+//! - Multicast addresses are sent to the 802.15.4 broadcast address.
+//! - Addresses with an entry in the cache are sent to the address in it.
+//! - Link-local addresses whose interface identifier is derived from a MAC
+//!   address are sent to that address.
+//! - Everything else is sent to the default router.
+//!
+//! The cache has a fixed number of entries. When it is full, a new neighbor
+//! replaces the entries in turn, except for the default router. Entries do not
+//! expire: neighbor discovery replaces the default router when it stops
+//! answering.
+
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::IPAddr;
+use core::cell::Cell;
+use kernel::common::cells::TakeCell;
+
+/// The 802.15.4 broadcast address, which multicast packets are sent to.
+const BROADCAST_MAC_ADDR: MacAddress = MacAddress::Short(0xffff);
+
+#[derive(Copy, Clone)]
+pub struct Neighbor {
+    pub ip_addr: IPAddr,
+    pub mac_addr: MacAddress,
+}
+
+pub struct NeighborCache {
+    entries: TakeCell<'static, [Option<Neighbor>]>,
+    default_router: Cell<Option<IPAddr>>,
+    // The entry replaced next when the cache is full
+    next_victim: Cell<usize>,
+}
+
+impl NeighborCache {
+    pub fn new(entries: &'static mut [Option<Neighbor>]) -> NeighborCache {
+        NeighborCache {
+            entries: TakeCell::new(entries),
+            default_router: Cell::new(None),
+            next_victim: Cell::new(0),
+        }
+    }
+
+    /// Adds a neighbor, or updates its link-layer address if it is in the
+    /// cache already.
+    pub fn insert(&self, ip_addr: IPAddr, mac_addr: MacAddress) {
+        let router = self.default_router.get();
+        self.entries.map(|entries| {
+            let neighbor = Some(Neighbor { ip_addr, mac_addr });
+            if let Some(entry) = entries
+                .iter_mut()
+                .find(|entry| entry.map_or(false, |n| n.ip_addr == ip_addr))
+            {
+                *entry = neighbor;
+            } else if let Some(entry) = entries.iter_mut().find(|entry| entry.is_none()) {
+                *entry = neighbor;
+            } else {
+                let len = entries.len();
+                let victim = (0..len)
+                    .map(|i| (self.next_victim.get() + i) % len)
+                    .find(|&i| entries[i].map_or(true, |n| Some(n.ip_addr) != router));
+                if let Some(victim) = victim {
+                    entries[victim] = neighbor;
+                    self.next_victim.set((victim + 1) % len);
+                }
+            }
+        });
+    }
+
+    pub fn remove(&self, ip_addr: IPAddr) {
+        self.entries.map(|entries| {
+            entries
+                .iter_mut()
+                .filter(|entry| entry.map_or(false, |n| n.ip_addr == ip_addr))
+                .for_each(|entry| *entry = None);
+        });
+        if self.default_router.get() == Some(ip_addr) {
+            self.default_router.set(None);
+        }
+    }
+
+    /// Returns the link-layer address of a neighbor in the cache.
+    pub fn lookup(&self, ip_addr: IPAddr) -> Option<MacAddress> {
+        self.entries
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|entry| *entry)
+                    .find(|n| n.ip_addr == ip_addr)
+                    .map(|n| n.mac_addr)
+            })
+            .unwrap_or(None)
+    }
+
+    /// Sets the router packets to other networks are sent to, and adds it
+    /// to the cache.
+    pub fn set_default_router(&self, ip_addr: IPAddr, mac_addr: MacAddress) {
+        self.default_router.set(Some(ip_addr));
+        self.insert(ip_addr, mac_addr);
+    }
+
+    /// Forgets the default router, which stays in the cache as a neighbor.
+    pub fn clear_default_router(&self) {
+        self.default_router.set(None);
+    }
+
+    pub fn get_default_router(&self) -> Option<IPAddr> {
+        self.default_router.get()
+    }
+
+    /// Returns the link-layer address a packet to `dst_addr` is sent to, or
+    /// `None` if the address is off-link and there is no default router.
+    pub fn resolve(&self, dst_addr: IPAddr) -> Option<MacAddress> {
+        if dst_addr.is_multicast() {
+            return Some(BROADCAST_MAC_ADDR);
+        }
+        self.lookup(dst_addr)
+            .or_else(|| {
+                if dst_addr.is_unicast_link_local() {
+                    Some(mac_from_iid(&dst_addr))
+                } else {
+                    None
+                }
+            })
+            .or_else(|| {
+                self.default_router
+                    .get()
+                    .and_then(|router| self.lookup(router))
+            })
+    }
+}
+
+/// Returns the MAC address an interface identifier was derived from, as
+/// done by `IPAddr::generate_from_mac()`. Identifiers that are not derived
+/// from a short address are taken to be derived from an extended address.
+pub fn mac_from_iid(ip_addr: &IPAddr) -> MacAddress {
+    let iid = &ip_addr.0[8..16];
+    if iid[..6] == [0x00, 0x00, 0x00, 0xff, 0xfe, 0x00] {
+        MacAddress::Short((iid[6] as u16) << 8 | iid[7] as u16)
+    } else {
+        let mut long_addr = [0; 8];
+        long_addr.copy_from_slice(iid);
+        long_addr[0] ^= 0b00000010;
+        MacAddress::Long(long_addr)
+    }
+}
@@ -0,0 +1,243 @@
+//! This file contains the network interface, which holds the configuration
+//! of the IPv6 stack on top of one MAC device: the IPv6 addresses of the
+//! node, the prefix of the network, the link-layer address and the MTU.
+//!
+//! The layers of the stack consult the interface instead of keeping their own
+//! copy of this configuration, so it can change at runtime:
+//!
+//! - The `IP6SendStruct` selects the source address of each packet with
+//!   `select_src_addr`, sends frames from the link-layer address and refuses
+//!   packets larger than the MTU.
+//! - The 6LoWPAN layer drops the packets the interface does not `accept`.
+//! - The UDP driver lists the addresses to apps, and only lets them bind to
+//!   the addresses of the interface.
+//! - The ICMPv6 layer answers the messages sent to the addresses of the
+//!   interface, and registers them with the router.
+//!
+//! Addresses are added and removed with `add_address` and `remove_address`,
+//! for example from the `ip` command of the process console. `set_prefix`
+//! configures the address formed from a prefix and the link-layer address,
+//! as done by stateless address autoconfiguration (SLAAC, RFC 4862) when
+//! Neighbor Discovery receives a prefix from the router. The client of the
+//! interface is told when an address is removed, so that the UDP driver can
+//! unbind the sockets bound to it.
+//!
+//! Changing the link-layer address of the interface does not change the
+//! address of the radio, which is configured through the `MacDevice`.
+
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::IPAddr;
+use core::cell::Cell;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::ReturnCode;
+
+/// The minimum MTU of IPv6 links (RFC 8200), which 6LoWPAN provides by
+/// fragmenting packets.
+pub const IPV6_MIN_MTU: usize = 1280;
+
+/// Implemented by users of the addresses of the interface, to learn when
+/// one is removed.
+pub trait NetworkInterfaceClient {
+    fn address_removed(&self, addr: IPAddr);
+}
+
+pub struct NetworkInterface {
+    addresses: TakeCell<'static, [Option<IPAddr>]>,
+    prefix: Cell<Option<(IPAddr, u8)>>,
+    mac_addr: Cell<MacAddress>,
+    mtu: Cell<usize>,
+    client: OptionalCell<&'static dyn NetworkInterfaceClient>,
+}
+
+/// Returns the number of leading bits two addresses have in common.
+fn common_prefix_len(a: &IPAddr, b: &IPAddr) -> u32 {
+    let mut len = 0;
+    for (x, y) in a.0.iter().zip(b.0.iter()) {
+        let diff = (x ^ y).leading_zeros();
+        len += diff;
+        if diff < 8 {
+            break;
+        }
+    }
+    len
+}
+
+/// Returns whether packets to `addr` stay on the link, so they should be sent
+/// from a link-local address.
+fn is_link_scope(addr: &IPAddr) -> bool {
+    addr.is_unicast_link_local() || (addr.is_multicast() && (addr.0[1] & 0x0f) <= 2)
+}
+
+impl NetworkInterface {
+    /// `addresses` holds the addresses of the interface, and its length is
+    /// the largest number of addresses the interface can have. The entries
+    /// that are `Some` are the initial addresses.
+    pub fn new(
+        addresses: &'static mut [Option<IPAddr>],
+        mac_addr: MacAddress,
+        mtu: usize,
+    ) -> NetworkInterface {
+        NetworkInterface {
+            addresses: TakeCell::new(addresses),
+            prefix: Cell::new(None),
+            mac_addr: Cell::new(mac_addr),
+            mtu: Cell::new(mtu),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'static dyn NetworkInterfaceClient) {
+        self.client.set(client);
+    }
+
+    /// Adds an address to the interface. Returns EINVAL for multicast and
+    /// unspecified addresses, EALREADY if the interface has the address
+    /// already, and ENOMEM if it has as many addresses as it can hold.
+    pub fn add_address(&self, addr: IPAddr) -> ReturnCode {
+        if addr.is_multicast() || addr.is_unspecified() {
+            return ReturnCode::EINVAL;
+        }
+        self.addresses
+            .map(|addresses| {
+                if addresses.iter().any(|entry| *entry == Some(addr)) {
+                    return ReturnCode::EALREADY;
+                }
+                match addresses.iter_mut().find(|entry| entry.is_none()) {
+                    Some(entry) => {
+                        *entry = Some(addr);
+                        ReturnCode::SUCCESS
+                    }
+                    None => ReturnCode::ENOMEM,
+                }
+            })
+            .unwrap_or(ReturnCode::FAIL)
+    }
+
+    /// Removes an address from the interface, and tells the client. Returns
+    /// EINVAL if the interface does not have the address.
+    pub fn remove_address(&self, addr: IPAddr) -> ReturnCode {
+        let result = self
+            .addresses
+            .map(
+                |addresses| match addresses.iter_mut().find(|entry| **entry == Some(addr)) {
+                    Some(entry) => {
+                        *entry = None;
+                        ReturnCode::SUCCESS
+                    }
+                    None => ReturnCode::EINVAL,
+                },
+            )
+            .unwrap_or(ReturnCode::FAIL);
+        if result == ReturnCode::SUCCESS {
+            self.client.map(|client| client.address_removed(addr));
+        }
+        result
+    }
+
+    pub fn has_address(&self, addr: &IPAddr) -> bool {
+        self.addresses.map_or(false, |addresses| {
+            addresses.iter().any(|entry| entry.as_ref() == Some(addr))
+        })
+    }
+
+    /// Returns the number of addresses of the interface.
+    pub fn address_count(&self) -> usize {
+        self.addresses.map_or(0, |addresses| {
+            addresses.iter().filter(|entry| entry.is_some()).count()
+        })
+    }
+
+    /// Returns the address at `index`, counting from 0 up to
+    /// `address_count()`. Removing an address moves the addresses after it
+    /// down by one.
+    pub fn get_address(&self, index: usize) -> Option<IPAddr> {
+        self.addresses
+            .map(|addresses| addresses.iter().filter_map(|entry| *entry).nth(index))
+            .unwrap_or(None)
+    }
+
+    /// Returns whether packets sent to `dst_addr` are for this node: they are
+    /// sent to one of the addresses of the interface or to a multicast
+    /// address.
+    pub fn accepts(&self, dst_addr: &IPAddr) -> bool {
+        dst_addr.is_multicast() || self.has_address(dst_addr)
+    }
+
+    /// Selects the source address of a packet to `dst_addr`, following a
+    /// subset of the rules of RFC 6724: the destination itself if it is an
+    /// address of the interface, then an address of the same scope, then the
+    /// address sharing the longest prefix with the destination. Returns
+    /// `None` if the interface has no address.
+    pub fn select_src_addr(&self, dst_addr: &IPAddr) -> Option<IPAddr> {
+        let link_scope = is_link_scope(dst_addr);
+        self.addresses
+            .map(|addresses| {
+                // Reversed, so the first of equally good addresses is selected
+                addresses
+                    .iter()
+                    .rev()
+                    .filter_map(|entry| *entry)
+                    .max_by_key(|addr| {
+                        (
+                            addr == dst_addr,
+                            addr.is_unicast_link_local() == link_scope,
+                            common_prefix_len(addr, dst_addr),
+                        )
+                    })
+            })
+            .unwrap_or(None)
+    }
+
+    /// Sets the prefix of the network, and replaces the address formed from
+    /// the previous prefix and the link-layer address with the one formed
+    /// from this prefix. Returns the result of adding the new address.
+    pub fn set_prefix(&self, prefix: IPAddr, prefix_len: u8) -> ReturnCode {
+        if prefix_len > 64 {
+            // The last 64 bits are the interface identifier
+            return ReturnCode::EINVAL;
+        }
+        self.clear_prefix();
+        self.prefix.set(Some((prefix, prefix_len)));
+        self.add_address(self.slaac_address(prefix, prefix_len))
+    }
+
+    /// Forgets the prefix of the network, and removes the address formed from
+    /// it.
+    pub fn clear_prefix(&self) {
+        if let Some((prefix, prefix_len)) = self.prefix.take() {
+            self.remove_address(self.slaac_address(prefix, prefix_len));
+        }
+    }
+
+    /// Returns the prefix of the network and its length in bits.
+    pub fn get_prefix(&self) -> Option<(IPAddr, u8)> {
+        self.prefix.get()
+    }
+
+    fn slaac_address(&self, prefix: IPAddr, prefix_len: u8) -> IPAddr {
+        let mut addr = IPAddr::generate_from_mac(self.mac_addr.get());
+        for byte in addr.0[..8].iter_mut() {
+            *byte = 0;
+        }
+        addr.set_prefix(&prefix.0, prefix_len);
+        addr
+    }
+
+    pub fn get_mac_addr(&self) -> MacAddress {
+        self.mac_addr.get()
+    }
+
+    pub fn set_mac_addr(&self, mac_addr: MacAddress) {
+        self.mac_addr.set(mac_addr);
+    }
+
+    /// Returns the largest IPv6 packet, headers included, that can be sent
+    /// over the interface.
+    pub fn get_mtu(&self) -> usize {
+        self.mtu.get()
+    }
+
+    pub fn set_mtu(&self, mtu: usize) {
+        self.mtu.set(mtu);
+    }
+}
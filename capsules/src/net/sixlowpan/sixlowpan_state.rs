@@ -228,7 +228,9 @@ use crate::ieee802154::device::{MacDevice, RxClient};
 use crate::ieee802154::framer::Frame;
 use crate::net::frag_utils::Bitmap;
 use crate::net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
+use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::ipv6::IP6Packet;
+use crate::net::network_interface::NetworkInterface;
 use crate::net::sixlowpan::sixlowpan_compression;
 use crate::net::sixlowpan::sixlowpan_compression::{is_lowpan, ContextStore};
 use crate::net::util::{network_slice_to_u16, u16_to_network_slice};
@@ -244,6 +246,9 @@ use kernel::ReturnCode;
 // Reassembly timeout in seconds
 const FRAG_TIMEOUT: u32 = 60;
 
+// Offset of the destination address in an IPv6 header
+const IP6_DST_ADDR_OFFSET: usize = 24;
+
 /// Objects that implement this trait can set themselves to be the client
 /// for the [Sixlowpan](struct.Sixlowpan.html) struct, and will then receive
 /// a callback once an IPv6 packet has been fully reassembled.
@@ -300,6 +305,7 @@ pub trait SixlowpanState<'a> {
     fn get_ctx_store(&self) -> &dyn ContextStore;
     fn add_rx_state(&self, rx_state: &'a RxState<'a>);
     fn set_rx_client(&'a self, client: &'a dyn SixlowpanRxClient);
+    fn set_interface(&self, interface: &'a NetworkInterface);
 }
 
 /// Tracks the compression state for a single IPv6 packet.
@@ -753,6 +759,21 @@ impl RxState<'a> {
         }
     }
 
+    // Returns the destination address of the reassembled packet
+    fn dst_addr(&self) -> Option<IPAddr> {
+        let end = IP6_DST_ADDR_OFFSET + 16;
+        if (self.dgram_size.get() as usize) < end {
+            return None;
+        }
+        self.packet.map(|packet| {
+            let mut dst_addr = IPAddr::new();
+            dst_addr
+                .0
+                .copy_from_slice(&packet[IP6_DST_ADDR_OFFSET..end]);
+            dst_addr
+        })
+    }
+
     fn end_receive(&self, client: Option<&'a dyn SixlowpanRxClient>, result: ReturnCode) {
         self.busy.set(false);
         self.bitmap.map(|bitmap| bitmap.clear());
@@ -782,12 +803,14 @@ impl RxState<'a> {
 /// packets concurrently.
 ///
 /// Finally, `set_client` controls the client that will receive transmission
-/// completion and reception callbacks.
+/// completion and reception callbacks. If a network interface is set with
+/// `set_interface`, only the packets it accepts are passed to the client.
 pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
     pub ctx_store: C,
     clock: &'a A,
     tx_dgram_tag: Cell<u16>,
     rx_client: Cell<Option<&'a dyn SixlowpanRxClient>>,
+    interface: Cell<Option<&'a NetworkInterface>>,
 
     // Receive state
     rx_states: List<'a, RxState<'a>>,
@@ -811,7 +834,14 @@ impl<A: time::Alarm<'a>, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
         );
         // Reception completed if rx_state is not None. Note that this can
         // also occur for some fail states (e.g. dropping an invalid packet)
-        rx_state.map(|state| state.end_receive(self.rx_client.get(), returncode));
+        rx_state.map(|state| {
+            if returncode == ReturnCode::SUCCESS && !self.is_for_interface(state) {
+                // Packets for other nodes are dropped without a callback
+                state.end_receive(None, ReturnCode::FAIL);
+            } else {
+                state.end_receive(self.rx_client.get(), returncode);
+            }
+        });
     }
 }
 
@@ -844,6 +874,12 @@ impl<A: time::Alarm<'a>, C: ContextStore> SixlowpanState<'a> for Sixlowpan<'a, A
     fn set_rx_client(&'a self, client: &'a dyn SixlowpanRxClient) {
         self.rx_client.set(Some(client));
     }
+
+    /// Sets the network interface that decides which of the received packets
+    /// are for this node.
+    fn set_interface(&self, interface: &'a NetworkInterface) {
+        self.interface.set(Some(interface));
+    }
 }
 
 impl<A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
@@ -866,11 +902,20 @@ impl<A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             clock: clock,
             tx_dgram_tag: Cell::new(0),
             rx_client: Cell::new(None),
+            interface: Cell::new(None),
 
             rx_states: List::new(),
         }
     }
 
+    fn is_for_interface(&self, rx_state: &RxState<'a>) -> bool {
+        self.interface.get().map_or(true, |interface| {
+            rx_state
+                .dst_addr()
+                .map_or(false, |dst_addr| interface.accepts(&dst_addr))
+        })
+    }
+
     fn receive_frame(
         &self,
         packet: &[u8],
@@ -0,0 +1,486 @@
+//! TCP userspace interface.
+//!
+//! Implements a userspace interface for opening TCP connections, sending and
+//! receiving data on them and closing them, on top of the `TCPStack`. Each
+//! process can have one connection and one listening socket at a time. The
+//! driver has a fixed number of sockets, which it hands out to processes when
+//! they open a connection, listen or accept a connection, and takes back once
+//! the socket is closed, together with a buffer for the data in flight on
+//! each.
+//!
+//! Addresses are passed in the config buffer in the same layout as for the
+//! UDP driver: 16 bytes of IPv6 address followed by the port in host byte
+//! order.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::tcp::tcp_socket::{TCPClient, TCPSocket, TCPStack, TCPState};
+use crate::net::util::host_slice_to_u16;
+use core::cell::Cell;
+use core::mem;
+use kernel::common::cells::MapCell;
+use kernel::common::leasable_buffer::LeasableBuffer;
+use kernel::{
+    AppId, AppSlice, Callback, Driver, Grant, GrantCleanupClient, ReadOnlyAppSlice, ReturnCode,
+    Shared,
+};
+
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tcp as usize;
+
+/// Size of an address and port in the config buffer.
+const ENDPOINT_LEN: usize = mem::size_of::<IPAddr>() + 2;
+
+/// The events passed to the connection callback.
+mod event {
+    pub const CONNECTED: usize = 0;
+    pub const REMOTE_CLOSED: usize = 1;
+    pub const CLOSED: usize = 2;
+}
+
+/// A socket of the driver, with the buffer for the data it sends and the
+/// process that uses it.
+pub struct TCPDriverSocket<'a> {
+    socket: TCPSocket<'a>,
+    buffer: MapCell<LeasableBuffer<'static, u8>>,
+    appid: Cell<Option<AppId>>,
+}
+
+impl<'a> TCPDriverSocket<'a> {
+    /// `id` must be the index of the socket in the slice passed to the
+    /// driver, and `buffer` must hold the largest segment of the stack.
+    pub fn new(id: usize, buffer: &'static mut [u8]) -> TCPDriverSocket<'a> {
+        TCPDriverSocket {
+            socket: TCPSocket::new(id),
+            buffer: MapCell::new(LeasableBuffer::new(buffer)),
+            appid: Cell::new(None),
+        }
+    }
+
+    pub fn get_socket(&'a self) -> &'a TCPSocket<'a> {
+        &self.socket
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    rx_callback: Option<Callback>,
+    tx_callback: Option<Callback>,
+    connection_callback: Option<Callback>,
+    app_read: Option<AppSlice<Shared, u8>>,
+    app_write: Option<ReadOnlyAppSlice<u8>>,
+    app_cfg: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct TCPDriver<'a> {
+    tcp: &'a dyn TCPStack<'a>,
+
+    /// Grant of apps that use this driver.
+    apps: Grant<App>,
+
+    /// The sockets processes can use, indexed by their id.
+    sockets: &'a [TCPDriverSocket<'a>],
+}
+
+impl<'a> TCPDriver<'a> {
+    /// The sockets must have been added to the stack, with this driver as
+    /// their client.
+    pub fn new(
+        tcp: &'a dyn TCPStack<'a>,
+        grant: Grant<App>,
+        sockets: &'a [TCPDriverSocket<'a>],
+    ) -> TCPDriver<'a> {
+        TCPDriver {
+            tcp: tcp,
+            apps: grant,
+            sockets: sockets,
+        }
+    }
+
+    /// Utility function to perform an action on an app in a system call.
+    #[inline]
+    fn do_with_app<F>(&self, appid: AppId, closure: F) -> ReturnCode
+    where
+        F: FnOnce(&mut App) -> ReturnCode,
+    {
+        self.apps
+            .enter(appid, |app, _| closure(app))
+            .unwrap_or_else(|err| err.into())
+    }
+
+    /// Utility function to perform an action on the app that uses a socket.
+    fn do_with_socket_app<F>(&self, socket_id: usize, closure: F)
+    where
+        F: FnOnce(&mut App),
+    {
+        self.sockets[socket_id].appid.get().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| closure(app));
+        });
+    }
+
+    /// Returns the socket of the connection of the app, if any.
+    fn socket_of(&self, appid: AppId) -> Option<&'a TCPDriverSocket<'a>> {
+        self.sockets
+            .iter()
+            .find(|s| s.appid.get() == Some(appid) && s.socket.get_state() != TCPState::Listen)
+    }
+
+    /// Returns the listening socket of the app, if any.
+    fn listener_of(&self, appid: AppId) -> Option<&'a TCPDriverSocket<'a>> {
+        self.sockets
+            .iter()
+            .find(|s| s.appid.get() == Some(appid) && s.socket.get_state() == TCPState::Listen)
+    }
+
+    /// Give the app a free socket.
+    fn allocate_socket(&self, appid: AppId) -> Option<&'a TCPDriverSocket<'a>> {
+        self.sockets
+            .iter()
+            .find(|s| s.appid.get().is_none())
+            .map(|s| {
+                s.appid.set(Some(appid));
+                s
+            })
+    }
+
+    /// Take the socket back from the app once its connection is closed.
+    fn free_socket(&self, socket_id: usize) {
+        self.sockets[socket_id].appid.set(None);
+    }
+
+    fn notify_connection(&self, socket_id: usize, event: usize, result: ReturnCode) {
+        self.do_with_socket_app(socket_id, |app| {
+            app.connection_callback
+                .map(|mut cb| cb.schedule(event, result.into(), 0));
+        });
+    }
+
+    #[inline]
+    fn parse_endpoint(&self, buf: &[u8]) -> Option<(IPAddr, u16)> {
+        if buf.len() != ENDPOINT_LEN {
+            None
+        } else {
+            let (a, p) = buf.split_at(mem::size_of::<IPAddr>());
+            let mut addr = IPAddr::new();
+            addr.0.copy_from_slice(a);
+            Some((addr, host_slice_to_u16(p)))
+        }
+    }
+
+    fn send(&self, appid: AppId, app: &mut App) -> ReturnCode {
+        let s = match self.socket_of(appid) {
+            Some(s) => s,
+            None => return ReturnCode::EINVAL,
+        };
+        let payload = match app.app_write {
+            Some(ref payload) => payload,
+            None => return ReturnCode::EINVAL,
+        };
+        if payload.len() > self.tcp.max_segment_size() {
+            return ReturnCode::ESIZE;
+        }
+        // The buffer is with the stack until the previous data is acknowledged
+        s.buffer.take().map_or(ReturnCode::EBUSY, |mut buf| {
+            buf[..payload.len()].copy_from_slice(payload.as_ref());
+            buf.slice(0..payload.len());
+            match self.tcp.send(&s.socket, buf) {
+                Ok(()) => ReturnCode::SUCCESS,
+                Err(mut buf) => {
+                    buf.reset();
+                    s.buffer.replace(buf);
+                    ReturnCode::FAIL
+                }
+            }
+        })
+    }
+
+    fn abort(&self, socket_id: usize) {
+        let s = &self.sockets[socket_id];
+        if let Some(mut buf) = self.tcp.abort(&s.socket) {
+            buf.reset();
+            s.buffer.replace(buf);
+        }
+        self.free_socket(socket_id);
+    }
+}
+
+impl<'a> Driver for TCPDriver<'a> {
+    /// Setup buffers to read/write from.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Read buffer. The data of the next received segment is written
+    ///        to it, and the buffer is then released: it must be shared again
+    ///        to receive more data.
+    /// - `1`: Write buffer. Contains the data to send. It can also be shared
+    ///        with `allow_readonly`.
+    /// - `2`: Config buffer. Contains the address and port to connect to, or
+    ///        receives the address and port of the peer.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        match allow_num {
+            0 | 2 => self.do_with_app(appid, |app| {
+                match allow_num {
+                    0 => app.app_read = slice,
+                    _ => app.app_cfg = slice,
+                }
+                ReturnCode::SUCCESS
+            }),
+            1 => self.allow_readonly(appid, allow_num, slice.map(ReadOnlyAppSlice::from)),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Setup read-only buffers, which may be in flash.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `1`: Write buffer. Contains the data to send.
+    fn allow_readonly(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<ReadOnlyAppSlice<u8>>,
+    ) -> ReturnCode {
+        match allow_num {
+            1 => self.do_with_app(appid, |app| {
+                app.app_write = slice;
+                ReturnCode::SUCCESS
+            }),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Setup callback for when data is received into the read buffer.
+    ///        The callback receives the length of the data.
+    /// - `1`: Setup callback for when the peer acknowledged the sent data, so
+    ///        that the next data can be sent. The callback receives the
+    ///        result, which is an error if the connection was lost first.
+    /// - `2`: Setup callback for the events of the connection. The callback
+    ///        receives the event and a result: `0` when the connection was
+    ///        established or failed to open, `1` when the peer closed
+    ///        its side, and `2` when the connection was closed, with `SUCCESS`
+    ///        after an orderly close, `ECANCEL` after a reset and `ENOACK`
+    ///        if the peer stopped answering.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        callback: Option<Callback>,
+        app_id: AppId,
+    ) -> ReturnCode {
+        self.do_with_app(app_id, |app| {
+            match subscribe_num {
+                0 => app.rx_callback = callback,
+                1 => app.tx_callback = callback,
+                2 => app.connection_callback = callback,
+                _ => return ReturnCode::ENOSUPPORT,
+            }
+            ReturnCode::SUCCESS
+        })
+    }
+
+    /// TCP control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Connect to the address and port in the config buffer. The
+    ///        connection callback tells when the connection is established.
+    ///        Returns ENOMEM if all sockets are in use, and EBUSY if the
+    ///        process already has a connection.
+    /// - `2`: Listen for connections on port `arg1`. While the process has
+    ///        no connection, the next peer that connects gets a free socket,
+    ///        and the connection callback tells when the connection is
+    ///        established. Other peers are refused. Returns EBUSY if the port
+    ///        is in use or the process is already listening.
+    /// - `3`: Send the data in the write buffer. Returns EBUSY if previously
+    ///        sent data has not been acknowledged yet, and ESIZE if the data
+    ///        does not fit in a segment.
+    /// - `4`: Close the connection once the sent data has been acknowledged.
+    /// - `5`: Reset the connection and close it immediately.
+    /// - `6`: Write the address and port of the peer to the config buffer.
+    /// - `7`: Returns the maximum amount of data that can be sent at once.
+    /// - `8`: Stop listening. Does not close the connection of the process.
+    fn command(&self, command_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+
+            1 => self.do_with_app(appid, |app| {
+                let endpoint = app
+                    .app_cfg
+                    .as_ref()
+                    .and_then(|cfg| self.parse_endpoint(cfg.as_ref()));
+                let (addr, port) = match endpoint {
+                    Some(endpoint) => endpoint,
+                    None => return ReturnCode::EINVAL,
+                };
+                if self.socket_of(appid).is_some() {
+                    return ReturnCode::EBUSY;
+                }
+                match self.allocate_socket(appid) {
+                    Some(s) => {
+                        let result = self.tcp.connect(&s.socket, addr, port);
+                        if result != ReturnCode::SUCCESS && s.socket.get_state() == TCPState::Closed
+                        {
+                            self.free_socket(s.socket.get_id());
+                        }
+                        result
+                    }
+                    None => ReturnCode::ENOMEM,
+                }
+            }),
+
+            2 if self.listener_of(appid).is_some() => ReturnCode::EBUSY,
+            2 => match self.allocate_socket(appid) {
+                Some(s) => {
+                    let result = self.tcp.listen(&s.socket, arg1 as u16);
+                    if result != ReturnCode::SUCCESS && s.socket.get_state() == TCPState::Closed {
+                        self.free_socket(s.socket.get_id());
+                    }
+                    result
+                }
+                None => ReturnCode::ENOMEM,
+            },
+
+            3 => self.do_with_app(appid, |app| self.send(appid, app)),
+
+            4 => match self.socket_of(appid) {
+                Some(s) => {
+                    let result = self.tcp.close(&s.socket);
+                    if s.socket.get_state() == TCPState::Closed {
+                        self.free_socket(s.socket.get_id());
+                    }
+                    result
+                }
+                None => ReturnCode::EALREADY,
+            },
+
+            5 => match self.socket_of(appid) {
+                Some(s) => {
+                    self.abort(s.socket.get_id());
+                    ReturnCode::SUCCESS
+                }
+                None => ReturnCode::EALREADY,
+            },
+
+            6 => self.do_with_app(appid, |app| {
+                let (addr, port) = match self.socket_of(appid) {
+                    Some(s) => s.socket.get_remote(),
+                    None => return ReturnCode::EINVAL,
+                };
+                app.app_cfg.as_mut().map_or(ReturnCode::EINVAL, |cfg| {
+                    if cfg.len() != ENDPOINT_LEN {
+                        return ReturnCode::EINVAL;
+                    }
+                    let cfg = cfg.as_mut();
+                    cfg[..mem::size_of::<IPAddr>()].copy_from_slice(&addr.0);
+                    cfg[mem::size_of::<IPAddr>()] = port as u8;
+                    cfg[mem::size_of::<IPAddr>() + 1] = (port >> 8) as u8;
+                    ReturnCode::SUCCESS
+                })
+            }),
+
+            7 => ReturnCode::SuccessWithValue {
+                value: self.tcp.max_segment_size(),
+            },
+
+            8 => match self.listener_of(appid) {
+                Some(s) => {
+                    self.tcp.close(&s.socket);
+                    self.free_socket(s.socket.get_id());
+                    ReturnCode::SUCCESS
+                }
+                None => ReturnCode::EALREADY,
+            },
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
+
+impl<'a> TCPClient for TCPDriver<'a> {
+    fn accept(&self, listener_id: usize, socket_id: usize) -> bool {
+        let appid = match self.sockets[listener_id].appid.get() {
+            Some(appid) => appid,
+            None => return false,
+        };
+        // Each process has one connection at a time
+        let s = &self.sockets[socket_id];
+        if s.appid.get().is_some() || self.socket_of(appid).is_some() {
+            return false;
+        }
+        s.appid.set(Some(appid));
+        true
+    }
+
+    fn connected(&self, socket_id: usize, result: ReturnCode) {
+        self.notify_connection(socket_id, event::CONNECTED, result);
+        if result != ReturnCode::SUCCESS {
+            self.free_socket(socket_id);
+        }
+    }
+
+    fn receive(&self, socket_id: usize, payload: &[u8]) -> bool {
+        let mut accepted = false;
+        self.do_with_socket_app(socket_id, |app| {
+            // The read buffer is released after each segment, so the app can
+            // process the data before it shares the buffer again.
+            let fits = app
+                .app_read
+                .as_ref()
+                .map_or(false, |rbuf| rbuf.len() >= payload.len());
+            if fits {
+                app.app_read.take().map(|mut rbuf| {
+                    rbuf.as_mut()[..payload.len()].copy_from_slice(payload);
+                });
+                app.rx_callback
+                    .map(|mut cb| cb.schedule(payload.len(), 0, 0));
+                accepted = true;
+            }
+        });
+        accepted
+    }
+
+    fn send_done(
+        &self,
+        socket_id: usize,
+        result: ReturnCode,
+        mut buf: LeasableBuffer<'static, u8>,
+    ) {
+        buf.reset();
+        self.sockets[socket_id].buffer.replace(buf);
+        self.do_with_socket_app(socket_id, |app| {
+            app.tx_callback
+                .map(|mut cb| cb.schedule(result.into(), 0, 0));
+        });
+    }
+
+    fn remote_closed(&self, socket_id: usize) {
+        self.notify_connection(socket_id, event::REMOTE_CLOSED, ReturnCode::SUCCESS);
+    }
+
+    fn closed(&self, socket_id: usize, result: ReturnCode) {
+        self.notify_connection(socket_id, event::CLOSED, result);
+        self.free_socket(socket_id);
+    }
+}
+
+impl<'a> GrantCleanupClient for TCPDriver<'a> {
+    fn process_teardown(&self, appid: AppId) {
+        // Reset the connection and stop the listening socket of a process
+        // that is gone, so that its sockets can be used by other processes.
+        if let Some(s) = self.socket_of(appid) {
+            self.abort(s.socket.get_id());
+        }
+        if let Some(s) = self.listener_of(appid) {
+            self.abort(s.socket.get_id());
+        }
+    }
+}
@@ -0,0 +1,1451 @@
+//! This file contains the definition and implementation of a minimal TCP layer
+//! on top of the `IP6Sender` and `IP6Receiver` interfaces. The
+//! [TCPStack](trait.TCPStack.html) trait provides an interface for kernel
+//! capsules to open, use and close connections, and the
+//! [TCPClient](trait.TCPClient.html) trait is implemented by them to receive
+//! data and the events of their connections.
+//!
+//! Each connection is a [TCPSocket](struct.TCPSocket.html), which capsules
+//! allocate statically and add to the [MuxTcp](struct.MuxTcp.html). The
+//! `MuxTcp` is the TCP client of the IP layer, and runs the TCP state machine
+//! of every socket. The userspace driver uses the same interface, with one
+//! socket for each connection that apps can open at a time.
+//!
+//! The implementation is kept small for constrained devices:
+//!
+//! - Each connection has a window of a single segment in both directions. A
+//!   socket only sends its next segment once the previous one has been
+//!   acknowledged, and it advertises a window of one segment. Segments that
+//!   arrive out of order are dropped, and the peer retransmits them.
+//! - Unacknowledged SYNs, data and FINs are retransmitted when a virtual
+//!   alarm fires, with an exponential backoff. A connection is aborted after
+//!   `MAX_RETRANSMISSIONS` retransmissions, or if the peer does not close its
+//!   side within `FIN_WAIT_2_MS` after we closed ours.
+//! - Initial sequence numbers are generated as in RFC 6528, from a clock and
+//!   a keyed hash of the connection. The key is drawn from an RNG when the
+//!   mux is started, and no connection can be opened before.
+//! - A listening socket keeps listening: each peer that connects gets
+//!   another closed socket of the same client, which the client picks with
+//!   `TCPClient::accept()`.
+//! - TCP options are not sent and are ignored on reception, so no MSS is
+//!   negotiated. Urgent data and simultaneous opens are not supported.
+//! - Connections are not matched on the local address, and the source
+//!   address of sent segments is the one of the `IP6Sender`.
+
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6::{IP6Header, TransportHeader};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::tcp::tcp::{tcp_flags, TCPHeader};
+use crate::sha256::hmac_sha256;
+use core::cell::Cell;
+use core::cmp;
+use kernel::common::cells::{MapCell, OptionalCell};
+use kernel::common::leasable_buffer::LeasableBuffer;
+use kernel::common::{List, ListLink, ListNode};
+use kernel::hil::rng;
+use kernel::hil::time::{self, Frequency};
+use kernel::ReturnCode;
+
+/// Initial retransmission timeout, which doubles with every retransmission.
+const INITIAL_RTO_MS: u32 = 1000;
+
+/// How often an unacknowledged segment is retransmitted before the
+/// connection is aborted.
+const MAX_RETRANSMISSIONS: u32 = 5;
+
+/// How long a closed connection stays in the TIME-WAIT state. This is much
+/// shorter than the 2 MSL of RFC 793, so that sockets can be reused quickly.
+const TIME_WAIT_MS: u32 = 2000;
+
+/// How long a connection waits in the FIN-WAIT-2 state for the peer to close
+/// its side, before it is aborted.
+const FIN_WAIT_2_MS: u32 = 60000;
+
+/// Length of the secret key of the initial sequence numbers.
+const ISN_SECRET_LEN: usize = 16;
+
+/// The first port used for active opens.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// The states of a TCP connection, from RFC 793.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TCPState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Users of the `TCPStack` implement this trait to receive the data and the
+/// events of their sockets. Each callback is passed the id of the socket it
+/// is for, which the user chose when creating the socket.
+pub trait TCPClient {
+    /// Called when a peer connects to the listening socket `listener_id`, to
+    /// ask whether the closed socket `socket_id` of the same client can take
+    /// the connection. The client is asked for each of its closed sockets
+    /// until it returns `true`, and the connection is refused if it never
+    /// does.
+    fn accept(&self, listener_id: usize, socket_id: usize) -> bool;
+
+    /// Called when a connection has been established, or with an error if
+    /// it could not be opened: `ECANCEL` if the peer refused or reset the
+    /// connection, and `ENOACK` if it did not answer.
+    fn connected(&self, socket_id: usize, result: ReturnCode);
+
+    /// Called with the data of each segment received in order. If the client
+    /// cannot take the data now, it returns `false`: the segment is not
+    /// acknowledged and the peer sends it again later.
+    fn receive(&self, socket_id: usize, payload: &[u8]) -> bool;
+
+    /// Called when the peer acknowledged the data passed to `send()`, or with
+    /// an error if the connection was lost first. Returns the buffer.
+    fn send_done(&self, socket_id: usize, result: ReturnCode, buf: LeasableBuffer<'static, u8>);
+
+    /// Called when the peer closed its side of the connection, so no more
+    /// data will be received. The socket can still send data until it is
+    /// closed.
+    fn remote_closed(&self, socket_id: usize);
+
+    /// Called when the connection is closed: with `SUCCESS` after both sides
+    /// closed it, `ECANCEL` if the peer reset it, and `ENOACK` if the peer
+    /// stopped acknowledging segments or did not close its side in time.
+    fn closed(&self, socket_id: usize, result: ReturnCode);
+}
+
+/// This trait provides the operations on TCP connections. All of them take
+/// the socket of the connection, which must have been added to the stack.
+pub trait TCPStack<'a> {
+    /// Wait for connections on `port` (passive open). For each peer that
+    /// connects, the client picks a socket for the connection with
+    /// `accept()`, and is told with `connected()` once the connection is
+    /// established on it. The socket listens until it is closed.
+    fn listen(&self, socket: &'a TCPSocket<'a>, port: u16) -> ReturnCode;
+
+    /// Open a connection to `port` at `addr` from an ephemeral port (active
+    /// open). The client is told with `connected()` once the connection is
+    /// established or failed. Returns `EOFF` if the stack has not been
+    /// started yet.
+    fn connect(&self, socket: &'a TCPSocket<'a>, addr: IPAddr, port: u16) -> ReturnCode;
+
+    /// Send the data in `buf`, which must fit in one segment. Only one send
+    /// can be outstanding on a socket: the buffer is returned by
+    /// `send_done()` once the peer acknowledged it. Returns the buffer if it
+    /// cannot be sent.
+    fn send(
+        &self,
+        socket: &'a TCPSocket<'a>,
+        buf: LeasableBuffer<'static, u8>,
+    ) -> Result<(), LeasableBuffer<'static, u8>>;
+
+    /// Close the connection once any outstanding data has been acknowledged.
+    /// The client is told with `closed()` once both sides closed it. A
+    /// socket that is listening or connecting is closed immediately, with no
+    /// callback. Closing a listening socket does not close the connections
+    /// it accepted.
+    fn close(&self, socket: &'a TCPSocket<'a>) -> ReturnCode;
+
+    /// Reset the connection and close the socket immediately, with no
+    /// callback. Returns the buffer of an outstanding send, if any.
+    fn abort(&self, socket: &'a TCPSocket<'a>) -> Option<LeasableBuffer<'static, u8>>;
+
+    /// The largest payload that `send()` accepts.
+    fn max_segment_size(&self) -> usize;
+}
+
+/// The state of one TCP connection. Sockets are added to a `MuxTcp` with
+/// `MuxTcp::add_socket()`, and are then used through its `TCPStack`
+/// interface.
+pub struct TCPSocket<'a> {
+    id: usize,
+    client: OptionalCell<&'a dyn TCPClient>,
+    next: ListLink<'a, TCPSocket<'a>>,
+    state: Cell<TCPState>,
+    local_port: Cell<u16>,
+    remote_addr: Cell<IPAddr>,
+    remote_port: Cell<u16>,
+    // Oldest unacknowledged and next sequence number to send. With a window
+    // of one segment, anything between the two is sent as a whole.
+    snd_una: Cell<u32>,
+    snd_nxt: Cell<u32>,
+    // Next sequence number expected from the peer
+    rcv_nxt: Cell<u32>,
+    tx_buffer: MapCell<LeasableBuffer<'static, u8>>,
+    // `close()` was called while data was outstanding
+    fin_queued: Cell<bool>,
+    // A segment is waiting for the IP sender
+    output: Cell<bool>,
+    // Deadline of the retransmission, FIN-WAIT-2 or TIME-WAIT timer, in
+    // alarm ticks
+    deadline: Cell<Option<u32>>,
+    retransmissions: Cell<u32>,
+}
+
+impl<'a> ListNode<'a, TCPSocket<'a>> for TCPSocket<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, TCPSocket<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> TCPSocket<'a> {
+    /// Creates a closed socket. `id` is passed to the callbacks of the
+    /// client, to tell the sockets of a client apart.
+    pub fn new(id: usize) -> TCPSocket<'a> {
+        TCPSocket {
+            id,
+            client: OptionalCell::empty(),
+            next: ListLink::empty(),
+            state: Cell::new(TCPState::Closed),
+            local_port: Cell::new(0),
+            remote_addr: Cell::new(IPAddr::new()),
+            remote_port: Cell::new(0),
+            snd_una: Cell::new(0),
+            snd_nxt: Cell::new(0),
+            rcv_nxt: Cell::new(0),
+            tx_buffer: MapCell::empty(),
+            fin_queued: Cell::new(false),
+            output: Cell::new(false),
+            deadline: Cell::new(None),
+            retransmissions: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn TCPClient) {
+        self.client.set(client);
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_state(&self) -> TCPState {
+        self.state.get()
+    }
+
+    pub fn get_local_port(&self) -> u16 {
+        self.local_port.get()
+    }
+
+    /// The address and port of the peer of an open connection.
+    pub fn get_remote(&self) -> (IPAddr, u16) {
+        (self.remote_addr.get(), self.remote_port.get())
+    }
+
+    // Is a SYN, data or FIN waiting to be acknowledged?
+    fn is_outstanding(&self) -> bool {
+        self.snd_una.get() != self.snd_nxt.get()
+    }
+
+    fn is_connection(&self, remote_addr: IPAddr, remote_port: u16, local_port: u16) -> bool {
+        match self.state.get() {
+            TCPState::Closed | TCPState::Listen => false,
+            _ => {
+                self.local_port.get() == local_port
+                    && self.remote_port.get() == remote_port
+                    && self.remote_addr.get() == remote_addr
+            }
+        }
+    }
+
+    fn has_client(&self, client: &dyn TCPClient) -> bool {
+        let client_ptr = client as *const dyn TCPClient as *const ();
+        self.client.map_or(false, |own_client| {
+            *own_client as *const dyn TCPClient as *const () == client_ptr
+        })
+    }
+
+    fn reset_state(&self) {
+        self.state.set(TCPState::Closed);
+        self.fin_queued.set(false);
+        self.output.set(false);
+        self.deadline.set(None);
+        self.retransmissions.set(0);
+    }
+}
+
+/// A reset to send for a segment that does not belong to any connection.
+#[derive(Copy, Clone)]
+struct Reset {
+    addr: IPAddr,
+    header: TCPHeader,
+}
+
+/// This struct implements the TCP state machine for the sockets added to it,
+/// and multiplexes their segments over a single `IP6Sender`. It must be set
+/// as the client of the `IP6Sender`, as the `ip6_nh::TCP` client of the
+/// `IP6Receiver`, and as the client of its alarm and its RNG, and then be
+/// started with `start()`.
+pub struct MuxTcp<'a, A: time::Alarm<'a>> {
+    sockets: List<'a, TCPSocket<'a>>,
+    ip_sender: &'a dyn IP6Sender<'a>,
+    alarm: &'a A,
+    rng: &'a dyn rng::Rng<'a>,
+    // Key of the hash in the initial sequence numbers, from the RNG
+    secret: OptionalCell<[u8; ISN_SECRET_LEN]>,
+    max_segment_size: usize,
+    // A segment is being sent by the IP sender
+    sending: Cell<bool>,
+    reset: OptionalCell<Reset>,
+    next_port: Cell<u16>,
+}
+
+impl<'a, A: time::Alarm<'a>> MuxTcp<'a, A> {
+    /// `max_segment_size` is the largest payload of a segment, which must
+    /// fit in the payload buffer of the `IP6Sender` with a TCP header.
+    pub fn new(
+        ip_sender: &'a dyn IP6Sender<'a>,
+        alarm: &'a A,
+        rng: &'a dyn rng::Rng<'a>,
+        max_segment_size: usize,
+    ) -> MuxTcp<'a, A> {
+        MuxTcp {
+            sockets: List::new(),
+            ip_sender,
+            alarm,
+            rng,
+            secret: OptionalCell::empty(),
+            max_segment_size,
+            sending: Cell::new(false),
+            reset: OptionalCell::empty(),
+            next_port: Cell::new(FIRST_EPHEMERAL_PORT),
+        }
+    }
+
+    /// Draw the secret of the initial sequence numbers from the RNG.
+    /// Connections can only be opened once it is available.
+    pub fn start(&self) -> ReturnCode {
+        if self.secret.is_some() {
+            return ReturnCode::EALREADY;
+        }
+        self.rng.get()
+    }
+
+    pub fn add_socket(&self, socket: &'a TCPSocket<'a>) {
+        self.sockets.push_tail(socket);
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.sockets
+            .iter()
+            .any(|socket| socket.state.get() != TCPState::Closed && socket.local_port.get() == port)
+    }
+
+    // Connections are matched before listeners, so a port can have a
+    // listener as well as connections.
+    fn is_listening(&self, port: u16) -> bool {
+        self.sockets
+            .iter()
+            .any(|socket| socket.state.get() == TCPState::Listen && socket.local_port.get() == port)
+    }
+
+    fn ephemeral_port(&self) -> u16 {
+        loop {
+            let port = self.next_port.get();
+            self.next_port
+                .set(port.checked_add(1).unwrap_or(FIRST_EPHEMERAL_PORT));
+            if !self.port_in_use(port) {
+                return port;
+            }
+        }
+    }
+
+    // The initial sequence number of a new connection, as in RFC 6528: a
+    // timer that ticks every 4 microseconds, plus a keyed hash of the
+    // connection, so that off-path attackers cannot predict it. Returns None
+    // until the secret key is available.
+    fn initial_sequence_number(&self, socket: &TCPSocket) -> Option<u32> {
+        self.secret.map(|secret| {
+            let digest = hmac_sha256(
+                &secret[..],
+                &[
+                    &socket.remote_addr.get().0,
+                    &socket.remote_port.get().to_be_bytes(),
+                    &socket.local_port.get().to_be_bytes(),
+                ],
+            );
+            let hash = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+            let timer =
+                u64::from(self.alarm.now()) * 250_000 / u64::from(<A::Frequency>::frequency());
+            (timer as u32).wrapping_add(hash)
+        })
+    }
+
+    fn ms_to_ticks(ms: u32) -> u32 {
+        ms * (<A::Frequency>::frequency() / 1000)
+    }
+
+    fn start_timer(&self, socket: &TCPSocket, ms: u32) {
+        let deadline = self.alarm.now().wrapping_add(Self::ms_to_ticks(ms));
+        socket.deadline.set(Some(deadline));
+        self.rearm_timer();
+    }
+
+    fn stop_timer(&self, socket: &TCPSocket) {
+        socket.deadline.set(None);
+        self.rearm_timer();
+    }
+
+    // Set the alarm for the earliest deadline of all sockets
+    fn rearm_timer(&self) {
+        let now = self.alarm.now();
+        let next = self
+            .sockets
+            .iter()
+            .filter_map(|socket| socket.deadline.get())
+            .min_by_key(|deadline| {
+                if Self::has_expired(*deadline, now) {
+                    0
+                } else {
+                    deadline.wrapping_sub(now)
+                }
+            });
+        match next {
+            Some(deadline) => {
+                // Fire as soon as possible for deadlines that have passed
+                if Self::has_expired(deadline, now) {
+                    self.alarm.set_alarm(now.wrapping_add(1));
+                } else {
+                    self.alarm.set_alarm(deadline);
+                }
+            }
+            None => self.alarm.disable(),
+        }
+    }
+
+    fn has_expired(deadline: u32, now: u32) -> bool {
+        (now.wrapping_sub(deadline) as i32) >= 0
+    }
+
+    // Start the retransmission timer for a new SYN, data or FIN, and send it
+    fn send_outstanding(&self, socket: &TCPSocket) {
+        socket.retransmissions.set(0);
+        self.start_timer(socket, INITIAL_RTO_MS);
+        self.queue_output(socket);
+    }
+
+    fn send_fin(&self, socket: &TCPSocket) {
+        socket.fin_queued.set(false);
+        socket.snd_nxt.set(socket.snd_una.get().wrapping_add(1));
+        match socket.state.get() {
+            TCPState::Established => socket.state.set(TCPState::FinWait1),
+            _ => socket.state.set(TCPState::LastAck),
+        }
+        self.send_outstanding(socket);
+    }
+
+    fn queue_output(&self, socket: &TCPSocket) {
+        socket.output.set(true);
+        self.transmit_next();
+    }
+
+    // Send the pending reset, or the segment of the next socket with output,
+    // if the IP sender is idle.
+    fn transmit_next(&self) {
+        if self.sending.get() {
+            return;
+        }
+        if let Some(reset) = self.reset.take() {
+            self.transmit(reset.addr, reset.header, None);
+            return;
+        }
+        if let Some(socket) = self.sockets.iter().find(|socket| socket.output.get()) {
+            socket.output.set(false);
+            self.transmit_segment(socket);
+        }
+    }
+
+    // Send the segment of `socket` that its state calls for: any outstanding
+    // SYN, data or FIN, or else an acknowledgement.
+    fn transmit_segment(&self, socket: &TCPSocket) {
+        let outstanding = socket.is_outstanding();
+        let (flags, seq_num, with_data) = match socket.state.get() {
+            TCPState::Closed | TCPState::Listen => return,
+            TCPState::SynSent => (tcp_flags::SYN, socket.snd_una.get(), false),
+            TCPState::SynReceived => (tcp_flags::SYN | tcp_flags::ACK, socket.snd_una.get(), false),
+            TCPState::Established | TCPState::CloseWait if outstanding => {
+                (tcp_flags::ACK | tcp_flags::PSH, socket.snd_una.get(), true)
+            }
+            TCPState::FinWait1 | TCPState::Closing | TCPState::LastAck if outstanding => {
+                (tcp_flags::FIN | tcp_flags::ACK, socket.snd_una.get(), false)
+            }
+            _ => (tcp_flags::ACK, socket.snd_nxt.get(), false),
+        };
+
+        let mut header = TCPHeader::new();
+        header.set_src_port(socket.local_port.get());
+        header.set_dst_port(socket.remote_port.get());
+        header.set_seq_num(seq_num);
+        if flags & tcp_flags::ACK != 0 {
+            header.set_ack_num(socket.rcv_nxt.get());
+        }
+        header.set_flags(flags);
+        header.set_window(cmp::min(self.max_segment_size, u16::max_value() as usize) as u16);
+
+        if with_data {
+            socket.tx_buffer.map(|buf| {
+                self.transmit(socket.remote_addr.get(), header, Some(buf));
+            });
+        } else {
+            self.transmit(socket.remote_addr.get(), header, None);
+        }
+    }
+
+    fn transmit(
+        &self,
+        addr: IPAddr,
+        header: TCPHeader,
+        payload: Option<&LeasableBuffer<'static, u8>>,
+    ) {
+        // The IP sender copies the payload before it returns, and may call
+        // `send_done()` before that.
+        self.sending.set(true);
+        let result = match payload {
+            Some(buf) => self
+                .ip_sender
+                .send_to(addr, TransportHeader::TCP(header), buf),
+            None => self.ip_sender.send_to(
+                addr,
+                TransportHeader::TCP(header),
+                &LeasableBuffer::new(&mut []),
+            ),
+        };
+        if result != ReturnCode::SUCCESS {
+            // Anything outstanding is sent again when the timer fires
+            self.sending.set(false);
+        }
+    }
+
+    // Reply to a segment that does not belong to a connection with a reset,
+    // as in RFC 793.
+    fn queue_reset(&self, addr: IPAddr, segment: &TCPHeader, payload_len: usize) {
+        if segment.has_flags(tcp_flags::RST) {
+            return;
+        }
+        let mut header = TCPHeader::new();
+        header.set_src_port(segment.get_dst_port());
+        header.set_dst_port(segment.get_src_port());
+        if segment.has_flags(tcp_flags::ACK) {
+            header.set_seq_num(segment.get_ack_num());
+            header.set_flags(tcp_flags::RST);
+        } else {
+            let mut len = payload_len as u32;
+            if segment.has_flags(tcp_flags::SYN) {
+                len += 1;
+            }
+            if segment.has_flags(tcp_flags::FIN) {
+                len += 1;
+            }
+            header.set_ack_num(segment.get_seq_num().wrapping_add(len));
+            header.set_flags(tcp_flags::RST | tcp_flags::ACK);
+        }
+        self.reset.set(Reset { addr, header });
+        self.transmit_next();
+    }
+
+    // Reset the connection of `socket` on the peer
+    fn queue_connection_reset(&self, socket: &TCPSocket) {
+        let mut header = TCPHeader::new();
+        header.set_src_port(socket.local_port.get());
+        header.set_dst_port(socket.remote_port.get());
+        header.set_seq_num(socket.snd_nxt.get());
+        header.set_flags(tcp_flags::RST);
+        self.reset.set(Reset {
+            addr: socket.remote_addr.get(),
+            header: header,
+        });
+        self.transmit_next();
+    }
+
+    // Close the connection of `socket` after an error or a reset, and tell
+    // its client.
+    fn drop_connection(&self, socket: &TCPSocket, result: ReturnCode) {
+        let state = socket.state.get();
+        socket.reset_state();
+        self.rearm_timer();
+        let buf = socket.tx_buffer.take();
+        socket.client.map(|client| {
+            if let Some(buf) = buf {
+                client.send_done(socket.id, result, buf);
+            }
+            match state {
+                TCPState::SynSent | TCPState::SynReceived => client.connected(socket.id, result),
+                _ => client.closed(socket.id, result),
+            }
+        });
+    }
+
+    fn retransmission_timeout(&self, socket: &TCPSocket) {
+        match socket.state.get() {
+            TCPState::TimeWait => {
+                socket.reset_state();
+                socket
+                    .client
+                    .map(|client| client.closed(socket.id, ReturnCode::SUCCESS));
+            }
+            TCPState::FinWait2 => {
+                // The peer never closed its side of the connection
+                self.queue_connection_reset(socket);
+                self.drop_connection(socket, ReturnCode::ENOACK);
+            }
+            _ if socket.is_outstanding() => {
+                let retransmissions = socket.retransmissions.get();
+                if retransmissions >= MAX_RETRANSMISSIONS {
+                    self.queue_connection_reset(socket);
+                    self.drop_connection(socket, ReturnCode::ENOACK);
+                } else {
+                    socket.retransmissions.set(retransmissions + 1);
+                    self.start_timer(socket, INITIAL_RTO_MS << (retransmissions + 1));
+                    self.queue_output(socket);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Handle a segment for a listening socket. A SYN opens the connection on
+    // a closed socket that the client of the listener accepts, and the
+    // listener keeps listening.
+    fn listen_segment(&self, listener: &TCPSocket, addr: IPAddr, header: &TCPHeader) {
+        if header.has_flags(tcp_flags::RST) {
+            return;
+        }
+        if header.has_flags(tcp_flags::ACK) {
+            self.queue_reset(addr, header, 0);
+            return;
+        }
+        if !header.has_flags(tcp_flags::SYN) || self.secret.is_none() {
+            // Without a secret, the peer retransmits the SYN later
+            return;
+        }
+        let socket = listener.client.and_then(|client| {
+            self.sockets.iter().find(|socket| {
+                socket.state.get() == TCPState::Closed
+                    && socket.has_client(client)
+                    && client.accept(listener.id, socket.id)
+            })
+        });
+        let socket = match socket {
+            Some(socket) => socket,
+            None => {
+                // Refuse the connection
+                self.queue_reset(addr, header, 0);
+                return;
+            }
+        };
+        socket.local_port.set(listener.local_port.get());
+        socket.remote_addr.set(addr);
+        socket.remote_port.set(header.get_src_port());
+        let iss = self.initial_sequence_number(socket).unwrap_or(0);
+        socket.rcv_nxt.set(header.get_seq_num().wrapping_add(1));
+        socket.snd_una.set(iss);
+        socket.snd_nxt.set(iss.wrapping_add(1));
+        socket.state.set(TCPState::SynReceived);
+        self.send_outstanding(socket);
+    }
+
+    // Handle a segment for a socket that sent a SYN
+    fn syn_sent_segment(&self, socket: &TCPSocket, addr: IPAddr, header: &TCPHeader) {
+        let has_ack = header.has_flags(tcp_flags::ACK);
+        if has_ack && header.get_ack_num() != socket.snd_nxt.get() {
+            self.queue_reset(addr, header, 0);
+            return;
+        }
+        if header.has_flags(tcp_flags::RST) {
+            if has_ack {
+                // Connection refused
+                self.drop_connection(socket, ReturnCode::ECANCEL);
+            }
+            return;
+        }
+        if has_ack && header.has_flags(tcp_flags::SYN) {
+            socket.rcv_nxt.set(header.get_seq_num().wrapping_add(1));
+            socket.snd_una.set(header.get_ack_num());
+            socket.state.set(TCPState::Established);
+            self.stop_timer(socket);
+            self.queue_output(socket);
+            socket
+                .client
+                .map(|client| client.connected(socket.id, ReturnCode::SUCCESS));
+        }
+    }
+
+    // Handle an acknowledgement for the outstanding SYN, data or FIN
+    fn acknowledged(&self, socket: &TCPSocket) {
+        socket.snd_una.set(socket.snd_nxt.get());
+        socket.retransmissions.set(0);
+        self.stop_timer(socket);
+        match socket.state.get() {
+            TCPState::SynReceived => {
+                socket.state.set(TCPState::Established);
+                socket
+                    .client
+                    .map(|client| client.connected(socket.id, ReturnCode::SUCCESS));
+            }
+            TCPState::Established | TCPState::CloseWait => {
+                let buf = socket.tx_buffer.take();
+                if let Some(buf) = buf {
+                    socket
+                        .client
+                        .map(|client| client.send_done(socket.id, ReturnCode::SUCCESS, buf));
+                }
+                if socket.fin_queued.get() {
+                    self.send_fin(socket);
+                }
+            }
+            TCPState::FinWait1 => {
+                socket.state.set(TCPState::FinWait2);
+                self.start_timer(socket, FIN_WAIT_2_MS);
+            }
+            TCPState::Closing => {
+                socket.state.set(TCPState::TimeWait);
+                self.start_timer(socket, TIME_WAIT_MS);
+            }
+            TCPState::LastAck => {
+                socket.reset_state();
+                socket
+                    .client
+                    .map(|client| client.closed(socket.id, ReturnCode::SUCCESS));
+            }
+            _ => {}
+        }
+    }
+
+    // Handle a segment for a socket in one of the synchronized states
+    fn connection_segment(
+        &self,
+        socket: &TCPSocket,
+        addr: IPAddr,
+        header: &TCPHeader,
+        payload: &[u8],
+    ) {
+        if header.get_seq_num() != socket.rcv_nxt.get() {
+            // Out of order or a retransmission: tell the peer what is
+            // expected next.
+            if !header.has_flags(tcp_flags::RST) {
+                self.queue_output(socket);
+                if socket.state.get() == TCPState::TimeWait && header.has_flags(tcp_flags::FIN) {
+                    // Our acknowledgement of the FIN was lost, so wait
+                    // another TIME-WAIT for the peer to receive this one.
+                    self.start_timer(socket, TIME_WAIT_MS);
+                }
+            }
+            return;
+        }
+        if header.has_flags(tcp_flags::RST) {
+            self.drop_connection(socket, ReturnCode::ECANCEL);
+            return;
+        }
+        if header.has_flags(tcp_flags::SYN) {
+            self.queue_reset(addr, header, payload.len());
+            self.drop_connection(socket, ReturnCode::ECANCEL);
+            return;
+        }
+        if !header.has_flags(tcp_flags::ACK) {
+            return;
+        }
+
+        // With a window of one segment, only the whole outstanding SYN, data
+        // or FIN can be acknowledged.
+        if socket.is_outstanding() && header.get_ack_num() == socket.snd_nxt.get() {
+            self.acknowledged(socket);
+            if socket.state.get() == TCPState::Closed {
+                return;
+            }
+        }
+
+        let mut accepted = true;
+        if !payload.is_empty() {
+            match socket.state.get() {
+                TCPState::Established | TCPState::FinWait1 | TCPState::FinWait2 => {
+                    accepted = socket
+                        .client
+                        .map_or(true, |client| client.receive(socket.id, payload));
+                    if accepted {
+                        socket
+                            .rcv_nxt
+                            .set(socket.rcv_nxt.get().wrapping_add(payload.len() as u32));
+                    }
+                    self.queue_output(socket);
+                }
+                _ => {}
+            }
+        }
+
+        if accepted && header.has_flags(tcp_flags::FIN) {
+            socket.rcv_nxt.set(socket.rcv_nxt.get().wrapping_add(1));
+            self.queue_output(socket);
+            match socket.state.get() {
+                TCPState::Established => {
+                    socket.state.set(TCPState::CloseWait);
+                    socket.client.map(|client| client.remote_closed(socket.id));
+                }
+                TCPState::FinWait1 => socket.state.set(TCPState::Closing),
+                TCPState::FinWait2 => {
+                    // Replaces the FIN-WAIT-2 timer
+                    socket.state.set(TCPState::TimeWait);
+                    self.start_timer(socket, TIME_WAIT_MS);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TCPStack<'a> for MuxTcp<'a, A> {
+    fn listen(&self, socket: &'a TCPSocket<'a>, port: u16) -> ReturnCode {
+        if socket.state.get() != TCPState::Closed {
+            return ReturnCode::EBUSY;
+        }
+        if port == 0 {
+            return ReturnCode::EINVAL;
+        }
+        if self.is_listening(port) {
+            return ReturnCode::EBUSY;
+        }
+        socket.local_port.set(port);
+        socket.state.set(TCPState::Listen);
+        ReturnCode::SUCCESS
+    }
+
+    fn connect(&self, socket: &'a TCPSocket<'a>, addr: IPAddr, port: u16) -> ReturnCode {
+        if socket.state.get() != TCPState::Closed {
+            return ReturnCode::EBUSY;
+        }
+        if port == 0 {
+            return ReturnCode::EINVAL;
+        }
+        if self.secret.is_none() {
+            return ReturnCode::EOFF;
+        }
+        socket.local_port.set(self.ephemeral_port());
+        socket.remote_addr.set(addr);
+        socket.remote_port.set(port);
+        let iss = self.initial_sequence_number(socket).unwrap_or(0);
+        socket.snd_una.set(iss);
+        socket.snd_nxt.set(iss.wrapping_add(1));
+        socket.state.set(TCPState::SynSent);
+        self.send_outstanding(socket);
+        ReturnCode::SUCCESS
+    }
+
+    fn send(
+        &self,
+        socket: &'a TCPSocket<'a>,
+        buf: LeasableBuffer<'static, u8>,
+    ) -> Result<(), LeasableBuffer<'static, u8>> {
+        match socket.state.get() {
+            TCPState::Established | TCPState::CloseWait => {}
+            _ => return Err(buf),
+        }
+        if socket.is_outstanding()
+            || socket.fin_queued.get()
+            || buf.len() == 0
+            || buf.len() > self.max_segment_size
+        {
+            return Err(buf);
+        }
+        socket
+            .snd_nxt
+            .set(socket.snd_una.get().wrapping_add(buf.len() as u32));
+        socket.tx_buffer.replace(buf);
+        self.send_outstanding(socket);
+        Ok(())
+    }
+
+    fn close(&self, socket: &'a TCPSocket<'a>) -> ReturnCode {
+        match socket.state.get() {
+            TCPState::Closed => ReturnCode::EALREADY,
+            TCPState::Listen | TCPState::SynSent => {
+                socket.reset_state();
+                self.rearm_timer();
+                ReturnCode::SUCCESS
+            }
+            TCPState::SynReceived => {
+                // Nothing has been sent on the connection yet
+                self.abort(socket);
+                ReturnCode::SUCCESS
+            }
+            TCPState::Established | TCPState::CloseWait => {
+                if socket.is_outstanding() {
+                    socket.fin_queued.set(true);
+                } else {
+                    self.send_fin(socket);
+                }
+                ReturnCode::SUCCESS
+            }
+            _ => ReturnCode::EALREADY,
+        }
+    }
+
+    fn abort(&self, socket: &'a TCPSocket<'a>) -> Option<LeasableBuffer<'static, u8>> {
+        match socket.state.get() {
+            TCPState::Closed | TCPState::Listen | TCPState::SynSent | TCPState::TimeWait => {}
+            _ => self.queue_connection_reset(socket),
+        }
+        socket.reset_state();
+        self.rearm_timer();
+        socket.tx_buffer.take()
+    }
+
+    fn max_segment_size(&self) -> usize {
+        self.max_segment_size
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for MuxTcp<'a, A> {
+    fn send_done(&self, _result: ReturnCode) {
+        // Lost segments are retransmitted when the timer fires, or sent again
+        // by the peer.
+        self.sending.set(false);
+        self.transmit_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for MuxTcp<'a, A> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::TCP {
+            return;
+        }
+        let (offset, header) = match TCPHeader::decode(payload).done() {
+            Some(decoded) => decoded,
+            None => return,
+        };
+        let addr = ip_header.get_src_addr();
+        let data = &payload[offset..];
+
+        let connection = self.sockets.iter().find(|socket| {
+            socket.is_connection(addr, header.get_src_port(), header.get_dst_port())
+        });
+        let socket = connection.or_else(|| {
+            self.sockets.iter().find(|socket| {
+                socket.state.get() == TCPState::Listen
+                    && socket.local_port.get() == header.get_dst_port()
+            })
+        });
+        match socket {
+            Some(socket) => match socket.state.get() {
+                TCPState::Listen => self.listen_segment(socket, addr, &header),
+                TCPState::SynSent => self.syn_sent_segment(socket, addr, &header),
+                _ => self.connection_segment(socket, addr, &header, data),
+            },
+            None => self.queue_reset(addr, &header, data.len()),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> rng::Client for MuxTcp<'a, A> {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: ReturnCode,
+    ) -> rng::Continue {
+        if error != ReturnCode::SUCCESS {
+            return rng::Continue::More;
+        }
+        let mut secret = [0; ISN_SECRET_LEN];
+        let mut len = 0;
+        for (bytes, word) in secret.chunks_mut(4).zip(randomness) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+            len += bytes.len();
+        }
+        if len < ISN_SECRET_LEN {
+            return rng::Continue::More;
+        }
+        self.secret.set(secret);
+        rng::Continue::Done
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for MuxTcp<'a, A> {
+    fn fired(&self) {
+        let now = self.alarm.now();
+        for socket in self.sockets.iter() {
+            if let Some(deadline) = socket.deadline.get() {
+                if Self::has_expired(deadline, now) {
+                    socket.deadline.set(None);
+                    self.retransmission_timeout(socket);
+                }
+            }
+        }
+        self.rearm_timer();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::{MuxTcp, TCPClient, TCPSocket, TCPStack, TCPState, FIN_WAIT_2_MS, TIME_WAIT_MS};
+    use crate::net::ieee802154::MacAddress;
+    use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+    use crate::net::ipv6::ipv6::{IP6Header, TransportHeader};
+    use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+    use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+    use crate::net::tcp::tcp::{tcp_flags, TCPHeader, TCP_HDR_LEN};
+    use core::cell::Cell;
+    use kernel::common::leasable_buffer::LeasableBuffer;
+    use kernel::hil::rng::{self, Client};
+    use kernel::hil::time::{self, Alarm, AlarmClient, Freq1KHz, Time};
+    use kernel::ReturnCode;
+
+    const PEER: IPAddr = IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    const PEER_PORT: u16 = 1000;
+    const PEER_ISS: u32 = 5000;
+
+    struct MockAlarm {
+        now: Cell<u32>,
+        alarm: Cell<Option<u32>>,
+    }
+
+    impl Time for MockAlarm {
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+
+        fn max_tics(&self) -> u32 {
+            u32::max_value()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm(&self, tics: u32) {
+            self.alarm.set(Some(tics));
+        }
+
+        fn get_alarm(&self) -> u32 {
+            self.alarm.get().unwrap_or(0)
+        }
+
+        fn set_client(&'a self, _client: &'a dyn time::AlarmClient) {}
+
+        fn is_enabled(&self) -> bool {
+            self.alarm.get().is_some()
+        }
+
+        fn disable(&self) {
+            self.alarm.set(None);
+        }
+    }
+
+    struct MockRng;
+
+    impl<'a> rng::Rng<'a> for MockRng {
+        fn get(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+
+        fn cancel(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+
+        fn set_client(&'a self, _client: &'a dyn rng::Client) {}
+    }
+
+    // Keeps the last segment until the test takes it
+    struct MockSender {
+        segment: Cell<Option<(IPAddr, TCPHeader, usize)>>,
+    }
+
+    impl<'a> IP6Sender<'a> for MockSender {
+        fn set_client(&self, _client: &'a dyn IP6SendClient) {}
+
+        fn set_gateway(&self, _gateway: MacAddress) {}
+
+        fn set_header(&mut self, _ip6_header: IP6Header) {}
+
+        fn send_to(
+            &self,
+            dst: IPAddr,
+            transport_header: TransportHeader,
+            payload: &LeasableBuffer<'static, u8>,
+        ) -> ReturnCode {
+            match transport_header {
+                TransportHeader::TCP(header) => {
+                    self.segment.set(Some((dst, header, payload.len())));
+                    ReturnCode::SUCCESS
+                }
+                _ => ReturnCode::FAIL,
+            }
+        }
+    }
+
+    struct MockClient {
+        accept: Cell<bool>,
+        connected: Cell<Option<(usize, ReturnCode)>>,
+        received: Cell<usize>,
+        sent: Cell<Option<(usize, ReturnCode)>>,
+        remote_closed: Cell<bool>,
+        closed: Cell<Option<(usize, ReturnCode)>>,
+    }
+
+    impl MockClient {
+        fn new() -> MockClient {
+            MockClient {
+                accept: Cell::new(true),
+                connected: Cell::new(None),
+                received: Cell::new(0),
+                sent: Cell::new(None),
+                remote_closed: Cell::new(false),
+                closed: Cell::new(None),
+            }
+        }
+    }
+
+    impl TCPClient for MockClient {
+        fn accept(&self, _listener_id: usize, _socket_id: usize) -> bool {
+            self.accept.get()
+        }
+
+        fn connected(&self, socket_id: usize, result: ReturnCode) {
+            self.connected.set(Some((socket_id, result)));
+        }
+
+        fn receive(&self, _socket_id: usize, payload: &[u8]) -> bool {
+            self.received.set(self.received.get() + payload.len());
+            true
+        }
+
+        fn send_done(
+            &self,
+            socket_id: usize,
+            result: ReturnCode,
+            _buf: LeasableBuffer<'static, u8>,
+        ) {
+            self.sent.set(Some((socket_id, result)));
+        }
+
+        fn remote_closed(&self, _socket_id: usize) {
+            self.remote_closed.set(true);
+        }
+
+        fn closed(&self, socket_id: usize, result: ReturnCode) {
+            self.closed.set(Some((socket_id, result)));
+        }
+    }
+
+    fn new_alarm() -> MockAlarm {
+        MockAlarm {
+            now: Cell::new(1234),
+            alarm: Cell::new(None),
+        }
+    }
+
+    fn new_sender() -> MockSender {
+        MockSender {
+            segment: Cell::new(None),
+        }
+    }
+
+    fn set_secret(mux: &MuxTcp<MockAlarm>, seed: u32) {
+        let mut randomness = (0..4).map(|i| seed + i);
+        assert_eq!(
+            mux.randomness_available(&mut randomness, ReturnCode::SUCCESS),
+            rng::Continue::Done
+        );
+    }
+
+    // Take the segment sent by the mux, and let it send the next one
+    fn take_segment(
+        mux: &MuxTcp<MockAlarm>,
+        sender: &MockSender,
+    ) -> Option<(IPAddr, TCPHeader, usize)> {
+        let segment = sender.segment.take();
+        if segment.is_some() {
+            IP6SendClient::send_done(mux, ReturnCode::SUCCESS);
+        }
+        segment
+    }
+
+    fn deliver(mux: &MuxTcp<MockAlarm>, port: u16, seq: u32, ack: u32, flags: u8, data: &[u8]) {
+        deliver_from(mux, PEER_PORT, port, seq, ack, flags, data);
+    }
+
+    fn deliver_from(
+        mux: &MuxTcp<MockAlarm>,
+        peer_port: u16,
+        port: u16,
+        seq: u32,
+        ack: u32,
+        flags: u8,
+        data: &[u8],
+    ) {
+        let mut header = TCPHeader::new();
+        header.set_src_port(peer_port);
+        header.set_dst_port(port);
+        header.set_seq_num(seq);
+        header.set_ack_num(ack);
+        header.set_flags(flags);
+        let mut segment = [0; 64];
+        header.encode(&mut segment, 0).done().unwrap();
+        segment[TCP_HDR_LEN..TCP_HDR_LEN + data.len()].copy_from_slice(data);
+
+        let mut ip_header = IP6Header::new();
+        ip_header.src_addr = PEER;
+        ip_header.set_next_header(ip6_nh::TCP);
+        IP6RecvClient::receive(mux, ip_header, &segment[..TCP_HDR_LEN + data.len()]);
+    }
+
+    fn advance(mux: &MuxTcp<MockAlarm>, alarm: &MockAlarm, ms: u32) {
+        alarm.now.set(alarm.now.get().wrapping_add(ms));
+        if let Some(when) = alarm.alarm.get() {
+            if MuxTcp::<MockAlarm>::has_expired(when, alarm.now.get()) {
+                alarm.alarm.set(None);
+                mux.fired();
+            }
+        }
+    }
+
+    // Open a connection from `socket` to the peer, and return our initial
+    // sequence number.
+    fn open(mux: &MuxTcp<'a, MockAlarm>, sender: &MockSender, socket: &'a TCPSocket<'a>) -> u32 {
+        assert_eq!(mux.connect(socket, PEER, PEER_PORT), ReturnCode::SUCCESS);
+        let (addr, syn, _) = take_segment(mux, sender).unwrap();
+        assert!(addr == PEER);
+        assert_eq!(syn.get_flags(), tcp_flags::SYN);
+        let iss = syn.get_seq_num();
+        deliver(
+            mux,
+            socket.get_local_port(),
+            PEER_ISS,
+            iss.wrapping_add(1),
+            tcp_flags::SYN | tcp_flags::ACK,
+            &[],
+        );
+        let (_, ack, _) = take_segment(mux, sender).unwrap();
+        assert_eq!(ack.get_flags(), tcp_flags::ACK);
+        assert_eq!(ack.get_ack_num(), PEER_ISS + 1);
+        iss
+    }
+
+    #[test]
+    fn connect_needs_secret() {
+        let (alarm, sender, client) = (new_alarm(), new_sender(), MockClient::new());
+        let socket = TCPSocket::new(0);
+        let mux = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        socket.set_client(&client);
+        mux.add_socket(&socket);
+
+        assert_eq!(mux.connect(&socket, PEER, 80), ReturnCode::EOFF);
+        assert_eq!(socket.get_state(), TCPState::Closed);
+        set_secret(&mux, 1);
+        assert_eq!(mux.connect(&socket, PEER, 80), ReturnCode::SUCCESS);
+        assert_eq!(socket.get_state(), TCPState::SynSent);
+    }
+
+    #[test]
+    fn isn_depends_on_secret_and_connection() {
+        let (alarm, sender) = (new_alarm(), new_sender());
+        let (socket_a, socket_b, socket_c) =
+            (TCPSocket::new(0), TCPSocket::new(1), TCPSocket::new(2));
+        let mux_a = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        let mux_b = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        mux_a.add_socket(&socket_a);
+        mux_a.add_socket(&socket_c);
+        mux_b.add_socket(&socket_b);
+        set_secret(&mux_a, 1);
+        set_secret(&mux_b, 2);
+
+        // The same connection at the same time
+        mux_a.connect(&socket_a, PEER, 80);
+        let (_, syn_a, _) = take_segment(&mux_a, &sender).unwrap();
+        mux_b.connect(&socket_b, PEER, 80);
+        let (_, syn_b, _) = take_segment(&mux_b, &sender).unwrap();
+        assert_eq!(syn_a.get_src_port(), syn_b.get_src_port());
+        assert_ne!(syn_a.get_seq_num(), syn_b.get_seq_num());
+
+        // Another port with the same secret
+        mux_a.connect(&socket_c, PEER, 81);
+        let (_, syn_c, _) = take_segment(&mux_a, &sender).unwrap();
+        assert_ne!(syn_a.get_seq_num(), syn_c.get_seq_num());
+    }
+
+    #[test]
+    fn connect_and_exchange_data() {
+        let (alarm, sender, client) = (new_alarm(), new_sender(), MockClient::new());
+        let socket = TCPSocket::new(0);
+        let mux = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        socket.set_client(&client);
+        mux.add_socket(&socket);
+        set_secret(&mux, 1);
+
+        let iss = open(&mux, &sender, &socket);
+        assert_eq!(socket.get_state(), TCPState::Established);
+        assert_eq!(client.connected.get(), Some((0, ReturnCode::SUCCESS)));
+
+        let data = std::boxed::Box::leak(std::boxed::Box::new([1, 2, 3]));
+        mux.send(&socket, LeasableBuffer::new(data)).ok().unwrap();
+        let (_, segment, len) = take_segment(&mux, &sender).unwrap();
+        assert_eq!(segment.get_seq_num(), iss.wrapping_add(1));
+        assert_eq!(len, 3);
+        deliver(
+            &mux,
+            socket.get_local_port(),
+            PEER_ISS + 1,
+            iss.wrapping_add(4),
+            tcp_flags::ACK,
+            &[],
+        );
+        assert_eq!(client.sent.get(), Some((0, ReturnCode::SUCCESS)));
+
+        deliver(
+            &mux,
+            socket.get_local_port(),
+            PEER_ISS + 1,
+            iss.wrapping_add(4),
+            tcp_flags::ACK,
+            &[9; 5],
+        );
+        assert_eq!(client.received.get(), 5);
+        let (_, ack, _) = take_segment(&mux, &sender).unwrap();
+        assert_eq!(ack.get_ack_num(), PEER_ISS + 6);
+    }
+
+    #[test]
+    fn listener_keeps_listening() {
+        let (alarm, sender, client) = (new_alarm(), new_sender(), MockClient::new());
+        let (listener, first, second) = (TCPSocket::new(0), TCPSocket::new(1), TCPSocket::new(2));
+        let mux = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        for socket in [&listener, &first, &second].iter() {
+            socket.set_client(&client);
+            mux.add_socket(socket);
+        }
+        set_secret(&mux, 1);
+        assert_eq!(mux.listen(&listener, 80), ReturnCode::SUCCESS);
+
+        deliver(&mux, 80, PEER_ISS, 0, tcp_flags::SYN, &[]);
+        let (_, syn_ack, _) = take_segment(&mux, &sender).unwrap();
+        assert_eq!(syn_ack.get_flags(), tcp_flags::SYN | tcp_flags::ACK);
+        assert_eq!(syn_ack.get_src_port(), 80);
+        assert_eq!(first.get_state(), TCPState::SynReceived);
+        assert_eq!(listener.get_state(), TCPState::Listen);
+
+        deliver(
+            &mux,
+            80,
+            PEER_ISS + 1,
+            syn_ack.get_seq_num().wrapping_add(1),
+            tcp_flags::ACK,
+            &[],
+        );
+        assert_eq!(first.get_state(), TCPState::Established);
+        assert_eq!(client.connected.get(), Some((1, ReturnCode::SUCCESS)));
+
+        // Closing the listener leaves the connection open
+        assert_eq!(mux.close(&listener), ReturnCode::SUCCESS);
+        assert_eq!(first.get_state(), TCPState::Established);
+        assert_eq!(mux.listen(&listener, 80), ReturnCode::SUCCESS);
+
+        // Another peer gets the next closed socket
+        deliver_from(&mux, PEER_PORT + 1, 80, PEER_ISS, 0, tcp_flags::SYN, &[]);
+        assert!(take_segment(&mux, &sender).is_some());
+        assert_eq!(second.get_state(), TCPState::SynReceived);
+
+        // A connection that the client does not accept is refused
+        client.accept.set(false);
+        mux.abort(&second);
+        take_segment(&mux, &sender).unwrap();
+        deliver_from(&mux, PEER_PORT + 2, 80, PEER_ISS, 0, tcp_flags::SYN, &[]);
+        let (_, reset, _) = take_segment(&mux, &sender).unwrap();
+        assert!(reset.has_flags(tcp_flags::RST));
+        assert_eq!(reset.get_dst_port(), PEER_PORT + 2);
+        assert_eq!(second.get_state(), TCPState::Closed);
+        assert_eq!(listener.get_state(), TCPState::Listen);
+    }
+
+    #[test]
+    fn close_waits_in_time_wait() {
+        let (alarm, sender, client) = (new_alarm(), new_sender(), MockClient::new());
+        let socket = TCPSocket::new(0);
+        let mux = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        socket.set_client(&client);
+        mux.add_socket(&socket);
+        set_secret(&mux, 1);
+        let iss = open(&mux, &sender, &socket);
+        let port = socket.get_local_port();
+
+        assert_eq!(mux.close(&socket), ReturnCode::SUCCESS);
+        let (_, fin, _) = take_segment(&mux, &sender).unwrap();
+        assert!(fin.has_flags(tcp_flags::FIN));
+        deliver(
+            &mux,
+            port,
+            PEER_ISS + 1,
+            iss.wrapping_add(2),
+            tcp_flags::ACK,
+            &[],
+        );
+        assert_eq!(socket.get_state(), TCPState::FinWait2);
+
+        let fin_flags = tcp_flags::FIN | tcp_flags::ACK;
+        deliver(
+            &mux,
+            port,
+            PEER_ISS + 1,
+            iss.wrapping_add(2),
+            fin_flags,
+            &[],
+        );
+        assert_eq!(socket.get_state(), TCPState::TimeWait);
+        let (_, ack, _) = take_segment(&mux, &sender).unwrap();
+        assert_eq!(ack.get_ack_num(), PEER_ISS + 2);
+
+        // A retransmitted FIN is acknowledged again and restarts the timer
+        advance(&mux, &alarm, TIME_WAIT_MS - 1);
+        deliver(
+            &mux,
+            port,
+            PEER_ISS + 1,
+            iss.wrapping_add(2),
+            fin_flags,
+            &[],
+        );
+        assert!(take_segment(&mux, &sender).is_some());
+        advance(&mux, &alarm, TIME_WAIT_MS - 1);
+        assert_eq!(socket.get_state(), TCPState::TimeWait);
+        assert_eq!(client.closed.get(), None);
+
+        advance(&mux, &alarm, 1);
+        assert_eq!(socket.get_state(), TCPState::Closed);
+        assert_eq!(client.closed.get(), Some((0, ReturnCode::SUCCESS)));
+    }
+
+    #[test]
+    fn fin_wait_2_times_out() {
+        let (alarm, sender, client) = (new_alarm(), new_sender(), MockClient::new());
+        let socket = TCPSocket::new(0);
+        let mux = MuxTcp::new(&sender, &alarm, &MockRng, 100);
+        socket.set_client(&client);
+        mux.add_socket(&socket);
+        set_secret(&mux, 1);
+        let iss = open(&mux, &sender, &socket);
+
+        mux.close(&socket);
+        take_segment(&mux, &sender).unwrap();
+        deliver(
+            &mux,
+            socket.get_local_port(),
+            PEER_ISS + 1,
+            iss.wrapping_add(2),
+            tcp_flags::ACK,
+            &[],
+        );
+        assert_eq!(socket.get_state(), TCPState::FinWait2);
+
+        advance(&mux, &alarm, FIN_WAIT_2_MS - 1);
+        assert_eq!(socket.get_state(), TCPState::FinWait2);
+        advance(&mux, &alarm, 1);
+        assert_eq!(socket.get_state(), TCPState::Closed);
+        assert_eq!(client.closed.get(), Some((0, ReturnCode::ENOACK)));
+        let (_, reset, _) = take_segment(&mux, &sender).unwrap();
+        assert!(reset.has_flags(tcp_flags::RST));
+    }
+}
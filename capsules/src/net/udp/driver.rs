@@ -3,10 +3,11 @@
 //! Implements a userspace interface for sending and receiving UDP messages.
 //! Processes use this driver to send UDP packets from a common interface
 //! and bind to UDP ports for receiving packets.
-//! Also exposes a list of interface addresses to the application (currently
-//! hard-coded).
+//! Also exposes the addresses of the network interface to the application.
 
 use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6::{IP6_HDR_LEN, UDP_HDR_LEN};
+use crate::net::network_interface::{NetworkInterface, NetworkInterfaceClient};
 use crate::net::stream::encode_u16;
 use crate::net::stream::encode_u8;
 use crate::net::stream::SResult;
@@ -19,7 +20,10 @@ use core::{cmp, mem};
 use kernel::capabilities::UdpDriverCapability;
 use kernel::common::cells::MapCell;
 use kernel::common::leasable_buffer::LeasableBuffer;
-use kernel::{debug, AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use kernel::{
+    debug, AppId, AppSlice, Callback, Driver, Grant, GrantCleanupClient, ReadOnlyAppSlice,
+    ReturnCode, Shared,
+};
 
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Udp as usize;
@@ -64,7 +68,7 @@ pub struct App {
     rx_callback: Option<Callback>,
     tx_callback: Option<Callback>,
     app_read: Option<AppSlice<Shared, u8>>,
-    app_write: Option<AppSlice<Shared, u8>>,
+    app_write: Option<ReadOnlyAppSlice<u8>>,
     app_cfg: Option<AppSlice<Shared, u8>>,
     app_rx_cfg: Option<AppSlice<Shared, u8>>,
     pending_tx: Option<[UDPEndpoint; 2]>,
@@ -81,8 +85,8 @@ pub struct UDPDriver<'a> {
     /// ID of app whose transmission request is being processed.
     current_app: Cell<Option<AppId>>,
 
-    /// List of IP Addresses of the interfaces on the device
-    interface_list: &'static [IPAddr],
+    /// Network interface whose addresses apps can bind to
+    interface: &'a NetworkInterface,
 
     /// Maximum length payload that an app can transmit via this driver
     max_tx_pyld_len: usize,
@@ -99,7 +103,7 @@ impl<'a> UDPDriver<'a> {
     pub fn new(
         sender: &'a dyn UDPSender<'a>,
         grant: Grant<App>,
-        interface_list: &'static [IPAddr],
+        interface: &'a NetworkInterface,
         max_tx_pyld_len: usize,
         port_table: &'static UdpPortManager,
         kernel_buffer: LeasableBuffer<'static, u8>,
@@ -109,7 +113,7 @@ impl<'a> UDPDriver<'a> {
             sender: sender,
             apps: grant,
             current_app: Cell::new(None),
-            interface_list: interface_list,
+            interface: interface,
             max_tx_pyld_len: max_tx_pyld_len,
             port_table: port_table,
             kernel_buffer: MapCell::new(kernel_buffer),
@@ -205,7 +209,7 @@ impl<'a> UDPDriver<'a> {
     /// If the driver is currently idle and there are pending transmissions,
     /// pick an app with a pending transmission and return its `AppId`.
     fn get_next_tx_if_idle(&self) -> Option<AppId> {
-        if self.current_app.get().is_some() {
+        if self.current_app.get().is_some() || self.kernel_buffer.is_none() {
             // Tx already in progress
             return None;
         }
@@ -348,7 +352,8 @@ impl<'a> Driver for UDPDriver<'a> {
     /// ### `allow_num`
     ///
     /// - `0`: Read buffer. Will contain the received payload.
-    /// - `1`: Write buffer. Contains the UDP payload to be transmitted.
+    /// - `1`: Write buffer. Contains the UDP payload to be transmitted. It can
+    ///        also be shared with `allow_readonly`.
     /// - `2`: Config buffer. Used to contain miscellaneous data associated with
     ///        some commands, namely source/destination addresses and ports.
     /// - `3`: Rx config buffer. Used to contain source/destination addresses
@@ -361,29 +366,42 @@ impl<'a> Driver for UDPDriver<'a> {
         slice: Option<AppSlice<Shared, u8>>,
     ) -> ReturnCode {
         match allow_num {
-            0 | 1 | 2 | 3 => self.do_with_app(appid, |app| {
-                let mut success = true;
+            0 | 2 | 3 => self.do_with_app(appid, |app| {
                 match allow_num {
                     0 => app.app_read = slice,
-                    1 => match slice {
-                        Some(s) => {
-                            if s.len() > self.max_tx_pyld_len {
-                                success = false;
-                            } else {
-                                app.app_write = Some(s);
-                            }
-                        }
-                        None => {}
-                    },
                     2 => app.app_cfg = slice,
                     3 => app.app_rx_cfg = slice,
                     _ => {}
                 }
-                if success {
-                    ReturnCode::SUCCESS
-                } else {
-                    ReturnCode::EINVAL //passed tx buffer too long
+                ReturnCode::SUCCESS
+            }),
+            1 => self.allow_readonly(appid, allow_num, slice.map(ReadOnlyAppSlice::from)),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Setup read-only buffers, which may be in flash.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `1`: Write buffer. Contains the UDP payload to be transmitted.
+    fn allow_readonly(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<ReadOnlyAppSlice<u8>>,
+    ) -> ReturnCode {
+        match allow_num {
+            1 => self.do_with_app(appid, |app| match slice {
+                Some(s) => {
+                    if s.len() > self.max_tx_pyld_len {
+                        ReturnCode::EINVAL //passed tx buffer too long
+                    } else {
+                        app.app_write = Some(s);
+                        ReturnCode::SUCCESS
+                    }
                 }
+                None => ReturnCode::SUCCESS,
             }),
             _ => ReturnCode::ENOSUPPORT,
         }
@@ -449,7 +467,8 @@ impl<'a> Driver for UDPDriver<'a> {
     ///        packet.
     ///        Currently, only will transmit if the app has bound to the port passed in the tx_cfg
     ///        buf as the source address. If no port is bound, returns ERESERVE, if it tries to
-    ///        send on a port other than the port which is bound, returns EINVALID.
+    ///        send on a port other than the port which is bound, returns EINVALID. Removing
+    ///        the bound address from the network interface unbinds the app.
     ///
     ///        Notably, the currently transmit implementation allows for starvation - an
     ///        an app with a lower app id can send constantly and starve an app with a
@@ -468,7 +487,8 @@ impl<'a> Driver for UDPDriver<'a> {
     ///        currently no mechanism for anything in the kernel to bind to ports, and there
     ///        is no distinction between ephemeral ports and reserved ports.
     /// - `4`: Returns the maximum payload that can be transmitted by apps using this driver.
-    ///        This represents the size of the payload buffer in the kernel. Apps can use this
+    ///        This represents the size of the payload buffer in the kernel, or the largest
+    ///        payload that fits in the MTU of the network interface if it is smaller. Apps can use this
     ///        syscall to ensure they do not attempt to send too-large messages.
 
     fn command(&self, command_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
@@ -478,16 +498,16 @@ impl<'a> Driver for UDPDriver<'a> {
             //  Writes the requested number of network interface addresses
             // `arg1`: number of interfaces requested that will fit into the buffer
             1 => self.do_with_cfg_mut(appid, arg1 * mem::size_of::<IPAddr>(), |cfg| {
-                let n_ifaces_to_copy = cmp::min(arg1, self.interface_list.len());
+                let n_ifaces = self.interface.address_count();
+                let n_ifaces_to_copy = cmp::min(arg1, n_ifaces);
                 let iface_size = mem::size_of::<IPAddr>();
                 for i in 0..n_ifaces_to_copy {
-                    cfg[i * iface_size..(i + 1) * iface_size]
-                        .copy_from_slice(&self.interface_list[i].0);
+                    self.interface.get_address(i).map(|addr| {
+                        cfg[i * iface_size..(i + 1) * iface_size].copy_from_slice(&addr.0)
+                    });
                 }
                 // Returns total number of interfaces
-                ReturnCode::SuccessWithValue {
-                    value: self.interface_list.len(),
-                }
+                ReturnCode::SuccessWithValue { value: n_ifaces }
             }),
 
             // Transmits UDP packet stored in tx_buf
@@ -552,13 +572,7 @@ impl<'a> Driver for UDPDriver<'a> {
                             return ReturnCode::SUCCESS;
                         }
                         // Check that requested addr is a local interface
-                        let mut requested_is_local = false;
-                        for i in 0..self.interface_list.len() {
-                            if requested_addr.addr == self.interface_list[i] {
-                                requested_is_local = true;
-                            }
-                        }
-                        if !requested_is_local {
+                        if !self.interface.has_address(&requested_addr.addr) {
                             return ReturnCode::EINVAL;
                         }
                         let mut addr_already_bound = false;
@@ -603,7 +617,12 @@ impl<'a> Driver for UDPDriver<'a> {
                 })
             }
             4 => ReturnCode::SuccessWithValue {
-                value: self.max_tx_pyld_len,
+                value: cmp::min(
+                    self.max_tx_pyld_len,
+                    self.interface
+                        .get_mtu()
+                        .saturating_sub(IP6_HDR_LEN + UDP_HDR_LEN),
+                ),
             },
             _ => ReturnCode::ENOSUPPORT,
         }
@@ -626,6 +645,34 @@ impl<'a> UDPSendClient for UDPDriver<'a> {
     }
 }
 
+impl<'a> GrantCleanupClient for UDPDriver<'a> {
+    fn process_teardown(&self, appid: AppId) {
+        // A transmission that is already queued in the UDP stack cannot be
+        // cancelled, but its completion must not be reported to whatever
+        // process next uses this `AppId`. The driver stays busy until the
+        // kernel buffer is returned in `send_done()`.
+        if self.current_app.get() == Some(appid) {
+            self.current_app.set(None);
+        }
+    }
+}
+
+impl<'a> NetworkInterfaceClient for UDPDriver<'a> {
+    // Apps bound to a removed address can no longer send or receive on it,
+    // so they are unbound and have to bind again.
+    fn address_removed(&self, addr: IPAddr) {
+        self.apps.each(|app| {
+            if app
+                .bound_port
+                .as_ref()
+                .map_or(false, |bound| bound.addr == addr)
+            {
+                app.bound_port = None;
+            }
+        });
+    }
+}
+
 impl<'a> UDPRecvClient for UDPDriver<'a> {
     fn receive(
         &self,
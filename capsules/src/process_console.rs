@@ -5,13 +5,51 @@
 //! --------
 //!
 //! This module provides a simple text-based console to inspect and control
-//! which processes are running. The console has five commands:
-//!  - 'help' prints the available commands and arguments
+//! which processes are running. The console has the following commands:
+//!  - 'help' lists the commands, 'help c' prints the arguments of command c
 //!  - 'status' prints the current system status
 //!  - 'list' lists the current processes with their IDs and running state
+//!  - 'process n' prints the state and memory map of the process with name n
 //!  - 'stop n' stops the process with name n
 //!  - 'start n' starts the stopped process with name n
 //!  - 'fault n' forces the process with name n into a fault state
+//!  - 'restart n' starts the process with name n over from its entry point
+//!  - 'kill n' terminates the process with name n, which then never runs
+//!    again
+//!  - 'grants' prints the grant memory each process uses, 'grants n' only
+//!    that of the process with name n
+//!  - 'kernel' prints how often the kernel has serviced interrupts and slept,
+//!    and how many deferred calls are registered and pending
+//!
+//! If the board provides a process loader with `set_process_loader()`, three
+//! more commands are available:
+//!  - 'load' loads the next app in flash that is not running
+//!  - 'unload n' terminates the process with name n and frees its slot
+//!  - 'replace n' replaces the process with name n with the app of the same
+//!    name in flash
+//!
+//! If the board provides a syscall trace with `set_syscall_trace()`, the
+//! 'trace' command controls which system calls are recorded:
+//!  - 'trace on' and 'trace off' start and stop recording
+//!  - 'trace app n' only records the process with name n, 'trace app all'
+//!    records all processes
+//!  - 'trace driver d' only records calls to driver number d (decimal or
+//!    0x-prefixed hex), 'trace driver all' records all drivers
+//!
+//! If the board provides its network interface with
+//! `set_network_interface()`, the 'ip' command shows and changes its IPv6
+//! addresses:
+//!  - 'ip' lists the addresses and the prefix of the network
+//!  - 'ip add a' and 'ip remove a' add and remove the address a, such as
+//!    `2001:db8::1`
+//!  - 'ip prefix p/l' sets the prefix p of length l and forms an address
+//!    from it, 'ip prefix none' removes the prefix and that address
+//!
+//! If the board allows it with `set_reboot()`, 'reboot' resets the board.
+//!
+//! Commands are matched by their full name, and extra arguments are
+//! rejected. Backspace deletes the last character, and the up and down
+//! arrow keys recall earlier commands.
 //!
 //! Setup
 //! -----
@@ -30,6 +68,7 @@
 //!                  &mut console::WRITE_BUF,
 //!                  &mut console::READ_BUF,
 //!                  &mut console::COMMAND_BUF,
+//!                  &mut console::HISTORY_BUF,
 //!                  kernel,
 //!                  Capability);
 //! hil::uart::UART::set_client(&usart::USART0, pconsole);
@@ -38,12 +77,41 @@
 //! pconsole.start();
 //! ```
 //!
+//! To enable the `load`, `unload` and `replace` commands, also pass the
+//! console a `DynamicProcessLoader`:
+//!
+//! ```rust
+//! pconsole.set_process_loader(loader);
+//! ```
+//!
+//! To enable the `ip` command, pass the console the network interface:
+//!
+//! ```rust
+//! pconsole.set_network_interface(interface);
+//! ```
+//!
+//! To enable the `reboot` command, pass the console a function that resets
+//! the board, together with the capability to do so:
+//!
+//! ```rust
+//! struct RebootCap;
+//! unsafe impl capabilities::RebootCapability for RebootCap {}
+//!
+//! fn reboot() {
+//!     unsafe { cortexm4::scb::reset() };
+//! }
+//!
+//! pconsole.set_reboot(reboot, &RebootCap);
+//! ```
+//!
 //! Buffer use and output
 //! ---------------------
-//! `ProcessConsole` does not use its own write buffer for output:
+//! `ProcessConsole` does not use its own write buffer for most output:
 //! it uses the debug!() buffer, so as not to repeat all of its buffering and
 //! to maintain a correct ordering with debug!() calls. The write buffer of
-//! `ProcessConsole` is used solely for echoing what someone types.
+//! `ProcessConsole` is used for echoing what someone types, and for the
+//! memory map printed by `process`, which is longer than the debug!() buffer
+//! and is sent one write buffer at a time.
 //!
 //! Using ProcessConsole
 //! --------------------
@@ -69,9 +137,13 @@
 //! Initialization complete. Entering main loop
 //! Hello World!
 //! list
-//! PID    Name    Quanta  Syscalls  Dropped Callbacks  Restarts    State
-//! 00     blink        0       113                  0         0  Yielded
-//! 01     c_hello      0         8                  0         0  Yielded
+//! PID    Name    Quanta  Syscalls  Dropped Callbacks  Restarts   CPU(ms)    State
+//! 00     blink        0       113                  0         0        12  Yielded
+//! 01     c_hello      0         8                  0         0         1  Yielded
+//! Syscall latency in us (average/max)
+//! PID    Yield      Subscribe  Command    Allow      Memop      RO Allow
+//! 00       3/9        12/15      21/48       0/0        4/6         0/0
+//! 01       2/4         9/9       30/35       8/8        3/5         7/9
 //! ```
 //!
 //! To get a general view of the system, use the status command:
@@ -81,6 +153,7 @@
 //! Total processes: 2
 //! Active processes: 2
 //! Timeslice expirations: 0
+//! Process CPU time: 13 ms
 //! ```
 //!
 //! and you can control processes with the `start` and `stop` commands:
@@ -89,27 +162,247 @@
 //! stop blink
 //! Process blink stopped
 //! ```
+//!
+//! `grants` shows how much grant memory each process uses, in total and per
+//! grant (as `grant:bytes`), and its quotas:
+//!
+//! ```text
+//! grants
+//!  PID    Name                  Used     Quota  Per-Grant  Exceeded  Grants
+//!   00    blink                   48         -          -         0  0:24 3:24
+//!   01    c_hello                 80         -          -         0  1:80
+//! ```
 
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_interface::NetworkInterface;
 use core::cell::Cell;
 use core::cmp;
 use core::str;
-use kernel::capabilities::ProcessManagementCapability;
-use kernel::common::cells::TakeCell;
+use kernel::capabilities::{ProcessManagementCapability, RebootCapability};
+use kernel::common::cells::{OptionalCell, TakeCell};
 use kernel::debug;
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
-use kernel::Kernel;
+use kernel::procs::{DynamicProcessLoading, ProcessType};
+use kernel::syscall::SyscallClass;
+use kernel::syscall_trace::SyscallTrace;
 use kernel::ReturnCode;
+use kernel::{AppId, Kernel};
 
-// Since writes are character echoes, we do not need more than 4 bytes:
-// the longest write is 3 bytes for a backspace (backspace, space, backspace).
-pub static mut WRITE_BUF: [u8; 4] = [0; 4];
+// Writes are character echoes, redrawn lines from the history, and pieces
+// of memory maps. A redrawn line needs 4 bytes to clear the line and up to
+// `COMMAND_BUF` bytes for the command.
+pub static mut WRITE_BUF: [u8; 64] = [0; 64];
 // Since reads are byte-by-byte, to properly echo what's typed,
 // we can use a very small read buffer.
 pub static mut READ_BUF: [u8; 4] = [0; 4];
-// Commands can be up to 32 bytes long: since commands themselves are 4-5
-// characters, limiting arguments to 25 bytes or so seems fine for now.
-pub static mut COMMAND_BUF: [u8; 32] = [0; 32];
+// Commands can be up to 48 bytes long, so that `ip add` fits an IPv6
+// address written out in full.
+pub static mut COMMAND_BUF: [u8; 48] = [0; 48];
+// The last 4 commands, each as long as `COMMAND_BUF`.
+pub static mut HISTORY_BUF: [u8; 4 * 48] = [0; 4 * 48];
+
+/// Most arguments any command takes.
+const MAX_ARGS: usize = 2;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const ESCAPE: u8 = 0x1b;
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(s: &str) -> Option<usize> {
+    if s.starts_with("0x") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse::<usize>().ok()
+    }
+}
+
+/// The length of the command stored in `buffer`, which is terminated by a 0
+/// byte unless it fills the buffer.
+fn command_len(buffer: &[u8]) -> usize {
+    buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+    Help,
+    Status,
+    List,
+    Process,
+    Start,
+    Stop,
+    Fault,
+    Restart,
+    Kill,
+    Grants,
+    KernelStats,
+    Load,
+    Unload,
+    Replace,
+    Trace,
+    Ip,
+    Reboot,
+}
+
+/// An entry in the command table.
+struct Command {
+    name: &'static str,
+    /// Arguments as shown by `help`.
+    arguments: &'static str,
+    description: &'static str,
+    min_args: usize,
+    max_args: usize,
+    action: Action,
+}
+
+const COMMANDS: [Command; 17] = [
+    Command {
+        name: "help",
+        arguments: "[command]",
+        description: "List the commands, or describe one",
+        min_args: 0,
+        max_args: 1,
+        action: Action::Help,
+    },
+    Command {
+        name: "status",
+        arguments: "",
+        description: "Print the system status",
+        min_args: 0,
+        max_args: 0,
+        action: Action::Status,
+    },
+    Command {
+        name: "list",
+        arguments: "",
+        description: "List the processes",
+        min_args: 0,
+        max_args: 0,
+        action: Action::List,
+    },
+    Command {
+        name: "process",
+        arguments: "<name>",
+        description: "Print the state and memory map of a process",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Process,
+    },
+    Command {
+        name: "start",
+        arguments: "<name>",
+        description: "Resume a stopped process",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Start,
+    },
+    Command {
+        name: "stop",
+        arguments: "<name>",
+        description: "Stop a process",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Stop,
+    },
+    Command {
+        name: "fault",
+        arguments: "<name>",
+        description: "Force a process into a fault state",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Fault,
+    },
+    Command {
+        name: "restart",
+        arguments: "<name>",
+        description: "Start a process over from its entry point",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Restart,
+    },
+    Command {
+        name: "kill",
+        arguments: "<name>",
+        description: "Terminate a process for good",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Kill,
+    },
+    Command {
+        name: "grants",
+        arguments: "[name]",
+        description: "Print the grant memory of processes",
+        min_args: 0,
+        max_args: 1,
+        action: Action::Grants,
+    },
+    Command {
+        name: "kernel",
+        arguments: "",
+        description: "Print interrupt, deferred call and sleep counts",
+        min_args: 0,
+        max_args: 0,
+        action: Action::KernelStats,
+    },
+    Command {
+        name: "load",
+        arguments: "",
+        description: "Load the next app in flash that is not running",
+        min_args: 0,
+        max_args: 0,
+        action: Action::Load,
+    },
+    Command {
+        name: "unload",
+        arguments: "<name>",
+        description: "Terminate a process and free its slot",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Unload,
+    },
+    Command {
+        name: "replace",
+        arguments: "<name>",
+        description: "Replace a process with the app of that name in flash",
+        min_args: 1,
+        max_args: 1,
+        action: Action::Replace,
+    },
+    Command {
+        name: "trace",
+        arguments: "[on|off|app <name|all>|driver <number|all>]",
+        description: "Control the syscall trace",
+        min_args: 0,
+        max_args: 2,
+        action: Action::Trace,
+    },
+    Command {
+        name: "ip",
+        arguments: "[add <address>|remove <address>|prefix <prefix/length|none>]",
+        description: "Show or change the addresses of the network interface",
+        min_args: 0,
+        max_args: 2,
+        action: Action::Ip,
+    },
+    Command {
+        name: "reboot",
+        arguments: "",
+        description: "Reset the board",
+        min_args: 0,
+        max_args: 0,
+        action: Action::Reboot,
+    },
+];
+
+/// Where the console is in an escape sequence sent by a terminal, such as
+/// `ESC [ A` for the up arrow key.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum EscapeState {
+    None,
+    Escape,
+    ControlSequence,
+}
 
 pub struct ProcessConsole<'a, C: ProcessManagementCapability> {
     uart: &'a dyn uart::UartData<'a>,
@@ -119,8 +412,24 @@ pub struct ProcessConsole<'a, C: ProcessManagementCapability> {
     rx_buffer: TakeCell<'static, [u8]>,
     command_buffer: TakeCell<'static, [u8]>,
     command_index: Cell<usize>,
+    /// Earlier commands, most recent first, each as long as the command
+    /// buffer.
+    history_buffer: TakeCell<'static, [u8]>,
+    /// How many entries of the history are in use.
+    history_len: Cell<usize>,
+    /// The history entry shown on the command line, counting from 1 for the
+    /// most recent one, or 0 while a new command is typed.
+    history_position: Cell<usize>,
+    escape_state: Cell<EscapeState>,
+    /// The process whose memory map is being printed, and how many bytes of
+    /// it have been sent.
+    memory_map: OptionalCell<(AppId, usize)>,
     running: Cell<bool>,
     kernel: &'static Kernel,
+    process_loader: OptionalCell<&'a dyn DynamicProcessLoading>,
+    syscall_trace: OptionalCell<&'a SyscallTrace>,
+    network_interface: OptionalCell<&'a NetworkInterface>,
+    reboot: OptionalCell<fn()>,
     capability: C,
 }
 
@@ -130,6 +439,7 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
         tx_buffer: &'static mut [u8],
         rx_buffer: &'static mut [u8],
         cmd_buffer: &'static mut [u8],
+        history_buffer: &'static mut [u8],
         kernel: &'static Kernel,
         capability: C,
     ) -> ProcessConsole<'a, C> {
@@ -141,12 +451,146 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
             rx_buffer: TakeCell::new(rx_buffer),
             command_buffer: TakeCell::new(cmd_buffer),
             command_index: Cell::new(0),
+            history_buffer: TakeCell::new(history_buffer),
+            history_len: Cell::new(0),
+            history_position: Cell::new(0),
+            escape_state: Cell::new(EscapeState::None),
+            memory_map: OptionalCell::empty(),
             running: Cell::new(false),
             kernel: kernel,
+            process_loader: OptionalCell::empty(),
+            syscall_trace: OptionalCell::empty(),
+            network_interface: OptionalCell::empty(),
+            reboot: OptionalCell::empty(),
             capability: capability,
         }
     }
 
+    /// Enable the commands that load, unload and replace processes.
+    pub fn set_process_loader(&self, loader: &'a dyn DynamicProcessLoading) {
+        self.process_loader.set(loader);
+    }
+
+    /// Enable the command that controls the syscall trace.
+    pub fn set_syscall_trace(&self, trace: &'a SyscallTrace) {
+        self.syscall_trace.set(trace);
+    }
+
+    /// Enable the command that shows and changes the addresses of the
+    /// network interface.
+    pub fn set_network_interface(&self, interface: &'a NetworkInterface) {
+        self.network_interface.set(interface);
+    }
+
+    /// Enable the `reboot` command, which calls `reboot` to reset the board.
+    pub fn set_reboot(&self, reboot: fn(), _capability: &dyn RebootCapability) {
+        self.reboot.set(reboot);
+    }
+
+    // Handle `trace on|off`, `trace app <name|all>` and
+    // `trace driver <number|all>`.
+    fn trace_command(&self, trace: &SyscallTrace, arguments: &[&str]) {
+        match arguments {
+            ["on"] => trace.set_enabled(true),
+            ["off"] => trace.set_enabled(false),
+            ["app", "all"] => trace.set_process_filter(None),
+            ["app", name] => match self.find_process(name) {
+                Some(appid) => trace.set_process_filter(Some(appid)),
+                None => debug!("No process named {}.", name),
+            },
+            ["driver", "all"] => trace.set_driver_filter(None),
+            ["driver", number] => match parse_number(number) {
+                Some(driver) => trace.set_driver_filter(Some(driver)),
+                None => debug!("Invalid driver number {}.", number),
+            },
+            [] => debug!(
+                "Syscall trace {}, {} records pending.",
+                if trace.is_enabled() { "on" } else { "off" },
+                trace.pending()
+            ),
+            _ => debug!("Usage: trace [on|off|app <name|all>|driver <number|all>]"),
+        }
+    }
+
+    // Handle `ip`, `ip add|remove <address>` and
+    // `ip prefix <prefix/length|none>`.
+    fn ip_command(&self, interface: &NetworkInterface, arguments: &[&str]) {
+        match arguments {
+            [] => {
+                for index in 0..interface.address_count() {
+                    interface
+                        .get_address(index)
+                        .map(|addr| debug!("Address: {}", addr));
+                }
+                match interface.get_prefix() {
+                    Some((prefix, prefix_len)) => debug!("Prefix: {}/{}", prefix, prefix_len),
+                    None => debug!("No prefix."),
+                }
+            }
+            ["add", text] => match IPAddr::parse(text) {
+                Some(addr) => match interface.add_address(addr) {
+                    ReturnCode::SUCCESS => debug!("Address {} added.", addr),
+                    err => debug!("Failed to add address {}: {:?}", addr, err),
+                },
+                None => debug!("Invalid address {}.", text),
+            },
+            ["remove", text] => match IPAddr::parse(text) {
+                Some(addr) => match interface.remove_address(addr) {
+                    ReturnCode::SUCCESS => debug!("Address {} removed.", addr),
+                    err => debug!("Failed to remove address {}: {:?}", addr, err),
+                },
+                None => debug!("Invalid address {}.", text),
+            },
+            ["prefix", "none"] => {
+                interface.clear_prefix();
+                debug!("Prefix removed.");
+            }
+            ["prefix", text] => {
+                let mut parts = text.splitn(2, '/');
+                let prefix = parts.next().and_then(IPAddr::parse);
+                let prefix_len = parts.next().and_then(|len| len.parse::<u8>().ok());
+                match (prefix, prefix_len) {
+                    (Some(prefix), Some(prefix_len)) => {
+                        match interface.set_prefix(prefix, prefix_len) {
+                            ReturnCode::SUCCESS => debug!("Prefix {}/{} set.", prefix, prefix_len),
+                            err => debug!("Failed to set prefix {}: {:?}", text, err),
+                        }
+                    }
+                    _ => debug!("Invalid prefix {}.", text),
+                }
+            }
+            _ => debug!("Usage: ip [add <address>|remove <address>|prefix <prefix/length|none>]"),
+        }
+    }
+
+    // Find the process with the given name.
+    fn find_process(&self, name: &str) -> Option<AppId> {
+        let appid = OptionalCell::empty();
+        self.kernel
+            .process_each_capability(&self.capability, |_i, proc| {
+                if proc.get_process_name() == name {
+                    appid.set(proc.appid());
+                }
+            });
+        appid.take()
+    }
+
+    // Run `f` on the process with the given name, or report that there is
+    // none.
+    fn with_process<F: Fn(&dyn ProcessType)>(&self, name: &str, f: F) {
+        let found = Cell::new(false);
+        self.kernel
+            .process_each_capability(&self.capability, |_i, proc| {
+                if proc.get_process_name() == name {
+                    found.set(true);
+                    f(proc);
+                }
+            });
+        if !found.get() {
+            debug!("No process named {}.", name);
+        }
+    }
+
     pub fn start(&self) -> ReturnCode {
         if self.running.get() == false {
             self.rx_buffer.take().map(|buffer| {
@@ -162,103 +606,12 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
     // Process the command in the command buffer and clear the buffer.
     fn read_command(&self) {
         self.command_buffer.map(|command| {
-            let mut terminator = 0;
-            let len = command.len();
-            for i in 0..len {
-                if command[i] == 0 {
-                    terminator = i;
-                    break;
-                }
-            }
-            //debug!("Command: {}-{} {:?}", start, terminator, command);
-            // A command is valid only if it starts inside the buffer,
-            // ends before the beginning of the buffer, and ends after
-            // it starts.
-            if terminator > 0 {
-                let cmd_str = str::from_utf8(&command[0..terminator]);
-                match cmd_str {
-                    Ok(s) => {
-                        let clean_str = s.trim();
-                        if clean_str.starts_with("help") {
-                            debug!("Welcome to the process console.");
-                            debug!("Valid commands are: help status list stop start");
-                        } else if clean_str.starts_with("start") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel.process_each_capability(
-                                    &self.capability,
-                                    |_i, proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.resume();
-                                            debug!("Process {} resumed.", name);
-                                        }
-                                    },
-                                );
-                            });
-                        } else if clean_str.starts_with("stop") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel.process_each_capability(
-                                    &self.capability,
-                                    |_i, proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.stop();
-                                            debug!("Process {} stopped", proc_name);
-                                        }
-                                    },
-                                );
-                            });
-                        } else if clean_str.starts_with("fault") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel.process_each_capability(
-                                    &self.capability,
-                                    |_i, proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.set_fault_state();
-                                            debug!("Process {} now faulted", proc_name);
-                                        }
-                                    },
-                                );
-                            });
-                        } else if clean_str.starts_with("list") {
-                            debug!(" PID    Name                Quanta  Syscalls  Dropped Callbacks  Restarts    State");
-                            self.kernel
-                                .process_each_capability(&self.capability, |i, proc| {
-                                    let pname = proc.get_process_name();
-                                    debug!(
-                                        "  {:02}\t{:<20}{:6}{:10}{:19}{:10}  {:?}",
-                                        i,
-                                        pname,
-                                        proc.debug_timeslice_expiration_count(),
-                                        proc.debug_syscall_count(),
-                                        proc.debug_dropped_callback_count(),
-                                        proc.debug_restart_count(),
-                                        proc.get_state()
-                                    );
-                                });
-                        } else if clean_str.starts_with("status") {
-                            let info: KernelInfo = KernelInfo::new(self.kernel);
-                            debug!(
-                                "Total processes: {}",
-                                info.number_loaded_processes(&self.capability)
-                            );
-                            debug!(
-                                "Active processes: {}",
-                                info.number_active_processes(&self.capability)
-                            );
-                            debug!(
-                                "Timeslice expirations: {}",
-                                info.timeslice_expirations(&self.capability)
-                            );
-                        } else {
-                            debug!("Valid commands are: help status list stop start fault");
-                        }
-                    }
-                    Err(_e) => debug!("Invalid command: {:?}", command),
+            let len = command_len(command);
+            if len > 0 {
+                self.add_to_history(&command[..len], command.len());
+                match str::from_utf8(&command[..len]) {
+                    Ok(s) => self.execute(s),
+                    Err(_e) => debug!("Invalid command: {:?}", &command[..len]),
                 }
             }
         });
@@ -266,44 +619,449 @@ impl<'a, C: ProcessManagementCapability> ProcessConsole<'a, C> {
             command[0] = 0;
         });
         self.command_index.set(0);
+        self.history_position.set(0);
     }
 
-    fn write_byte(&self, byte: u8) -> ReturnCode {
+    // Split a command line into the command and its arguments, check them
+    // against the command table, and run the command.
+    fn execute(&self, line: &str) {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return,
+        };
+        let mut arguments = [""; MAX_ARGS];
+        let mut count = 0;
+        for word in words {
+            if count == MAX_ARGS {
+                count += 1;
+                break;
+            }
+            arguments[count] = word;
+            count += 1;
+        }
+
+        match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) if count < command.min_args || count > command.max_args => {
+                if command.arguments.is_empty() {
+                    debug!("Usage: {}", command.name);
+                } else {
+                    debug!("Usage: {} {}", command.name, command.arguments);
+                }
+            }
+            Some(command) => self.run(command.action, &arguments[..count]),
+            None => {
+                debug!("Unknown command {}.", name);
+                self.print_command_names();
+            }
+        }
+    }
+
+    fn print_command_names(&self) {
+        let mut names = [""; COMMANDS.len()];
+        for (name, command) in names.iter_mut().zip(COMMANDS.iter()) {
+            *name = command.name;
+        }
+        debug!("Valid commands are: {}", Words(&names));
+    }
+
+    fn run(&self, action: Action, arguments: &[&str]) {
+        match action {
+            Action::Help => match arguments.first() {
+                Some(name) => match COMMANDS.iter().find(|command| command.name == *name) {
+                    Some(command) => {
+                        if command.arguments.is_empty() {
+                            debug!("{}", command.name);
+                        } else {
+                            debug!("{} {}", command.name, command.arguments);
+                        }
+                        debug!("  {}", command.description);
+                    }
+                    None => debug!("Unknown command {}.", name),
+                },
+                None => {
+                    debug!("Welcome to the process console.");
+                    self.print_command_names();
+                    debug!("Type help <command> for its arguments.");
+                }
+            },
+            Action::Status => {
+                let info: KernelInfo = KernelInfo::new(self.kernel);
+                debug!(
+                    "Total processes: {}",
+                    info.number_loaded_processes(&self.capability)
+                );
+                debug!(
+                    "Active processes: {}",
+                    info.number_active_processes(&self.capability)
+                );
+                debug!(
+                    "Timeslice expirations: {}",
+                    info.timeslice_expirations(&self.capability)
+                );
+                debug!(
+                    "Process CPU time: {} ms",
+                    info.cpu_time_us(&self.capability) / 1000
+                );
+            }
+            Action::List => self.list(),
+            Action::Process => match self.find_process(arguments[0]) {
+                Some(appid) => {
+                    self.memory_map.set((appid, 0));
+                    self.send_memory_map();
+                }
+                None => debug!("No process named {}.", arguments[0]),
+            },
+            Action::Start => self.with_process(arguments[0], |proc| {
+                proc.resume();
+                debug!("Process {} resumed.", proc.get_process_name());
+            }),
+            Action::Stop => self.with_process(arguments[0], |proc| {
+                proc.stop();
+                debug!("Process {} stopped", proc.get_process_name());
+            }),
+            Action::Fault => self.with_process(arguments[0], |proc| {
+                proc.set_fault_state();
+                debug!("Process {} now faulted", proc.get_process_name());
+            }),
+            Action::Restart => self.with_process(arguments[0], |proc| {
+                proc.restart();
+                debug!("Process {} restarted.", proc.get_process_name());
+            }),
+            Action::Kill => self.with_process(arguments[0], |proc| {
+                proc.terminate();
+                debug!("Process {} terminated.", proc.get_process_name());
+            }),
+            Action::Grants => self.grants(arguments.first().cloned()),
+            Action::KernelStats => self.kernel_stats(),
+            Action::Load => {
+                self.process_loader.map_or_else(
+                    || debug!("No process loader configured."),
+                    |loader| match loader.load_new_process(&self.capability) {
+                        Ok(appid) => debug!("Process loaded as {:?}.", appid),
+                        Err(e) => debug!("Failed to load process: {:?}", e),
+                    },
+                );
+            }
+            Action::Unload => {
+                let name = arguments[0];
+                self.process_loader.map_or_else(
+                    || debug!("No process loader configured."),
+                    |loader| match self.find_process(name) {
+                        Some(appid) => match loader.unload_process(appid, &self.capability) {
+                            Ok(()) => debug!("Process {} unloaded.", name),
+                            Err(e) => debug!("Failed to unload process {}: {:?}", name, e),
+                        },
+                        None => debug!("No process named {}.", name),
+                    },
+                );
+            }
+            Action::Replace => {
+                let name = arguments[0];
+                self.process_loader.map_or_else(
+                    || debug!("No process loader configured."),
+                    |loader| match self.find_process(name) {
+                        Some(appid) => match loader.replace_process(appid, &self.capability) {
+                            Ok(_) => debug!("Process {} replaced.", name),
+                            Err(e) => debug!("Failed to replace process {}: {:?}", name, e),
+                        },
+                        None => debug!("No process named {}.", name),
+                    },
+                );
+            }
+            Action::Trace => {
+                self.syscall_trace.map_or_else(
+                    || debug!("No syscall trace configured."),
+                    |trace| self.trace_command(trace, arguments),
+                );
+            }
+            Action::Ip => {
+                self.network_interface.map_or_else(
+                    || debug!("No network interface configured."),
+                    |interface| self.ip_command(interface, arguments),
+                );
+            }
+            Action::Reboot => {
+                self.reboot
+                    .map_or_else(|| debug!("Reboot is not enabled."), |reboot| reboot());
+            }
+        }
+    }
+
+    fn list(&self) {
+        debug!(" PID    Name                Quanta  Syscalls  Dropped Callbacks  Restarts   CPU(ms)    State");
+        self.kernel
+            .process_each_capability(&self.capability, |i, proc| {
+                let pname = proc.get_process_name();
+                debug!(
+                    "  {:02}\t{:<20}{:6}{:10}{:19}{:10}{:10}  {:?}",
+                    i,
+                    pname,
+                    proc.debug_timeslice_expiration_count(),
+                    proc.debug_syscall_count(),
+                    proc.debug_dropped_callback_count(),
+                    proc.debug_restart_count(),
+                    proc.debug_cpu_time_us() / 1000,
+                    proc.get_state()
+                );
+            });
+        debug!(" Syscall latency in us (average/max)");
+        debug!(" PID    Yield      Subscribe  Command    Allow      Memop      RO Allow");
+        self.kernel
+            .process_each_capability(&self.capability, |i, proc| {
+                let latency = |class| {
+                    let l = proc.debug_syscall_latency(class);
+                    (l.average_us(), l.max_us)
+                };
+                let (yield_avg, yield_max) = latency(SyscallClass::Yield);
+                let (sub_avg, sub_max) = latency(SyscallClass::Subscribe);
+                let (cmd_avg, cmd_max) = latency(SyscallClass::Command);
+                let (allow_avg, allow_max) = latency(SyscallClass::Allow);
+                let (memop_avg, memop_max) = latency(SyscallClass::Memop);
+                let (ro_avg, ro_max) = latency(SyscallClass::AllowReadOnly);
+                debug!(
+                    "  {:02}\t{:>4}/{:<6}{:>4}/{:<6}{:>4}/{:<6}{:>4}/{:<6}{:>4}/{:<6}{:>4}/{:<6}",
+                    i,
+                    yield_avg,
+                    yield_max,
+                    sub_avg,
+                    sub_max,
+                    cmd_avg,
+                    cmd_max,
+                    allow_avg,
+                    allow_max,
+                    memop_avg,
+                    memop_max,
+                    ro_avg,
+                    ro_max
+                );
+            });
+    }
+
+    // Print the grant memory of every process, or only of the process with
+    // the given name.
+    fn grants(&self, name: Option<&str>) {
+        if let Some(name) = name {
+            if self.find_process(name).is_none() {
+                debug!("No process named {}.", name);
+                return;
+            }
+        }
+        let info = KernelInfo::new(self.kernel);
+        let grant_count = info.number_grants(&self.capability);
+        debug!(" PID    Name                  Used     Quota  Per-Grant  Exceeded  Grants");
+        self.kernel
+            .process_each_capability(&self.capability, |i, proc| {
+                if name.map_or(false, |name| proc.get_process_name() != name) {
+                    return;
+                }
+                let appid = proc.appid();
+                let quota = info
+                    .app_grant_memory_quota(appid, &self.capability)
+                    .unwrap_or_default();
+                debug!(
+                    "  {:02}\t{:<20}{:6}{:>10}{:>11}{:10}  {}",
+                    i,
+                    proc.get_process_name(),
+                    info.app_grant_memory_used(appid, &self.capability),
+                    Limit(quota.grant_bytes),
+                    Limit(quota.per_grant_bytes),
+                    info.number_app_grant_quota_exceeded(appid, &self.capability),
+                    GrantUsage {
+                        info: &info,
+                        appid: appid,
+                        grant_count: grant_count,
+                        capability: &self.capability,
+                    }
+                );
+            });
+    }
+
+    fn kernel_stats(&self) {
+        let info = KernelInfo::new(self.kernel);
+        debug!(
+            "Interrupts serviced: {}",
+            info.interrupt_service_count(&self.capability)
+        );
+        let stats = info.deferred_calls(&self.capability);
+        debug!(
+            "Deferred calls: {}/{} registered, {} pending",
+            stats.registered, stats.capacity, stats.pending
+        );
+        debug!("Sleeps: {}", info.sleep_count(&self.capability));
+    }
+
+    // Send the next piece of the memory map being printed, unless the write
+    // buffer is in use. `transmitted_buffer()` sends the pieces after that.
+    fn send_memory_map(&self) {
         if self.tx_in_progress.get() {
-            ReturnCode::EBUSY
-        } else {
-            self.tx_in_progress.set(true);
+            return;
+        }
+        self.memory_map.take().map(|(appid, offset)| {
             self.tx_buffer.take().map(|buffer| {
-                buffer[0] = byte;
-                self.uart.transmit_buffer(buffer, 1);
+                let info = KernelInfo::new(self.kernel);
+                let len = info.app_memory_map(appid, offset, buffer, &self.capability);
+                if len == 0 {
+                    self.tx_buffer.replace(buffer);
+                    return;
+                }
+                self.memory_map.set((appid, offset + len));
+                self.transmit(buffer, len);
             });
-            ReturnCode::SUCCESS
+        });
+    }
+
+    fn transmit(&self, buffer: &'static mut [u8], len: usize) {
+        self.tx_in_progress.set(true);
+        let (rcode, buffer) = self.uart.transmit_buffer(buffer, len);
+        if rcode != ReturnCode::SUCCESS {
+            self.tx_in_progress.set(false);
+            buffer.map(|buffer| self.tx_buffer.replace(buffer));
         }
     }
 
+    fn write_byte(&self, byte: u8) -> ReturnCode {
+        self.write_bytes(&[byte])
+    }
+
     fn write_bytes(&self, bytes: &[u8]) -> ReturnCode {
         if self.tx_in_progress.get() {
             ReturnCode::EBUSY
         } else {
-            self.tx_in_progress.set(true);
             self.tx_buffer.take().map(|buffer| {
                 let len = cmp::min(bytes.len(), buffer.len());
                 for i in 0..len {
                     buffer[i] = bytes[i];
                 }
-                self.uart.transmit_buffer(buffer, len);
+                self.transmit(buffer, len);
             });
             ReturnCode::SUCCESS
         }
     }
+
+    // Save a command in the history, unless it repeats the most recent one.
+    // History entries are `entry_len` bytes long, like the command buffer.
+    fn add_to_history(&self, command: &[u8], entry_len: usize) {
+        self.history_buffer.map(|history| {
+            let entries = history.len() / entry_len;
+            if entries == 0 || command.len() >= entry_len {
+                return;
+            }
+            let latest = &history[..entry_len];
+            if self.history_len.get() > 0 && &latest[..command_len(latest)] == command {
+                return;
+            }
+            history.copy_within(0..(entries - 1) * entry_len, entry_len);
+            history[..command.len()].copy_from_slice(command);
+            history[command.len()] = 0;
+            self.history_len
+                .set(cmp::min(self.history_len.get() + 1, entries));
+        });
+    }
+
+    // Replace the command line with the history entry at `position`, or
+    // with an empty line for position 0, and redraw it.
+    fn recall_history(&self, position: usize) {
+        self.command_buffer.map(|command| {
+            let entry_len = command.len();
+            let len = if position == 0 {
+                0
+            } else {
+                self.history_buffer.map_or(0, |history| {
+                    let entry = &history[(position - 1) * entry_len..position * entry_len];
+                    let len = command_len(entry);
+                    command[..len].copy_from_slice(&entry[..len]);
+                    len
+                })
+            };
+            if len < entry_len {
+                command[len] = 0;
+            }
+            self.command_index.set(len);
+            self.history_position.set(position);
+
+            // Return to the start of the line and clear it, then print the
+            // command.
+            let mut line = [0; 64];
+            line[..4].copy_from_slice(&[b'\r', ESCAPE, b'[', b'K']);
+            let end = cmp::min(4 + len, line.len());
+            line[4..end].copy_from_slice(&command[..end - 4]);
+            self.write_bytes(&line[..end]);
+        });
+    }
+
+    // Handle the last byte of an escape sequence. Only the up and down arrow
+    // keys do something.
+    fn escape_sequence(&self, byte: u8) {
+        let position = self.history_position.get();
+        match byte {
+            b'A' if position < self.history_len.get() => self.recall_history(position + 1),
+            b'B' if position > 0 => self.recall_history(position - 1),
+            _ => {}
+        }
+    }
+}
+
+/// Formats a grant memory quota, `-` for no limit.
+struct Limit(Option<usize>);
+
+impl core::fmt::Display for Limit {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.0 {
+            Some(limit) => core::fmt::Display::fmt(&limit, f),
+            None => f.pad("-"),
+        }
+    }
+}
+
+/// Formats the grants a process uses as `grant:bytes` pairs.
+struct GrantUsage<'a> {
+    info: &'a KernelInfo,
+    appid: AppId,
+    grant_count: usize,
+    capability: &'a dyn ProcessManagementCapability,
+}
+
+impl core::fmt::Display for GrantUsage<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let mut first = true;
+        for grant_num in 0..self.grant_count {
+            let used = self
+                .info
+                .app_grant_memory_used_by(self.appid, grant_num, self.capability);
+            if used > 0 {
+                if !first {
+                    f.write_str(" ")?;
+                }
+                first = false;
+                write!(f, "{}:{}", grant_num, used)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a list of words separated by spaces.
+struct Words<'a>(&'a [&'a str]);
+
+impl core::fmt::Display for Words<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for (i, word) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(word)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a, C: ProcessManagementCapability> uart::TransmitClient for ProcessConsole<'a, C> {
     fn transmitted_buffer(&self, buffer: &'static mut [u8], _tx_len: usize, _rcode: ReturnCode) {
-        // Either print more from the AppSlice or send a callback to the
-        // application.
         self.tx_buffer.replace(buffer);
         self.tx_in_progress.set(false);
+        // Continue printing a memory map.
+        self.send_memory_map();
     }
 }
 impl<'a, C: ProcessManagementCapability> uart::ReceiveClient for ProcessConsole<'a, C> {
@@ -319,28 +1077,54 @@ impl<'a, C: ProcessManagementCapability> uart::ReceiveClient for ProcessConsole<
             match rx_len {
                 0 => debug!("ProcessConsole had read of 0 bytes"),
                 1 => {
-                    self.command_buffer.map(|command| {
-                        let index = self.command_index.get() as usize;
-                        if read_buf[0] == ('\n' as u8) || read_buf[0] == ('\r' as u8) {
-                            execute = true;
-                            self.write_bytes(&['\r' as u8, '\n' as u8]);
-                        } else if read_buf[0] == ('\x08' as u8) && index > 0 {
-                            // Backspace, echo and remove last byte
-                            // Note echo is '\b \b' to erase
-                            self.write_bytes(&['\x08' as u8, ' ' as u8, '\x08' as u8]);
-                            command[index - 1] = '\0' as u8;
-                            self.command_index.set(index - 1);
-                        } else if index < (command.len() - 1) && read_buf[0] < 128 {
-                            // For some reason, sometimes reads return > 127 but no error,
-                            // which causes utf-8 decoding failure, so check byte is < 128. -pal
-
-                            // Echo the byte and store it
-                            self.write_byte(read_buf[0]);
-                            command[index] = read_buf[0];
-                            self.command_index.set(index + 1);
-                            command[index + 1] = 0;
+                    let byte = read_buf[0];
+                    match self.escape_state.get() {
+                        EscapeState::Escape => {
+                            self.escape_state.set(if byte == b'[' {
+                                EscapeState::ControlSequence
+                            } else {
+                                EscapeState::None
+                            });
+                        }
+                        EscapeState::ControlSequence => {
+                            // Parameters of the sequence come before the
+                            // final byte, which is a letter or `~`.
+                            if byte.is_ascii_alphabetic() || byte == b'~' {
+                                self.escape_state.set(EscapeState::None);
+                                self.escape_sequence(byte);
+                            }
                         }
-                    });
+                        EscapeState::None if byte == ESCAPE => {
+                            self.escape_state.set(EscapeState::Escape);
+                        }
+                        EscapeState::None => {
+                            self.command_buffer.map(|command| {
+                                let index = self.command_index.get() as usize;
+                                if byte == b'\n' || byte == b'\r' {
+                                    execute = true;
+                                    self.write_bytes(&[b'\r', b'\n']);
+                                } else if (byte == BACKSPACE || byte == DELETE) && index > 0 {
+                                    // Backspace, echo and remove last byte
+                                    // Note echo is '\b \b' to erase
+                                    self.write_bytes(&[BACKSPACE, b' ', BACKSPACE]);
+                                    command[index - 1] = '\0' as u8;
+                                    self.command_index.set(index - 1);
+                                } else if index < (command.len() - 1)
+                                    && byte < 128
+                                    && !byte.is_ascii_control()
+                                {
+                                    // For some reason, sometimes reads return > 127 but no error,
+                                    // which causes utf-8 decoding failure, so check byte is < 128. -pal
+
+                                    // Echo the byte and store it
+                                    self.write_byte(byte);
+                                    command[index] = byte;
+                                    self.command_index.set(index + 1);
+                                    command[index + 1] = 0;
+                                }
+                            });
+                        }
+                    }
                 }
                 _ => debug!(
                     "ProcessConsole issues reads of 1 byte, but receive_complete was length {}",
@@ -0,0 +1,140 @@
+//! Restarts faulted processes after a delay.
+//!
+//! A process whose `FaultPolicy` asks for a restart backoff is left stopped
+//! after it faults, and the kernel asks its `RestartTimer` to restart it
+//! later. This capsule implements `RestartTimer` on top of an alarm. It keeps
+//! one pending restart per process slot, so the board passes it an array with
+//! one entry for each process.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! struct ProcessMgmtCap;
+//! unsafe impl capabilities::ProcessManagementCapability for ProcessMgmtCap {}
+//!
+//! static mut RESTARTS: [Option<(u32, u32)>; NUM_PROCS] = [None; NUM_PROCS];
+//!
+//! let restarter_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let restarter = static_init!(
+//!     capsules::process_restarter::ProcessRestarter<
+//!         'static,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!         ProcessMgmtCap,
+//!     >,
+//!     capsules::process_restarter::ProcessRestarter::new(
+//!         restarter_alarm,
+//!         board_kernel,
+//!         &mut RESTARTS,
+//!         ProcessMgmtCap
+//!     )
+//! );
+//! restarter_alarm.set_client(restarter);
+//! board_kernel.set_restart_timer(restarter);
+//! ```
+
+use core::cmp;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::common::cells::TakeCell;
+use kernel::hil::time::{self, Alarm, Frequency};
+use kernel::procs::{RestartTimer, State};
+use kernel::{AppId, Kernel};
+
+pub struct ProcessRestarter<'a, A: Alarm<'a>, C: ProcessManagementCapability> {
+    alarm: &'a A,
+    kernel: &'static Kernel,
+    /// Pending restart of each process slot as `(reference, delay)` in alarm
+    /// ticks: the process is restarted once `delay` ticks have passed since
+    /// `reference`.
+    restarts: TakeCell<'static, [Option<(u32, u32)>]>,
+    capability: C,
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> ProcessRestarter<'a, A, C> {
+    pub fn new(
+        alarm: &'a A,
+        kernel: &'static Kernel,
+        restarts: &'static mut [Option<(u32, u32)>],
+        capability: C,
+    ) -> ProcessRestarter<'a, A, C> {
+        ProcessRestarter {
+            alarm: alarm,
+            kernel: kernel,
+            restarts: TakeCell::new(restarts),
+            capability: capability,
+        }
+    }
+
+    /// Set the alarm for the earliest pending restart, or disable it if there
+    /// is none. The alarm is set at least a millisecond ahead, as an alarm set
+    /// for a time that has already passed by the time it is armed would only
+    /// fire after the counter wraps around.
+    fn rearm(&self) {
+        let now = self.alarm.now();
+        let next = self.restarts.map_or(None, |restarts| {
+            restarts
+                .iter()
+                .filter_map(|restart| {
+                    restart
+                        .map(|(reference, delay)| delay.saturating_sub(now.wrapping_sub(reference)))
+                })
+                .min()
+        });
+        match next {
+            Some(remaining) => {
+                let min_delay = cmp::max(<A::Frequency>::frequency() / 1000, 1);
+                self.alarm
+                    .set_alarm(now.wrapping_add(cmp::max(remaining, min_delay)))
+            }
+            None => self.alarm.disable(),
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> RestartTimer for ProcessRestarter<'a, A, C> {
+    fn restart_after(&self, appid: AppId, delay_ms: u32) {
+        let frequency = <A::Frequency>::frequency() as u64;
+        let delay = cmp::min(
+            delay_ms as u64 * frequency / 1000,
+            core::u32::MAX as u64 / 2,
+        );
+        let now = self.alarm.now();
+        self.restarts.map(|restarts| {
+            if let Some(restart) = restarts.get_mut(appid.idx()) {
+                *restart = Some((now, delay as u32));
+            }
+        });
+        self.rearm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability> time::AlarmClient
+    for ProcessRestarter<'a, A, C>
+{
+    fn fired(&self) {
+        let now = self.alarm.now();
+        self.restarts.map(|restarts| {
+            for (index, restart) in restarts.iter_mut().enumerate() {
+                let expired = restart.map_or(false, |(reference, delay)| {
+                    now.wrapping_sub(reference) >= delay
+                });
+                if expired {
+                    *restart = None;
+                    // The process may have been restarted or unloaded while
+                    // it was waiting, so only restart it if it is still
+                    // stopped after its fault.
+                    self.kernel
+                        .process_each_capability(&self.capability, |i, process| {
+                            if i == index && process.get_state() == State::StoppedFaulted {
+                                process.restart();
+                            }
+                        });
+                }
+            }
+        });
+        self.rearm();
+    }
+}
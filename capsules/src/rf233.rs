@@ -24,6 +24,7 @@ use kernel::ReturnCode;
 use crate::rf233_const::CSMA_SEED_1;
 use crate::rf233_const::IRQ_MASK;
 use crate::rf233_const::PHY_CC_CCA_MODE_CS_OR_ED;
+use crate::rf233_const::PHY_RSSI_RSSI_MASK;
 use crate::rf233_const::PHY_RSSI_RX_CRC_VALID;
 use crate::rf233_const::PHY_TX_PWR;
 use crate::rf233_const::SHORT_ADDR_0;
@@ -141,6 +142,9 @@ enum InternalState {
     RX_READING_FRAME_DONE, // Now read a register to verify FCS
     RX_READING_FRAME_FCS_DONE,
     RX_ENABLING_RECEPTION, // Re-enabling reception
+
+    // Reading the RSSI for an energy measurement
+    ED_READING_RSSI,
 }
 
 // There are two tricky parts to this capsule: buffer management
@@ -198,6 +202,7 @@ pub struct RF233<'a, S: spi::SpiMasterDevice> {
     sleep_pending: Cell<bool>,
     wake_pending: Cell<bool>,
     power_client_pending: Cell<bool>,
+    ed_pending: Cell<bool>,
     reset_pin: &'a dyn gpio::Pin,
     sleep_pin: &'a dyn gpio::Pin,
     irq_pin: &'a dyn gpio::InterruptPin,
@@ -209,6 +214,7 @@ pub struct RF233<'a, S: spi::SpiMasterDevice> {
     rx_client: OptionalCell<&'static dyn radio::RxClient>,
     cfg_client: OptionalCell<&'static dyn radio::ConfigClient>,
     power_client: OptionalCell<&'static dyn radio::PowerClient>,
+    ed_client: OptionalCell<&'static dyn radio::EnergyDetectClient>,
     addr: Cell<u16>,
     addr_long: Cell<[u8; 8]>,
     pan: Cell<u16>,
@@ -271,6 +277,20 @@ fn power_to_setting(power: i8) -> u8 {
     }
 }
 
+// The received power of the lowest RSSI reading, for the 250 kb/s O-QPSK
+// mode (RF233 datasheet, 11.2).
+const RSSI_BASE_VAL: i8 = -94;
+
+/// Converts the RSSI field of PHY_RSSI to the received power in dBm. Each
+/// step above 0 is 3 dB, and 0 means a power below RSSI_BASE_VAL.
+fn rssi_to_power(rssi: u8) -> i8 {
+    if rssi == 0 {
+        RSSI_BASE_VAL - 3
+    } else {
+        RSSI_BASE_VAL + 3 * (rssi as i8 - 1)
+    }
+}
+
 fn interrupt_included(mask: u8, interrupt: InteruptFlags) -> bool {
     let int = interrupt as u8;
     (mask & int) == int
@@ -432,6 +452,13 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                         RF233TrxCmd::OFF as u8,
                         InternalState::SLEEP_TRX_OFF,
                     );
+                } else if self.ed_pending.get() {
+                    // The RSSI is continuously updated while receiving,
+                    // so a single read measures the energy on the channel
+                    self.state_transition_read(
+                        RF233Register::PHY_RSSI,
+                        InternalState::ED_READING_RSSI,
+                    );
                 } else if self.power_client_pending.get() {
                     // fixes bug where client would start transmitting before this state completed
                     self.power_client_pending.set(false);
@@ -1013,6 +1040,16 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                     c.config_done(ReturnCode::SUCCESS);
                 });
             }
+            InternalState::ED_READING_RSSI => {
+                self.ed_pending.set(false);
+                self.state_transition_read(RF233Register::TRX_STATUS, InternalState::READY);
+                self.ed_client.map(|c| {
+                    c.energy_detect_done(
+                        rssi_to_power(result & PHY_RSSI_RSSI_MASK),
+                        ReturnCode::SUCCESS,
+                    );
+                });
+            }
         }
     }
 }
@@ -1048,6 +1085,7 @@ impl<S: spi::SpiMasterDevice> RF233<'a, S> {
             sleep_pending: Cell::new(false),
             wake_pending: Cell::new(false),
             power_client_pending: Cell::new(false),
+            ed_pending: Cell::new(false),
             tx_buf: TakeCell::empty(),
             rx_buf: TakeCell::empty(),
             tx_len: Cell::new(0),
@@ -1055,6 +1093,7 @@ impl<S: spi::SpiMasterDevice> RF233<'a, S> {
             rx_client: OptionalCell::empty(),
             cfg_client: OptionalCell::empty(),
             power_client: OptionalCell::empty(),
+            ed_client: OptionalCell::empty(),
             addr: Cell::new(0),
             addr_long: Cell::new([0x00; 8]),
             pan: Cell::new(0),
@@ -1327,6 +1366,28 @@ impl<S: spi::SpiMasterDevice> radio::RadioConfig for RF233<'a, S> {
             }
         }
     }
+
+    fn energy_detect(&self) -> ReturnCode {
+        if !self.radio_on.get() {
+            return ReturnCode::EOFF;
+        } else if self.ed_pending.get() {
+            return ReturnCode::EBUSY;
+        }
+        self.ed_pending.set(true);
+        if self.state.get() == InternalState::READY
+            && !self.spi_busy.get()
+            && !self.config_pending.get()
+        {
+            self.state_transition_read(RF233Register::PHY_RSSI, InternalState::ED_READING_RSSI);
+        } else {
+            // The measurement starts on return to READY
+        }
+        ReturnCode::SUCCESS
+    }
+
+    fn set_energy_detect_client(&self, client: &'static dyn radio::EnergyDetectClient) {
+        self.ed_client.set(client);
+    }
 }
 
 impl<S: spi::SpiMasterDevice> radio::RadioData for RF233<'a, S> {
@@ -0,0 +1,253 @@
+//! Software implementation of SHA-256 and HMAC-SHA256.
+//!
+//! This is a small, synchronous implementation for kernel code that has to
+//! hash data before anything else runs, such as the app credentials checker
+//! used while processes are loaded. It is not constant time with respect to
+//! the length of the data, and it is much slower than a hardware hash engine.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let mut sha = Sha256::new();
+//! sha.update(b"abc");
+//! let digest: [u8; 32] = sha.finish();
+//!
+//! let mac = capsules::sha256::hmac_sha256(key, &[message]);
+//! ```
+
+/// Length of a SHA-256 digest, in bytes.
+pub const DIGEST_LEN: usize = 32;
+
+/// Length of a SHA-256 block, in bytes.
+const BLOCK_LEN: usize = 64;
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Incremental SHA-256 hash.
+pub struct Sha256 {
+    state: [u32; 8],
+    /// Data that does not fill a whole block yet.
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+    /// Total length of the data, in bytes.
+    length: u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Sha256 {
+        Sha256 {
+            state: INITIAL_STATE,
+            buffer: [0; BLOCK_LEN],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    /// Add `data` to the hash.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+
+        if self.buffered > 0 {
+            let take = core::cmp::min(BLOCK_LEN - self.buffered, data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < BLOCK_LEN {
+                return;
+            }
+            let block = self.buffer;
+            self.compress(&block);
+            self.buffered = 0;
+        }
+
+        while data.len() >= BLOCK_LEN {
+            self.compress(&data[..BLOCK_LEN]);
+            data = &data[BLOCK_LEN..];
+        }
+
+        self.buffer[..data.len()].copy_from_slice(data);
+        self.buffered = data.len();
+    }
+
+    /// Finish the hash and return the digest.
+    pub fn finish(mut self) -> [u8; DIGEST_LEN] {
+        let bit_length = self.length.wrapping_mul(8);
+
+        // Pad with a one bit, zeros, and the length in bits, so that the
+        // padded data is a multiple of the block length.
+        let mut padding = [0; BLOCK_LEN + 8];
+        padding[0] = 0x80;
+        let zeros = (BLOCK_LEN + BLOCK_LEN - 8 - 1 - self.buffered) % BLOCK_LEN;
+        padding[1 + zeros..1 + zeros + 8].copy_from_slice(&bit_length.to_be_bytes());
+        let length = self.length;
+        self.update(&padding[..1 + zeros + 8]);
+        self.length = length;
+
+        let mut digest = [0; DIGEST_LEN];
+        for (i, word) in self.state.iter().enumerate() {
+            digest[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    /// Process one 64-byte block.
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            let mut word = [0; 4];
+            word.copy_from_slice(&block[4 * i..4 * i + 4]);
+            w[i] = u32::from_be_bytes(word);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut v = self.state;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(ROUND_CONSTANTS[i])
+                .wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v = [
+                t1.wrapping_add(t2),
+                v[0],
+                v[1],
+                v[2],
+                v[3].wrapping_add(t1),
+                v[4],
+                v[5],
+                v[6],
+            ];
+        }
+
+        for (state, value) in self.state.iter_mut().zip(v.iter()) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+}
+
+/// SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut sha = Sha256::new();
+    sha.update(data);
+    sha.finish()
+}
+
+/// HMAC-SHA256 (RFC 2104) of the concatenation of the slices in `message`,
+/// with `key`.
+pub fn hmac_sha256(key: &[u8], message: &[&[u8]]) -> [u8; DIGEST_LEN] {
+    let mut block_key = [0; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block_key[..DIGEST_LEN].copy_from_slice(&sha256(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let mut pad = [0; BLOCK_LEN];
+    for (pad, key) in pad.iter_mut().zip(block_key.iter()) {
+        *pad = key ^ 0x36;
+    }
+    let mut inner = Sha256::new();
+    inner.update(&pad);
+    for part in message {
+        inner.update(part);
+    }
+    let inner = inner.finish();
+
+    for (pad, key) in pad.iter_mut().zip(block_key.iter()) {
+        *pad = key ^ 0x5c;
+    }
+    let mut outer = Sha256::new();
+    outer.update(&pad);
+    outer.update(&inner);
+    outer.finish()
+}
+
+/// Compare two digests without returning early, so that the time taken does
+/// not reveal how many bytes match.
+pub fn digests_equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hmac_sha256, sha256, Sha256};
+
+    fn hex(digest: &[u8]) -> [u8; 64] {
+        let mut out = [0; 64];
+        for (i, byte) in digest.iter().enumerate() {
+            out[2 * i] = b"0123456789abcdef"[(byte >> 4) as usize];
+            out[2 * i + 1] = b"0123456789abcdef"[(byte & 0xf) as usize];
+        }
+        out
+    }
+
+    #[test]
+    fn sha256_known_answers() {
+        assert_eq!(
+            &hex(&sha256(b""))[..],
+            &b"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"[..]
+        );
+        assert_eq!(
+            &hex(&sha256(b"abc"))[..],
+            &b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"[..]
+        );
+        assert_eq!(
+            &hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ))[..],
+            &b"248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"[..]
+        );
+    }
+
+    #[test]
+    fn sha256_incremental() {
+        let data = [0x61; 1000];
+        let mut sha = Sha256::new();
+        for chunk in data.chunks(7) {
+            sha.update(chunk);
+        }
+        assert_eq!(sha.finish(), sha256(&data));
+    }
+
+    #[test]
+    fn hmac_sha256_known_answer() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            &hex(&hmac_sha256(
+                b"Jefe",
+                &[b"what do ya want ", b"for nothing?"]
+            ))[..],
+            &b"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"[..]
+        );
+    }
+}
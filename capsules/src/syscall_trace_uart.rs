@@ -0,0 +1,80 @@
+//! Sends syscall trace records over a UART.
+//!
+//! This is a `TraceWriter` for the kernel's `SyscallTrace` that writes the
+//! encoded records to a UART of its own, so that the trace does not compete
+//! with `debug!()` output for space in the debug buffer. With a Segger RTT
+//! channel as the UART, the trace can be read over the debugger without any
+//! extra pins.
+//!
+//! Records are only sent when the kernel is idle. If a transmission is still
+//! in progress at that point, the records stay buffered until the next time
+//! the kernel is idle.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! static mut TRACE_RECORDS: [TraceRecord; 32] = [TraceRecord::EMPTY; 32];
+//! static mut TRACE_BUF: [u8; 4 * kernel::syscall_trace::FRAME_LEN] =
+//!     [0; 4 * kernel::syscall_trace::FRAME_LEN];
+//!
+//! let trace_ring = static_init!(
+//!     RingBuffer<'static, TraceRecord>,
+//!     RingBuffer::new(&mut TRACE_RECORDS)
+//! );
+//! let trace = static_init!(SyscallTrace, SyscallTrace::new(trace_ring));
+//! let trace_uart = static_init!(
+//!     capsules::syscall_trace_uart::SyscallTraceUart<'static>,
+//!     capsules::syscall_trace_uart::SyscallTraceUart::new(rtt, &mut TRACE_BUF)
+//! );
+//! hil::uart::Transmit::set_transmit_client(rtt, trace_uart);
+//! trace.set_writer(trace_uart);
+//! board_kernel.set_syscall_trace(trace);
+//! ```
+
+use kernel::common::cells::TakeCell;
+use kernel::hil::uart;
+use kernel::syscall_trace::{SyscallTrace, TraceWriter};
+use kernel::ReturnCode;
+
+pub struct SyscallTraceUart<'a> {
+    uart: &'a dyn uart::Transmit<'a>,
+    /// Buffer the records are encoded into. It should be a multiple of
+    /// `FRAME_LEN` bytes long.
+    tx_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a> SyscallTraceUart<'a> {
+    pub fn new(
+        uart: &'a dyn uart::Transmit<'a>,
+        tx_buffer: &'static mut [u8],
+    ) -> SyscallTraceUart<'a> {
+        SyscallTraceUart {
+            uart: uart,
+            tx_buffer: TakeCell::new(tx_buffer),
+        }
+    }
+}
+
+impl TraceWriter for SyscallTraceUart<'a> {
+    fn write_trace(&self, trace: &SyscallTrace) {
+        // If the buffer is not here, a transmission is in progress.
+        self.tx_buffer.take().map(|buffer| {
+            let len = trace.drain(buffer);
+            if len == 0 {
+                self.tx_buffer.replace(buffer);
+            } else {
+                let (_rval, buffer) = self.uart.transmit_buffer(buffer, len);
+                self.tx_buffer.put(buffer);
+            }
+        });
+    }
+}
+
+impl uart::TransmitClient for SyscallTraceUart<'a> {
+    fn transmitted_buffer(&self, buffer: &'static mut [u8], _tx_len: usize, _rcode: ReturnCode) {
+        self.tx_buffer.replace(buffer);
+    }
+
+    fn transmitted_word(&self, _rcode: ReturnCode) {}
+}
@@ -0,0 +1,152 @@
+//! Virtualize the Rng interface to enable multiple users of an underlying
+//! random number generator.
+//!
+//! Each user gets a `VirtualRngDevice`. The mux keeps the underlying RNG
+//! running while any device has requested randomness, and passes the same
+//! iterator of random numbers to each of them in turn, so a client that needs
+//! more than is left returns `Continue::More` and is called again.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let mux_rng = static_init!(
+//!     capsules::virtual_rng::MuxRng<'static>,
+//!     capsules::virtual_rng::MuxRng::new(entropy_to_random)
+//! );
+//! entropy_to_random.set_client(mux_rng);
+//!
+//! let rng_device = static_init!(
+//!     capsules::virtual_rng::VirtualRngDevice<'static>,
+//!     capsules::virtual_rng::VirtualRngDevice::new(mux_rng)
+//! );
+//! rng_device.setup();
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use kernel::common::{List, ListLink, ListNode};
+use kernel::hil::rng::{Client, Continue, Rng};
+use kernel::ReturnCode;
+
+pub struct VirtualRngDevice<'a> {
+    mux: &'a MuxRng<'a>,
+    next: ListLink<'a, VirtualRngDevice<'a>>,
+    client: OptionalCell<&'a dyn Client>,
+    requested: Cell<bool>,
+}
+
+impl ListNode<'a, VirtualRngDevice<'a>> for VirtualRngDevice<'a> {
+    fn next(&self) -> &'a ListLink<VirtualRngDevice<'a>> {
+        &self.next
+    }
+}
+
+impl VirtualRngDevice<'a> {
+    pub fn new(mux: &'a MuxRng<'a>) -> VirtualRngDevice<'a> {
+        VirtualRngDevice {
+            mux,
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+            requested: Cell::new(false),
+        }
+    }
+
+    /// Add the device to its mux. This must be called once before the
+    /// device is used.
+    pub fn setup(&'a self) {
+        self.mux.devices.push_head(self);
+    }
+}
+
+impl Rng<'a> for VirtualRngDevice<'a> {
+    fn get(&self) -> ReturnCode {
+        self.requested.set(true);
+        let result = self.mux.do_next_op();
+        if result != ReturnCode::SUCCESS {
+            self.requested.set(false);
+        }
+        result
+    }
+
+    fn cancel(&self) -> ReturnCode {
+        self.requested.set(false);
+        self.mux.cancel_unrequested()
+    }
+
+    fn set_client(&'a self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+}
+
+pub struct MuxRng<'a> {
+    rng: &'a dyn Rng<'a>,
+    devices: List<'a, VirtualRngDevice<'a>>,
+    running: Cell<bool>,
+}
+
+impl MuxRng<'a> {
+    pub const fn new(rng: &'a dyn Rng<'a>) -> MuxRng<'a> {
+        MuxRng {
+            rng,
+            devices: List::new(),
+            running: Cell::new(false),
+        }
+    }
+
+    fn is_requested(&self) -> bool {
+        self.devices.iter().any(|device| device.requested.get())
+    }
+
+    // Start the underlying RNG if a device is waiting for randomness.
+    fn do_next_op(&self) -> ReturnCode {
+        if self.running.get() || !self.is_requested() {
+            return ReturnCode::SUCCESS;
+        }
+        let result = self.rng.get();
+        if result == ReturnCode::SUCCESS {
+            self.running.set(true);
+        }
+        result
+    }
+
+    // Stop the underlying RNG once no device is waiting for randomness.
+    fn cancel_unrequested(&self) -> ReturnCode {
+        if !self.running.get() || self.is_requested() {
+            return ReturnCode::SUCCESS;
+        }
+        let result = self.rng.cancel();
+        if result == ReturnCode::SUCCESS {
+            self.running.set(false);
+        }
+        result
+    }
+}
+
+impl Client for MuxRng<'a> {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: ReturnCode,
+    ) -> Continue {
+        for device in self.devices.iter() {
+            if device.requested.get() {
+                // Clients may ask for more randomness from their callback
+                device.requested.set(false);
+                let result = device.client.map_or(Continue::Done, |client| {
+                    client.randomness_available(randomness, error)
+                });
+                if result == Continue::More {
+                    device.requested.set(true);
+                }
+            }
+        }
+
+        if self.is_requested() {
+            Continue::More
+        } else {
+            self.running.set(false);
+            Continue::Done
+        }
+    }
+}
@@ -17,8 +17,9 @@
 //! // Create a shared UART channel for the console and for kernel debug.
 //! let uart_mux = static_init!(
 //!     MuxUart<'static>,
-//!     MuxUart::new(&sam4l::usart::USART0, &mut capsules::virtual_uart::RX_BUF)
+//!     MuxUart::new(&sam4l::usart::USART0, &mut capsules::virtual_uart::RX_BUF, 115200)
 //! )
+//! uart_mux.register();
 //! hil::uart::UART::set_receive_client(&sam4l::usart::USART0, uart_mux);
 //! hil::uart::UART::set_transmit_client(&sam4l::usart::USART0, uart_mux);
 //!
@@ -43,11 +44,10 @@ use core::cell::Cell;
 use core::cmp;
 
 use kernel::common::cells::{OptionalCell, TakeCell};
-use kernel::common::dynamic_deferred_call::{
-    DeferredCallHandle, DynamicDeferredCall, DynamicDeferredCallClient,
-};
+use kernel::common::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::common::{List, ListLink, ListNode};
 use kernel::hil::uart;
+use kernel::power::{self, PowerRequirement};
 use kernel::ReturnCode;
 
 const RX_BUF_LEN: usize = 64;
@@ -60,8 +60,7 @@ pub struct MuxUart<'a> {
     inflight: OptionalCell<&'a UartDevice<'a>>,
     buffer: TakeCell<'static, [u8]>,
     completing_read: Cell<bool>,
-    deferred_caller: &'a DynamicDeferredCall,
-    handle: OptionalCell<DeferredCallHandle>,
+    deferred_call: DeferredCall,
 }
 
 impl<'a> uart::TransmitClient for MuxUart<'a> {
@@ -82,6 +81,7 @@ impl<'a> uart::ReceiveClient for MuxUart<'a> {
         rcode: ReturnCode,
         error: uart::Error,
     ) {
+        power::release(PowerRequirement::UartReceive);
         let mut next_read_len = RX_BUF_LEN;
         let mut read_pending = false;
         self.completing_read.set(true);
@@ -127,7 +127,19 @@ impl<'a> uart::ReceiveClient for MuxUart<'a> {
                     // more data.
                     if remaining == 0 {
                         device.state.set(UartDeviceReceiveState::Idle);
-                        device.received_buffer(rxbuf, position, rcode, error);
+                        // Aborting the underlying read, to start a read of a
+                        // different length or for another device, does not
+                        // affect the reads it completed.
+                        if error == uart::Error::Aborted {
+                            device.received_buffer(
+                                rxbuf,
+                                position,
+                                ReturnCode::SUCCESS,
+                                uart::Error::None,
+                            );
+                        } else {
+                            device.received_buffer(rxbuf, position, rcode, error);
+                        }
                         // Need to check if receive was called in callback
                         if device.state.get() == UartDeviceReceiveState::Receiving {
                             read_pending = true;
@@ -160,12 +172,7 @@ impl<'a> uart::ReceiveClient for MuxUart<'a> {
 }
 
 impl<'a> MuxUart<'a> {
-    pub fn new(
-        uart: &'a dyn uart::Uart<'a>,
-        buffer: &'static mut [u8],
-        speed: u32,
-        deferred_caller: &'a DynamicDeferredCall,
-    ) -> MuxUart<'a> {
+    pub fn new(uart: &'a dyn uart::Uart<'a>, buffer: &'static mut [u8], speed: u32) -> MuxUart<'a> {
         MuxUart {
             uart: uart,
             speed: speed,
@@ -173,8 +180,7 @@ impl<'a> MuxUart<'a> {
             inflight: OptionalCell::empty(),
             buffer: TakeCell::new(buffer),
             completing_read: Cell::new(false),
-            deferred_caller: deferred_caller,
-            handle: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
         }
     }
 
@@ -188,10 +194,6 @@ impl<'a> MuxUart<'a> {
         });
     }
 
-    pub fn initialize_callback_handle(&self, handle: DeferredCallHandle) {
-        self.handle.replace(handle);
-    }
-
     fn do_next_op(&self) {
         if self.inflight.is_none() {
             let mnode = self.devices.iter().find(|node| node.operation.is_some());
@@ -248,7 +250,10 @@ impl<'a> MuxUart<'a> {
             },
             |rxbuf| {
                 let len = cmp::min(rx_len, rxbuf.len());
-                self.uart.receive_buffer(rxbuf, len);
+                let (rcode, _) = self.uart.receive_buffer(rxbuf, len);
+                if rcode == ReturnCode::SUCCESS {
+                    power::vote(PowerRequirement::UartReceive);
+                }
                 false
             },
         )
@@ -263,14 +268,18 @@ impl<'a> MuxUart<'a> {
     ///
     /// https://github.com/tock/tock/issues/1496
     fn do_next_op_async(&self) {
-        self.handle.map(|handle| self.deferred_caller.set(*handle));
+        self.deferred_call.set();
     }
 }
 
-impl<'a> DynamicDeferredCallClient for MuxUart<'a> {
-    fn call(&self, _handle: DeferredCallHandle) {
+impl DeferredCallClient for MuxUart<'static> {
+    fn handle_deferred_call(&self) {
         self.do_next_op();
     }
+
+    fn register(&'static self) -> ReturnCode {
+        self.deferred_call.register(self)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -0,0 +1,331 @@
+//! Services a hardware watchdog from the kernel loop while watched tasks
+//! keep checking in.
+//!
+//! `WatchdogService` implements `kernel::watchdog::KernelWatchdog`: the
+//! kernel loop kicks it on every iteration, and it tickles the hardware
+//! watchdog as long as every watched task has checked in within its
+//! deadline. If a task misses its deadline, the kernel panics with the name
+//! of the task, and since the hardware watchdog is no longer tickled it
+//! resets the board after the panic has been printed. If the kernel loop
+//! itself stops running, for example because a capsule hangs, the hardware
+//! watchdog resets the board as well.
+//!
+//! Capsules register a `WatchedTask` with `watch()` and call
+//! `WatchedTask::check_in()` whenever they make progress. Check-ins are
+//! noticed on the next kick, so the deadline should allow for the longest
+//! time the kernel loop may take for one iteration, such as a process
+//! timeslice.
+//!
+//! Processes can be watched too, through the syscall interface. A process
+//! that misses its deadline does not reset the board: it faults, and its
+//! fault policy decides whether it is restarted. Only the time the process
+//! is able to run counts against its deadline, so it need not check in while
+//! it waits for a callback. The service keeps one deadline per process slot,
+//! so the board passes it an array with one entry for each process.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! static mut APP_DEADLINES: [Option<(u32, u32)>; NUM_PROCS] = [None; NUM_PROCS];
+//!
+//! pub struct ProcessMgmtCap;
+//! unsafe impl capabilities::ProcessManagementCapability for ProcessMgmtCap {}
+//!
+//! let watchdog = static_init!(
+//!     capsules::watchdog::WatchdogService<
+//!         'static,
+//!         sam4l::ast::Ast,
+//!         sam4l::wdt::Wdt,
+//!         ProcessMgmtCap,
+//!     >,
+//!     capsules::watchdog::WatchdogService::new(
+//!         &sam4l::ast::AST,
+//!         &sam4l::wdt::WDT,
+//!         1000,
+//!         board_kernel,
+//!         &mut APP_DEADLINES,
+//!         ProcessMgmtCap
+//!     )
+//! );
+//! board_kernel.set_watchdog(watchdog);
+//! let cleanup_hook = static_init!(GrantCleanupHook, GrantCleanupHook::new(watchdog));
+//! board_kernel.register_grant_cleanup_hook(cleanup_hook);
+//!
+//! // A capsule that must make progress at least every 5 seconds.
+//! let task = static_init!(
+//!     capsules::watchdog::WatchedTask<'static>,
+//!     capsules::watchdog::WatchedTask::new("sensor poll", 5000)
+//! );
+//! watchdog.watch(task);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::common::cells::TakeCell;
+use kernel::common::{List, ListLink, ListNode};
+use kernel::hil::time::{Frequency, Time};
+use kernel::hil::watchdog::Watchdog;
+use kernel::procs::State;
+use kernel::watchdog::KernelWatchdog;
+use kernel::{AppId, Driver, GrantCleanupClient, Kernel, ReturnCode};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Watchdog as usize;
+
+/// A capsule that must check in with the watchdog within a deadline.
+pub struct WatchedTask<'a> {
+    name: &'static str,
+    deadline_ms: u32,
+    /// Whether the task is being watched.
+    active: Cell<bool>,
+    /// Set by `check_in()` until the next kick notices it.
+    checked_in: Cell<bool>,
+    /// Time of the last check-in the watchdog noticed, in ticks of its
+    /// time source.
+    reference: Cell<u32>,
+    next: ListLink<'a, WatchedTask<'a>>,
+}
+
+impl WatchedTask<'a> {
+    /// A task named `name`, which must check in at least every
+    /// `deadline_ms` milliseconds once it is watched.
+    pub const fn new(name: &'static str, deadline_ms: u32) -> WatchedTask<'a> {
+        WatchedTask {
+            name: name,
+            deadline_ms: deadline_ms,
+            active: Cell::new(true),
+            checked_in: Cell::new(true),
+            reference: Cell::new(0),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Report progress, restarting the deadline. This also resumes watching
+    /// a task that was paused.
+    pub fn check_in(&self) {
+        self.active.set(true);
+        self.checked_in.set(true);
+    }
+
+    /// Stop watching the task until it checks in again, for example while it
+    /// has nothing to do.
+    pub fn pause(&self) {
+        self.active.set(false);
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl ListNode<'a, WatchedTask<'a>> for WatchedTask<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, WatchedTask<'a>> {
+        &self.next
+    }
+}
+
+pub struct WatchdogService<'a, T: Time, W: Watchdog, C: ProcessManagementCapability> {
+    time: &'a T,
+    watchdog: &'a W,
+    period_ms: usize,
+    running: Cell<bool>,
+    tasks: List<'a, WatchedTask<'a>>,
+    kernel: &'static Kernel,
+    /// Deadline of each process slot as `(reference, deadline)` in ticks:
+    /// the process must check in before `deadline` ticks have passed since
+    /// `reference`. The reference moves forward while the process cannot
+    /// run.
+    app_deadlines: TakeCell<'static, [Option<(u32, u32)>]>,
+    /// Time of the last kick, in ticks.
+    last_check: Cell<u32>,
+    capability: C,
+}
+
+impl<T: Time, W: Watchdog, C: ProcessManagementCapability> WatchdogService<'a, T, W, C> {
+    /// Service `watchdog`, which resets the board if it is not tickled for
+    /// `period_ms` milliseconds, measuring deadlines with `time`. Processes
+    /// of `kernel` that miss their deadline are faulted with `capability`.
+    pub fn new(
+        time: &'a T,
+        watchdog: &'a W,
+        period_ms: usize,
+        kernel: &'static Kernel,
+        app_deadlines: &'static mut [Option<(u32, u32)>],
+        capability: C,
+    ) -> WatchdogService<'a, T, W, C> {
+        WatchdogService {
+            time,
+            watchdog,
+            period_ms,
+            running: Cell::new(false),
+            tasks: List::new(),
+            kernel,
+            app_deadlines: TakeCell::new(app_deadlines),
+            last_check: Cell::new(0),
+            capability,
+        }
+    }
+
+    /// Start watching `task`. Its first deadline starts now.
+    pub fn watch(&self, task: &'a WatchedTask<'a>) {
+        self.tasks.push_tail(task);
+    }
+
+    fn ms_to_ticks(ms: u32) -> u32 {
+        let frequency = <T::Frequency>::frequency() as u64;
+        cmp::min(ms as u64 * frequency / 1000, core::u32::MAX as u64 / 2) as u32
+    }
+
+    /// Panic if a task missed its deadline, and fault every process that
+    /// missed its own.
+    fn check_deadlines(&self) {
+        let now = self.time.now();
+        let elapsed = if self.running.get() {
+            now.wrapping_sub(self.last_check.get())
+        } else {
+            0
+        };
+        self.last_check.set(now);
+        for task in self.tasks.iter() {
+            if task.checked_in.get() {
+                task.checked_in.set(false);
+                task.reference.set(now);
+            } else if task.active.get()
+                && now.wrapping_sub(task.reference.get()) > Self::ms_to_ticks(task.deadline_ms)
+            {
+                panic!("Watchdog: task {} missed its deadline", task.name);
+            }
+        }
+        self.kernel
+            .process_each_capability(&self.capability, |index, process| {
+                // A process that waits for a callback, or that is stopped,
+                // cannot check in, so the time since the last kick does not
+                // count against its deadline.
+                let runnable = process.get_state() == State::Running;
+                let missed = self.app_deadlines.map_or(false, |deadlines| {
+                    let slot = match deadlines.get_mut(index) {
+                        Some(slot) => slot,
+                        None => return false,
+                    };
+                    let missed = match slot {
+                        Some((reference, _)) if !runnable => {
+                            *reference = reference.wrapping_add(elapsed);
+                            false
+                        }
+                        Some((reference, deadline)) => now.wrapping_sub(*reference) > *deadline,
+                        None => false,
+                    };
+                    if missed {
+                        // The process starts over if its fault policy
+                        // restarts it.
+                        *slot = None;
+                    }
+                    missed
+                });
+                if missed {
+                    process.set_fault_state();
+                }
+            });
+    }
+
+    fn set_app_deadline(&self, appid: AppId, deadline: Option<(u32, u32)>) -> ReturnCode {
+        self.app_deadlines.map_or(ReturnCode::FAIL, |deadlines| {
+            deadlines
+                .get_mut(appid.idx())
+                .map_or(ReturnCode::ENOMEM, |slot| {
+                    *slot = deadline;
+                    ReturnCode::SUCCESS
+                })
+        })
+    }
+}
+
+impl<T: Time, W: Watchdog, C: ProcessManagementCapability> KernelWatchdog
+    for WatchdogService<'a, T, W, C>
+{
+    fn kick(&self) {
+        self.check_deadlines();
+        if self.running.get() {
+            self.watchdog.tickle();
+        } else {
+            self.running.set(true);
+            self.watchdog.start(self.period_ms);
+        }
+    }
+
+    fn suspend(&self) {
+        if self.running.get() {
+            self.watchdog.stop();
+        }
+    }
+
+    fn resume(&self) {
+        if self.running.get() {
+            self.watchdog.start(self.period_ms);
+        }
+    }
+}
+
+impl<T: Time, W: Watchdog, C: ProcessManagementCapability> GrantCleanupClient
+    for WatchdogService<'a, T, W, C>
+{
+    fn process_teardown(&self, appid: AppId) {
+        // A process that is gone cannot check in, so stop watching it. It
+        // starts over when it runs again.
+        self.set_app_deadline(appid, None);
+    }
+}
+
+impl<T: Time, W: Watchdog, C: ProcessManagementCapability> Driver for WatchdogService<'a, T, W, C> {
+    /// Watch the calling process. All commands complete at once, so there
+    /// are no callbacks.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Start watching the process, which must check in at least
+    ///        every `arg1` milliseconds from now on.
+    /// - `2`: Check in, restarting the deadline. Returns `EOFF` if the
+    ///        process is not being watched.
+    /// - `3`: Stop watching the process.
+    fn command(&self, command_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 =>
+            /* This driver exists. */
+            {
+                ReturnCode::SUCCESS
+            }
+
+            // Start watching.
+            1 => {
+                if arg1 == 0 {
+                    return ReturnCode::EINVAL;
+                }
+                let deadline = Self::ms_to_ticks(arg1 as u32);
+                self.set_app_deadline(appid, Some((self.time.now(), deadline)))
+            }
+
+            // Check in.
+            2 => {
+                let now = self.time.now();
+                self.app_deadlines.map_or(ReturnCode::FAIL, |deadlines| {
+                    match deadlines.get_mut(appid.idx()) {
+                        Some(Some((reference, _))) => {
+                            *reference = now;
+                            ReturnCode::SUCCESS
+                        }
+                        _ => ReturnCode::EOFF,
+                    }
+                })
+            }
+
+            // Stop watching.
+            3 => self.set_app_deadline(appid, None),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
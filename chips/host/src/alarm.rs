@@ -0,0 +1,137 @@
+//! Alarm based on the host clock.
+//!
+//! The counter runs at 32 kHz from the time the chip is created. A host
+//! thread sleeps until the alarm expires, and then raises the alarm
+//! interrupt.
+
+use std::cell::Cell;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::time::{self, Alarm, Freq32KHz, Frequency, Time};
+
+use crate::events::Events;
+use crate::interrupts;
+
+/// When the alarm thread should raise the alarm interrupt, if at all.
+struct Deadline {
+    deadline: Mutex<Option<Instant>>,
+    changed: Condvar,
+}
+
+pub struct HostAlarm<'a> {
+    start: Instant,
+    alarm: Cell<u32>,
+    enabled: Cell<bool>,
+    client: OptionalCell<&'a dyn time::AlarmClient>,
+    deadline: Arc<Deadline>,
+}
+
+impl HostAlarm<'a> {
+    crate fn new(events: &'static Events) -> HostAlarm<'a> {
+        let deadline = Arc::new(Deadline {
+            deadline: Mutex::new(None),
+            changed: Condvar::new(),
+        });
+        let thread_deadline = deadline.clone();
+        thread::Builder::new()
+            .name("alarm".to_string())
+            .spawn(move || {
+                let mut deadline = thread_deadline.deadline.lock().unwrap();
+                loop {
+                    deadline = match *deadline {
+                        Some(expiration) => {
+                            let now = Instant::now();
+                            if now >= expiration {
+                                *deadline = None;
+                                events.raise(interrupts::ALARM);
+                                continue;
+                            }
+                            thread_deadline
+                                .changed
+                                .wait_timeout(deadline, expiration - now)
+                                .unwrap()
+                                .0
+                        }
+                        None => thread_deadline.changed.wait(deadline).unwrap(),
+                    };
+                }
+            })
+            .expect("failed to start alarm thread");
+
+        HostAlarm {
+            start: Instant::now(),
+            alarm: Cell::new(0),
+            enabled: Cell::new(false),
+            client: OptionalCell::empty(),
+            deadline: deadline,
+        }
+    }
+
+    fn set_deadline(&self, deadline: Option<Instant>) {
+        *self.deadline.deadline.lock().unwrap() = deadline;
+        self.deadline.changed.notify_all();
+    }
+
+    crate fn handle_interrupt(&self) {
+        // An interrupt for an alarm that was replaced by a later one can
+        // still be pending, so check that the alarm has expired.
+        let remaining = self.alarm.get().wrapping_sub(self.now());
+        if self.enabled.get() && (remaining == 0 || remaining > core::u32::MAX / 2) {
+            self.enabled.set(false);
+            self.client.map(|client| client.fired());
+        }
+    }
+}
+
+impl Time for HostAlarm<'a> {
+    type Frequency = Freq32KHz;
+
+    fn now(&self) -> u32 {
+        let elapsed = self.start.elapsed();
+        let tics = elapsed.as_secs() * Freq32KHz::frequency() as u64
+            + elapsed.subsec_nanos() as u64 * Freq32KHz::frequency() as u64 / 1_000_000_000;
+        tics as u32
+    }
+
+    fn max_tics(&self) -> u32 {
+        core::u32::MAX
+    }
+}
+
+impl Alarm<'a> for HostAlarm<'a> {
+    fn set_alarm(&self, tics: u32) {
+        self.alarm.set(tics);
+        self.enabled.set(true);
+
+        // Alarms more than half the counter range away are in the past, and
+        // expire right away. Others expire one tic late, so that the counter
+        // has certainly reached the alarm when the interrupt is handled.
+        let delta = tics.wrapping_sub(self.now());
+        let delay = if delta > core::u32::MAX / 2 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_nanos((delta as u64 + 1) * 1_000_000_000 / Freq32KHz::frequency() as u64)
+        };
+        self.set_deadline(Some(Instant::now() + delay));
+    }
+
+    fn get_alarm(&self) -> u32 {
+        self.alarm.get()
+    }
+
+    fn set_client(&'a self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    fn disable(&self) {
+        self.enabled.set(false);
+        self.set_deadline(None);
+    }
+}
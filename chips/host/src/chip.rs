@@ -0,0 +1,143 @@
+//! The host chip.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::alarm::HostAlarm;
+use crate::events::Events;
+use crate::flash::HostFlash;
+use crate::gpio::GpioPort;
+use crate::interrupts;
+use crate::syscall::SysCall;
+use crate::systick::SysTick;
+use crate::uart::Uart;
+use crate::watchdog::HostWatchdog;
+
+/// Where the simulated peripherals keep their state.
+pub struct Config {
+    /// File the levels of GPIO input pins are read from.
+    pub gpio_input: Option<PathBuf>,
+    /// File the levels of GPIO output pins are written to.
+    pub gpio_output: Option<PathBuf>,
+    /// File backing the flash. Without one, the flash is erased every time
+    /// the simulator starts.
+    pub flash: Option<PathBuf>,
+    /// Size of the flash, in pages.
+    pub flash_pages: usize,
+    /// Texts the simulator waits for: once all of them have been written to
+    /// the UART, the simulator exits.
+    pub exit_when: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            gpio_input: None,
+            gpio_output: None,
+            flash: None,
+            flash_pages: 128,
+            exit_when: Vec::new(),
+        }
+    }
+}
+
+pub struct Host {
+    events: &'static Events,
+    systick: &'static SysTick,
+    userspace_kernel_boundary: SysCall,
+    pub uart: Uart<'static>,
+    pub alarm: HostAlarm<'static>,
+    pub gpio: GpioPort,
+    pub flash: HostFlash,
+    pub watchdog: HostWatchdog,
+}
+
+impl Host {
+    pub fn new(config: &Config) -> Host {
+        let events: &'static Events = Box::leak(Box::new(Events::new()));
+        let systick: &'static SysTick = Box::leak(Box::new(SysTick::new()));
+        Host {
+            events: events,
+            systick: systick,
+            userspace_kernel_boundary: SysCall::new(events, systick),
+            uart: Uart::new(events, &config.exit_when),
+            alarm: HostAlarm::new(events),
+            gpio: GpioPort::new(
+                events,
+                config.gpio_input.as_ref().map(PathBuf::as_path),
+                config.gpio_output.as_ref().map(PathBuf::as_path),
+            ),
+            flash: HostFlash::new(
+                events,
+                config.flash_pages,
+                config.flash.as_ref().map(PathBuf::as_path),
+            ),
+            watchdog: HostWatchdog::new(),
+        }
+    }
+}
+
+impl kernel::Chip for Host {
+    type MPU = ();
+    type UserspaceKernelBoundary = SysCall;
+    type SysTick = SysTick;
+
+    fn mpu(&self) -> &Self::MPU {
+        &()
+    }
+
+    fn systick(&self) -> &Self::SysTick {
+        self.systick
+    }
+
+    fn userspace_kernel_boundary(&self) -> &SysCall {
+        &self.userspace_kernel_boundary
+    }
+
+    fn service_pending_interrupts(&self) {
+        loop {
+            let pending = self.events.take_interrupts();
+            if pending == 0 {
+                break;
+            }
+            if pending & (1 << interrupts::UART) != 0 {
+                self.uart.handle_interrupt();
+            }
+            if pending & (1 << interrupts::ALARM) != 0 {
+                self.alarm.handle_interrupt();
+            }
+            if pending & (1 << interrupts::GPIO) != 0 {
+                self.gpio.handle_interrupt();
+            }
+            if pending & (1 << interrupts::FLASH) != 0 {
+                self.flash.handle_interrupt();
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        self.events.has_interrupts()
+    }
+
+    fn sleep(&self) {
+        self.events.wait(None, None);
+    }
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Interrupts are only handled on the kernel thread, in
+        // `service_pending_interrupts()`, so nothing can interrupt `f`.
+        f()
+    }
+
+    unsafe fn print_state(&self, writer: &mut dyn Write) {
+        let _ = writer.write_fmt(format_args!(
+            "\r\n---| Host State |---\
+             \r\n Pending interrupts: {}\
+             \r\n",
+            self.events.has_interrupts()
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+//! Events that wake up the kernel thread.
+//!
+//! The kernel runs on the main thread of the simulator. Peripherals raise
+//! interrupts from their own threads, and apps trap into the kernel from
+//! theirs. Both are recorded here, and the kernel thread waits for them when
+//! the chip sleeps or while an app is running.
+
+use std::sync::{Condvar, Mutex};
+use std::time::Instant;
+
+/// Why the thread of an app returned to the kernel.
+#[derive(Copy, Clone, Debug)]
+crate enum Trap {
+    /// The app called a system call.
+    Syscall { number: u8, args: [usize; 4] },
+    /// The app panicked.
+    Fault,
+}
+
+/// Why `Events::wait()` returned.
+crate enum Wakeup {
+    Trap(Trap),
+    Interrupt,
+    Timeout,
+}
+
+struct State {
+    /// Bit mask of pending interrupts.
+    interrupts: u32,
+    /// Traps the kernel has not handled yet, with the context of the app
+    /// that trapped.
+    traps: Vec<(usize, Trap)>,
+}
+
+crate struct Events {
+    state: Mutex<State>,
+    wakeup: Condvar,
+}
+
+impl Events {
+    crate fn new() -> Events {
+        Events {
+            state: Mutex::new(State {
+                interrupts: 0,
+                traps: Vec::new(),
+            }),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// Mark `interrupt` as pending, and wake up the kernel thread.
+    crate fn raise(&self, interrupt: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.interrupts |= 1 << interrupt;
+        self.wakeup.notify_all();
+    }
+
+    crate fn has_interrupts(&self) -> bool {
+        self.state.lock().unwrap().interrupts != 0
+    }
+
+    /// Return the bit mask of pending interrupts, and clear it.
+    crate fn take_interrupts(&self) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        let interrupts = state.interrupts;
+        state.interrupts = 0;
+        interrupts
+    }
+
+    /// Record that the app with the context `context` trapped into the
+    /// kernel, and wake up the kernel thread.
+    crate fn trap(&self, context: usize, trap: Trap) {
+        let mut state = self.state.lock().unwrap();
+        state.traps.push((context, trap));
+        self.wakeup.notify_all();
+    }
+
+    /// Forget the traps of the app with the context `context`.
+    crate fn discard_traps(&self, context: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.traps.retain(|&(c, _)| c != context);
+    }
+
+    /// Wait until the app with the context `context`, if any, traps, an
+    /// interrupt is pending, or `deadline` passes. A trap is reported before
+    /// pending interrupts.
+    crate fn wait(&self, context: Option<usize>, deadline: Option<Instant>) -> Wakeup {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(context) = context {
+                if let Some(i) = state.traps.iter().position(|&(c, _)| c == context) {
+                    return Wakeup::Trap(state.traps.remove(i).1);
+                }
+            }
+            if state.interrupts != 0 {
+                return Wakeup::Interrupt;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Wakeup::Timeout;
+                    }
+                    self.wakeup.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.wakeup.wait(state).unwrap(),
+            };
+        }
+    }
+}
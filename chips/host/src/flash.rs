@@ -0,0 +1,197 @@
+//! Flash backed by a file.
+//!
+//! The flash contents are kept in memory, and every write and erase is also
+//! written to the backing file, if there is one, so that the contents survive
+//! restarts of the simulator. Operations complete with the flash interrupt.
+
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::crash_dump::CrashDumpFlash;
+use kernel::hil;
+use kernel::ReturnCode;
+
+use crate::events::Events;
+use crate::interrupts;
+
+pub const PAGE_SIZE: usize = 512;
+
+pub struct HostPage(pub [u8; PAGE_SIZE]);
+
+impl HostPage {
+    pub const fn new() -> HostPage {
+        HostPage([0; PAGE_SIZE])
+    }
+}
+
+impl AsMut<[u8]> for HostPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operation {
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct HostFlash {
+    events: &'static Events,
+    contents: RefCell<Vec<u8>>,
+    file: RefCell<Option<File>>,
+    client: OptionalCell<&'static dyn hil::flash::Client<HostFlash>>,
+    operation: Cell<Option<Operation>>,
+    buffer: TakeCell<'static, HostPage>,
+}
+
+impl HostFlash {
+    /// Create a flash of `pages` pages, backed by the file at `path`, if
+    /// any. Existing contents of the file are loaded, and the rest of the
+    /// flash starts out erased.
+    crate fn new(events: &'static Events, pages: usize, path: Option<&Path>) -> HostFlash {
+        let mut contents = vec![0xff; pages * PAGE_SIZE];
+        let file = path.map(|path| {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(path)
+                .unwrap_or_else(|err| panic!("cannot open {}: {}", path.display(), err));
+            let mut existing = Vec::new();
+            let _ = file.read_to_end(&mut existing);
+            let len = existing.len().min(contents.len());
+            contents[..len].copy_from_slice(&existing[..len]);
+            file
+        });
+        HostFlash {
+            events: events,
+            contents: RefCell::new(contents),
+            file: RefCell::new(file),
+            client: OptionalCell::empty(),
+            operation: Cell::new(None),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    pub fn number_of_pages(&self) -> usize {
+        self.contents.borrow().len() / PAGE_SIZE
+    }
+
+    /// Store `data` at page `page_number`, in memory and in the file.
+    fn store(&self, page_number: usize, data: &[u8]) {
+        let offset = page_number * PAGE_SIZE;
+        self.contents.borrow_mut()[offset..offset + PAGE_SIZE].copy_from_slice(data);
+        if let Some(file) = self.file.borrow_mut().as_mut() {
+            let _ = file
+                .seek(SeekFrom::Start(offset as u64))
+                .and_then(|_| file.write_all(data));
+        }
+    }
+
+    fn start(
+        &self,
+        operation: Operation,
+        page_number: usize,
+        buffer: Option<&'static mut HostPage>,
+    ) -> ReturnCode {
+        if self.operation.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        if page_number >= self.number_of_pages() {
+            return ReturnCode::EINVAL;
+        }
+        let offset = page_number * PAGE_SIZE;
+        match operation {
+            Operation::Read => {
+                buffer.map(|buffer| {
+                    buffer
+                        .0
+                        .copy_from_slice(&self.contents.borrow()[offset..offset + PAGE_SIZE]);
+                    self.buffer.replace(buffer);
+                });
+            }
+            Operation::Write => {
+                buffer.map(|buffer| {
+                    self.store(page_number, &buffer.0);
+                    self.buffer.replace(buffer);
+                });
+            }
+            Operation::Erase => self.store(page_number, &[0xff; PAGE_SIZE]),
+        }
+        self.operation.set(Some(operation));
+        self.events.raise(interrupts::FLASH);
+        ReturnCode::SUCCESS
+    }
+
+    crate fn handle_interrupt(&self) {
+        let operation = self.operation.take();
+        self.client.map(|client| match operation {
+            Some(Operation::Read) => {
+                self.buffer.take().map(|buffer| {
+                    client.read_complete(buffer, hil::flash::Error::CommandComplete);
+                });
+            }
+            Some(Operation::Write) => {
+                self.buffer.take().map(|buffer| {
+                    client.write_complete(buffer, hil::flash::Error::CommandComplete);
+                });
+            }
+            Some(Operation::Erase) => client.erase_complete(hil::flash::Error::CommandComplete),
+            None => {}
+        });
+    }
+}
+
+impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for HostFlash {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for HostFlash {
+    type Page = HostPage;
+
+    fn read_page(&self, page_number: usize, buf: &'static mut Self::Page) -> ReturnCode {
+        self.start(Operation::Read, page_number, Some(buf))
+    }
+
+    fn write_page(&self, page_number: usize, buf: &'static mut Self::Page) -> ReturnCode {
+        self.start(Operation::Write, page_number, Some(buf))
+    }
+
+    fn erase_page(&self, page_number: usize) -> ReturnCode {
+        self.start(Operation::Erase, page_number, None)
+    }
+}
+
+/// Addresses are offsets into the flash. Operations on the simulated flash
+/// always complete at once, only their callbacks wait for the interrupt, but
+/// like on hardware writes are refused until the callback has run.
+impl CrashDumpFlash for HostFlash {
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn read_blocking(&self, address: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.contents.borrow()[address..address + buf.len()]);
+    }
+
+    fn write_page_blocking(&self, address: usize, data: &[u8]) -> ReturnCode {
+        if self.operation.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        if address % PAGE_SIZE != 0
+            || address / PAGE_SIZE >= self.number_of_pages()
+            || data.len() != PAGE_SIZE
+        {
+            return ReturnCode::EINVAL;
+        }
+        self.store(address / PAGE_SIZE, data);
+        ReturnCode::SUCCESS
+    }
+}
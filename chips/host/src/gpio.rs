@@ -0,0 +1,297 @@
+//! GPIO pins backed by files.
+//!
+//! The levels of input pins are read from a text file with one character per
+//! pin, `1` for high and anything else for low, which a host thread polls for
+//! changes. Whenever an output pin changes, the levels of all pins are written
+//! to another text file, with `1` or `0` for output pins and `-` for the other
+//! pins. Without an input file all inputs are low, and without an output file
+//! outputs are only kept in memory.
+
+use std::cell::Cell;
+use std::fs;
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::gpio::{self, Configure};
+
+use crate::events::Events;
+use crate::interrupts;
+
+pub const NUM_PINS: usize = 16;
+
+/// How often the input file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// State shared by the pins of a port.
+struct PortState {
+    /// Levels read from the input file.
+    inputs: Arc<Mutex<u32>>,
+    /// Pins configured as outputs.
+    outputs: Cell<u32>,
+    /// Levels of the output pins.
+    levels: Cell<u32>,
+    output_path: Option<PathBuf>,
+}
+
+impl PortState {
+    fn write_outputs(&self) {
+        if let Some(path) = &self.output_path {
+            let line: String = (0..NUM_PINS)
+                .map(|pin| {
+                    if self.outputs.get() & (1 << pin) == 0 {
+                        '-'
+                    } else if self.levels.get() & (1 << pin) == 0 {
+                        '0'
+                    } else {
+                        '1'
+                    }
+                })
+                .collect();
+            let _ = fs::write(path, line + "\n");
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Edge {
+    Rising,
+    Falling,
+    Either,
+}
+
+pub struct GpioPin {
+    pin: usize,
+    port: &'static PortState,
+    input: Cell<bool>,
+    floating_state: Cell<gpio::FloatingState>,
+    interrupt: Cell<Option<Edge>>,
+    pending: Cell<bool>,
+    client: OptionalCell<&'static dyn gpio::Client>,
+}
+
+impl GpioPin {
+    fn new(pin: usize, port: &'static PortState) -> GpioPin {
+        GpioPin {
+            pin: pin,
+            port: port,
+            input: Cell::new(false),
+            floating_state: Cell::new(gpio::FloatingState::PullNone),
+            interrupt: Cell::new(None),
+            pending: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn mask(&self) -> u32 {
+        1 << self.pin
+    }
+
+    fn set_level(&self, high: bool) {
+        let levels = self.port.levels.get();
+        self.port.levels.set(if high {
+            levels | self.mask()
+        } else {
+            levels & !self.mask()
+        });
+        if self.is_output() {
+            self.port.write_outputs();
+        }
+    }
+
+    /// Call the client if the change from `old` to `new` is an edge the pin
+    /// interrupts on.
+    fn input_changed(&self, old: bool, new: bool) {
+        let fire = match self.interrupt.get() {
+            Some(Edge::Rising) => !old && new,
+            Some(Edge::Falling) => old && !new,
+            Some(Edge::Either) => old != new,
+            None => false,
+        };
+        if fire {
+            self.pending.set(true);
+            self.client.map(|client| client.fired());
+            self.pending.set(false);
+        }
+    }
+}
+
+impl gpio::Configure for GpioPin {
+    fn configuration(&self) -> gpio::Configuration {
+        match (self.input.get(), self.is_output()) {
+            (true, true) => gpio::Configuration::InputOutput,
+            (true, false) => gpio::Configuration::Input,
+            (false, true) => gpio::Configuration::Output,
+            (false, false) => gpio::Configuration::LowPower,
+        }
+    }
+
+    fn make_output(&self) -> gpio::Configuration {
+        self.port.outputs.set(self.port.outputs.get() | self.mask());
+        self.port.write_outputs();
+        self.configuration()
+    }
+
+    fn disable_output(&self) -> gpio::Configuration {
+        self.port
+            .outputs
+            .set(self.port.outputs.get() & !self.mask());
+        self.port.write_outputs();
+        self.configuration()
+    }
+
+    fn make_input(&self) -> gpio::Configuration {
+        self.input.set(true);
+        self.configuration()
+    }
+
+    fn disable_input(&self) -> gpio::Configuration {
+        self.input.set(false);
+        self.configuration()
+    }
+
+    fn deactivate_to_low_power(&self) {
+        self.disable_input();
+        self.disable_output();
+    }
+
+    fn set_floating_state(&self, state: gpio::FloatingState) {
+        self.floating_state.set(state);
+    }
+
+    fn floating_state(&self) -> gpio::FloatingState {
+        self.floating_state.get()
+    }
+
+    fn is_output(&self) -> bool {
+        self.port.outputs.get() & self.mask() != 0
+    }
+}
+
+impl gpio::Output for GpioPin {
+    fn set(&self) {
+        self.set_level(true);
+    }
+
+    fn clear(&self) {
+        self.set_level(false);
+    }
+
+    fn toggle(&self) -> bool {
+        let high = self.port.levels.get() & self.mask() == 0;
+        self.set_level(high);
+        high
+    }
+}
+
+impl gpio::Input for GpioPin {
+    fn read(&self) -> bool {
+        if self.is_output() {
+            self.port.levels.get() & self.mask() != 0
+        } else {
+            *self.port.inputs.lock().unwrap() & self.mask() != 0
+        }
+    }
+}
+
+impl gpio::Interrupt for GpioPin {
+    fn set_client(&self, client: &'static dyn gpio::Client) {
+        self.client.set(client);
+    }
+
+    fn enable_interrupts(&self, mode: gpio::InterruptEdge) {
+        self.interrupt.set(Some(match mode {
+            gpio::InterruptEdge::RisingEdge => Edge::Rising,
+            gpio::InterruptEdge::FallingEdge => Edge::Falling,
+            gpio::InterruptEdge::EitherEdge => Edge::Either,
+        }));
+    }
+
+    fn disable_interrupts(&self) {
+        self.interrupt.set(None);
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending.get()
+    }
+}
+
+impl gpio::Pin for GpioPin {}
+impl gpio::InterruptPin for GpioPin {}
+
+pub struct GpioPort {
+    pins: Vec<GpioPin>,
+    port: &'static PortState,
+    /// Input levels when the GPIO interrupt was last handled.
+    last_inputs: Cell<u32>,
+}
+
+impl GpioPort {
+    crate fn new(
+        events: &'static Events,
+        input_path: Option<&Path>,
+        output_path: Option<&Path>,
+    ) -> GpioPort {
+        let port: &'static PortState = Box::leak(Box::new(PortState {
+            inputs: Arc::new(Mutex::new(0)),
+            outputs: Cell::new(0),
+            levels: Cell::new(0),
+            output_path: output_path.map(Path::to_path_buf),
+        }));
+
+        if let Some(path) = input_path {
+            let path = path.to_path_buf();
+            let inputs = port.inputs.clone();
+            thread::Builder::new()
+                .name("gpio".to_string())
+                .spawn(move || loop {
+                    let levels = fs::read(&path).unwrap_or_default();
+                    let levels = levels
+                        .iter()
+                        .take(NUM_PINS)
+                        .enumerate()
+                        .filter(|&(_, &level)| level == b'1')
+                        .fold(0, |inputs, (pin, _)| inputs | 1 << pin);
+                    let changed = {
+                        let mut inputs = inputs.lock().unwrap();
+                        let changed = *inputs != levels;
+                        *inputs = levels;
+                        changed
+                    };
+                    if changed {
+                        events.raise(interrupts::GPIO);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                })
+                .expect("failed to start gpio thread");
+        }
+
+        GpioPort {
+            pins: (0..NUM_PINS).map(|pin| GpioPin::new(pin, port)).collect(),
+            port: port,
+            last_inputs: Cell::new(0),
+        }
+    }
+
+    crate fn handle_interrupt(&self) {
+        let inputs = *self.port.inputs.lock().unwrap();
+        let old = self.last_inputs.replace(inputs);
+        for pin in self.pins.iter() {
+            let mask = pin.mask();
+            if (old ^ inputs) & mask != 0 && !pin.is_output() {
+                pin.input_changed(old & mask != 0, inputs & mask != 0);
+            }
+        }
+    }
+}
+
+impl Index<usize> for GpioPort {
+    type Output = GpioPin;
+
+    fn index(&self, index: usize) -> &GpioPin {
+        &self.pins[index]
+    }
+}
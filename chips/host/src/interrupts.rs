@@ -0,0 +1,6 @@
+//! Interrupt numbers of the simulated peripherals.
+
+pub const UART: u32 = 0;
+pub const ALARM: u32 = 1;
+pub const GPIO: u32 = 2;
+pub const FLASH: u32 = 3;
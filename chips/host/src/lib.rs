@@ -0,0 +1,44 @@
+//! Chip support for running Tock as a Linux process.
+//!
+//! The host "chip" lets the kernel, capsules and apps run on a development
+//! machine, so that `kernel_loop`, the system call paths and capsules can be
+//! tested end to end without a board.
+//!
+//! - Apps run on their own host threads, and enter the kernel through the
+//!   system call functions in `userspace`. `syscall::SysCall` implements
+//!   `UserspaceKernelBoundary` by passing those system calls to the kernel
+//!   thread. The thread of an app the kernel preempts is parked until the app
+//!   is switched to again, so only one app runs at a time.
+//! - Apps are packaged as TBF images by `tbf::flash_image()`, so that they are
+//!   loaded by the same process loader as on hardware. Apps can be built into
+//!   the simulator, or be shared libraries in TBF files (see `library`).
+//! - Peripherals are simulated with host resources: the UART uses stdin and
+//!   stdout, the alarm uses the host clock, GPIO pins are read from and
+//!   written to files, flash is backed by a file, and the watchdog exits the
+//!   simulator when it expires.
+//! - Peripherals that complete asynchronously raise interrupts from host
+//!   threads, which the kernel handles in `service_pending_interrupts()` like
+//!   on any other chip.
+//!
+//! There is no memory protection: apps share the address space of the
+//! kernel, and only the kernel's own checks, for example on allowed buffers,
+//! are enforced.
+
+#![crate_name = "host"]
+#![crate_type = "rlib"]
+#![feature(crate_visibility_modifier, in_band_lifetimes)]
+
+mod events;
+
+pub mod alarm;
+pub mod chip;
+pub mod flash;
+pub mod gpio;
+pub mod interrupts;
+pub mod library;
+pub mod syscall;
+pub mod systick;
+pub mod tbf;
+pub mod uart;
+pub mod userspace;
+pub mod watchdog;
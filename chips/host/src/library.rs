@@ -0,0 +1,178 @@
+//! Apps that are host shared libraries.
+//!
+//! Besides the apps built into the simulator, apps can be built separately as
+//! shared libraries (`crate-type = ["cdylib"]`) and packaged as TBF files with
+//! `tbf::library_app_image()`. The binary of such an app holds
+//! `LIBRARY_MAGIC`, the length of the library as a little-endian `u64`, and
+//! the library itself. When the kernel starts the app, the library is loaded
+//! on the thread of the app, and its `LIBRARY_ENTRY` function is called.
+//!
+//! A library has its own copy of this crate, which cannot reach the kernel.
+//! So the simulator passes its system call function to the entry point, and
+//! the copy of `userspace` in the library makes system calls through it. The
+//! `library_app!` macro defines the entry point:
+//!
+//! ```ignore
+//! extern "C" fn main(_: usize, _: usize, _: usize, _: usize) {
+//!     // Use `host::userspace` like the built-in apps do.
+//! }
+//!
+//! host::library_app!(main);
+//! ```
+//!
+//! A library app that panics faults, like a built-in app, but its thread is
+//! not unwound across the boundary between the library and the simulator.
+//! The thread waits forever instead.
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::PathBuf;
+use std::process;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::userspace::{self, AppFn};
+
+/// Start of the binary of a library app.
+pub const LIBRARY_MAGIC: [u8; 8] = *b"TOCKHOST";
+
+/// Name of the entry point of a library app.
+pub const LIBRARY_ENTRY: &str = "tock_host_library_app";
+
+/// System call function of the simulator, passed to library apps.
+pub type SyscallFn = extern "C" fn(u8, usize, usize, usize, usize) -> isize;
+
+/// Fault function of the simulator, passed to library apps. It does not
+/// return.
+pub type FaultFn = extern "C" fn();
+
+/// The entry point of a library app: it is passed the system call and fault
+/// functions of the simulator, and the arguments of the app's entry point.
+pub type LibraryEntry = extern "C" fn(SyscallFn, FaultFn, usize, usize, usize, usize);
+
+const RTLD_NOW: c_int = 2;
+
+#[link(name = "dl")]
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+}
+
+/// In a library app, the system call function of the simulator.
+static SYSCALL: AtomicUsize = AtomicUsize::new(0);
+/// In a library app, the fault function of the simulator.
+static FAULT: AtomicUsize = AtomicUsize::new(0);
+
+/// The system call function of the simulator, if this is the copy of the
+/// crate in a library app.
+crate fn simulator_syscall() -> Option<SyscallFn> {
+    match SYSCALL.load(Ordering::SeqCst) {
+        0 => None,
+        syscall => Some(unsafe { std::mem::transmute::<usize, SyscallFn>(syscall) }),
+    }
+}
+
+/// Set up the copy of this crate in a library app, and call its `main`. Used
+/// by `library_app!`.
+pub fn start_library_app(
+    syscall: SyscallFn,
+    fault: FaultFn,
+    main: AppFn,
+    args: (usize, usize, usize, usize),
+) {
+    SYSCALL.store(syscall as usize, Ordering::SeqCst);
+    FAULT.store(fault as usize, Ordering::SeqCst);
+    // Fault before a panic unwinds, so that it never unwinds into the
+    // simulator.
+    std::panic::set_hook(Box::new(|info| {
+        eprintln!("library app {}", info);
+        let fault: FaultFn = unsafe { std::mem::transmute(FAULT.load(Ordering::SeqCst)) };
+        fault();
+    }));
+    main(args.0, args.1, args.2, args.3);
+}
+
+/// Define the entry point of a library app, which calls `main` with the
+/// arguments of the app's entry point.
+#[macro_export]
+macro_rules! library_app {
+    ($main:expr) => {
+        #[no_mangle]
+        pub extern "C" fn tock_host_library_app(
+            syscall: $crate::library::SyscallFn,
+            fault: $crate::library::FaultFn,
+            argument0: usize,
+            argument1: usize,
+            argument2: usize,
+            argument3: usize,
+        ) {
+            $crate::library::start_library_app(
+                syscall,
+                fault,
+                $main,
+                (argument0, argument1, argument2, argument3),
+            );
+        }
+    };
+}
+
+extern "C" fn syscall(number: u8, r0: usize, r1: usize, r2: usize, r3: usize) -> isize {
+    match std::panic::catch_unwind(|| userspace::syscall(number, r0, r1, r2, r3)) {
+        Ok(value) => value,
+        // The kernel dropped the app, so it must not run any more.
+        Err(_) => stop(),
+    }
+}
+
+extern "C" fn fault() {
+    userspace::fault();
+    stop()
+}
+
+/// Keep the thread of a library app from ever running the app again.
+fn stop() -> ! {
+    loop {
+        thread::park();
+    }
+}
+
+/// If `binary` is the binary of a library app, load the library and return
+/// its entry point. `context` makes the file name of the library unique, so
+/// that every instance of the app gets its own copy of the library.
+crate unsafe fn load(binary: *const u8, context: usize) -> Option<LibraryEntry> {
+    if slice::from_raw_parts(binary, LIBRARY_MAGIC.len()) != LIBRARY_MAGIC {
+        return None;
+    }
+    let mut len = [0; 8];
+    len.copy_from_slice(slice::from_raw_parts(binary.add(8), 8));
+    let library = slice::from_raw_parts(binary.add(16), u64::from_le_bytes(len) as usize);
+
+    // The dynamic loader only loads libraries from files.
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("tock-host-{}-app-{}.so", process::id(), context));
+    fs::write(&path, library).expect("failed to write library app");
+    let path_c = CString::new(path.to_string_lossy().into_owned()).unwrap();
+    let handle = dlopen(path_c.as_ptr(), RTLD_NOW);
+    let _ = fs::remove_file(&path);
+    if handle.is_null() {
+        panic!(
+            "failed to load library app: {:?}",
+            CStr::from_ptr(dlerror())
+        );
+    }
+    let entry_c = CString::new(LIBRARY_ENTRY).unwrap();
+    let entry = dlsym(handle, entry_c.as_ptr());
+    if entry.is_null() {
+        panic!("library app has no {} function", LIBRARY_ENTRY);
+    }
+    Some(std::mem::transmute::<*mut c_void, LibraryEntry>(entry))
+}
+
+/// Call the entry point of a library app with the arguments of the app's
+/// entry point.
+crate fn call(entry: LibraryEntry, args: (usize, usize, usize, usize)) {
+    entry(syscall, fault, args.0, args.1, args.2, args.3);
+}
@@ -0,0 +1,216 @@
+//! Interface between the kernel and apps running on host threads.
+//!
+//! Each process has a context, which owns the host thread the app runs on.
+//! Switching to a process resumes its thread and waits until the app traps
+//! into the kernel again with a system call or a fault, an interrupt is
+//! raised, or its timeslice expires. In the last two cases the thread is
+//! parked until the app is switched to again.
+//!
+//! Restarting a process gives it a new context. The thread of the old context
+//! is unwound if it is in the kernel, and otherwise stays parked.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use kernel::procs::FunctionCall;
+use kernel::syscall::{self, ContextSwitchReason};
+
+use crate::events::{Events, Trap, Wakeup};
+use crate::systick::SysTick;
+use crate::userspace::{self, AppThread};
+
+/// What the thread of an app does when it is switched to.
+#[derive(Copy, Clone, Debug)]
+crate enum Resume {
+    /// Return `value` from the system call the app is in.
+    Return(isize),
+    /// Call a function of the app. If the app is in a yield, the yield
+    /// returns once the function does.
+    Call(FunctionCall),
+}
+
+/// State the kernel stores for each process: the context of the app.
+#[derive(Default, Copy, Clone)]
+pub struct HostStoredState {
+    context: usize,
+}
+
+struct Context {
+    /// The thread of the app, once it has been started.
+    thread: Option<AppThread>,
+    /// What the thread does when the app is next switched to.
+    resume: Option<Resume>,
+    /// The last time the app trapped into the kernel, for debugging.
+    last_trap: Option<Trap>,
+}
+
+pub struct SysCall {
+    events: &'static Events,
+    systick: &'static SysTick,
+    contexts: RefCell<BTreeMap<usize, Context>>,
+    next_context: Cell<usize>,
+}
+
+impl SysCall {
+    crate fn new(events: &'static Events, systick: &'static SysTick) -> SysCall {
+        SysCall {
+            events: events,
+            systick: systick,
+            contexts: RefCell::new(BTreeMap::new()),
+            next_context: Cell::new(1),
+        }
+    }
+
+    fn set_resume(&self, state: &HostStoredState, resume: Resume) {
+        if let Some(context) = self.contexts.borrow_mut().get_mut(&state.context) {
+            context.resume = Some(resume);
+        }
+    }
+
+    /// Resume the thread of the app, starting it if this is the first time
+    /// the app runs. Returns whether the app is running.
+    fn resume(&self, state: &HostStoredState) -> bool {
+        let mut contexts = self.contexts.borrow_mut();
+        let context = match contexts.get_mut(&state.context) {
+            Some(context) => context,
+            None => return false,
+        };
+        match (context.resume.take(), &context.thread) {
+            (resume, Some(thread)) => thread.resume(resume),
+            (Some(Resume::Call(init)), None) => {
+                context.thread = Some(userspace::start(state.context, self.events, init));
+                true
+            }
+            (_, None) => false,
+        }
+    }
+
+    /// Stop the thread of the app until it is resumed.
+    fn preempt(&self, state: &HostStoredState) {
+        if let Some(thread) = self
+            .contexts
+            .borrow()
+            .get(&state.context)
+            .and_then(|context| context.thread.as_ref())
+        {
+            thread.preempt();
+        }
+    }
+}
+
+impl kernel::syscall::UserspaceKernelBoundary for SysCall {
+    type StoredState = HostStoredState;
+
+    unsafe fn initialize_process(
+        &self,
+        stack_pointer: *const usize,
+        _stack_size: usize,
+        state: &mut Self::StoredState,
+    ) -> Result<*const usize, ()> {
+        let mut contexts = self.contexts.borrow_mut();
+
+        // Dropping the old context closes the channel to its thread, which
+        // unwinds the thread.
+        if contexts.remove(&state.context).is_some() {
+            self.events.discard_traps(state.context);
+        }
+
+        let context = self.next_context.get();
+        self.next_context.set(context + 1);
+        contexts.insert(
+            context,
+            Context {
+                thread: None,
+                resume: None,
+                last_trap: None,
+            },
+        );
+        state.context = context;
+
+        // Apps use the stacks of their host threads, so the stack in process
+        // memory is left alone.
+        Ok(stack_pointer)
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        _stack_pointer: *const usize,
+        state: &mut Self::StoredState,
+        return_value: isize,
+    ) {
+        self.set_resume(state, Resume::Return(return_value));
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        stack_pointer: *const usize,
+        _remaining_stack_memory: usize,
+        state: &mut Self::StoredState,
+        callback: FunctionCall,
+    ) -> Result<*mut usize, *mut usize> {
+        self.set_resume(state, Resume::Call(callback));
+        Ok(stack_pointer as *mut usize)
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        stack_pointer: *const usize,
+        state: &mut Self::StoredState,
+    ) -> (*mut usize, ContextSwitchReason) {
+        let stack_pointer = stack_pointer as *mut usize;
+        if !self.resume(state) {
+            return (stack_pointer, ContextSwitchReason::Fault);
+        }
+
+        let reason = match self
+            .events
+            .wait(Some(state.context), self.systick.interrupt_deadline())
+        {
+            Wakeup::Trap(trap) => {
+                if let Some(context) = self.contexts.borrow_mut().get_mut(&state.context) {
+                    context.last_trap = Some(trap);
+                }
+                match trap {
+                    Trap::Syscall { number, args } => {
+                        match syscall::arguments_to_syscall(
+                            number, args[0], args[1], args[2], args[3],
+                        ) {
+                            Some(syscall) => ContextSwitchReason::SyscallFired { syscall },
+                            None => ContextSwitchReason::Fault,
+                        }
+                    }
+                    Trap::Fault => ContextSwitchReason::Fault,
+                }
+            }
+            Wakeup::Interrupt => {
+                self.preempt(state);
+                ContextSwitchReason::Interrupted
+            }
+            Wakeup::Timeout => {
+                self.preempt(state);
+                ContextSwitchReason::TimesliceExpired
+            }
+        };
+        (stack_pointer, reason)
+    }
+
+    unsafe fn print_context(
+        &self,
+        _stack_pointer: *const usize,
+        state: &Self::StoredState,
+        writer: &mut dyn Write,
+    ) {
+        let contexts = self.contexts.borrow();
+        let context = contexts.get(&state.context);
+        let _ = writer.write_fmt(format_args!(
+            "\
+             \r\n Context: {}   Thread started: {}\
+             \r\n Last trap: {:?}\
+             \r\n",
+            state.context,
+            context.map_or(false, |context| context.thread.is_some()),
+            context.and_then(|context| context.last_trap),
+        ));
+    }
+}
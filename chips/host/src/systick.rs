@@ -0,0 +1,64 @@
+//! SysTick timer for the host, based on the host clock.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Measures timeslices with the host clock. The timer counts down from the
+/// time it is set, whether or not it is enabled, and only preempts apps if it
+/// is enabled with interrupts.
+pub struct SysTick {
+    deadline: Cell<Option<Instant>>,
+    interrupt: Cell<bool>,
+}
+
+impl SysTick {
+    crate fn new() -> SysTick {
+        SysTick {
+            deadline: Cell::new(None),
+            interrupt: Cell::new(false),
+        }
+    }
+
+    /// When the running app should be preempted, if the timer is enabled
+    /// with interrupts.
+    crate fn interrupt_deadline(&self) -> Option<Instant> {
+        if self.interrupt.get() {
+            self.deadline.get()
+        } else {
+            None
+        }
+    }
+}
+
+impl kernel::SysTick for SysTick {
+    fn set_timer(&self, us: u32) {
+        self.deadline
+            .set(Some(Instant::now() + Duration::from_micros(us as u64)));
+    }
+
+    fn get_value(&self) -> u32 {
+        self.deadline.get().map_or(0, |deadline| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            remaining.as_micros() as u32
+        })
+    }
+
+    fn greater_than(&self, us: u32) -> bool {
+        self.get_value() > us
+    }
+
+    fn overflowed(&self) -> bool {
+        self.deadline
+            .get()
+            .map_or(false, |deadline| Instant::now() >= deadline)
+    }
+
+    fn reset(&self) {
+        self.deadline.set(None);
+        self.interrupt.set(false);
+    }
+
+    fn enable(&self, with_interrupt: bool) {
+        self.interrupt.set(with_interrupt);
+    }
+}
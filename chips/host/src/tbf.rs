@@ -0,0 +1,114 @@
+//! Packaging of host apps as TBF images.
+//!
+//! The kernel loads processes from TBF images in flash, so host apps are
+//! wrapped in images as well. The binary of a built-in app is just the address
+//! of its entry point, which the app thread reads when the kernel starts the
+//! app. The binary of an app that is a shared library holds the library (see
+//! `library`), so it can be stored in a TBF file and loaded into the simulator
+//! like an app for a board.
+
+use std::mem;
+
+use crate::library::LIBRARY_MAGIC;
+use crate::userspace::AppFn;
+
+/// A host app.
+pub struct HostApp {
+    /// The package name of the app, which is also its process name.
+    pub name: &'static str,
+    /// The entry point of the app.
+    pub main: AppFn,
+    /// How much memory the kernel gives the app, in bytes.
+    pub minimum_ram_size: u32,
+}
+
+const TBF_VERSION: u16 = 2;
+const TBF_BASE_SIZE: usize = 16;
+const TBF_TLV_MAIN: u16 = 1;
+const TBF_TLV_PACKAGE_NAME: u16 = 3;
+const TBF_FLAG_ENABLED: u32 = 1;
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Build the TBF image of the app named `name` with the binary `binary`.
+fn image(name: &str, binary: &[u8], minimum_ram_size: u32) -> Vec<u8> {
+    let name = name.as_bytes();
+    let header_size = TBF_BASE_SIZE + 4 + 12 + 4 + align4(name.len());
+    let total_size = align4(header_size + binary.len());
+
+    let mut image = Vec::with_capacity(total_size);
+    image.extend_from_slice(&TBF_VERSION.to_le_bytes());
+    image.extend_from_slice(&(header_size as u16).to_le_bytes());
+    image.extend_from_slice(&(total_size as u32).to_le_bytes());
+    image.extend_from_slice(&TBF_FLAG_ENABLED.to_le_bytes());
+    // The checksum is filled in once the header is complete.
+    image.extend_from_slice(&0u32.to_le_bytes());
+
+    // The entry point is the start of the binary, right after the header.
+    image.extend_from_slice(&TBF_TLV_MAIN.to_le_bytes());
+    image.extend_from_slice(&12u16.to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&minimum_ram_size.to_le_bytes());
+
+    image.extend_from_slice(&TBF_TLV_PACKAGE_NAME.to_le_bytes());
+    image.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    image.extend_from_slice(name);
+    image.resize(header_size, 0);
+
+    let checksum = image
+        .chunks(4)
+        .enumerate()
+        .filter(|&(i, _)| i != 3)
+        .fold(0, |checksum, (_, word)| {
+            checksum ^ u32::from_le_bytes([word[0], word[1], word[2], word[3]])
+        });
+    image[12..16].copy_from_slice(&checksum.to_le_bytes());
+
+    image.extend_from_slice(binary);
+    image.resize(total_size, 0);
+    image
+}
+
+/// Build the TBF image of the app named `name` that is the shared library
+/// `library`.
+pub fn library_app_image(name: &str, library: &[u8], minimum_ram_size: u32) -> Vec<u8> {
+    let mut binary = LIBRARY_MAGIC.to_vec();
+    binary.extend_from_slice(&(library.len() as u64).to_le_bytes());
+    binary.extend_from_slice(library);
+    image(name, &binary, minimum_ram_size)
+}
+
+/// Build a flash image holding the TBF images of `apps`, then the TBF images
+/// in `images`, followed by an empty word that ends the list of apps for the
+/// process loader.
+///
+/// The image is leaked, since the kernel keeps references into it for as
+/// long as it runs.
+pub fn flash_image(apps: &[HostApp], images: &[Vec<u8>]) -> &'static [u8] {
+    let mut image: Vec<u8> = apps
+        .iter()
+        .flat_map(|app| {
+            image(
+                app.name,
+                &(app.main as usize).to_ne_bytes(),
+                app.minimum_ram_size,
+            )
+        })
+        .collect();
+    for tbf in images {
+        image.extend_from_slice(tbf);
+        image.resize(align4(image.len()), 0);
+    }
+    image.extend_from_slice(&[0; 4]);
+
+    // The kernel reads the headers as words, so keep the image word aligned.
+    let mut words = vec![0u32; image.len() / mem::size_of::<u32>()];
+    for (word, bytes) in words.iter_mut().zip(image.chunks(4)) {
+        *word = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    let words: &'static [u32] = Box::leak(words.into_boxed_slice());
+    unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, image.len()) }
+}
@@ -0,0 +1,264 @@
+//! UART backed by stdin and stdout.
+//!
+//! Transmitted bytes are written to stdout. A host thread reads stdin and
+//! raises the UART interrupt whenever bytes arrive, so that they can be
+//! passed to the receive client. Tests can make the simulator exit once the
+//! texts they wait for have been transmitted.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::uart;
+use kernel::ReturnCode;
+
+use crate::events::Events;
+use crate::interrupts;
+
+/// How long receiving a byte takes.
+const BYTE_TIME: Duration = Duration::from_millis(1);
+
+pub struct Uart<'a> {
+    events: &'static Events,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    /// A transmitted buffer that has not been returned to the client yet.
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_index: Cell<usize>,
+    rx_aborted: Cell<bool>,
+    /// Bytes read from stdin that have not been received yet.
+    input: Arc<Mutex<VecDeque<u8>>>,
+    /// Texts that have not been transmitted yet, after which the simulator
+    /// exits.
+    exit_when: RefCell<Vec<Vec<u8>>>,
+    /// The end of the transmitted bytes, to find texts that are split across
+    /// transmissions.
+    output_tail: RefCell<Vec<u8>>,
+    /// Whether all texts have appeared, and the simulator exits at the end
+    /// of the line the last one is on.
+    exiting: Cell<bool>,
+}
+
+impl Uart<'a> {
+    crate fn new(events: &'static Events, exit_when: &[String]) -> Uart<'a> {
+        Uart {
+            events: events,
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_index: Cell::new(0),
+            rx_aborted: Cell::new(false),
+            input: Arc::new(Mutex::new(VecDeque::new())),
+            exit_when: RefCell::new(
+                exit_when
+                    .iter()
+                    .map(|text| text.clone().into_bytes())
+                    .collect(),
+            ),
+            output_tail: RefCell::new(Vec::new()),
+            exiting: Cell::new(false),
+        }
+    }
+
+    /// Forget the texts that appear in the output, and exit once all of the
+    /// texts the simulator waits for have appeared, and the line the last
+    /// one is on is complete.
+    fn check_output(&self, output: &[u8]) {
+        let mut exit_when = self.exit_when.borrow_mut();
+        if exit_when.is_empty() && !self.exiting.get() {
+            return;
+        }
+        let mut tail = self.output_tail.borrow_mut();
+        tail.extend_from_slice(output);
+        if !self.exiting.get() {
+            let mut end = 0;
+            exit_when.retain(|text| {
+                match tail
+                    .windows(text.len())
+                    .position(|window| window == &text[..])
+                {
+                    Some(position) => {
+                        end = end.max(position + text.len());
+                        false
+                    }
+                    None => true,
+                }
+            });
+            if !exit_when.is_empty() {
+                let keep = exit_when.iter().map(|text| text.len()).max().unwrap_or(0);
+                let start = tail.len().saturating_sub(keep);
+                tail.drain(..start);
+                return;
+            }
+            tail.drain(..end);
+            self.exiting.set(true);
+        }
+        if tail.contains(&b'\n') {
+            process::exit(0);
+        }
+    }
+
+    /// Start reading stdin. Without this, the UART never receives anything,
+    /// which is useful when stdin is not a terminal.
+    pub fn start_input(&self) {
+        let input = self.input.clone();
+        let events = self.events;
+        thread::Builder::new()
+            .name("uart".to_string())
+            .spawn(move || {
+                let mut buffer = [0; 64];
+                while let Ok(len) = io::stdin().read(&mut buffer) {
+                    if len == 0 {
+                        break;
+                    }
+                    // Pass on one byte at a time, at about the speed of a
+                    // serial line, so that input piped into the simulator
+                    // arrives no faster than on hardware.
+                    for &byte in buffer[..len].iter() {
+                        input.lock().unwrap().push_back(byte);
+                        events.raise(interrupts::UART);
+                        thread::sleep(BYTE_TIME);
+                    }
+                }
+            })
+            .expect("failed to start uart thread");
+    }
+
+    crate fn handle_interrupt(&self) {
+        self.tx_buffer.take().map(|buffer| {
+            self.tx_client.map(move |client| {
+                client.transmitted_buffer(buffer, self.tx_len.get(), ReturnCode::SUCCESS);
+            });
+        });
+
+        if self.rx_buffer.is_none() {
+            return;
+        }
+        {
+            let mut input = self.input.lock().unwrap();
+            self.rx_buffer.map(|buffer| {
+                while self.rx_index.get() < self.rx_len.get() {
+                    match input.pop_front() {
+                        Some(byte) => {
+                            buffer[self.rx_index.get()] = byte;
+                            self.rx_index.set(self.rx_index.get() + 1);
+                        }
+                        None => break,
+                    }
+                }
+            });
+        }
+        let (rval, error) = if self.rx_aborted.get() {
+            (ReturnCode::ECANCEL, uart::Error::Aborted)
+        } else if self.rx_index.get() == self.rx_len.get() {
+            (ReturnCode::SUCCESS, uart::Error::None)
+        } else {
+            return;
+        };
+        self.rx_aborted.set(false);
+        self.rx_buffer.take().map(|buffer| {
+            self.rx_client.map(move |client| {
+                client.received_buffer(buffer, self.rx_index.get(), rval, error);
+            });
+        });
+    }
+}
+
+impl uart::Configure for Uart<'a> {
+    fn configure(&self, _params: uart::Parameters) -> ReturnCode {
+        ReturnCode::SUCCESS
+    }
+}
+
+impl uart::Transmit<'a> for Uart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> (ReturnCode, Option<&'static mut [u8]>) {
+        if self.tx_buffer.is_some() {
+            return (ReturnCode::EBUSY, Some(tx_buffer));
+        }
+        if tx_len > tx_buffer.len() {
+            return (ReturnCode::ESIZE, Some(tx_buffer));
+        }
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&tx_buffer[..tx_len]);
+        let _ = stdout.flush();
+        self.check_output(&tx_buffer[..tx_len]);
+
+        // Like hardware, complete the transmission with an interrupt rather
+        // than calling the client back from within this call.
+        self.tx_len.set(tx_len);
+        self.tx_buffer.replace(tx_buffer);
+        self.events.raise(interrupts::UART);
+        (ReturnCode::SUCCESS, None)
+    }
+
+    fn transmit_word(&self, _word: u32) -> ReturnCode {
+        ReturnCode::ENOSUPPORT
+    }
+
+    fn transmit_abort(&self) -> ReturnCode {
+        // Transmissions complete immediately, so there is nothing to abort.
+        ReturnCode::SUCCESS
+    }
+}
+
+impl uart::Receive<'a> for Uart<'a> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> (ReturnCode, Option<&'static mut [u8]>) {
+        if self.rx_buffer.is_some() {
+            return (ReturnCode::EBUSY, Some(rx_buffer));
+        }
+        if rx_len > rx_buffer.len() {
+            return (ReturnCode::ESIZE, Some(rx_buffer));
+        }
+        self.rx_len.set(rx_len);
+        self.rx_index.set(0);
+        self.rx_buffer.replace(rx_buffer);
+        // Bytes may have arrived before the buffer.
+        if !self.input.lock().unwrap().is_empty() {
+            self.events.raise(interrupts::UART);
+        }
+        (ReturnCode::SUCCESS, None)
+    }
+
+    fn receive_word(&self) -> ReturnCode {
+        ReturnCode::ENOSUPPORT
+    }
+
+    fn receive_abort(&self) -> ReturnCode {
+        if self.rx_buffer.is_none() {
+            return ReturnCode::SUCCESS;
+        }
+        self.rx_aborted.set(true);
+        self.events.raise(interrupts::UART);
+        ReturnCode::EBUSY
+    }
+}
+
+impl uart::UartData<'a> for Uart<'a> {}
+impl uart::Uart<'a> for Uart<'a> {}
@@ -0,0 +1,354 @@
+//! System calls for apps running on host threads.
+//!
+//! Apps for the host chip are Rust functions built into the simulator. The
+//! entry point of an app and its callbacks are `AppFn`s, which take the four
+//! arguments the kernel passes to functions it calls in the app. The entry
+//! point is called with the start of the app's flash, the start and size of
+//! its memory, and its initial program break.
+//!
+//! When the kernel preempts an app, it parks the thread of the app with a
+//! `SIGUSR1` signal, and the thread waits in the signal handler until the app
+//! is switched to again.
+//!
+//! The functions in this module can only be called from the thread of an
+//! app. Buffers shared with the kernel through `allow()` must be in the
+//! app's memory, for example allocated with `alloc()`, since the kernel
+//! refuses buffers anywhere else.
+//!
+//! ```ignore
+//! extern "C" fn main(_: usize, _: usize, _: usize, _: usize) {
+//!     let message = userspace::alloc(6);
+//!     message.copy_from_slice(b"Hello\n");
+//!     userspace::allow(CONSOLE, 1, message);
+//!     userspace::command(CONSOLE, 1, message.len(), 0);
+//! }
+//! ```
+
+use std::cell::Cell;
+use std::mem;
+use std::os::unix::thread::{JoinHandleExt, RawPthread};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Once};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use kernel::procs::FunctionCall;
+
+use crate::events::{Events, Trap};
+use crate::library;
+use crate::syscall::Resume;
+
+/// A function of an app the kernel can call: an entry point or a callback.
+pub type AppFn = extern "C" fn(usize, usize, usize, usize);
+
+/// Signal that parks the thread of a preempted app, `SIGUSR1` on Linux.
+const PREEMPT_SIGNAL: i32 = 10;
+
+/// How often a parked app thread checks whether it may run again.
+const PARK_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn pthread_kill(thread: RawPthread, signum: i32) -> i32;
+}
+
+/// The app thread is running app code.
+const RUNNING: usize = 0;
+/// The app thread is in the kernel, or unwinding, and does not run app code
+/// until the kernel resumes it.
+const IN_KERNEL: usize = 1;
+/// The app thread is parked until the kernel resumes it.
+const PARKED: usize = 2;
+
+/// Preemption state shared by the kernel thread and the thread of an app.
+struct Preemption {
+    /// Set by the kernel thread while the app must not run.
+    requested: AtomicBool,
+    /// What the app thread is doing: `RUNNING`, `IN_KERNEL` or `PARKED`.
+    state: AtomicUsize,
+    /// How often the app thread was parked.
+    parks: AtomicUsize,
+}
+
+impl Preemption {
+    /// Park the app thread while the kernel preempts the app. Threads that
+    /// are in the kernel stay there, and panicking threads unwind into the
+    /// kernel, so they do not run app code either.
+    fn park(&self) {
+        if self.state.load(Ordering::SeqCst) != RUNNING || thread::panicking() {
+            return;
+        }
+        if !self.requested.load(Ordering::SeqCst) {
+            return;
+        }
+        self.state.store(PARKED, Ordering::SeqCst);
+        self.parks.fetch_add(1, Ordering::SeqCst);
+        // Only sleep here: the app may have been interrupted while holding
+        // any lock, so the handler must not take one.
+        while self.requested.load(Ordering::SeqCst) {
+            thread::sleep(PARK_POLL_INTERVAL);
+        }
+        self.state.store(RUNNING, Ordering::SeqCst);
+    }
+}
+
+/// The kernel's handle on the thread of an app.
+crate struct AppThread {
+    resume: Sender<Resume>,
+    preemption: Arc<Preemption>,
+    /// Keeps the thread joinable, so that it can be signalled even after it
+    /// exited.
+    thread: JoinHandle<()>,
+}
+
+impl AppThread {
+    /// Let the app run again, passing `resume` to its thread if the app is
+    /// in the kernel. Returns whether the thread is still there.
+    crate fn resume(&self, resume: Option<Resume>) -> bool {
+        self.preemption.requested.store(false, Ordering::SeqCst);
+        resume.map_or(true, |resume| self.resume.send(resume).is_ok())
+    }
+
+    /// Stop the app, and wait until its thread no longer runs app code.
+    crate fn preempt(&self) {
+        self.preemption.requested.store(true, Ordering::SeqCst);
+        unsafe {
+            pthread_kill(self.thread.as_pthread_t(), PREEMPT_SIGNAL);
+        }
+        while self.preemption.state.load(Ordering::SeqCst) == RUNNING {
+            thread::yield_now();
+        }
+    }
+}
+
+struct AppContext {
+    context: usize,
+    events: &'static Events,
+    resume: Receiver<Resume>,
+    preemption: Arc<Preemption>,
+}
+
+thread_local! {
+    /// The context of the app running on this thread.
+    static CONTEXT: Cell<Option<&'static AppContext>> = Cell::new(None);
+}
+
+/// Payload of the panic that unwinds the thread of an app whose context the
+/// kernel has dropped.
+struct Terminated;
+
+extern "C" fn preempted(_signum: i32) {
+    let _ = CONTEXT.try_with(|current| current.get().map(|app| app.preemption.park()));
+}
+
+/// Start the thread of the app with the context `context`, which calls the
+/// entry point `init` points to.
+crate fn start(context: usize, events: &'static Events, init: FunctionCall) -> AppThread {
+    static INSTALL_HANDLER: Once = Once::new();
+    INSTALL_HANDLER.call_once(|| unsafe {
+        signal(PREEMPT_SIGNAL, preempted);
+    });
+
+    let (sender, receiver) = channel();
+    let preemption = Arc::new(Preemption {
+        requested: AtomicBool::new(false),
+        state: AtomicUsize::new(RUNNING),
+        parks: AtomicUsize::new(0),
+    });
+    let app_preemption = preemption.clone();
+    let thread = thread::Builder::new()
+        .name(format!("app-{}", context))
+        .spawn(move || {
+            let app: &'static AppContext = Box::leak(Box::new(AppContext {
+                context: context,
+                events: events,
+                resume: receiver,
+                preemption: app_preemption,
+            }));
+            CONTEXT.with(|current| current.set(Some(app)));
+            // The app may have been preempted before the signal handler
+            // could find it.
+            app.preemption.park();
+
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let args = (
+                    init.argument0,
+                    init.argument1,
+                    init.argument2,
+                    init.argument3,
+                );
+                // The kernel passes the start of the TBF binary as the
+                // function to start the app with. The binary of a built-in
+                // app holds the address of its entry point.
+                match unsafe { library::load(init.pc as *const u8, context) } {
+                    Some(entry) => library::call(entry, args),
+                    None => {
+                        let entry = unsafe { ptr::read_unaligned(init.pc as *const AppFn) };
+                        entry(args.0, args.1, args.2, args.3);
+                    }
+                }
+                // Like on hardware, an app that returns from its entry point
+                // only runs callbacks from then on.
+                loop {
+                    yield_now();
+                }
+            }));
+            if let Err(payload) = result {
+                app.preemption.state.store(IN_KERNEL, Ordering::SeqCst);
+                if !payload.is::<Terminated>() {
+                    events.trap(context, Trap::Fault);
+                }
+            }
+        })
+        .expect("failed to start app thread");
+    AppThread {
+        resume: sender,
+        preemption: preemption,
+        thread: thread,
+    }
+}
+
+/// Trap into the kernel with the system call `number` and wait until the
+/// kernel resumes the app.
+pub fn syscall(number: u8, r0: usize, r1: usize, r2: usize, r3: usize) -> isize {
+    if let Some(syscall) = library::simulator_syscall() {
+        return syscall(number, r0, r1, r2, r3);
+    }
+    let app = CONTEXT
+        .with(|current| current.get())
+        .expect("system call outside of an app thread");
+    app.preemption.state.store(IN_KERNEL, Ordering::SeqCst);
+    app.events.trap(
+        app.context,
+        Trap::Syscall {
+            number: number,
+            args: [r0, r1, r2, r3],
+        },
+    );
+    let resume = app.resume.recv();
+    // If the kernel preempted the app while it was on its way out of the
+    // kernel, it waits here until it is switched to again.
+    if resume.is_ok() {
+        app.preemption.state.store(RUNNING, Ordering::SeqCst);
+        app.preemption.park();
+    }
+    match resume {
+        Ok(Resume::Return(value)) => value,
+        Ok(Resume::Call(function)) => {
+            let callback: AppFn = unsafe { mem::transmute(function.pc) };
+            callback(
+                function.argument0,
+                function.argument1,
+                function.argument2,
+                function.argument3,
+            );
+            0
+        }
+        Err(_) => panic::resume_unwind(Box::new(Terminated)),
+    }
+}
+
+/// Report a fault of the app running on this thread to the kernel, without
+/// unwinding its thread.
+crate fn fault() {
+    let app = CONTEXT
+        .with(|current| current.get())
+        .expect("fault outside of an app thread");
+    app.preemption.state.store(IN_KERNEL, Ordering::SeqCst);
+    app.events.trap(app.context, Trap::Fault);
+}
+
+/// How often the kernel has stopped the app while it was running app code,
+/// which lets tests tell whether other apps may have run in the meantime.
+pub fn preemptions() -> usize {
+    CONTEXT
+        .with(|current| current.get())
+        .map_or(0, |app| app.preemption.parks.load(Ordering::SeqCst))
+}
+
+/// Wait for a callback, and run it.
+pub fn yield_now() {
+    syscall(0, 0, 0, 0, 0);
+}
+
+/// Run callbacks until `done` returns true.
+pub fn yield_for(done: &dyn Fn() -> bool) {
+    while !done() {
+        yield_now();
+    }
+}
+
+/// Subscribe `callback` to the upcall `subscribe_num` of the driver
+/// `driver_num`, or unsubscribe if `callback` is `None`.
+pub fn subscribe(
+    driver_num: usize,
+    subscribe_num: usize,
+    callback: Option<AppFn>,
+    userdata: usize,
+) -> isize {
+    let callback_ptr = callback.map_or(0, |callback| callback as usize);
+    syscall(1, driver_num, subscribe_num, callback_ptr, userdata)
+}
+
+pub fn command(driver_num: usize, command_num: usize, arg0: usize, arg1: usize) -> isize {
+    syscall(2, driver_num, command_num, arg0, arg1)
+}
+
+/// Share `buffer` with the driver `driver_num`. The driver may access the
+/// buffer until the app calls `unallow()` or shares another buffer.
+pub fn allow(driver_num: usize, allow_num: usize, buffer: &mut [u8]) -> isize {
+    syscall(
+        3,
+        driver_num,
+        allow_num,
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+    )
+}
+
+/// Share `buffer` with the driver `driver_num` for reading only. The driver
+/// may read the buffer until the app calls `unallow_readonly()` or shares
+/// another buffer.
+pub fn allow_readonly(driver_num: usize, allow_num: usize, buffer: &[u8]) -> isize {
+    syscall(
+        5,
+        driver_num,
+        allow_num,
+        buffer.as_ptr() as usize,
+        buffer.len(),
+    )
+}
+
+/// Stop sharing a read-only buffer with the driver `driver_num`.
+pub fn unallow_readonly(driver_num: usize, allow_num: usize) -> isize {
+    syscall(5, driver_num, allow_num, 0, 0)
+}
+
+/// Stop sharing a buffer with the driver `driver_num`.
+pub fn unallow(driver_num: usize, allow_num: usize) -> isize {
+    syscall(3, driver_num, allow_num, 0, 0)
+}
+
+pub fn memop(operand: usize, arg0: usize) -> isize {
+    syscall(4, operand, arg0, 0, 0)
+}
+
+/// Allocate `len` zeroed bytes of the app's memory, by moving its program
+/// break. Panics, and so faults the app, if there is not enough memory.
+pub fn alloc(len: usize) -> &'static mut [u8] {
+    // Keep the break aligned to a word.
+    let start = memop(1, (len + 7) & !7);
+    if start < 0 {
+        panic!("out of app memory");
+    }
+    unsafe {
+        let buffer = std::slice::from_raw_parts_mut(start as *mut u8, len);
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+        buffer
+    }
+}
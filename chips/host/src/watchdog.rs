@@ -0,0 +1,68 @@
+//! Watchdog based on the host clock.
+//!
+//! A host thread checks when the watchdog was last tickled. If it was not
+//! tickled within the period, the simulator "resets" by exiting with status
+//! `RESET_EXIT_STATUS`, so that whatever runs it can start it again.
+
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kernel::hil;
+
+/// Exit status of the simulator when the watchdog expires.
+pub const RESET_EXIT_STATUS: i32 = 3;
+
+/// How often the watchdog thread checks for expiration.
+const CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+struct State {
+    /// The period of the watchdog while it is running.
+    period: Option<Duration>,
+    last_tickle: Instant,
+}
+
+pub struct HostWatchdog {
+    state: Arc<Mutex<State>>,
+}
+
+impl HostWatchdog {
+    crate fn new() -> HostWatchdog {
+        let state = Arc::new(Mutex::new(State {
+            period: None,
+            last_tickle: Instant::now(),
+        }));
+        let thread_state = state.clone();
+        thread::Builder::new()
+            .name("watchdog".to_string())
+            .spawn(move || loop {
+                thread::sleep(CHECK_INTERVAL);
+                let state = thread_state.lock().unwrap();
+                if let Some(period) = state.period {
+                    if state.last_tickle.elapsed() > period {
+                        eprintln!("Watchdog expired, resetting");
+                        process::exit(RESET_EXIT_STATUS);
+                    }
+                }
+            })
+            .unwrap();
+        HostWatchdog { state: state }
+    }
+}
+
+impl hil::watchdog::Watchdog for HostWatchdog {
+    fn start(&self, period: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.period = Some(Duration::from_millis(period as u64));
+        state.last_tickle = Instant::now();
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().period = None;
+    }
+
+    fn tickle(&self) {
+        self.state.lock().unwrap().last_tickle = Instant::now();
+    }
+}
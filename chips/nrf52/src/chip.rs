@@ -1,10 +1,12 @@
-use crate::deferred_call_tasks::DeferredCallTask;
+use crate::ieee802154_radio;
 use crate::interrupt_service::InterruptService;
 use crate::nvmc;
+use crate::power;
 use core::fmt::Write;
 use cortexm4::{self, nvic};
-use kernel::common::deferred_call;
+use kernel::common::deferred_call::DeferredCallClient;
 use kernel::debug;
+use kernel::power::SleepState;
 
 pub struct NRF52<I: InterruptService> {
     mpu: cortexm4::mpu::MPU,
@@ -15,6 +17,8 @@ pub struct NRF52<I: InterruptService> {
 
 impl<I: InterruptService> NRF52<I> {
     pub unsafe fn new(interrupt_service: I) -> NRF52<I> {
+        nvmc::NVMC.register();
+        ieee802154_radio::RADIO.register();
         NRF52 {
             mpu: cortexm4::mpu::MPU::new(),
             userspace_kernel_boundary: cortexm4::syscall::SysCall::new(),
@@ -46,11 +50,7 @@ impl<I: InterruptService> kernel::Chip for NRF52<I> {
     fn service_pending_interrupts(&self) {
         unsafe {
             loop {
-                if let Some(task) = deferred_call::DeferredCall::next_pending() {
-                    match task {
-                        DeferredCallTask::Nvmc => nvmc::NVMC.handle_interrupt(),
-                    }
-                } else if let Some(interrupt) = nvic::next_pending() {
+                if let Some(interrupt) = nvic::next_pending() {
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         debug!("NvicIdx not supported by Tock: {}", interrupt);
                     }
@@ -65,7 +65,7 @@ impl<I: InterruptService> kernel::Chip for NRF52<I> {
     }
 
     fn has_pending_interrupts(&self) -> bool {
-        unsafe { nvic::has_pending() || deferred_call::has_tasks() }
+        unsafe { nvic::has_pending() }
     }
 
     fn sleep(&self) {
@@ -74,6 +74,17 @@ impl<I: InterruptService> kernel::Chip for NRF52<I> {
         }
     }
 
+    fn sleep_in(&self, deepest: SleepState) {
+        // `Idle` is the constant latency mode, in which the regulators and
+        // the high-frequency clock keep running while the CPU sleeps. The
+        // deeper states are the low-power mode. System OFF does not retain
+        // RAM, so it is not used.
+        unsafe {
+            power::POWER.set_constant_latency(deepest == SleepState::Idle);
+        }
+        self.sleep();
+    }
+
     unsafe fn atomic<F, R>(&self, f: F) -> R
     where
         F: FnOnce() -> R,
@@ -4,10 +4,12 @@ use core::cell::Cell;
 use core::convert::TryFrom;
 use kernel;
 use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::common::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::common::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil::radio::{self, PowerClient};
 use kernel::hil::time::Alarm;
+use kernel::power::{self, PowerRequirement};
 use kernel::ReturnCode;
 
 use crate::ppi;
@@ -655,6 +657,8 @@ pub struct Radio {
     tx_power: Cell<TxPower>,
     rx_client: OptionalCell<&'static dyn radio::RxClient>,
     tx_client: OptionalCell<&'static dyn radio::TxClient>,
+    cfg_client: OptionalCell<&'static dyn radio::ConfigClient>,
+    ed_client: OptionalCell<&'static dyn radio::EnergyDetectClient>,
     tx_buf: TakeCell<'static, [u8]>,
     rx_buf: TakeCell<'static, [u8]>,
     addr: Cell<u16>,
@@ -665,6 +669,14 @@ pub struct Radio {
     random_nonce: Cell<u32>,
     channel: Cell<RadioChannel>,
     transmitting: Cell<bool>,
+    /// Whether the radio is powered, and so holds a vote for the
+    /// high-frequency clock.
+    powered: Cell<bool>,
+    config_pending: Cell<bool>,
+    ed_pending: Cell<bool>,
+    /// Configuration and RSSI measurements complete without an interrupt,
+    /// so their callbacks are deferred.
+    deferred_call: DeferredCall,
 }
 
 pub static mut RADIO: Radio = Radio::new();
@@ -676,6 +688,8 @@ impl Radio {
             tx_power: Cell::new(TxPower::ZerodBm),
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
+            cfg_client: OptionalCell::empty(),
+            ed_client: OptionalCell::empty(),
             tx_buf: TakeCell::empty(),
             rx_buf: TakeCell::empty(),
             addr: Cell::new(0),
@@ -686,9 +700,24 @@ impl Radio {
             random_nonce: Cell::new(0xDEADBEEF),
             channel: Cell::new(RadioChannel::DataChannel11),
             transmitting: Cell::new(false),
+            powered: Cell::new(false),
+            config_pending: Cell::new(false),
+            ed_pending: Cell::new(false),
+            deferred_call: DeferredCall::new(),
         }
     }
 
+    fn is_receiving(&self) -> bool {
+        let state = self.registers.state.get();
+        state == nrf5x::constants::RADIO_STATE_RXIDLE || state == nrf5x::constants::RADIO_STATE_RX
+    }
+
+    fn start_rssi_sample(&self) {
+        let regs = &*self.registers;
+        regs.event_rssiend.write(Event::READY::CLEAR);
+        regs.task_rssistart.write(Task::ENABLE::SET);
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.registers
             .mode
@@ -734,11 +763,18 @@ impl Radio {
         // reset and enable power
         regs.power.write(Task::ENABLE::CLEAR);
         regs.power.write(Task::ENABLE::SET);
+        // The radio timing needs the crystal oscillator.
+        if !self.powered.replace(true) {
+            power::vote(PowerRequirement::HighFrequencyClock);
+        }
     }
 
     fn radio_off(&self) {
         let regs = &*self.registers;
         regs.power.write(Task::ENABLE::CLEAR);
+        if self.powered.replace(false) {
+            power::release(PowerRequirement::HighFrequencyClock);
+        }
     }
 
     fn set_tx_power(&self) {
@@ -1055,9 +1091,29 @@ impl kernel::hil::radio::RadioConfig for Radio {
     fn config_commit(&self) {
         self.radio_off();
         self.radio_initialize(self.channel.get());
+        self.config_pending.set(true);
+        self.deferred_call.set();
     }
 
-    fn set_config_client(&self, _client: &'static dyn radio::ConfigClient) {}
+    fn set_config_client(&self, client: &'static dyn radio::ConfigClient) {
+        self.cfg_client.set(client);
+    }
+
+    /// The radio samples the RSSI only while receiving. A sample takes
+    /// 0.25us, so it is read from the deferred call.
+    fn energy_detect(&self) -> ReturnCode {
+        if self.ed_pending.get() {
+            return ReturnCode::EBUSY;
+        }
+        self.ed_pending.set(true);
+        self.start_rssi_sample();
+        self.deferred_call.set();
+        ReturnCode::SUCCESS
+    }
+
+    fn set_energy_detect_client(&self, client: &'static dyn radio::EnergyDetectClient) {
+        self.ed_client.set(client);
+    }
 
     //#################################################
     /// Accessors
@@ -1124,6 +1180,37 @@ impl kernel::hil::radio::RadioConfig for Radio {
     }
 }
 
+impl DeferredCallClient for Radio {
+    fn handle_deferred_call(&self) {
+        let regs = &*self.registers;
+        if self.config_pending.replace(false) {
+            self.cfg_client
+                .map(|client| client.config_done(ReturnCode::SUCCESS));
+        }
+        if self.ed_pending.get() {
+            if regs.event_rssiend.is_set(Event::READY) {
+                regs.event_rssiend.write(Event::READY::CLEAR);
+                self.ed_pending.set(false);
+                // The sample is the received power in -dBm
+                let power = -(regs.rssisample.read(RssiSample::RSSISAMPLE) as i8);
+                self.ed_client
+                    .map(|client| client.energy_detect_done(power, ReturnCode::SUCCESS));
+            } else {
+                // The RSSISTART task is ignored unless the radio is
+                // receiving, for example while it transmits a frame
+                if self.is_receiving() {
+                    self.start_rssi_sample();
+                }
+                self.deferred_call.set();
+            }
+        }
+    }
+
+    fn register(&'static self) -> ReturnCode {
+        self.deferred_call.register(self)
+    }
+}
+
 impl kernel::hil::radio::RadioData for Radio {
     fn set_receive_client(&self, client: &'static dyn radio::RxClient, buffer: &'static mut [u8]) {
         self.rx_client.set(client);
@@ -4,17 +4,17 @@
 
 use core::cell::Cell;
 use core::ops::{Index, IndexMut};
+use core::ptr;
 use kernel::common::cells::OptionalCell;
 use kernel::common::cells::TakeCell;
 use kernel::common::cells::VolatileCell;
-use kernel::common::deferred_call::DeferredCall;
+use kernel::common::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::common::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::common::StaticRef;
+use kernel::crash_dump::CrashDumpFlash;
 use kernel::hil;
 use kernel::ReturnCode;
 
-use crate::deferred_call_tasks::DeferredCallTask;
-
 const NVMC_BASE: StaticRef<NvmcRegisters> =
     unsafe { StaticRef::new(0x4001E400 as *const NvmcRegisters) };
 
@@ -136,11 +136,6 @@ register_bitfields! [u32,
     ]
 ];
 
-/// This mechanism allows us to schedule "interrupts" even if the hardware
-/// does not support them.
-static DEFERRED_CALL: DeferredCall<DeferredCallTask> =
-    unsafe { DeferredCall::new(DeferredCallTask::Nvmc) };
-
 const PAGE_SIZE: usize = 4096;
 
 /// This is a wrapper around a u8 array that is sized to a single page for the
@@ -203,6 +198,9 @@ pub struct Nvmc {
     client: OptionalCell<&'static dyn hil::flash::Client<Nvmc>>,
     buffer: TakeCell<'static, NrfPage>,
     state: Cell<FlashState>,
+    /// This mechanism allows us to schedule "interrupts" even if the hardware
+    /// does not support them.
+    deferred_call: DeferredCall,
 }
 
 impl Nvmc {
@@ -212,6 +210,7 @@ impl Nvmc {
             client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
             state: Cell::new(FlashState::Ready),
+            deferred_call: DeferredCall::new(),
         }
     }
 
@@ -300,12 +299,12 @@ impl Nvmc {
         // Mark the need for an interrupt so we can call the read done
         // callback.
         self.state.set(FlashState::Read);
-        DEFERRED_CALL.set();
+        self.deferred_call.set();
 
         ReturnCode::SUCCESS
     }
 
-    fn write_page(&self, page_number: usize, data: &'static mut NrfPage) -> ReturnCode {
+    fn write_page_helper(&self, page_number: usize, data: &[u8]) {
         let regs = &*self.registers;
 
         // Need to erase the page first.
@@ -328,6 +327,10 @@ impl Nvmc {
         // Make sure that the NVMC is done. The CPU should be blocked while the
         // write is happening, but it doesn't hurt to check too.
         while !regs.ready.is_set(Ready::READY) {}
+    }
+
+    fn write_page(&self, page_number: usize, data: &'static mut NrfPage) -> ReturnCode {
+        self.write_page_helper(page_number, &data.0);
 
         // Save the buffer so we can return it with the callback.
         self.buffer.replace(data);
@@ -335,7 +338,7 @@ impl Nvmc {
         // Mark the need for an interrupt so we can call the write done
         // callback.
         self.state.set(FlashState::Write);
-        DEFERRED_CALL.set();
+        self.deferred_call.set();
 
         ReturnCode::SUCCESS
     }
@@ -347,7 +350,7 @@ impl Nvmc {
         // Mark that we want to trigger a pseudo interrupt so that we can issue
         // the callback even though the NVMC is completely blocking.
         self.state.set(FlashState::Erase);
-        DEFERRED_CALL.set();
+        self.deferred_call.set();
 
         ReturnCode::SUCCESS
     }
@@ -374,3 +377,39 @@ impl hil::flash::Flash for Nvmc {
         self.erase_page(page_number)
     }
 }
+
+impl DeferredCallClient for Nvmc {
+    fn handle_deferred_call(&self) {
+        self.handle_interrupt();
+    }
+
+    fn register(&'static self) -> ReturnCode {
+        self.deferred_call.register(self)
+    }
+}
+
+impl CrashDumpFlash for Nvmc {
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn read_blocking(&self, address: usize, buf: &mut [u8]) {
+        unsafe {
+            ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len());
+        }
+    }
+
+    fn write_page_blocking(&self, address: usize, data: &[u8]) -> ReturnCode {
+        // Flash operations of the NVMC always complete before they return,
+        // but the client of an operation whose callback is still deferred
+        // expects the flash to be unchanged until then.
+        if self.state.get() != FlashState::Ready {
+            return ReturnCode::EBUSY;
+        }
+        if address % PAGE_SIZE != 0 || data.len() != PAGE_SIZE {
+            return ReturnCode::EINVAL;
+        }
+        self.write_page_helper(address / PAGE_SIZE, data);
+        ReturnCode::SUCCESS
+    }
+}
@@ -1,5 +1,6 @@
 //! Power management
 
+use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
 use kernel::common::registers::{
     register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
@@ -238,6 +239,8 @@ pub struct Power<'a> {
     registers: StaticRef<PowerRegisters>,
     /// A client to which to notify USB plug-in/plug-out/power-ready events.
     usb_client: OptionalCell<&'a dyn PowerClient>,
+    /// Whether the chip is in constant latency mode.
+    constant_latency: Cell<bool>,
 }
 
 pub enum MainVoltage {
@@ -267,6 +270,22 @@ impl<'a> Power<'a> {
         Power {
             registers: POWER_BASE,
             usb_client: OptionalCell::empty(),
+            constant_latency: Cell::new(false),
+        }
+    }
+
+    /// Select the sub power mode the chip sleeps in. In constant latency mode
+    /// the high-frequency clock and the regulators keep running while the
+    /// CPU sleeps, so it wakes up at once. In low-power mode, the default,
+    /// they only run while a peripheral needs them.
+    pub fn set_constant_latency(&self, constant_latency: bool) {
+        let regs = &*self.registers;
+        if self.constant_latency.replace(constant_latency) != constant_latency {
+            if constant_latency {
+                regs.task_constlat.write(Task::ENABLE::SET);
+            } else {
+                regs.task_lowpwr.write(Task::ENABLE::SET);
+            }
         }
     }
 
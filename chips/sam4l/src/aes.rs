@@ -17,6 +17,8 @@ use kernel::common::StaticRef;
 use kernel::debug;
 use kernel::hil;
 use kernel::hil::symmetric_encryption::{AES128_BLOCK_SIZE, AES128_KEY_SIZE};
+use kernel::power::{PowerClock, PowerRequirement};
+use kernel::ClockInterface;
 use kernel::ReturnCode;
 
 #[allow(dead_code)]
@@ -158,6 +160,9 @@ pub struct Aes<'a> {
 
     // The index just after the last byte of `dest` that should receive encrypted output
     stop_index: Cell<usize>,
+
+    // The AESA runs from the bus clock, which must keep running while it is enabled
+    hsb_clock: PowerClock<pm::Clock>,
 }
 
 impl Aes<'a> {
@@ -170,11 +175,15 @@ impl Aes<'a> {
             write_index: Cell::new(0),
             read_index: Cell::new(0),
             stop_index: Cell::new(0),
+            hsb_clock: PowerClock::new(
+                pm::Clock::HSB(pm::HSBClock::AESA),
+                PowerRequirement::HighFrequencyClock,
+            ),
         }
     }
 
     fn enable_clock(&self) {
-        pm::enable_clock(pm::Clock::HSB(pm::HSBClock::AESA));
+        self.hsb_clock.enable();
         scif::generic_clock_enable_divided(
             scif::GenericClock::GCLK4,
             scif::ClockSource::CLK_CPU,
@@ -185,7 +194,7 @@ impl Aes<'a> {
 
     fn disable_clock(&self) {
         scif::generic_clock_disable(scif::GenericClock::GCLK4);
-        pm::disable_clock(pm::Clock::HSB(pm::HSBClock::AESA));
+        self.hsb_clock.disable();
     }
 
     fn enable_interrupts(&self) {
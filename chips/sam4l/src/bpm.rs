@@ -172,6 +172,24 @@ pub unsafe fn set_ck32source(source: CK32Source) {
         .modify_no_read(control, PowerModeControl::CK32S.val(source as u32));
 }
 
+/// The SLEEP modes that stop the CPU while the clocks needed to wake it up
+/// quickly keep running. The WAIT and RETENTION modes are entered with
+/// `SLEEPDEEP` instead.
+pub enum SleepMode {
+    /// SLEEP0: only the CPU stops.
+    CpuStopped = 0,
+    /// SLEEP1: the CPU and the AHB clocks stop.
+    CpuAhbStopped = 1,
+}
+
+/// Selects the SLEEP mode that `wfi` enters while `SLEEPDEEP` is clear.
+pub unsafe fn set_sleep_mode(mode: SleepMode) {
+    let control = BPM.pmcon.extract();
+    unlock_register(0x1c); // Control
+    BPM.pmcon
+        .modify_no_read(control, PowerModeControl::SLEEP.val(mode as u32));
+}
+
 unsafe fn unlock_register(register_offset: u32) {
     BPM.unlock
         .write(Unlock::KEY.val(BPM_UNLOCK_KEY) + Unlock::ADDR.val(register_offset));
@@ -4,9 +4,9 @@ use crate::acifc;
 use crate::adc;
 use crate::aes;
 use crate::ast;
+use crate::bpm;
 use crate::crccu;
 use crate::dac;
-use crate::deferred_call_tasks::Task;
 use crate::dma;
 use crate::eic;
 use crate::flashcalw;
@@ -21,7 +21,8 @@ use crate::usbc;
 
 use core::fmt::Write;
 use cortexm4;
-use kernel::common::deferred_call;
+use kernel::common::deferred_call::DeferredCallClient;
+use kernel::power::SleepState;
 use kernel::Chip;
 
 pub struct Sam4l {
@@ -64,6 +65,8 @@ impl Sam4l {
         adc::ADC0.set_dma(&dma::DMA_CHANNELS[13]);
         dma::DMA_CHANNELS[13].initialize(&mut adc::ADC0, dma::DMAWidth::Width16Bit);
 
+        flashcalw::FLASH_CONTROLLER.register();
+
         Sam4l {
             mpu: cortexm4::mpu::MPU::new(),
             userspace_kernel_boundary: cortexm4::syscall::SysCall::new(),
@@ -80,11 +83,7 @@ impl Chip for Sam4l {
     fn service_pending_interrupts(&self) {
         unsafe {
             loop {
-                if let Some(task) = deferred_call::DeferredCall::next_pending() {
-                    match task {
-                        Task::Flashcalw => flashcalw::FLASH_CONTROLLER.handle_interrupt(),
-                    }
-                } else if let Some(interrupt) = cortexm4::nvic::next_pending() {
+                if let Some(interrupt) = cortexm4::nvic::next_pending() {
                     match interrupt {
                         nvic::ASTALARM => ast::AST.handle_interrupt(),
 
@@ -168,7 +167,7 @@ impl Chip for Sam4l {
     }
 
     fn has_pending_interrupts(&self) -> bool {
-        unsafe { cortexm4::nvic::has_pending() || deferred_call::has_tasks() }
+        unsafe { cortexm4::nvic::has_pending() }
     }
 
     fn mpu(&self) -> &cortexm4::mpu::MPU {
@@ -184,12 +183,27 @@ impl Chip for Sam4l {
     }
 
     fn sleep(&self) {
-        if pm::deep_sleep_ready() {
+        self.sleep_in(SleepState::DeepSleep);
+    }
+
+    fn sleep_in(&self, deepest: SleepState) {
+        // `Idle` is the SLEEP0 mode, in which only the CPU stops, and `Sleep`
+        // the SLEEP1 mode, which stops the AHB clocks as well. `DeepSleep`
+        // uses the WAIT mode if no peripheral that stops in it has its clock
+        // enabled, and SLEEP1 otherwise. The RETENTION mode is not used,
+        // since GPIO interrupts cannot wake the chip from it.
+        if deepest == SleepState::DeepSleep && pm::deep_sleep_ready() {
             unsafe {
                 cortexm4::scb::set_sleepdeep();
             }
         } else {
+            let mode = if deepest == SleepState::Idle {
+                bpm::SleepMode::CpuStopped
+            } else {
+                bpm::SleepMode::CpuAhbStopped
+            };
             unsafe {
+                bpm::set_sleep_mode(mode);
                 cortexm4::scb::unset_sleepdeep();
             }
         }
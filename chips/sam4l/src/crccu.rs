@@ -53,6 +53,8 @@ use core::cell::Cell;
 use kernel::common::registers::{register_bitfields, FieldValue, ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil::crc::{self, CrcAlg};
+use kernel::power::{PowerClock, PowerRequirement};
+use kernel::ClockInterface;
 use kernel::ReturnCode;
 
 // Base address of CRCCU registers.  See "7.1 Product Mapping"
@@ -232,6 +234,10 @@ pub struct Crccu<'a> {
     // Guaranteed room for a Descriptor with 512-byte alignment.
     // (Can we do this statically instead?)
     descriptor_space: [u8; DSCR_RESERVE],
+
+    // The CRCCU reads memory over the bus, whose clock must keep running
+    // while it is enabled
+    hsb_clock: PowerClock<Clock>,
 }
 
 const DSCR_RESERVE: usize = 512 + 5 * 4;
@@ -244,6 +250,10 @@ impl Crccu<'a> {
             state: Cell::new(State::Invalid),
             alg: Cell::new(CrcAlg::Crc32C),
             descriptor_space: [0; DSCR_RESERVE],
+            hsb_clock: PowerClock::new(
+                Clock::HSB(HSBClock::CRCCU),
+                PowerRequirement::HighFrequencyClock,
+            ),
         }
     }
 
@@ -259,7 +269,7 @@ impl Crccu<'a> {
         if self.state.get() != State::Enabled {
             self.init();
             // see "10.7.4 Clock Mask"
-            enable_clock(Clock::HSB(HSBClock::CRCCU));
+            self.hsb_clock.enable();
             enable_clock(Clock::PBB(PBBClock::CRCCU));
             self.state.set(State::Enabled);
         }
@@ -269,7 +279,7 @@ impl Crccu<'a> {
     fn disable(&self) {
         if self.state.get() == State::Enabled {
             disable_clock(Clock::PBB(PBBClock::CRCCU));
-            disable_clock(Clock::HSB(HSBClock::CRCCU));
+            self.hsb_clock.disable();
             self.state.set(State::Initialized);
         }
     }
@@ -7,6 +7,7 @@ use kernel::common::cells::VolatileCell;
 use kernel::common::cells::{OptionalCell, TakeCell};
 use kernel::common::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
+use kernel::power::{self, PowerRequirement};
 
 /// Memory registers for a DMA channel. Section 16.6.1 of the datasheet.
 #[repr(C)]
@@ -198,6 +199,9 @@ pub struct DMAChannel {
     width: Cell<DMAWidth>,
     enabled: Cell<bool>,
     buffer: TakeCell<'static, [u8]>,
+    // Whether a transfer is prepared or in progress, which keeps the chip
+    // from stopping the bus clocks.
+    transferring: Cell<bool>,
 }
 
 pub trait DMAClient {
@@ -216,6 +220,7 @@ impl DMAChannel {
             width: Cell::new(DMAWidth::Width8Bit),
             enabled: Cell::new(false),
             buffer: TakeCell::empty(),
+            transferring: Cell::new(false),
         }
     }
 
@@ -271,6 +276,7 @@ impl DMAChannel {
             .idr
             .write(Interrupt::TERR::SET + Interrupt::TRC::SET + Interrupt::RCZ::SET);
         let channel = registers.psr.get();
+        self.end_transfer();
 
         self.client.map(|client| {
             client.transfer_done(channel);
@@ -307,6 +313,16 @@ impl DMAChannel {
         // Store the buffer reference in the TakeCell so it can be returned to
         // the caller in `handle_interrupt`
         self.buffer.replace(buf);
+
+        if !self.transferring.replace(true) {
+            power::vote(PowerRequirement::Dma);
+        }
+    }
+
+    fn end_transfer(&self) {
+        if self.transferring.replace(false) {
+            power::release(PowerRequirement::Dma);
+        }
     }
 
     pub fn do_transfer(&self, pid: DMAPeripheral, buf: &'static mut [u8], len: usize) {
@@ -324,6 +340,7 @@ impl DMAChannel {
 
         // Reset counter
         registers.tcr.write(TransferCounter::TCV.val(0));
+        self.end_transfer();
 
         self.buffer.take()
     }
@@ -21,14 +21,14 @@
 //! - Author:  Kevin Baichoo <kbaichoo@cs.stanford.edu>
 //! - Date: July 27, 2016
 
-use crate::deferred_call_tasks::Task;
 use crate::pm;
 use core::cell::Cell;
 use core::ops::{Index, IndexMut};
 use kernel::common::cells::{OptionalCell, TakeCell};
-use kernel::common::deferred_call::DeferredCall;
+use kernel::common::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::common::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
+use kernel::crash_dump::CrashDumpFlash;
 use kernel::hil;
 use kernel::ReturnCode;
 
@@ -318,8 +318,6 @@ enum RegKey {
     GPFRLO,
 }
 
-static DEFERRED_CALL: DeferredCall<Task> = unsafe { DeferredCall::new(Task::Flashcalw) };
-
 /// There are 18 recognized commands for the flash. These are "bare-bones"
 /// commands and values that are written to the Flash's command register to
 /// inform the flash what to do. Table 14-5.
@@ -412,6 +410,7 @@ pub struct FLASHCALW {
     client: OptionalCell<&'static dyn hil::flash::Client<FLASHCALW>>,
     current_state: Cell<FlashState>,
     buffer: TakeCell<'static, Sam4lPage>,
+    deferred_call: DeferredCall,
 }
 
 // static instance for the board. Only one FLASHCALW on chip.
@@ -452,6 +451,7 @@ impl FLASHCALW {
             client: OptionalCell::empty(),
             current_state: Cell::new(FlashState::Unconfigured),
             buffer: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
         }
     }
 
@@ -770,6 +770,12 @@ impl FLASHCALW {
     // Instead of having several memset/memcpy functions as Atmel's ASF
     // implementation will only have one to write to the page buffer.
     fn write_to_page_buffer(&self, pg_buff_addr: usize) {
+        self.buffer.map(|buffer| {
+            self.copy_to_page_buffer(pg_buff_addr, &buffer.0);
+        });
+    }
+
+    fn copy_to_page_buffer(&self, pg_buff_addr: usize, data: &[u8]) {
         let mut page_buffer: *mut u8 = pg_buff_addr as *mut u8;
 
         // Errata 45.1.7 - Need to write a 64-bit all one word for every write
@@ -777,24 +783,36 @@ impl FLASHCALW {
         let cleared_double_word: [u8; 8] = [255; 8];
         let clr_ptr: *const u8 = &cleared_double_word[0] as *const u8;
 
-        self.buffer.map(|buffer| {
-            unsafe {
-                use core::ptr;
-
-                let mut start_buffer: *const u8 = &buffer[0] as *const u8;
-                let mut data_transfered: u32 = 0;
-                while data_transfered < PAGE_SIZE {
-                    // errata copy..
-                    ptr::copy(clr_ptr, page_buffer, 8);
-
-                    // real copy
-                    ptr::copy(start_buffer, page_buffer, 8);
-                    page_buffer = page_buffer.offset(8);
-                    start_buffer = start_buffer.offset(8);
-                    data_transfered += 8;
-                }
+        unsafe {
+            use core::ptr;
+
+            let mut start_buffer: *const u8 = &data[0] as *const u8;
+            let mut data_transfered: u32 = 0;
+            while data_transfered < PAGE_SIZE {
+                // errata copy..
+                ptr::copy(clr_ptr, page_buffer, 8);
+
+                // real copy
+                ptr::copy(start_buffer, page_buffer, 8);
+                page_buffer = page_buffer.offset(8);
+                start_buffer = start_buffer.offset(8);
+                data_transfered += 8;
             }
-        });
+        }
+    }
+
+    /// Wait for the command started last to finish, without relying on the
+    /// ready interrupt. Returns whether the command failed. Reading the
+    /// status clears the error flags, so they are checked in the same read
+    /// that sees the command finish.
+    fn wait_until_ready(&self) -> bool {
+        let regs: &FlashcalwRegisters = &*self.registers;
+        loop {
+            let status = regs.fsr.extract();
+            if status.is_set(FlashStatus::FRDY) {
+                return status.is_set(FlashStatus::LOCKE) || status.is_set(FlashStatus::PROGE);
+            }
+        }
     }
 }
 
@@ -870,7 +888,7 @@ impl FLASHCALW {
         // This is kind of strange, but because read() in this case is
         // synchronous, we still need to schedule as if we had an interrupt so
         // we can allow this function to return and then call the callback.
-        DEFERRED_CALL.set();
+        self.deferred_call.set();
 
         ReturnCode::SUCCESS
     }
@@ -930,3 +948,66 @@ impl hil::flash::Flash for FLASHCALW {
         self.erase_page(page_number as i32)
     }
 }
+
+impl DeferredCallClient for FLASHCALW {
+    fn handle_deferred_call(&self) {
+        self.handle_interrupt();
+    }
+
+    fn register(&'static self) -> ReturnCode {
+        self.deferred_call.register(self)
+    }
+}
+
+impl CrashDumpFlash for FLASHCALW {
+    fn page_size(&self) -> usize {
+        PAGE_SIZE as usize
+    }
+
+    fn read_blocking(&self, address: usize, buf: &mut [u8]) {
+        pm::enable_clock(self.ahb_clock);
+        unsafe {
+            core::ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len());
+        }
+    }
+
+    fn write_page_blocking(&self, address: usize, data: &[u8]) -> ReturnCode {
+        let regs: &FlashcalwRegisters = &*self.registers;
+        pm::enable_clock(self.ahb_clock);
+
+        match self.current_state.get() {
+            FlashState::Unconfigured => return ReturnCode::FAIL,
+            FlashState::Ready => {}
+            _ => return ReturnCode::EBUSY,
+        }
+        if address % PAGE_SIZE as usize != 0 || data.len() != PAGE_SIZE as usize {
+            return ReturnCode::EINVAL;
+        }
+        let page = (address / PAGE_SIZE as usize) as i32;
+
+        // Run the same steps as the interrupt driven write, but spin on FRDY
+        // after each of them.
+        self.lock_page_region(page, false);
+        let mut failed = self.wait_until_ready();
+        if !failed {
+            self.flashcalw_erase_page(page);
+            failed = self.wait_until_ready();
+        }
+        if !failed {
+            self.clear_page_buffer();
+            self.copy_to_page_buffer(address, data);
+            self.flashcalw_write_page(page);
+            failed = self.wait_until_ready();
+        }
+
+        // `issue_command()` enabled the ready interrupt for these commands.
+        regs.fcr.modify(FlashControl::FRDY::CLEAR);
+        self.invalidate_cache();
+
+        if failed {
+            ReturnCode::FAIL
+        } else {
+            ReturnCode::SUCCESS
+        }
+    }
+}
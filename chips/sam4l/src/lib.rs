@@ -8,8 +8,6 @@
 #![feature(in_band_lifetimes)]
 #![no_std]
 
-mod deferred_call_tasks;
-
 pub mod acifc;
 pub mod adc;
 pub mod aes;
@@ -19,6 +19,8 @@ use kernel::common::StaticRef;
 use kernel::debug as debugln;
 use kernel::hil;
 use kernel::hil::usb::TransferType;
+use kernel::power::{PowerClock, PowerRequirement};
+use kernel::ClockInterface;
 
 // The following macros provide some diagnostics and panics(!)
 // while this module is experimental and should eventually be removed or
@@ -242,6 +244,8 @@ pub struct Usbc<'a> {
     state: OptionalCell<State>,
     requests: [Cell<Requests>; N_ENDPOINTS],
     client: Option<&'a dyn hil::usb::Client<'a>>,
+    // The bus clock, which must keep running while the controller is enabled
+    hsb_clock: PowerClock<Clock>,
 }
 
 #[derive(Copy, Clone, Default, Debug)]
@@ -448,6 +452,10 @@ impl Usbc<'a> {
                 Cell::new(Requests::new()),
                 Cell::new(Requests::new()),
             ],
+            hsb_clock: PowerClock::new(
+                Clock::HSB(HSBClock::USBC),
+                PowerRequirement::HighFrequencyClock,
+            ),
         }
     }
 
@@ -503,7 +511,7 @@ impl Usbc<'a> {
                 //   10.7.4 says no, but 17.5.3 says yes
                 // Also, "Being in Idle state does not require the USB clocks to
                 //   be activated" (17.6.2)
-                enable_clock(Clock::HSB(HSBClock::USBC));
+                self.hsb_clock.enable();
                 enable_clock(Clock::PBB(PBBClock::USBC));
 
                 // If we got to this state via disable() instead of chip reset,
@@ -567,7 +575,7 @@ impl Usbc<'a> {
                 usbc_regs().usbcon.modify(Control::USBE::CLEAR);
 
                 disable_clock(Clock::PBB(PBBClock::USBC));
-                disable_clock(Clock::HSB(HSBClock::USBC));
+                self.hsb_clock.disable();
 
                 self.set_state(State::Reset);
             }
@@ -4,20 +4,31 @@ use core::fmt;
 use core::ptr::NonNull;
 
 use crate::config;
-use crate::debug;
 use crate::process;
 use crate::sched::Kernel;
+use crate::syscall_trace::TraceEvent;
 
 /// Userspace app identifier.
+///
+/// An `AppId` refers to one instance of a process: the process in slot `idx`
+/// of the kernel's process table, as it was when the `AppId` was created.
+/// Every time a process is loaded or restarted it gets a new generation, and
+/// the kernel rejects `AppId`s of older generations, so a capsule that holds
+/// on to an `AppId` cannot reach a restarted or different app by mistake.
+///
+/// To recognize the same application across restarts, reflashes and reboots,
+/// capsules should use the `stable_id()`.
 #[derive(Clone, Copy)]
 pub struct AppId {
     crate kernel: &'static Kernel,
     idx: usize,
+    generation: usize,
+    stable_id: u32,
 }
 
 impl PartialEq for AppId {
     fn eq(&self, other: &AppId) -> bool {
-        self.idx == other.idx
+        self.idx == other.idx && self.generation == other.generation
     }
 }
 
@@ -30,10 +41,12 @@ impl fmt::Debug for AppId {
 }
 
 impl AppId {
-    crate fn new(kernel: &'static Kernel, idx: usize) -> AppId {
+    crate fn new(kernel: &'static Kernel, idx: usize, generation: usize, stable_id: u32) -> AppId {
         AppId {
             kernel: kernel,
             idx: idx,
+            generation: generation,
+            stable_id: stable_id,
         }
     }
 
@@ -41,12 +54,55 @@ impl AppId {
         self.idx
     }
 
+    /// Which instance of the process in slot `idx()` this `AppId` refers to.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Identifier of the application that stays the same when the process
+    /// is restarted, reflashed or loaded into a different slot. It is taken
+    /// from the app's TBF header, or is a hash of its package name if the
+    /// header does not set one. Capsules can use it as the key for state they
+    /// keep for an app beyond the lifetime of its process, such as storage.
+    pub fn stable_id(&self) -> u32 {
+        self.stable_id
+    }
+
+    /// Whether the process this `AppId` refers to still exists and has not
+    /// been restarted or replaced since.
+    pub fn is_current(&self) -> bool {
+        self.kernel.app_map_or(false, *self, |_| true)
+    }
+
+    /// Whether the process was explicitly given permission to use the driver
+    /// `driver_number`, by the board with `Kernel::set_syscall_permissions()`
+    /// or in the Permissions element of a TBF header that the app's
+    /// credentials authenticated. Drivers that give
+    /// access to sensitive state can require this on top of the check every
+    /// system call gets, which lets unlisted processes through by default.
+    pub fn has_driver_permission(&self, driver_number: usize) -> bool {
+        self.kernel.app_map_or(false, *self, |process| {
+            self.kernel.driver_listed(process, driver_number)
+        })
+    }
+
+    /// Whether the board gave the process permission to use the driver
+    /// `driver_number` with `Kernel::set_syscall_permissions()`. Unlike
+    /// `has_driver_permission()`, the Permissions element of the process's
+    /// TBF header never counts, so drivers that give access to the state of
+    /// other processes or the kernel can leave the decision to the board.
+    pub fn has_board_permission(&self, driver_number: usize) -> bool {
+        self.kernel.app_map_or(false, *self, |process| {
+            self.kernel.board_listed(process, driver_number)
+        })
+    }
+
     /// Returns the full address of the start and end of the flash region that
     /// the app owns and can write to. This includes the app's code and data and
     /// any padding at the end of the app. It does not include the TBF header,
     /// or any space that the kernel is using for any potential bookkeeping.
     pub fn get_editable_flash_range(&self) -> (usize, usize) {
-        self.kernel.process_map_or((0, 0), self.idx, |process| {
+        self.kernel.app_map_or((0, 0), *self, |process| {
             let start = process.flash_non_protected_start() as usize;
             let end = process.flash_end() as usize;
             (start, end)
@@ -101,7 +157,7 @@ impl Callback {
         let res = self
             .app_id
             .kernel
-            .process_map_or(false, self.app_id.idx(), |process| {
+            .app_map_or(false, self.app_id, |process| {
                 process.enqueue_task(process::Task::FunctionCall(process::FunctionCall {
                     source: process::FunctionCallSource::Driver(self.callback_id),
                     argument0: r0,
@@ -112,17 +168,13 @@ impl Callback {
                 }))
             });
         if config::CONFIG.trace_syscalls {
-            debug!(
-                "[{:?}] schedule[{:#x}:{}] @{:#x}({:#x}, {:#x}, {:#x}, {:#x}) = {}",
+            self.app_id.kernel.trace(
                 self.app_id,
-                self.callback_id.driver_num,
+                TraceEvent::CallbackScheduled,
+                Some(self.callback_id.driver_num),
                 self.callback_id.subscribe_num,
-                self.fn_ptr.as_ptr() as usize,
-                r0,
-                r1,
-                r2,
-                self.appdata,
-                res
+                [r0, r1, r2, self.appdata],
+                res as usize,
             );
         }
         res
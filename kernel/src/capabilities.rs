@@ -58,6 +58,10 @@ pub unsafe trait MainLoopCapability {}
 /// memory, for example by creating grants.
 pub unsafe trait MemoryAllocationCapability {}
 
+/// The `RebootCapability` capability allows the holder to reset the board,
+/// for example from a debugging console.
+pub unsafe trait RebootCapability {}
+
 /// The `UdpDriverCapability` capability allows the holder to use
 /// two functions only allowed by the UDP driver.
 /// The first `driver_send_to()` function in udp_send.rs, which does
@@ -1,17 +1,71 @@
 //! Deferred call mechanism.
 //!
-//! This is a tool to allow chip peripherals to schedule "interrupts"
-//! in the chip scheduler if the hardware doesn't support interrupts where
-//! they are needed.
+//! This is a tool to allow chip peripherals and capsules to schedule work that
+//! the kernel runs later from its main loop, like the bottom half of an
+//! interrupt. Chip peripherals use it if the hardware doesn't support
+//! interrupts where they are needed, and capsules use it to return the
+//! function call stack up to the scheduler before calling their clients back,
+//! so that they work like hardware devices.
+//!
+//! Every user holds a `DeferredCall` and implements `DeferredCallClient`. The
+//! kernel keeps a table of up to `MAX_DEFERRED_CALLS` registered clients, so
+//! deferred calls are registered at runtime during board setup, and chips do
+//! not need to list them anywhere.
+//!
+//! The kernel services deferred calls in a round-robin order: each pass
+//! handles every call that was pending when the pass started at most once, and
+//! starts after the call that was handled last. A client that keeps setting
+//! its deferred call therefore cannot starve the others.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! use kernel::common::deferred_call::{DeferredCall, DeferredCallClient};
+//! use kernel::{static_init, ReturnCode};
+//!
+//! struct SomeCapsule {
+//!     deferred_call: DeferredCall,
+//! }
+//!
+//! impl SomeCapsule {
+//!     fn do_something(&self) {
+//!         // Finish later, from the kernel loop.
+//!         self.deferred_call.set();
+//!     }
+//! }
+//!
+//! impl DeferredCallClient for SomeCapsule {
+//!     fn handle_deferred_call(&self) {
+//!         // Call the client of the capsule.
+//!     }
+//!
+//!     fn register(&'static self) -> ReturnCode {
+//!         self.deferred_call.register(self)
+//!     }
+//! }
+//!
+//! // During board setup:
+//! let some_capsule = unsafe {
+//!     static_init!(
+//!         SomeCapsule,
+//!         SomeCapsule {
+//!             deferred_call: DeferredCall::new(),
+//!         }
+//!     )
+//! };
+//! assert_eq!(some_capsule.register(), ReturnCode::SUCCESS);
+//! ```
 
+use crate::common::cells::OptionalCell;
+use crate::returncode::ReturnCode;
 use core::cell::UnsafeCell;
-use core::convert::Into;
-use core::convert::TryFrom;
-use core::convert::TryInto;
 use core::intrinsics;
-use core::marker::Copy;
 use core::marker::Sync;
 
+/// How many deferred calls can be registered in total.
+pub const MAX_DEFERRED_CALLS: usize = 32;
+
 /// AtomicUsize with no CAS operations that works on targets that have "no atomic
 /// support" according to their specification. This makes it work on thumbv6
 /// platforms.
@@ -44,41 +98,156 @@ impl AtomicUsize {
 
 unsafe impl Sync for AtomicUsize {}
 
+/// One bit for each registered deferred call that is pending.
 static DEFERRED_CALL: AtomicUsize = AtomicUsize::new(0);
 
+/// How many deferred calls have been registered.
+static REGISTERED: AtomicUsize = AtomicUsize::new(0);
+
+/// The deferred call the next pass starts with.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// The clients of the registered deferred calls, indexed by their bit in
+/// `DEFERRED_CALL`.
+static mut CLIENTS: [Option<&'static dyn DeferredCallClient>; MAX_DEFERRED_CALLS] =
+    [None; MAX_DEFERRED_CALLS];
+
+/// Usage of the deferred calls, for debugging
+#[derive(Copy, Clone, Debug)]
+pub struct DeferredCallStats {
+    /// Number of registered deferred calls
+    pub registered: usize,
+    /// Number of deferred calls that can be registered in total
+    pub capacity: usize,
+    /// Number of pending deferred calls
+    pub pending: usize,
+}
+
 /// Are there any pending `DeferredCall`s?
 pub fn has_tasks() -> bool {
     DEFERRED_CALL.load_relaxed() != 0
 }
 
+/// How many `DeferredCall`s are pending.
+pub fn pending_count() -> usize {
+    DEFERRED_CALL.load_relaxed().count_ones() as usize
+}
+
+/// Usage statistics of the deferred calls.
+pub fn stats() -> DeferredCallStats {
+    DeferredCallStats {
+        registered: REGISTERED.load_relaxed(),
+        capacity: MAX_DEFERRED_CALLS,
+        pending: pending_count(),
+    }
+}
+
+/// Handle the pending deferred calls while the supplied predicate returns
+/// `true`.
+///
+/// Each call that is pending when this is called is handled at most once,
+/// starting after the call that was handled last. Calls that are set while
+/// this runs are handled by the next call to this function.
+///
+/// This is called by the kernel loop, and must not be called from within a
+/// deferred call.
+pub unsafe fn service_while<F: Fn() -> bool>(f: F) {
+    let pending = DEFERRED_CALL.load_relaxed();
+    let start = NEXT.load_relaxed();
+    for offset in 0..MAX_DEFERRED_CALLS {
+        let index = (start + offset) % MAX_DEFERRED_CALLS;
+        if pending & (1 << index) == 0 {
+            continue;
+        }
+        if !f() {
+            break;
+        }
+        DEFERRED_CALL.store_relaxed(DEFERRED_CALL.load_relaxed() & !(1 << index));
+        NEXT.store_relaxed((index + 1) % MAX_DEFERRED_CALLS);
+        if let Some(client) = CLIENTS[index] {
+            client.handle_deferred_call();
+        }
+    }
+}
+
+/// Client of a `DeferredCall`.
+///
+/// This trait needs to be implemented for some struct to receive its deferred
+/// calls.
+pub trait DeferredCallClient {
+    /// Called from the kernel loop after the deferred call has been set.
+    fn handle_deferred_call(&self);
+
+    /// Register the deferred call of the client with the kernel. This must be
+    /// called during board setup, before the deferred call is set. Returns
+    /// `ENOMEM` if there is no room left in the kernel's table.
+    fn register(&'static self) -> ReturnCode;
+}
+
 /// Represents a way to generate an asynchronous call without a hardware
-/// interrupt. Supports up to 32 possible deferrable tasks.
-pub struct DeferredCall<T>(T);
+/// interrupt.
+pub struct DeferredCall {
+    index: OptionalCell<usize>,
+}
 
-impl<T: Into<usize> + TryFrom<usize> + Copy> DeferredCall<T> {
-    /// Creates a new DeferredCall
+impl DeferredCall {
+    /// Creates a new `DeferredCall`, which must be registered before it is
+    /// set.
+    pub const fn new() -> DeferredCall {
+        DeferredCall {
+            index: OptionalCell::empty(),
+        }
+    }
+
+    /// Register `client` to handle this `DeferredCall`.
     ///
-    /// Only create one per task, preferably in the module that it will be used
-    /// in.
-    pub const unsafe fn new(task: T) -> Self {
-        DeferredCall(task)
+    /// Registering is idempotent: a `DeferredCall` that is registered already,
+    /// or that is registered for a client that has a slot already, keeps
+    /// using that slot. Returns `ENOMEM` if all `MAX_DEFERRED_CALLS` slots are
+    /// taken.
+    pub fn register(&self, client: &'static dyn DeferredCallClient) -> ReturnCode {
+        if self.index.is_some() {
+            return ReturnCode::SUCCESS;
+        }
+        let registered = REGISTERED.load_relaxed();
+        let client_ptr = client as *const dyn DeferredCallClient as *const ();
+        let known = unsafe {
+            CLIENTS[..registered].iter().position(|registered_client| {
+                registered_client.map_or(false, |registered_client| {
+                    registered_client as *const dyn DeferredCallClient as *const () == client_ptr
+                })
+            })
+        };
+        let index = match known {
+            Some(index) => index,
+            None if registered < MAX_DEFERRED_CALLS => {
+                unsafe {
+                    CLIENTS[registered] = Some(client);
+                }
+                REGISTERED.store_relaxed(registered + 1);
+                registered
+            }
+            None => return ReturnCode::ENOMEM,
+        };
+        self.index.set(index);
+        ReturnCode::SUCCESS
     }
 
-    /// Set the `DeferredCall` as pending
-    pub fn set(&self) {
-        DEFERRED_CALL.fetch_or_relaxed(1 << self.0.into() as usize);
+    /// Set the `DeferredCall` as pending.
+    ///
+    /// Returns `ERESERVE` if the `DeferredCall` has not been registered, in
+    /// which case it is not called.
+    pub fn set(&self) -> ReturnCode {
+        self.index.map_or(ReturnCode::ERESERVE, |index| {
+            DEFERRED_CALL.fetch_or_relaxed(1 << *index);
+            ReturnCode::SUCCESS
+        })
     }
 
-    /// Gets and clears the next pending `DeferredCall`
-    pub fn next_pending() -> Option<T> {
-        let val = DEFERRED_CALL.load_relaxed();
-        if val == 0 {
-            None
-        } else {
-            let bit = val.trailing_zeros() as usize;
-            let new_val = val & !(1 << bit);
-            DEFERRED_CALL.store_relaxed(new_val);
-            bit.try_into().ok()
-        }
+    /// Is the `DeferredCall` pending?
+    pub fn is_pending(&self) -> bool {
+        self.index.map_or(false, |index| {
+            DEFERRED_CALL.load_relaxed() & (1 << *index) != 0
+        })
     }
 }
@@ -60,9 +60,13 @@ macro_rules! static_init_half {
 /// initialize their state. The linker script kernel_layout.ld makes
 /// sure that the .storage section is aligned on a 512-byte boundary
 /// and the next section is aligned as well.
+///
+/// Attributes before the name, such as doc comments, are applied to the
+/// symbol.
 #[macro_export]
 macro_rules! storage_volume {
-    ($N:ident, $kB:expr) => {
+    ($(#[$attr:meta])* $N:ident, $kB:expr) => {
+        $(#[$attr])*
         #[link_section = ".storage"]
         #[used]
         #[no_mangle]
@@ -28,15 +28,18 @@
 /// To change the configuration, modify the relevant values in the `CONFIG` constant object defined
 /// at the end of this file.
 crate struct Config {
-    /// Whether the kernel should trace syscalls to the debug output.
+    /// Whether the kernel should trace syscalls.
     ///
-    /// If enabled, the kernel will print a message in the debug output for each system call and
-    /// callback, with details including the application ID, and system call or callback parameters.
+    /// If enabled, the kernel will add a record to the `SyscallTrace` the board set with
+    /// `Kernel::set_syscall_trace()` for each system call and callback, with details including
+    /// the application ID, and system call or callback parameters. See `syscall_trace` for how
+    /// the records are written out. Boards that do not set a `SyscallTrace` only pay for a check
+    /// per system call; disabling this removes the tracing code entirely.
     crate trace_syscalls: bool,
 }
 
 /// A unique instance of `Config` where compile-time configuration options are defined. These
 /// options are available in the kernel crate to be used for relevant configuration.
 crate const CONFIG: Config = Config {
-    trace_syscalls: false,
+    trace_syscalls: true,
 };
@@ -0,0 +1,553 @@
+//! Crash dumps that persist in flash across resets.
+//!
+//! When a board installs a `CrashDump` with `set_crash_dump()`, the kernel
+//! saves what it knows about a crash into a region of flash reserved for the
+//! dump, so that it can still be read after the board has been reset. Two
+//! kinds of crashes are recorded:
+//!
+//! - Kernel panics, from `debug::panic()`: the panic message, the state of
+//!   the chip and the state of every process, as printed on the console. A
+//!   panic replaces any dump saved earlier.
+//! - Faults of processes that do not panic the kernel: the memory map and
+//!   the full state of the faulting process. A fault is only recorded if the
+//!   region does not hold a dump yet, so that an app which keeps faulting
+//!   and restarting neither wears out the flash nor hides an earlier dump.
+//!   The kernel keeps running after a fault, so the text is formatted into a
+//!   RAM buffer, and written to flash one page at a time from a deferred
+//!   call. Pages that cannot be written because the flash is busy are
+//!   retried later.
+//!
+//! After the next boot, `capsules::crash_dump` lets apps read and clear the
+//! dump, and `tools/crash_dump` decodes a copy of the region read from the
+//! board.
+//!
+//! Flash format
+//! ------------
+//!
+//! The first page of the region holds the header, and the text of the dump
+//! starts at the second page. The header records where the text starts, so
+//! decoders do not need to know the page size of the chip. All values are
+//! little endian.
+//!
+//! ```text
+//! offset  size  field
+//!  0      4     magic, `MAGIC` ("TKCD")
+//!  4      2     format version, `VERSION`
+//!  6      2     kind of crash (`CrashKind`): 1 kernel panic, 2 process fault
+//!  8      4     length of the text in bytes
+//! 12      4     32-bit FNV-1a hash of the text
+//! 16      4     offset of the text from the start of the region
+//! 20      4     flags: bit 0 is set if the text was cut off at the end of
+//!               the region
+//! ```
+//!
+//! The header is erased before the text is written and only written once
+//! the text is complete, so a dump interrupted by a reset is never valid.
+
+use core::fmt::{Result, Write};
+use core::panic::PanicInfo;
+
+use crate::common::cells::{OptionalCell, TakeCell};
+use crate::common::deferred_call::{DeferredCall, DeferredCallClient};
+use crate::debug::{self, IoWrite};
+use crate::platform::Chip;
+use crate::process::ProcessType;
+use crate::returncode::ReturnCode;
+
+/// First word of a valid header.
+pub const MAGIC: u32 = 0x4443_4b54;
+
+/// Version of the flash format.
+pub const VERSION: u16 = 1;
+
+/// Length of the header at the start of the first page.
+pub const HEADER_LEN: usize = 24;
+
+const FLAG_TRUNCATED: u32 = 1;
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Flash operations a crash dump needs.
+///
+/// Crash dumps are written while the kernel panics, with interrupts
+/// disabled, so unlike `hil::flash::Flash` every operation completes before
+/// it returns.
+pub trait CrashDumpFlash {
+    /// The size of a flash page in bytes.
+    fn page_size(&self) -> usize;
+
+    /// Copy `buf.len()` bytes of flash starting at `address` into `buf`.
+    fn read_blocking(&self, address: usize, buf: &mut [u8]);
+
+    /// Erase the page that starts at `address` and write `data` to it.
+    /// `data` must be exactly one page long. Returns `EBUSY` if an operation
+    /// started through the asynchronous flash interface is in progress.
+    fn write_page_blocking(&self, address: usize, data: &[u8]) -> ReturnCode;
+}
+
+/// What caused a crash dump.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CrashKind {
+    KernelPanic = 1,
+    ProcessFault = 2,
+}
+
+/// The header of a valid crash dump.
+#[derive(Copy, Clone, Debug)]
+pub struct CrashDumpHeader {
+    pub kind: CrashKind,
+    /// Length of the text of the dump in bytes.
+    pub length: usize,
+    /// Whether the text was cut off because it did not fit in the region.
+    pub truncated: bool,
+}
+
+fn fnv1a(hash: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Header of a dump, written to the first page of the region once the text
+/// is complete.
+#[derive(Copy, Clone)]
+struct Summary {
+    kind: CrashKind,
+    length: usize,
+    hash: u32,
+    truncated: bool,
+}
+
+impl Summary {
+    fn new(kind: CrashKind) -> Summary {
+        Summary {
+            kind,
+            length: 0,
+            hash: FNV_OFFSET_BASIS,
+            truncated: false,
+        }
+    }
+
+    /// Fill `page` with the header.
+    fn write_header(&self, page: &mut [u8]) {
+        for byte in page.iter_mut() {
+            *byte = 0xff;
+        }
+        let flags = if self.truncated { FLAG_TRUNCATED } else { 0 };
+        let text_offset = page.len() as u32;
+        page[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        page[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        page[6..8].copy_from_slice(&(self.kind as u16).to_le_bytes());
+        page[8..12].copy_from_slice(&(self.length as u32).to_le_bytes());
+        page[12..16].copy_from_slice(&self.hash.to_le_bytes());
+        page[16..20].copy_from_slice(&text_offset.to_le_bytes());
+        page[20..24].copy_from_slice(&flags.to_le_bytes());
+    }
+}
+
+/// A fault dump whose text is in the RAM buffer and is being written to
+/// flash.
+#[derive(Copy, Clone)]
+struct FaultWrite {
+    summary: Summary,
+    /// Whether the header of the previous dump has been erased.
+    erased: bool,
+    /// How much of the text has been written to flash.
+    written: usize,
+    /// Whether the header has been written, which completes the dump.
+    complete: bool,
+}
+
+/// A region of flash that holds at most one crash dump.
+pub struct CrashDump {
+    flash: &'static dyn CrashDumpFlash,
+    start: usize,
+    size: usize,
+    page: TakeCell<'static, [u8]>,
+    /// The text of a fault dump, until it is written to flash.
+    fault_text: TakeCell<'static, [u8]>,
+    fault_write: OptionalCell<FaultWrite>,
+    deferred_call: DeferredCall,
+}
+
+impl CrashDump {
+    /// Keep crash dumps in the `size` bytes of flash at `start`. The region
+    /// must be page aligned and at least two pages long, and `page` must be
+    /// one page long. The text of fault dumps is cut off at the length of
+    /// `fault_text`.
+    ///
+    /// The deferred call of the `CrashDump` must be registered with
+    /// `DeferredCallClient::register()` for fault dumps to be written.
+    pub fn new(
+        flash: &'static dyn CrashDumpFlash,
+        start: usize,
+        size: usize,
+        page: &'static mut [u8],
+        fault_text: &'static mut [u8],
+    ) -> CrashDump {
+        CrashDump {
+            flash,
+            start,
+            size,
+            page: TakeCell::new(page),
+            fault_text: TakeCell::new(fault_text),
+            fault_write: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// The most text a dump can hold.
+    fn capacity(&self) -> usize {
+        self.size.saturating_sub(self.flash.page_size())
+    }
+
+    fn text_start(&self) -> usize {
+        self.start + self.flash.page_size()
+    }
+
+    /// Returns the header of the saved dump, or `None` if the region does
+    /// not hold a complete dump.
+    pub fn header(&self) -> Option<CrashDumpHeader> {
+        let mut raw = [0; HEADER_LEN];
+        self.flash.read_blocking(self.start, &mut raw);
+        if read_u32(&raw, 0) != MAGIC || read_u16(&raw, 4) != VERSION {
+            return None;
+        }
+        let kind = match read_u16(&raw, 6) {
+            1 => CrashKind::KernelPanic,
+            2 => CrashKind::ProcessFault,
+            _ => return None,
+        };
+        let length = read_u32(&raw, 8) as usize;
+        if length > self.capacity() || read_u32(&raw, 16) as usize != self.flash.page_size() {
+            return None;
+        }
+
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut chunk = [0; 32];
+        let mut offset = 0;
+        while offset < length {
+            let len = core::cmp::min(chunk.len(), length - offset);
+            self.flash
+                .read_blocking(self.text_start() + offset, &mut chunk[..len]);
+            hash = fnv1a(hash, &chunk[..len]);
+            offset += len;
+        }
+        if hash != read_u32(&raw, 12) {
+            return None;
+        }
+
+        Some(CrashDumpHeader {
+            kind,
+            length,
+            truncated: read_u32(&raw, 20) & FLAG_TRUNCATED != 0,
+        })
+    }
+
+    /// Copy text of the saved dump starting at `offset` into `buf`. Returns
+    /// the number of bytes copied, which is 0 if there is no dump or
+    /// `offset` is past its end.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let length = self.header().map_or(0, |header| header.length);
+        if offset >= length {
+            return 0;
+        }
+        let len = core::cmp::min(buf.len(), length - offset);
+        self.flash
+            .read_blocking(self.text_start() + offset, &mut buf[..len]);
+        len
+    }
+
+    /// Erase the saved dump, if there is one, and stop writing a fault dump.
+    pub fn clear(&self) -> ReturnCode {
+        self.fault_write.clear();
+        self.page.take().map_or(ReturnCode::EBUSY, |page| {
+            let result = self.erase_header(page);
+            self.page.replace(page);
+            result
+        })
+    }
+
+    fn erase_header(&self, page: &mut [u8]) -> ReturnCode {
+        for byte in page.iter_mut() {
+            *byte = 0xff;
+        }
+        self.flash.write_page_blocking(self.start, page)
+    }
+
+    /// Start a new dump, erasing the one saved before.
+    fn writer(&self, kind: CrashKind) -> Option<DumpWriter> {
+        self.page.take().and_then(|page| {
+            if self.erase_header(page) != ReturnCode::SUCCESS {
+                self.page.replace(page);
+                return None;
+            }
+            // A panic replaces a fault dump that is still being written.
+            self.fault_write.clear();
+            Some(DumpWriter {
+                dump: self,
+                page,
+                fill: 0,
+                address: self.text_start(),
+                summary: Summary::new(kind),
+                failed: false,
+            })
+        })
+    }
+
+    /// Format a fault dump with `format` into the RAM buffer, and start
+    /// writing it to flash. Does nothing if a fault dump is being written
+    /// already, or if the deferred call that writes it is not registered.
+    fn record_fault<F: FnOnce(&mut TextWriter)>(&self, format: F) {
+        if self.fault_write.is_some() {
+            return;
+        }
+        self.fault_text.map(|text| {
+            let capacity = core::cmp::min(text.len(), self.capacity());
+            let mut writer = TextWriter {
+                text: &mut text[..capacity],
+                summary: Summary::new(CrashKind::ProcessFault),
+            };
+            format(&mut writer);
+            self.fault_write.set(FaultWrite {
+                summary: writer.summary,
+                erased: false,
+                written: 0,
+                complete: false,
+            });
+            if self.deferred_call.set() != ReturnCode::SUCCESS {
+                self.fault_write.clear();
+            }
+        });
+    }
+
+    /// Take the next step of writing a fault dump: erase the previous header,
+    /// write a page of text, or write the header, which completes the dump.
+    /// Returns the result of the flash operation.
+    fn write_fault_step(&self, write: &mut FaultWrite, page: &mut [u8], text: &[u8]) -> ReturnCode {
+        if !write.erased {
+            let result = self.erase_header(page);
+            write.erased = result == ReturnCode::SUCCESS;
+            result
+        } else if write.written < write.summary.length {
+            let len = core::cmp::min(page.len(), write.summary.length - write.written);
+            page[..len].copy_from_slice(&text[write.written..write.written + len]);
+            for byte in page[len..].iter_mut() {
+                *byte = 0xff;
+            }
+            let result = self
+                .flash
+                .write_page_blocking(self.text_start() + write.written, page);
+            if result == ReturnCode::SUCCESS {
+                write.written += page.len();
+            }
+            result
+        } else {
+            write.summary.write_header(page);
+            let result = self.flash.write_page_blocking(self.start, page);
+            write.complete = result == ReturnCode::SUCCESS;
+            result
+        }
+    }
+}
+
+impl DeferredCallClient for CrashDump {
+    /// Write the next page of the fault dump, if one is being written.
+    fn handle_deferred_call(&self) {
+        let mut write = match self.fault_write.take() {
+            Some(write) => write,
+            None => return,
+        };
+        let result = self.page.take().map_or(ReturnCode::EBUSY, |page| {
+            let result = self.fault_text.map_or(ReturnCode::FAIL, |text| {
+                self.write_fault_step(&mut write, page, text)
+            });
+            self.page.replace(page);
+            result
+        });
+        match result {
+            ReturnCode::SUCCESS if write.complete => {}
+            ReturnCode::SUCCESS | ReturnCode::EBUSY => {
+                self.fault_write.set(write);
+                self.deferred_call.set();
+            }
+            // The flash failed, give up on the dump.
+            _ => {}
+        }
+    }
+
+    fn register(&'static self) -> ReturnCode {
+        self.deferred_call.register(self)
+    }
+}
+
+/// Formats the text of a fault dump into the RAM buffer.
+struct TextWriter<'a> {
+    text: &'a mut [u8],
+    summary: Summary,
+}
+
+impl TextWriter<'a> {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.summary.length >= self.text.len() {
+                self.summary.truncated = true;
+                return;
+            }
+            self.text[self.summary.length] = byte;
+            self.summary.length += 1;
+            self.summary.hash = fnv1a(self.summary.hash, &[byte]);
+        }
+    }
+}
+
+impl Write for TextWriter<'a> {
+    fn write_str(&mut self, s: &str) -> Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl IoWrite for TextWriter<'a> {
+    fn write(&mut self, buf: &[u8]) {
+        self.push(buf);
+    }
+}
+
+/// Writes the text of a dump page by page. The header is only written by
+/// `finish()`.
+struct DumpWriter<'a> {
+    dump: &'a CrashDump,
+    page: &'static mut [u8],
+    /// Bytes of `page` holding text not yet written to flash.
+    fill: usize,
+    /// Flash address `page` is written to next.
+    address: usize,
+    summary: Summary,
+    failed: bool,
+}
+
+impl DumpWriter<'a> {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.summary.length >= self.dump.capacity() {
+                self.summary.truncated = true;
+                return;
+            }
+            self.page[self.fill] = byte;
+            self.fill += 1;
+            self.summary.length += 1;
+            self.summary.hash = fnv1a(self.summary.hash, &[byte]);
+            if self.fill == self.page.len() {
+                self.write_page();
+            }
+        }
+    }
+
+    fn write_page(&mut self) {
+        for byte in self.page[self.fill..].iter_mut() {
+            *byte = 0xff;
+        }
+        if self.dump.flash.write_page_blocking(self.address, self.page) != ReturnCode::SUCCESS {
+            self.failed = true;
+        }
+        self.address += self.page.len();
+        self.fill = 0;
+    }
+
+    /// Write the rest of the text and then the header, which makes the dump
+    /// valid.
+    fn finish(mut self) {
+        if self.fill > 0 {
+            self.write_page();
+        }
+        if !self.failed {
+            self.summary.write_header(self.page);
+            self.dump
+                .flash
+                .write_page_blocking(self.dump.start, self.page);
+        }
+        self.dump.page.replace(self.page);
+    }
+}
+
+impl Write for DumpWriter<'a> {
+    fn write_str(&mut self, s: &str) -> Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl IoWrite for DumpWriter<'a> {
+    fn write(&mut self, buf: &[u8]) {
+        self.push(buf);
+    }
+}
+
+static mut CRASH_DUMP: Option<&'static CrashDump> = None;
+
+/// Save crash dumps in `dump` from now on.
+///
+/// # Safety
+///
+/// Must be called during board setup, before the kernel loop runs.
+pub unsafe fn set_crash_dump(dump: &'static CrashDump) {
+    CRASH_DUMP = Some(dump);
+}
+
+/// Save a dump of a kernel panic, with the same information
+/// `debug::panic()` prints.
+///
+/// `debug::panic()` calls this, boards with a panic handler of their own
+/// can call it after printing.
+///
+/// # Safety
+///
+/// Must only be called while the kernel panics, as it takes the flash from
+/// whatever was using it.
+pub unsafe fn record_panic<C: Chip>(
+    panic_info: &PanicInfo,
+    processes: &'static [Option<&'static dyn ProcessType>],
+    chip: &'static Option<&'static C>,
+) {
+    if let Some(dump) = CRASH_DUMP {
+        if let Some(mut writer) = dump.writer(CrashKind::KernelPanic) {
+            debug::panic_banner(&mut writer, panic_info);
+            debug::panic_cpu_state(chip, &mut writer);
+            debug::panic_process_info(processes, &mut writer);
+            writer.finish();
+        }
+    }
+}
+
+/// Save a dump of a process fault, unless a dump is saved already. The dump
+/// is written to flash later, from the deferred call of the `CrashDump`.
+crate unsafe fn record_fault(process: &dyn ProcessType) {
+    if let Some(dump) = CRASH_DUMP {
+        if dump.header().is_some() {
+            return;
+        }
+        dump.record_fault(|writer| {
+            let _ = writer.write_fmt(format_args!(
+                "\r\n\nProcess {} faulted\r\n\tKernel version {}\r\n",
+                process.get_process_name(),
+                option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
+            ));
+            process.print_full_process(writer);
+        });
+    }
+}
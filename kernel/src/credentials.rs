@@ -0,0 +1,177 @@
+//! Checking the credentials of apps before they are loaded.
+//!
+//! The TBF header checksum only protects against corrupted headers. To make
+//! sure that an app binary is intact, or that it was built by a trusted party,
+//! an app can carry credentials in its TBF header: a hash, a keyed hash or a
+//! signature of the app. The board passes a `CredentialsCheck` to
+//! `load_processes()` (and to a `DynamicProcessLoader`), which asks the board's
+//! `CredentialsChecker` about every enabled app before it is loaded.
+//!
+//! The kernel does not implement any cryptography itself. Checkers can use
+//! software implementations, such as
+//! `capsules::app_checker::AppCheckerSha256` and
+//! `capsules::app_checker::AppCheckerEd25519`, or hardware accelerators.
+//!
+//! The credentials cover the whole app: the fixed fields at the start of the
+//! TBF header with the checksum set to zero, then the elements of the header
+//! other than the Credentials element itself, in the order they appear in the
+//! header, followed by the app binary: everything in the app's flash region
+//! after the TBF header, up to its total size. This way the sizes, flags,
+//! permissions and other settings of an app cannot be changed without its
+//! credentials, and bytes of the binary cannot be turned into header elements
+//! by moving the end of the header. Disabled apps are not checked, so tools
+//! can still disable an app and enable it again without signing it again.
+
+use crate::tbfheader::TbfHeader;
+use core::cmp;
+
+/// Size of the fixed fields at the start of a TBF header.
+const TBF_HEADER_BASE_SIZE: usize = 16;
+
+/// Offset of the checksum in the fixed fields of a TBF header. The checksum
+/// covers the credentials, so the credentials cover zeros in its place.
+const TBF_HEADER_CHECKSUM_OFFSET: usize = 12;
+const ZERO_CHECKSUM: [u8; 4] = [0; 4];
+
+/// Kind of credentials in the Credentials TLV of a TBF header.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CredentialsFormat {
+    /// SHA-256 hash of the app binary, 32 bytes.
+    Sha256,
+    /// HMAC-SHA256 of the app binary with a key shared between the signer
+    /// and the board, 32 bytes.
+    HmacSha256,
+    /// Ed25519 signature of the app binary, 64 bytes. Checkers verify it
+    /// with a public key, so the board holds no secret.
+    Ed25519,
+    /// A format the kernel does not know. A checker may still understand it.
+    Unknown(u32),
+}
+
+impl CredentialsFormat {
+    /// Decode a credentials format from the encoding used in TBF headers.
+    fn from_tbf(format: u32) -> CredentialsFormat {
+        match format {
+            1 => CredentialsFormat::Sha256,
+            2 => CredentialsFormat::HmacSha256,
+            3 => CredentialsFormat::Ed25519,
+            _ => CredentialsFormat::Unknown(format),
+        }
+    }
+}
+
+/// The credentials an app carries in its TBF header.
+#[derive(Copy, Clone, Debug)]
+pub struct Credentials {
+    pub format: CredentialsFormat,
+    pub data: &'static [u8],
+}
+
+/// Decides whether an app may be loaded, based on its credentials.
+pub trait CredentialsChecker {
+    /// Check the app with the package name `name`. `credentials` are the
+    /// credentials in its TBF header, or `None` if it has none, and the
+    /// concatenation of the slices in `message` is the part of the app the
+    /// credentials cover. Returns whether the app may be loaded.
+    fn check_credentials(
+        &self,
+        name: &str,
+        credentials: Option<Credentials>,
+        message: &[&'static [u8]],
+    ) -> bool;
+}
+
+/// What the kernel does when loading the apps at boot finds an app that its
+/// `CredentialsChecker` rejects.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RejectedAppResponse {
+    /// Do not load the app, and continue with the next one.
+    Skip,
+
+    /// Generate a `panic!()` call, so the board does not boot with an app it
+    /// does not trust in flash.
+    Panic,
+}
+
+/// The credentials checking a board asks the process loaders to do.
+#[derive(Copy, Clone)]
+pub struct CredentialsCheck {
+    checker: &'static dyn CredentialsChecker,
+    response: RejectedAppResponse,
+}
+
+impl CredentialsCheck {
+    /// Check apps with `checker`, and handle rejected apps at boot with
+    /// `response`. Apps that are loaded after boot are always skipped if they
+    /// are rejected.
+    pub fn new(
+        checker: &'static dyn CredentialsChecker,
+        response: RejectedAppResponse,
+    ) -> CredentialsCheck {
+        CredentialsCheck { checker, response }
+    }
+
+    crate fn response(&self) -> RejectedAppResponse {
+        self.response
+    }
+
+    /// Check whether the credentials of an accepted app with the header
+    /// `header` also prove that the header was written by someone the board
+    /// trusts. Only keyed hashes and signatures do: anyone can compute a plain
+    /// hash of a modified app, and the kernel cannot tell what an unknown
+    /// format proves.
+    crate fn authenticates_header(&self, header: &TbfHeader) -> bool {
+        match header.get_credentials() {
+            Some((format, _)) => match CredentialsFormat::from_tbf(format) {
+                CredentialsFormat::HmacSha256 | CredentialsFormat::Ed25519 => true,
+                CredentialsFormat::Sha256 | CredentialsFormat::Unknown(_) => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Check the app at `app_flash` with the header `header`. Padding and
+    /// disabled apps are not checked, since they are never loaded.
+    crate unsafe fn app_accepted(&self, app_flash: *const u8, header: &TbfHeader) -> bool {
+        if !header.is_app() || !header.enabled() {
+            return true;
+        }
+        let header_size = header.get_header_size() as usize;
+        let total_size = header.get_total_size() as usize;
+        if total_size < header_size || header_size < TBF_HEADER_BASE_SIZE {
+            return false;
+        }
+        let app = core::slice::from_raw_parts(app_flash, total_size);
+        let (elements, binary) =
+            app[TBF_HEADER_BASE_SIZE..].split_at(header_size - TBF_HEADER_BASE_SIZE);
+
+        // Leave the Credentials element, with its TLV header and padding, out
+        // of the elements the credentials cover.
+        let (credentials, before, after) = match header.get_credentials() {
+            Some((format, data)) => {
+                // `data` follows the TLV header and the format, and the
+                // element is padded to a multiple of four bytes.
+                let start = data.as_ptr() as usize - elements.as_ptr() as usize - 8;
+                let length = 8 + data.len();
+                let end = cmp::min(start + ((length + 3) & !3), elements.len());
+                let credentials = Credentials {
+                    format: CredentialsFormat::from_tbf(format),
+                    data,
+                };
+                (Some(credentials), &elements[..start], &elements[end..])
+            }
+            None => (None, elements, &elements[elements.len()..]),
+        };
+        self.checker.check_credentials(
+            header.get_package_name(),
+            credentials,
+            &[
+                &app[..TBF_HEADER_CHECKSUM_OFFSET],
+                &ZERO_CHECKSUM,
+                before,
+                after,
+                binary,
+            ],
+        )
+    }
+}
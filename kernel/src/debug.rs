@@ -47,8 +47,10 @@ use crate::common::cells::NumericCellExt;
 use crate::common::cells::{MapCell, TakeCell};
 use crate::common::queue::Queue;
 use crate::common::ring_buffer::RingBuffer;
+use crate::crash_dump;
 use crate::hil;
 use crate::process::ProcessType;
+use crate::syscall_trace::{self, SyscallTrace, TraceWriter};
 use crate::Chip;
 use crate::ReturnCode;
 
@@ -84,6 +86,7 @@ pub unsafe fn panic<L: hil::led::Led, W: Write + IoWrite, C: Chip>(
     flush(writer);
     panic_cpu_state(chip, writer);
     panic_process_info(processes, writer);
+    crash_dump::record_panic(panic_info, processes, chip);
     panic_blink_forever(leds)
 }
 
@@ -383,6 +386,51 @@ impl Write for DebugWriterWrapper {
     }
 }
 
+impl DebugWriterWrapper {
+    /// Copy as many whole syscall trace frames into the internal buffer as
+    /// fit, leaving room for the buffer full warning of `debug!()`. Each frame
+    /// is written as a line of text, `TRACE:` followed by the frame in hex.
+    fn write_trace(&self, trace: &SyscallTrace) {
+        const PREFIX: &[u8] = b"TRACE:";
+        const HEX: &[u8] = b"0123456789abcdef";
+        const LINE_LEN: usize = PREFIX.len() + 2 * syscall_trace::FRAME_LEN + 2;
+
+        self.dw.map(|dw| {
+            dw.internal_buffer.map(|ring_buffer| {
+                let mut frame = [0; syscall_trace::FRAME_LEN];
+                while ring_buffer.available_len() >= 2 * LINE_LEN
+                    && trace.drain(&mut frame) == syscall_trace::FRAME_LEN
+                {
+                    for &b in PREFIX {
+                        ring_buffer.enqueue(b);
+                    }
+                    for &b in frame.iter() {
+                        ring_buffer.enqueue(HEX[(b >> 4) as usize]);
+                        ring_buffer.enqueue(HEX[(b & 0xf) as usize]);
+                    }
+                    ring_buffer.enqueue(b'\r');
+                    ring_buffer.enqueue(b'\n');
+                }
+            });
+        });
+    }
+}
+
+/// Writes syscall trace records to the debug output as lines of text between
+/// the `debug!()` messages. `tools/syscall_trace` decodes these lines and
+/// passes the other text through.
+pub struct DebugTraceWriter;
+
+impl TraceWriter for DebugTraceWriter {
+    fn write_trace(&self, trace: &SyscallTrace) {
+        unsafe {
+            let writer = get_debug_writer();
+            writer.write_trace(trace);
+            writer.publish_str();
+        }
+    }
+}
+
 pub fn begin_debug_fmt(args: Arguments) {
     unsafe {
         let writer = get_debug_writer();
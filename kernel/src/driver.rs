@@ -4,8 +4,8 @@
 //!
 //! # System-call Overview
 //!
-//! Tock supports four system calls. The `yield` system call is handled entirely
-//! by the scheduler, while three others are passed along to drivers:
+//! Tock supports five system calls. The `yield` system call is handled entirely
+//! by the scheduler, while four others are passed along to drivers:
 //!
 //!   * `subscribe` lets an application pass a callback to the driver to be
 //!   called later, when an event has occurred or data of interest is available.
@@ -14,6 +14,9 @@
 //!
 //!   * `allow` provides the driver access to an application buffer.
 //!
+//!   * `allow_readonly` provides the driver read-only access to an application
+//!   buffer, which may be in flash.
+//!
 //! ## Mapping system-calls to drivers
 //!
 //! Each of these three system calls takes at least two parameters. The first is
@@ -39,7 +42,7 @@
 //! understand its function and how it interacts with `subscribe`.
 
 use crate::callback::{AppId, Callback};
-use crate::mem::{AppSlice, Shared};
+use crate::mem::{AppSlice, ReadOnlyAppSlice, Shared};
 use crate::returncode::ReturnCode;
 
 /// `Driver`s implement the three driver-specific system calls: `subscribe`,
@@ -111,4 +114,21 @@ pub trait Driver {
     ) -> ReturnCode {
         ReturnCode::ENOSUPPORT
     }
+
+    /// `allow_readonly` lets an application give the driver read-only access
+    /// to a buffer in the application's memory or flash, such as a constant
+    /// string. This returns `ENOSUPPORT` if not used.
+    ///
+    /// Drivers that only read a buffer usually accept it from both `allow`
+    /// and `allow_readonly` with the same `minor_num`, by turning the
+    /// `AppSlice` from `allow` into a `ReadOnlyAppSlice`.
+    #[allow(unused_variables)]
+    fn allow_readonly(
+        &self,
+        app: AppId,
+        minor_num: usize,
+        slice: Option<ReadOnlyAppSlice<u8>>,
+    ) -> ReturnCode {
+        ReturnCode::ENOSUPPORT
+    }
 }
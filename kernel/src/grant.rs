@@ -6,6 +6,7 @@ use core::ops::{Deref, DerefMut};
 use core::ptr::{write, write_volatile, Unique};
 
 use crate::callback::AppId;
+use crate::common::{ListLink, ListNode};
 use crate::process::Error;
 use crate::sched::Kernel;
 
@@ -18,6 +19,7 @@ pub struct Grant<T: Default> {
 
 pub struct AppliedGrant<T> {
     appid: AppId,
+    grant_num: usize,
     grant: *mut T,
     _phantom: PhantomData<T>,
 }
@@ -28,14 +30,20 @@ impl<T> AppliedGrant<T> {
         F: FnOnce(&mut Owned<T>, &mut Allocator) -> R,
         R: Copy,
     {
-        let mut allocator = Allocator { appid: self.appid };
+        let mut allocator = Allocator {
+            appid: self.appid,
+            grant_num: self.grant_num,
+        };
         let mut root = unsafe { Owned::new(self.grant, self.appid) };
         fun(&mut root, &mut allocator)
     }
 }
 
+/// Allocates memory in the grant region of a process. Allocations count
+/// towards the process's `MemoryQuota` for the grant that was entered.
 pub struct Allocator {
     appid: AppId,
+    grant_num: usize,
 }
 
 pub struct Owned<T: ?Sized> {
@@ -60,11 +68,9 @@ impl<T: ?Sized> Drop for Owned<T> {
     fn drop(&mut self) {
         unsafe {
             let data = self.data.as_ptr() as *mut u8;
-            self.appid
-                .kernel
-                .process_map_or((), self.appid.idx(), |process| {
-                    process.free(data);
-                });
+            self.appid.kernel.app_map_or((), self.appid, |process| {
+                process.free(data);
+            });
         }
     }
 }
@@ -87,17 +93,16 @@ impl Allocator {
         unsafe {
             self.appid
                 .kernel
-                .process_map_or(Err(Error::NoSuchApp), self.appid.idx(), |process| {
-                    process.alloc(size_of::<T>(), align_of::<T>()).map_or(
-                        Err(Error::OutOfMemory),
-                        |arr| {
+                .app_map_or(Err(Error::NoSuchApp), self.appid, |process| {
+                    process
+                        .alloc(size_of::<T>(), align_of::<T>(), self.grant_num)
+                        .map_or(Err(Error::OutOfGrantMemory), |arr| {
                             let ptr = arr.as_mut_ptr() as *mut T;
                             // We use `ptr::write` to avoid `Drop`ping the uninitialized memory in
                             // case `T` implements the `Drop` trait.
                             write(ptr, data);
                             Ok(Owned::new(ptr, self.appid))
-                        },
-                    )
+                        })
                 })
         }
     }
@@ -145,13 +150,14 @@ impl<T: Default> Grant<T> {
 
     pub fn grant(&self, appid: AppId) -> Option<AppliedGrant<T>> {
         unsafe {
-            appid.kernel.process_map_or(None, appid.idx(), |process| {
+            appid.kernel.app_map_or(None, appid, |process| {
                 let cntr = *(process.grant_ptr(self.grant_num) as *mut *mut T);
                 if cntr.is_null() {
                     None
                 } else {
                     Some(AppliedGrant {
                         appid: appid,
+                        grant_num: self.grant_num,
                         grant: cntr,
                         _phantom: PhantomData,
                     })
@@ -168,7 +174,7 @@ impl<T: Default> Grant<T> {
         unsafe {
             appid
                 .kernel
-                .process_map_or(Err(Error::NoSuchApp), appid.idx(), |process| {
+                .app_map_or(Err(Error::NoSuchApp), appid, |process| {
                     // Here is an example of how the grants are laid out in a
                     // process's memory:
                     //
@@ -204,7 +210,7 @@ impl<T: Default> Grant<T> {
                     // memory needs to be allocated.
                     let new_grant = if (*ctr_ptr).is_null() {
                         process
-                            .alloc(size_of::<T>(), align_of::<T>())
+                            .alloc(size_of::<T>(), align_of::<T>(), self.grant_num)
                             .map(|root_arr| {
                                 let root_ptr = root_arr.as_mut_ptr() as *mut T;
                                 // Initialize the grant contents using ptr::write, to
@@ -222,10 +228,13 @@ impl<T: Default> Grant<T> {
                     // If the grant region already exists or there was enough
                     // memory to allocate it, call the passed in closure with
                     // the borrowed grant region.
-                    new_grant.map_or(Err(Error::OutOfMemory), move |root_ptr| {
+                    new_grant.map_or(Err(Error::OutOfGrantMemory), move |root_ptr| {
                         let root_ptr = root_ptr as *mut T;
                         let mut root = Borrowed::new(&mut *root_ptr, appid);
-                        let mut allocator = Allocator { appid: appid };
+                        let mut allocator = Allocator {
+                            appid: appid,
+                            grant_num: self.grant_num,
+                        };
                         let res = fun(&mut root, &mut allocator);
                         Ok(res)
                     })
@@ -268,7 +277,10 @@ impl<T: Default> Iterator for Iter<'a, T> {
         while self.index < self.len {
             let idx = self.index;
             self.index += 1;
-            let res = self.grant.grant(AppId::new(self.grant.kernel, idx));
+            let res = self
+                .grant
+                .kernel
+                .process_map_or(None, idx, |process| self.grant.grant(process.appid()));
             if res.is_some() {
                 return res;
             }
@@ -276,3 +288,40 @@ impl<T: Default> Iterator for Iter<'a, T> {
         None
     }
 }
+
+/// Implemented by capsules that keep per-process state outside of their
+/// grant, such as the `AppId` of an in-flight operation, so that they can
+/// release it when a process goes away.
+pub trait GrantCleanupClient {
+    /// Called when `appid` is terminated, restarted, or stopped after a fault.
+    /// The process's grant regions have already been cleared, so the capsule
+    /// should not enter its grant for `appid` here; it should only cancel or
+    /// forget any operations it started on the process's behalf.
+    fn process_teardown(&self, appid: AppId);
+}
+
+/// Registration of a `GrantCleanupClient` with the kernel, created by the
+/// board and passed to `Kernel::register_grant_cleanup_hook()`.
+pub struct GrantCleanupHook {
+    client: &'static dyn GrantCleanupClient,
+    next: ListLink<'static, GrantCleanupHook>,
+}
+
+impl GrantCleanupHook {
+    pub fn new(client: &'static dyn GrantCleanupClient) -> GrantCleanupHook {
+        GrantCleanupHook {
+            client: client,
+            next: ListLink::empty(),
+        }
+    }
+
+    crate fn process_teardown(&self, appid: AppId) {
+        self.client.process_teardown(appid);
+    }
+}
+
+impl ListNode<'static, GrantCleanupHook> for GrantCleanupHook {
+    fn next(&'static self) -> &'static ListLink<'static, GrantCleanupHook> {
+        &self.next
+    }
+}
@@ -30,6 +30,12 @@ pub trait PowerClient {
     fn changed(&self, on: bool);
 }
 
+pub trait EnergyDetectClient {
+    /// Called when a measurement started by `energy_detect` completes.
+    /// `power` is the received signal power on the channel, in dBm.
+    fn energy_detect_done(&self, power: i8, result: ReturnCode);
+}
+
 /// These constants are used for interacting with the SPI buffer, which contains
 /// a 1-byte SPI command, a 1-byte PHY header, and then the 802.15.4 frame. In
 /// theory, the number of extra bytes in front of the frame can depend on the
@@ -98,6 +104,13 @@ pub trait RadioConfig {
     fn set_pan(&self, id: u16);
     fn set_tx_power(&self, power: i8) -> ReturnCode;
     fn set_channel(&self, chan: u8) -> ReturnCode;
+
+    /// Measure the received signal power on the current channel, as
+    /// needed for energy detection scans and clear channel assessment.
+    /// Issues a callback to the energy detect client when done. Returns
+    /// EOFF if the radio is off and EBUSY if a measurement is under way.
+    fn energy_detect(&self) -> ReturnCode;
+    fn set_energy_detect_client(&self, client: &'static dyn EnergyDetectClient);
 }
 
 pub trait RadioData {
@@ -11,12 +11,37 @@
 //! correct capabilities to can use it.
 
 use core::cell::Cell;
+use core::fmt::{Result, Write};
 
 use crate::callback::AppId;
 use crate::capabilities::ProcessManagementCapability;
 use crate::common::cells::NumericCellExt;
+use crate::common::deferred_call;
 use crate::process;
 use crate::sched::Kernel;
+use crate::syscall::{SyscallClass, SyscallLatency};
+
+/// Keeps the part of the formatted text that starts `skip` bytes in and fits
+/// in `buf`.
+struct WindowWriter<'a> {
+    buf: &'a mut [u8],
+    skip: usize,
+    len: usize,
+}
+
+impl Write for WindowWriter<'a> {
+    fn write_str(&mut self, s: &str) -> Result {
+        for &byte in s.as_bytes() {
+            if self.skip > 0 {
+                self.skip -= 1;
+            } else if self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
 
 /// This struct provides the inspection functions.
 pub struct KernelInfo {
@@ -78,7 +103,7 @@ impl KernelInfo {
         _capability: &dyn ProcessManagementCapability,
     ) -> &'static str {
         self.kernel
-            .process_map_or("unknown", app.idx(), |process| process.get_process_name())
+            .app_map_or("unknown", app, |process| process.get_process_name())
     }
 
     /// Returns the number of syscalls the app has called.
@@ -88,7 +113,7 @@ impl KernelInfo {
         _capability: &dyn ProcessManagementCapability,
     ) -> usize {
         self.kernel
-            .process_map_or(0, app.idx(), |process| process.debug_syscall_count())
+            .app_map_or(0, app, |process| process.debug_syscall_count())
     }
 
     /// Returns the number of dropped callbacks the app has experience.
@@ -99,9 +124,8 @@ impl KernelInfo {
         app: AppId,
         _capability: &dyn ProcessManagementCapability,
     ) -> usize {
-        self.kernel.process_map_or(0, app.idx(), |process| {
-            process.debug_dropped_callback_count()
-        })
+        self.kernel
+            .app_map_or(0, app, |process| process.debug_dropped_callback_count())
     }
 
     /// Returns the number of time this app has been restarted.
@@ -111,7 +135,7 @@ impl KernelInfo {
         _capability: &dyn ProcessManagementCapability,
     ) -> usize {
         self.kernel
-            .process_map_or(0, app.idx(), |process| process.debug_restart_count())
+            .app_map_or(0, app, |process| process.debug_restart_count())
     }
 
     /// Returns the number of time this app has exceeded its timeslice.
@@ -120,11 +144,149 @@ impl KernelInfo {
         app: AppId,
         _capability: &dyn ProcessManagementCapability,
     ) -> usize {
-        self.kernel.process_map_or(0, app.idx(), |process| {
-            process.debug_timeslice_expiration_count()
+        self.kernel
+            .app_map_or(0, app, |process| process.debug_timeslice_expiration_count())
+    }
+
+    /// Returns how many bytes of grant memory the kernel has allocated for
+    /// the app.
+    pub fn app_grant_memory_used(
+        &self,
+        app: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .app_map_or(0, app, |process| process.grant_memory_used())
+    }
+
+    /// Returns how many bytes of grant memory the kernel has allocated for
+    /// the grant `grant_num` of the app.
+    pub fn app_grant_memory_used_by(
+        &self,
+        app: AppId,
+        grant_num: usize,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .app_map_or(0, app, |process| process.grant_memory_used_by(grant_num))
+    }
+
+    /// Returns the limits on the grant memory of the app, or `None` if the
+    /// app no longer exists.
+    pub fn app_grant_memory_quota(
+        &self,
+        app: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<process::MemoryQuota> {
+        self.kernel
+            .app_map_or(None, app, |process| Some(process.get_memory_quota()))
+    }
+
+    /// Copies the memory map of the app, as `ProcessType::print_memory_map()`
+    /// prints it, into `buf`, starting `offset` bytes into the text. Returns
+    /// the number of bytes copied, which is 0 once `offset` is past the end
+    /// or if the app no longer exists.
+    ///
+    /// This lets callers print the memory map in pieces, since it is longer
+    /// than most output buffers.
+    pub fn app_memory_map(
+        &self,
+        app: AppId,
+        offset: usize,
+        buf: &mut [u8],
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel.app_map_or(0, app, |process| {
+            let mut window = WindowWriter {
+                buf: buf,
+                skip: offset,
+                len: 0,
+            };
+            unsafe { process.print_memory_map(&mut window) };
+            window.len
         })
     }
 
+    /// Returns the number of grant allocations for the app that were refused
+    /// because they would have exceeded its memory quota.
+    pub fn number_app_grant_quota_exceeded(
+        &self,
+        app: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .app_map_or(0, app, |process| process.debug_grant_quota_exceeded_count())
+    }
+
+    /// Returns the number of system calls of the app that were refused
+    /// because its permissions do not include the driver or command.
+    pub fn number_app_denied_syscalls(
+        &self,
+        app: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .app_map_or(0, app, |process| process.debug_denied_syscall_count())
+    }
+
+    /// Returns how long the app has executed since it was loaded, in
+    /// microseconds.
+    pub fn app_cpu_time_us(
+        &self,
+        app: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .app_map_or(0, app, |process| process.debug_cpu_time_us())
+    }
+
+    /// Returns how long the kernel has taken to handle system calls of
+    /// `class` for the app.
+    pub fn app_syscall_latency(
+        &self,
+        app: AppId,
+        class: SyscallClass,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> SyscallLatency {
+        self.kernel
+            .app_map_or(SyscallLatency::default(), app, |process| {
+                process.debug_syscall_latency(class)
+            })
+    }
+
+    /// Returns how long all processes have executed in total, in
+    /// microseconds.
+    pub fn cpu_time_us(&self, _capability: &dyn ProcessManagementCapability) -> u64 {
+        let time: Cell<u64> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            time.set(time.get() + proc.debug_cpu_time_us());
+        });
+        time.get()
+    }
+
+    /// Returns how many grants the kernel has created.
+    pub fn number_grants(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        self.kernel.get_grant_count()
+    }
+
+    /// Returns how often the kernel loop has serviced pending interrupts.
+    pub fn interrupt_service_count(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        self.kernel.get_interrupt_service_count()
+    }
+
+    /// Returns how often the kernel loop has put the chip to sleep.
+    pub fn sleep_count(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        self.kernel.get_sleep_count()
+    }
+
+    /// Returns how many deferred calls are registered and pending.
+    pub fn deferred_calls(
+        &self,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> deferred_call::DeferredCallStats {
+        deferred_call::stats()
+    }
+
     /// Returns the total number of times all processes have exceeded
     /// their timeslices.
     pub fn timeslice_expirations(&self, _capability: &dyn ProcessManagementCapability) -> usize {
@@ -2,12 +2,53 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! Access control
+//! --------------
+//!
+//! Without access lists, any process can discover any other process as a
+//! service, notify it and share buffers with it. A service can restrict which
+//! apps may use it with an IPC access list in its TBF header, and the board
+//! can declare access lists with `IPC::set_access_lists()`. A client must be
+//! named in every list that applies to a service. To any other client the
+//! service does not exist: discovering it fails and notifying it returns
+//! `EINVAL`, so the client cannot even tell that the service is running.
+//!
+//! A shared buffer only becomes accessible to the other process when one of
+//! the two processes notifies the other, which requires the access check to
+//! pass. The sharing process chooses whether the other process may write to
+//! the buffer, and either process can revoke the shares between them. When a
+//! process is restarted, stopped or unloaded, the shares between it and all
+//! other processes are revoked, so a new instance of either process never
+//! sees a buffer that was shared with the old one. For this, the board
+//! registers the IPC driver with `Kernel::register_grant_cleanup_hook()`.
+//!
+//! Calls
+//! -----
+//!
+//! Instead of sharing memory, a client can call a service with a message of up
+//! to `MAX_MESSAGE_LEN` bytes. Both processes allow a message buffer with
+//! `MESSAGE_ALLOW`. A call copies the message from the client's buffer into
+//! the service's buffer and invokes the service's `MESSAGE_SUBSCRIBE` callback
+//! with the client's ID and the message length. The service answers with a
+//! reply value, and the kernel copies the service's message buffer back into
+//! the client's buffer and invokes the client's `REPLY_SUBSCRIBE` callback
+//! with the service's ID, the reply value and the reply length. A client has
+//! at most one call outstanding and waits for the reply by yielding, like for
+//! any other callback. A service handles one call at a time; further calls are
+//! queued by the kernel and delivered after the service replies. When a
+//! service is restarted, stopped or unloaded, the calls made to it, handled
+//! or queued, fail with `FAIL` as their reply value.
+
+use core::cell::Cell;
+use core::cmp;
 
 use crate::callback::{AppId, Callback};
 use crate::capabilities::MemoryAllocationCapability;
 use crate::driver::Driver;
-use crate::grant::Grant;
+use crate::grant::{Grant, GrantCleanupClient};
 use crate::mem::{AppSlice, Shared};
+use crate::platform::mpu;
 use crate::process;
 use crate::returncode::ReturnCode;
 use crate::sched::Kernel;
@@ -15,30 +56,270 @@ use crate::sched::Kernel;
 /// Syscall number
 pub const DRIVER_NUM: usize = 0x10000;
 
+/// Allow number of the buffer calls and replies are copied from and to.
+pub const MESSAGE_ALLOW: usize = 0x100;
+/// Subscribe number of the callback a service receives calls with.
+pub const MESSAGE_SUBSCRIBE: usize = 0x100;
+/// Subscribe number of the callback a client receives replies with.
+pub const REPLY_SUBSCRIBE: usize = 0x101;
+/// Largest message, in bytes, that is copied for a call or a reply.
+pub const MAX_MESSAGE_LEN: usize = 64;
+
+/// An IPC access list declared by the board: only the apps named in `clients`
+/// may use the app named `service` as an IPC service.
+pub struct IpcAccessList {
+    pub service: &'static str,
+    pub clients: &'static [&'static str],
+}
+
+/// A buffer a process shares with one other process.
+#[derive(Default)]
+struct Share {
+    slice: Option<AppSlice<Shared, u8>>,
+    /// Whether the other process may only read the buffer.
+    read_only: bool,
+    /// The MPU region that gives the other process access to the buffer, once
+    /// it has been exposed.
+    exposed: Option<mpu::Region>,
+}
+
+/// A call a client made that has not been answered yet.
+#[derive(Copy, Clone)]
+struct Call {
+    /// Index of the service process.
+    service: usize,
+    /// Length of the message in the client's message buffer.
+    len: usize,
+    /// Whether the message was copied to the service.
+    delivered: bool,
+}
+
 struct IPCData {
-    shared_memory: [Option<AppSlice<Shared, u8>>; 8],
+    shared_memory: [Share; 8],
     client_callbacks: [Option<Callback>; 8],
     callback: Option<Callback>,
+    message_buffer: Option<AppSlice<Shared, u8>>,
+    message_callback: Option<Callback>,
+    reply_callback: Option<Callback>,
+    /// The call this process is waiting for a reply to, as a client.
+    call: Option<Call>,
+    /// Index of the client whose call this process is handling, as a service.
+    serving: Option<usize>,
+    /// MPU regions that give this process access to the buffers other
+    /// processes share with it, by the index of the sharing process. The
+    /// sharing process keeps the region in its `Share` as well, but its grant
+    /// is gone by the time it is torn down.
+    exposed_from: [Option<mpu::Region>; 8],
 }
 
 impl Default for IPCData {
     fn default() -> IPCData {
         IPCData {
-            shared_memory: [None, None, None, None, None, None, None, None],
+            shared_memory: Default::default(),
             client_callbacks: [None, None, None, None, None, None, None, None],
             callback: None,
+            message_buffer: None,
+            message_callback: None,
+            reply_callback: None,
+            call: None,
+            serving: None,
+            exposed_from: [None; 8],
         }
     }
 }
 
 pub struct IPC {
     data: Grant<IPCData>,
+    access_lists: Cell<&'static [IpcAccessList]>,
 }
 
 impl IPC {
     pub fn new(kernel: &'static Kernel, capability: &dyn MemoryAllocationCapability) -> IPC {
         IPC {
             data: kernel.create_grant(capability),
+            access_lists: Cell::new(&[]),
+        }
+    }
+
+    /// Restrict which apps may use the services named in `access_lists`, in
+    /// addition to the access lists in the TBF headers of the services.
+    pub fn set_access_lists(&self, access_lists: &'static [IpcAccessList]) {
+        self.access_lists.set(access_lists);
+    }
+
+    /// Check whether `client` may discover, notify and share buffers with
+    /// `service`.
+    fn client_allowed(&self, service: &dyn process::ProcessType, client: AppId) -> bool {
+        if service.appid().idx() == client.idx() {
+            return true;
+        }
+        let client_name = self
+            .data
+            .kernel
+            .process_map_or("", client.idx(), |client| client.get_process_name());
+        let service_name = service.get_process_name();
+
+        service.ipc_allows_client(client_name).unwrap_or(true)
+            && self
+                .access_lists
+                .get()
+                .iter()
+                .filter(|list| list.service == service_name)
+                .all(|list| list.clients.contains(&client_name))
+    }
+
+    /// Remove the access the process in slot `other` has to the buffer in
+    /// `share` of the process in slot `owner`. The buffer stays shared, and
+    /// is exposed again the next time one of the processes notifies the
+    /// other.
+    fn unexpose(&self, owner: usize, share: &mut Share, other: usize) {
+        share.exposed.take().map(|region| {
+            self.data.kernel.process_map_or((), other, |process| {
+                let _ = process.remove_mpu_region(region);
+                self.data.grant(process.appid()).map(|data| {
+                    data.enter(|data, _| {
+                        data.exposed_from
+                            .get_mut(owner)
+                            .map(|exposed| *exposed = None);
+                    })
+                });
+            });
+        });
+    }
+
+    /// Stop sharing the buffer of `owner` with the process in slot `other`.
+    fn revoke(&self, owner: AppId, other: usize) -> ReturnCode {
+        self.data
+            .enter(owner, |data, _| {
+                data.shared_memory
+                    .get_mut(other)
+                    .map_or(ReturnCode::EINVAL, |share| {
+                        self.unexpose(owner.idx(), share, other);
+                        share.slice = None;
+                        ReturnCode::SUCCESS
+                    })
+            })
+            .unwrap_or(ReturnCode::EBUSY)
+    }
+
+    /// Copy up to `len` bytes of the message buffer of `appid` into `message`.
+    /// Returns the number of bytes copied, or `None` if the process has no
+    /// message buffer.
+    fn read_message(&self, appid: AppId, len: usize, message: &mut [u8]) -> Option<usize> {
+        self.data
+            .enter(appid, |data, _| {
+                data.message_buffer.as_ref().map(|buffer| {
+                    let len = cmp::min(len, cmp::min(buffer.len(), message.len()));
+                    message[..len].copy_from_slice(&buffer.as_ref()[..len]);
+                    len
+                })
+            })
+            .unwrap_or(None)
+    }
+
+    /// Look up the `AppId` of the process in slot `index`.
+    fn appid_of(&self, index: usize) -> Option<AppId> {
+        self.data
+            .kernel
+            .process_map_or(None, index, |process| Some(process.appid()))
+    }
+
+    /// Fail the calls made to the service in slot `service`, which was torn
+    /// down and will not reply to them.
+    fn fail_calls_to(&self, service: usize) {
+        for index in 0..self.data.kernel.number_of_process_slots() {
+            if index == service {
+                continue;
+            }
+            if let Some(client) = self.appid_of(index) {
+                let pending = self
+                    .data
+                    .grant(client)
+                    .and_then(|client| client.enter(|data, _| data.call))
+                    .map_or(false, |call| call.service == service);
+                if pending {
+                    self.complete_call(client, service, usize::from(ReturnCode::FAIL), &[]);
+                }
+            }
+        }
+    }
+
+    /// Finish the call of `client` with `result`, copying `reply` into the
+    /// client's message buffer.
+    fn complete_call(&self, client: AppId, service: usize, result: usize, reply: &[u8]) {
+        let _ = self.data.enter(client, |data, _| {
+            if data.call.take().is_none() {
+                // The client was restarted while its call was handled.
+                return;
+            }
+            let len = data.message_buffer.as_mut().map_or(0, |buffer| {
+                let len = cmp::min(reply.len(), buffer.len());
+                buffer.as_mut()[..len].copy_from_slice(&reply[..len]);
+                len
+            });
+            data.reply_callback
+                .map(|mut callback| callback.schedule(service + 1, result, len));
+        });
+    }
+
+    /// If `service` is not handling a call, deliver the next queued call to
+    /// it.
+    fn deliver_next_call(&self, service: AppId) {
+        let idle = self
+            .data
+            .enter(service, |data, _| {
+                data.serving.is_none() && data.message_callback.is_some()
+            })
+            .unwrap_or(false);
+        if !idle {
+            return;
+        }
+
+        // Deliver the queued call of the client in the lowest process slot.
+        let next = (0..self.data.kernel.number_of_process_slots()).find_map(|index| {
+            let client = self.appid_of(index)?;
+            let call = self.data.grant(client)?.enter(|data, _| data.call)?;
+            if call.service == service.idx() && !call.delivered {
+                Some((client, call))
+            } else {
+                None
+            }
+        });
+        let (client, call) = match next {
+            Some(next) => next,
+            None => return,
+        };
+
+        let mut message = [0; MAX_MESSAGE_LEN];
+        let len = self
+            .read_message(client, call.len, &mut message)
+            .unwrap_or(0);
+        let delivered = self
+            .data
+            .enter(service, |data, _| {
+                let data: &mut IPCData = &mut *data;
+                match data.message_buffer.as_mut() {
+                    Some(buffer) if buffer.len() >= len => {
+                        buffer.as_mut()[..len].copy_from_slice(&message[..len]);
+                        data.serving = Some(client.idx());
+                        data.message_callback
+                            .map(|mut callback| callback.schedule(client.idx() + 1, len, 0));
+                        true
+                    }
+                    _ => false,
+                }
+            })
+            .unwrap_or(false);
+
+        if delivered {
+            let _ = self.data.enter(client, |data, _| {
+                data.call.as_mut().map(|call| call.delivered = true);
+            });
+        } else {
+            // The service has no room for the message, so fail this call and
+            // try the next one.
+            self.complete_call(client, service.idx(), usize::from(ReturnCode::ESIZE), &[]);
+            self.deliver_next_call(service);
         }
     }
 
@@ -62,9 +343,23 @@ impl IPC {
                             if appid.idx() >= otherdata.shared_memory.len() {
                                 return;
                             }
-                            match otherdata.shared_memory[appid.idx()] {
+                            let share = &mut otherdata.shared_memory[appid.idx()];
+                            match share.slice {
                                 Some(ref slice) => {
-                                    slice.expose_to(appid);
+                                    let permissions = if share.read_only {
+                                        mpu::Permissions::ReadOnly
+                                    } else {
+                                        mpu::Permissions::ReadWriteOnly
+                                    };
+                                    // If the buffer is already exposed no new
+                                    // region is created.
+                                    if let Some(region) = slice.expose_to(appid, permissions) {
+                                        share.exposed = Some(region);
+                                        mydata
+                                            .exposed_from
+                                            .get_mut(otherapp.idx())
+                                            .map(|exposed| *exposed = Some(region));
+                                    }
                                     callback.schedule(
                                         otherapp.idx() + 1,
                                         slice.len(),
@@ -109,6 +404,36 @@ impl Driver for IPC {
                 })
                 .unwrap_or(ReturnCode::EBUSY),
 
+            // subscribe(MESSAGE_SUBSCRIBE)
+            //
+            // A service registers the callback that receives calls. Queued
+            // calls are delivered once it is registered.
+            MESSAGE_SUBSCRIBE => {
+                let res = self
+                    .data
+                    .enter(app_id, |data, _| {
+                        data.message_callback = callback;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+                if res == ReturnCode::SUCCESS {
+                    self.deliver_next_call(app_id);
+                }
+                res
+            }
+
+            // subscribe(REPLY_SUBSCRIBE)
+            //
+            // A client registers the callback that receives replies to its
+            // calls.
+            REPLY_SUBSCRIBE => self
+                .data
+                .enter(app_id, |data, _| {
+                    data.reply_callback = callback;
+                    ReturnCode::SUCCESS
+                })
+                .unwrap_or(ReturnCode::EBUSY),
+
             // subscribe(>=1)
             //
             // Subscribe with subscribe_num >= 1 is how a client registers
@@ -131,35 +456,162 @@ impl Driver for IPC {
         }
     }
 
-    /// command is how notify() is implemented.
-    /// Notifying an IPC service is done by setting client_or_svc to 0,
-    /// and notifying an IPC client is done by setting client_or_svc to 1.
-    /// In either case, the target_id is the same number as provided in a notify
-    /// callback or as returned by allow.
+    /// command is how notify() is implemented, and how shares are managed.
+    /// The target_id is the same number as provided in a notify callback or as
+    /// returned by allow.
+    ///
+    /// - `0`: Notify the IPC service target_id.
+    /// - `1`: Notify the IPC client target_id.
+    /// - `2`: Set whether target_id may write to the buffer this process
+    ///   shares with it: `arg` is 0 for read-write (the default) and 1 for
+    ///   read-only. Takes effect the next time the buffer is exposed.
+    /// - `3`: Revoke the shares between this process and target_id. Neither
+    ///   process can access the other's buffer any more until it is shared
+    ///   again with allow.
+    /// - `4`: Call the IPC service target_id with the first `arg` bytes of
+    ///   this process's message buffer. Returns EBUSY if this process is
+    ///   already waiting for a reply, and ESIZE if the message is longer than
+    ///   `MAX_MESSAGE_LEN`.
+    /// - `5`: Reply to the call of the IPC client target_id with the value
+    ///   `arg` and the contents of this process's message buffer. Returns
+    ///   EINVAL if this process is not handling a call of target_id.
     ///
-    /// Returns EINVAL if the other process doesn't exist.
+    /// Returns EINVAL if the other process doesn't exist, or if this process is
+    /// not allowed to communicate with it.
     fn command(
         &self,
         target_id: usize,
-        client_or_svc: usize,
-        _: usize,
+        command_num: usize,
+        arg: usize,
         appid: AppId,
     ) -> ReturnCode {
-        let cb_type = if client_or_svc == 0 {
-            process::IPCType::Service
-        } else {
-            process::IPCType::Client
-        };
+        if target_id == 0 {
+            return ReturnCode::EINVAL;
+        }
+        let target_index = target_id - 1;
 
-        self.data
-            .kernel
-            .process_map_or(ReturnCode::EINVAL, target_id - 1, |target| {
-                let ret = target.enqueue_task(process::Task::IPC((appid, cb_type)));
-                match ret {
-                    true => ReturnCode::SUCCESS,
-                    false => ReturnCode::FAIL,
+        match command_num {
+            0 | 1 => self
+                .data
+                .kernel
+                .process_map_or(ReturnCode::EINVAL, target_index, |target| {
+                    let (cb_type, allowed) = if command_num == 0 {
+                        (
+                            process::IPCType::Service,
+                            self.client_allowed(target, appid),
+                        )
+                    } else {
+                        (
+                            process::IPCType::Client,
+                            self.data.kernel.process_map_or(false, appid.idx(), |me| {
+                                self.client_allowed(me, target.appid())
+                            }),
+                        )
+                    };
+                    if !allowed {
+                        return ReturnCode::EINVAL;
+                    }
+
+                    let ret = target.enqueue_task(process::Task::IPC((appid, cb_type)));
+                    match ret {
+                        true => ReturnCode::SUCCESS,
+                        false => ReturnCode::FAIL,
+                    }
+                }),
+            2 => self
+                .data
+                .enter(appid, |data, _| {
+                    data.shared_memory
+                        .get_mut(target_index)
+                        .map_or(ReturnCode::EINVAL, |share| {
+                            let read_only = arg != 0;
+                            if share.read_only != read_only {
+                                share.read_only = read_only;
+                                self.unexpose(appid.idx(), share, target_index);
+                            }
+                            ReturnCode::SUCCESS
+                        })
+                })
+                .unwrap_or(ReturnCode::EBUSY),
+            3 => {
+                let res = self.revoke(appid, target_index);
+                if res == ReturnCode::SUCCESS {
+                    let target = self
+                        .data
+                        .kernel
+                        .process_map_or(None, target_index, |target| Some(target.appid()));
+                    target.map(|target| self.revoke(target, appid.idx()));
                 }
-            })
+                res
+            }
+            4 => {
+                let allowed = target_index != appid.idx()
+                    && self
+                        .data
+                        .kernel
+                        .process_map_or(false, target_index, |service| {
+                            self.client_allowed(service, appid)
+                        });
+                if !allowed {
+                    return ReturnCode::EINVAL;
+                }
+                if arg > MAX_MESSAGE_LEN {
+                    return ReturnCode::ESIZE;
+                }
+                let res = self
+                    .data
+                    .enter(appid, |data, _| {
+                        if data.call.is_some() {
+                            ReturnCode::EBUSY
+                        } else if arg > data.message_buffer.as_ref().map_or(0, |b| b.len()) {
+                            ReturnCode::EINVAL
+                        } else {
+                            data.call = Some(Call {
+                                service: target_index,
+                                len: arg,
+                                delivered: false,
+                            });
+                            ReturnCode::SUCCESS
+                        }
+                    })
+                    .unwrap_or(ReturnCode::ENOMEM);
+                if res == ReturnCode::SUCCESS {
+                    self.appid_of(target_index)
+                        .map(|service| self.deliver_next_call(service));
+                }
+                res
+            }
+            5 => {
+                let serving = self
+                    .data
+                    .enter(appid, |data, _| data.serving)
+                    .unwrap_or(None);
+                if serving != Some(target_index) {
+                    return ReturnCode::EINVAL;
+                }
+                let mut reply = [0; MAX_MESSAGE_LEN];
+                let len = self
+                    .read_message(appid, MAX_MESSAGE_LEN, &mut reply)
+                    .unwrap_or(0);
+                let _ = self.data.enter(appid, |data, _| data.serving = None);
+
+                // The client may have been restarted, and may even have made
+                // a new call, since its call was delivered.
+                self.appid_of(target_index).map(|client| {
+                    let waiting = self
+                        .data
+                        .grant(client)
+                        .and_then(|client| client.enter(|data, _| data.call))
+                        .map_or(false, |call| call.service == appid.idx() && call.delivered);
+                    if waiting {
+                        self.complete_call(client, appid.idx(), arg, &reply[..len]);
+                    }
+                });
+                self.deliver_next_call(appid);
+                ReturnCode::SUCCESS
+            }
+            _ => ReturnCode::ENOSUPPORT,
+        }
     }
 
     /// allow enables processes to discover IPC services on the platform or
@@ -167,20 +619,34 @@ impl Driver for IPC {
     ///
     /// If allow is called with target_id == 0, it is an IPC service discover
     /// call. The contents of the slice should be the string name of the IPC
-    /// service. If this mechanism can find that service, allow will return
-    /// an ID that can be used to notify that service. Otherwise an error will
-    /// be returned.
+    /// service. If this mechanism can find that service, and this process is
+    /// allowed to use it, allow will return an ID that can be used to notify
+    /// that service. Otherwise an error will be returned.
     ///
     /// If allow is called with target_id >= 1, it is a share command where the
     /// application is explicitly sharing a slice with an IPC service (as
     /// specified by the target_id). allow() simply allows both processes to
-    /// access the buffer, it does not signal the service.
+    /// access the buffer, it does not signal the service. Any buffer that was
+    /// shared with the service before is no longer accessible to it.
+    ///
+    /// If allow is called with target_id == MESSAGE_ALLOW, the slice is the
+    /// buffer that calls and replies are copied from and to. It is never
+    /// accessible to other processes.
     fn allow(
         &self,
         appid: AppId,
         target_id: usize,
         slice: Option<AppSlice<Shared, u8>>,
     ) -> ReturnCode {
+        if target_id == MESSAGE_ALLOW {
+            return self
+                .data
+                .enter(appid, |data, _| {
+                    data.message_buffer = slice;
+                    ReturnCode::SUCCESS
+                })
+                .unwrap_or(ReturnCode::EBUSY);
+        }
         if target_id == 0 {
             match slice {
                 Some(slice_data) => {
@@ -189,6 +655,7 @@ impl Driver for IPC {
                         // are slices equal?
                         if s.len() == slice_data.len()
                             && s.iter().zip(slice_data.iter()).all(|(c1, c2)| c1 == c2)
+                            && self.client_allowed(p, appid)
                         {
                             ReturnCode::SuccessWithValue {
                                 value: (p.appid().idx() as usize) + 1,
@@ -210,8 +677,9 @@ impl Driver for IPC {
             .enter(appid, |data, _| {
                 data.shared_memory.get_mut(target_id - 1).map_or(
                     ReturnCode::EINVAL, /* Target process does not exist */
-                    |smem| {
-                        *smem = slice;
+                    |share| {
+                        self.unexpose(appid.idx(), share, target_id - 1);
+                        share.slice = slice;
                         ReturnCode::SUCCESS
                     },
                 )
@@ -219,3 +687,49 @@ impl Driver for IPC {
             .unwrap_or(ReturnCode::EBUSY)
     }
 }
+
+impl GrantCleanupClient for IPC {
+    /// Revoke the shares between the process that was torn down and all
+    /// other processes, and fail the calls made to it. The grant of the
+    /// torn-down process is already gone, so the state of the shares is taken
+    /// from the grants of the others.
+    fn process_teardown(&self, appid: AppId) {
+        self.fail_calls_to(appid.idx());
+
+        for index in 0..self.data.kernel.number_of_process_slots() {
+            if index == appid.idx() {
+                continue;
+            }
+            let other = match self
+                .appid_of(index)
+                .and_then(|other| self.data.grant(other))
+            {
+                Some(other) => other,
+                None => continue,
+            };
+            let (shared, exposed) = other.enter(|data, _| {
+                // The buffer the other process shared with the torn-down one.
+                let shared = data.shared_memory.get_mut(appid.idx()).and_then(|share| {
+                    share.slice = None;
+                    share.exposed.take()
+                });
+                // The buffer the torn-down process shared with the other one.
+                let exposed = data
+                    .exposed_from
+                    .get_mut(appid.idx())
+                    .and_then(|exposed| exposed.take());
+                (shared, exposed)
+            });
+            shared.map(|region| {
+                self.data.kernel.process_map_or((), appid.idx(), |process| {
+                    let _ = process.remove_mpu_region(region);
+                });
+            });
+            exposed.map(|region| {
+                self.data.kernel.process_map_or((), index, |process| {
+                    let _ = process.remove_mpu_region(region);
+                });
+            });
+        }
+    }
+}
@@ -16,33 +16,43 @@
 pub mod capabilities;
 pub mod common;
 pub mod component;
+pub mod crash_dump;
 pub mod debug;
 pub mod hil;
 pub mod introspection;
 pub mod ipc;
+pub mod power;
 pub mod syscall;
+pub mod syscall_trace;
+pub mod watchdog;
 
 mod callback;
 mod config;
+mod credentials;
 mod driver;
 mod grant;
 mod mem;
 mod memop;
+mod permissions;
 mod platform;
 mod process;
+mod process_loader;
 mod returncode;
 mod sched;
 mod tbfheader;
 
 pub use crate::callback::{AppId, Callback};
 pub use crate::driver::Driver;
-pub use crate::grant::Grant;
-pub use crate::mem::{AppPtr, AppSlice, Private, Shared};
+pub use crate::grant::{Grant, GrantCleanupClient, GrantCleanupHook};
+pub use crate::mem::{AppPtr, AppSlice, Private, ReadOnlyAppSlice, Shared};
 pub use crate::platform::systick::SysTick;
 pub use crate::platform::{mpu, Chip, Platform};
 pub use crate::platform::{ClockInterface, NoClockControl, NO_CLOCK_CONTROL};
 pub use crate::returncode::ReturnCode;
-pub use crate::sched::Kernel;
+pub use crate::sched::cooperative::CooperativeSched;
+pub use crate::sched::priority::PrioritySched;
+pub use crate::sched::round_robin::RoundRobinSched;
+pub use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
 
 // Export only select items from the process module. To remove the name conflict
 // this cannot be called `process`, so we use a shortened version. These
@@ -50,7 +60,15 @@ pub use crate::sched::Kernel;
 // processes.
 /// Publicly available process-related objects.
 pub mod procs {
+    pub use crate::credentials::{
+        Credentials, CredentialsCheck, CredentialsChecker, CredentialsFormat, RejectedAppResponse,
+    };
+    pub use crate::permissions::{AppPermissions, DefaultPermission, DriverPermission};
     pub use crate::process::{
-        load_processes, Error, FaultResponse, FunctionCall, Process, ProcessType,
+        load_processes, Error, FaultPolicy, FaultResponse, FunctionCall, MemoryQuota, Process,
+        ProcessType, RestartTimer, SchedulingParameters, State,
+    };
+    pub use crate::process_loader::{
+        DynamicProcessLoader, DynamicProcessLoading, ProcessLoadError,
     };
 }
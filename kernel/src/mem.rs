@@ -6,6 +6,7 @@ use core::ptr::Unique;
 use core::slice;
 
 use crate::callback::AppId;
+use crate::platform::mpu;
 
 /// Type for specifying an AppSlice is hidden from the kernel.
 #[derive(Debug)]
@@ -51,7 +52,7 @@ impl<L, T> Drop for AppPtr<L, T> {
     fn drop(&mut self) {
         self.process
             .kernel
-            .process_map_or((), self.process.idx(), |process| unsafe {
+            .app_map_or((), self.process, |process| unsafe {
                 process.free(self.ptr.as_ptr() as *mut u8)
             })
     }
@@ -87,19 +88,19 @@ impl<L, T> AppSlice<L, T> {
     }
 
     /// Provide access to one app's AppSlice to another app. This is used for
-    /// IPC.
-    crate unsafe fn expose_to(&self, appid: AppId) -> bool {
+    /// IPC. Returns the MPU region that was added to the other app, which can
+    /// be passed to `ProcessType::remove_mpu_region()` to revoke the access.
+    crate unsafe fn expose_to(
+        &self,
+        appid: AppId,
+        permissions: mpu::Permissions,
+    ) -> Option<mpu::Region> {
         if appid.idx() != self.ptr.process.idx() {
-            self.ptr
-                .process
-                .kernel
-                .process_map_or(false, appid.idx(), |process| {
-                    process
-                        .add_mpu_region(self.ptr() as *const u8, self.len(), self.len())
-                        .is_some()
-                })
+            self.ptr.process.kernel.app_map_or(None, appid, |process| {
+                process.add_mpu_region(self.ptr() as *const u8, self.len(), self.len(), permissions)
+            })
         } else {
-            false
+            None
         }
     }
 
@@ -131,3 +132,53 @@ impl<L, T> AsMut<[T]> for AppSlice<L, T> {
         unsafe { slice::from_raw_parts_mut(self.ptr.ptr.as_mut(), self.len) }
     }
 }
+
+/// Buffer of memory shared read-only from an app to the kernel.
+///
+/// This is the type created after an app calls the `allow_readonly` syscall.
+/// Unlike an `AppSlice`, the buffer may be in the app's flash as well as in
+/// its RAM, so the kernel can only read it. Drivers that only read a buffer
+/// can also turn an `AppSlice` from the `allow` syscall into a
+/// `ReadOnlyAppSlice`, to handle buffers from both syscalls the same way.
+pub struct ReadOnlyAppSlice<T> {
+    slice: AppSlice<Shared, T>,
+}
+
+impl<T> ReadOnlyAppSlice<T> {
+    crate fn new(ptr: *const T, len: usize, appid: AppId) -> ReadOnlyAppSlice<T> {
+        ReadOnlyAppSlice {
+            slice: AppSlice::new(ptr as *mut T, len, appid),
+        }
+    }
+
+    /// Number of bytes in the `ReadOnlyAppSlice`.
+    pub fn len(&self) -> usize {
+        self.slice.len()
+    }
+
+    /// Get the raw pointer to the buffer. This will be a pointer inside of the
+    /// app's memory region or flash.
+    pub fn ptr(&self) -> *const T {
+        self.slice.ptr()
+    }
+
+    pub fn iter(&self) -> slice::Iter<T> {
+        self.slice.iter()
+    }
+
+    pub fn chunks(&self, size: usize) -> slice::Chunks<T> {
+        self.slice.chunks(size)
+    }
+}
+
+impl<T> AsRef<[T]> for ReadOnlyAppSlice<T> {
+    fn as_ref(&self) -> &[T] {
+        self.slice.as_ref()
+    }
+}
+
+impl<T> From<AppSlice<Shared, T>> for ReadOnlyAppSlice<T> {
+    fn from(slice: AppSlice<Shared, T>) -> ReadOnlyAppSlice<T> {
+        ReadOnlyAppSlice { slice: slice }
+    }
+}
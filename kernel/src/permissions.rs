@@ -0,0 +1,194 @@
+//! Restricting which drivers processes may use.
+//!
+//! By default, a process can subscribe, command and allow with every driver
+//! the board's `Platform` routes system calls to. A process can be limited to
+//! a list of drivers, and optionally a list of commands for each driver, in
+//! two ways:
+//!
+//! - The app declares the drivers it uses in the Permissions TLV of its TBF
+//!   header.
+//! - The board declares `AppPermissions` for apps by name with
+//!   `Kernel::set_syscall_permissions()`.
+//!
+//! If both exist, a system call must be allowed by both. System calls that are
+//! not allowed return `ReturnCode::EPERM` without reaching the driver, and are
+//! counted in the process's debug statistics. Memop and yield are always
+//! allowed.
+//!
+//! A process that the board does not list may use every driver its header
+//! allows, or every driver if its header has no permissions, unless the board
+//! sets `DefaultPermission::DenyAll`. Then such a process may only use memop
+//! and yield, so that an app the board does not know about gets no access to
+//! drivers. Any app can put any permissions in its own header, so under
+//! `DenyAll` header permissions only grant access if the app's credentials
+//! authenticated the header with a key or signature the board trusts (see
+//! `credentials`). Drivers can also check `AppId::has_driver_permission()` to
+//! only serve processes that are explicitly allowed to use them, by the board
+//! or by an authenticated header.
+//!
+//! ```ignore
+//! static PERMISSIONS: [AppPermissions; 1] = [AppPermissions {
+//!     app: "sensors",
+//!     drivers: &[
+//!         DriverPermission::driver(capsules::console::DRIVER_NUM),
+//!         DriverPermission::commands(capsules::temperature::DRIVER_NUM, &[0, 1]),
+//!     ],
+//! }];
+//! board_kernel.set_syscall_permissions(&PERMISSIONS, DefaultPermission::DenyAll);
+//! ```
+
+/// What a process may use if neither its TBF header nor the board lists the
+/// drivers it may use.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DefaultPermission {
+    /// The process may use every driver.
+    AllowAll,
+    /// The process may not use any driver.
+    DenyAll,
+}
+
+/// A driver a process may use.
+pub struct DriverPermission {
+    driver_number: usize,
+    /// The commands the process may call, or `None` for all of them.
+    commands: Option<&'static [usize]>,
+}
+
+impl DriverPermission {
+    /// Allow every command of the driver `driver_number`.
+    pub const fn driver(driver_number: usize) -> DriverPermission {
+        DriverPermission {
+            driver_number,
+            commands: None,
+        }
+    }
+
+    /// Allow only the commands in `commands` of the driver `driver_number`.
+    /// Subscribe and allow calls to the driver are still allowed.
+    pub const fn commands(driver_number: usize, commands: &'static [usize]) -> DriverPermission {
+        DriverPermission {
+            driver_number,
+            commands: Some(commands),
+        }
+    }
+
+    fn allows(&self, driver_number: usize, command: Option<usize>) -> bool {
+        self.driver_number == driver_number
+            && match (command, self.commands) {
+                (Some(command), Some(commands)) => commands.contains(&command),
+                _ => true,
+            }
+    }
+}
+
+/// Decide whether a process may use a driver (or one of its commands). `header`
+/// and `board` are whether the permissions in the process's TBF header and
+/// the board's `AppPermissions` for it allow the driver, or `None` if they do
+/// not list permissions for the process. `header_authenticated` is whether the
+/// app's credentials authenticated the header.
+crate fn permitted(
+    header: Option<bool>,
+    header_authenticated: bool,
+    board: Option<bool>,
+    default: DefaultPermission,
+) -> bool {
+    match (header, board) {
+        // The header can only narrow what the board allows.
+        (header, Some(board)) => board && header.unwrap_or(true),
+        // Without a board entry, the header only grants access where the
+        // default would, unless it is authenticated.
+        (Some(header), None) => {
+            header && (header_authenticated || default == DefaultPermission::AllowAll)
+        }
+        (None, None) => default == DefaultPermission::AllowAll,
+    }
+}
+
+/// The drivers the board allows the app named `app` to use.
+pub struct AppPermissions {
+    pub app: &'static str,
+    pub drivers: &'static [DriverPermission],
+}
+
+impl AppPermissions {
+    /// Check whether the permissions allow the driver `driver_number` and, if
+    /// `command` is given, that command of the driver.
+    crate fn allows(&self, driver_number: usize, command: Option<usize>) -> bool {
+        self.drivers
+            .iter()
+            .any(|driver| driver.allows(driver_number, command))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{permitted, DefaultPermission};
+
+    #[test]
+    fn no_header_no_board() {
+        assert!(permitted(None, false, None, DefaultPermission::AllowAll));
+        assert!(!permitted(None, false, None, DefaultPermission::DenyAll));
+    }
+
+    #[test]
+    fn header_only() {
+        for &default in &[DefaultPermission::AllowAll, DefaultPermission::DenyAll] {
+            assert!(!permitted(Some(false), false, None, default));
+            assert!(!permitted(Some(false), true, None, default));
+            assert!(permitted(Some(true), true, None, default));
+        }
+        // An app cannot grant itself access with an unauthenticated header.
+        assert!(permitted(
+            Some(true),
+            false,
+            None,
+            DefaultPermission::AllowAll
+        ));
+        assert!(!permitted(
+            Some(true),
+            false,
+            None,
+            DefaultPermission::DenyAll
+        ));
+    }
+
+    #[test]
+    fn board_only() {
+        for &default in &[DefaultPermission::AllowAll, DefaultPermission::DenyAll] {
+            assert!(permitted(None, false, Some(true), default));
+            assert!(!permitted(None, false, Some(false), default));
+        }
+    }
+
+    #[test]
+    fn header_and_board() {
+        for &default in &[DefaultPermission::AllowAll, DefaultPermission::DenyAll] {
+            for &header_authenticated in &[false, true] {
+                assert!(permitted(
+                    Some(true),
+                    header_authenticated,
+                    Some(true),
+                    default
+                ));
+                assert!(!permitted(
+                    Some(false),
+                    header_authenticated,
+                    Some(true),
+                    default
+                ));
+                assert!(!permitted(
+                    Some(true),
+                    header_authenticated,
+                    Some(false),
+                    default
+                ));
+                assert!(!permitted(
+                    Some(false),
+                    header_authenticated,
+                    Some(false),
+                    default
+                ));
+            }
+        }
+    }
+}
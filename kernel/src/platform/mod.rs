@@ -1,6 +1,7 @@
 //! Interface for chips and boards.
 
 use crate::driver::Driver;
+use crate::power;
 use crate::syscall;
 use core::fmt::Write;
 
@@ -95,6 +96,15 @@ pub trait Chip {
     /// chip and resumes the scheduler.
     fn sleep(&self);
 
+    /// Like `sleep()`, but sleep no deeper than `deepest`. The kernel loop
+    /// passes the deepest state the votes in the `power::PowerManager`
+    /// permit, and the chip picks the deepest of its sleep modes within that
+    /// limit. Chips with a single sleep mode can keep the default, which
+    /// calls `sleep()`.
+    fn sleep_in(&self, _deepest: power::SleepState) {
+        self.sleep();
+    }
+
     /// Run a function in an atomic state, which means that interrupts are
     /// disabled so that an interrupt will not fire during the passed in
     /// function's execution.
@@ -16,7 +16,7 @@ pub enum Permissions {
 /// MPU region.
 ///
 /// This is one contiguous address space protected by the MPU.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Region {
     /// The memory address where the region starts.
     ///
@@ -148,6 +148,27 @@ pub trait MPU {
         }
     }
 
+    /// Removes an MPU region.
+    ///
+    /// An implementation must remove the region that `allocate_region`
+    /// returned as `region` from `config`, so that the memory it covered is no
+    /// longer accessible in user mode after the next call to `configure_mpu`.
+    ///
+    /// # Arguments
+    ///
+    /// - `region`: the region to remove
+    /// - `config`: MPU region configuration
+    ///
+    /// # Return Value
+    ///
+    /// Returns an error if `region` is not stored in `config`. The default
+    /// implementation always returns an error, since an MPU that does not
+    /// implement this cannot revoke access to memory.
+    #[allow(unused_variables)]
+    fn remove_memory_region(&self, region: Region, config: &mut Self::MpuConfig) -> Result<(), ()> {
+        Err(())
+    }
+
     /// Chooses the location for a process's memory, and allocates an MPU region
     /// covering the app-owned part.
     ///
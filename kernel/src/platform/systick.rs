@@ -18,6 +18,14 @@ pub trait SysTick {
     /// accurate and values up to 400ms are valid.
     fn set_timer(&self, us: u32);
 
+    /// Returns the number of microseconds remaining in the current timer
+    /// interval.
+    ///
+    /// This must be called before `reset()`, which clears the current value.
+    /// Schedulers use this to account for how much of a timeslice a process
+    /// actually used.
+    fn get_value(&self) -> u32;
+
     /// Returns if there is at least `us` microseconds left
     fn greater_than(&self, us: u32) -> bool;
 
@@ -47,6 +55,10 @@ impl SysTick for () {
 
     fn set_timer(&self, _: u32) {}
 
+    fn get_value(&self) -> u32 {
+        core::u32::MAX
+    }
+
     fn enable(&self, _: bool) {}
 
     fn overflowed(&self) -> bool {
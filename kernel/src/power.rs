@@ -0,0 +1,202 @@
+//! Power management: how deeply the chip may sleep.
+//!
+//! Peripherals and capsules that need something to keep running while the
+//! kernel sleeps, such as a high-frequency clock or a UART that is receiving,
+//! vote for a `PowerRequirement` and release the vote once they are done.
+//! When there is nothing left to do, `Kernel::kernel_loop()` passes the
+//! deepest `SleepState` the current votes permit to `Chip::sleep_in()`. The
+//! chip then enters the deepest of its hardware sleep modes within that limit
+//! that its own state, such as the enabled peripheral clocks, allows.
+//!
+//! Votes are counted, so several users can vote for the same requirement.
+//! Boards that do not register a global `PowerManager` behave as if nothing
+//! has voted, and voting does nothing. A peripheral whose clock must keep
+//! running while the chip sleeps can wrap that clock in a `PowerClock`, which
+//! votes for as long as the clock is enabled.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! # use kernel::static_init;
+//! use kernel::power::{self, PowerManager, PowerRequirement, SleepState};
+//!
+//! let power_manager = unsafe { static_init!(PowerManager, PowerManager::new()) };
+//! unsafe { PowerManager::set_global_instance(power_manager) };
+//! assert_eq!(power::deepest_permitted(), SleepState::DeepSleep);
+//!
+//! // A UART starts receiving, and a radio needs an accurate clock.
+//! power::vote(PowerRequirement::UartReceive);
+//! assert_eq!(power::deepest_permitted(), SleepState::Sleep);
+//! power::vote(PowerRequirement::HighFrequencyClock);
+//! assert_eq!(power::deepest_permitted(), SleepState::Idle);
+//!
+//! // And both are done.
+//! power::release(PowerRequirement::HighFrequencyClock);
+//! power::release(PowerRequirement::UartReceive);
+//! assert_eq!(power::deepest_permitted(), SleepState::DeepSleep);
+//! ```
+
+use core::cell::Cell;
+
+use crate::platform::ClockInterface;
+
+/// Kernel-global power manager, which peripherals and capsules vote into
+/// with `vote()` and `release()`.
+static mut POWER_MANAGER: Option<&'static PowerManager> = None;
+
+/// How deeply the chip sleeps, from the lightest state to the deepest.
+/// Each chip maps these to its hardware sleep modes, and uses a lighter state
+/// for any it has no mode for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum SleepState {
+    /// Only the CPU stops. The bus clocks and all enabled clocks keep
+    /// running, and the chip wakes up at once.
+    Idle,
+    /// The CPU and bus clocks stop, and so may clocks that no enabled
+    /// peripheral uses. Enabled peripherals keep working, but waking up may
+    /// take longer.
+    Sleep,
+    /// Peripheral clocks stop as well, except for low-frequency timers and
+    /// wake-up sources. RAM is retained.
+    DeepSleep,
+}
+
+/// Something that must keep working while the chip sleeps.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PowerRequirement {
+    /// A high-frequency clock must keep running, for example for the timing
+    /// of a radio.
+    HighFrequencyClock,
+    /// A UART must be able to receive.
+    UartReceive,
+    /// A DMA transfer is in progress, which needs the bus clocks.
+    Dma,
+}
+
+const NUM_REQUIREMENTS: usize = 3;
+
+impl PowerRequirement {
+    /// The deepest state in which the requirement is still met.
+    pub fn deepest_state(&self) -> SleepState {
+        match self {
+            PowerRequirement::HighFrequencyClock => SleepState::Idle,
+            PowerRequirement::UartReceive => SleepState::Sleep,
+            PowerRequirement::Dma => SleepState::Idle,
+        }
+    }
+
+    fn all() -> [PowerRequirement; NUM_REQUIREMENTS] {
+        [
+            PowerRequirement::HighFrequencyClock,
+            PowerRequirement::UartReceive,
+            PowerRequirement::Dma,
+        ]
+    }
+}
+
+/// Counts the votes for each `PowerRequirement`.
+pub struct PowerManager {
+    votes: [Cell<usize>; NUM_REQUIREMENTS],
+}
+
+impl PowerManager {
+    pub const fn new() -> PowerManager {
+        PowerManager {
+            votes: [Cell::new(0), Cell::new(0), Cell::new(0)],
+        }
+    }
+
+    /// Sets the global power manager that `vote()`, `release()` and the
+    /// kernel loop use.
+    ///
+    /// Returns `false` if a different instance is already registered, which
+    /// is kept.
+    pub unsafe fn set_global_instance(manager: &'static PowerManager) -> bool {
+        (*POWER_MANAGER.get_or_insert(manager)) as *const _ == manager as *const _
+    }
+
+    /// Add a vote for `requirement`.
+    pub fn vote(&self, requirement: PowerRequirement) {
+        let votes = &self.votes[requirement as usize];
+        votes.set(votes.get() + 1);
+    }
+
+    /// Remove a vote for `requirement`. Every release must match an earlier
+    /// vote.
+    pub fn release(&self, requirement: PowerRequirement) {
+        let votes = &self.votes[requirement as usize];
+        debug_assert!(votes.get() > 0, "released {:?} without a vote", requirement);
+        votes.set(votes.get() - 1);
+    }
+
+    /// The number of votes for `requirement`.
+    pub fn votes(&self, requirement: PowerRequirement) -> usize {
+        self.votes[requirement as usize].get()
+    }
+
+    /// The deepest state that meets every requirement with a vote.
+    pub fn deepest_permitted(&self) -> SleepState {
+        PowerRequirement::all()
+            .iter()
+            .filter(|requirement| self.votes(**requirement) > 0)
+            .map(|requirement| requirement.deepest_state())
+            .min()
+            .unwrap_or(SleepState::DeepSleep)
+    }
+}
+
+/// Vote for `requirement` in the global power manager, if there is one.
+pub fn vote(requirement: PowerRequirement) {
+    unsafe { POWER_MANAGER.map(|manager| manager.vote(requirement)) };
+}
+
+/// Release a vote for `requirement` in the global power manager, if there is
+/// one.
+pub fn release(requirement: PowerRequirement) {
+    unsafe { POWER_MANAGER.map(|manager| manager.release(requirement)) };
+}
+
+/// The deepest state the votes in the global power manager permit, or
+/// `DeepSleep` if there is no global power manager.
+pub fn deepest_permitted() -> SleepState {
+    unsafe { POWER_MANAGER.map_or(SleepState::DeepSleep, |manager| manager.deepest_permitted()) }
+}
+
+/// A clock that votes for a `PowerRequirement` while it is enabled, so that
+/// the chip does not sleep deeper than the peripheral it drives permits.
+pub struct PowerClock<C> {
+    clock: C,
+    requirement: PowerRequirement,
+    voted: Cell<bool>,
+}
+
+impl<C> PowerClock<C> {
+    pub const fn new(clock: C, requirement: PowerRequirement) -> PowerClock<C> {
+        PowerClock {
+            clock,
+            requirement,
+            voted: Cell::new(false),
+        }
+    }
+}
+
+impl<C: ClockInterface> ClockInterface for PowerClock<C> {
+    fn is_enabled(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    fn enable(&self) {
+        if !self.voted.replace(true) {
+            vote(self.requirement);
+        }
+        self.clock.enable();
+    }
+
+    fn disable(&self) {
+        self.clock.disable();
+        if self.voted.replace(false) {
+            release(self.requirement);
+        }
+    }
+}
@@ -3,47 +3,76 @@
 use core::cell::Cell;
 use core::fmt::Write;
 use core::ptr::write_volatile;
-use core::{mem, ptr, slice, str};
+use core::{cmp, mem, ptr, slice, str};
 
 use crate::callback::{AppId, CallbackId};
 use crate::capabilities::ProcessManagementCapability;
 use crate::common::cells::MapCell;
 use crate::common::{Queue, RingBuffer};
 use crate::config;
-use crate::debug;
-use crate::mem::{AppSlice, Shared};
+use crate::crash_dump;
+use crate::credentials::{CredentialsCheck, RejectedAppResponse};
+use crate::mem::{AppSlice, ReadOnlyAppSlice, Shared};
 use crate::platform::mpu::{self, MPU};
 use crate::platform::Chip;
 use crate::returncode::ReturnCode;
-use crate::sched::Kernel;
-use crate::syscall::{self, Syscall, UserspaceKernelBoundary};
+use crate::sched::{self, Kernel};
+use crate::syscall::{self, Syscall, SyscallClass, SyscallLatency, UserspaceKernelBoundary};
+use crate::syscall_trace::TraceEvent;
 use crate::tbfheader;
 use core::cmp::max;
 
-/// Helper function to load processes from flash into an array of active
-/// processes. This is the default template for loading processes, but a board
+/// Helper function to load processes from flash into the process slots of the
+/// kernel. This is the default template for loading processes, but a board
 /// is able to create its own `load_processes()` function and use that instead.
 ///
 /// Processes are found in flash starting from the given address and iterating
 /// through Tock Binary Format headers. Processes are given memory out of the
 /// `app_memory` buffer until either the memory is exhausted or the allocated
 /// number of processes are created, with process structures placed in the
-/// provided array. How process faults are handled by the kernel is also
-/// selected.
+/// kernel's process slots. How process faults are handled by the kernel is also
+/// selected. If the board passes a `CredentialsCheck`, the credentials of
+/// every enabled app are checked before it is loaded.
+///
+/// Returns the part of `app_memory` that was not given to any process, which a
+/// board can hand to a `DynamicProcessLoader` to load more processes later.
 pub fn load_processes<C: Chip>(
     kernel: &'static Kernel,
     chip: &'static C,
     start_of_flash: *const u8,
-    app_memory: &mut [u8],
-    procs: &'static mut [Option<&'static dyn ProcessType>],
+    app_memory: &'a mut [u8],
     fault_response: FaultResponse,
+    credentials_check: Option<CredentialsCheck>,
     _capability: &dyn ProcessManagementCapability,
-) {
+) -> &'a mut [u8] {
     let mut apps_in_flash_ptr = start_of_flash;
     let mut app_memory_ptr = app_memory.as_mut_ptr();
     let mut app_memory_size = app_memory.len();
-    for i in 0..procs.len() {
+    for i in 0..kernel.number_of_process_slots() {
         unsafe {
+            // Check the credentials of the app before giving it any memory.
+            // Rejected apps are skipped without using up a process slot.
+            let mut header_authenticated = false;
+            if let Some(check) = credentials_check {
+                while let Some(header) = tbfheader::parse_and_validate_tbf_header(apps_in_flash_ptr)
+                {
+                    if check.app_accepted(apps_in_flash_ptr, &header) {
+                        header_authenticated = check.authenticates_header(&header);
+                        break;
+                    }
+                    match check.response() {
+                        RejectedAppResponse::Panic => panic!(
+                            "Credentials of app {} were rejected",
+                            header.get_package_name()
+                        ),
+                        RejectedAppResponse::Skip => {
+                            apps_in_flash_ptr =
+                                apps_in_flash_ptr.add(header.get_total_size() as usize);
+                        }
+                    }
+                }
+            }
+
             let (process, flash_offset, memory_offset) = Process::create(
                 kernel,
                 chip,
@@ -51,6 +80,7 @@ pub fn load_processes<C: Chip>(
                 app_memory_ptr,
                 app_memory_size,
                 fault_response,
+                header_authenticated,
                 i,
             );
 
@@ -63,7 +93,7 @@ pub fn load_processes<C: Chip>(
                     break;
                 }
             } else {
-                procs[i] = process;
+                kernel.set_process(i, process);
             }
 
             apps_in_flash_ptr = apps_in_flash_ptr.add(flash_offset);
@@ -71,6 +101,7 @@ pub fn load_processes<C: Chip>(
             app_memory_size -= memory_offset;
         }
     }
+    unsafe { slice::from_raw_parts_mut(app_memory_ptr, app_memory_size) }
 }
 
 /// This trait is implemented by process structs.
@@ -98,10 +129,35 @@ pub trait ProcessType {
     /// queue.
     fn remove_pending_callbacks(&self, callback_id: CallbackId);
 
+    /// Returns whether this process is ready to execute, i.e. it is either
+    /// running or it is waiting and has a `Task` queued. Schedulers use this
+    /// to decide which process to switch to next.
+    fn ready(&self) -> bool;
+
     /// Returns the current state the process is in. Common states are "running"
     /// or "yielded".
     fn get_state(&self) -> State;
 
+    /// Returns the scheduling parameters requested by the process in its TBF
+    /// header, or the defaults if it did not request any.
+    fn get_scheduling_parameters(&self) -> SchedulingParameters;
+
+    /// Returns whether the process has used up its CPU budget for the current
+    /// period. Processes without a budget never exhaust it.
+    fn cpu_budget_exhausted(&self) -> bool;
+
+    /// Returns how many microseconds of CPU budget the process has left in the
+    /// current period, or `None` if the process has no budget.
+    fn cpu_budget_remaining(&self) -> Option<u32>;
+
+    /// Account for `elapsed_us` microseconds of process execution time, of
+    /// which `used_us` were used by this process. This advances the process's
+    /// budget period and replenishes the budget when the period ends.
+    fn update_cpu_budget(&self, elapsed_us: u32, used_us: u32);
+
+    /// Start a new budget period for this process immediately.
+    fn replenish_cpu_budget(&self);
+
     /// Move this process from the running state to the yielded state.
     fn set_yielded_state(&self);
 
@@ -111,13 +167,56 @@ pub trait ProcessType {
     /// Move this stopped process back into its original state
     fn resume(&self);
 
-    /// Put this process in the fault state. This will trigger the
-    /// `FaultResponse` for this process to occur.
+    /// Put this process in the fault state. This will trigger the response
+    /// selected by the process's `FaultPolicy`.
     fn set_fault_state(&self);
 
+    /// Returns how faults of this process are currently handled.
+    fn get_fault_policy(&self) -> FaultPolicy;
+
+    /// Change how faults of this process are handled. This overrides the
+    /// policy the process was loaded with.
+    fn set_fault_policy(&self, policy: FaultPolicy);
+
+    /// Returns the limits on the grant memory of this process.
+    fn get_memory_quota(&self) -> MemoryQuota;
+
+    /// Change the limits on the grant memory of this process. This overrides
+    /// the quota the process was loaded with. Memory that is already
+    /// allocated is not released if it exceeds the new quota.
+    fn set_memory_quota(&self, quota: MemoryQuota);
+
+    /// Start this process over from its entry point, regardless of its
+    /// `FaultPolicy`. Queued tasks are dropped, grant regions are cleared
+    /// and registered `GrantCleanupClient`s are notified, as when a process
+    /// with `FaultResponse::Restart` faults.
+    fn restart(&self);
+
+    /// Stop this process permanently and release the kernel resources it
+    /// holds: any queued tasks are dropped, its grant regions are cleared and
+    /// registered `GrantCleanupClient`s are notified. The process is left in
+    /// the `Terminated` state and is never scheduled again. This is used
+    /// before a process is unloaded.
+    fn terminate(&self);
+
     /// Get the name of the process. Used for IPC.
     fn get_process_name(&self) -> &'static str;
 
+    /// Check whether the IPC access list in the process's TBF header lets the
+    /// app named `client` use this process as an IPC service. Returns `None`
+    /// if the header has no IPC access list.
+    fn ipc_allows_client(&self, client: &str) -> Option<bool>;
+
+    /// Check whether the permissions in the process's TBF header let it use
+    /// the driver `driver_number` and, if `command` is given, that command of
+    /// the driver. Returns `None` if the header has no permissions.
+    fn header_allows_syscall(&self, driver_number: usize, command: Option<usize>) -> Option<bool>;
+
+    /// Whether the credentials of the process's app authenticated its TBF
+    /// header when it was loaded, so that the permissions the header declares
+    /// were set by someone the board trusts.
+    fn header_authenticated(&self) -> bool;
+
     // memop operations
 
     /// Change the location of the program break and reallocate the MPU region
@@ -176,6 +275,20 @@ pub trait ProcessType {
         size: usize,
     ) -> Result<Option<AppSlice<Shared, u8>>, ReturnCode>;
 
+    /// Creates a `ReadOnlyAppSlice` from the given offset and size in process
+    /// memory or flash.
+    ///
+    /// ## Returns
+    ///
+    /// If the buffer is null (a zero-valued offset), return None, signaling the capsule to delete
+    /// the entry. If the buffer is within the process's accessible memory or its flash, returns
+    /// a ReadOnlyAppSlice wrapping that buffer. Otherwise, returns an error `ReturnCode`.
+    fn allow_readonly(
+        &self,
+        buf_start_addr: *const u8,
+        size: usize,
+    ) -> Result<Option<ReadOnlyAppSlice<u8>>, ReturnCode>;
+
     /// Get the first address of process's flash that isn't protected by the
     /// kernel. The protected range of flash contains the TBF header and
     /// potentially other state the kernel is storing on behalf of the process,
@@ -188,25 +301,41 @@ pub trait ProcessType {
     fn setup_mpu(&self);
 
     /// Allocate a new MPU region for the process that is at least `min_region_size`
-    /// bytes and lies within the specified stretch of unallocated memory.
+    /// bytes and lies within the specified stretch of unallocated memory, with
+    /// the given user mode permissions.
     fn add_mpu_region(
         &self,
         unallocated_memory_start: *const u8,
         unallocated_memory_size: usize,
         min_region_size: usize,
+        permissions: mpu::Permissions,
     ) -> Option<mpu::Region>;
 
+    /// Remove an MPU region that was allocated with `add_mpu_region()`. The
+    /// process can no longer access the memory after it is next scheduled.
+    fn remove_mpu_region(&self, region: mpu::Region) -> Result<(), ()>;
+
     // grants
 
-    /// Create new memory in the grant region, and check that the MPU region
-    /// covering program memory does not extend past the kernel memory break.
-    unsafe fn alloc(&self, size: usize, align: usize) -> Option<&mut [u8]>;
+    /// Create new memory in the grant region on behalf of grant `grant_num`,
+    /// and check that the MPU region covering program memory does not extend
+    /// past the kernel memory break. Fails if the allocation would exceed the
+    /// process's `MemoryQuota`.
+    unsafe fn alloc(&self, size: usize, align: usize, grant_num: usize) -> Option<&mut [u8]>;
 
     unsafe fn free(&self, _: *mut u8);
 
     /// Get a pointer to the grant pointer for this grant number.
     unsafe fn grant_ptr(&self, grant_num: usize) -> *mut *mut u8;
 
+    /// Returns how many bytes of grant memory have been allocated for this
+    /// process, by all grants together.
+    fn grant_memory_used(&self) -> usize;
+
+    /// Returns how many bytes of grant memory have been allocated for this
+    /// process on behalf of grant `grant_num`.
+    fn grant_memory_used_by(&self, grant_num: usize) -> usize;
+
     // functions for processes that are architecture specific
 
     /// Set the return value the process should see when it begins executing
@@ -235,6 +364,18 @@ pub trait ProcessType {
     /// Returns how many callbacks for this process have been dropped.
     fn debug_dropped_callback_count(&self) -> usize;
 
+    /// Returns how many grant allocations for this process were refused
+    /// because they would have exceeded its `MemoryQuota`.
+    fn debug_grant_quota_exceeded_count(&self) -> usize;
+
+    /// Returns how many system calls of this process were refused because
+    /// its permissions do not include the driver or command.
+    fn debug_denied_syscall_count(&self) -> usize;
+
+    /// Count a system call that was refused because of the process's
+    /// permissions.
+    fn debug_syscall_denied(&self);
+
     /// Returns how many times this process has been restarted.
     fn debug_restart_count(&self) -> usize;
 
@@ -246,12 +387,31 @@ pub trait ProcessType {
 
     /// Increment the number of times the process called a syscall.
     fn debug_syscall_called(&self);
+
+    /// Returns how long this process has executed since it was loaded, in
+    /// microseconds, as measured by the SysTick.
+    fn debug_cpu_time_us(&self) -> u64;
+
+    /// Add `us` microseconds to the time this process has executed.
+    fn debug_ran_for(&self, us: u32);
+
+    /// Returns how long the kernel has taken to handle system calls of
+    /// `class` for this process.
+    fn debug_syscall_latency(&self, class: SyscallClass) -> SyscallLatency;
+
+    /// Record that the kernel took `latency_us` microseconds to handle a
+    /// system call of `class` for this process.
+    fn debug_syscall_handled(&self, class: SyscallClass, latency_us: u32);
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Error {
     NoSuchApp,
     OutOfMemory,
+    /// There is no grant memory left for the process, either because the
+    /// grant region would collide with the app's heap or because its
+    /// `MemoryQuota` would be exceeded.
+    OutOfGrantMemory,
     AddressOutOfBounds,
     KernelError, // This likely indicates a bug in the kernel and that some
                  // state is inconsistent in the kernel.
@@ -261,6 +421,7 @@ impl From<Error> for ReturnCode {
     fn from(err: Error) -> ReturnCode {
         match err {
             Error::OutOfMemory => ReturnCode::ENOMEM,
+            Error::OutOfGrantMemory => ReturnCode::ENOGRANT,
             Error::AddressOutOfBounds => ReturnCode::EINVAL,
             Error::NoSuchApp => ReturnCode::EINVAL,
             Error::KernelError => ReturnCode::FAIL,
@@ -306,6 +467,10 @@ pub enum State {
     /// processes yet. It can also happen if an process is terminated and all
     /// of its state is reset as if it has not been executed yet.
     Unstarted,
+
+    /// The process has been terminated by the kernel. It holds no tasks or
+    /// grant memory and will not execute again.
+    Terminated,
 }
 
 /// The reaction the kernel should take when an app encounters a fault.
@@ -330,6 +495,220 @@ pub enum FaultResponse {
     Stop,
 }
 
+impl FaultResponse {
+    /// Decode a fault response from the encoding used in TBF headers.
+    fn from_tbf(response: u32) -> Option<FaultResponse> {
+        match response {
+            0 => Some(FaultResponse::Panic),
+            1 => Some(FaultResponse::Restart),
+            2 => Some(FaultResponse::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// How the kernel handles the faults of a single process.
+///
+/// Each process starts with the policy requested in its TBF header, or with a
+/// policy built from the board's `FaultResponse` if it did not request one.
+/// Boards can change it with `ProcessType::set_fault_policy()`.
+///
+/// The number of restarts is the process's `debug_restart_count()`, so
+/// restarts from the process console also count towards the limit.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FaultPolicy {
+    /// What to do when the process faults.
+    pub response: FaultResponse,
+
+    /// With `FaultResponse::Restart`, how many times the process may be
+    /// restarted before `limit_response` is used instead. `None` means there
+    /// is no limit.
+    pub restart_limit: Option<usize>,
+
+    /// What to do when the process faults after it has been restarted
+    /// `restart_limit` times. `FaultResponse::Restart` is treated as
+    /// `FaultResponse::Stop`.
+    pub limit_response: FaultResponse,
+
+    /// With `FaultResponse::Restart`, how long to wait before restarting the
+    /// process, in milliseconds. The delay doubles with every restart, up to
+    /// `max_backoff_ms`. Zero restarts the process immediately. Delays need a
+    /// `RestartTimer`, see `Kernel::set_restart_timer()`.
+    pub backoff_ms: u32,
+
+    /// Upper bound on the restart delay, in milliseconds. Zero means the
+    /// delay is not capped.
+    pub max_backoff_ms: u32,
+}
+
+impl FaultPolicy {
+    /// Always respond to faults with `response`.
+    pub fn new(response: FaultResponse) -> FaultPolicy {
+        FaultPolicy {
+            response: response,
+            restart_limit: None,
+            limit_response: FaultResponse::Stop,
+            backoff_ms: 0,
+            max_backoff_ms: 0,
+        }
+    }
+
+    /// Restart the process at most `limit` times, and after that respond to
+    /// faults with `limit_response`.
+    pub fn restart_limited(limit: usize, limit_response: FaultResponse) -> FaultPolicy {
+        FaultPolicy {
+            restart_limit: Some(limit),
+            limit_response: limit_response,
+            ..FaultPolicy::new(FaultResponse::Restart)
+        }
+    }
+
+    /// Wait `backoff_ms` before the first restart, doubling the delay for
+    /// every later restart up to `max_backoff_ms`, or without a cap if
+    /// `max_backoff_ms` is zero.
+    pub fn with_backoff(self, backoff_ms: u32, max_backoff_ms: u32) -> FaultPolicy {
+        FaultPolicy {
+            backoff_ms: backoff_ms,
+            max_backoff_ms: max_backoff_ms,
+            ..self
+        }
+    }
+
+    /// The response to a fault of a process that has been restarted
+    /// `restart_count` times.
+    fn response_after(&self, restart_count: usize) -> FaultResponse {
+        match self.response {
+            FaultResponse::Restart
+                if self
+                    .restart_limit
+                    .map_or(false, |limit| restart_count >= limit) =>
+            {
+                match self.limit_response {
+                    FaultResponse::Panic => FaultResponse::Panic,
+                    FaultResponse::Restart | FaultResponse::Stop => FaultResponse::Stop,
+                }
+            }
+            response => response,
+        }
+    }
+
+    /// How long to wait before restarting a process that has been restarted
+    /// `restart_count` times, in milliseconds.
+    fn restart_delay_ms(&self, restart_count: usize) -> u32 {
+        let shift = cmp::min(restart_count, 31) as u32;
+        let delay = (self.backoff_ms as u64) << shift;
+        let max_delay = match self.max_backoff_ms {
+            0 => core::u32::MAX,
+            max_backoff_ms => max_backoff_ms,
+        };
+        cmp::min(delay, max_delay as u64) as u32
+    }
+}
+
+impl From<FaultResponse> for FaultPolicy {
+    fn from(response: FaultResponse) -> FaultPolicy {
+        FaultPolicy::new(response)
+    }
+}
+
+/// Limits on the grant memory the kernel allocates for a single process.
+///
+/// Grant memory is carved out of the process's own RAM block, so without a
+/// quota a single capsule can use up all of the memory the app could have
+/// used for its heap. Each process starts with the quota requested in its TBF
+/// header, or with no limits. Boards can change it with
+/// `ProcessType::set_memory_quota()`.
+///
+/// Allocations that would exceed the quota fail with
+/// `ReturnCode::ENOGRANT`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryQuota {
+    /// How many bytes of grant memory all grants together may use. `None`
+    /// means there is no limit.
+    pub grant_bytes: Option<usize>,
+
+    /// How many bytes of grant memory any single grant may use. `None` means
+    /// there is no limit.
+    pub per_grant_bytes: Option<usize>,
+}
+
+impl MemoryQuota {
+    /// Limit the grant memory of all grants together to `grant_bytes`, and of
+    /// any single grant to `per_grant_bytes`.
+    pub fn new(grant_bytes: usize, per_grant_bytes: usize) -> MemoryQuota {
+        MemoryQuota {
+            grant_bytes: Some(grant_bytes),
+            per_grant_bytes: Some(per_grant_bytes),
+        }
+    }
+
+    /// Decode a quota from the encoding used in TBF headers, where zero
+    /// means no limit.
+    fn from_tbf(grant_quota: u32, per_grant_quota: u32) -> MemoryQuota {
+        let limit = |bytes| {
+            if bytes == 0 {
+                None
+            } else {
+                Some(bytes as usize)
+            }
+        };
+        MemoryQuota {
+            grant_bytes: limit(grant_quota),
+            per_grant_bytes: limit(per_grant_quota),
+        }
+    }
+
+    /// Whether a process may use `grant_used` bytes of grant memory, of
+    /// which `used_by_grant` bytes are used by a single grant.
+    fn allows(&self, grant_used: usize, used_by_grant: usize) -> bool {
+        self.grant_bytes.map_or(true, |limit| grant_used <= limit)
+            && self
+                .per_grant_bytes
+                .map_or(true, |limit| used_by_grant <= limit)
+    }
+}
+
+/// Restarts faulted processes after a delay.
+///
+/// The kernel has no timer of its own, so a board that wants restart backoff
+/// provides one with `Kernel::set_restart_timer()`, typically
+/// `capsules::process_restarter::ProcessRestarter`.
+pub trait RestartTimer {
+    /// Call `ProcessType::restart()` on the process `appid` after `delay_ms`
+    /// milliseconds, if it is still stopped after its fault by then.
+    fn restart_after(&self, appid: AppId, delay_ms: u32);
+}
+
+/// Scheduling parameters for a process.
+///
+/// These are requested by the app in its TBF header, and schedulers use them
+/// to decide which process to run and for how long.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SchedulingParameters {
+    /// Priority of the process. Lower values are more important. Processes
+    /// that do not specify a priority have the lowest possible priority.
+    pub priority: u32,
+
+    /// Timeslice the process should be given, in microseconds. `None` means
+    /// the scheduler's default is used.
+    pub timeslice_us: Option<u32>,
+
+    /// CPU budget as `(budget_us, period_us)`: the process may use at most
+    /// `budget_us` of every `period_us` microseconds of process execution
+    /// time. `None` means the process is not limited.
+    pub cpu_budget: Option<(u32, u32)>,
+}
+
+impl Default for SchedulingParameters {
+    fn default() -> SchedulingParameters {
+        SchedulingParameters {
+            priority: core::u32::MAX,
+            timeslice_us: None,
+            cpu_budget: None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum IPCType {
     Service,
@@ -401,9 +780,25 @@ struct ProcessDebug {
     /// kernel has restarted it.
     restart_count: usize,
 
+    /// How many grant allocations were refused because of the process's
+    /// `MemoryQuota`.
+    grant_quota_exceeded_count: usize,
+
+    /// How many system calls were refused because of the process's
+    /// permissions.
+    denied_syscall_count: usize,
+
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// How long the process has executed, in microseconds. Unlike the counts
+    /// above, this is not reset when the process restarts.
+    cpu_time_us: u64,
+
+    /// How long the kernel has taken to handle each class of system call,
+    /// indexed by `SyscallClass`.
+    syscall_latency: [SyscallLatency; 6],
 }
 
 pub struct Process<'a, C: 'static + Chip> {
@@ -412,6 +807,15 @@ pub struct Process<'a, C: 'static + Chip> {
     /// Corresponds to AppId
     app_idx: usize,
 
+    /// Which instance of the process this is. This changes every time the
+    /// process is restarted, so that `AppId`s of the earlier instance are
+    /// rejected.
+    generation: Cell<usize>,
+
+    /// Identifier of the application that does not change when it is
+    /// restarted or reflashed.
+    stable_id: u32,
+
     /// Pointer to the main Kernel struct.
     kernel: &'static Kernel,
 
@@ -456,6 +860,13 @@ pub struct Process<'a, C: 'static + Chip> {
     /// the kernel_memory break to without having to recalculate it.
     original_kernel_memory_break: *const u8,
 
+    /// Bytes of grant memory allocated on behalf of each grant, indexed by
+    /// grant number. Stored below the grant pointers in process memory.
+    grant_usage: &'a [Cell<usize>],
+
+    /// Limits on the grant memory of the process.
+    memory_quota: Cell<MemoryQuota>,
+
     /// Pointer to the end of process RAM that has been sbrk'd to the process.
     app_break: Cell<*const u8>,
     original_app_break: *const u8,
@@ -475,6 +886,9 @@ pub struct Process<'a, C: 'static + Chip> {
     /// Collection of pointers to the TBF header in flash.
     header: tbfheader::TbfHeader,
 
+    /// Whether the app's credentials authenticated the TBF header.
+    header_authenticated: bool,
+
     /// State saved on behalf of the process each time the app switches to the
     /// kernel.
     stored_state:
@@ -484,7 +898,16 @@ pub struct Process<'a, C: 'static + Chip> {
     state: Cell<State>,
 
     /// How to deal with Faults occurring in the process
-    fault_response: FaultResponse,
+    fault_policy: Cell<FaultPolicy>,
+
+    /// How the process asked to be scheduled.
+    scheduling: SchedulingParameters,
+
+    /// CPU time used by the process in the current budget period.
+    cpu_budget_used_us: Cell<u32>,
+
+    /// Process execution time elapsed in the current budget period.
+    cpu_budget_period_elapsed_us: Cell<u32>,
 
     /// Configuration data for the MPU
     mpu_config: MapCell<<<C as Chip>::MPU as MPU>::MpuConfig>,
@@ -505,13 +928,18 @@ pub struct Process<'a, C: 'static + Chip> {
 
 impl<C: Chip> ProcessType for Process<'a, C> {
     fn appid(&self) -> AppId {
-        AppId::new(self.kernel, self.app_idx)
+        AppId::new(
+            self.kernel,
+            self.app_idx,
+            self.generation.get(),
+            self.stable_id,
+        )
     }
 
     fn enqueue_task(&self, task: Task) -> bool {
-        // If this app is in the `Fault` state then we shouldn't schedule
-        // any work for it.
-        if self.state.get() == State::Fault {
+        // If this app is in the `Fault` or `Terminated` state then we
+        // shouldn't schedule any work for it.
+        if self.state.get() == State::Fault || self.state.get() == State::Terminated {
             return false;
         }
 
@@ -544,21 +972,75 @@ impl<C: Chip> ProcessType for Process<'a, C> {
             });
             if config::CONFIG.trace_syscalls {
                 let count_after = tasks.len();
-                debug!(
-                    "[{}] remove_pending_callbacks[{:#x}:{}] = {} callback(s) removed",
-                    self.app_idx,
-                    callback_id.driver_num,
+                self.kernel.trace(
+                    self.appid(),
+                    TraceEvent::CallbacksRemoved,
+                    Some(callback_id.driver_num),
                     callback_id.subscribe_num,
+                    [0; 4],
                     count_before - count_after,
                 );
             }
         });
     }
 
+    fn ready(&self) -> bool {
+        match self.state.get() {
+            State::Running => true,
+            State::Yielded | State::Unstarted => {
+                self.tasks.map_or(false, |tasks| tasks.has_elements())
+            }
+            _ => false,
+        }
+    }
+
     fn get_state(&self) -> State {
         self.state.get()
     }
 
+    fn get_scheduling_parameters(&self) -> SchedulingParameters {
+        self.scheduling
+    }
+
+    fn cpu_budget_exhausted(&self) -> bool {
+        // A budget smaller than the minimum quantum the kernel will schedule
+        // cannot be used, so treat it as exhausted.
+        self.cpu_budget_remaining().map_or(false, |remaining| {
+            remaining < sched::MIN_QUANTA_THRESHOLD_US
+        })
+    }
+
+    fn cpu_budget_remaining(&self) -> Option<u32> {
+        self.scheduling
+            .cpu_budget
+            .map(|(budget, _)| budget.saturating_sub(self.cpu_budget_used_us.get()))
+    }
+
+    fn update_cpu_budget(&self, elapsed_us: u32, used_us: u32) {
+        if let Some((_, period)) = self.scheduling.cpu_budget {
+            let elapsed = self
+                .cpu_budget_period_elapsed_us
+                .get()
+                .saturating_add(elapsed_us);
+            if elapsed >= period {
+                // Start a new period, charged with the part of this execution
+                // that falls after the end of the previous one.
+                let overrun = elapsed.checked_rem(period).unwrap_or(0);
+                self.cpu_budget_period_elapsed_us.set(overrun);
+                self.cpu_budget_used_us.set(cmp::min(used_us, overrun));
+            } else {
+                self.cpu_budget_period_elapsed_us.set(elapsed);
+                self.cpu_budget_used_us
+                    .set(self.cpu_budget_used_us.get().saturating_add(used_us));
+            }
+        }
+    }
+
+    fn replenish_cpu_budget(&self) {
+        self.cpu_budget_used_us.set(0);
+        self.cpu_budget_period_elapsed_us.set(0);
+    }
+
     fn set_yielded_state(&self) {
         if self.state.get() == State::Running {
             self.state.set(State::Yielded);
@@ -585,131 +1067,197 @@ impl<C: Chip> ProcessType for Process<'a, C> {
     fn set_fault_state(&self) {
         self.state.set(State::Fault);
 
-        match self.fault_response {
+        let policy = self.fault_policy.get();
+        let restart_count = self.debug_restart_count();
+        let response = policy.response_after(restart_count);
+        // A kernel panic saves a crash dump of its own.
+        if response != FaultResponse::Panic {
+            unsafe { crash_dump::record_fault(self) };
+        }
+        match response {
             FaultResponse::Panic => {
                 // process faulted. Panic and print status
                 panic!("Process {} had a fault", self.process_name);
             }
             FaultResponse::Restart => {
-                // Remove the tasks that were scheduled for the app from the
-                // amount of work queue.
-                let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
-                for _ in 0..tasks_len {
-                    self.kernel.decrement_work();
+                let delay_ms = policy.restart_delay_ms(restart_count);
+                match self.kernel.get_restart_timer() {
+                    Some(timer) if delay_ms > 0 => {
+                        // Keep the process stopped until the timer restarts
+                        // it.
+                        self.stop_faulted();
+                        timer.restart_after(self.appid(), delay_ms);
+                    }
+                    _ => self.restart(),
                 }
+            }
+            FaultResponse::Stop => {
+                self.stop_faulted();
+            }
+        }
+    }
 
-                // And remove those tasks
-                self.tasks.map(|tasks| {
-                    tasks.empty();
-                });
+    fn get_fault_policy(&self) -> FaultPolicy {
+        self.fault_policy.get()
+    }
 
-                // Update debug information
-                self.debug.map(|debug| {
-                    // Mark that we restarted this process.
-                    debug.restart_count += 1;
+    fn set_fault_policy(&self, policy: FaultPolicy) {
+        self.fault_policy.set(policy);
+    }
 
-                    // Reset some state for the process.
-                    debug.syscall_count = 0;
-                    debug.last_syscall = None;
-                    debug.dropped_callback_count = 0;
-                });
+    fn get_memory_quota(&self) -> MemoryQuota {
+        self.memory_quota.get()
+    }
 
-                // We are going to start this process over again, so need
-                // the init_fn location.
-                let app_flash_address = self.flash_start();
-                let init_fn = unsafe {
-                    app_flash_address.offset(self.header.get_init_function_offset() as isize)
-                        as usize
-                };
-                self.state.set(State::Unstarted);
-
-                // Need to reset the grant region.
-                unsafe {
-                    self.grant_ptrs_reset();
-                }
-                self.kernel_memory_break
-                    .set(self.original_kernel_memory_break);
-
-                // Reset other memory pointers.
-                self.app_break.set(self.original_app_break);
-                self.current_stack_pointer.set(self.original_stack_pointer);
-                self.allow_high_water_mark
-                    .set(self.original_allow_high_water_mark);
-
-                // Handle any architecture-specific requirements for a process
-                // when it first starts (as it would when it is new).
-                let mut stored_state = self.stored_state.get();
-                let new_stack_pointer_res = unsafe {
-                    self.chip.userspace_kernel_boundary().initialize_process(
-                        self.sp(),
-                        self.sp() as usize - self.memory.as_ptr() as usize,
-                        &mut stored_state,
-                    )
-                };
-                match new_stack_pointer_res {
-                    Ok(new_stack_pointer) => {
-                        self.current_stack_pointer.set(new_stack_pointer as *mut u8);
-                        self.debug_set_max_stack_depth();
-                        self.stored_state.set(stored_state);
-                    }
-                    Err(_) => {
-                        // We couldn't initialize the architecture-specific
-                        // state for this process. This shouldn't happen since
-                        // the app was able to be started before, but at this
-                        // point the app is no longer valid. The best thing we
-                        // can do now is mark the app as still faulted and not
-                        // schedule it.
-                        self.state.set(State::Fault);
-                        return;
-                    }
-                };
-
-                // And queue up this app to be restarted.
-                let flash_protected_size = self.header.get_protected_size() as usize;
-                let flash_app_start = app_flash_address as usize + flash_protected_size;
-
-                self.tasks.map(|tasks| {
-                    tasks.empty();
-                    tasks.enqueue(Task::FunctionCall(FunctionCall {
-                        source: FunctionCallSource::Kernel,
-                        pc: init_fn,
-                        argument0: flash_app_start,
-                        argument1: self.memory.as_ptr() as usize,
-                        argument2: self.memory.len() as usize,
-                        argument3: self.app_break.get() as usize,
-                    }));
-                });
+    fn set_memory_quota(&self, quota: MemoryQuota) {
+        self.memory_quota.set(quota);
+    }
 
-                self.kernel.increment_work();
-            }
-            FaultResponse::Stop => {
-                // This looks a lot like restart, except we just leave the app
-                // how it faulted and mark it as `StoppedFaulted`. By clearing
-                // all of the app's todo work it will not be scheduled, and
-                // clearing all of the grant regions will cause capsules to drop
-                // this app as well.
-
-                // Remove the tasks that were scheduled for the app from the
-                // amount of work queue.
-                let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
-                for _ in 0..tasks_len {
-                    self.kernel.decrement_work();
-                }
+    fn restart(&self) {
+        // A running process counts as outstanding work. `set_fault_state()`
+        // marks the process faulted before restarting it, so this only
+        // applies when a process is restarted explicitly.
+        match self.state.get() {
+            State::Running | State::StoppedRunning => self.kernel.decrement_work(),
+            _ => {}
+        }
 
-                // And remove those tasks
-                self.tasks.map(|tasks| {
-                    tasks.empty();
-                });
+        // Remove the tasks that were scheduled for the app from the
+        // amount of work queue.
+        let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
+        for _ in 0..tasks_len {
+            self.kernel.decrement_work();
+        }
+
+        // And remove those tasks
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+
+        // Update debug information
+        self.debug.map(|debug| {
+            // Mark that we restarted this process.
+            debug.restart_count += 1;
+
+            // Reset some state for the process.
+            debug.syscall_count = 0;
+            debug.last_syscall = None;
+            debug.dropped_callback_count = 0;
+            debug.grant_quota_exceeded_count = 0;
+            debug.denied_syscall_count = 0;
+            debug.cpu_time_us = 0;
+            debug.syscall_latency = [SyscallLatency::default(); 6];
+        });
+
+        // The new instance starts with a full CPU budget and a fresh period.
+        self.replenish_cpu_budget();
 
-                // Clear any grant regions this app has setup with any capsules.
-                unsafe {
-                    self.grant_ptrs_reset();
+        // Nor does it keep access to the memory other processes shared with
+        // the old instance.
+        self.mpu_config.map(|config| {
+            for slot in self.mpu_regions.iter() {
+                if let Some(region) = slot.take() {
+                    let _ = self.chip.mpu().remove_memory_region(region, config);
                 }
+            }
+        });
 
-                // Mark the app as stopped so the scheduler won't try to run it.
-                self.state.set(State::StoppedFaulted);
+        // We are going to start this process over again, so need
+        // the init_fn location.
+        let app_flash_address = self.flash_start();
+        let init_fn = unsafe {
+            app_flash_address.offset(self.header.get_init_function_offset() as isize) as usize
+        };
+        self.state.set(State::Unstarted);
+
+        // Need to reset the grant region, and let capsules drop any other
+        // state they hold for the old instance of the process.
+        unsafe {
+            self.grant_ptrs_reset();
+        }
+        self.kernel.grant_cleanup(self.appid());
+
+        // From here on this is a new instance of the process, and `AppId`s
+        // that capsules still hold for the old one no longer work.
+        self.generation.set(self.kernel.next_app_generation());
+        self.kernel_memory_break
+            .set(self.original_kernel_memory_break);
+
+        // Reset other memory pointers.
+        self.app_break.set(self.original_app_break);
+        self.current_stack_pointer.set(self.original_stack_pointer);
+        self.allow_high_water_mark
+            .set(self.original_allow_high_water_mark);
+
+        // Handle any architecture-specific requirements for a process
+        // when it first starts (as it would when it is new).
+        let mut stored_state = self.stored_state.get();
+        let new_stack_pointer_res = unsafe {
+            self.chip.userspace_kernel_boundary().initialize_process(
+                self.sp(),
+                self.sp() as usize - self.memory.as_ptr() as usize,
+                &mut stored_state,
+            )
+        };
+        match new_stack_pointer_res {
+            Ok(new_stack_pointer) => {
+                self.current_stack_pointer.set(new_stack_pointer as *mut u8);
+                self.debug_set_max_stack_depth();
+                self.stored_state.set(stored_state);
+            }
+            Err(_) => {
+                // We couldn't initialize the architecture-specific
+                // state for this process. This shouldn't happen since
+                // the app was able to be started before, but at this
+                // point the app is no longer valid. The best thing we
+                // can do now is mark the app as still faulted and not
+                // schedule it.
+                self.state.set(State::Fault);
+                return;
             }
+        };
+
+        // And queue up this app to be restarted.
+        let flash_protected_size = self.header.get_protected_size() as usize;
+        let flash_app_start = app_flash_address as usize + flash_protected_size;
+
+        self.tasks.map(|tasks| {
+            tasks.empty();
+            tasks.enqueue(Task::FunctionCall(FunctionCall {
+                source: FunctionCallSource::Kernel,
+                pc: init_fn,
+                argument0: flash_app_start,
+                argument1: self.memory.as_ptr() as usize,
+                argument2: self.memory.len() as usize,
+                argument3: self.app_break.get() as usize,
+            }));
+        });
+
+        self.kernel.increment_work();
+    }
+
+    fn terminate(&self) {
+        // A running process counts as outstanding work, as does each of its
+        // queued tasks.
+        match self.state.get() {
+            State::Running | State::StoppedRunning => self.kernel.decrement_work(),
+            _ => {}
+        }
+        let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
+        for _ in 0..tasks_len {
+            self.kernel.decrement_work();
         }
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+
+        // Clear any grant regions this app has setup with any capsules.
+        unsafe {
+            self.grant_ptrs_reset();
+        }
+        self.kernel.grant_cleanup(self.appid());
+
+        self.state.set(State::Terminated);
     }
 
     fn dequeue_task(&self) -> Option<Task> {
@@ -784,13 +1332,14 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         unallocated_memory_start: *const u8,
         unallocated_memory_size: usize,
         min_region_size: usize,
+        permissions: mpu::Permissions,
     ) -> Option<mpu::Region> {
         self.mpu_config.and_then(|mut config| {
             let new_region = self.chip.mpu().allocate_region(
                 unallocated_memory_start,
                 unallocated_memory_size,
                 min_region_size,
-                mpu::Permissions::ReadWriteOnly,
+                permissions,
                 &mut config,
             );
 
@@ -810,6 +1359,19 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         })
     }
 
+    fn remove_mpu_region(&self, region: mpu::Region) -> Result<(), ()> {
+        self.mpu_config.map_or(Err(()), |config| {
+            let slot = self
+                .mpu_regions
+                .iter()
+                .find(|slot| slot.get() == Some(region))
+                .ok_or(())?;
+            self.chip.mpu().remove_memory_region(region, config)?;
+            slot.set(None);
+            Ok(())
+        })
+    }
+
     fn sbrk(&self, increment: isize) -> Result<*const u8, Error> {
         let new_break = unsafe { self.app_break.get().offset(increment) };
         self.brk(new_break)
@@ -862,15 +1424,57 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         }
     }
 
-    unsafe fn alloc(&self, size: usize, align: usize) -> Option<&mut [u8]> {
+    fn allow_readonly(
+        &self,
+        buf_start_addr: *const u8,
+        size: usize,
+    ) -> Result<Option<ReadOnlyAppSlice<u8>>, ReturnCode> {
+        if buf_start_addr == ptr::null() {
+            // A null buffer means pass in `None` to the capsule
+            Ok(None)
+        } else if self.in_app_owned_memory(buf_start_addr, size) {
+            // The same as for `allow`, the app's watermark covers the buffer
+            let buf_end_addr = buf_start_addr.wrapping_add(size);
+            let new_water_mark = max(self.allow_high_water_mark.get(), buf_end_addr);
+            self.allow_high_water_mark.set(new_water_mark);
+            Ok(Some(ReadOnlyAppSlice::new(
+                buf_start_addr,
+                size,
+                self.appid(),
+            )))
+        } else if self.in_app_flash(buf_start_addr, size) {
+            Ok(Some(ReadOnlyAppSlice::new(
+                buf_start_addr,
+                size,
+                self.appid(),
+            )))
+        } else {
+            Err(ReturnCode::EINVAL)
+        }
+    }
+
+    unsafe fn alloc(&self, size: usize, align: usize, grant_num: usize) -> Option<&mut [u8]> {
         self.mpu_config.and_then(|mut config| {
             let new_break_unaligned = self.kernel_memory_break.get().offset(-(size as isize));
             // The alignment must be a power of two, 2^a. The expression `!(align - 1)` then
             // returns a mask with leading ones, followed by `a` trailing zeros.
             let alignment_mask = !(align - 1);
             let new_break = (new_break_unaligned as usize & alignment_mask) as *const u8;
+            // Charge the alignment padding to the grant as well.
+            let charged =
+                (self.kernel_memory_break.get() as usize).wrapping_sub(new_break as usize);
+            let used_by_grant = self.grant_memory_used_by(grant_num) + charged;
             if new_break < self.app_break.get() {
                 None
+            } else if !self
+                .memory_quota
+                .get()
+                .allows(self.grant_memory_used() + charged, used_by_grant)
+            {
+                self.debug.map(|debug| {
+                    debug.grant_quota_exceeded_count += 1;
+                });
+                None
             } else if let Err(_) = self.chip.mpu().update_app_memory_region(
                 self.app_break.get(),
                 new_break,
@@ -880,6 +1484,9 @@ impl<C: Chip> ProcessType for Process<'a, C> {
                 None
             } else {
                 self.kernel_memory_break.set(new_break);
+                self.grant_usage
+                    .get(grant_num)
+                    .map(|usage| usage.set(used_by_grant));
                 Some(slice::from_raw_parts_mut(new_break as *mut u8, size))
             }
         })
@@ -887,6 +1494,16 @@ impl<C: Chip> ProcessType for Process<'a, C> {
 
     unsafe fn free(&self, _: *mut u8) {}
 
+    fn grant_memory_used(&self) -> usize {
+        self.original_kernel_memory_break as usize - self.kernel_memory_break.get() as usize
+    }
+
+    fn grant_memory_used_by(&self, grant_num: usize) -> usize {
+        self.grant_usage
+            .get(grant_num)
+            .map_or(0, |usage| usage.get())
+    }
+
     #[allow(clippy::cast_ptr_alignment)]
     unsafe fn grant_ptr(&self, grant_num: usize) -> *mut *mut u8 {
         let grant_num = grant_num as isize;
@@ -897,6 +1514,18 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         self.process_name
     }
 
+    fn ipc_allows_client(&self, client: &str) -> Option<bool> {
+        self.header.ipc_allows_client(client)
+    }
+
+    fn header_allows_syscall(&self, driver_number: usize, command: Option<usize>) -> Option<bool> {
+        self.header.allows_syscall(driver_number, command)
+    }
+
+    fn header_authenticated(&self) -> bool {
+        self.header_authenticated
+    }
+
     unsafe fn set_syscall_return_value(&self, return_value: isize) {
         let mut stored_state = self.stored_state.get();
         self.chip
@@ -997,6 +1626,19 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         self.debug.map_or(0, |debug| debug.dropped_callback_count)
     }
 
+    fn debug_grant_quota_exceeded_count(&self) -> usize {
+        self.debug
+            .map_or(0, |debug| debug.grant_quota_exceeded_count)
+    }
+
+    fn debug_denied_syscall_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.denied_syscall_count)
+    }
+
+    fn debug_syscall_denied(&self) {
+        self.debug.map(|debug| debug.denied_syscall_count += 1);
+    }
+
     fn debug_restart_count(&self) -> usize {
         self.debug.map_or(0, |debug| debug.restart_count)
     }
@@ -1015,6 +1657,25 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         self.debug.map(|debug| debug.syscall_count += 1);
     }
 
+    fn debug_cpu_time_us(&self) -> u64 {
+        self.debug.map_or(0, |debug| debug.cpu_time_us)
+    }
+
+    fn debug_ran_for(&self, us: u32) {
+        self.debug.map(|debug| debug.cpu_time_us += us as u64);
+    }
+
+    fn debug_syscall_latency(&self, class: SyscallClass) -> SyscallLatency {
+        self.debug.map_or(SyscallLatency::default(), |debug| {
+            debug.syscall_latency[class as usize]
+        })
+    }
+
+    fn debug_syscall_handled(&self, class: SyscallClass, latency_us: u32) {
+        self.debug
+            .map(|debug| debug.syscall_latency[class as usize].record(latency_us));
+    }
+
     unsafe fn print_memory_map(&self, writer: &mut dyn Write) {
         // Flash
         let flash_end = self.flash.as_ptr().add(self.flash.len()) as usize;
@@ -1053,19 +1714,50 @@ impl<C: Chip> ProcessType for Process<'a, C> {
             "\
              App: {}   -   [{:?}]\
              \r\n Events Queued: {}   Syscall Count: {}   Dropped Callback Count: {}\
-             \n Restart Count: {}\n",
+             \n Restart Count: {}   Stable ID: {:#010x}   Generation: {}\n",
             self.process_name,
             self.state.get(),
             events_queued,
             syscall_count,
             dropped_callback_count,
             restart_count,
+            self.stable_id,
+            self.generation.get(),
         ));
 
         let _ = match last_syscall {
             Some(syscall) => writer.write_fmt(format_args!(" Last Syscall: {:?}", syscall)),
             None => writer.write_str(" Last Syscall: None"),
         };
+        let _ = writer.write_fmt(format_args!(
+            "   Denied Syscalls: {}",
+            self.debug_denied_syscall_count()
+        ));
+
+        // Grant memory accounting. Quotas of `None` are shown as "-".
+        let quota = self.memory_quota.get();
+        let _ = writer.write_fmt(format_args!(
+            "\r\n Grant Memory: {} bytes   Quota: ",
+            self.grant_memory_used(),
+        ));
+        let _ = match quota.grant_bytes {
+            Some(limit) => writer.write_fmt(format_args!("{}", limit)),
+            None => writer.write_str("-"),
+        };
+        let _ = writer.write_str("   Per-Grant Quota: ");
+        let _ = match quota.per_grant_bytes {
+            Some(limit) => writer.write_fmt(format_args!("{}", limit)),
+            None => writer.write_str("-"),
+        };
+        let _ = writer.write_fmt(format_args!(
+            "   Quota Exceeded: {}\r\n Grant Usage:",
+            self.debug_grant_quota_exceeded_count(),
+        ));
+        for (grant_num, usage) in self.grant_usage.iter().enumerate() {
+            if usage.get() > 0 {
+                let _ = writer.write_fmt(format_args!(" {}:{}", grant_num, usage.get()));
+            }
+        }
 
         let _ = writer.write_fmt(format_args!(
             "\
@@ -1209,6 +1901,14 @@ fn exceeded_check(size: usize, allocated: usize) -> &'static str {
     }
 }
 
+/// Stable identifier for an app whose TBF header does not set one: the 32-bit
+/// FNV-1a hash of its package name.
+fn hash_package_name(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
 impl<C: 'static + Chip> Process<'a, C> {
     #[allow(clippy::cast_ptr_alignment)]
     crate unsafe fn create(
@@ -1218,14 +1918,16 @@ impl<C: 'static + Chip> Process<'a, C> {
         remaining_app_memory: *mut u8,
         remaining_app_memory_size: usize,
         fault_response: FaultResponse,
+        header_authenticated: bool,
         index: usize,
     ) -> (Option<&'static dyn ProcessType>, usize, usize) {
         if let Some(tbf_header) = tbfheader::parse_and_validate_tbf_header(app_flash_address) {
             let app_flash_size = tbf_header.get_total_size() as usize;
 
             // If this isn't an app (i.e. it is padding) or it is an app but it
-            // isn't enabled, then we can skip it but increment past its flash.
-            if !tbf_header.is_app() || !tbf_header.enabled() {
+            // isn't enabled or its header is malformed, then we can skip it but
+            // increment past its flash.
+            if !tbf_header.is_app() || !tbf_header.enabled() || tbf_header.is_malformed() {
                 return (None, app_flash_size, 0);
             }
 
@@ -1258,6 +1960,9 @@ impl<C: 'static + Chip> Process<'a, C> {
             let grant_ptrs_num = kernel.get_grant_count_and_finalize();
             let grant_ptrs_offset = grant_ptrs_num * grant_ptr_size;
 
+            // Make room for the grant memory accounting.
+            let grant_usage_offset = grant_ptrs_num * mem::size_of::<Cell<usize>>();
+
             // Allocate memory for callback ring buffer.
             let callback_size = mem::size_of::<Task>();
             let callback_len = 10;
@@ -1269,7 +1974,7 @@ impl<C: 'static + Chip> Process<'a, C> {
             // Initial sizes of the app-owned and kernel-owned parts of process memory.
             // Provide the app with plenty of initial process accessible memory.
             let initial_kernel_memory_size =
-                grant_ptrs_offset + callbacks_offset + process_struct_offset;
+                grant_ptrs_offset + grant_usage_offset + callbacks_offset + process_struct_offset;
             let initial_app_memory_size = 3 * 1024;
 
             if min_app_ram_size < initial_app_memory_size {
@@ -1320,6 +2025,14 @@ impl<C: 'static + Chip> Process<'a, C> {
                 *opt = ptr::null()
             }
 
+            // Below the grant pointers goes how much memory each grant uses.
+            kernel_memory_break = kernel_memory_break.offset(-(grant_usage_offset as isize));
+            let grant_usage =
+                slice::from_raw_parts_mut(kernel_memory_break as *mut Cell<usize>, grant_ptrs_num);
+            for usage in grant_usage.iter_mut() {
+                ptr::write(usage, Cell::new(0));
+            }
+
             // Now that we know we have the space we can setup the memory
             // for the callbacks.
             kernel_memory_break = kernel_memory_break.offset(-(callbacks_offset as isize));
@@ -1344,12 +2057,24 @@ impl<C: 'static + Chip> Process<'a, C> {
                 &mut *(process_struct_memory_location as *mut Process<'static, C>);
 
             process.app_idx = index;
+            process.generation = Cell::new(kernel.next_app_generation());
+            process.stable_id = tbf_header
+                .get_stable_id()
+                .unwrap_or_else(|| hash_package_name(process_name));
             process.kernel = kernel;
             process.chip = chip;
             process.memory = app_memory;
             process.header = tbf_header;
+            process.header_authenticated = header_authenticated;
             process.kernel_memory_break = Cell::new(kernel_memory_break);
             process.original_kernel_memory_break = kernel_memory_break;
+            process.grant_usage = grant_usage;
+            process.memory_quota = Cell::new(process.header.get_memory_quota().map_or(
+                MemoryQuota::default(),
+                |(grant_quota, per_grant_quota)| {
+                    MemoryQuota::from_tbf(grant_quota, per_grant_quota)
+                },
+            ));
             process.app_break = Cell::new(initial_sbrk_pointer);
             process.original_app_break = initial_sbrk_pointer;
             process.allow_high_water_mark = Cell::new(remaining_app_memory);
@@ -1361,7 +2086,45 @@ impl<C: 'static + Chip> Process<'a, C> {
 
             process.stored_state = Cell::new(Default::default());
             process.state = Cell::new(State::Unstarted);
-            process.fault_response = fault_response;
+            process.fault_policy = Cell::new(
+                process
+                    .header
+                    .get_fault_policy()
+                    .and_then(
+                        |(response, restart_limit, limit_response, backoff_ms, max_backoff_ms)| {
+                            Some(FaultPolicy {
+                                response: FaultResponse::from_tbf(response)?,
+                                restart_limit: if restart_limit == 0 {
+                                    None
+                                } else {
+                                    Some(restart_limit as usize)
+                                },
+                                limit_response: FaultResponse::from_tbf(limit_response)?,
+                                backoff_ms: backoff_ms,
+                                max_backoff_ms: max_backoff_ms,
+                            })
+                        },
+                    )
+                    .unwrap_or_else(|| FaultPolicy::from(fault_response)),
+            );
+            process.scheduling = process.header.get_scheduling_parameters().map_or(
+                SchedulingParameters::default(),
+                |(priority, timeslice_us, budget_us, period_us)| SchedulingParameters {
+                    priority: priority,
+                    timeslice_us: if timeslice_us == 0 {
+                        None
+                    } else {
+                        Some(timeslice_us)
+                    },
+                    cpu_budget: if budget_us == 0 || period_us == 0 {
+                        None
+                    } else {
+                        Some((budget_us, period_us))
+                    },
+                },
+            );
+            process.cpu_budget_used_us = Cell::new(0);
+            process.cpu_budget_period_elapsed_us = Cell::new(0);
 
             process.mpu_config = MapCell::new(mpu_config);
             process.mpu_regions = [
@@ -1383,7 +2146,11 @@ impl<C: 'static + Chip> Process<'a, C> {
                 last_syscall: None,
                 dropped_callback_count: 0,
                 restart_count: 0,
+                grant_quota_exceeded_count: 0,
+                denied_syscall_count: 0,
                 timeslice_expiration_count: 0,
+                cpu_time_us: 0,
+                syscall_latency: [SyscallLatency::default(); 6],
             });
 
             let flash_protected_size = process.header.get_protected_size() as usize;
@@ -1449,7 +2216,47 @@ impl<C: 'static + Chip> Process<'a, C> {
             && buf_end_addr <= self.app_break.get()
     }
 
-    /// Reset all `grant_ptr`s to NULL.
+    /// Checks if the buffer represented by the passed in base pointer and size
+    /// is within the flash of the process, including its TBF header.
+    fn in_app_flash(&self, buf_start_addr: *const u8, size: usize) -> bool {
+        let buf_end_addr = buf_start_addr.wrapping_add(size);
+
+        buf_end_addr >= buf_start_addr
+            && buf_start_addr >= self.flash_start()
+            && buf_end_addr <= self.flash_end()
+    }
+
+    /// Leave a faulted process how it faulted and mark it as `StoppedFaulted`.
+    fn stop_faulted(&self) {
+        // This looks a lot like restart, except we just leave the app how it
+        // faulted and mark it as `StoppedFaulted`. By clearing all of the
+        // app's todo work it will not be scheduled, and clearing all of the
+        // grant regions will cause capsules to drop this app as well.
+
+        // Remove the tasks that were scheduled for the app from the amount of
+        // work queue.
+        let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
+        for _ in 0..tasks_len {
+            self.kernel.decrement_work();
+        }
+
+        // And remove those tasks
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+
+        // Clear any grant regions this app has setup with any capsules.
+        unsafe {
+            self.grant_ptrs_reset();
+        }
+        self.kernel.grant_cleanup(self.appid());
+
+        // Mark the app as stopped so the scheduler won't try to run it.
+        self.state.set(State::StoppedFaulted);
+    }
+
+    /// Reset all `grant_ptr`s to NULL and the grant memory accounting to
+    /// zero.
     #[allow(clippy::cast_ptr_alignment)]
     unsafe fn grant_ptrs_reset(&self) {
         let grant_ptrs_num = self.kernel.get_grant_count_and_finalize();
@@ -1458,6 +2265,9 @@ impl<C: 'static + Chip> Process<'a, C> {
             let ctr_ptr = (self.mem_end() as *mut *mut usize).offset(-(grant_num + 1));
             write_volatile(ctr_ptr, ptr::null_mut());
         }
+        for usage in self.grant_usage.iter() {
+            usage.set(0);
+        }
     }
 
     fn debug_set_max_stack_depth(&self) {
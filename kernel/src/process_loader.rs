@@ -0,0 +1,306 @@
+//! Support for loading and unloading processes while the kernel is running.
+//!
+//! `load_processes()` only runs once at boot. A `DynamicProcessLoader` lets a
+//! board load apps that were written to flash after boot (for example by
+//! `app_flash_driver` or a bootloader), and remove or replace running apps,
+//! without resetting the board. The loader is given the range of flash that
+//! holds apps and the app memory `load_processes()` did not use.
+//!
+//! All operations require the `ProcessManagementCapability`, so only trusted
+//! code (such as the process console) can trigger them.
+
+use core::cell::Cell;
+use core::mem;
+
+use crate::callback::AppId;
+use crate::capabilities::ProcessManagementCapability;
+use crate::credentials::CredentialsCheck;
+use crate::platform::Chip;
+use crate::process::{FaultResponse, Process};
+use crate::sched::Kernel;
+use crate::tbfheader;
+
+/// Reasons loading or unloading a process at runtime can fail.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProcessLoadError {
+    /// There is no process with the given `AppId`.
+    NoSuchApp,
+    /// No enabled app that is not already running was found in flash.
+    NoNewApp,
+    /// All process slots are in use.
+    NoFreeSlot,
+    /// There was not enough free memory to create the process.
+    OutOfMemory,
+    /// The board's `CredentialsChecker` rejected the app.
+    CredentialsRejected,
+    /// The kernel is still using a reference to a process, for example
+    /// because the request was made while handling a system call. The
+    /// process memory cannot be reused until it is done, so the request
+    /// should be retried from a callback.
+    Busy,
+}
+
+/// Interface for loading and unloading processes at runtime.
+///
+/// This is a trait so that users such as the process console do not need to
+/// know the type of the chip the loader is creating processes for.
+pub trait DynamicProcessLoading {
+    /// Scan the flash range for an enabled app that is not loaded, and load it
+    /// into a free process slot. Returns the `AppId` of the new process.
+    fn load_new_process(
+        &self,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<AppId, ProcessLoadError>;
+
+    /// Terminate a process, release its grant and process memory, and free
+    /// its process slot.
+    fn unload_process(
+        &self,
+        appid: AppId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ProcessLoadError>;
+
+    /// Replace a process with an app from flash with the same package name.
+    /// An app other than the running one is preferred, so that an updated
+    /// version written elsewhere in flash replaces the old one; otherwise the
+    /// app is reloaded from its current location, which picks up an image that
+    /// was rewritten in place. The replacement is loaded into the same process
+    /// slot and reuses the old process's memory if it fits.
+    fn replace_process(
+        &self,
+        appid: AppId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<AppId, ProcessLoadError>;
+}
+
+/// Loads processes from a range of flash into free process slots.
+pub struct DynamicProcessLoader<C: 'static + Chip> {
+    kernel: &'static Kernel,
+    chip: &'static C,
+    /// The flash range that is scanned for apps.
+    flash: &'static [u8],
+    /// Start of the app memory that is not used by any process.
+    app_memory_start: Cell<*mut u8>,
+    /// Length of the app memory that is not used by any process.
+    app_memory_size: Cell<usize>,
+    /// How faults in loaded processes are handled.
+    fault_response: FaultResponse,
+    /// How the credentials of apps are checked before they are loaded.
+    credentials_check: Option<CredentialsCheck>,
+}
+
+impl<C: 'static + Chip> DynamicProcessLoader<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        chip: &'static C,
+        flash: &'static [u8],
+        app_memory: &'static mut [u8],
+        fault_response: FaultResponse,
+        credentials_check: Option<CredentialsCheck>,
+    ) -> DynamicProcessLoader<C> {
+        DynamicProcessLoader {
+            kernel,
+            chip,
+            flash,
+            app_memory_start: Cell::new(app_memory.as_mut_ptr()),
+            app_memory_size: Cell::new(app_memory.len()),
+            fault_response,
+            credentials_check,
+        }
+    }
+
+    /// Ask the board's `CredentialsChecker`, if there is one, whether the app
+    /// at `app_flash` may be loaded. Returns whether the credentials of the
+    /// app authenticated its TBF header.
+    fn check_credentials(&self, app_flash: *const u8) -> Result<bool, ProcessLoadError> {
+        self.credentials_check.map_or(Ok(false), |check| unsafe {
+            match tbfheader::parse_and_validate_tbf_header(app_flash) {
+                Some(header) if check.app_accepted(app_flash, &header) => {
+                    Ok(check.authenticates_header(&header))
+                }
+                _ => Err(ProcessLoadError::CredentialsRejected),
+            }
+        })
+    }
+
+    /// Walk the TBF headers in the flash range and return the address of the
+    /// first enabled app that has the package name `name` (if given) and is
+    /// not loaded by any process other than the one in slot `ignore_index`.
+    fn find_app(&self, name: Option<&str>, ignore_index: Option<usize>) -> Option<*const u8> {
+        let flash_end = self.flash.as_ptr() as usize + self.flash.len();
+        let mut address = self.flash.as_ptr();
+
+        while address as usize + mem::size_of::<tbfheader::TbfHeaderV2Base>() <= flash_end {
+            let header = match unsafe { tbfheader::parse_and_validate_tbf_header(address) } {
+                Some(header) => header,
+                None => break,
+            };
+            let total_size = header.get_total_size() as usize;
+            if total_size == 0 || address as usize + total_size > flash_end {
+                break;
+            }
+
+            if header.is_app()
+                && header.enabled()
+                && !header.is_malformed()
+                && name.map_or(true, |name| header.get_package_name() == name)
+                && !self.is_loaded(address, ignore_index)
+            {
+                return Some(address);
+            }
+
+            address = unsafe { address.add(total_size) };
+        }
+        None
+    }
+
+    /// Check whether any process, other than the one in slot `ignore_index`,
+    /// was loaded from the app at `address`.
+    fn is_loaded(&self, address: *const u8, ignore_index: Option<usize>) -> bool {
+        let loaded = Cell::new(false);
+        self.kernel.process_each(|process| {
+            if process.flash_start() == address && Some(process.appid().idx()) != ignore_index {
+                loaded.set(true);
+            }
+        });
+        loaded.get()
+    }
+
+    /// Try to create a process for the app at `app_flash` in slot `index`,
+    /// first in `memory` (if given) and then in the free app memory.
+    unsafe fn load_into_slot(
+        &self,
+        app_flash: *const u8,
+        header_authenticated: bool,
+        index: usize,
+        memory: Option<(*mut u8, usize)>,
+    ) -> Result<AppId, ProcessLoadError> {
+        if let Some((memory_start, memory_size)) = memory {
+            let (process, _, _) = Process::create(
+                self.kernel,
+                self.chip,
+                app_flash,
+                memory_start,
+                memory_size,
+                self.fault_response,
+                header_authenticated,
+                index,
+            );
+            if let Some(process) = process {
+                self.kernel.set_process(index, Some(process));
+                return Ok(process.appid());
+            }
+            // The replacement does not fit, so give back the old memory.
+            self.reclaim_memory(memory_start, memory_size);
+        }
+
+        let (process, _, memory_offset) = Process::create(
+            self.kernel,
+            self.chip,
+            app_flash,
+            self.app_memory_start.get(),
+            self.app_memory_size.get(),
+            self.fault_response,
+            header_authenticated,
+            index,
+        );
+        match process {
+            Some(process) => {
+                self.app_memory_start
+                    .set(self.app_memory_start.get().add(memory_offset));
+                self.app_memory_size
+                    .set(self.app_memory_size.get() - memory_offset);
+                self.kernel.set_process(index, Some(process));
+                Ok(process.appid())
+            }
+            None => Err(ProcessLoadError::OutOfMemory),
+        }
+    }
+
+    /// Terminate the process in slot `index` and empty the slot. Returns the
+    /// memory region the process used.
+    fn teardown(&self, index: usize) -> Option<(*mut u8, usize)> {
+        let region = self.kernel.process_map_or(None, index, |process| {
+            process.terminate();
+            let start = process.mem_start();
+            let size = process.mem_end() as usize - start as usize;
+            Some((start as *mut u8, size))
+        });
+        self.kernel.set_process(index, None);
+        region
+    }
+
+    /// Return process memory to the free app memory. This is only possible
+    /// if the region is directly below the free memory; otherwise the memory
+    /// can only be reused by replacing a process in the same slot.
+    fn reclaim_memory(&self, start: *mut u8, size: usize) {
+        if start as usize + size == self.app_memory_start.get() as usize {
+            self.app_memory_start.set(start);
+            self.app_memory_size.set(self.app_memory_size.get() + size);
+        }
+    }
+}
+
+impl<C: 'static + Chip> DynamicProcessLoading for DynamicProcessLoader<C> {
+    fn load_new_process(
+        &self,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<AppId, ProcessLoadError> {
+        let index = self
+            .kernel
+            .free_process_slot()
+            .ok_or(ProcessLoadError::NoFreeSlot)?;
+        let app_flash = self
+            .find_app(None, None)
+            .ok_or(ProcessLoadError::NoNewApp)?;
+        let header_authenticated = self.check_credentials(app_flash)?;
+        unsafe { self.load_into_slot(app_flash, header_authenticated, index, None) }
+    }
+
+    fn unload_process(
+        &self,
+        appid: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ProcessLoadError> {
+        if self.kernel.process_refs_held() {
+            return Err(ProcessLoadError::Busy);
+        }
+        if !self.kernel.app_map_or(false, appid, |_| true) {
+            return Err(ProcessLoadError::NoSuchApp);
+        }
+        let (start, size) = self
+            .teardown(appid.idx())
+            .ok_or(ProcessLoadError::NoSuchApp)?;
+        self.reclaim_memory(start, size);
+        Ok(())
+    }
+
+    fn replace_process(
+        &self,
+        appid: AppId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<AppId, ProcessLoadError> {
+        if self.kernel.process_refs_held() {
+            return Err(ProcessLoadError::Busy);
+        }
+        let index = appid.idx();
+        let (name, current_flash) = self
+            .kernel
+            .app_map_or(None, appid, |process| {
+                Some((process.get_process_name(), process.flash_start()))
+            })
+            .ok_or(ProcessLoadError::NoSuchApp)?;
+
+        // Prefer a different copy of the app over the one that is running.
+        let app_flash = self
+            .find_app(Some(name), Some(index))
+            .filter(|&address| address != current_flash)
+            .or_else(|| self.find_app(Some(name), Some(index)))
+            .ok_or(ProcessLoadError::NoNewApp)?;
+        // Check the replacement before the running process is torn down.
+        let header_authenticated = self.check_credentials(app_flash)?;
+
+        let memory = self.teardown(index);
+        unsafe { self.load_into_slot(app_flash, header_authenticated, index, memory) }
+    }
+}
@@ -37,6 +37,10 @@ pub enum ReturnCode {
     EUNINSTALLED,
     /// Packet transmission not acknowledged
     ENOACK,
+    /// The process has no grant memory left for the operation
+    ENOGRANT,
+    /// The process is not permitted to use the driver or command
+    EPERM,
 }
 
 impl From<ReturnCode> for isize {
@@ -57,6 +61,8 @@ impl From<ReturnCode> for isize {
             ReturnCode::ENODEVICE => -11,
             ReturnCode::EUNINSTALLED => -12,
             ReturnCode::ENOACK => -13,
+            ReturnCode::ENOGRANT => -14,
+            ReturnCode::EPERM => -15,
         }
     }
 }
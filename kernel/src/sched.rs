@@ -1,36 +1,151 @@
 //! Tock core scheduler.
+//!
+//! The `Kernel` struct owns the process array and implements the mechanism for
+//! running a process (`do_process()`). Which process runs next, and for how
+//! long, is a policy decision delegated to an implementation of the
+//! `Scheduler` trait that the board passes to `kernel_loop()`.
+
+crate mod cooperative;
+crate mod priority;
+crate mod round_robin;
 
 use core::cell::Cell;
 use core::ptr::NonNull;
 
-use crate::callback::{Callback, CallbackId};
+use crate::callback::{AppId, Callback, CallbackId};
 use crate::capabilities;
-use crate::common::cells::NumericCellExt;
-use crate::common::dynamic_deferred_call::DynamicDeferredCall;
+use crate::common::cells::{NumericCellExt, OptionalCell};
+use crate::common::deferred_call;
+use crate::common::List;
 use crate::config;
-use crate::debug;
-use crate::grant::Grant;
+use crate::grant::{Grant, GrantCleanupHook};
 use crate::ipc;
 use crate::memop;
+use crate::permissions::{self, AppPermissions, DefaultPermission};
 use crate::platform::mpu::MPU;
 use crate::platform::systick::SysTick;
 use crate::platform::{Chip, Platform};
+use crate::power;
 use crate::process::{self, Task};
 use crate::returncode::ReturnCode;
 use crate::syscall::{ContextSwitchReason, Syscall};
+use crate::syscall_trace::{SyscallTrace, TraceEvent};
+use crate::watchdog::KernelWatchdog;
 
-/// The time a process is permitted to run before being pre-empted
-const KERNEL_TICK_DURATION_US: u32 = 10000;
 /// Skip re-scheduling a process if its quanta is nearly exhausted
-const MIN_QUANTA_THRESHOLD_US: u32 = 500;
+crate const MIN_QUANTA_THRESHOLD_US: u32 = 500;
+
+/// Length of the SysTick interval used to measure how long a process runs
+/// when it is not given a timeslice. This is short enough to fit in a 24-bit
+/// SysTick on fast clocks.
+const ACCOUNTING_WINDOW_US: u32 = 100_000;
+
+/// Trait which any scheduler must implement.
+///
+/// The kernel main loop asks the scheduler which process to run next and
+/// reports back why that process stopped executing. The mechanism of actually
+/// running a process is shared by all schedulers and lives in the kernel.
+pub trait Scheduler<C: Chip> {
+    /// Decide which process to run next.
+    ///
+    /// The scheduler must decide whether to run a process, and if so, which
+    /// one. If the scheduler chooses not to run a process, it can request that
+    /// the chip enter sleep mode.
+    ///
+    /// If the scheduler selects a process to run it must also specify the
+    /// timeslice for the process in microseconds, or `None` if the process
+    /// should run cooperatively until it yields.
+    fn next(&self, kernel: &Kernel) -> SchedulingDecision;
+
+    /// Inform the scheduler of why the last process stopped executing, and how
+    /// long it executed for. The execution time is only `Some` if the process
+    /// was given a timeslice.
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>);
+
+    /// Tell the scheduler to execute kernel work such as interrupt bottom
+    /// halves and deferred calls. Most schedulers will use this default
+    /// implementation.
+    unsafe fn execute_kernel_work(&self, chip: &C) {
+        chip.service_pending_interrupts();
+        deferred_call::service_while(|| !chip.has_pending_interrupts());
+    }
+
+    /// Ask the scheduler whether to take a break from executing userspace
+    /// processes to handle kernel tasks. Most schedulers will use this default
+    /// implementation, which always prioritizes kernel work, but schedulers
+    /// that wish to defer interrupt handling may reimplement it.
+    unsafe fn do_kernel_work_now(&self, chip: &C) -> bool {
+        chip.has_pending_interrupts() || deferred_call::has_tasks()
+    }
+
+    /// Ask the scheduler whether to continue trying to execute a process.
+    ///
+    /// Once a process is scheduled the kernel will try to execute it until it
+    /// has no more work to do or exhausts its timeslice. The kernel will call
+    /// this function before every loop to check with the scheduler if it wants
+    /// to continue trying to execute this process.
+    ///
+    /// Most schedulers will use this default implementation, which causes the
+    /// `do_process()` loop to return if there are interrupts or deferred calls
+    /// that need to be serviced.
+    unsafe fn continue_process(&self, _kernel: &Kernel, _appid: AppId, chip: &C) -> bool {
+        !(chip.has_pending_interrupts() || deferred_call::has_tasks())
+    }
+}
+
+/// Enum representing the actions the scheduler can request in each call to
+/// `scheduler.next()`.
+#[derive(Copy, Clone)]
+pub enum SchedulingDecision {
+    /// Tell the kernel to run the specified process with the passed timeslice.
+    /// If `None` is passed as a timeslice, the process will be run
+    /// cooperatively.
+    RunProcess((AppId, Option<u32>)),
+
+    /// Tell the kernel to go to sleep. Notably, if the scheduler asks the
+    /// kernel to sleep when kernel tasks are ready, the kernel will not sleep,
+    /// and will instead restart the main loop and call `next()` again.
+    TrySleep,
+}
+
+/// Enum used to inform the scheduler why a process stopped executing (aka why
+/// `do_process()` returned).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StoppedExecutingReason {
+    /// The process returned because it is no longer ready to run.
+    NoWorkLeft,
+
+    /// The process faulted, and the board restart policy was configured such
+    /// that it was not restarted and there was not a kernel panic.
+    StoppedFaulted,
+
+    /// The kernel stopped the process.
+    Stopped,
+
+    /// The process was preempted because its timeslice expired.
+    TimesliceExpired,
+
+    /// The process returned because it was preempted by the kernel. This can
+    /// mean that kernel work became ready (most likely because an interrupt
+    /// fired and the kernel thread needs to execute the bottom half of the
+    /// interrupt), or because the scheduler no longer wants to execute that
+    /// process.
+    KernelPreemption,
+}
 
 /// Main object for the kernel. Each board will need to create one.
 pub struct Kernel {
     /// How many "to-do" items exist at any given time. These include
     /// outstanding callbacks and processes in the Running state.
     work: Cell<usize>,
-    /// This holds a pointer to the static array of Process pointers.
-    processes: &'static [Option<&'static dyn process::ProcessType>],
+    /// This holds a pointer to the static array of Process pointers. The
+    /// slots are cells so that processes can be loaded and unloaded while the
+    /// kernel is running.
+    processes: &'static [Cell<Option<&'static dyn process::ProcessType>>],
+    /// How many references to processes are currently handed out to closures
+    /// by `process_map_or()` and friends. The memory of a process may only be
+    /// reused once no reference to it can remain.
+    process_refs: Cell<usize>,
     /// How many grant regions have been setup. This is incremented on every
     /// call to `create_grant()`. We need to explicitly track this so that when
     /// processes are created they can allocated pointers for each grant.
@@ -40,15 +155,190 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+    /// Capsules to notify when a process is torn down.
+    grant_cleanup_hooks: List<'static, GrantCleanupHook>,
+    /// Timer used to delay restarts of faulted processes.
+    restart_timer: OptionalCell<&'static dyn process::RestartTimer>,
+    /// Where system calls are recorded.
+    syscall_trace: OptionalCell<&'static SyscallTrace>,
+    /// Watchdog serviced by the kernel loop.
+    watchdog: OptionalCell<&'static dyn KernelWatchdog>,
+    /// How often the kernel loop has serviced pending interrupts.
+    interrupt_service_count: Cell<usize>,
+    /// How often the kernel loop has put the chip to sleep.
+    sleep_count: Cell<usize>,
+    /// Generation given to the next process that is loaded or restarted.
+    next_generation: Cell<usize>,
+    /// Drivers the board allows apps to use, in addition to the permissions
+    /// in the TBF headers of the apps.
+    syscall_permissions: Cell<&'static [AppPermissions]>,
+    /// What apps that are not listed in `syscall_permissions` and have no
+    /// permissions in their TBF headers may use.
+    default_permission: Cell<DefaultPermission>,
 }
 
 impl Kernel {
-    pub fn new(processes: &'static [Option<&'static dyn process::ProcessType>]) -> Kernel {
+    pub fn new(processes: &'static mut [Option<&'static dyn process::ProcessType>]) -> Kernel {
         Kernel {
             work: Cell::new(0),
-            processes: processes,
+            processes: Cell::from_mut(processes).as_slice_of_cells(),
+            process_refs: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            grant_cleanup_hooks: List::new(),
+            restart_timer: OptionalCell::empty(),
+            syscall_trace: OptionalCell::empty(),
+            watchdog: OptionalCell::empty(),
+            interrupt_service_count: Cell::new(0),
+            sleep_count: Cell::new(0),
+            next_generation: Cell::new(0),
+            syscall_permissions: Cell::new(&[]),
+            default_permission: Cell::new(DefaultPermission::AllowAll),
+        }
+    }
+
+    /// Set the timer used to wait before restarting faulted processes whose
+    /// `FaultPolicy` asks for a restart backoff. Without a timer, such
+    /// processes are restarted immediately.
+    pub fn set_restart_timer(&self, timer: &'static dyn process::RestartTimer) {
+        self.restart_timer.set(timer);
+    }
+
+    crate fn get_restart_timer(&self) -> Option<&'static dyn process::RestartTimer> {
+        self.restart_timer.map(|timer| *timer)
+    }
+
+    /// Set the buffer system calls and callbacks are recorded in. This has
+    /// no effect if `config::CONFIG.trace_syscalls` is disabled.
+    pub fn set_syscall_trace(&self, trace: &'static SyscallTrace) {
+        self.syscall_trace.set(trace);
+    }
+
+    /// Set the watchdog the kernel loop kicks on every iteration and suspends
+    /// while the chip sleeps.
+    pub fn set_watchdog(&self, watchdog: &'static dyn KernelWatchdog) {
+        self.watchdog.set(watchdog);
+    }
+
+    /// Add a record to the syscall trace, if there is one.
+    crate fn trace(
+        &self,
+        appid: AppId,
+        event: TraceEvent,
+        driver: Option<usize>,
+        subdriver: usize,
+        args: [usize; 4],
+        result: usize,
+    ) {
+        self.syscall_trace
+            .map(|trace| trace.record(appid, event, driver, subdriver, args, result));
+    }
+
+    /// Restrict the apps named in `permissions` to the drivers listed for
+    /// them, in addition to the permissions in the TBF headers of the apps.
+    /// `default` is what apps the board does not list may use.
+    pub fn set_syscall_permissions(
+        &self,
+        permissions: &'static [AppPermissions],
+        default: DefaultPermission,
+    ) {
+        self.syscall_permissions.set(permissions);
+        self.default_permission.set(default);
+    }
+
+    /// Check whether the permissions the board lists for `process` allow the
+    /// driver `driver_number` and, if `command` is given, that command of the
+    /// driver. Returns `None` if the board lists no permissions for the
+    /// process.
+    fn board_permission(
+        &self,
+        process: &dyn process::ProcessType,
+        driver_number: usize,
+        command: Option<usize>,
+    ) -> Option<bool> {
+        let name = process.get_process_name();
+        let mut board = self
+            .syscall_permissions
+            .get()
+            .iter()
+            .filter(|permissions| permissions.app == name)
+            .peekable();
+        if board.peek().is_none() {
+            None
+        } else {
+            Some(board.all(|permissions| permissions.allows(driver_number, command)))
+        }
+    }
+
+    /// Check whether the TBF header of `process` and the board permit it to
+    /// use the driver `driver_number` and, if `command` is given, that command
+    /// of the driver, with `default` for processes the board does not list.
+    fn permitted(
+        &self,
+        process: &dyn process::ProcessType,
+        driver_number: usize,
+        command: Option<usize>,
+        default: DefaultPermission,
+    ) -> bool {
+        permissions::permitted(
+            process.header_allows_syscall(driver_number, command),
+            process.header_authenticated(),
+            self.board_permission(process, driver_number, command),
+            default,
+        )
+    }
+
+    /// Check whether `process` was explicitly given permission to use the
+    /// driver `driver_number`, by the board or by an authenticated TBF
+    /// header. Unlike system calls, this is never allowed by
+    /// `DefaultPermission::AllowAll`.
+    crate fn driver_listed(
+        &self,
+        process: &dyn process::ProcessType,
+        driver_number: usize,
+    ) -> bool {
+        self.permitted(process, driver_number, None, DefaultPermission::DenyAll)
+    }
+
+    /// Check whether the board gave `process` permission to use the driver
+    /// `driver_number` with `set_syscall_permissions()`. The process's TBF
+    /// header is ignored, even if its credentials authenticated it.
+    crate fn board_listed(&self, process: &dyn process::ProcessType, driver_number: usize) -> bool {
+        self.board_permission(process, driver_number, None) == Some(true)
+    }
+
+    /// Check whether `process` may use the driver `driver_number` and, for
+    /// commands, the command `command`. System calls that are not permitted
+    /// are counted in the process's debug statistics.
+    fn syscall_permitted(
+        &self,
+        process: &dyn process::ProcessType,
+        driver_number: usize,
+        command: Option<usize>,
+    ) -> bool {
+        let permitted = self.permitted(
+            process,
+            driver_number,
+            command,
+            self.default_permission.get(),
+        );
+        if !permitted {
+            process.debug_syscall_denied();
+        }
+        permitted
+    }
+
+    /// Register a capsule to be told when a process is terminated, restarted,
+    /// or stopped after a fault, so that it can drop any state it holds for
+    /// that process outside of its grant.
+    pub fn register_grant_cleanup_hook(&self, hook: &'static GrantCleanupHook) {
+        self.grant_cleanup_hooks.push_tail(hook);
+    }
+
+    /// Notify all registered capsules that `appid` was torn down.
+    crate fn grant_cleanup(&self, appid: AppId) {
+        for hook in self.grant_cleanup_hooks.iter() {
+            hook.process_teardown(appid);
         }
     }
 
@@ -77,10 +367,52 @@ impl Kernel {
     where
         F: FnOnce(&dyn process::ProcessType) -> R,
     {
-        if process_index > self.processes.len() {
+        if process_index >= self.processes.len() {
             return default;
         }
-        self.processes[process_index].map_or(default, |process| closure(process))
+        self.processes[process_index]
+            .get()
+            .map_or(default, |process| self.with_process_ref(process, closure))
+    }
+
+    /// Run a closure on the process `appid` refers to. If that process no
+    /// longer exists, or it has been restarted or replaced since `appid` was
+    /// created, then `default` will be returned.
+    crate fn app_map_or<F, R>(&self, default: R, appid: AppId, closure: F) -> R
+    where
+        F: FnOnce(&dyn process::ProcessType) -> R,
+    {
+        match self.processes.get(appid.idx()).and_then(|slot| slot.get()) {
+            Some(process) if process.appid() == appid => self.with_process_ref(process, closure),
+            _ => default,
+        }
+    }
+
+    /// Run a closure on `process`, counting the reference the closure holds
+    /// while it runs.
+    fn with_process_ref<F, R>(&self, process: &'static dyn process::ProcessType, closure: F) -> R
+    where
+        F: FnOnce(&dyn process::ProcessType) -> R,
+    {
+        self.process_refs.set(self.process_refs.get() + 1);
+        let result = closure(process);
+        self.process_refs.set(self.process_refs.get() - 1);
+        result
+    }
+
+    /// Whether a closure is currently running on a reference to a process.
+    /// Process memory must not be reused while this is the case.
+    crate fn process_refs_held(&self) -> bool {
+        self.process_refs.get() > 0
+    }
+
+    /// Returns a new generation for a process that is being loaded or
+    /// restarted. Generations are never reused, so `AppId`s of earlier
+    /// instances of the process no longer match.
+    crate fn next_app_generation(&self) -> usize {
+        let generation = self.next_generation.get();
+        self.next_generation.set(generation.wrapping_add(1));
+        generation
     }
 
     /// Run a closure on every valid process. This will iterate the array of
@@ -90,9 +422,9 @@ impl Kernel {
         F: Fn(&dyn process::ProcessType),
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    closure(*p);
+                    self.with_process_ref(p, |p| closure(p));
                 }
                 None => {}
             }
@@ -111,9 +443,9 @@ impl Kernel {
         F: Fn(usize, &dyn process::ProcessType),
     {
         for (i, process) in self.processes.iter().enumerate() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    closure(i, *p);
+                    self.with_process_ref(p, |p| closure(i, p));
                 }
                 None => {}
             }
@@ -129,9 +461,9 @@ impl Kernel {
         F: Fn(&dyn process::ProcessType) -> ReturnCode,
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    let ret = closure(*p);
+                    let ret = self.with_process_ref(p, |p| closure(p));
                     if ret != ReturnCode::FAIL {
                         return ret;
                     }
@@ -142,6 +474,48 @@ impl Kernel {
         ReturnCode::FAIL
     }
 
+    /// Account `executed_us` microseconds of execution time by process `appid`
+    /// against the CPU budgets of all processes.
+    fn update_cpu_budgets(&self, appid: AppId, executed_us: u32) {
+        self.process_each(|process| {
+            let used_us = if process.appid() == appid {
+                executed_us
+            } else {
+                0
+            };
+            process.update_cpu_budget(executed_us, used_us);
+        });
+    }
+
+    /// Start a new budget period for every process that is ready to run but has
+    /// exhausted its CPU budget. Schedulers call this when no other process is
+    /// ready, so that budgets only limit processes when the CPU is contended.
+    /// Returns whether any process was replenished.
+    crate fn replenish_exhausted_cpu_budgets(&self) -> bool {
+        let replenished = Cell::new(false);
+        self.process_each(|process| {
+            if process.ready() && process.cpu_budget_exhausted() {
+                process.replenish_cpu_budget();
+                replenished.set(true);
+            }
+        });
+        replenished.get()
+    }
+
+    /// Return the index of the first empty process slot, if any.
+    crate fn free_process_slot(&self) -> Option<usize> {
+        self.processes.iter().position(|slot| slot.get().is_none())
+    }
+
+    /// Place `process` in the process slot at `index`, or empty the slot if
+    /// `process` is `None`. This is how processes are loaded and unloaded
+    /// while the kernel is running.
+    crate fn set_process(&self, index: usize, process: Option<&'static dyn process::ProcessType>) {
+        if let Some(slot) = self.processes.get(index) {
+            slot.set(process);
+        }
+    }
+
     /// Return how many processes this board supports.
     crate fn number_of_process_slots(&self) -> usize {
         self.processes.len()
@@ -172,6 +546,21 @@ impl Kernel {
         Grant::new(self, grant_index)
     }
 
+    /// Returns how many grants have been created so far.
+    crate fn get_grant_count(&self) -> usize {
+        self.grant_counter.get()
+    }
+
+    /// Returns how often the kernel loop has serviced pending interrupts.
+    crate fn get_interrupt_service_count(&self) -> usize {
+        self.interrupt_service_count.get()
+    }
+
+    /// Returns how often the kernel loop has put the chip to sleep.
+    crate fn get_sleep_count(&self) -> usize {
+        self.sleep_count.get()
+    }
+
     /// Returns the number of grants that have been setup in the system and
     /// marks the grants as "finalized". This means that no more grants can
     /// be created because data structures have been setup based on the number
@@ -196,68 +585,124 @@ impl Kernel {
     /// apps.
     pub fn hardfault_all_apps<C: capabilities::ProcessManagementCapability>(&self, _c: &C) {
         for p in self.processes.iter() {
-            p.map(|process| {
+            p.get().map(|process| {
                 process.set_fault_state();
             });
         }
     }
 
     /// Main loop.
-    pub fn kernel_loop<P: Platform, C: Chip>(
+    ///
+    /// Each iteration the `scheduler` is consulted to decide whether kernel
+    /// work (interrupts and deferred calls) should be serviced, which process
+    /// should run next, or whether the chip may sleep.
+    pub fn kernel_loop<P: Platform, C: Chip, SC: Scheduler<C>>(
         &'static self,
         platform: &P,
         chip: &C,
         ipc: Option<&ipc::IPC>,
+        scheduler: &SC,
         _capability: &dyn capabilities::MainLoopCapability,
     ) {
         loop {
+            self.watchdog.map(|watchdog| watchdog.kick());
             unsafe {
-                chip.service_pending_interrupts();
-                DynamicDeferredCall::call_global_instance_while(|| !chip.has_pending_interrupts());
-
-                for p in self.processes.iter() {
-                    p.map(|process| {
-                        self.do_process(platform, chip, process, ipc);
-                    });
-                    if chip.has_pending_interrupts()
-                        || DynamicDeferredCall::global_instance_calls_pending().unwrap_or(false)
-                    {
-                        break;
+                if scheduler.do_kernel_work_now(chip) {
+                    if chip.has_pending_interrupts() {
+                        self.interrupt_service_count.increment();
                     }
-                }
-
-                chip.atomic(|| {
-                    if !chip.has_pending_interrupts()
-                        && !DynamicDeferredCall::global_instance_calls_pending().unwrap_or(false)
-                        && self.processes_blocked()
-                    {
-                        chip.sleep();
+                    scheduler.execute_kernel_work(chip);
+                } else {
+                    match scheduler.next(self) {
+                        SchedulingDecision::RunProcess((appid, timeslice_us)) => {
+                            self.process_map_or((), appid.idx(), |process| {
+                                let (reason, time_executed) = self.do_process(
+                                    platform,
+                                    chip,
+                                    scheduler,
+                                    process,
+                                    ipc,
+                                    timeslice_us,
+                                );
+                                scheduler.result(reason, time_executed);
+                                if let Some(executed_us) = time_executed {
+                                    self.update_cpu_budgets(appid, executed_us);
+                                }
+                            });
+                            // Write out the syscall trace records of the
+                            // process before the buffer fills up.
+                            self.syscall_trace.map(|trace| trace.flush());
+                        }
+                        SchedulingDecision::TrySleep => {
+                            // Write out syscall trace records, such as those
+                            // of callbacks scheduled by interrupt handlers.
+                            self.syscall_trace.map(|trace| trace.flush());
+                            chip.atomic(|| {
+                                // Cannot sleep if interrupts are pending, as on
+                                // most platforms unhandled interrupts will wake
+                                // the device. Also, if an interrupt occurred
+                                // after the scheduler decided to sleep, but
+                                // before this atomic section, it would not be
+                                // serviced until the next interrupt.
+                                if !chip.has_pending_interrupts()
+                                    && !deferred_call::has_tasks()
+                                    && self.processes_blocked()
+                                {
+                                    self.sleep_count.increment();
+                                    self.watchdog.map(|watchdog| watchdog.suspend());
+                                    chip.sleep_in(power::deepest_permitted());
+                                    self.watchdog.map(|watchdog| watchdog.resume());
+                                }
+                            });
+                        }
                     }
-                });
+                }
             };
         }
     }
 
-    unsafe fn do_process<P: Platform, C: Chip>(
+    /// Transfer control to a process and handle its system calls and tasks
+    /// until it runs out of work, exhausts `timeslice_us`, or the `scheduler`
+    /// asks for it to be preempted.
+    ///
+    /// This is the mechanism shared by all `Scheduler` policies. It returns
+    /// why the process stopped executing and, if the process was given a
+    /// timeslice, how many microseconds of it were used.
+    crate unsafe fn do_process<P: Platform, C: Chip, SC: Scheduler<C>>(
         &self,
         platform: &P,
         chip: &C,
+        scheduler: &SC,
         process: &dyn process::ProcessType,
         ipc: Option<&crate::ipc::IPC>,
-    ) {
-        let appid = process.appid();
+        timeslice_us: Option<u32>,
+    ) -> (StoppedExecutingReason, Option<u32>) {
         let systick = chip.systick();
         systick.reset();
-        systick.set_timer(KERNEL_TICK_DURATION_US);
+        // Without a timeslice the SysTick is not used to preempt the process,
+        // but it still measures how long the process runs. It counts down one
+        // measurement window at a time and is only set again once the window
+        // is used up.
+        systick.set_timer(timeslice_us.unwrap_or(ACCOUNTING_WINDOW_US));
         systick.enable(false);
 
+        let mut return_reason = StoppedExecutingReason::NoWorkLeft;
+
         loop {
-            if chip.has_pending_interrupts() {
+            // A process that faults and is restarted keeps running here as a
+            // new instance, with a new `AppId`.
+            let appid = process.appid();
+
+            if !scheduler.continue_process(self, appid, chip) {
+                return_reason = StoppedExecutingReason::KernelPreemption;
                 break;
             }
 
-            if systick.overflowed() || !systick.greater_than(MIN_QUANTA_THRESHOLD_US) {
+            if timeslice_us.is_some()
+                && (systick.overflowed() || !systick.greater_than(MIN_QUANTA_THRESHOLD_US))
+            {
                 process.debug_timeslice_expired();
+                return_reason = StoppedExecutingReason::TimesliceExpired;
                 break;
             }
 
@@ -268,9 +713,30 @@ impl Kernel {
                     // the process.
                     process.setup_mpu();
                     chip.mpu().enable_mpu();
-                    systick.enable(true);
+                    if timeslice_us.is_some() {
+                        systick.enable(true);
+                    } else if systick.overflowed() || !systick.greater_than(MIN_QUANTA_THRESHOLD_US)
+                    {
+                        systick.set_timer(ACCOUNTING_WINDOW_US);
+                        systick.enable(false);
+                    }
+                    let run_start_us = systick.get_value();
                     let context_switch_reason = process.switch_to();
-                    systick.enable(false);
+                    if timeslice_us.is_some() {
+                        systick.enable(false);
+                    }
+                    // A process that ran until the timeslice or the
+                    // measurement window ran out is counted for the rest of
+                    // it.
+                    let ran_us = if context_switch_reason
+                        == Some(ContextSwitchReason::TimesliceExpired)
+                        || systick.overflowed()
+                    {
+                        run_start_us
+                    } else {
+                        run_start_us.saturating_sub(systick.get_value())
+                    };
+                    process.debug_ran_for(ran_us);
                     chip.mpu().disable_mpu();
 
                     // Now the process has returned back to the kernel. Check
@@ -282,30 +748,31 @@ impl Kernel {
                         }
                         Some(ContextSwitchReason::SyscallFired { syscall }) => {
                             process.debug_syscall_called();
+                            let syscall_start_us = systick.get_value();
 
                             // Handle each of the syscalls.
                             match syscall {
                                 Syscall::MEMOP { operand, arg0 } => {
                                     let res = memop::memop(process, operand, arg0);
                                     if config::CONFIG.trace_syscalls {
-                                        debug!(
-                                            "[{:?}] memop({}, {:#x}) = {:#x}",
+                                        self.trace(
                                             appid,
+                                            TraceEvent::Memop,
+                                            None,
                                             operand,
-                                            arg0,
-                                            usize::from(res)
+                                            [arg0, 0, 0, 0],
+                                            usize::from(res),
                                         );
                                     }
                                     process.set_syscall_return_value(res.into());
                                 }
                                 Syscall::YIELD => {
                                     if config::CONFIG.trace_syscalls {
-                                        debug!("[{:?}] yield", appid);
+                                        self.trace(appid, TraceEvent::Yield, None, 0, [0; 4], 0);
                                     }
+                                    // There might be already enqueued callbacks,
+                                    // which the next loop iteration handles.
                                     process.set_yielded_state();
-
-                                    // There might be already enqueued callbacks
-                                    continue;
                                 }
                                 Syscall::SUBSCRIBE {
                                     driver_number,
@@ -317,31 +784,40 @@ impl Kernel {
                                         driver_num: driver_number,
                                         subscribe_num: subdriver_number,
                                     };
-                                    process.remove_pending_callbacks(callback_id);
+                                    let res =
+                                        if self.syscall_permitted(process, driver_number, None) {
+                                            process.remove_pending_callbacks(callback_id);
 
-                                    let callback = NonNull::new(callback_ptr).map(|ptr| {
-                                        Callback::new(appid, callback_id, appdata, ptr.cast())
-                                    });
+                                            let callback = NonNull::new(callback_ptr).map(|ptr| {
+                                                Callback::new(
+                                                    appid,
+                                                    callback_id,
+                                                    appdata,
+                                                    ptr.cast(),
+                                                )
+                                            });
 
-                                    let res =
-                                        platform.with_driver(
-                                            driver_number,
-                                            |driver| match driver {
-                                                Some(d) => {
-                                                    d.subscribe(subdriver_number, callback, appid)
+                                            platform.with_driver(driver_number, |driver| {
+                                                match driver {
+                                                    Some(d) => d.subscribe(
+                                                        subdriver_number,
+                                                        callback,
+                                                        appid,
+                                                    ),
+                                                    None => ReturnCode::ENODEVICE,
                                                 }
-                                                None => ReturnCode::ENODEVICE,
-                                            },
-                                        );
+                                            })
+                                        } else {
+                                            ReturnCode::EPERM
+                                        };
                                     if config::CONFIG.trace_syscalls {
-                                        debug!(
-                                            "[{:?}] subscribe({:#x}, {}, @{:#x}, {:#x}) = {:#x}",
+                                        self.trace(
                                             appid,
-                                            driver_number,
+                                            TraceEvent::Subscribe,
+                                            Some(driver_number),
                                             subdriver_number,
-                                            callback_ptr as usize,
-                                            appdata,
-                                            usize::from(res)
+                                            [callback_ptr as usize, appdata, 0, 0],
+                                            usize::from(res),
                                         );
                                     }
                                     process.set_syscall_return_value(res.into());
@@ -351,70 +827,128 @@ impl Kernel {
                                     subdriver_number,
                                     arg0,
                                     arg1,
+                                } => {
+                                    let res = if self.syscall_permitted(
+                                        process,
+                                        driver_number,
+                                        Some(subdriver_number),
+                                    ) {
+                                        platform.with_driver(driver_number, |driver| match driver {
+                                            Some(d) => {
+                                                d.command(subdriver_number, arg0, arg1, appid)
+                                            }
+                                            None => ReturnCode::ENODEVICE,
+                                        })
+                                    } else {
+                                        ReturnCode::EPERM
+                                    };
+                                    if config::CONFIG.trace_syscalls {
+                                        self.trace(
+                                            appid,
+                                            TraceEvent::Command,
+                                            Some(driver_number),
+                                            subdriver_number,
+                                            [arg0, arg1, 0, 0],
+                                            usize::from(res),
+                                        );
+                                    }
+                                    process.set_syscall_return_value(res.into());
+                                }
+                                Syscall::ALLOW {
+                                    driver_number,
+                                    subdriver_number,
+                                    allow_address,
+                                    allow_size,
                                 } => {
                                     let res =
-                                        platform.with_driver(
-                                            driver_number,
-                                            |driver| match driver {
-                                                Some(d) => {
-                                                    d.command(subdriver_number, arg0, arg1, appid)
+                                        if self.syscall_permitted(process, driver_number, None) {
+                                            platform.with_driver(driver_number, |driver| {
+                                                match driver {
+                                                    Some(d) => {
+                                                        match process
+                                                            .allow(allow_address, allow_size)
+                                                        {
+                                                            Ok(oslice) => d.allow(
+                                                                appid,
+                                                                subdriver_number,
+                                                                oslice,
+                                                            ),
+                                                            Err(err) => err, /* memory not valid */
+                                                        }
+                                                    }
+                                                    None => ReturnCode::ENODEVICE,
                                                 }
-                                                None => ReturnCode::ENODEVICE,
-                                            },
-                                        );
+                                            })
+                                        } else {
+                                            ReturnCode::EPERM
+                                        };
                                     if config::CONFIG.trace_syscalls {
-                                        debug!(
-                                            "[{:?}] cmd({:#x}, {}, {:#x}, {:#x}) = {:#x}",
+                                        self.trace(
                                             appid,
-                                            driver_number,
+                                            TraceEvent::Allow,
+                                            Some(driver_number),
                                             subdriver_number,
-                                            arg0,
-                                            arg1,
-                                            usize::from(res)
+                                            [allow_address as usize, allow_size, 0, 0],
+                                            usize::from(res),
                                         );
                                     }
                                     process.set_syscall_return_value(res.into());
                                 }
-                                Syscall::ALLOW {
+                                Syscall::ALLOW_READONLY {
                                     driver_number,
                                     subdriver_number,
                                     allow_address,
                                     allow_size,
                                 } => {
-                                    let res = platform.with_driver(driver_number, |driver| {
-                                        match driver {
-                                            Some(d) => {
-                                                match process.allow(allow_address, allow_size) {
-                                                    Ok(oslice) => {
-                                                        d.allow(appid, subdriver_number, oslice)
+                                    let res =
+                                        if self.syscall_permitted(process, driver_number, None) {
+                                            platform.with_driver(driver_number, |driver| {
+                                                match driver {
+                                                    Some(d) => {
+                                                        match process.allow_readonly(
+                                                            allow_address,
+                                                            allow_size,
+                                                        ) {
+                                                            Ok(oslice) => d.allow_readonly(
+                                                                appid,
+                                                                subdriver_number,
+                                                                oslice,
+                                                            ),
+                                                            Err(err) => err, /* memory not valid */
+                                                        }
                                                     }
-                                                    Err(err) => err, /* memory not valid */
+                                                    None => ReturnCode::ENODEVICE,
                                                 }
-                                            }
-                                            None => ReturnCode::ENODEVICE,
-                                        }
-                                    });
+                                            })
+                                        } else {
+                                            ReturnCode::EPERM
+                                        };
                                     if config::CONFIG.trace_syscalls {
-                                        debug!(
-                                            "[{:?}] allow({:#x}, {}, @{:#x}, {:#x}) = {:#x}",
+                                        self.trace(
                                             appid,
-                                            driver_number,
+                                            TraceEvent::AllowReadOnly,
+                                            Some(driver_number),
                                             subdriver_number,
-                                            allow_address as usize,
-                                            allow_size,
-                                            usize::from(res)
+                                            [allow_address as usize, allow_size, 0, 0],
+                                            usize::from(res),
                                         );
                                     }
                                     process.set_syscall_return_value(res.into());
                                 }
                             }
+                            process.debug_syscall_handled(
+                                syscall.class(),
+                                syscall_start_us.saturating_sub(systick.get_value()),
+                            );
                         }
                         Some(ContextSwitchReason::TimesliceExpired) => {
                             // break to handle other processes.
+                            return_reason = StoppedExecutingReason::TimesliceExpired;
                             break;
                         }
                         Some(ContextSwitchReason::Interrupted) => {
                             // break to handle other processes.
+                            return_reason = StoppedExecutingReason::KernelPreemption;
                             break;
                         }
                         None => {
@@ -434,14 +968,19 @@ impl Kernel {
                     Some(cb) => match cb {
                         Task::FunctionCall(ccb) => {
                             if config::CONFIG.trace_syscalls {
-                                debug!(
-                                    "[{:?}] function_call @{:#x}({:#x}, {:#x}, {:#x}, {:#x})",
+                                let (driver, subdriver) = match ccb.source {
+                                    process::FunctionCallSource::Kernel => (None, 0),
+                                    process::FunctionCallSource::Driver(id) => {
+                                        (Some(id.driver_num), id.subscribe_num)
+                                    }
+                                };
+                                self.trace(
                                     appid,
+                                    TraceEvent::FunctionCall,
+                                    driver,
+                                    subdriver,
+                                    [ccb.argument0, ccb.argument1, ccb.argument2, ccb.argument3],
                                     ccb.pc,
-                                    ccb.argument0,
-                                    ccb.argument1,
-                                    ccb.argument2,
-                                    ccb.argument3,
                                 );
                             }
                             process.set_process_function(ccb);
@@ -466,19 +1005,39 @@ impl Kernel {
                     panic!("Attempted to schedule a faulty process");
                 }
                 process::State::StoppedRunning => {
+                    return_reason = StoppedExecutingReason::Stopped;
                     break;
                     // Do nothing
                 }
                 process::State::StoppedYielded => {
+                    return_reason = StoppedExecutingReason::Stopped;
                     break;
                     // Do nothing
                 }
                 process::State::StoppedFaulted => {
+                    return_reason = StoppedExecutingReason::StoppedFaulted;
+                    break;
+                    // Do nothing
+                }
+                process::State::Terminated => {
+                    return_reason = StoppedExecutingReason::Stopped;
                     break;
                     // Do nothing
                 }
             }
         }
+
+        // Check how much of the timeslice the process used. This must happen
+        // before the timer is reset.
+        let time_executed_us = timeslice_us.map(|timeslice| {
+            if systick.overflowed() {
+                timeslice
+            } else {
+                timeslice.saturating_sub(systick.get_value())
+            }
+        });
         systick.reset();
+
+        (return_reason, time_executed_us)
     }
 }
@@ -0,0 +1,64 @@
+//! Cooperative Scheduler for Tock
+//!
+//! This scheduler runs all processes in a round-robin fashion, but does not use
+//! a scheduler timer to enforce process timeslices. That is, all processes are
+//! run cooperatively. Processes are run until they yield or stop executing
+//! (i.e. they crash or exit).
+//!
+//! When hardware interrupts occur while a userspace process is executing, this
+//! scheduler executes the top half of the interrupt, and then stops executing
+//! the userspace process immediately and handles the bottom half of the
+//! interrupt. It then resumes the same process, so an interrupt never causes
+//! a process to lose its turn.
+
+use core::cell::Cell;
+
+use crate::platform::Chip;
+use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
+
+/// Cooperative scheduler.
+pub struct CooperativeSched {
+    /// Index of the process that ran most recently (or should run next).
+    next_up: Cell<usize>,
+}
+
+impl CooperativeSched {
+    pub const fn new() -> CooperativeSched {
+        CooperativeSched {
+            next_up: Cell::new(0),
+        }
+    }
+}
+
+impl<C: Chip> Scheduler<C> for CooperativeSched {
+    fn next(&self, kernel: &Kernel) -> SchedulingDecision {
+        let num_procs = kernel.number_of_process_slots();
+        let start = self.next_up.get() % num_procs.max(1);
+
+        for offset in 0..num_procs {
+            let index = (start + offset) % num_procs;
+            let ready = kernel.process_map_or(None, index, |process| {
+                if process.ready() {
+                    Some(process.appid())
+                } else {
+                    None
+                }
+            });
+
+            if let Some(appid) = ready {
+                self.next_up.set(index);
+                return SchedulingDecision::RunProcess((appid, None));
+            }
+        }
+
+        SchedulingDecision::TrySleep
+    }
+
+    fn result(&self, result: StoppedExecutingReason, _: Option<u32>) {
+        // Processes keep running until they yield, so only move on if the
+        // process did not simply get interrupted by kernel work.
+        if result != StoppedExecutingReason::KernelPreemption {
+            self.next_up.set(self.next_up.get().wrapping_add(1));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Fixed Priority Scheduler for Tock
+//!
+//! This scheduler assigns priority to processes based on the priority they
+//! request in their TBF header, with ties (including processes that do not
+//! request a priority) broken by their order in the `PROCESSES` array. It runs
+//! the highest priority process available at any point in time. Kernel tasks
+//! (bottom half interrupt handling / deferred call handling) always take
+//! priority over userspace processes.
+//!
+//! Processes are not given a timeslice. Instead, the running process is
+//! preempted whenever a higher priority process becomes ready, which is only
+//! possible after kernel work (e.g. an interrupt) has scheduled a task for it.
+//! Processes that have a CPU budget are given their remaining budget as a
+//! timeslice, and are not scheduled again once it is used up unless no other
+//! process is ready.
+
+use crate::callback::AppId;
+use crate::common::deferred_call;
+use crate::platform::Chip;
+use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
+
+/// Fixed priority scheduler.
+pub struct PrioritySched {}
+
+impl PrioritySched {
+    pub const fn new() -> PrioritySched {
+        PrioritySched {}
+    }
+
+    /// Return the highest priority process that is ready to run and has CPU
+    /// budget left, along with its priority as a `(priority, index)` pair.
+    fn highest_ready(&self, kernel: &Kernel) -> Option<(AppId, (u32, usize))> {
+        let mut highest: Option<(AppId, (u32, usize))> = None;
+        for index in 0..kernel.number_of_process_slots() {
+            let candidate = kernel.process_map_or(None, index, |process| {
+                if process.ready() && !process.cpu_budget_exhausted() {
+                    let priority = process.get_scheduling_parameters().priority;
+                    Some((process.appid(), (priority, index)))
+                } else {
+                    None
+                }
+            });
+            if let Some((appid, key)) = candidate {
+                if highest.map_or(true, |(_, highest_key)| key < highest_key) {
+                    highest = Some((appid, key));
+                }
+            }
+        }
+        highest
+    }
+}
+
+impl<C: Chip> Scheduler<C> for PrioritySched {
+    fn next(&self, kernel: &Kernel) -> SchedulingDecision {
+        let next = self.highest_ready(kernel).or_else(|| {
+            if kernel.replenish_exhausted_cpu_budgets() {
+                self.highest_ready(kernel)
+            } else {
+                None
+            }
+        });
+
+        match next {
+            Some((appid, _)) => {
+                let timeslice = kernel
+                    .process_map_or(None, appid.idx(), |process| process.cpu_budget_remaining());
+                SchedulingDecision::RunProcess((appid, timeslice))
+            }
+            None => SchedulingDecision::TrySleep,
+        }
+    }
+
+    unsafe fn continue_process(&self, kernel: &Kernel, appid: AppId, chip: &C) -> bool {
+        // In addition to kernel work, also preempt the running process if a
+        // higher priority process has become ready.
+        !(chip.has_pending_interrupts() || deferred_call::has_tasks())
+            && self.highest_ready(kernel).map_or(true, |(ready, _)| {
+                ready == appid
+                    || kernel.process_map_or(true, appid.idx(), |process| !process.ready())
+            })
+    }
+
+    fn result(&self, _: StoppedExecutingReason, _: Option<u32>) {}
+}
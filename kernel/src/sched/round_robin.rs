@@ -0,0 +1,135 @@
+//! Round Robin Scheduler for Tock
+//!
+//! This scheduler is specifically a Round Robin Scheduler with Interrupts.
+//!
+//! Processes are visited in the order they appear in the processes array,
+//! starting after the process that ran most recently. Each process is given a
+//! fixed timeslice. If a process is interrupted by kernel work before its
+//! timeslice expires it is resumed next, with the remainder of its timeslice,
+//! rather than moved to the back of the queue.
+//!
+//! Processes may request their own timeslice in their TBF header, which is used
+//! instead of the scheduler default. Processes with a CPU budget are skipped
+//! once it is used up, unless no other process is ready.
+
+use core::cell::Cell;
+use core::cmp::min;
+
+use crate::callback::AppId;
+use crate::platform::Chip;
+use crate::sched::{Kernel, Scheduler, SchedulingDecision, StoppedExecutingReason};
+
+/// Default timeslice in microseconds given to each process that does not
+/// request its own.
+const DEFAULT_TIMESLICE_US: u32 = 10000;
+
+/// Round robin scheduler.
+pub struct RoundRobinSched {
+    /// Timeslice given to each process.
+    timeslice_us: u32,
+    /// How much of the current process's timeslice is left.
+    time_remaining: Cell<u32>,
+    /// Index of the process that ran most recently (or should run next).
+    next_up: Cell<usize>,
+    /// Whether the last process was interrupted and should be resumed.
+    last_rescheduled: Cell<bool>,
+}
+
+impl RoundRobinSched {
+    /// Create a round robin scheduler using the default timeslice.
+    pub const fn new() -> RoundRobinSched {
+        RoundRobinSched::new_with_timeslice(DEFAULT_TIMESLICE_US)
+    }
+
+    /// Create a round robin scheduler that gives each process
+    /// `timeslice_us` microseconds to run before it is preempted.
+    pub const fn new_with_timeslice(timeslice_us: u32) -> RoundRobinSched {
+        RoundRobinSched {
+            timeslice_us: timeslice_us,
+            time_remaining: Cell::new(timeslice_us),
+            next_up: Cell::new(0),
+            last_rescheduled: Cell::new(false),
+        }
+    }
+}
+
+impl RoundRobinSched {
+    /// Find the next process, starting with `start` and wrapping around, that
+    /// is ready and has CPU budget left. Returns its index, `AppId` and the
+    /// timeslice it should be given.
+    fn find_next(&self, kernel: &Kernel, start: usize) -> Option<(usize, AppId, u32)> {
+        let num_procs = kernel.number_of_process_slots();
+        for offset in 0..num_procs {
+            let index = (start + offset) % num_procs;
+            let next = kernel.process_map_or(None, index, |process| {
+                if process.ready() && !process.cpu_budget_exhausted() {
+                    let params = process.get_scheduling_parameters();
+                    let timeslice = params.timeslice_us.unwrap_or(self.timeslice_us);
+                    let timeslice = process
+                        .cpu_budget_remaining()
+                        .map_or(timeslice, |remaining| min(timeslice, remaining));
+                    Some((index, process.appid(), timeslice))
+                } else {
+                    None
+                }
+            });
+            if next.is_some() {
+                return next;
+            }
+        }
+        None
+    }
+}
+
+impl<C: Chip> Scheduler<C> for RoundRobinSched {
+    fn next(&self, kernel: &Kernel) -> SchedulingDecision {
+        let start = self.next_up.get() % kernel.number_of_process_slots().max(1);
+
+        let next = self.find_next(kernel, start).or_else(|| {
+            if kernel.replenish_exhausted_cpu_budgets() {
+                self.find_next(kernel, start)
+            } else {
+                None
+            }
+        });
+
+        match next {
+            Some((index, appid, timeslice)) => {
+                // Only resume with the leftover timeslice if this is the same
+                // process that was interrupted.
+                let timeslice = if self.last_rescheduled.get() && index == start {
+                    min(timeslice, self.time_remaining.get())
+                } else {
+                    timeslice
+                };
+                self.next_up.set(index);
+                self.time_remaining.set(timeslice);
+                SchedulingDecision::RunProcess((appid, Some(timeslice)))
+            }
+            None => {
+                self.last_rescheduled.set(false);
+                SchedulingDecision::TrySleep
+            }
+        }
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let execution_time_us = execution_time_us.unwrap_or(self.time_remaining.get());
+        let reschedule = match result {
+            StoppedExecutingReason::KernelPreemption => {
+                if self.time_remaining.get() > execution_time_us {
+                    self.time_remaining
+                        .set(self.time_remaining.get() - execution_time_us);
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        };
+        self.last_rescheduled.set(reschedule);
+        if !reschedule {
+            self.next_up.set(self.next_up.get().wrapping_add(1));
+        }
+    }
+}
@@ -47,6 +47,89 @@ pub enum Syscall {
     ///
     /// SVC_NUM = 4
     MEMOP { operand: usize, arg0: usize },
+
+    /// Share a memory buffer with the kernel, which can only read it. The
+    /// buffer may also be in the process's flash.
+    ///
+    /// SVC_NUM = 5
+    #[allow(non_camel_case_types)]
+    ALLOW_READONLY {
+        driver_number: usize,
+        subdriver_number: usize,
+        allow_address: *const u8,
+        allow_size: usize,
+    },
+}
+
+impl Syscall {
+    /// Which class of system call this is.
+    pub fn class(&self) -> SyscallClass {
+        match *self {
+            Syscall::YIELD => SyscallClass::Yield,
+            Syscall::SUBSCRIBE { .. } => SyscallClass::Subscribe,
+            Syscall::COMMAND { .. } => SyscallClass::Command,
+            Syscall::ALLOW { .. } => SyscallClass::Allow,
+            Syscall::MEMOP { .. } => SyscallClass::Memop,
+            Syscall::ALLOW_READONLY { .. } => SyscallClass::AllowReadOnly,
+        }
+    }
+}
+
+/// The kinds of system calls, without their arguments.
+///
+/// The kernel keeps statistics for each class of system call a process makes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SyscallClass {
+    Yield = 0,
+    Subscribe = 1,
+    Command = 2,
+    Allow = 3,
+    Memop = 4,
+    AllowReadOnly = 5,
+}
+
+impl SyscallClass {
+    /// All system call classes, in SVC number order.
+    pub const ALL: [SyscallClass; 6] = [
+        SyscallClass::Yield,
+        SyscallClass::Subscribe,
+        SyscallClass::Command,
+        SyscallClass::Allow,
+        SyscallClass::Memop,
+        SyscallClass::AllowReadOnly,
+    ];
+}
+
+/// How long the kernel took to handle one class of system call for a process.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyscallLatency {
+    /// How many of these system calls were handled.
+    pub count: usize,
+
+    /// Total time spent handling them, in microseconds.
+    pub total_us: u64,
+
+    /// Longest time spent handling one of them, in microseconds.
+    pub max_us: u32,
+}
+
+impl SyscallLatency {
+    /// Average time spent handling one system call, in microseconds.
+    pub fn average_us(&self) -> u32 {
+        if self.count == 0 {
+            0
+        } else {
+            (self.total_us / self.count as u64) as u32
+        }
+    }
+
+    crate fn record(&mut self, latency_us: u32) {
+        self.count += 1;
+        self.total_us += latency_us as u64;
+        if latency_us > self.max_us {
+            self.max_us = latency_us;
+        }
+    }
 }
 
 /// Why the process stopped executing and execution returned to the kernel.
@@ -205,6 +288,12 @@ pub fn arguments_to_syscall(
             operand: r0,
             arg0: r1,
         }),
+        5 => Some(Syscall::ALLOW_READONLY {
+            driver_number: r0,
+            subdriver_number: r1,
+            allow_address: r2 as *const u8,
+            allow_size: r3,
+        }),
         _ => None,
     }
 }
@@ -0,0 +1,345 @@
+//! Binary trace of the system calls and callbacks of processes.
+//!
+//! When the board installs a `SyscallTrace` with
+//! `Kernel::set_syscall_trace()`, the kernel appends a
+//! fixed-size `TraceRecord` to a ring buffer for every system call, every
+//! function call into a process and every callback a capsule schedules.
+//! Recording a trace record does not format or print anything, so it perturbs
+//! timing far less than printing each system call with `debug!()`.
+//!
+//! Records are written out after each time a process runs and when the kernel
+//! is idle, by the `TraceWriter` the board selects: `debug::DebugTraceWriter`
+//! writes each frame as a line of text, `TRACE:` followed by the frame in hex,
+//! between the `debug!()` messages, and
+//! `capsules::syscall_trace_uart::SyscallTraceUart` sends the binary frames
+//! over a UART of their own, such as a Segger RTT channel. The process console
+//! can change which records are kept at runtime. If the buffer fills up, new
+//! records are dropped and a `RecordsDropped` record reports how many were
+//! lost.
+//!
+//! Tracing can be compiled out by disabling `trace_syscalls` in
+//! `kernel/src/config.rs`.
+//!
+//! Frame format
+//! ------------
+//!
+//! Each record is written as a frame of `FRAME_LEN` bytes. All values are
+//! little endian.
+//!
+//! ```text
+//! 0      1      2       3                                   39
+//! +------+------+-------+-----------------...----------------+----------+
+//! | 0xA5 | 0x5A | 36    | payload                            | checksum |
+//! +------+------+-------+-----------------...----------------+----------+
+//! ```
+//!
+//! The checksum is the wrapping sum of the payload bytes. The payload is:
+//!
+//! ```text
+//! offset  size  field
+//!  0      4     timestamp in microseconds, wraps around
+//!  4      2     index of the process (`AppId`)
+//!  6      1     event (`TraceEvent`)
+//!  7      1     reserved, zero
+//!  8      4     driver number, 0xFFFFFFFF if the event has no driver
+//! 12      4     subscribe/command/allow number, or the memop operand
+//! 16     16     four arguments
+//! 32      4     result: the return code, or the function address for
+//!               function calls
+//! ```
+//!
+//! `tools/syscall_trace` decodes this format on the host.
+
+use core::cell::Cell;
+
+use crate::callback::AppId;
+use crate::common::cells::{MapCell, OptionalCell};
+use crate::common::{Queue, RingBuffer};
+use crate::hil::time::{Frequency, Time};
+
+/// Length of an encoded trace record, in bytes.
+pub const FRAME_LEN: usize = 40;
+
+/// Length of the payload of an encoded trace record, in bytes.
+const PAYLOAD_LEN: usize = 36;
+
+/// Bytes that start every frame.
+const FRAME_SYNC: [u8; 2] = [0xA5, 0x5A];
+
+/// Driver number recorded for events that are not for a driver.
+const NO_DRIVER: u32 = 0xFFFF_FFFF;
+
+/// What a trace record describes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum TraceEvent {
+    Yield = 0,
+    Subscribe = 1,
+    Command = 2,
+    Allow = 3,
+    Memop = 4,
+    /// The kernel called a function in the process, such as a callback or the
+    /// process's entry point.
+    FunctionCall = 5,
+    /// A capsule scheduled a callback for the process.
+    CallbackScheduled = 6,
+    /// Pending callbacks were removed because the process subscribed again.
+    CallbacksRemoved = 7,
+    /// The trace buffer was full and `result` records were dropped.
+    RecordsDropped = 8,
+    AllowReadOnly = 9,
+}
+
+/// One entry in the syscall trace.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TraceRecord {
+    pub timestamp_us: u32,
+    pub app: u16,
+    pub event: TraceEvent,
+    pub driver: u32,
+    pub subdriver: u32,
+    pub args: [u32; 4],
+    pub result: u32,
+}
+
+impl TraceRecord {
+    /// Placeholder used to initialize trace buffers.
+    pub const EMPTY: TraceRecord = TraceRecord {
+        timestamp_us: 0,
+        app: 0,
+        event: TraceEvent::Yield,
+        driver: NO_DRIVER,
+        subdriver: 0,
+        args: [0; 4],
+        result: 0,
+    };
+
+    /// Write this record as a frame to the start of `buf`, which must be at
+    /// least `FRAME_LEN` bytes long.
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0..2].copy_from_slice(&FRAME_SYNC);
+        buf[2] = PAYLOAD_LEN as u8;
+
+        let payload = &mut buf[3..3 + PAYLOAD_LEN];
+        payload[0..4].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        payload[4..6].copy_from_slice(&self.app.to_le_bytes());
+        payload[6] = self.event as u8;
+        payload[7] = 0;
+        payload[8..12].copy_from_slice(&self.driver.to_le_bytes());
+        payload[12..16].copy_from_slice(&self.subdriver.to_le_bytes());
+        for (i, arg) in self.args.iter().enumerate() {
+            payload[16 + 4 * i..20 + 4 * i].copy_from_slice(&arg.to_le_bytes());
+        }
+        payload[32..36].copy_from_slice(&self.result.to_le_bytes());
+
+        let checksum = payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        buf[3 + PAYLOAD_LEN] = checksum;
+    }
+}
+
+/// Source of timestamps for trace records.
+pub trait TraceClock {
+    /// The current time in microseconds. This wraps around after 2^32
+    /// microseconds.
+    fn now_us(&self) -> u32;
+}
+
+/// A `TraceClock` that counts microseconds with a `Time`.
+///
+/// The ticks of the `Time` wrap around at a different point than the
+/// microseconds, so rather than converting the current tick count, the
+/// microseconds that passed since the last timestamp are added up. Timestamps
+/// must be taken at least once per wrap-around of the tick counter.
+pub struct TimeTraceClock<'a, T: Time> {
+    time: &'a T,
+    /// Tick count at the last timestamp.
+    last_ticks: Cell<u32>,
+    /// Microseconds at the last timestamp.
+    us: Cell<u32>,
+    /// Part of a microsecond that passed before the last timestamp, in
+    /// millionths of a tick.
+    remainder: Cell<u64>,
+}
+
+impl<T: Time> TimeTraceClock<'a, T> {
+    pub fn new(time: &'a T) -> TimeTraceClock<'a, T> {
+        TimeTraceClock {
+            time,
+            last_ticks: Cell::new(time.now()),
+            us: Cell::new(0),
+            remainder: Cell::new(0),
+        }
+    }
+}
+
+impl<T: Time> TraceClock for TimeTraceClock<'a, T> {
+    fn now_us(&self) -> u32 {
+        let hertz = T::Frequency::frequency() as u64;
+        if hertz == 0 {
+            return 0;
+        }
+        let ticks = self.time.now();
+        let elapsed =
+            ticks.wrapping_sub(self.last_ticks.get()) as u64 * 1_000_000 + self.remainder.get();
+        self.last_ticks.set(ticks);
+        self.remainder.set(elapsed % hertz);
+        self.us
+            .set(self.us.get().wrapping_add((elapsed / hertz) as u32));
+        self.us.get()
+    }
+}
+
+/// Writes trace records out of the kernel.
+pub trait TraceWriter {
+    /// Called when the kernel is idle and `trace` has records. The writer
+    /// should take as many records from `trace` with `SyscallTrace::drain()`
+    /// as it can send now.
+    fn write_trace(&self, trace: &SyscallTrace);
+}
+
+/// Ring buffer of trace records and the settings that select which records
+/// are kept.
+pub struct SyscallTrace {
+    records: MapCell<&'static mut RingBuffer<'static, TraceRecord>>,
+    clock: OptionalCell<&'static dyn TraceClock>,
+    writer: OptionalCell<&'static dyn TraceWriter>,
+    enabled: Cell<bool>,
+    /// Only keep records of the process with this index.
+    process_filter: Cell<Option<usize>>,
+    /// Only keep records for this driver number.
+    driver_filter: Cell<Option<usize>>,
+    /// Records dropped because the buffer was full.
+    dropped: Cell<usize>,
+}
+
+impl SyscallTrace {
+    pub fn new(records: &'static mut RingBuffer<'static, TraceRecord>) -> SyscallTrace {
+        SyscallTrace {
+            records: MapCell::new(records),
+            clock: OptionalCell::empty(),
+            writer: OptionalCell::empty(),
+            enabled: Cell::new(true),
+            process_filter: Cell::new(None),
+            driver_filter: Cell::new(None),
+            dropped: Cell::new(0),
+        }
+    }
+
+    /// Set the clock used to timestamp records. Without a clock all
+    /// timestamps are zero.
+    pub fn set_clock(&self, clock: &'static dyn TraceClock) {
+        self.clock.set(clock);
+    }
+
+    /// Set where records are written out.
+    pub fn set_writer(&self, writer: &'static dyn TraceWriter) {
+        self.writer.set(writer);
+    }
+
+    /// Start or stop recording.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    /// Only record events of `app`, or of all processes if `None`.
+    pub fn set_process_filter(&self, app: Option<AppId>) {
+        self.process_filter.set(app.map(|app| app.idx()));
+    }
+
+    /// Only record events for driver number `driver`, or for all drivers if
+    /// `None`. Events that are not for a driver, such as yields, are only
+    /// recorded without a driver filter.
+    pub fn set_driver_filter(&self, driver: Option<usize>) {
+        self.driver_filter.set(driver);
+    }
+
+    /// Number of records waiting to be written out.
+    pub fn pending(&self) -> usize {
+        self.records.map_or(0, |records| records.len())
+    }
+
+    /// Encode as many whole records as fit into `buf` and remove them from
+    /// the trace. Returns the number of bytes written.
+    pub fn drain(&self, buf: &mut [u8]) -> usize {
+        let mut written = 0;
+        if self.dropped.get() > 0 && buf.len() >= FRAME_LEN {
+            let dropped = TraceRecord {
+                event: TraceEvent::RecordsDropped,
+                result: self.dropped.get() as u32,
+                ..TraceRecord::EMPTY
+            };
+            dropped.encode(&mut buf[0..FRAME_LEN]);
+            self.dropped.set(0);
+            written += FRAME_LEN;
+        }
+        self.records.map(|records| {
+            while buf.len() - written >= FRAME_LEN {
+                match records.dequeue() {
+                    Some(record) => {
+                        record.encode(&mut buf[written..written + FRAME_LEN]);
+                        written += FRAME_LEN;
+                    }
+                    None => break,
+                }
+            }
+        });
+        written
+    }
+
+    /// Record an event of `appid`, if it passes the filters.
+    crate fn record(
+        &self,
+        appid: AppId,
+        event: TraceEvent,
+        driver: Option<usize>,
+        subdriver: usize,
+        args: [usize; 4],
+        result: usize,
+    ) {
+        if !self.enabled.get()
+            || self
+                .process_filter
+                .get()
+                .map_or(false, |idx| idx != appid.idx())
+            || self
+                .driver_filter
+                .get()
+                .map_or(false, |filter| driver != Some(filter))
+        {
+            return;
+        }
+
+        let record = TraceRecord {
+            timestamp_us: self.clock.map_or(0, |clock| clock.now_us()),
+            app: appid.idx() as u16,
+            event,
+            driver: driver.map_or(NO_DRIVER, |driver| driver as u32),
+            subdriver: subdriver as u32,
+            args: [
+                args[0] as u32,
+                args[1] as u32,
+                args[2] as u32,
+                args[3] as u32,
+            ],
+            result: result as u32,
+        };
+        let stored = self
+            .records
+            .map_or(false, |records| records.enqueue(record));
+        if !stored {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    /// Hand pending records to the writer. Called after a process runs and
+    /// when the kernel is idle.
+    crate fn flush(&self) {
+        if self.pending() > 0 || self.dropped.get() > 0 {
+            self.writer.map(|writer| writer.write_trace(self));
+        }
+    }
+}
@@ -21,6 +21,11 @@ crate struct TbfHeaderV2Base {
 }
 
 /// Types in TLV structures for each optional block of the header.
+///
+/// Types 1 to 4 are the standard TBF types. Types 5 to 11 are specific to this
+/// kernel; their layouts are described in the "TLV Types" section of
+/// `doc/TockBinaryFormat.md`, which must be updated along with this list. New
+/// types take the next free number, and `Unused` follows the last one.
 #[repr(u16)]
 #[derive(Clone, Copy, Debug)]
 #[allow(dead_code)]
@@ -28,7 +33,23 @@ crate enum TbfHeaderTypes {
     TbfHeaderMain = 1,
     TbfHeaderWriteableFlashRegions = 2,
     TbfHeaderPackageName = 3,
-    Unused = 5,
+    /// Position independent code options, not used by this kernel.
+    TbfHeaderPicOption1 = 4,
+    /// Priority, timeslice and CPU budget.
+    TbfHeaderScheduling = 5,
+    /// Response to faults, restart limit and backoff.
+    TbfHeaderFaultPolicy = 6,
+    /// Names of the IPC services the process may discover.
+    TbfHeaderIpcAccess = 7,
+    /// Limits on the grant memory of the process.
+    TbfHeaderMemoryQuota = 8,
+    /// Identifier of the app that persists across reboots and updates.
+    TbfHeaderStableId = 9,
+    /// Signature or digest checked before the process is loaded.
+    TbfHeaderCredentials = 10,
+    /// Drivers and commands the process may use.
+    TbfHeaderPermissions = 11,
+    Unused = 12,
 }
 
 /// The TLV header (T and L).
@@ -62,6 +83,69 @@ crate struct TbfHeaderV2WriteableFlashRegion {
     writeable_flash_region_size: u32,
 }
 
+/// Scheduling parameters the app requests from the kernel.
+///
+/// A `timeslice_us` of zero means the scheduler's default timeslice should be
+/// used, and a `budget_us` of zero means the app has no CPU budget limit.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+crate struct TbfHeaderV2Scheduling {
+    priority: u32,
+    timeslice_us: u32,
+    budget_us: u32,
+    period_us: u32,
+}
+
+/// How the app wants the kernel to handle its faults.
+///
+/// `response` and `limit_response` are 0 for panic, 1 for restart and 2 for
+/// stop. A `restart_limit` of zero means the app may be restarted any number
+/// of times, a `backoff_ms` of zero means it is restarted immediately, and a
+/// `max_backoff_ms` of zero means the restart delay is not capped.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+crate struct TbfHeaderV2FaultPolicy {
+    response: u32,
+    restart_limit: u32,
+    limit_response: u32,
+    backoff_ms: u32,
+    max_backoff_ms: u32,
+}
+
+/// Limits on the grant memory the kernel may allocate for the app.
+///
+/// `grant_quota` bounds the grant memory used by all capsules together, and
+/// `per_grant_quota` the grant memory used by any single capsule. Zero means
+/// no limit.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+crate struct TbfHeaderV2MemoryQuota {
+    grant_quota: u32,
+    per_grant_quota: u32,
+}
+
+/// Identifier of the application that stays the same across versions of the
+/// app.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+crate struct TbfHeaderV2StableId {
+    stable_id: u32,
+}
+
+/// Commands of a driver the app may use.
+///
+/// Bit `n` of `allowed_commands` allows command number `32 * offset + n`. An
+/// app may use subscribe and allow with every driver that has an entry, and
+/// can list several entries for the same driver to allow command numbers
+/// beyond 31.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+crate struct TbfHeaderV2Permission {
+    driver_number: u32,
+    offset: u32,
+    allowed_commands: u32,
+}
+
 /// Single header that can contain all parts of a v2 header.
 #[derive(Clone, Copy, Debug)]
 crate struct TbfHeaderV2 {
@@ -69,6 +153,18 @@ crate struct TbfHeaderV2 {
     main: Option<&'static TbfHeaderV2Main>,
     package_name: Option<&'static str>,
     writeable_regions: Option<&'static [TbfHeaderV2WriteableFlashRegion]>,
+    scheduling: Option<&'static TbfHeaderV2Scheduling>,
+    fault_policy: Option<&'static TbfHeaderV2FaultPolicy>,
+    /// Package names of the apps that may use this app as an IPC service,
+    /// separated by zero bytes.
+    ipc_clients: Option<&'static [u8]>,
+    memory_quota: Option<&'static TbfHeaderV2MemoryQuota>,
+    stable_id: Option<&'static TbfHeaderV2StableId>,
+    /// A `u32` credentials format followed by the credentials.
+    credentials: Option<&'static [u8]>,
+    permissions: Option<&'static [TbfHeaderV2Permission]>,
+    /// Whether the Permissions element is malformed.
+    malformed_permissions: bool,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -104,6 +200,16 @@ impl TbfHeader {
         }
     }
 
+    /// Return whether an element of the header that restricts what the app
+    /// may do is malformed. Such apps are not loaded, since they would
+    /// otherwise run without the restriction.
+    crate fn is_malformed(&self) -> bool {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.malformed_permissions,
+            TbfHeader::Padding(_) => false,
+        }
+    }
+
     /// Get the total size in flash of this app or padding.
     crate fn get_total_size(&self) -> u32 {
         match *self {
@@ -112,6 +218,14 @@ impl TbfHeader {
         }
     }
 
+    /// Get the size of the header, which is where the app binary starts.
+    crate fn get_header_size(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.base.header_size as u32,
+            TbfHeader::Padding(hd) => hd.header_size as u32,
+        }
+    }
+
     /// Add up all of the relevant fields in header version 1, or just used the
     /// app provided value in version 2 to get the total amount of RAM that is
     /// needed for this app.
@@ -176,6 +290,98 @@ impl TbfHeader {
             _ => (0, 0),
         }
     }
+
+    /// Get the scheduling parameters the app requested, as a tuple of
+    /// `(priority, timeslice_us, budget_us, period_us)`.
+    crate fn get_scheduling_parameters(&self) -> Option<(u32, u32, u32, u32)> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd
+                .scheduling
+                .map(|s| (s.priority, s.timeslice_us, s.budget_us, s.period_us)),
+            _ => None,
+        }
+    }
+
+    /// Check whether the app's IPC access list includes the app named
+    /// `client`. Returns `None` if the app has no IPC access list.
+    crate fn ipc_allows_client(&self, client: &str) -> Option<bool> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.ipc_clients.map(|clients| {
+                clients
+                    .split(|&b| b == 0)
+                    .any(|name| !name.is_empty() && name == client.as_bytes())
+            }),
+            _ => None,
+        }
+    }
+
+    /// Get the fault policy the app requested, as a tuple of `(response,
+    /// restart_limit, limit_response, backoff_ms, max_backoff_ms)`.
+    crate fn get_fault_policy(&self) -> Option<(u32, u32, u32, u32, u32)> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.fault_policy.map(|f| {
+                (
+                    f.response,
+                    f.restart_limit,
+                    f.limit_response,
+                    f.backoff_ms,
+                    f.max_backoff_ms,
+                )
+            }),
+            _ => None,
+        }
+    }
+
+    /// Get the grant memory quotas the app requested, as a tuple of
+    /// `(grant_quota, per_grant_quota)`.
+    crate fn get_memory_quota(&self) -> Option<(u32, u32)> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => {
+                hd.memory_quota.map(|q| (q.grant_quota, q.per_grant_quota))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the stable identifier of the app, if the header sets one.
+    crate fn get_stable_id(&self) -> Option<u32> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.stable_id.map(|id| id.stable_id),
+            _ => None,
+        }
+    }
+
+    /// Check whether the app's permissions include the driver
+    /// `driver_number` and, if `command` is given, that command of the
+    /// driver. Returns `None` if the app has no permissions in its header.
+    crate fn allows_syscall(&self, driver_number: usize, command: Option<usize>) -> Option<bool> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.permissions.map(|permissions| {
+                permissions
+                    .iter()
+                    .filter(|p| p.driver_number as usize == driver_number)
+                    .any(|p| {
+                        command.map_or(true, |command| {
+                            command / 32 == p.offset as usize
+                                && p.allowed_commands & (1 << (command % 32)) != 0
+                        })
+                    })
+            }),
+            _ => None,
+        }
+    }
+
+    /// Get the credentials of the app, as a tuple of `(format, data)`.
+    crate fn get_credentials(&self) -> Option<(u32, &'static [u8])> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.credentials.map(|credentials| {
+                let mut format = [0; 4];
+                format.copy_from_slice(&credentials[0..4]);
+                (u32::from_le_bytes(format), &credentials[4..])
+            }),
+            _ => None,
+        }
+    }
 }
 
 /// Converts a pointer to memory to a TbfHeader struct
@@ -240,6 +446,14 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                 // options.
                 let mut main_pointer: Option<&TbfHeaderV2Main> = None;
                 let mut wfr_pointer: Option<&'static [TbfHeaderV2WriteableFlashRegion]> = None;
+                let mut scheduling_pointer: Option<&TbfHeaderV2Scheduling> = None;
+                let mut fault_policy_pointer: Option<&TbfHeaderV2FaultPolicy> = None;
+                let mut ipc_clients: Option<&'static [u8]> = None;
+                let mut memory_quota_pointer: Option<&TbfHeaderV2MemoryQuota> = None;
+                let mut stable_id_pointer: Option<&TbfHeaderV2StableId> = None;
+                let mut credentials: Option<&'static [u8]> = None;
+                let mut permissions: Option<&'static [TbfHeaderV2Permission]> = None;
+                let mut malformed_permissions = false;
                 let mut app_name_str = "";
 
                 // Loop through the header looking for known options.
@@ -299,7 +513,98 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                                         });
                                 }
                             }
-                            TbfHeaderTypes::Unused => {}
+                            TbfHeaderTypes::TbfHeaderScheduling =>
+                            /* Scheduling */
+                            {
+                                if remaining_length >= mem::size_of::<TbfHeaderV2Scheduling>()
+                                    && tbf_tlv_header.length as usize
+                                        == mem::size_of::<TbfHeaderV2Scheduling>()
+                                {
+                                    let tbf_scheduling =
+                                        &*(address.offset(offset) as *const TbfHeaderV2Scheduling);
+                                    scheduling_pointer = Some(tbf_scheduling);
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderFaultPolicy =>
+                            /* Fault Policy */
+                            {
+                                if remaining_length >= mem::size_of::<TbfHeaderV2FaultPolicy>()
+                                    && tbf_tlv_header.length as usize
+                                        == mem::size_of::<TbfHeaderV2FaultPolicy>()
+                                {
+                                    let tbf_fault_policy =
+                                        &*(address.offset(offset) as *const TbfHeaderV2FaultPolicy);
+                                    fault_policy_pointer = Some(tbf_fault_policy);
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderIpcAccess =>
+                            /* IPC Access */
+                            {
+                                if remaining_length >= tbf_tlv_header.length as usize {
+                                    ipc_clients = Some(slice::from_raw_parts(
+                                        address.offset(offset),
+                                        tbf_tlv_header.length as usize,
+                                    ));
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderMemoryQuota =>
+                            /* Memory Quota */
+                            {
+                                if remaining_length >= mem::size_of::<TbfHeaderV2MemoryQuota>()
+                                    && tbf_tlv_header.length as usize
+                                        == mem::size_of::<TbfHeaderV2MemoryQuota>()
+                                {
+                                    let tbf_memory_quota =
+                                        &*(address.offset(offset) as *const TbfHeaderV2MemoryQuota);
+                                    memory_quota_pointer = Some(tbf_memory_quota);
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderStableId =>
+                            /* Stable ID */
+                            {
+                                if remaining_length >= mem::size_of::<TbfHeaderV2StableId>()
+                                    && tbf_tlv_header.length as usize
+                                        == mem::size_of::<TbfHeaderV2StableId>()
+                                {
+                                    let tbf_stable_id =
+                                        &*(address.offset(offset) as *const TbfHeaderV2StableId);
+                                    stable_id_pointer = Some(tbf_stable_id);
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderCredentials =>
+                            /* Credentials */
+                            {
+                                if remaining_length >= tbf_tlv_header.length as usize
+                                    && tbf_tlv_header.length as usize >= mem::size_of::<u32>()
+                                {
+                                    credentials = Some(slice::from_raw_parts(
+                                        address.offset(offset),
+                                        tbf_tlv_header.length as usize,
+                                    ));
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderPermissions =>
+                            /* Permissions */
+                            {
+                                // Length must be a multiple of the size of a permission.
+                                if remaining_length >= tbf_tlv_header.length as usize
+                                    && tbf_tlv_header.length as usize
+                                        % mem::size_of::<TbfHeaderV2Permission>()
+                                        == 0
+                                {
+                                    let number_permissions = tbf_tlv_header.length as usize
+                                        / mem::size_of::<TbfHeaderV2Permission>();
+                                    let permissions_start =
+                                        &*(address.offset(offset) as *const TbfHeaderV2Permission);
+                                    permissions = Some(slice::from_raw_parts(
+                                        permissions_start,
+                                        number_permissions,
+                                    ));
+                                } else {
+                                    malformed_permissions = true;
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderPicOption1 | TbfHeaderTypes::Unused => {}
                         }
                     }
 
@@ -314,6 +619,14 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                     main: main_pointer,
                     package_name: Some(app_name_str),
                     writeable_regions: wfr_pointer,
+                    scheduling: scheduling_pointer,
+                    fault_policy: fault_policy_pointer,
+                    ipc_clients: ipc_clients,
+                    memory_quota: memory_quota_pointer,
+                    stable_id: stable_id_pointer,
+                    credentials: credentials,
+                    permissions,
+                    malformed_permissions,
                 };
 
                 Some(TbfHeader::TbfHeaderV2(tbf_header))
@@ -0,0 +1,25 @@
+//! Servicing a watchdog from the kernel loop.
+//!
+//! A board that installs a `KernelWatchdog` with `Kernel::set_watchdog()`
+//! has it kicked on every iteration of `Kernel::kernel_loop()`. If a capsule
+//! hangs, or interrupts keep the kernel from getting back to the loop, the
+//! kicks stop and the hardware watchdog resets the board. The watchdog is
+//! suspended while the chip sleeps, since the kernel loop does not run then
+//! and the board may sleep for longer than the watchdog period.
+//!
+//! Implementations decide what a kick does. `capsules::watchdog`, for
+//! example, only services the hardware watchdog while every task registered
+//! with it has checked in within its deadline.
+
+/// A watchdog the kernel loop services.
+pub trait KernelWatchdog {
+    /// Called on every iteration of the kernel loop. Starts the watchdog the
+    /// first time it is called.
+    fn kick(&self);
+
+    /// Called right before the chip goes to sleep.
+    fn suspend(&self);
+
+    /// Called right after the chip wakes up.
+    fn resume(&self);
+}
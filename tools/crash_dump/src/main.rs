@@ -0,0 +1,143 @@
+//! Decodes a crash dump the kernel saved in flash (see
+//! `kernel/src/crash_dump.rs` for the format).
+//!
+//! The input is a copy of the flash region holding the dump, for example
+//! read from the board with `tockloader read`, or a larger flash image with
+//! the offset of the region in it.
+
+use std::fs;
+
+const MAGIC: u32 = 0x4443_4b54;
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 24;
+const FLAG_TRUNCATED: u32 = 1 << 0;
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Prints an error message and usage string. Used to report command line
+/// argument errors.
+fn usage_error(message: &str) {
+    println!(
+        "{}
+
+Usage: crash_dump FILE [OFFSET]
+Print the crash dump saved in FILE, starting OFFSET bytes into the file (0 if
+omitted). OFFSET may be given in hex with a 0x prefix.
+
+Examples:
+  tockloader read 0x7e000 8192 > dump.bin && crash_dump dump.bin
+  crash_dump flash.bin 0xe000                Read the dump of the host board",
+        message
+    );
+}
+
+fn parse_offset(arg: &str) -> Option<usize> {
+    if arg.starts_with("0x") {
+        usize::from_str_radix(&arg[2..], 16).ok()
+    } else {
+        arg.parse().ok()
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// A valid dump decoded from a flash region.
+struct Dump<'a> {
+    kind: &'static str,
+    truncated: bool,
+    text: &'a [u8],
+}
+
+/// Decode the dump in `region`, or explain why there is no valid one.
+fn decode(region: &[u8]) -> Result<Dump, String> {
+    if region.len() < HEADER_LEN {
+        return Err("The region is too short to hold a header".to_string());
+    }
+    let magic = u32_at(region, 0);
+    if magic != MAGIC {
+        return Err(format!("No crash dump (magic is {:#010x})", magic));
+    }
+    let version = u16_at(region, 4);
+    if version != VERSION {
+        return Err(format!("Unsupported crash dump version {}", version));
+    }
+    let kind = match u16_at(region, 6) {
+        1 => "kernel panic",
+        2 => "process fault",
+        kind => return Err(format!("Unknown kind of crash {}", kind)),
+    };
+    let length = u32_at(region, 8) as usize;
+    let start = u32_at(region, 16) as usize;
+    let text = start
+        .checked_add(length)
+        .and_then(|end| region.get(start..end))
+        .ok_or_else(|| {
+            format!(
+                "The dump ({} bytes at offset {}) does not fit in the region",
+                length, start
+            )
+        })?;
+    if fnv1a(text) != u32_at(region, 12) {
+        return Err("The checksum of the dump does not match, it is incomplete".to_string());
+    }
+    Ok(Dump {
+        kind: kind,
+        truncated: u32_at(region, 20) & FLAG_TRUNCATED != 0,
+        text: text,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        usage_error("Incorrect number of arguments");
+        std::process::exit(1);
+    }
+    let offset = match args.get(2).map(|arg| parse_offset(arg)) {
+        None => 0,
+        Some(Some(offset)) => offset,
+        Some(None) => {
+            usage_error(&format!("Invalid offset {}", args[2]));
+            std::process::exit(1);
+        }
+    };
+    let contents = match fs::read(&args[1]) {
+        Ok(contents) => contents,
+        Err(e) => {
+            usage_error(&format!("Unable to read {}: {}", args[1], e));
+            std::process::exit(1);
+        }
+    };
+
+    match decode(contents.get(offset..).unwrap_or(&[])) {
+        Ok(dump) => {
+            println!(
+                "Crash dump: {}, {} bytes{}",
+                dump.kind,
+                dump.text.len(),
+                if dump.truncated { " (truncated)" } else { "" }
+            );
+            // The kernel writes `\r\n` line endings for serial consoles.
+            print!("{}", String::from_utf8_lossy(dump.text).replace('\r', ""));
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    }
+}
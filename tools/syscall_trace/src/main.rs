@@ -0,0 +1,317 @@
+//! Decodes the syscall trace records the kernel writes when
+//! `trace_syscalls` is enabled (see `kernel/src/syscall_trace.rs` for the
+//! format).
+//!
+//! The input is the raw output of the board, for example from
+//! `tockloader listen` or an RTT channel. Records are either binary frames,
+//! or lines of text that start with `TRACE:` followed by a frame in hex, as
+//! written to the debug output. Text between the records, such as `debug!()`
+//! messages, is passed through unchanged.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+const FRAME_SYNC: [u8; 2] = [0xA5, 0x5A];
+const TEXT_PREFIX: &[u8] = b"TRACE:";
+const PAYLOAD_LEN: usize = 36;
+const FRAME_LEN: usize = 3 + PAYLOAD_LEN + 1;
+const NO_DRIVER: u32 = 0xFFFF_FFFF;
+
+/// Prints an error message and usage string. Used to report command line
+/// argument errors.
+fn usage_error(message: &str) {
+    println!(
+        "{}
+
+Usage: syscall_trace [FILE]
+Pretty-print the syscall trace in FILE, or standard input if FILE is omitted.
+
+Examples:
+  tockloader listen | syscall_trace  Decode the trace of a running board
+  syscall_trace capture.bin          Decode a saved capture",
+        message
+    );
+}
+
+/// Returns the input named on the command line, or standard input.
+fn open_input() -> Result<Box<dyn Read>, ()> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.len() {
+        1 => Ok(Box::new(io::stdin())),
+        2 => File::open(&args[1])
+            .map(|file| Box::new(file) as Box<dyn Read>)
+            .map_err(|e| usage_error(&format!("Unable to open {}: {}", args[1], e))),
+        _ => {
+            usage_error("Incorrect number of arguments");
+            Err(())
+        }
+    }
+}
+
+fn u32_at(payload: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&payload[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Name of a driver number, from `capsules/src/driver.rs`.
+fn driver_name(driver: u32) -> Option<&'static str> {
+    Some(match driver {
+        0x00000 => "alarm",
+        0x00001 => "console",
+        0x00002 => "led",
+        0x00003 => "button",
+        0x00004 => "gpio",
+        0x00005 => "adc",
+        0x00006 => "dac",
+        0x00007 => "analog_comparator",
+        0x10000 => "ipc",
+        0x10001 => "watchdog",
+        0x20001 => "spi",
+        0x20005 => "usb_user",
+        0x20006 => "i2c_master_slave",
+        0x30000 => "ble_advertising",
+        0x30001 => "ieee802154",
+        0x30002 => "udp",
+        0x30003 => "tcp",
+        0x40001 => "rng",
+        0x40002 => "crc",
+        0x40006 => "i2c_master",
+        0x50000 => "app_flash",
+        0x50001 => "nvm_storage",
+        0x50002 => "sdcard",
+        0x50003 => "crash_dump",
+        0x60000 => "temperature",
+        0x60001 => "humidity",
+        0x60002 => "ambient_light",
+        0x60004 => "ninedof",
+        0x90000 => "buzzer",
+        _ => return None,
+    })
+}
+
+/// Formats a return code like the kernel's `ReturnCode`.
+fn return_code(value: u32) -> String {
+    let name = match value as i32 {
+        0 => "SUCCESS",
+        -1 => "FAIL",
+        -2 => "EBUSY",
+        -3 => "EALREADY",
+        -4 => "EOFF",
+        -5 => "ERESERVE",
+        -6 => "EINVAL",
+        -7 => "ESIZE",
+        -8 => "ECANCEL",
+        -9 => "ENOMEM",
+        -10 => "ENOSUPPORT",
+        -11 => "ENODEVICE",
+        -12 => "EUNINSTALLED",
+        -13 => "ENOACK",
+        -14 => "ENOGRANT",
+        -15 => "EPERM",
+        _ => return format!("{:#x}", value),
+    };
+    name.to_string()
+}
+
+/// Formats the decoded payload of one record.
+fn format_record(payload: &[u8]) -> String {
+    let timestamp_us = u32_at(payload, 0);
+    let app = u16::from(payload[4]) | u16::from(payload[5]) << 8;
+    let event = payload[6];
+    let driver_number = u32_at(payload, 8);
+    let sub = u32_at(payload, 12);
+    let args = [
+        u32_at(payload, 16),
+        u32_at(payload, 20),
+        u32_at(payload, 24),
+        u32_at(payload, 28),
+    ];
+    let result = u32_at(payload, 32);
+
+    let driver = if driver_number == NO_DRIVER {
+        "kernel".to_string()
+    } else {
+        match driver_name(driver_number) {
+            Some(name) => format!("{:#x} ({})", driver_number, name),
+            None => format!("{:#x}", driver_number),
+        }
+    };
+
+    let description = match event {
+        0 => "yield".to_string(),
+        1 => format!(
+            "subscribe({}, {}, @{:#x}, {:#x}) = {}",
+            driver,
+            sub,
+            args[0],
+            args[1],
+            return_code(result)
+        ),
+        2 => format!(
+            "command({}, {}, {:#x}, {:#x}) = {}",
+            driver,
+            sub,
+            args[0],
+            args[1],
+            return_code(result)
+        ),
+        3 => format!(
+            "allow({}, {}, @{:#x}, {}) = {}",
+            driver,
+            sub,
+            args[0],
+            args[1],
+            return_code(result)
+        ),
+        4 => format!("memop({}, {:#x}) = {:#x}", sub, args[0], result),
+        5 => format!(
+            "function_call[{}:{}] @{:#x}({:#x}, {:#x}, {:#x}, {:#x})",
+            driver, sub, result, args[0], args[1], args[2], args[3]
+        ),
+        6 => format!(
+            "schedule[{}:{}]({:#x}, {:#x}, {:#x}, {:#x}) = {}",
+            driver,
+            sub,
+            args[0],
+            args[1],
+            args[2],
+            args[3],
+            if result != 0 { "queued" } else { "dropped" }
+        ),
+        7 => format!(
+            "remove_pending_callbacks[{}:{}] = {} removed",
+            driver, sub, result
+        ),
+        8 => return format!("*** {} trace records dropped ***", result),
+        9 => format!(
+            "allow_readonly({}, {}, @{:#x}, {}) = {}",
+            driver,
+            sub,
+            args[0],
+            args[1],
+            return_code(result)
+        ),
+        _ => format!("unknown event {}", event),
+    };
+    format!(
+        "{:>10}.{:03} ms  [{}] {}",
+        timestamp_us / 1000,
+        timestamp_us % 1000,
+        app,
+        description
+    )
+}
+
+/// Checks whether a valid frame starts at the beginning of `data`.
+fn is_frame(data: &[u8]) -> bool {
+    data[0..2] == FRAME_SYNC
+        && data[2] as usize == PAYLOAD_LEN
+        && data[3..3 + PAYLOAD_LEN]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            == data[3 + PAYLOAD_LEN]
+}
+
+/// Decodes a frame written as hex digits, as in a `TRACE:` line.
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() != 2 * FRAME_LEN {
+        return None;
+    }
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let frame: Option<Vec<u8>> = hex
+        .chunks(2)
+        .map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?))
+        .collect();
+    frame.filter(|frame| is_frame(frame))
+}
+
+/// Decodes the frames in `pending` and passes other bytes through. Returns
+/// the number of bytes consumed; a possible frame at the end is left for the
+/// next call unless this is the end of the input.
+fn decode(pending: &[u8], end_of_input: bool, out: &mut dyn Write) -> io::Result<usize> {
+    let mut index = 0;
+    let mut text_start = 0;
+    while index < pending.len() {
+        let line_start = index == 0 || pending[index - 1] == b'\n';
+        if line_start && pending[index] == TEXT_PREFIX[0] {
+            let line_end = pending[index..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map(|len| index + len);
+            let line = match line_end {
+                Some(line_end) => &pending[index..line_end],
+                None if !end_of_input => break,
+                None => &pending[index..],
+            };
+            let frame = if line.starts_with(TEXT_PREFIX) {
+                let mut hex = &line[TEXT_PREFIX.len()..];
+                if hex.ends_with(b"\r") {
+                    hex = &hex[..hex.len() - 1];
+                }
+                decode_hex(hex)
+            } else {
+                None
+            };
+            if let Some(frame) = frame {
+                out.write_all(&pending[text_start..index])?;
+                writeln!(out, "{}", format_record(&frame[3..3 + PAYLOAD_LEN]))?;
+                index = line_end.map_or(pending.len(), |line_end| line_end + 1);
+                text_start = index;
+                continue;
+            }
+        }
+        if pending[index] != FRAME_SYNC[0] {
+            index += 1;
+            continue;
+        }
+        if pending.len() - index < FRAME_LEN && !end_of_input {
+            break;
+        }
+        if pending.len() - index >= FRAME_LEN && is_frame(&pending[index..index + FRAME_LEN]) {
+            out.write_all(&pending[text_start..index])?;
+            if text_start < index && pending[index - 1] != b'\n' {
+                writeln!(out)?;
+            }
+            writeln!(
+                out,
+                "{}",
+                format_record(&pending[index + 3..index + 3 + PAYLOAD_LEN])
+            )?;
+            index += FRAME_LEN;
+            text_start = index;
+        } else {
+            index += 1;
+        }
+    }
+    out.write_all(&pending[text_start..index])?;
+    Ok(index)
+}
+
+fn main() {
+    let mut input = match open_input() {
+        Ok(input) => input,
+        Err(()) => std::process::exit(1),
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let mut pending = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let len = match input.read(&mut chunk) {
+            Ok(len) => len,
+            Err(e) => {
+                eprintln!("Read error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        pending.extend_from_slice(&chunk[..len]);
+        let consumed = decode(&pending, len == 0, &mut out).expect("Unable to write output");
+        pending.drain(..consumed);
+        out.flush().expect("Unable to write output");
+        if len == 0 {
+            break;
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Adds a Credentials element to the header of a Tock Binary Format (TBF)
+//! file, so that boards with a credentials checker accept the app (see
+//! `kernel/src/credentials.rs` and `doc/TockBinaryFormat.md`).
+//!
+//! The credentials cover the fixed fields of the header with a zero checksum,
+//! the other elements of the header, and the app binary, everything after the
+//! TBF header. The Credentials element is placed last in the header. If the
+//! header does not fit into the protected region of the app any more, the
+//! binary is moved back and the offsets in the header are updated, which the
+//! kernel supports because apps are position independent.
+//!
+//! Hashes and signatures are computed with the `openssl` command line tool.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const TBF_HEADER_MAIN: u16 = 1;
+const TBF_HEADER_WRITEABLE_FLASH_REGIONS: u16 = 2;
+const TBF_HEADER_CREDENTIALS: u16 = 10;
+
+/// Length of the fields every TBF v2 header starts with.
+const BASE_LEN: usize = 16;
+
+/// The kind of credentials to add.
+enum Format {
+    Sha256,
+    HmacSha256 { key_file: String },
+    Ed25519 { key_file: String },
+}
+
+impl Format {
+    /// The value of the `format` field.
+    fn id(&self) -> u32 {
+        match self {
+            Format::Sha256 => 1,
+            Format::HmacSha256 { .. } => 2,
+            Format::Ed25519 { .. } => 3,
+        }
+    }
+
+    /// Length of the credentials, in bytes.
+    fn len(&self) -> usize {
+        match self {
+            Format::Sha256 | Format::HmacSha256 { .. } => 32,
+            Format::Ed25519 { .. } => 64,
+        }
+    }
+}
+
+/// Prints an error message and usage string. Used to report command line
+/// argument errors.
+fn usage_error(message: &str) {
+    println!(
+        "{}
+
+Usage: tbf_sign FORMAT INPUT [OUTPUT]
+Add credentials to the TBF file INPUT and write the result to OUTPUT, or back to
+INPUT if OUTPUT is omitted. Existing credentials are replaced.
+
+Formats:
+  --sha256                SHA-256 hash of the app
+  --hmac-sha256 KEYFILE   HMAC-SHA256 with the raw key in KEYFILE
+  --ed25519 KEYFILE       Ed25519 signature with the PEM private key in KEYFILE
+
+Examples:
+  tbf_sign --sha256 blink.tbf
+  tbf_sign --hmac-sha256 release.key blink.tbf blink-signed.tbf
+  tbf_sign --ed25519 release.pem blink.tbf blink-signed.tbf
+
+Create an Ed25519 key and print the public key for the board with:
+  openssl genpkey -algorithm ed25519 -out release.pem
+  openssl pkey -in release.pem -pubout -outform DER | tail -c 32 | xxd -i",
+        message
+    );
+}
+
+/// Parses the command line into the format, input and output file names.
+fn parse_args() -> Result<(Format, String, String), ()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (format, files) = match args.get(0).map(|s| s.as_str()) {
+        Some("--sha256") => (Format::Sha256, &args[1..]),
+        Some(option @ "--hmac-sha256") | Some(option @ "--ed25519") => {
+            let key_file = match args.get(1) {
+                Some(key_file) => key_file.clone(),
+                None => {
+                    usage_error(&format!("{} needs a key file", option));
+                    return Err(());
+                }
+            };
+            if option == "--ed25519" {
+                (Format::Ed25519 { key_file }, &args[2..])
+            } else {
+                (Format::HmacSha256 { key_file }, &args[2..])
+            }
+        }
+        _ => {
+            usage_error("Missing or unknown format");
+            return Err(());
+        }
+    };
+    match files {
+        [input] => Ok((format, input.clone(), input.clone())),
+        [input, output] => Ok((format, input.clone(), output.clone())),
+        _ => {
+            usage_error("Incorrect number of arguments");
+            Err(())
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+/// Runs `openssl` with `args`, passing `input` on standard input, and
+/// returns its output.
+fn openssl(args: &[&str], input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("openssl")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Unable to run openssl: {}", e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input)
+        .map_err(|e| format!("Unable to write to openssl: {}", e))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Unable to run openssl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("openssl {} failed", args.join(" ")));
+    }
+    Ok(output.stdout)
+}
+
+/// Computes the credentials of `message`.
+fn credentials(format: &Format, message: &[u8]) -> Result<Vec<u8>, String> {
+    let credentials = match format {
+        Format::Sha256 => openssl(&["dgst", "-sha256", "-binary"], message)?,
+        Format::HmacSha256 { key_file } => {
+            let key =
+                fs::read(key_file).map_err(|e| format!("Unable to read {}: {}", key_file, e))?;
+            let hex_key: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+            openssl(
+                &[
+                    "dgst",
+                    "-sha256",
+                    "-binary",
+                    "-mac",
+                    "HMAC",
+                    "-macopt",
+                    &format!("hexkey:{}", hex_key),
+                ],
+                message,
+            )?
+        }
+        Format::Ed25519 { key_file } => {
+            // Ed25519 signs the whole message at once, which openssl only
+            // supports for input files, not for standard input.
+            let message_file =
+                std::env::temp_dir().join(format!("tbf_sign-{}.bin", std::process::id()));
+            fs::write(&message_file, message)
+                .map_err(|e| format!("Unable to write {}: {}", message_file.display(), e))?;
+            let signature = openssl(
+                &[
+                    "pkeyutl",
+                    "-sign",
+                    "-rawin",
+                    "-inkey",
+                    key_file,
+                    "-in",
+                    &message_file.to_string_lossy(),
+                ],
+                &[],
+            );
+            let _ = fs::remove_file(&message_file);
+            signature?
+        }
+    };
+    if credentials.len() != format.len() {
+        return Err(format!(
+            "Expected {} bytes of credentials, openssl produced {}",
+            format.len(),
+            credentials.len()
+        ));
+    }
+    Ok(credentials)
+}
+
+/// Returns `tbf` with a Credentials element of `format` in its header.
+fn sign(tbf: &[u8], format: &Format) -> Result<Vec<u8>, String> {
+    if tbf.len() < BASE_LEN || u16_at(tbf, 0) != 2 {
+        return Err("Not a version 2 TBF file".to_string());
+    }
+    let header_size = u16_at(tbf, 2) as usize;
+    let total_size = u32_at(tbf, 4) as usize;
+    if header_size > total_size || total_size > tbf.len() {
+        return Err("TBF header sizes are inconsistent with the file".to_string());
+    }
+
+    // Copy all elements other than existing credentials into the new header.
+    let mut header = tbf[..BASE_LEN].to_vec();
+    let mut protected_size = header_size;
+    let mut offset = BASE_LEN;
+    while offset + 4 <= header_size {
+        let tipe = u16_at(tbf, offset);
+        let len = u16_at(tbf, offset + 2) as usize;
+        let end = offset + 4 + align4(len);
+        if end > header_size {
+            return Err("TBF header element extends past the header".to_string());
+        }
+        if tipe == TBF_HEADER_MAIN && len >= 8 {
+            protected_size = u32_at(tbf, offset + 8) as usize;
+        }
+        if tipe != TBF_HEADER_CREDENTIALS {
+            header.extend_from_slice(&tbf[offset..end]);
+        }
+        offset = end;
+    }
+
+    let credentials_offset = header.len();
+    header.extend_from_slice(&TBF_HEADER_CREDENTIALS.to_le_bytes());
+    header.extend_from_slice(&((4 + format.len()) as u16).to_le_bytes());
+    header.extend_from_slice(&format.id().to_le_bytes());
+    header.extend(std::iter::repeat(0).take(align4(format.len())));
+
+    // If the new header fits into the protected region, it replaces part of
+    // the padding there and the binary stays in place. Otherwise the binary
+    // is moved back by `shift` bytes.
+    let shift = header.len().saturating_sub(protected_size.max(header_size));
+    let keep_from = if shift == 0 {
+        header.len()
+    } else {
+        header_size
+    };
+    let mut signed = header.clone();
+    signed.extend_from_slice(&tbf[keep_from..total_size]);
+
+    // Fix up the sizes and offsets in the new header.
+    let new_header_size = header.len();
+    let new_total_size = total_size + shift;
+    signed[2..4].copy_from_slice(&(new_header_size as u16).to_le_bytes());
+    set_u32(&mut signed, 4, new_total_size as u32);
+    let mut offset = BASE_LEN;
+    while offset < credentials_offset {
+        let tipe = u16_at(&signed, offset);
+        let len = u16_at(&signed, offset + 2) as usize;
+        if shift > 0 && tipe == TBF_HEADER_MAIN && len >= 8 {
+            // `init_fn_offset` and `protected_size`.
+            let init_fn_offset = u32_at(&signed, offset + 4);
+            set_u32(&mut signed, offset + 4, init_fn_offset + shift as u32);
+            let protected = u32_at(&signed, offset + 8);
+            set_u32(&mut signed, offset + 8, protected + shift as u32);
+        }
+        if shift > 0 && tipe == TBF_HEADER_WRITEABLE_FLASH_REGIONS {
+            for region in (offset + 4..offset + 4 + len).step_by(8) {
+                let region_offset = u32_at(&signed, region);
+                set_u32(&mut signed, region, region_offset + shift as u32);
+            }
+        }
+        offset += 4 + align4(len);
+    }
+
+    // The credentials cover the fixed fields with a zero checksum, the
+    // elements before them and the binary.
+    set_u32(&mut signed, 12, 0);
+    let mut message = signed[..credentials_offset].to_vec();
+    message.extend_from_slice(&signed[new_header_size..new_total_size]);
+    let data = credentials(format, &message)?;
+    signed[credentials_offset + 8..credentials_offset + 8 + data.len()].copy_from_slice(&data);
+
+    // The checksum is the XOR of the words of the header, without the
+    // checksum itself.
+    let checksum = (0..new_header_size / 4)
+        .filter(|&word| word != 3)
+        .fold(0, |checksum, word| checksum ^ u32_at(&signed, 4 * word));
+    set_u32(&mut signed, 12, checksum);
+
+    // Keep anything that followed the app in the file.
+    signed.extend_from_slice(&tbf[total_size..]);
+    Ok(signed)
+}
+
+fn main() {
+    let (format, input, output) = match parse_args() {
+        Ok(args) => args,
+        Err(()) => std::process::exit(1),
+    };
+    let tbf = match fs::read(&input) {
+        Ok(tbf) => tbf,
+        Err(e) => {
+            eprintln!("Unable to read {}: {}", input, e);
+            std::process::exit(1);
+        }
+    };
+    let signed = match sign(&tbf, &format) {
+        Ok(signed) => signed,
+        Err(e) => {
+            eprintln!("{}: {}", input, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = fs::write(&output, signed) {
+        eprintln!("Unable to write {}: {}", output, e);
+        std::process::exit(1);
+    }
+}